@@ -0,0 +1,715 @@
+use super::model_catalog::{AgentModel, ModelDefinition, ModelProvider};
+use serde::{Deserialize, Serialize};
+
+/// Config file location of an agent on each platform, in the syntax settings paths accept
+/// (`~`, `{config}`, environment variables)
+struct PlatformPaths {
+   macos: &'static str,
+   linux: &'static str,
+   windows: &'static str,
+}
+
+impl PlatformPaths {
+   /// The same path on every platform
+   const fn everywhere(path: &'static str) -> Self {
+      Self {
+         macos: path,
+         linux: path,
+         windows: path,
+      }
+   }
+
+   fn current(&self) -> &'static str {
+      if cfg!(target_os = "macos") {
+         self.macos
+      } else if cfg!(windows) {
+         self.windows
+      } else {
+         self.linux
+      }
+   }
+}
+
+/// Built-in form of `AgentCapabilities`. `reasoning_values` is `None` for agents without a
+/// reasoning setting.
+struct CapabilityDefinition {
+   reasoning_values: Option<&'static [&'static str]>,
+   preview: bool,
+   temperature: bool,
+   mcp: bool,
+}
+
+impl CapabilityDefinition {
+   const NONE: Self = Self {
+      reasoning_values: None,
+      preview: false,
+      temperature: false,
+      mcp: false,
+   };
+}
+
+/// Built-in form of `CapabilityOverride`
+struct CapabilityOverrideDefinition {
+   min_version: Option<&'static str>,
+   max_version: Option<&'static str>,
+   capabilities: CapabilityDefinition,
+}
+
+/// Built-in knowledge about one agent CLI
+struct AgentDefinition {
+   id: &'static str,
+   name: &'static str,
+   binary_name: &'static str,
+   settings_paths: PlatformPaths,
+   /// Settings files in a project that override the global one, relative to the workspace: the
+   /// one checked into the repository and the per-user one next to it
+   project_settings: Option<(&'static str, Option<&'static str>)>,
+   model_key: &'static str,
+   preview_key: Option<&'static str>,
+   reasoning_key: Option<&'static str>,
+   /// Key choosing how the agent signs in, for agents that keep it in their config
+   auth_key: Option<&'static str>,
+   capabilities: CapabilityDefinition,
+   capability_overrides: &'static [CapabilityOverrideDefinition],
+   /// Providers whose model catalogs apply to the agent
+   providers: &'static [ModelProvider],
+   /// Models only this agent accepts, such as aliases
+   models: &'static [ModelDefinition],
+   /// Arguments that make the CLI print its version
+   version_args: &'static [&'static str],
+   /// Environment variable reference the agent expands in its config, `{name}` standing for the
+   /// variable. Agents without one have secrets removed instead.
+   env_reference: Option<&'static str>,
+   /// Where the agent's config can hold a provider's API key
+   api_key_paths: &'static [(ModelProvider, &'static str)],
+   /// Key of the agent's MCP server definitions and how each one is laid out
+   mcp_servers: Option<(&'static str, McpServerFormat)>,
+   /// Instructions file the agent reads from the root of a project, and from its global location
+   /// when it has one
+   memory_files: Option<(&'static str, Option<PlatformPaths>)>,
+   /// Directories of the agent's custom slash commands, one Markdown file each: relative to the
+   /// project, and global
+   command_dirs: Option<(Option<&'static str>, Option<PlatformPaths>)>,
+}
+
+/// Agents Athas knows out of the box. Ids match the ACP agent registry.
+const BUILTIN_AGENTS: [AgentDefinition; 5] = [
+   AgentDefinition {
+      id: "claude-code",
+      name: "Claude Code",
+      binary_name: "claude",
+      settings_paths: PlatformPaths::everywhere("~/.claude/settings.json"),
+      project_settings: Some((".claude/settings.json", Some(".claude/settings.local.json"))),
+      model_key: "model",
+      preview_key: None,
+      reasoning_key: None,
+      auth_key: None,
+      capabilities: CapabilityDefinition {
+         mcp: true,
+         ..CapabilityDefinition::NONE
+      },
+      capability_overrides: &[],
+      providers: &[ModelProvider::Anthropic],
+      models: &[
+         ModelDefinition {
+            id: "opus",
+            display_name: "Opus (latest)",
+            context_window: Some(200_000),
+            supports_reasoning: true,
+         },
+         ModelDefinition {
+            id: "sonnet",
+            display_name: "Sonnet (latest)",
+            context_window: Some(200_000),
+            supports_reasoning: true,
+         },
+         ModelDefinition {
+            id: "haiku",
+            display_name: "Haiku (latest)",
+            context_window: Some(200_000),
+            supports_reasoning: true,
+         },
+      ],
+      version_args: &["--version"],
+      env_reference: None,
+      api_key_paths: &[(ModelProvider::Anthropic, "env.ANTHROPIC_API_KEY")],
+      mcp_servers: Some(("mcpServers", McpServerFormat::Claude)),
+      memory_files: Some((
+         "CLAUDE.md",
+         Some(PlatformPaths::everywhere("~/.claude/CLAUDE.md")),
+      )),
+      command_dirs: Some((
+         Some(".claude/commands"),
+         Some(PlatformPaths::everywhere("~/.claude/commands")),
+      )),
+   },
+   AgentDefinition {
+      id: "codex-cli",
+      name: "Codex CLI",
+      binary_name: "codex",
+      settings_paths: PlatformPaths::everywhere("~/.codex/config.toml"),
+      project_settings: None,
+      model_key: "model",
+      preview_key: None,
+      reasoning_key: Some("model_reasoning_effort"),
+      auth_key: None,
+      capabilities: CapabilityDefinition {
+         reasoning_values: Some(&["minimal", "low", "medium", "high", "xhigh"]),
+         mcp: true,
+         ..CapabilityDefinition::NONE
+      },
+      // `xhigh` only exists from 0.59
+      capability_overrides: &[CapabilityOverrideDefinition {
+         min_version: None,
+         max_version: Some("0.59.0"),
+         capabilities: CapabilityDefinition {
+            reasoning_values: Some(&["minimal", "low", "medium", "high"]),
+            mcp: true,
+            ..CapabilityDefinition::NONE
+         },
+      }],
+      providers: &[ModelProvider::OpenAi],
+      models: &[],
+      version_args: &["--version"],
+      env_reference: None,
+      api_key_paths: &[],
+      mcp_servers: Some(("mcp_servers", McpServerFormat::Codex)),
+      memory_files: Some((
+         "AGENTS.md",
+         Some(PlatformPaths::everywhere("~/.codex/AGENTS.md")),
+      )),
+      // Codex only reads custom prompts from its home directory
+      command_dirs: Some((None, Some(PlatformPaths::everywhere("~/.codex/prompts")))),
+   },
+   AgentDefinition {
+      id: "aider",
+      name: "Aider",
+      binary_name: "aider",
+      settings_paths: PlatformPaths::everywhere("~/.aider.conf.yml"),
+      // Aider reads `.aider.conf.yml` from the root of the git repository
+      project_settings: Some((".aider.conf.yml", None)),
+      model_key: "model",
+      preview_key: None,
+      reasoning_key: Some("reasoning-effort"),
+      auth_key: None,
+      capabilities: CapabilityDefinition {
+         reasoning_values: Some(&["low", "medium", "high"]),
+         ..CapabilityDefinition::NONE
+      },
+      capability_overrides: &[],
+      providers: &[
+         ModelProvider::Anthropic,
+         ModelProvider::OpenAi,
+         ModelProvider::Google,
+      ],
+      models: &[],
+      version_args: &["--version"],
+      env_reference: None,
+      api_key_paths: &[
+         (ModelProvider::Anthropic, "anthropic-api-key"),
+         (ModelProvider::OpenAi, "openai-api-key"),
+      ],
+      mcp_servers: None,
+      // Aider reads conventions only when told to, with `read: CONVENTIONS.md` in its config
+      memory_files: Some(("CONVENTIONS.md", None)),
+      command_dirs: None,
+   },
+   AgentDefinition {
+      id: "gemini-cli",
+      name: "Gemini CLI",
+      binary_name: "gemini",
+      settings_paths: PlatformPaths::everywhere("~/.gemini/settings.json"),
+      project_settings: Some((".gemini/settings.json", None)),
+      model_key: "model.name",
+      preview_key: Some("general.previewFeatures"),
+      reasoning_key: None,
+      auth_key: Some("security.auth.selectedType"),
+      capabilities: CapabilityDefinition {
+         preview: true,
+         mcp: true,
+         ..CapabilityDefinition::NONE
+      },
+      capability_overrides: &[],
+      providers: &[ModelProvider::Google],
+      models: &[],
+      version_args: &["--version"],
+      env_reference: Some("${name}"),
+      api_key_paths: &[],
+      mcp_servers: Some(("mcpServers", McpServerFormat::Gemini)),
+      memory_files: Some((
+         "GEMINI.md",
+         Some(PlatformPaths::everywhere("~/.gemini/GEMINI.md")),
+      )),
+      // Gemini CLI's custom commands are TOML files rather than Markdown
+      command_dirs: None,
+   },
+   AgentDefinition {
+      id: "opencode",
+      name: "OpenCode",
+      binary_name: "opencode",
+      // OpenCode uses ~/.config on every platform rather than the native config directory
+      settings_paths: PlatformPaths {
+         macos: "~/.config/opencode/opencode.json",
+         linux: "{config}/opencode/opencode.json",
+         windows: "~/.config/opencode/opencode.json",
+      },
+      project_settings: Some(("opencode.json", None)),
+      model_key: "model",
+      preview_key: None,
+      reasoning_key: None,
+      auth_key: None,
+      capabilities: CapabilityDefinition {
+         mcp: true,
+         ..CapabilityDefinition::NONE
+      },
+      capability_overrides: &[],
+      providers: &[
+         ModelProvider::Anthropic,
+         ModelProvider::OpenAi,
+         ModelProvider::Google,
+      ],
+      models: &[],
+      version_args: &["--version"],
+      env_reference: Some("{env:{name}}"),
+      api_key_paths: &[
+         (
+            ModelProvider::Anthropic,
+            "provider.anthropic.options.apiKey",
+         ),
+         (ModelProvider::OpenAi, "provider.openai.options.apiKey"),
+         (ModelProvider::Google, "provider.google.options.apiKey"),
+      ],
+      mcp_servers: Some(("mcp", McpServerFormat::OpenCode)),
+      memory_files: Some((
+         "AGENTS.md",
+         Some(PlatformPaths {
+            macos: "~/.config/opencode/AGENTS.md",
+            linux: "{config}/opencode/AGENTS.md",
+            windows: "~/.config/opencode/AGENTS.md",
+         }),
+      )),
+      command_dirs: Some((
+         Some(".opencode/command"),
+         Some(PlatformPaths {
+            macos: "~/.config/opencode/command",
+            linux: "{config}/opencode/command",
+            windows: "~/.config/opencode/command",
+         }),
+      )),
+   },
+];
+
+/// Which of the settings Athas manages an agent actually has
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AgentCapabilities {
+   pub supports_reasoning: bool,
+   /// Values the agent accepts for its reasoning setting. Empty accepts any value.
+   pub reasoning_values: Vec<String>,
+   pub supports_preview: bool,
+   pub supports_temperature: bool,
+   pub supports_mcp: bool,
+}
+
+impl From<&CapabilityDefinition> for AgentCapabilities {
+   fn from(definition: &CapabilityDefinition) -> Self {
+      Self {
+         supports_reasoning: definition.reasoning_values.is_some(),
+         reasoning_values: definition
+            .reasoning_values
+            .unwrap_or_default()
+            .iter()
+            .map(|value| value.to_string())
+            .collect(),
+         supports_preview: definition.preview,
+         supports_temperature: definition.temperature,
+         supports_mcp: definition.mcp,
+      }
+   }
+}
+
+/// Capabilities that replace an agent's usual ones for a range of CLI versions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilityOverride {
+   /// First version the override applies to, unbounded when absent
+   pub min_version: Option<String>,
+   /// First version the override no longer applies to, unbounded when absent
+   pub max_version: Option<String>,
+   pub capabilities: AgentCapabilities,
+}
+
+impl CapabilityOverride {
+   fn applies_to(&self, version: &str) -> bool {
+      let version = version_numbers(version);
+      let after_min = self
+         .min_version
+         .as_deref()
+         .is_none_or(|min| version >= version_numbers(min));
+      let before_max = self
+         .max_version
+         .as_deref()
+         .is_none_or(|max| version < version_numbers(max));
+      after_min && before_max
+   }
+}
+
+/// Numeric components of a version such as `0.59.0-alpha.1`, for ordering. Pre-release suffixes
+/// are ignored.
+fn version_numbers(version: &str) -> Vec<u64> {
+   let version = version.trim().trim_start_matches(['v', 'V']);
+   let numeric_end = version
+      .find(|c: char| !c.is_ascii_digit() && c != '.')
+      .unwrap_or(version.len());
+   let mut numbers: Vec<u64> = version[..numeric_end]
+      .split('.')
+      .map_while(|part| part.parse().ok())
+      .collect();
+   // 1.2 and 1.2.0 are the same version
+   while numbers.last() == Some(&0) {
+      numbers.pop();
+   }
+   numbers
+}
+
+/// A key path in an agent's config that holds a provider's API key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderKeyPath {
+   pub provider: String,
+   pub key_path: String,
+}
+
+/// How an agent's config lays out one MCP server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum McpServerFormat {
+   /// `command`, `args` and `env`, or `type` and `url` for a remote server
+   Claude,
+   /// `command`, `args` and `env`, or `url` for a streamable HTTP server. Codex has no SSE
+   /// transport.
+   Codex,
+   /// `command`, `args` and `env`, or `url` for an SSE server and `httpUrl` for a streamable HTTP
+   /// one
+   Gemini,
+   /// `type: local` with the command and its arguments in one `command` array and `environment`,
+   /// or `type: remote` with `url`
+   OpenCode,
+}
+
+/// Where an agent's config keeps its MCP servers, one entry per server name under `key_path`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpServersKey {
+   pub key_path: String,
+   pub format: McpServerFormat,
+}
+
+/// Where an agent reads its instructions from: a file at the root of each project, such as
+/// `CLAUDE.md` or `.cursorrules`, and for some agents one that applies to every project
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentMemoryFiles {
+   /// Relative to the workspace
+   pub project_path: String,
+   /// In the syntax settings paths accept
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub global_path: Option<String>,
+}
+
+/// Where an agent reads custom slash commands from, one Markdown file per command named after
+/// it, such as `.claude/commands/review.md` for `/review`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentCommandDirs {
+   /// Relative to the workspace
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub project_path: Option<String>,
+   /// In the syntax settings paths accept
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub global_path: Option<String>,
+}
+
+/// Settings files in a project that are layered over an agent's global settings, later ones
+/// winning
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectSettingsPaths {
+   /// Relative to the workspace, usually checked into the repository
+   pub project_path: String,
+   /// Relative to the workspace, kept out of the repository
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub local_path: Option<String>,
+}
+
+/// What `migrate_secret_to_keychain` leaves in an agent's config in place of a secret it moved
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SecretStrategy {
+   /// Delete the key, leaving the agent to read the key from its environment
+   #[default]
+   Remove,
+   /// Replace the value with a reference to an environment variable, `{name}` in `template`
+   /// standing for the variable's name
+   EnvReference { template: String },
+}
+
+impl SecretStrategy {
+   /// The value replacing a secret, or `None` when it is removed
+   pub fn replacement(&self, env_var: &str) -> Option<String> {
+      match self {
+         SecretStrategy::Remove => None,
+         SecretStrategy::EnvReference { template } => Some(template.replace("{name}", env_var)),
+      }
+   }
+}
+
+/// What Athas knows about an agent: where its config file lives on this platform, which keys
+/// hold the settings Athas manages and which of those settings the agent supports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KnownAgent {
+   pub id: String,
+   pub name: String,
+   pub binary_name: String,
+   pub settings_path: String,
+   /// Project files layered over `settings_path`, for agents that read them
+   #[serde(default)]
+   pub project_settings: Option<ProjectSettingsPaths>,
+   pub model_key: String,
+   pub preview_key: Option<String>,
+   pub reasoning_key: Option<String>,
+   /// Key choosing how the agent signs in, such as Gemini CLI's `oauth-personal` or
+   /// `gemini-api-key`
+   #[serde(default)]
+   pub auth_key: Option<String>,
+   #[serde(default)]
+   pub capabilities: AgentCapabilities,
+   /// Checked in order; the first one covering the detected CLI version wins
+   #[serde(default)]
+   pub capability_overrides: Vec<CapabilityOverride>,
+   /// Providers whose model catalogs apply to the agent
+   #[serde(default)]
+   pub providers: Vec<ModelProvider>,
+   /// Models the agent accepts besides its providers' catalogs
+   #[serde(default)]
+   pub models: Vec<AgentModel>,
+   /// Arguments that make the CLI print its version, `--version` unless the agent says otherwise
+   #[serde(default = "default_version_args")]
+   pub version_args: Vec<String>,
+   /// How secrets moved to the keychain are replaced in the agent's config
+   #[serde(default)]
+   pub secret_strategy: SecretStrategy,
+   /// Where the agent's config can hold provider API keys
+   #[serde(default)]
+   pub api_key_paths: Vec<ProviderKeyPath>,
+   /// Where the agent's config keeps MCP servers, for agents that support them
+   #[serde(default)]
+   pub mcp_servers: Option<McpServersKey>,
+   /// Instructions files the agent reads, for agents that have them
+   #[serde(default)]
+   pub memory_files: Option<AgentMemoryFiles>,
+   /// Directories of custom slash commands, for agents that have them
+   #[serde(default)]
+   pub command_dirs: Option<AgentCommandDirs>,
+   /// Whether the agent ships with Athas rather than being defined by the user
+   #[serde(default)]
+   pub builtin: bool,
+}
+
+impl KnownAgent {
+   /// Capabilities of the given CLI version, or of current releases when the version is unknown
+   pub fn capabilities_for(&self, version: Option<&str>) -> &AgentCapabilities {
+      version
+         .and_then(|version| {
+            self
+               .capability_overrides
+               .iter()
+               .find(|capability_override| capability_override.applies_to(version))
+         })
+         .map_or(&self.capabilities, |capability_override| {
+            &capability_override.capabilities
+         })
+   }
+}
+
+fn default_version_args() -> Vec<String> {
+   vec!["--version".to_string()]
+}
+
+impl From<&AgentDefinition> for KnownAgent {
+   fn from(definition: &AgentDefinition) -> Self {
+      Self {
+         id: definition.id.to_string(),
+         name: definition.name.to_string(),
+         binary_name: definition.binary_name.to_string(),
+         settings_path: definition.settings_paths.current().to_string(),
+         project_settings: definition
+            .project_settings
+            .map(|(project_path, local_path)| ProjectSettingsPaths {
+               project_path: project_path.to_string(),
+               local_path: local_path.map(String::from),
+            }),
+         model_key: definition.model_key.to_string(),
+         preview_key: definition.preview_key.map(String::from),
+         reasoning_key: definition.reasoning_key.map(String::from),
+         auth_key: definition.auth_key.map(String::from),
+         capabilities: AgentCapabilities::from(&definition.capabilities),
+         capability_overrides: definition
+            .capability_overrides
+            .iter()
+            .map(|capability_override| CapabilityOverride {
+               min_version: capability_override.min_version.map(String::from),
+               max_version: capability_override.max_version.map(String::from),
+               capabilities: AgentCapabilities::from(&capability_override.capabilities),
+            })
+            .collect(),
+         providers: definition.providers.to_vec(),
+         models: definition.models.iter().map(AgentModel::from).collect(),
+         version_args: definition
+            .version_args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect(),
+         secret_strategy: match definition.env_reference {
+            Some(template) => SecretStrategy::EnvReference {
+               template: template.to_string(),
+            },
+            None => SecretStrategy::Remove,
+         },
+         api_key_paths: definition
+            .api_key_paths
+            .iter()
+            .map(|(provider, key_path)| ProviderKeyPath {
+               provider: provider.id().to_string(),
+               key_path: key_path.to_string(),
+            })
+            .collect(),
+         mcp_servers: definition
+            .mcp_servers
+            .map(|(key_path, format)| McpServersKey {
+               key_path: key_path.to_string(),
+               format,
+            }),
+         memory_files: definition
+            .memory_files
+            .as_ref()
+            .map(|(project_path, global_paths)| AgentMemoryFiles {
+               project_path: project_path.to_string(),
+               global_path: global_paths
+                  .as_ref()
+                  .map(|paths| paths.current().to_string()),
+            }),
+         command_dirs: definition
+            .command_dirs
+            .as_ref()
+            .map(|(project_path, global_paths)| AgentCommandDirs {
+               project_path: project_path.map(String::from),
+               global_path: global_paths
+                  .as_ref()
+                  .map(|paths| paths.current().to_string()),
+            }),
+         builtin: true,
+      }
+   }
+}
+
+pub fn known_agents() -> Vec<KnownAgent> {
+   BUILTIN_AGENTS.iter().map(KnownAgent::from).collect()
+}
+
+pub fn find_known_agent(agent_id: &str) -> Option<KnownAgent> {
+   BUILTIN_AGENTS
+      .iter()
+      .find(|definition| definition.id == agent_id)
+      .map(KnownAgent::from)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::collections::HashSet;
+
+   #[test]
+   fn test_builtin_agents_are_unique_and_complete() {
+      let agents = known_agents();
+      let ids: HashSet<_> = agents.iter().map(|agent| agent.id.as_str()).collect();
+      assert_eq!(ids.len(), agents.len());
+
+      for agent in &agents {
+         assert!(!agent.settings_path.is_empty(), "{}", agent.id);
+         let overrides = agent.capability_overrides.iter();
+         for capabilities in
+            std::iter::once(&agent.capabilities).chain(overrides.map(|o| &o.capabilities))
+         {
+            assert_eq!(
+               capabilities.supports_reasoning,
+               agent.reasoning_key.is_some(),
+               "{}",
+               agent.id
+            );
+            assert_eq!(
+               capabilities.supports_preview,
+               agent.preview_key.is_some(),
+               "{}",
+               agent.id
+            );
+            assert_eq!(
+               capabilities.supports_mcp,
+               agent.mcp_servers.is_some(),
+               "{}",
+               agent.id
+            );
+         }
+         for key_path in &agent.api_key_paths {
+            assert!(
+               agent
+                  .providers
+                  .iter()
+                  .any(|provider| provider.id() == key_path.provider),
+               "{}",
+               agent.id
+            );
+         }
+      }
+   }
+
+   #[test]
+   fn test_find_known_agent() {
+      let codex = find_known_agent("codex-cli").unwrap();
+      assert_eq!(codex.settings_path, "~/.codex/config.toml");
+      assert_eq!(
+         codex.reasoning_key.as_deref(),
+         Some("model_reasoning_effort")
+      );
+      assert!(find_known_agent("unknown").is_none());
+
+      let gemini = find_known_agent("gemini-cli").unwrap();
+      assert_eq!(gemini.settings_path, "~/.gemini/settings.json");
+      assert_eq!(gemini.model_key, "model.name");
+      assert_eq!(
+         gemini.auth_key.as_deref(),
+         Some("security.auth.selectedType")
+      );
+   }
+
+   #[test]
+   fn test_capabilities_for_version() {
+      let codex = find_known_agent("codex-cli").unwrap();
+      let has_xhigh = |version| {
+         codex
+            .capabilities_for(version)
+            .reasoning_values
+            .iter()
+            .any(|value| value == "xhigh")
+      };
+      assert!(has_xhigh(None));
+      assert!(has_xhigh(Some("0.59.0")));
+      assert!(has_xhigh(Some("v0.61")));
+      assert!(!has_xhigh(Some("0.58.2")));
+      assert!(!has_xhigh(Some("0.9")));
+
+      assert_eq!(version_numbers("1.2.0"), version_numbers("1.2"));
+      assert_eq!(version_numbers("v2.1.3-beta.2"), vec![2, 1, 3]);
+   }
+}
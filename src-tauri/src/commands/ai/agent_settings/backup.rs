@@ -0,0 +1,336 @@
+use super::{
+   error::AgentSettingsError,
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   storage::{AgentSettingsLocks, write_atomic},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+   fs,
+   path::{Path, PathBuf},
+};
+use tauri::{State, command};
+
+/// Subdirectory next to a config file that holds its backups
+const BACKUP_DIR: &str = "backups";
+/// Separator between the config file name and the timestamp in backup file names
+const BACKUP_MARKER: &str = ".athas-bak.";
+/// Suffix of the marker recording that Athas created a config file rather than the user
+const CREATED_MARKER: &str = ".athas-created";
+/// Number of backups kept per config file when the caller doesn't specify a retention
+pub(super) const DEFAULT_BACKUP_RETENTION: usize = 5;
+
+/// A backup copy of an agent config file, as listed for the "restore previous config" UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsBackup {
+   pub name: String,
+   pub path: String,
+   pub created_at: String,
+   pub size: u64,
+}
+
+fn backup_dir(path: &Path) -> PathBuf {
+   path
+      .parent()
+      .map(|parent| parent.join(BACKUP_DIR))
+      .unwrap_or_else(|| PathBuf::from(BACKUP_DIR))
+}
+
+fn backup_prefix(path: &Path) -> String {
+   let file_name = path
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_default();
+   format!("{}{}", file_name, BACKUP_MARKER)
+}
+
+/// Copy the current content of a config file into its backup directory as
+/// `<name>.athas-bak.<timestamp>`, then prune the oldest backups beyond `retention`.
+pub(super) fn create_backup(
+   path: &Path,
+   content: &str,
+   retention: usize,
+) -> Result<PathBuf, AgentSettingsError> {
+   let dir = backup_dir(path);
+   fs::create_dir_all(&dir).map_err(|e| AgentSettingsError::io(&dir, e))?;
+
+   // Several backups within the same second get an increasing `-N` suffix
+   let timestamp = chrono::Local::now().format("%Y%m%dT%H%M%S").to_string();
+   let latest_in_second = list_backups(path)?
+      .iter()
+      .map(|backup| backup_order(&backup.created_at))
+      .filter(|(ts, _)| *ts == timestamp)
+      .map(|(_, counter)| counter)
+      .max();
+   let name = match latest_in_second {
+      Some(counter) => format!("{}{}-{}", backup_prefix(path), timestamp, counter + 1),
+      None => format!("{}{}", backup_prefix(path), timestamp),
+   };
+   let backup_path = dir.join(name);
+
+   write_atomic(&backup_path, content)?;
+   prune_backups(path, retention)?;
+   Ok(backup_path)
+}
+
+fn created_marker(path: &Path) -> PathBuf {
+   let file_name = path
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_default();
+   backup_dir(path).join(format!("{}{}", file_name, CREATED_MARKER))
+}
+
+/// Note that Athas created the config file at `path`, so a later reset may remove it again
+pub(super) fn mark_created(path: &Path) -> Result<(), AgentSettingsError> {
+   let marker = created_marker(path);
+   let dir = backup_dir(path);
+   fs::create_dir_all(&dir).map_err(|e| AgentSettingsError::io(&dir, e))?;
+   fs::write(&marker, "").map_err(|e| AgentSettingsError::io(&marker, e))
+}
+
+/// Whether the config file at `path` was created by Athas
+pub(super) fn was_created(path: &Path) -> bool {
+   created_marker(path).is_file()
+}
+
+/// As `was_created`, without blocking the async runtime on the check
+pub(super) async fn was_created_async(path: &Path) -> bool {
+   tokio::fs::metadata(created_marker(path))
+      .await
+      .is_ok_and(|metadata| metadata.is_file())
+}
+
+/// Remove an Athas-created config file along with its marker
+pub(super) fn remove_created(path: &Path) -> Result<(), AgentSettingsError> {
+   fs::remove_file(path).map_err(|e| AgentSettingsError::io(path, e))?;
+   let marker = created_marker(path);
+   fs::remove_file(&marker).map_err(|e| AgentSettingsError::io(&marker, e))
+}
+
+/// An unused `<name>.corrupt-<timestamp>` path next to the config file at `path`
+fn corrupt_path(path: &Path) -> PathBuf {
+   let timestamp = chrono::Local::now().format("%Y%m%dT%H%M%S").to_string();
+   let base = format!("{}.corrupt-{}", path.display(), timestamp);
+
+   let mut preserved = PathBuf::from(&base);
+   let mut counter = 1;
+   while preserved.exists() {
+      preserved = PathBuf::from(format!("{}-{}", base, counter));
+      counter += 1;
+   }
+   preserved
+}
+
+/// Keep a copy of a config file that failed to parse as `<name>.corrupt-<timestamp>` next to it,
+/// before Athas overwrites it.
+pub(super) fn preserve_corrupt_file(
+   path: &Path,
+   content: &str,
+) -> Result<PathBuf, AgentSettingsError> {
+   let preserved = corrupt_path(path);
+   write_atomic(&preserved, content)?;
+   Ok(preserved)
+}
+
+/// Move a config file that can't be read aside to `<name>.corrupt-<timestamp>`, leaving its
+/// bytes untouched whatever they are
+pub(super) fn quarantine_corrupt_file(path: &Path) -> Result<PathBuf, AgentSettingsError> {
+   let quarantined = corrupt_path(path);
+   fs::rename(path, &quarantined).map_err(|e| AgentSettingsError::io(path, e))?;
+   Ok(quarantined)
+}
+
+/// Backups of a config file, newest first
+pub(super) fn list_backups(path: &Path) -> Result<Vec<AgentSettingsBackup>, AgentSettingsError> {
+   let dir = backup_dir(path);
+   if !dir.exists() {
+      return Ok(Vec::new());
+   }
+
+   let prefix = backup_prefix(path);
+   let entries = fs::read_dir(&dir).map_err(|e| AgentSettingsError::io(&dir, e))?;
+
+   let mut backups: Vec<AgentSettingsBackup> = entries
+      .flatten()
+      .filter_map(|entry| {
+         let name = entry.file_name().to_string_lossy().to_string();
+         let created_at = name.strip_prefix(&prefix)?.to_string();
+         let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+         Some(AgentSettingsBackup {
+            path: entry.path().to_string_lossy().to_string(),
+            name,
+            created_at,
+            size: metadata.len(),
+         })
+      })
+      .collect();
+
+   backups.sort_by(|a, b| backup_order(&b.created_at).cmp(&backup_order(&a.created_at)));
+   Ok(backups)
+}
+
+/// Sort key for a backup timestamp such as `20240601T120000` or `20240601T120000-2`
+fn backup_order(created_at: &str) -> (&str, u32) {
+   match created_at.split_once('-') {
+      Some((timestamp, counter)) => (timestamp, counter.parse().unwrap_or(0)),
+      None => (created_at, 0),
+   }
+}
+
+fn prune_backups(path: &Path, retention: usize) -> Result<(), AgentSettingsError> {
+   for backup in list_backups(path)?.into_iter().skip(retention) {
+      fs::remove_file(&backup.path)
+         .map_err(|e| AgentSettingsError::io(Path::new(&backup.path), e))?;
+   }
+   Ok(())
+}
+
+/// Restore a backup over its config file. The current content is itself backed up first, so a
+/// restore can be undone.
+async fn restore_backup(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   backup_name: &str,
+) -> Result<(), AgentSettingsError> {
+   let backup = list_backups(path)?
+      .into_iter()
+      .find(|backup| backup.name == backup_name)
+      .ok_or_else(|| AgentSettingsError::NotFound {
+         path: backup_dir(path).join(backup_name).display().to_string(),
+      })?;
+   let content = fs::read_to_string(&backup.path)
+      .map_err(|e| AgentSettingsError::io(Path::new(&backup.path), e))?;
+
+   let lock = locks.lock_for(path).await;
+   let _guard = lock.write().await;
+
+   if let Ok(current) = fs::read_to_string(path)
+      && current != content
+   {
+      create_backup(path, &current, DEFAULT_BACKUP_RETENTION)?;
+   }
+   write_atomic(path, &content)?;
+   locks.record_write(path, &content).await;
+   Ok(())
+}
+
+/// List backups Athas made of an agent's config file, newest first
+#[command]
+pub async fn list_agent_settings_backups(
+   settings_path: String,
+) -> Result<Vec<AgentSettingsBackup>, AgentSettingsError> {
+   list_backups(&resolve_settings_path(&settings_path)?)
+}
+
+/// Replace an agent's config file with one of its backups
+#[command]
+pub async fn restore_agent_settings_backup(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   agent_id: String,
+   settings_path: String,
+   backup_name: String,
+   follow_symlinks: Option<bool>,
+) -> Result<(), AgentSettingsError> {
+   let path = roots.check(
+      &resolve_settings_path(&settings_path)?,
+      follow_symlinks.unwrap_or(false),
+   )?;
+   restore_backup(&locks, &path, &backup_name).await?;
+
+   log::info!(
+      "Restored settings backup {} for agent {}",
+      backup_name,
+      agent_id
+   );
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_settings::{
+      format::ConfigFormat,
+      keys::set_nested_value,
+      storage::{WriteOptions, update_config_file},
+   };
+   use serde_json::Value;
+
+   #[tokio::test]
+   async fn test_backups_created_only_on_change_and_pruned() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("config.toml");
+      fs::write(&path, "model = \"o3\"\n").unwrap();
+      let locks = AgentSettingsLocks::new();
+      let options = WriteOptions {
+         backup_retention: Some(3),
+         ..WriteOptions::default()
+      };
+
+      // Writing the same value again must not produce a backup
+      update_config_file(&locks, &path, ConfigFormat::Toml, options, |value| {
+         set_nested_value(value, "model", Value::String("o3".into()))
+      })
+      .await
+      .unwrap();
+      assert!(list_backups(&path).unwrap().is_empty());
+
+      for i in 0..5 {
+         update_config_file(&locks, &path, ConfigFormat::Toml, options, |value| {
+            set_nested_value(value, "model", Value::String(format!("model-{}", i)))
+         })
+         .await
+         .unwrap();
+      }
+
+      let backups = list_backups(&path).unwrap();
+      assert_eq!(backups.len(), 3);
+      assert!(
+         backups
+            .iter()
+            .all(|b| b.name.starts_with("config.toml.athas-bak."))
+      );
+      // Newest backup holds the content from just before the last write
+      assert_eq!(
+         fs::read_to_string(&backups[0].path).unwrap(),
+         "model = \"model-3\"\n"
+      );
+      assert!(dir.path().join("backups").is_dir());
+   }
+
+   #[tokio::test]
+   async fn test_restore_backup_keeps_current_content_restorable() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.json");
+      fs::write(&path, "{\"model\": \"old\"}").unwrap();
+      let locks = AgentSettingsLocks::new();
+      let options = WriteOptions {
+         backup_retention: Some(DEFAULT_BACKUP_RETENTION),
+         ..WriteOptions::default()
+      };
+
+      update_config_file(&locks, &path, ConfigFormat::Json, options, |value| {
+         set_nested_value(value, "model", Value::String("new".into()))
+      })
+      .await
+      .unwrap();
+
+      let backup = list_backups(&path).unwrap().remove(0);
+      restore_backup(&locks, &path, &backup.name).await.unwrap();
+      assert_eq!(fs::read_to_string(&path).unwrap(), "{\"model\": \"old\"}");
+
+      let backups = list_backups(&path).unwrap();
+      assert_eq!(backups.len(), 2);
+      assert!(
+         fs::read_to_string(&backups[0].path)
+            .unwrap()
+            .contains("\"new\"")
+      );
+
+      assert!(matches!(
+         restore_backup(&locks, &path, "../settings.json").await,
+         Err(AgentSettingsError::NotFound { .. })
+      ));
+   }
+}
@@ -0,0 +1,407 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   keys::{get_nested_value, key_names, set_nested_value, validate_key_path},
+   operations::JournaledOperation,
+   patch::{ArrayStrategy, WriteMode, combine},
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   secrets::{is_secret_key, strip_secrets},
+   storage::{AgentSettingsLocks, WriteOptions, read_config_file, update_config_file},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tauri::{State, command};
+
+/// Bundle format version written by `export_agent_settings_bundle`
+const BUNDLE_VERSION: u64 = 1;
+
+/// One agent to include in an exported bundle
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleEntry {
+   pub agent_id: String,
+   pub settings_path: String,
+   pub keys: Vec<String>,
+}
+
+/// Settings of every exported agent, meant to be carried to another machine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct AgentSettingsBundle {
+   version: u64,
+   agents: Vec<BundledAgent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct BundledAgent {
+   agent_id: String,
+   /// Settings path as the export was given it, so `~` and `{config}` resolve on the new machine
+   settings_path: String,
+   values: BTreeMap<String, Value>,
+}
+
+/// How an import treats keys the target file already has
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStrategy {
+   /// Leave existing keys alone and only add missing ones
+   #[default]
+   SkipExisting,
+   /// Replace existing values with the bundle's
+   Overwrite,
+   /// Merge objects key by key and union arrays, with the bundle winning on conflicts
+   Merge,
+}
+
+/// Outcome of importing one agent from a bundle
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleImportResult {
+   pub agent_id: String,
+   pub settings_path: String,
+   pub imported: Vec<String>,
+   pub skipped: Vec<String>,
+   /// Why nothing was imported for this agent, when the import failed
+   pub error: Option<AgentSettingsError>,
+}
+
+/// Whether any object key along `key` looks like it holds a secret
+fn is_secret_path(key: &str) -> Result<bool, AgentSettingsError> {
+   Ok(key_names(key)?.iter().any(|name| is_secret_key(name)))
+}
+
+/// Pick `keys` out of a parsed config file. Unset keys are left out, and unless
+/// `include_secrets` is set so are secret-looking keys and anything nested under them.
+fn export_values(
+   value: &Value,
+   keys: &[String],
+   include_secrets: bool,
+) -> Result<BTreeMap<String, Value>, AgentSettingsError> {
+   let mut values = BTreeMap::new();
+   for key in keys {
+      if !include_secrets && is_secret_path(key)? {
+         continue;
+      }
+      let Some(mut exported) = get_nested_value(value, key)
+         .filter(|v| !v.is_null())
+         .cloned()
+      else {
+         continue;
+      };
+      if !include_secrets {
+         strip_secrets(&mut exported);
+      }
+      values.insert(key.clone(), exported);
+   }
+   Ok(values)
+}
+
+/// Parse and validate a bundle, checking its version before its shape so that a bundle from a
+/// newer Athas is reported as such rather than as malformed
+fn parse_bundle(bundle: &str) -> Result<AgentSettingsBundle, AgentSettingsError> {
+   let invalid = |message: String| AgentSettingsError::InvalidBundle { message };
+   let value: Value = serde_json::from_str(bundle).map_err(|e| invalid(e.to_string()))?;
+   match value.get("version").and_then(Value::as_u64) {
+      Some(BUNDLE_VERSION) => {}
+      Some(version) => return Err(invalid(format!("unsupported bundle version {}", version))),
+      None => return Err(invalid("missing bundle version".into())),
+   }
+   serde_json::from_value(value).map_err(|e| invalid(e.to_string()))
+}
+
+/// Write one bundled agent's values into `document`, returning the keys imported and skipped
+fn import_values(
+   document: &mut Value,
+   values: BTreeMap<String, Value>,
+   strategy: ImportStrategy,
+) -> Result<(Vec<String>, Vec<String>), AgentSettingsError> {
+   let mut imported = Vec::new();
+   let mut skipped = Vec::new();
+   for (key, value) in values {
+      let existing = get_nested_value(document, &key).cloned();
+      let value = match strategy {
+         ImportStrategy::SkipExisting if existing.is_some() => {
+            skipped.push(key);
+            continue;
+         }
+         ImportStrategy::Merge => combine(existing, value, WriteMode::Merge, ArrayStrategy::Union),
+         _ => value,
+      };
+      set_nested_value(document, &key, value)?;
+      imported.push(key);
+   }
+   Ok((imported, skipped))
+}
+
+async fn import_agent(
+   locks: &AgentSettingsLocks,
+   roots: &AgentSettingsRoots,
+   history: &AgentSettingsHistory,
+   agent: &BundledAgent,
+   strategy: ImportStrategy,
+   follow_symlinks: bool,
+) -> Result<(Vec<String>, Vec<String>), AgentSettingsError> {
+   agent
+      .values
+      .keys()
+      .try_for_each(|key| validate_key_path(key))?;
+   let path = roots.check(
+      &resolve_settings_path(&agent.settings_path)?,
+      follow_symlinks,
+   )?;
+
+   let mut outcome = (Vec::new(), Vec::new());
+   update_config_file(
+      locks,
+      &path,
+      ConfigFormat::from_path(&agent.settings_path),
+      WriteOptions {
+         journal: Some(Journal {
+            history,
+            agent_id: &agent.agent_id,
+         }),
+         ..WriteOptions::default()
+      },
+      |document| {
+         outcome = import_values(document, agent.values.clone(), strategy)?;
+         Ok(())
+      },
+   )
+   .await?;
+   Ok(outcome)
+}
+
+/// Export the selected keys of several agents' config files as one JSON bundle. Secret-looking
+/// keys such as `apiKey` or `env.ANTHROPIC_AUTH_TOKEN` are left out unless `include_secrets`
+/// is set. Agents whose config file doesn't exist are exported with no values.
+#[command]
+pub async fn export_agent_settings_bundle(
+   locks: State<'_, AgentSettingsLocks>,
+   entries: Vec<BundleEntry>,
+   include_secrets: Option<bool>,
+) -> Result<String, AgentSettingsError> {
+   let include_secrets = include_secrets.unwrap_or(false);
+
+   let mut agents = Vec::new();
+   for entry in entries {
+      entry
+         .keys
+         .iter()
+         .try_for_each(|key| validate_key_path(key))?;
+      let path = resolve_settings_path(&entry.settings_path)?;
+      let format = ConfigFormat::from_path(&entry.settings_path);
+      let values = match read_config_file(&locks, &path, format).await? {
+         Some(file) => export_values(&file.value, &entry.keys, include_secrets)?,
+         None => BTreeMap::new(),
+      };
+      agents.push(BundledAgent {
+         agent_id: entry.agent_id,
+         settings_path: entry.settings_path,
+         values,
+      });
+   }
+
+   log::info!("Exported settings bundle for {} agents", agents.len());
+   let bundle = AgentSettingsBundle {
+      version: BUNDLE_VERSION,
+      agents,
+   };
+   serde_json::to_string_pretty(&bundle).map_err(|e| AgentSettingsError::Serialize {
+      format: ConfigFormat::Json,
+      message: e.to_string(),
+   })
+}
+
+/// Import a bundle made by `export_agent_settings_bundle`. The bundle as a whole must be valid,
+/// but each agent is imported on its own: one that fails is reported in its result and the rest
+/// are still written. The files are journaled, so an import interrupted halfway can be rolled
+/// back with `recover_pending_operations`.
+#[command]
+pub async fn import_agent_settings_bundle(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   bundle: String,
+   strategy: Option<ImportStrategy>,
+   follow_symlinks: Option<bool>,
+) -> Result<Vec<BundleImportResult>, AgentSettingsError> {
+   let bundle = parse_bundle(&bundle)?;
+   let strategy = strategy.unwrap_or_default();
+   let follow_symlinks = follow_symlinks.unwrap_or(false);
+
+   // Agents whose path is rejected fail below without writing anything
+   let mut paths = Vec::new();
+   for agent in &bundle.agents {
+      if let Ok(path) = resolve_settings_path(&agent.settings_path)
+         .and_then(|path| roots.check(&path, follow_symlinks))
+         && !paths.contains(&path)
+      {
+         paths.push(path);
+      }
+   }
+   let operation =
+      JournaledOperation::begin(&locks, "import_agent_settings_bundle", &paths).await?;
+
+   let mut results = Vec::new();
+   for agent in bundle.agents {
+      let outcome = import_agent(&locks, &roots, &history, &agent, strategy, follow_symlinks).await;
+      if let Err(e) = &outcome {
+         log::warn!(
+            "Failed to import settings for agent {}: {}",
+            agent.agent_id,
+            e
+         );
+      }
+      let (imported, skipped, error) = match outcome {
+         Ok((imported, skipped)) => (imported, skipped, None),
+         Err(e) => (Vec::new(), Vec::new(), Some(e)),
+      };
+      results.push(BundleImportResult {
+         agent_id: agent.agent_id,
+         settings_path: agent.settings_path,
+         imported,
+         skipped,
+         error,
+      });
+   }
+   operation.complete()?;
+   Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+   use tauri::Manager;
+
+   #[test]
+   fn test_export_leaves_out_secrets() {
+      let value = json!({
+         "model": "opus",
+         "apiKey": "sk-ant",
+         "env": { "ANTHROPIC_AUTH_TOKEN": "t", "DISABLE_TELEMETRY": "1" },
+      });
+      let keys = vec![
+         "model".into(),
+         "apiKey".into(),
+         "env".into(),
+         "theme".into(),
+      ];
+
+      assert_eq!(
+         export_values(&value, &keys, false).unwrap(),
+         BTreeMap::from([
+            ("model".to_string(), json!("opus")),
+            ("env".to_string(), json!({ "DISABLE_TELEMETRY": "1" })),
+         ])
+      );
+      assert_eq!(export_values(&value, &keys, true).unwrap().len(), 3);
+   }
+
+   #[test]
+   fn test_parse_bundle_checks_version_and_shape() {
+      assert!(parse_bundle(r#"{ "version": 1, "agents": [] }"#).is_ok());
+      for bundle in [
+         "not json",
+         r#"{ "agents": [] }"#,
+         r#"{ "version": 2, "agents": [] }"#,
+         r#"{ "version": 1, "agents": [{ "agentId": "claude" }] }"#,
+      ] {
+         assert!(matches!(
+            parse_bundle(bundle),
+            Err(AgentSettingsError::InvalidBundle { .. })
+         ));
+      }
+   }
+
+   #[test]
+   fn test_import_strategies() {
+      let existing = json!({ "model": "sonnet", "permissions": { "allow": ["Read"] } });
+      let values = BTreeMap::from([
+         ("model".to_string(), json!("opus")),
+         ("permissions".to_string(), json!({ "allow": ["Bash"] })),
+         ("theme".to_string(), json!("dark")),
+      ]);
+      let import = |strategy| {
+         let mut document = existing.clone();
+         let outcome = import_values(&mut document, values.clone(), strategy).unwrap();
+         (document, outcome)
+      };
+
+      let (document, (imported, skipped)) = import(ImportStrategy::SkipExisting);
+      assert_eq!(document["model"], "sonnet");
+      assert_eq!(imported, vec!["theme"]);
+      assert_eq!(skipped, vec!["model", "permissions"]);
+
+      let (document, _) = import(ImportStrategy::Overwrite);
+      assert_eq!(document["permissions"], json!({ "allow": ["Bash"] }));
+
+      let (document, _) = import(ImportStrategy::Merge);
+      assert_eq!(document["model"], "opus");
+      assert_eq!(
+         document["permissions"],
+         json!({ "allow": ["Read", "Bash"] })
+      );
+   }
+
+   #[tokio::test]
+   async fn test_round_trip_reports_each_agent() {
+      let dir = tempfile::tempdir().unwrap();
+      let claude = dir.path().join("claude.json");
+      std::fs::write(&claude, r#"{ "model": "opus", "apiKey": "sk-ant-secret" }"#).unwrap();
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+      let roots = AgentSettingsRoots::new();
+      roots.allow(dir.path().join("new"));
+      app.manage(roots);
+      app.manage(AgentSettingsHistory::at(
+         Some(dir.path().join("history.jsonl")),
+         u64::MAX,
+      ));
+
+      let bundle = export_agent_settings_bundle(
+         app.state(),
+         vec![BundleEntry {
+            agent_id: "claude".into(),
+            settings_path: claude.display().to_string(),
+            keys: vec!["model".into(), "apiKey".into()],
+         }],
+         None,
+      )
+      .await
+      .unwrap();
+      assert!(!bundle.contains("sk-ant-secret"));
+
+      // Point the bundle at a fresh location, plus an agent outside the allowed roots
+      let target = dir.path().join("new/claude.json");
+      let mut value: Value = serde_json::from_str(&bundle).unwrap();
+      value["agents"][0]["settingsPath"] = json!(target.display().to_string());
+      let mut outside = value["agents"][0].clone();
+      outside["settingsPath"] = json!(dir.path().join("elsewhere.json").display().to_string());
+      value["agents"].as_array_mut().unwrap().push(outside);
+
+      let results = import_agent_settings_bundle(
+         app.state(),
+         app.state(),
+         app.state(),
+         value.to_string(),
+         Some(ImportStrategy::Overwrite),
+         None,
+      )
+      .await
+      .unwrap();
+
+      assert_eq!(results[0].imported, vec!["model"]);
+      assert!(results[0].error.is_none());
+      assert!(matches!(
+         results[1].error,
+         Some(AgentSettingsError::PathNotAllowed { .. })
+      ));
+      let written: Value =
+         serde_json::from_str(&std::fs::read_to_string(&target).unwrap()).unwrap();
+      assert_eq!(written, json!({ "model": "opus" }));
+   }
+}
@@ -0,0 +1,371 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   keys::{delete_nested_value, get_nested_value, push_key_name, set_nested_value},
+   mcp::{known_agent, writable_path},
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   secrets::mask_secret_values,
+   settings::{AgentSettings, SettingsKeys},
+   storage::{
+      AgentSettingsLocks, MISSING_VERSION, WriteOptions, read_config_file, update_config_file,
+   },
+};
+use crate::commands::ai::agent_registry::KnownAgent;
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{State, command};
+
+const CODEX_AGENT_ID: &str = "codex-cli";
+
+/// Top-level key naming the profile Codex starts with
+const PROFILE_KEY: &str = "profile";
+
+/// Table holding one sub-table per profile
+const PROFILES_KEY: &str = "profiles";
+
+/// Where the model Codex runs with is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CodexModelSource {
+   /// The active profile sets `model`
+   Profile,
+   /// The top-level `model` applies, as no profile is active or the active one doesn't set it
+   Default,
+}
+
+/// A profile as listed by `list_codex_profiles`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodexProfileSummary {
+   pub name: String,
+   /// The model the profile sets, if any
+   pub model: Option<String>,
+   pub active: bool,
+}
+
+/// The profiles of Codex's config.toml and which one is in effect
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodexProfiles {
+   pub settings_path: String,
+   /// The top-level `profile` selector, even when it names a profile that isn't defined
+   pub active_profile: Option<String>,
+   pub profiles: Vec<CodexProfileSummary>,
+   /// The model Codex runs with when started without `--profile`
+   pub model: Option<String>,
+   /// `None` when no model is set at all
+   pub model_source: Option<CodexModelSource>,
+   /// Version token of the config file
+   pub version: String,
+}
+
+/// One profile with the settings Codex runs with when it is selected
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodexProfile {
+   pub name: String,
+   pub active: bool,
+   /// The profile's table as written, with secrets masked
+   pub values: Value,
+   /// The managed settings with the profile applied over the top-level defaults
+   pub settings: AgentSettings,
+   pub model_source: Option<CodexModelSource>,
+}
+
+/// Key path of profile `name`'s table
+pub(super) fn profile_key(name: &str) -> String {
+   let mut key = PROFILES_KEY.to_string();
+   push_key_name(&mut key, name);
+   key
+}
+
+fn active_profile(config: &Value) -> Option<&str> {
+   get_nested_value(config, PROFILE_KEY).and_then(Value::as_str)
+}
+
+fn profile_table<'a>(config: &'a Value, name: &str) -> Option<&'a Value> {
+   get_nested_value(config, &profile_key(name)).filter(|table| table.is_object())
+}
+
+/// `profile`'s settings, falling back to `defaults` for those it leaves out, as Codex does
+fn overlay(profile: AgentSettings, defaults: AgentSettings) -> AgentSettings {
+   AgentSettings {
+      model: profile.model.or(defaults.model),
+      preview_features: profile.preview_features.or(defaults.preview_features),
+      reasoning_effort: profile.reasoning_effort.or(defaults.reasoning_effort),
+      temperature: profile.temperature.or(defaults.temperature),
+      max_tokens: profile.max_tokens.or(defaults.max_tokens),
+      allowed_tools: profile.allowed_tools.or(defaults.allowed_tools),
+      version: defaults.version,
+      meta: defaults.meta,
+   }
+}
+
+fn model_source(
+   profile: Option<&Value>,
+   config: &Value,
+   model_key: &str,
+) -> Option<CodexModelSource> {
+   let sets_model =
+      |table: &Value| get_nested_value(table, model_key).is_some_and(Value::is_string);
+   if profile.is_some_and(sets_model) {
+      Some(CodexModelSource::Profile)
+   } else if sets_model(config) {
+      Some(CodexModelSource::Default)
+   } else {
+      None
+   }
+}
+
+fn summarize(agent: &KnownAgent, config: &Value, version: String) -> CodexProfiles {
+   let keys = SettingsKeys::for_agent(agent);
+   let active = active_profile(config);
+   let profiles = get_nested_value(config, PROFILES_KEY)
+      .and_then(Value::as_object)
+      .into_iter()
+      .flatten()
+      .filter(|(_, table)| table.is_object())
+      .map(|(name, table)| CodexProfileSummary {
+         name: name.clone(),
+         model: keys.read(table).model,
+         active: active == Some(name.as_str()),
+      })
+      .collect();
+   let active_table = active.and_then(|name| profile_table(config, name));
+   let model = overlay(
+      active_table
+         .map(|table| keys.read(table))
+         .unwrap_or_default(),
+      keys.read(config),
+   )
+   .model;
+
+   CodexProfiles {
+      settings_path: agent.settings_path.clone(),
+      active_profile: active.map(String::from),
+      profiles,
+      model,
+      model_source: model_source(active_table, config, &keys.model),
+      version,
+   }
+}
+
+fn describe(
+   agent: &KnownAgent,
+   config: &Value,
+   name: &str,
+   version: String,
+) -> Result<CodexProfile, AgentSettingsError> {
+   let table =
+      profile_table(config, name).ok_or_else(|| AgentSettingsError::UnknownCodexProfile {
+         name: name.to_string(),
+      })?;
+   let keys = SettingsKeys::for_agent(agent);
+   let mut values = table.clone();
+   mask_secret_values(&profile_key(name), &mut values);
+
+   Ok(CodexProfile {
+      name: name.to_string(),
+      active: active_profile(config) == Some(name),
+      values,
+      settings: overlay(
+         keys.read(table),
+         AgentSettings {
+            version: Some(version),
+            ..keys.read(config)
+         },
+      ),
+      model_source: model_source(Some(table), config, &keys.model),
+   })
+}
+
+/// Point the `profile` selector at `name`, which must be defined, or remove it
+fn select_profile(config: &mut Value, name: Option<&str>) -> Result<(), AgentSettingsError> {
+   match name {
+      Some(name) => {
+         if profile_table(config, name).is_none() {
+            return Err(AgentSettingsError::UnknownCodexProfile {
+               name: name.to_string(),
+            });
+         }
+         set_nested_value(config, PROFILE_KEY, Value::String(name.to_string()))
+      }
+      None => delete_nested_value(config, PROFILE_KEY, false).map(|_| ()),
+   }
+}
+
+async fn read_codex_config(
+   locks: &AgentSettingsLocks,
+) -> Result<(KnownAgent, Value, String), AgentSettingsError> {
+   let agent = known_agent(locks, CODEX_AGENT_ID).await?;
+   let path = resolve_settings_path(&agent.settings_path)?;
+   let (value, version) =
+      match read_config_file(locks, &path, ConfigFormat::from_path(&agent.settings_path)).await? {
+         Some(file) => (file.value, file.version),
+         None => (
+            Value::Object(Default::default()),
+            MISSING_VERSION.to_string(),
+         ),
+      };
+   Ok((agent, value, version))
+}
+
+/// List the `[profiles.*]` tables of Codex's config.toml, which one the top-level `profile`
+/// selects, and whether the model Codex runs with comes from that profile or the top level
+#[command]
+pub async fn list_codex_profiles(
+   locks: State<'_, AgentSettingsLocks>,
+) -> Result<CodexProfiles, AgentSettingsError> {
+   let (agent, config, version) = read_codex_config(&locks).await?;
+   Ok(summarize(&agent, &config, version))
+}
+
+/// Read one Codex profile, with the settings it runs with once top-level defaults fill in what
+/// it leaves out. An undefined profile is an `UnknownCodexProfile` error.
+#[command]
+pub async fn get_codex_profile(
+   locks: State<'_, AgentSettingsLocks>,
+   name: String,
+) -> Result<CodexProfile, AgentSettingsError> {
+   let (agent, config, version) = read_codex_config(&locks).await?;
+   describe(&agent, &config, &name, version)
+}
+
+/// Make `name` the profile Codex starts with, or go back to the top-level settings when `None`.
+/// Naming an undefined profile is an `UnknownCodexProfile` error.
+#[command]
+pub async fn set_active_codex_profile(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   name: Option<String>,
+) -> Result<(), AgentSettingsError> {
+   let agent = known_agent(&locks, CODEX_AGENT_ID).await?;
+   let path = writable_path(&roots, &agent)?;
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &agent.id,
+      }),
+      ..WriteOptions::default()
+   };
+   update_config_file(
+      &locks,
+      &path,
+      ConfigFormat::from_path(&agent.settings_path),
+      options,
+      |config| select_profile(config, name.as_deref()),
+   )
+   .await?;
+
+   log::info!("Set active Codex profile to {:?}", name);
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::{
+      agent_registry::find_known_agent,
+      agent_settings::format::{parse_config, serialize_config},
+   };
+   use serde_json::json;
+
+   const CONFIG: &str = r#"model = "gpt-5"
+model_reasoning_effort = "medium"
+profile = "work"
+
+[profiles.work]
+model = "o3"
+model_provider = "azure"
+
+[profiles.oss]
+model_provider = "ollama"
+"#;
+
+   fn codex() -> KnownAgent {
+      find_known_agent(CODEX_AGENT_ID).unwrap()
+   }
+
+   fn config() -> Value {
+      parse_config(CONFIG, ConfigFormat::Toml).unwrap()
+   }
+
+   #[test]
+   fn test_lists_profiles_and_model_source() {
+      let profiles = summarize(&codex(), &config(), "v1".into());
+      assert_eq!(profiles.active_profile.as_deref(), Some("work"));
+      assert_eq!(
+         profiles.profiles,
+         vec![
+            CodexProfileSummary {
+               name: "oss".into(),
+               model: None,
+               active: false,
+            },
+            CodexProfileSummary {
+               name: "work".into(),
+               model: Some("o3".into()),
+               active: true,
+            },
+         ]
+      );
+      assert_eq!(profiles.model.as_deref(), Some("o3"));
+      assert_eq!(profiles.model_source, Some(CodexModelSource::Profile));
+
+      let mut config = config();
+      select_profile(&mut config, Some("oss")).unwrap();
+      let profiles = summarize(&codex(), &config, "v2".into());
+      assert_eq!(profiles.model.as_deref(), Some("gpt-5"));
+      assert_eq!(profiles.model_source, Some(CodexModelSource::Default));
+
+      let profiles = summarize(&codex(), &json!({}), MISSING_VERSION.into());
+      assert!(profiles.profiles.is_empty());
+      assert_eq!(profiles.model_source, None);
+   }
+
+   #[test]
+   fn test_profile_settings_fall_back_to_top_level() {
+      let profile = describe(&codex(), &config(), "oss", "v1".into()).unwrap();
+      assert!(!profile.active);
+      assert_eq!(profile.values, json!({ "model_provider": "ollama" }));
+      assert_eq!(profile.settings.model.as_deref(), Some("gpt-5"));
+      assert_eq!(profile.settings.reasoning_effort.as_deref(), Some("medium"));
+      assert_eq!(profile.settings.version.as_deref(), Some("v1"));
+      assert_eq!(profile.model_source, Some(CodexModelSource::Default));
+
+      assert!(matches!(
+         describe(&codex(), &config(), "missing", "v1".into()),
+         Err(AgentSettingsError::UnknownCodexProfile { name }) if name == "missing"
+      ));
+   }
+
+   #[test]
+   fn test_select_profile_writes_selector() {
+      let mut config = config();
+      select_profile(&mut config, Some("oss")).unwrap();
+      let written = serialize_config(config.clone(), ConfigFormat::Toml, Some(CONFIG)).unwrap();
+      assert_eq!(
+         written,
+         CONFIG.replace("profile = \"work\"", "profile = \"oss\"")
+      );
+
+      assert!(matches!(
+         select_profile(&mut config, Some("home")),
+         Err(AgentSettingsError::UnknownCodexProfile { .. })
+      ));
+
+      select_profile(&mut config, None).unwrap();
+      let written = serialize_config(config, ConfigFormat::Toml, Some(CONFIG)).unwrap();
+      assert_eq!(written, CONFIG.replace("profile = \"work\"\n", ""));
+   }
+
+   #[test]
+   fn test_profile_key_quotes_dotted_names() {
+      assert_eq!(profile_key("work"), "profiles.work");
+      assert_eq!(profile_key("gpt-5.1"), "profiles.\"gpt-5.1\"");
+      let config = json!({ "profiles": { "gpt-5.1": { "model": "gpt-5.1" } } });
+      assert!(profile_table(&config, "gpt-5.1").is_some());
+   }
+}
@@ -0,0 +1,211 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   keys::{get_nested_value, set_nested_value, validate_key_path},
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   storage::{AgentSettingsLocks, WriteOptions, read_config_file, update_config_file},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use tauri::{State, command};
+
+/// One side of a `copy_agent_settings` call. `keys` maps a setting name shared by both sides,
+/// such as `reasoning`, to where this agent keeps it (`thinkingLevel` for one agent,
+/// `model_reasoning_effort` for another).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsEndpoint {
+   pub agent_id: String,
+   pub settings_path: String,
+   pub keys: HashMap<String, String>,
+}
+
+/// Which settings a copy wrote, by shared setting name
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CopySummary {
+   /// Settings written to the target, including translated ones
+   pub copied: Vec<String>,
+   /// Settings whose value went through the translation table on the way
+   pub translated: Vec<String>,
+   /// Settings that are unset in the source or have no key on the target
+   pub skipped: Vec<String>,
+}
+
+/// Work out the target writes for a copy. `translations` maps a setting name to a table from
+/// source string values to the target's equivalent, for enums that differ between agents.
+fn plan_copy(
+   source: &Value,
+   source_keys: &HashMap<String, String>,
+   target_keys: &HashMap<String, String>,
+   translations: &HashMap<String, HashMap<String, Value>>,
+) -> (Vec<(String, Value)>, CopySummary) {
+   let mut writes = Vec::new();
+   let mut summary = CopySummary::default();
+
+   let names: BTreeMap<&String, &String> = source_keys.iter().collect();
+   for (name, source_key) in names {
+      let value = get_nested_value(source, source_key).filter(|value| !value.is_null());
+      let (Some(value), Some(target_key)) = (value, target_keys.get(name)) else {
+         summary.skipped.push(name.clone());
+         continue;
+      };
+
+      let translated = value
+         .as_str()
+         .and_then(|value| translations.get(name)?.get(value));
+      if translated.is_some() {
+         summary.translated.push(name.clone());
+      }
+      writes.push((target_key.clone(), translated.unwrap_or(value).clone()));
+      summary.copied.push(name.clone());
+   }
+   (writes, summary)
+}
+
+/// Copy settings from one agent's config file into another's, renaming keys and translating
+/// values so that, say, Claude's reasoning effort becomes Codex's. Settings unset in the source
+/// are skipped rather than written as nulls.
+#[command]
+pub async fn copy_agent_settings(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   source: AgentSettingsEndpoint,
+   target: AgentSettingsEndpoint,
+   translations: Option<HashMap<String, HashMap<String, Value>>>,
+   follow_symlinks: Option<bool>,
+) -> Result<CopySummary, AgentSettingsError> {
+   source
+      .keys
+      .values()
+      .chain(target.keys.values())
+      .try_for_each(|key| validate_key_path(key))?;
+   let source_path = resolve_settings_path(&source.settings_path)?;
+   let target_path = roots.check(
+      &resolve_settings_path(&target.settings_path)?,
+      follow_symlinks.unwrap_or(false),
+   )?;
+
+   let source_value = read_config_file(
+      &locks,
+      &source_path,
+      ConfigFormat::from_path(&source.settings_path),
+   )
+   .await?
+   .map(|file| file.value)
+   .unwrap_or(Value::Null);
+   let (writes, summary) = plan_copy(
+      &source_value,
+      &source.keys,
+      &target.keys,
+      &translations.unwrap_or_default(),
+   );
+
+   update_config_file(
+      &locks,
+      &target_path,
+      ConfigFormat::from_path(&target.settings_path),
+      WriteOptions {
+         journal: Some(Journal {
+            history: &history,
+            agent_id: &target.agent_id,
+         }),
+         ..WriteOptions::default()
+      },
+      |value| {
+         writes
+            .into_iter()
+            .try_for_each(|(key, new_value)| set_nested_value(value, &key, new_value))
+      },
+   )
+   .await?;
+
+   log::info!(
+      "Copied settings from agent {} to agent {}: {:?}",
+      source.agent_id,
+      target.agent_id,
+      summary.copied
+   );
+   Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   fn keys(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+      pairs
+         .iter()
+         .map(|(name, key)| (name.to_string(), key.to_string()))
+         .collect()
+   }
+
+   #[test]
+   fn test_plan_copy_maps_translates_and_skips() {
+      let source = json!({
+         "model": "gpt-5",
+         "thinkingLevel": "high",
+         "preview": null,
+      });
+      let translations = HashMap::from([(
+         "reasoning".to_string(),
+         HashMap::from([("high".to_string(), json!("xhigh"))]),
+      )]);
+
+      let (writes, summary) = plan_copy(
+         &source,
+         &keys(&[
+            ("model", "model"),
+            ("reasoning", "thinkingLevel"),
+            ("preview", "preview"),
+            ("tools", "allowedTools"),
+         ]),
+         &keys(&[
+            ("model", "model"),
+            ("reasoning", "model_reasoning_effort"),
+            ("preview", "features.preview"),
+         ]),
+         &translations,
+      );
+
+      assert_eq!(
+         writes,
+         vec![
+            ("model".to_string(), json!("gpt-5")),
+            ("model_reasoning_effort".to_string(), json!("xhigh")),
+         ]
+      );
+      assert_eq!(
+         summary,
+         CopySummary {
+            copied: vec!["model".into(), "reasoning".into()],
+            translated: vec!["reasoning".into()],
+            skipped: vec!["preview".into(), "tools".into()],
+         }
+      );
+   }
+
+   #[test]
+   fn test_untranslated_values_copy_as_is() {
+      let translations = HashMap::from([(
+         "reasoning".to_string(),
+         HashMap::from([("high".to_string(), json!("xhigh"))]),
+      )]);
+      let (writes, summary) = plan_copy(
+         &json!({ "effort": "low" }),
+         &keys(&[("reasoning", "effort")]),
+         &keys(&[("reasoning", "model_reasoning_effort")]),
+         &translations,
+      );
+
+      assert_eq!(
+         writes,
+         vec![("model_reasoning_effort".to_string(), json!("low"))]
+      );
+      assert!(summary.translated.is_empty());
+   }
+}
@@ -0,0 +1,483 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   keys::{
+      delete_nested_value, format_key_path, get_nested_value, set_nested_value, validate_key_path,
+   },
+   paths::get_home_dir,
+   platform::{Platform, platform_key, resolve_platform},
+   storage::{AgentSettingsLocks, WriteOptions, read_athas_config, update_config_file},
+};
+use crate::commands::ai::agent_registry::{
+   KnownAgent, SecretStrategy, find_known_agent, known_agents,
+};
+use serde_json::Value;
+use std::path::{Component, Path, PathBuf};
+use tauri::{State, command};
+
+/// Where custom agent definitions are kept, relative to the home directory
+const CUSTOM_AGENTS_FILE: &str = ".athas/agents.toml";
+
+fn custom_agents_path() -> Result<PathBuf, AgentSettingsError> {
+   Ok(get_home_dir()?.join(CUSTOM_AGENTS_FILE))
+}
+
+fn invalid_agent(agent_id: &str, message: impl Into<String>) -> AgentSettingsError {
+   AgentSettingsError::InvalidAgent {
+      agent_id: agent_id.to_string(),
+      message: message.into(),
+   }
+}
+
+/// Key path of an agent's table in the custom agents file
+fn agent_key(agent_id: &str) -> String {
+   format_key_path(&["agents".to_string(), agent_id.to_string()])
+}
+
+/// Whether `path`, relative to a workspace, stays inside it
+fn is_inside_project(path: &str) -> bool {
+   let path = Path::new(path.trim());
+   !path.as_os_str().is_empty()
+      && !path.is_absolute()
+      && !path
+         .components()
+         .any(|component| matches!(component, Component::ParentDir))
+}
+
+/// Reject definitions that would shadow a built-in agent or that Athas couldn't use
+fn validate_agent(agent: &KnownAgent) -> Result<(), AgentSettingsError> {
+   if agent.id.trim().is_empty() {
+      return Err(invalid_agent(&agent.id, "agent id is empty"));
+   }
+   if find_known_agent(&agent.id).is_some() {
+      return Err(invalid_agent(&agent.id, "a built-in agent has this id"));
+   }
+   if agent.settings_path.trim().is_empty() {
+      return Err(invalid_agent(&agent.id, "settings path is empty"));
+   }
+   if agent.binary_name.trim().is_empty() {
+      return Err(invalid_agent(&agent.id, "binary name is empty"));
+   }
+   let overrides = agent.capability_overrides.iter();
+   for capabilities in
+      std::iter::once(&agent.capabilities).chain(overrides.map(|o| &o.capabilities))
+   {
+      if capabilities.supports_reasoning && agent.reasoning_key.is_none() {
+         return Err(invalid_agent(
+            &agent.id,
+            "reasoning is supported but has no key",
+         ));
+      }
+      if capabilities.supports_preview && agent.preview_key.is_none() {
+         return Err(invalid_agent(
+            &agent.id,
+            "preview features are supported but have no key",
+         ));
+      }
+      if capabilities.supports_mcp && agent.mcp_servers.is_none() {
+         return Err(invalid_agent(
+            &agent.id,
+            "MCP servers are supported but have no key",
+         ));
+      }
+   }
+   if let SecretStrategy::EnvReference { template } = &agent.secret_strategy
+      && !template.contains("{name}")
+   {
+      return Err(invalid_agent(
+         &agent.id,
+         "environment variable reference has no {name} placeholder",
+      ));
+   }
+   if let Some(project_settings) = &agent.project_settings
+      && !std::iter::once(&project_settings.project_path)
+         .chain(&project_settings.local_path)
+         .all(|path| is_inside_project(path))
+   {
+      return Err(invalid_agent(
+         &agent.id,
+         "project settings must be paths inside the project",
+      ));
+   }
+   if let Some(memory_files) = &agent.memory_files
+      && !is_inside_project(&memory_files.project_path)
+   {
+      return Err(invalid_agent(
+         &agent.id,
+         "memory file must be a path inside the project",
+      ));
+   }
+   if let Some(project_path) = agent
+      .command_dirs
+      .as_ref()
+      .and_then(|dirs| dirs.project_path.as_deref())
+      && !is_inside_project(project_path)
+   {
+      return Err(invalid_agent(
+         &agent.id,
+         "commands directory must be a path inside the project",
+      ));
+   }
+   let api_key_paths = agent.api_key_paths.iter().map(|p| p.key_path.as_str());
+   let mcp_servers = agent.mcp_servers.iter().map(|m| m.key_path.as_str());
+   std::iter::once(agent.model_key.as_str())
+      .chain(agent.preview_key.as_deref())
+      .chain(agent.reasoning_key.as_deref())
+      .chain(agent.auth_key.as_deref())
+      .chain(api_key_paths)
+      .chain(mcp_servers)
+      .try_for_each(validate_key_path)
+}
+
+/// Custom agents stored in the file at `path` as seen on this OS, in id order. Entries that
+/// don't parse as agent definitions are skipped with a warning.
+async fn load_from(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+) -> Result<Vec<KnownAgent>, AgentSettingsError> {
+   let Some(file) = read_athas_config(locks, path, false).await? else {
+      return Ok(Vec::new());
+   };
+   let Some(Value::Object(agents)) = file.value.get("agents") else {
+      return Ok(Vec::new());
+   };
+
+   let mut loaded = Vec::new();
+   for (id, definition) in agents {
+      let mut definition = definition.clone();
+      if let Value::Object(map) = &mut definition {
+         map.insert("id".to_string(), Value::String(id.clone()));
+      }
+      match serde_json::from_value::<KnownAgent>(definition) {
+         Ok(agent) => loaded.push(agent),
+         Err(e) => log::warn!("Skipping custom agent {} in {}: {}", id, path.display(), e),
+      }
+   }
+   Ok(loaded)
+}
+
+/// Write `agent` into the file at `path`, inside `platform`'s section when given. With
+/// `replace`, the agent must already exist on that platform; otherwise it must not.
+async fn save_to(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   agent: KnownAgent,
+   replace: bool,
+   platform: Option<Platform>,
+) -> Result<(), AgentSettingsError> {
+   validate_agent(&agent)?;
+   let key = agent_key(&agent.id);
+   let mut definition =
+      serde_json::to_value(&agent).map_err(|e| AgentSettingsError::Serialize {
+         format: ConfigFormat::Toml,
+         message: e.to_string(),
+      })?;
+   if let Value::Object(map) = &mut definition {
+      // The id is the table name, and nulls have no TOML representation
+      map.remove("id");
+      map.remove("builtin");
+      map.retain(|_, value| !value.is_null());
+   }
+
+   update_config_file(
+      locks,
+      path,
+      ConfigFormat::Toml,
+      WriteOptions::default(),
+      |value| {
+         let resolved = resolve_platform(value.clone(), platform);
+         let exists = get_nested_value(&resolved, &key).is_some();
+         match (replace, exists) {
+            (true, false) => Err(AgentSettingsError::UnknownAgent {
+               agent_id: agent.id.clone(),
+            }),
+            (false, true) => Err(invalid_agent(&agent.id, "a custom agent has this id")),
+            _ => set_nested_value(value, &platform_key(platform, &key), definition),
+         }
+      },
+   )
+   .await?;
+   Ok(())
+}
+
+/// Remove an agent from the file at `path`, or only from `platform`'s section when given
+async fn remove_from(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   agent_id: &str,
+   platform: Option<Platform>,
+) -> Result<bool, AgentSettingsError> {
+   if !path.exists() {
+      return Ok(false);
+   }
+   let mut removed = false;
+   update_config_file(
+      locks,
+      path,
+      ConfigFormat::Toml,
+      WriteOptions::default(),
+      |value| {
+         let key = platform_key(platform, &agent_key(agent_id));
+         removed = delete_nested_value(value, &key, platform.is_some())?;
+         Ok(())
+      },
+   )
+   .await?;
+   Ok(removed)
+}
+
+/// Custom agents defined in `~/.athas/agents.toml`
+pub(super) async fn load_custom_agents(
+   locks: &AgentSettingsLocks,
+) -> Result<Vec<KnownAgent>, AgentSettingsError> {
+   load_from(locks, &custom_agents_path()?).await
+}
+
+/// Built-in agents followed by custom ones
+pub(super) async fn all_agents(
+   locks: &AgentSettingsLocks,
+) -> Result<Vec<KnownAgent>, AgentSettingsError> {
+   let mut agents = known_agents();
+   agents.extend(load_custom_agents(locks).await?);
+   Ok(agents)
+}
+
+/// Look an agent up among the built-in agents, then the custom ones
+pub(super) async fn find_agent(
+   locks: &AgentSettingsLocks,
+   agent_id: &str,
+) -> Result<Option<KnownAgent>, AgentSettingsError> {
+   if let Some(agent) = find_known_agent(agent_id) {
+      return Ok(Some(agent));
+   }
+   Ok(load_custom_agents(locks)
+      .await?
+      .into_iter()
+      .find(|agent| agent.id == agent_id))
+}
+
+/// Define a custom agent so it shows up alongside the built-in ones. With `platform`, the agent
+/// is only defined on that OS.
+#[command]
+pub async fn add_custom_agent(
+   locks: State<'_, AgentSettingsLocks>,
+   agent: KnownAgent,
+   platform: Option<Platform>,
+) -> Result<(), AgentSettingsError> {
+   let agent_id = agent.id.clone();
+   save_to(&locks, &custom_agents_path()?, agent, false, platform).await?;
+   log::info!("Added custom agent {}", agent_id);
+   Ok(())
+}
+
+/// Replace the definition of an existing custom agent. With `platform`, the new definition is
+/// written to that OS' section and overrides the shared one there, e.g. for a different binary
+/// path on macOS.
+#[command]
+pub async fn update_custom_agent(
+   locks: State<'_, AgentSettingsLocks>,
+   agent: KnownAgent,
+   platform: Option<Platform>,
+) -> Result<(), AgentSettingsError> {
+   let agent_id = agent.id.clone();
+   save_to(&locks, &custom_agents_path()?, agent, true, platform).await?;
+   log::info!("Updated custom agent {}", agent_id);
+   Ok(())
+}
+
+/// Remove a custom agent, or only its override for `platform`. Removing one that doesn't exist
+/// is an `UnknownAgent` error.
+#[command]
+pub async fn remove_custom_agent(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   platform: Option<Platform>,
+) -> Result<(), AgentSettingsError> {
+   if !remove_from(&locks, &custom_agents_path()?, &agent_id, platform).await? {
+      return Err(AgentSettingsError::UnknownAgent { agent_id });
+   }
+   log::info!("Removed custom agent {}", agent_id);
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::{
+      agent_registry::{AgentCapabilities, AgentMemoryFiles, ProviderKeyPath},
+      model_catalog::{AgentModel, ModelProvider},
+   };
+
+   fn agent(id: &str) -> KnownAgent {
+      KnownAgent {
+         id: id.into(),
+         name: "Acme Agent".into(),
+         binary_name: "acme".into(),
+         settings_path: "~/.acme/config.toml".into(),
+         project_settings: None,
+         model_key: "agent.model".into(),
+         preview_key: None,
+         reasoning_key: Some("agent.effort".into()),
+         auth_key: None,
+         capabilities: AgentCapabilities {
+            supports_reasoning: true,
+            reasoning_values: vec!["low".into(), "high".into()],
+            ..AgentCapabilities::default()
+         },
+         capability_overrides: Vec::new(),
+         providers: vec![ModelProvider::OpenAi],
+         models: vec![AgentModel {
+            id: "acme-large".into(),
+            display_name: "Acme Large".into(),
+            context_window: None,
+            supports_reasoning: true,
+         }],
+         version_args: vec!["version".into()],
+         secret_strategy: SecretStrategy::EnvReference {
+            template: "${name}".into(),
+         },
+         api_key_paths: vec![ProviderKeyPath {
+            provider: "acme".into(),
+            key_path: "auth.apiKey".into(),
+         }],
+         mcp_servers: None,
+         memory_files: None,
+         command_dirs: None,
+         builtin: false,
+      }
+   }
+
+   #[tokio::test]
+   async fn test_custom_agent_crud() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("agents.toml");
+      let locks = AgentSettingsLocks::new();
+
+      save_to(&locks, &path, agent("acme.internal"), false, None)
+         .await
+         .unwrap();
+      assert_eq!(
+         load_from(&locks, &path).await.unwrap(),
+         vec![agent("acme.internal")]
+      );
+      assert!(
+         std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("[agents.\"acme.internal\"]")
+      );
+
+      let mut renamed = agent("acme.internal");
+      renamed.name = "Acme".into();
+      save_to(&locks, &path, renamed, true, None).await.unwrap();
+      assert_eq!(load_from(&locks, &path).await.unwrap()[0].name, "Acme");
+
+      assert!(matches!(
+         save_to(&locks, &path, agent("acme.internal"), false, None).await,
+         Err(AgentSettingsError::InvalidAgent { .. })
+      ));
+      assert!(matches!(
+         save_to(&locks, &path, agent("other"), true, None).await,
+         Err(AgentSettingsError::UnknownAgent { .. })
+      ));
+
+      assert!(
+         remove_from(&locks, &path, "acme.internal", None)
+            .await
+            .unwrap()
+      );
+      assert!(
+         !remove_from(&locks, &path, "acme.internal", None)
+            .await
+            .unwrap()
+      );
+      assert!(load_from(&locks, &path).await.unwrap().is_empty());
+   }
+
+   #[tokio::test]
+   async fn test_platform_override() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("agents.toml");
+      let locks = AgentSettingsLocks::new();
+      let current = Platform::current().unwrap();
+      let other = if current == Platform::Windows {
+         Platform::Linux
+      } else {
+         Platform::Windows
+      };
+
+      save_to(&locks, &path, agent("acme"), false, None)
+         .await
+         .unwrap();
+      // The agent exists on every platform, so adding it to one is rejected
+      assert!(matches!(
+         save_to(&locks, &path, agent("acme"), false, Some(other)).await,
+         Err(AgentSettingsError::InvalidAgent { .. })
+      ));
+
+      let mut local = agent("acme");
+      local.binary_name = "/opt/acme/bin/acme".into();
+      save_to(&locks, &path, local, true, Some(current))
+         .await
+         .unwrap();
+      let mut elsewhere = agent("acme");
+      elsewhere.binary_name = "acme.exe".into();
+      save_to(&locks, &path, elsewhere, true, Some(other))
+         .await
+         .unwrap();
+      assert_eq!(
+         load_from(&locks, &path).await.unwrap()[0].binary_name,
+         "/opt/acme/bin/acme"
+      );
+
+      assert!(
+         remove_from(&locks, &path, "acme", Some(current))
+            .await
+            .unwrap()
+      );
+      assert_eq!(load_from(&locks, &path).await.unwrap(), vec![agent("acme")]);
+      assert!(
+         remove_from(&locks, &path, "acme", Some(other))
+            .await
+            .unwrap()
+      );
+      assert!(!std::fs::read_to_string(&path).unwrap().contains("platform"));
+   }
+
+   #[test]
+   fn test_validation() {
+      assert!(validate_agent(&agent("acme")).is_ok());
+      assert!(matches!(
+         validate_agent(&agent("codex-cli")),
+         Err(AgentSettingsError::InvalidAgent { .. })
+      ));
+      let mut no_key = agent("acme");
+      no_key.reasoning_key = None;
+      assert!(matches!(
+         validate_agent(&no_key),
+         Err(AgentSettingsError::InvalidAgent { .. })
+      ));
+      let mut bad_key = agent("acme");
+      bad_key.model_key = "agent..model".into();
+      assert!(matches!(
+         validate_agent(&bad_key),
+         Err(AgentSettingsError::InvalidKeyPath { .. })
+      ));
+      let mut bad_reference = agent("acme");
+      bad_reference.secret_strategy = SecretStrategy::EnvReference {
+         template: "$API_KEY".into(),
+      };
+      assert!(matches!(
+         validate_agent(&bad_reference),
+         Err(AgentSettingsError::InvalidAgent { .. })
+      ));
+      let mut outside_project = agent("acme");
+      outside_project.memory_files = Some(AgentMemoryFiles {
+         project_path: "../ACME.md".into(),
+         global_path: None,
+      });
+      assert!(matches!(
+         validate_agent(&outside_project),
+         Err(AgentSettingsError::InvalidAgent { .. })
+      ));
+   }
+}
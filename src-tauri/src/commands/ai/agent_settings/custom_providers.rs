@@ -0,0 +1,417 @@
+use super::{
+   enabled::athas_settings_path,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   interpolate::{env_var, interpolate_env_at, interpolate_str},
+   keys::{delete_nested_value, format_key_path, get_nested_value, set_nested_value},
+   platform::{Platform, platform_key, resolve_platform},
+   storage::{AgentSettingsLocks, WriteOptions, read_athas_config, update_config_file},
+};
+use crate::commands::ai::{
+   credentials::validate_provider_id,
+   providers::{CustomProvider, is_built_in_provider},
+};
+use serde_json::Value;
+use std::path::Path;
+use tauri::{State, command};
+
+/// Table of `~/.athas/settings.toml` custom providers are kept in, one subtable per id
+const PROVIDERS_TABLE: &str = "providers";
+
+fn invalid_provider(provider_id: &str, message: impl Into<String>) -> AgentSettingsError {
+   AgentSettingsError::InvalidProvider {
+      provider_id: provider_id.to_string(),
+      message: message.into(),
+   }
+}
+
+fn provider_key(provider_id: &str) -> String {
+   format_key_path(&[PROVIDERS_TABLE.to_string(), provider_id.to_string()])
+}
+
+/// The definition as it is saved, or why it can't be: ids name keychain entries and must not
+/// shadow a built-in provider, and the base URL must be an http(s) URL once its `${VAR}`
+/// references are expanded. A base URL with references is saved as written.
+pub(super) fn validate_provider(
+   provider: &CustomProvider,
+) -> Result<CustomProvider, AgentSettingsError> {
+   let provider_id = provider.id.trim();
+   validate_provider_id(provider_id).map_err(|message| invalid_provider(provider_id, message))?;
+   if is_built_in_provider(provider_id) {
+      return Err(invalid_provider(
+         provider_id,
+         "a built-in provider has this id",
+      ));
+   }
+   let url_key = format!("{}.baseUrl", provider_key(provider_id));
+   let base_url = interpolate_str(&provider.base_url, &url_key, &env_var)?;
+   let raw_url = &provider.base_url;
+   let expanded = base_url != *raw_url;
+   let mut provider = CustomProvider {
+      base_url,
+      ..provider.clone()
+   }
+   .normalized()
+   .map_err(|e| invalid_provider(provider_id, e.to_string()))?;
+   if provider.display_name.is_empty() {
+      return Err(invalid_provider(provider_id, "display name is empty"));
+   }
+   if expanded {
+      provider.base_url = raw_url.trim().trim_end_matches('/').to_string();
+   }
+   Ok(provider)
+}
+
+/// `provider` with the `${VAR}` references in its definition expanded, as it is used for
+/// requests. Definitions are otherwise kept as written, so saving one back doesn't replace a
+/// reference with what it expanded to on this machine.
+fn expand_provider(provider: CustomProvider) -> Result<CustomProvider, AgentSettingsError> {
+   let serialize_error = |e: serde_json::Error| AgentSettingsError::Serialize {
+      format: ConfigFormat::Toml,
+      message: e.to_string(),
+   };
+   let key = provider_key(&provider.id);
+   let mut definition = serde_json::to_value(&provider).map_err(serialize_error)?;
+   interpolate_env_at(&mut definition, &key, &env_var)?;
+   serde_json::from_value(definition).map_err(serialize_error)
+}
+
+/// Custom providers in the settings file at `path` as seen on this OS, in id order, as written.
+/// Entries that don't parse as provider definitions are skipped with a warning.
+async fn load_from(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+) -> Result<Vec<CustomProvider>, AgentSettingsError> {
+   let Some(file) = read_athas_config(locks, path, false).await? else {
+      return Ok(Vec::new());
+   };
+   let Some(Value::Object(providers)) = file.value.get(PROVIDERS_TABLE) else {
+      return Ok(Vec::new());
+   };
+
+   let mut loaded = Vec::new();
+   for (id, definition) in providers {
+      let mut definition = definition.clone();
+      if let Value::Object(map) = &mut definition {
+         map.insert("id".to_string(), Value::String(id.clone()));
+      }
+      match serde_json::from_value::<CustomProvider>(definition) {
+         Ok(provider) => loaded.push(provider),
+         Err(e) => log::warn!(
+            "Skipping custom provider {} in {}: {}",
+            id,
+            path.display(),
+            e
+         ),
+      }
+   }
+   Ok(loaded)
+}
+
+/// Write `provider` into the settings file at `path`, inside `platform`'s section when given,
+/// returning it as saved. With `replace`, the provider must already exist on that platform;
+/// otherwise it must not.
+pub(super) async fn save_to(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   provider: &CustomProvider,
+   replace: bool,
+   platform: Option<Platform>,
+) -> Result<CustomProvider, AgentSettingsError> {
+   let provider = validate_provider(provider)?;
+   let key = provider_key(&provider.id);
+   let mut definition =
+      serde_json::to_value(&provider).map_err(|e| AgentSettingsError::Serialize {
+         format: ConfigFormat::Toml,
+         message: e.to_string(),
+      })?;
+   if let Value::Object(map) = &mut definition {
+      // The id is the table name
+      map.remove("id");
+   }
+
+   update_config_file(
+      locks,
+      path,
+      ConfigFormat::Toml,
+      WriteOptions::default(),
+      |value| {
+         let resolved = resolve_platform(value.clone(), platform);
+         let exists = get_nested_value(&resolved, &key).is_some();
+         match (replace, exists) {
+            (true, false) => Err(AgentSettingsError::UnknownProvider {
+               provider_id: provider.id.clone(),
+            }),
+            (false, true) => Err(invalid_provider(
+               &provider.id,
+               "a custom provider has this id",
+            )),
+            _ => set_nested_value(value, &platform_key(platform, &key), definition),
+         }
+      },
+   )
+   .await?;
+   Ok(provider)
+}
+
+/// Remove a provider from the settings file at `path`, or only from `platform`'s section when
+/// given
+async fn remove_from(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   provider_id: &str,
+   platform: Option<Platform>,
+) -> Result<bool, AgentSettingsError> {
+   if !path.exists() {
+      return Ok(false);
+   }
+   let mut removed = false;
+   update_config_file(
+      locks,
+      path,
+      ConfigFormat::Toml,
+      WriteOptions::default(),
+      |value| {
+         let key = platform_key(platform, &provider_key(provider_id));
+         removed = delete_nested_value(value, &key, platform.is_some())?;
+         Ok(())
+      },
+   )
+   .await?;
+   Ok(removed)
+}
+
+/// Custom providers defined in `~/.athas/settings.toml`
+pub(super) async fn load_custom_providers(
+   locks: &AgentSettingsLocks,
+) -> Result<Vec<CustomProvider>, AgentSettingsError> {
+   load_from(locks, &athas_settings_path()?).await
+}
+
+/// The custom provider registered as `provider_id`, for the commands that accept a provider id,
+/// with its `${VAR}` references expanded. A reference that doesn't resolve only fails lookups of
+/// the provider it is in.
+pub async fn find_custom_provider(
+   locks: &AgentSettingsLocks,
+   provider_id: &str,
+) -> Result<Option<CustomProvider>, AgentSettingsError> {
+   load_custom_providers(locks)
+      .await?
+      .into_iter()
+      .find(|provider| provider.id == provider_id)
+      .map(expand_provider)
+      .transpose()
+}
+
+/// Custom providers as written in `~/.athas/settings.toml`, `${VAR}` references included, so
+/// they can be edited and saved back with `update_custom_provider` as they are
+#[command]
+pub async fn list_custom_providers(
+   locks: State<'_, AgentSettingsLocks>,
+) -> Result<Vec<CustomProvider>, AgentSettingsError> {
+   load_custom_providers(&locks).await
+}
+
+/// Register an OpenAI-compatible provider so its id can be used wherever a built-in provider's
+/// is. Its API key is stored with `set_api_key` under the same id. With `platform`, the provider
+/// is only registered on that OS.
+#[command]
+pub async fn add_custom_provider(
+   locks: State<'_, AgentSettingsLocks>,
+   provider: CustomProvider,
+   platform: Option<Platform>,
+) -> Result<CustomProvider, AgentSettingsError> {
+   let provider = save_to(&locks, &athas_settings_path()?, &provider, false, platform).await?;
+   log::info!(
+      "Added custom provider {} at {}",
+      provider.id,
+      provider.base_url
+   );
+   Ok(provider)
+}
+
+/// Replace the definition of an existing custom provider, or override it on `platform` only
+#[command]
+pub async fn update_custom_provider(
+   locks: State<'_, AgentSettingsLocks>,
+   provider: CustomProvider,
+   platform: Option<Platform>,
+) -> Result<CustomProvider, AgentSettingsError> {
+   let provider = save_to(&locks, &athas_settings_path()?, &provider, true, platform).await?;
+   log::info!("Updated custom provider {}", provider.id);
+   Ok(provider)
+}
+
+/// Remove a custom provider. Its stored API key is kept until deleted with `delete_api_key`.
+/// With `platform`, only its override for that OS is removed. Removing one that doesn't exist is
+/// an `UnknownProvider` error.
+#[command]
+pub async fn remove_custom_provider(
+   locks: State<'_, AgentSettingsLocks>,
+   provider_id: String,
+   platform: Option<Platform>,
+) -> Result<(), AgentSettingsError> {
+   if !remove_from(&locks, &athas_settings_path()?, &provider_id, platform).await? {
+      return Err(AgentSettingsError::UnknownProvider { provider_id });
+   }
+   log::info!("Removed custom provider {}", provider_id);
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn provider(id: &str) -> CustomProvider {
+      CustomProvider {
+         id: id.into(),
+         display_name: "OpenRouter".into(),
+         base_url: "https://openrouter.ai/api/v1".into(),
+         api_key_header: None,
+         models_endpoint: None,
+         chat_endpoint: None,
+      }
+   }
+
+   #[tokio::test]
+   async fn test_custom_provider_crud() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.toml");
+      std::fs::write(&path, "[agents.aider]\nenabled = false\n").unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      let added = save_to(&locks, &path, &provider("openrouter"), false, None)
+         .await
+         .unwrap();
+      assert_eq!(load_from(&locks, &path).await.unwrap(), vec![added]);
+      let content = std::fs::read_to_string(&path).unwrap();
+      assert!(content.contains("[providers.openrouter]"));
+      assert!(content.contains("enabled = false"));
+
+      let gateway = CustomProvider {
+         base_url: "https://llm.internal.example.com/".into(),
+         api_key_header: Some("api-key".into()),
+         ..provider("openrouter")
+      };
+      let updated = save_to(&locks, &path, &gateway, true, None).await.unwrap();
+      assert_eq!(updated.base_url, "https://llm.internal.example.com");
+      assert_eq!(load_from(&locks, &path).await.unwrap(), vec![updated]);
+
+      assert!(matches!(
+         save_to(&locks, &path, &provider("openrouter"), false, None).await,
+         Err(AgentSettingsError::InvalidProvider { .. })
+      ));
+      assert!(matches!(
+         save_to(&locks, &path, &provider("groq"), true, None).await,
+         Err(AgentSettingsError::UnknownProvider { .. })
+      ));
+
+      assert!(
+         remove_from(&locks, &path, "openrouter", None)
+            .await
+            .unwrap()
+      );
+      assert!(
+         !remove_from(&locks, &path, "openrouter", None)
+            .await
+            .unwrap()
+      );
+      assert!(load_from(&locks, &path).await.unwrap().is_empty());
+   }
+
+   #[tokio::test]
+   async fn test_base_url_references_are_saved_as_written() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.toml");
+      let locks = AgentSettingsLocks::new();
+      let gateway = CustomProvider {
+         base_url: "${ATHAS_TEST_UNSET_GATEWAY:-https://llm.example.com}/v1/".into(),
+         ..provider("gateway")
+      };
+
+      let saved = save_to(&locks, &path, &gateway, false, None).await.unwrap();
+      assert_eq!(
+         saved.base_url,
+         "${ATHAS_TEST_UNSET_GATEWAY:-https://llm.example.com}/v1"
+      );
+      let content = std::fs::read_to_string(&path).unwrap();
+      assert!(content.contains("${ATHAS_TEST_UNSET_GATEWAY:-https://llm.example.com}/v1"));
+      let loaded = load_from(&locks, &path).await.unwrap();
+      assert_eq!(loaded, vec![saved.clone()]);
+      let expanded = expand_provider(loaded[0].clone()).unwrap();
+      assert_eq!(expanded.base_url, "https://llm.example.com/v1");
+
+      // Listing, editing another field and saving back keeps the reference
+      let edited = CustomProvider {
+         display_name: "Gateway".into(),
+         ..loaded[0].clone()
+      };
+      save_to(&locks, &path, &edited, true, None).await.unwrap();
+      let content = std::fs::read_to_string(&path).unwrap();
+      assert!(content.contains("${ATHAS_TEST_UNSET_GATEWAY:-https://llm.example.com}/v1"));
+      assert!(!content.contains("\"https://llm.example.com/v1\""));
+      assert_eq!(
+         load_from(&locks, &path).await.unwrap()[0].display_name,
+         "Gateway"
+      );
+
+      let unresolved = CustomProvider {
+         base_url: "${ATHAS_TEST_UNSET_GATEWAY}/v1".into(),
+         ..provider("gateway")
+      };
+      assert!(matches!(
+         validate_provider(&unresolved),
+         Err(AgentSettingsError::UnresolvedVariable { ref variable, ref key })
+            if variable == "ATHAS_TEST_UNSET_GATEWAY" && key == "providers.gateway.baseUrl"
+      ));
+   }
+
+   #[tokio::test]
+   async fn test_unresolved_reference_only_affects_its_provider() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.toml");
+      std::fs::write(
+         &path,
+         "[agents.aider]\nenabled = \"${ATHAS_TEST_UNSET_ELSEWHERE}\"\n\n[providers.broken]\n\
+          displayName = \"Broken\"\nbaseUrl = \"${ATHAS_TEST_UNSET_BROKEN}/v1\"\n\n\
+          [providers.groq]\ndisplayName = \"Groq\"\nbaseUrl = \"https://api.groq.com/openai/v1\"\n",
+      )
+      .unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      let loaded = load_from(&locks, &path).await.unwrap();
+      assert_eq!(loaded.len(), 2);
+      assert!(matches!(
+         expand_provider(loaded[0].clone()),
+         Err(AgentSettingsError::UnresolvedVariable { ref key, .. })
+            if key == "providers.broken.baseUrl"
+      ));
+      assert_eq!(
+         expand_provider(loaded[1].clone()).unwrap().base_url,
+         "https://api.groq.com/openai/v1"
+      );
+   }
+
+   #[test]
+   fn test_validation() {
+      assert!(validate_provider(&provider("groq")).is_ok());
+      for id in ["openai", "ollama", "Groq", "my gateway", ""] {
+         assert!(
+            matches!(
+               validate_provider(&provider(id)),
+               Err(AgentSettingsError::InvalidProvider { .. })
+            ),
+            "{}",
+            id
+         );
+      }
+      let bad_url = CustomProvider {
+         base_url: "openrouter.ai/api/v1".into(),
+         ..provider("openrouter")
+      };
+      assert!(matches!(
+         validate_provider(&bad_url),
+         Err(AgentSettingsError::InvalidProvider { .. })
+      ));
+   }
+}
@@ -0,0 +1,424 @@
+use super::{
+   enabled::athas_settings_path,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   keys::{delete_nested_value, set_nested_value},
+   paths::resolve_settings_path,
+   platform::{Platform, platform_key},
+   settings::{AgentSettings, SettingsKeys},
+   storage::{
+      AgentSettingsLocks, WriteOptions, read_athas_config, read_config_file, update_config_file,
+   },
+};
+use crate::commands::ai::agent_registry::AgentCapabilities;
+use serde::Serialize;
+use serde_json::{Value, json};
+use tauri::{State, command};
+
+/// Table of `~/.athas/settings.toml` holding the settings every agent inherits
+const AGENT_DEFAULTS_TABLE: &str = "agent_defaults";
+
+/// Settings agents can inherit from `[agent_defaults]`, named as in `AgentSettings`. Models are
+/// left out since no model name is valid for every agent.
+const INHERITED_SETTINGS: &[&str] = &[
+   "previewFeatures",
+   "reasoningEffort",
+   "temperature",
+   "maxTokens",
+   "allowedTools",
+];
+
+/// Where a setting's value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SettingOrigin {
+   /// The agent's config file sets it
+   File,
+   /// The file doesn't set it and the caller's default was used
+   Default,
+}
+
+/// Origin of each setting, `None` for settings that have neither a file value nor a default
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsOrigins {
+   pub model: Option<SettingOrigin>,
+   pub preview_features: Option<SettingOrigin>,
+   pub reasoning_effort: Option<SettingOrigin>,
+   pub temperature: Option<SettingOrigin>,
+   pub max_tokens: Option<SettingOrigin>,
+   pub allowed_tools: Option<SettingOrigin>,
+}
+
+/// Settings with missing values filled in from defaults
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsWithDefaults {
+   pub settings: AgentSettings,
+   pub origins: AgentSettingsOrigins,
+   /// Whether the file sets any of the settings, i.e. whether a reset would change anything
+   pub overridden: bool,
+}
+
+/// Take `value` if the file set it, `default` otherwise, noting which one was used
+fn pick<T>(value: Option<T>, default: Option<T>, origin: &mut Option<SettingOrigin>) -> Option<T> {
+   *origin = match (&value, &default) {
+      (Some(_), _) => Some(SettingOrigin::File),
+      (None, Some(_)) => Some(SettingOrigin::Default),
+      (None, None) => None,
+   };
+   value.or(default)
+}
+
+pub(super) fn with_defaults(
+   settings: AgentSettings,
+   defaults: AgentSettings,
+) -> AgentSettingsWithDefaults {
+   let mut origins = AgentSettingsOrigins::default();
+   let settings = AgentSettings {
+      model: pick(settings.model, defaults.model, &mut origins.model),
+      preview_features: pick(
+         settings.preview_features,
+         defaults.preview_features,
+         &mut origins.preview_features,
+      ),
+      reasoning_effort: pick(
+         settings.reasoning_effort,
+         defaults.reasoning_effort,
+         &mut origins.reasoning_effort,
+      ),
+      temperature: pick(
+         settings.temperature,
+         defaults.temperature,
+         &mut origins.temperature,
+      ),
+      max_tokens: pick(
+         settings.max_tokens,
+         defaults.max_tokens,
+         &mut origins.max_tokens,
+      ),
+      allowed_tools: pick(
+         settings.allowed_tools,
+         defaults.allowed_tools,
+         &mut origins.allowed_tools,
+      ),
+      version: settings.version,
+      meta: settings.meta,
+   };
+
+   let overridden = [
+      origins.model,
+      origins.preview_features,
+      origins.reasoning_effort,
+      origins.temperature,
+      origins.max_tokens,
+      origins.allowed_tools,
+   ]
+   .contains(&Some(SettingOrigin::File));
+
+   AgentSettingsWithDefaults {
+      settings,
+      origins,
+      overridden,
+   }
+}
+
+/// Read the same settings as `get_agent_settings`, filling settings the file doesn't set from
+/// `defaults` (such as a `medium` reasoning effort for Codex) and reporting where each value came
+/// from. Without `defaults` the settings are exactly what `get_agent_settings` returns.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_agent_settings_with_defaults(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   settings_path: String,
+   model_key: String,
+   preview_key: Option<String>,
+   reasoning_key: Option<String>,
+   temperature_key: Option<String>,
+   max_tokens_key: Option<String>,
+   tools_key: Option<String>,
+   defaults: Option<AgentSettings>,
+) -> Result<AgentSettingsWithDefaults, AgentSettingsError> {
+   let keys = SettingsKeys {
+      model: model_key,
+      preview: preview_key,
+      reasoning: reasoning_key,
+      temperature: temperature_key,
+      max_tokens: max_tokens_key,
+      tools: tools_key,
+   };
+   keys.validate()?;
+   let path = resolve_settings_path(&settings_path)?;
+
+   let format = ConfigFormat::from_path(&settings_path);
+   let file = read_config_file(&locks, &path, format).await?;
+   let result = with_defaults(
+      keys.read_file(file.as_ref(), format),
+      defaults.unwrap_or_default(),
+   );
+   log::debug!(
+      "Loaded settings for agent {} with defaults: overridden={}",
+      agent_id,
+      result.overridden
+   );
+   Ok(result)
+}
+
+/// Where the inherited settings live in `~/.athas/settings.toml`
+fn agent_defaults_keys() -> SettingsKeys {
+   let key = |name: &str| format!("{}.{}", AGENT_DEFAULTS_TABLE, name);
+   SettingsKeys {
+      model: key("model"),
+      preview: Some(key("previewFeatures")),
+      reasoning: Some(key("reasoningEffort")),
+      temperature: Some(key("temperature")),
+      max_tokens: Some(key("maxTokens")),
+      tools: Some(key("allowedTools")),
+   }
+}
+
+/// The settings in `[agent_defaults]`, which every agent inherits where its own files don't set
+/// them
+pub(super) async fn load_agent_defaults(
+   locks: &AgentSettingsLocks,
+) -> Result<AgentSettings, AgentSettingsError> {
+   let file = read_athas_config(locks, &athas_settings_path()?, false).await?;
+   let defaults = file
+      .map(|file| agent_defaults_keys().read(&file.value))
+      .unwrap_or_default();
+   Ok(AgentSettings {
+      model: None,
+      ..defaults
+   })
+}
+
+/// The part of `defaults` an agent with `keys` can take. Settings it has no key for are dropped,
+/// and so are those its capabilities rule out, including a reasoning effort outside its allowed
+/// values. Agents without known capabilities keep every setting they have a key for.
+pub(super) fn applicable_defaults(
+   defaults: AgentSettings,
+   keys: &SettingsKeys,
+   capabilities: Option<&AgentCapabilities>,
+) -> AgentSettings {
+   let supports = |check: fn(&AgentCapabilities) -> bool| capabilities.is_none_or(check);
+   AgentSettings {
+      model: None,
+      preview_features: defaults
+         .preview_features
+         .filter(|_| keys.preview.is_some() && supports(|c| c.supports_preview)),
+      reasoning_effort: defaults.reasoning_effort.filter(|effort| {
+         keys.reasoning.is_some()
+            && capabilities.is_none_or(|c| {
+               c.supports_reasoning
+                  && (c.reasoning_values.is_empty() || c.reasoning_values.contains(effort))
+            })
+      }),
+      temperature: defaults
+         .temperature
+         .filter(|_| keys.temperature.is_some() && supports(|c| c.supports_temperature)),
+      max_tokens: defaults.max_tokens.filter(|_| keys.max_tokens.is_some()),
+      allowed_tools: defaults.allowed_tools.filter(|_| keys.tools.is_some()),
+      version: None,
+      meta: None,
+   }
+}
+
+/// Check that `key` is a setting agents inherit and `value` has its type
+fn check_agent_default(key: &str, value: &Value) -> Result<(), AgentSettingsError> {
+   let invalid = |message: String| AgentSettingsError::InvalidAgentDefault {
+      key: key.to_string(),
+      message,
+   };
+   if !INHERITED_SETTINGS.contains(&key) {
+      return Err(invalid(format!(
+         "expected one of: {}",
+         INHERITED_SETTINGS.join(", ")
+      )));
+   }
+   if !value.is_null() {
+      serde_json::from_value::<AgentSettings>(json!({ key: value }))
+         .map_err(|err| invalid(err.to_string()))?;
+   }
+   Ok(())
+}
+
+/// Read the settings every agent inherits from `[agent_defaults]` in `~/.athas/settings.toml`
+#[command]
+pub async fn get_agent_defaults(
+   locks: State<'_, AgentSettingsLocks>,
+) -> Result<AgentSettings, AgentSettingsError> {
+   load_agent_defaults(&locks).await
+}
+
+/// Set a setting every agent inherits, such as `reasoningEffort`, in `[agent_defaults]` of
+/// `~/.athas/settings.toml`, or remove it with a `null` value. With `platform`, the default only
+/// applies on that OS. Agents' own files are not touched, and a setting they set themselves
+/// still wins.
+#[command]
+pub async fn set_agent_default(
+   locks: State<'_, AgentSettingsLocks>,
+   key: String,
+   value: Value,
+   platform: Option<Platform>,
+) -> Result<(), AgentSettingsError> {
+   check_agent_default(&key, &value)?;
+   let path = platform_key(platform, &format!("{}.{}", AGENT_DEFAULTS_TABLE, key));
+   let changed = update_config_file(
+      &locks,
+      &athas_settings_path()?,
+      ConfigFormat::Toml,
+      WriteOptions::default(),
+      |root| {
+         if value.is_null() {
+            delete_nested_value(root, &path, true).map(drop)
+         } else {
+            set_nested_value(root, &path, value)
+         }
+      },
+   )
+   .await?;
+   if changed {
+      log::info!("Updated agent default {}", key);
+   }
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_defaults_fill_only_missing_settings() {
+      let settings = AgentSettings {
+         model: Some("gpt-5".into()),
+         version: Some("v1".into()),
+         ..AgentSettings::default()
+      };
+      let defaults = AgentSettings {
+         model: Some("o3".into()),
+         reasoning_effort: Some("medium".into()),
+         ..AgentSettings::default()
+      };
+
+      let result = with_defaults(settings, defaults);
+
+      assert_eq!(result.settings.model.as_deref(), Some("gpt-5"));
+      assert_eq!(result.settings.reasoning_effort.as_deref(), Some("medium"));
+      assert_eq!(result.settings.version.as_deref(), Some("v1"));
+      assert_eq!(
+         result.origins,
+         AgentSettingsOrigins {
+            model: Some(SettingOrigin::File),
+            reasoning_effort: Some(SettingOrigin::Default),
+            ..AgentSettingsOrigins::default()
+         }
+      );
+      assert!(result.overridden);
+   }
+
+   #[test]
+   fn test_nothing_overridden_without_file_values() {
+      let defaults = AgentSettings {
+         reasoning_effort: Some("auto".into()),
+         ..AgentSettings::default()
+      };
+      let result = with_defaults(AgentSettings::default(), defaults);
+      assert!(!result.overridden);
+      assert_eq!(
+         result.origins.reasoning_effort,
+         Some(SettingOrigin::Default)
+      );
+   }
+
+   fn capabilities(reasoning_values: &[&str]) -> AgentCapabilities {
+      AgentCapabilities {
+         supports_reasoning: true,
+         reasoning_values: reasoning_values.iter().map(|v| v.to_string()).collect(),
+         supports_preview: false,
+         supports_temperature: false,
+         supports_mcp: false,
+      }
+   }
+
+   #[test]
+   fn test_applicable_defaults_follow_keys_and_capabilities() {
+      let defaults = AgentSettings {
+         preview_features: Some(true),
+         reasoning_effort: Some("high".into()),
+         temperature: Some(0.2),
+         max_tokens: Some(4096),
+         ..AgentSettings::default()
+      };
+      let keys = SettingsKeys {
+         model: "model".into(),
+         preview: Some("preview".into()),
+         reasoning: Some("reasoning".into()),
+         temperature: Some("temperature".into()),
+         ..SettingsKeys::default()
+      };
+
+      let applied = applicable_defaults(
+         defaults.clone(),
+         &keys,
+         Some(&capabilities(&["low", "high"])),
+      );
+      assert_eq!(applied.reasoning_effort.as_deref(), Some("high"));
+      // No capability, and no key for max tokens
+      assert_eq!(applied.preview_features, None);
+      assert_eq!(applied.temperature, None);
+      assert_eq!(applied.max_tokens, None);
+
+      let applied = applicable_defaults(defaults.clone(), &keys, Some(&capabilities(&["low"])));
+      assert_eq!(applied.reasoning_effort, None);
+
+      let applied = applicable_defaults(defaults, &keys, None);
+      assert_eq!(applied.preview_features, Some(true));
+      assert_eq!(applied.temperature, Some(0.2));
+   }
+
+   #[test]
+   fn test_agent_file_overrides_inherited_defaults() {
+      let defaults = AgentSettings {
+         reasoning_effort: Some("high".into()),
+         allowed_tools: Some(vec!["Read".into()]),
+         ..AgentSettings::default()
+      };
+      let settings = AgentSettings {
+         reasoning_effort: Some("low".into()),
+         ..AgentSettings::default()
+      };
+
+      let result = with_defaults(settings, defaults);
+      assert_eq!(result.settings.reasoning_effort.as_deref(), Some("low"));
+      assert_eq!(result.origins.reasoning_effort, Some(SettingOrigin::File));
+      assert_eq!(result.origins.allowed_tools, Some(SettingOrigin::Default));
+   }
+
+   #[test]
+   fn test_check_agent_default() {
+      assert!(check_agent_default("reasoningEffort", &json!("high")).is_ok());
+      assert!(check_agent_default("maxTokens", &Value::Null).is_ok());
+      assert!(check_agent_default("allowedTools", &json!(["Read", "Edit"])).is_ok());
+      assert!(matches!(
+         check_agent_default("model", &json!("o3")),
+         Err(AgentSettingsError::InvalidAgentDefault { .. })
+      ));
+      assert!(matches!(
+         check_agent_default("maxTokens", &json!("lots")),
+         Err(AgentSettingsError::InvalidAgentDefault { .. })
+      ));
+   }
+
+   #[test]
+   fn test_read_agent_defaults_table() {
+      let value = json!({
+         "agents": { "codex": { "enabled": true } },
+         "agent_defaults": { "reasoningEffort": "high", "temperature": 0.5 }
+      });
+      let defaults = agent_defaults_keys().read(&value);
+      assert_eq!(defaults.reasoning_effort.as_deref(), Some("high"));
+      assert_eq!(defaults.temperature, Some(0.5));
+      assert_eq!(defaults.max_tokens, None);
+   }
+}
@@ -0,0 +1,99 @@
+use super::{
+   custom_agents::all_agents, enabled::enabled_flags, error::AgentSettingsError,
+   storage::AgentSettingsLocks,
+};
+use crate::features::ai::acp::find_binary;
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::time::Duration;
+use tauri::{State, command};
+
+/// How long to look for one binary before reporting it as not installed. PATH entries on slow
+/// network mounts can otherwise stall the lookup indefinitely.
+const DETECTION_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledAgent {
+   pub agent_id: String,
+   pub installed: bool,
+   pub binary_path: Option<String>,
+}
+
+/// Look for `binary_name` on PATH and in common install locations without blocking the runtime
+pub(super) async fn detect_binary(agent_id: String, binary_name: String) -> InstalledAgent {
+   let lookup = tokio::task::spawn_blocking({
+      let binary_name = binary_name.clone();
+      move || find_binary(&binary_name)
+   });
+   let binary_path = match tokio::time::timeout(DETECTION_TIMEOUT, lookup).await {
+      Ok(Ok(path)) => path,
+      Ok(Err(e)) => {
+         log::warn!("Looking for {} failed: {}", binary_name, e);
+         None
+      }
+      Err(_) => {
+         log::warn!(
+            "Looking for {} took longer than {:?}, treating it as not installed",
+            binary_name,
+            DETECTION_TIMEOUT
+         );
+         None
+      }
+   };
+
+   InstalledAgent {
+      agent_id,
+      installed: binary_path.is_some(),
+      binary_path: binary_path.map(|path| path.display().to_string()),
+   }
+}
+
+/// Check which known agents, built-in and custom, have their CLI installed. Binaries are looked
+/// up concurrently, each within a bounded time. Agents the user disabled are skipped unless
+/// `include_disabled` is set.
+#[command]
+pub async fn detect_installed_agents(
+   locks: State<'_, AgentSettingsLocks>,
+   include_disabled: Option<bool>,
+) -> Result<Vec<InstalledAgent>, AgentSettingsError> {
+   let flags = enabled_flags(&locks).await?;
+   let include_disabled = include_disabled.unwrap_or(false);
+
+   let detected = join_all(
+      all_agents(&locks)
+         .await?
+         .into_iter()
+         .filter(|agent| include_disabled || flags.get(&agent.id) != Some(&false))
+         .map(|agent| detect_binary(agent.id, agent.binary_name)),
+   )
+   .await;
+   log::debug!(
+      "Detected {} of {} agent CLIs",
+      detected.iter().filter(|agent| agent.installed).count(),
+      detected.len()
+   );
+   Ok(detected)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[tokio::test]
+   async fn test_detect_binary() {
+      let missing = detect_binary("none".into(), "athas-no-such-binary".into()).await;
+      assert_eq!(
+         missing,
+         InstalledAgent {
+            agent_id: "none".into(),
+            installed: false,
+            binary_path: None,
+         }
+      );
+
+      let cargo = detect_binary("cargo".into(), "cargo".into()).await;
+      assert!(cargo.installed);
+      assert!(cargo.binary_path.unwrap().contains("cargo"));
+   }
+}
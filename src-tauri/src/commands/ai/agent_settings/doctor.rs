@@ -0,0 +1,572 @@
+use super::{
+   custom_agents::find_agent,
+   detect::detect_binary,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   keys::get_nested_value,
+   paths::resolve_settings_path,
+   storage::{AgentSettingsLocks, ConfigFile, read_config_file},
+   version::{parse_version, run_version_command},
+};
+use crate::commands::ai::{
+   agent_registry::KnownAgent,
+   credentials::{CredentialSource, CredentialStore},
+   model_catalog::ModelProvider,
+};
+use futures_util::future::join_all;
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::BTreeSet, path::Path};
+use tauri::{State, command};
+
+/// Keys agents keep their MCP servers under
+const MCP_SERVER_KEYS: [&str; 3] = ["mcpServers", "mcp_servers", "mcp"];
+
+/// The checks `diagnose_agent` runs, in the order it reports them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DoctorCheck {
+   Binary,
+   Version,
+   ConfigExists,
+   ConfigParses,
+   ModelKey,
+   EnvVars,
+   ApiKey,
+   McpServers,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticStatus {
+   Ok,
+   Warn,
+   Fail,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+   pub check: DoctorCheck,
+   pub status: DiagnosticStatus,
+   pub detail: String,
+   /// What the user can do about a warning or failure
+   pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+   fn ok(check: DoctorCheck, detail: impl Into<String>) -> Self {
+      Self {
+         check,
+         status: DiagnosticStatus::Ok,
+         detail: detail.into(),
+         suggestion: None,
+      }
+   }
+
+   fn problem(
+      check: DoctorCheck,
+      status: DiagnosticStatus,
+      detail: impl Into<String>,
+      suggestion: impl Into<String>,
+   ) -> Self {
+      Self {
+         check,
+         status,
+         detail: detail.into(),
+         suggestion: Some(suggestion.into()),
+      }
+   }
+}
+
+fn binary_check(agent: &KnownAgent, binary_path: Option<&str>) -> Diagnostic {
+   match binary_path {
+      Some(path) => Diagnostic::ok(DoctorCheck::Binary, format!("Found {}", path)),
+      None => Diagnostic::problem(
+         DoctorCheck::Binary,
+         DiagnosticStatus::Fail,
+         format!(
+            "{} is not on PATH or in a common install location",
+            agent.binary_name
+         ),
+         format!(
+            "Install {} and make sure `{}` is on PATH",
+            agent.name, agent.binary_name
+         ),
+      ),
+   }
+}
+
+fn version_check(output: Result<String, AgentSettingsError>) -> Diagnostic {
+   match output {
+      Ok(output) => match parse_version(&output) {
+         Some(version) => Diagnostic::ok(DoctorCheck::Version, format!("Version {}", version)),
+         None => Diagnostic::problem(
+            DoctorCheck::Version,
+            DiagnosticStatus::Warn,
+            format!("Could not find a version in '{}'", output),
+            "Check that the binary on PATH is the agent CLI and not another program",
+         ),
+      },
+      Err(e) => Diagnostic::problem(
+         DoctorCheck::Version,
+         DiagnosticStatus::Fail,
+         e.to_string(),
+         "Run the agent once from a terminal to see whether it starts",
+      ),
+   }
+}
+
+/// Whether the config file exists and parses. A missing file only warns, since agents run on
+/// their defaults without one.
+fn config_file_checks(
+   path: &Path,
+   file: &Result<Option<ConfigFile>, AgentSettingsError>,
+) -> Vec<Diagnostic> {
+   let exists = Diagnostic::ok(DoctorCheck::ConfigExists, path.display().to_string());
+   match file {
+      Ok(Some(_)) => vec![
+         exists,
+         Diagnostic::ok(DoctorCheck::ConfigParses, "The config file is valid"),
+      ],
+      Ok(None) => vec![Diagnostic::problem(
+         DoctorCheck::ConfigExists,
+         DiagnosticStatus::Warn,
+         format!("{} does not exist", path.display()),
+         "The agent will use its defaults; save settings in Athas to create the file",
+      )],
+      Err(e @ AgentSettingsError::Parse { line, .. }) => vec![
+         exists,
+         Diagnostic::problem(
+            DoctorCheck::ConfigParses,
+            DiagnosticStatus::Fail,
+            e.to_string(),
+            match line {
+               Some(line) => format!(
+                  "Fix the syntax error on line {} of {}",
+                  line,
+                  path.display()
+               ),
+               None => format!("Fix the syntax of {}", path.display()),
+            },
+         ),
+      ],
+      Err(e) => vec![Diagnostic::problem(
+         DoctorCheck::ConfigExists,
+         DiagnosticStatus::Fail,
+         e.to_string(),
+         format!("Check the permissions of {}", path.display()),
+      )],
+   }
+}
+
+fn model_key_check(value: &Value, model_key: &str) -> Diagnostic {
+   match get_nested_value(value, model_key) {
+      Some(Value::String(model)) => {
+         Diagnostic::ok(DoctorCheck::ModelKey, format!("{} = {}", model_key, model))
+      }
+      Some(other) => Diagnostic::problem(
+         DoctorCheck::ModelKey,
+         DiagnosticStatus::Fail,
+         format!("{} is {} rather than a model name", model_key, other),
+         format!("Set {} to a model id", model_key),
+      ),
+      None => Diagnostic::problem(
+         DoctorCheck::ModelKey,
+         DiagnosticStatus::Warn,
+         format!("{} is not set", model_key),
+         "The agent will use its default model; pick one in Athas to pin it",
+      ),
+   }
+}
+
+/// Environment variables a config refers to: `${NAME}`, `${env:NAME}` and `{env:NAME}` in string
+/// values, and the values of `env_key` entries
+pub(super) fn env_references(value: &Value, found: &mut BTreeSet<String>) {
+   match value {
+      Value::Object(map) => {
+         for (key, child) in map {
+            if key == "env_key"
+               && let Value::String(name) = child
+            {
+               found.insert(name.clone());
+            }
+            env_references(child, found);
+         }
+      }
+      Value::Array(items) => items.iter().for_each(|item| env_references(item, found)),
+      Value::String(text) => {
+         let mut rest = text.as_str();
+         while let Some(start) = rest.find('{') {
+            let after = &rest[start + 1..];
+            let Some(end) = after.find('}') else { break };
+            let name = after[..end].strip_prefix("env:").unwrap_or(&after[..end]);
+            let braced_var = start > 0 && rest[..start].ends_with('$');
+            let env_form = after.starts_with("env:");
+            if (braced_var || env_form)
+               && !name.is_empty()
+               && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+               found.insert(name.to_string());
+            }
+            rest = &after[end + 1..];
+         }
+      }
+      _ => {}
+   }
+}
+
+fn env_vars_check(value: &Value, is_set: &impl Fn(&str) -> bool) -> Diagnostic {
+   let mut referenced = BTreeSet::new();
+   env_references(value, &mut referenced);
+   let missing: Vec<_> = referenced
+      .iter()
+      .filter(|name| !is_set(name))
+      .cloned()
+      .collect();
+   if missing.is_empty() {
+      return Diagnostic::ok(
+         DoctorCheck::EnvVars,
+         format!(
+            "{} referenced environment variables are set",
+            referenced.len()
+         ),
+      );
+   }
+   Diagnostic::problem(
+      DoctorCheck::EnvVars,
+      DiagnosticStatus::Fail,
+      format!("Not set: {}", missing.join(", ")),
+      "Export the variables in your shell profile and restart Athas",
+   )
+}
+
+/// Whether Athas has a key stored for one of the agent's providers, or else whether an API key
+/// variable is set. Agents can also log in through their CLI, so a missing key only warns.
+fn api_key_check(
+   agent: &KnownAgent,
+   stored: &impl Fn(ModelProvider) -> Option<CredentialSource>,
+   is_set: &impl Fn(&str) -> bool,
+) -> Option<Diagnostic> {
+   if let Some((provider, source)) = agent
+      .providers
+      .iter()
+      .find_map(|&provider| Some((provider, stored(provider)?)))
+   {
+      return Some(Diagnostic::ok(
+         DoctorCheck::ApiKey,
+         format!("{} key stored in {}", provider.id(), source.describe()),
+      ));
+   }
+
+   let candidates: Vec<_> = agent
+      .providers
+      .iter()
+      .flat_map(|provider| provider.api_key_env_vars())
+      .copied()
+      .collect();
+   if candidates.is_empty() {
+      return None;
+   }
+   Some(match candidates.iter().find(|name| is_set(name)) {
+      Some(name) => Diagnostic::ok(DoctorCheck::ApiKey, format!("{} is set", name)),
+      None => Diagnostic::problem(
+         DoctorCheck::ApiKey,
+         DiagnosticStatus::Warn,
+         format!("None of {} is set", candidates.join(", ")),
+         format!(
+            "Log in with `{}`, add an API key in Athas or set an API key environment variable",
+            agent.binary_name
+         ),
+      ),
+   })
+}
+
+/// `(server name, command)` for every MCP server in the config that is started by a command
+/// rather than reached by URL. OpenCode gives the command as an array with its arguments.
+fn mcp_commands(value: &Value) -> Vec<(String, String)> {
+   MCP_SERVER_KEYS
+      .iter()
+      .filter_map(|key| value.get(key)?.as_object())
+      .flatten()
+      .filter_map(|(name, server)| {
+         let command = match server.get("command")? {
+            Value::String(command) => command,
+            Value::Array(parts) => parts.first()?.as_str()?,
+            _ => return None,
+         };
+         Some((name.clone(), command.to_string()))
+      })
+      .collect()
+}
+
+async fn mcp_servers_check(value: &Value) -> Option<Diagnostic> {
+   let commands = mcp_commands(value);
+   if commands.is_empty() {
+      return None;
+   }
+
+   let lookups = commands.iter().map(|(name, command)| async move {
+      let found = if Path::new(command).is_absolute() {
+         Path::new(command).exists()
+      } else {
+         detect_binary(name.clone(), command.clone()).await.installed
+      };
+      (!found).then(|| format!("{} ({})", name, command))
+   });
+   let missing: Vec<_> = join_all(lookups).await.into_iter().flatten().collect();
+   Some(if missing.is_empty() {
+      Diagnostic::ok(
+         DoctorCheck::McpServers,
+         format!("Commands of {} MCP servers found", commands.len()),
+      )
+   } else {
+      Diagnostic::problem(
+         DoctorCheck::McpServers,
+         DiagnosticStatus::Fail,
+         format!("Commands not found: {}", missing.join(", ")),
+         "Install the missing commands or remove the servers from the config",
+      )
+   })
+}
+
+async fn diagnose(
+   locks: &AgentSettingsLocks,
+   agent: &KnownAgent,
+   stored: impl Fn(ModelProvider) -> Option<CredentialSource>,
+   is_set: impl Fn(&str) -> bool,
+) -> Result<Vec<Diagnostic>, AgentSettingsError> {
+   let mut diagnostics = Vec::new();
+
+   let binary_path = detect_binary(agent.id.clone(), agent.binary_name.clone())
+      .await
+      .binary_path;
+   diagnostics.push(binary_check(agent, binary_path.as_deref()));
+   if let Some(binary_path) = &binary_path {
+      let output =
+         run_version_command(&agent.id, Path::new(binary_path), &agent.version_args).await;
+      diagnostics.push(version_check(output));
+   }
+
+   let path = resolve_settings_path(&agent.settings_path)?;
+   let file = read_config_file(locks, &path, ConfigFormat::from_path(&agent.settings_path)).await;
+   diagnostics.extend(config_file_checks(&path, &file));
+   let value = file.ok().flatten().map(|file| file.value);
+   if let Some(value) = &value {
+      diagnostics.push(model_key_check(value, &agent.model_key));
+      diagnostics.push(env_vars_check(value, &is_set));
+   }
+   diagnostics.extend(api_key_check(agent, &stored, &is_set));
+   if let Some(value) = &value {
+      diagnostics.extend(mcp_servers_check(value).await);
+   }
+   Ok(diagnostics)
+}
+
+/// Run a series of checks on an agent's setup (binary, version, config file, model, environment
+/// variables, MCP server commands) and report each with a suggestion for fixing it. Every check
+/// that runs a process or searches PATH has its own timeout, so the whole run is bounded.
+#[command]
+pub async fn diagnose_agent(
+   locks: State<'_, AgentSettingsLocks>,
+   credentials: State<'_, CredentialStore>,
+   agent_id: String,
+) -> Result<Vec<Diagnostic>, AgentSettingsError> {
+   let Some(agent) = find_agent(&locks, &agent_id).await? else {
+      return Err(AgentSettingsError::UnknownAgent { agent_id });
+   };
+   let stored = |provider: ModelProvider| {
+      credentials
+         .source(provider.id())
+         .inspect_err(|e| log::warn!("Failed to read credentials index: {}", e))
+         .ok()
+         .flatten()
+   };
+   let diagnostics = diagnose(&locks, &agent, stored, |name| {
+      std::env::var_os(name).is_some()
+   })
+   .await?;
+   log::info!(
+      "Diagnosed agent {}: {} failures, {} warnings",
+      agent_id,
+      diagnostics
+         .iter()
+         .filter(|d| d.status == DiagnosticStatus::Fail)
+         .count(),
+      diagnostics
+         .iter()
+         .filter(|d| d.status == DiagnosticStatus::Warn)
+         .count()
+   );
+   Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_registry::find_known_agent;
+   use serde_json::json;
+
+   fn statuses(diagnostics: &[Diagnostic]) -> Vec<(DoctorCheck, DiagnosticStatus)> {
+      diagnostics.iter().map(|d| (d.check, d.status)).collect()
+   }
+
+   #[test]
+   fn test_binary_and_version_checks() {
+      let codex = find_known_agent("codex-cli").unwrap();
+      assert_eq!(
+         binary_check(&codex, Some("/usr/bin/codex")).status,
+         DiagnosticStatus::Ok
+      );
+      let missing = binary_check(&codex, None);
+      assert_eq!(missing.status, DiagnosticStatus::Fail);
+      assert!(missing.suggestion.unwrap().contains("`codex`"));
+
+      assert_eq!(
+         version_check(Ok("codex-cli 0.46.0".into())).detail,
+         "Version 0.46.0"
+      );
+      assert_eq!(
+         version_check(Ok("usage: codex".into())).status,
+         DiagnosticStatus::Warn
+      );
+      let timed_out = version_check(Err(AgentSettingsError::CommandTimedOut {
+         agent_id: "codex-cli".into(),
+         command: "codex --version".into(),
+         seconds: 5,
+      }));
+      assert_eq!(timed_out.status, DiagnosticStatus::Fail);
+   }
+
+   async fn check_fixture(dir: &Path, name: &str, content: Option<&str>) -> Vec<Diagnostic> {
+      let path = dir.join(name);
+      if let Some(content) = content {
+         std::fs::write(&path, content).unwrap();
+      }
+      let locks = AgentSettingsLocks::new();
+      let file = read_config_file(&locks, &path, ConfigFormat::from_path(name)).await;
+      config_file_checks(&path, &file)
+   }
+
+   #[tokio::test]
+   async fn test_config_file_checks() {
+      let dir = tempfile::tempdir().unwrap();
+      let read = |name, content| check_fixture(dir.path(), name, content);
+      assert_eq!(
+         statuses(&read("ok.toml", Some("model = \"o3\"\n")).await),
+         vec![
+            (DoctorCheck::ConfigExists, DiagnosticStatus::Ok),
+            (DoctorCheck::ConfigParses, DiagnosticStatus::Ok),
+         ]
+      );
+      assert_eq!(
+         statuses(&read("missing.json", None).await),
+         vec![(DoctorCheck::ConfigExists, DiagnosticStatus::Warn)]
+      );
+      let broken = read(
+         "broken.toml",
+         Some("model = \"o3\"\nmodel_reasoning_effort = \n"),
+      )
+      .await;
+      assert_eq!(
+         statuses(&broken),
+         vec![
+            (DoctorCheck::ConfigExists, DiagnosticStatus::Ok),
+            (DoctorCheck::ConfigParses, DiagnosticStatus::Fail),
+         ]
+      );
+      assert!(broken[1].suggestion.as_ref().unwrap().contains("line 2"));
+   }
+
+   #[test]
+   fn test_model_key_check() {
+      let config = json!({ "model": { "name": "gemini-2.5-pro" }, "broken": 3 });
+      assert_eq!(
+         model_key_check(&config, "model.name").detail,
+         "model.name = gemini-2.5-pro"
+      );
+      assert_eq!(
+         model_key_check(&config, "broken").status,
+         DiagnosticStatus::Fail
+      );
+      assert_eq!(
+         model_key_check(&config, "missing").status,
+         DiagnosticStatus::Warn
+      );
+   }
+
+   #[test]
+   fn test_env_vars_check() {
+      let config = json!({
+         "model_providers": { "proxy": { "env_key": "PROXY_KEY" } },
+         "mcpServers": {
+            "github": { "env": { "TOKEN": "${GITHUB_TOKEN}" } },
+            "db": { "url": "{env:DATABASE_URL}/mcp" },
+         },
+         "template": "{not_env} and $HOME",
+      });
+      let mut referenced = BTreeSet::new();
+      env_references(&config, &mut referenced);
+      assert_eq!(
+         referenced.into_iter().collect::<Vec<_>>(),
+         vec!["DATABASE_URL", "GITHUB_TOKEN", "PROXY_KEY"]
+      );
+
+      let check = env_vars_check(&config, &|name| name == "GITHUB_TOKEN");
+      assert_eq!(check.status, DiagnosticStatus::Fail);
+      assert_eq!(check.detail, "Not set: DATABASE_URL, PROXY_KEY");
+      assert_eq!(
+         env_vars_check(&config, &|_| true).status,
+         DiagnosticStatus::Ok
+      );
+   }
+
+   #[test]
+   fn test_api_key_check() {
+      let gemini = find_known_agent("gemini-cli").unwrap();
+      let nothing_stored = |_| None;
+      let check =
+         api_key_check(&gemini, &nothing_stored, &|name| name == "GOOGLE_API_KEY").unwrap();
+      assert_eq!(check.detail, "GOOGLE_API_KEY is set");
+      let check = api_key_check(&gemini, &nothing_stored, &|_| false).unwrap();
+      assert_eq!(check.status, DiagnosticStatus::Warn);
+
+      let stored =
+         |provider| (provider == ModelProvider::Google).then_some(CredentialSource::EncryptedFile);
+      let check = api_key_check(&gemini, &stored, &|_| true).unwrap();
+      assert_eq!(
+         check.detail,
+         "google key stored in the encrypted credentials file"
+      );
+
+      let mut custom = gemini.clone();
+      custom.providers.clear();
+      assert!(api_key_check(&custom, &stored, &|_| false).is_none());
+   }
+
+   #[tokio::test]
+   async fn test_mcp_servers_check() {
+      let config = json!({
+         "mcpServers": {
+            "build": { "command": "cargo", "args": ["run"] },
+            "remote": { "url": "https://example.com/mcp" },
+         },
+         "mcp": { "broken": { "command": ["athas-no-such-binary", "--stdio"] } },
+      });
+      assert_eq!(
+         mcp_commands(&config),
+         vec![
+            ("build".to_string(), "cargo".to_string()),
+            ("broken".to_string(), "athas-no-such-binary".to_string()),
+         ]
+      );
+
+      let check = mcp_servers_check(&config).await.unwrap();
+      assert_eq!(check.status, DiagnosticStatus::Fail);
+      assert_eq!(
+         check.detail,
+         "Commands not found: broken (athas-no-such-binary)"
+      );
+      assert!(mcp_servers_check(&json!({ "model": "o3" })).await.is_none());
+   }
+}
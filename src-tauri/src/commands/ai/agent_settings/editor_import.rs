@@ -0,0 +1,853 @@
+use super::{
+   custom_providers::{load_custom_providers, save_to as save_provider, validate_provider},
+   enabled::athas_settings_path,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   mcp::{McpServer, McpTransport, known_agent, read_servers, servers_key, writable_path},
+   mcp_import::{McpDesktopImport, import_into},
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   storage::{AgentSettingsLocks, WriteOptions, read_config_file, update_config_file},
+};
+use crate::commands::ai::{
+   agent_registry::{KnownAgent, McpServerFormat, McpServersKey},
+   credentials::CredentialStore,
+   providers::{CustomProvider, is_built_in_provider},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tauri::{State, command};
+
+/// Continue's config, the YAML format it moved to before the JSON one it replaced
+const CONTINUE_CONFIGS: [&str; 2] = ["~/.continue/config.yaml", "~/.continue/config.json"];
+
+/// Cursor's global MCP servers, laid out like Claude Code's
+const CURSOR_MCP_CONFIG: &str = "~/.cursor/mcp.json";
+
+/// OpenAI-compatible endpoints of Continue providers that work without an `apiBase`
+const CONTINUE_PROVIDER_ENDPOINTS: [(&str, &str); 6] = [
+   ("deepseek", "https://api.deepseek.com/v1"),
+   ("groq", "https://api.groq.com/openai/v1"),
+   ("lmstudio", "http://localhost:1234/v1"),
+   ("mistral", "https://api.mistral.ai/v1"),
+   ("openrouter", "https://openrouter.ai/api/v1"),
+   ("together", "https://api.together.xyz/v1"),
+];
+
+/// Top-level keys of Continue's config that describe the file rather than settings
+const CONTINUE_METADATA_KEYS: [&str; 4] = ["name", "version", "schema", "$schema"];
+
+/// An editor whose configuration can be carried over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExternalEditor {
+   Continue,
+   Cursor,
+}
+
+/// A model of the source editor on a provider Athas has built in, usable without importing
+/// anything once that provider has an API key
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalModel {
+   pub name: String,
+   pub provider: String,
+   pub model: String,
+}
+
+/// An entry of the source config Athas has no place for, and why
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnmappedEntry {
+   pub entry: String,
+   pub reason: String,
+}
+
+/// What importing an editor's configuration does, or did once `applied` is set
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalEditorImport {
+   pub source: ExternalEditor,
+   /// The files the configuration was looked for in
+   pub source_paths: Vec<String>,
+   /// Whether any of them exists. When none does, nothing else is set.
+   pub found: bool,
+   /// Custom providers added for models on OpenAI-compatible endpoints
+   pub providers: Vec<CustomProvider>,
+   /// Providers the import would add whose id is already registered, left as they are
+   pub existing_providers: Vec<String>,
+   /// Providers whose API key the source config holds and that are stored in the credential
+   /// store, as no key is stored for them yet
+   pub api_keys: Vec<String>,
+   pub builtin_models: Vec<ExternalModel>,
+   pub mcp_servers: Vec<McpServer>,
+   /// How `mcp_servers` are written into the target agent, `None` without one
+   pub mcp: Option<McpDesktopImport>,
+   pub unmapped: Vec<UnmappedEntry>,
+   pub applied: bool,
+}
+
+impl ExternalEditorImport {
+   fn new(source: ExternalEditor, source_paths: Vec<String>) -> Self {
+      Self {
+         source,
+         source_paths,
+         found: false,
+         providers: Vec::new(),
+         existing_providers: Vec::new(),
+         api_keys: Vec::new(),
+         builtin_models: Vec::new(),
+         mcp_servers: Vec::new(),
+         mcp: None,
+         unmapped: Vec::new(),
+         applied: false,
+      }
+   }
+
+   fn unmapped(&mut self, entry: impl Into<String>, reason: impl Into<String>) {
+      self.unmapped.push(UnmappedEntry {
+         entry: entry.into(),
+         reason: reason.into(),
+      });
+   }
+}
+
+/// An entry of Continue's `models`, in either config format
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ContinueModel {
+   /// `title` in config.json, `name` in config.yaml
+   title: Option<String>,
+   name: Option<String>,
+   provider: Option<String>,
+   model: Option<String>,
+   api_base: Option<String>,
+   api_key: Option<String>,
+   /// A block pulled from Continue Hub rather than defined in the file
+   uses: Option<String>,
+}
+
+/// An MCP server of Continue's config: an entry of `mcpServers` in config.yaml, or the
+/// `transport` of one in config.json's `experimental.modelContextProtocolServers`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ContinueMcpServer {
+   name: Option<String>,
+   #[serde(rename = "type")]
+   transport: Option<String>,
+   command: Option<String>,
+   args: Vec<String>,
+   env: HashMap<String, String>,
+   url: Option<String>,
+}
+
+/// Athas's id of a built-in provider as Continue names it
+fn builtin_provider(provider: &str) -> Option<&'static str> {
+   match provider {
+      "anthropic" => Some("anthropic"),
+      "openai" => Some("openai"),
+      "gemini" => Some("google"),
+      "ollama" => Some("ollama"),
+      _ => None,
+   }
+}
+
+/// `name` turned into a usable provider id: lowercase, with runs of other characters as `-`
+fn provider_id(name: &str) -> String {
+   let mut id = String::new();
+   for c in name.trim().to_lowercase().chars() {
+      if c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '.') {
+         id.push(c);
+      } else if !id.is_empty() && !id.ends_with('-') {
+         id.push('-');
+      }
+   }
+   id.trim_end_matches('-').to_string()
+}
+
+/// Whether `key` refers to a secret of Continue's, `${{ secrets.NAME }}`, instead of being one
+fn is_secret_reference(key: &str) -> bool {
+   key.contains("${{")
+}
+
+/// The import plan for one editor's config, before it is checked against what Athas has
+struct Plan {
+   import: ExternalEditorImport,
+   /// API keys by provider id, kept out of the report
+   api_keys: Vec<(String, String)>,
+}
+
+impl Plan {
+   /// The provider for `continue_provider` models at `base_url`, reusing one planned for the
+   /// same endpoint
+   fn provider_for(
+      &mut self,
+      display_name: &str,
+      continue_provider: &str,
+      base_url: &str,
+   ) -> String {
+      let base_url = base_url.trim().trim_end_matches('/');
+      if let Some(provider) = self
+         .import
+         .providers
+         .iter()
+         .find(|provider| provider.base_url.trim_end_matches('/') == base_url)
+      {
+         return provider.id.clone();
+      }
+      let name = if builtin_provider(continue_provider).is_some() || continue_provider.is_empty() {
+         display_name
+      } else {
+         continue_provider
+      };
+      let base_id = match provider_id(name) {
+         id if id.is_empty() => "imported".to_string(),
+         id => id,
+      };
+      let mut id = base_id.clone();
+      let mut suffix = 1;
+      while is_built_in_provider(&id) || self.import.providers.iter().any(|p| p.id == id) {
+         suffix += 1;
+         id = format!("{}-{}", base_id, suffix);
+      }
+      self.import.providers.push(CustomProvider {
+         id: id.clone(),
+         display_name: display_name.to_string(),
+         base_url: base_url.to_string(),
+         api_key_header: None,
+         models_endpoint: None,
+         chat_endpoint: None,
+      });
+      id
+   }
+
+   fn add_api_key(&mut self, entry: &str, provider_id: &str, key: Option<&str>) {
+      let Some(key) = key.map(str::trim).filter(|key| !key.is_empty()) else {
+         return;
+      };
+      if is_secret_reference(key) {
+         self.import.unmapped(
+            format!("{}.apiKey", entry),
+            "refers to a Continue secret, which Athas can't read",
+         );
+      } else if !self.api_keys.iter().any(|(id, _)| id == provider_id) {
+         self
+            .api_keys
+            .push((provider_id.to_string(), key.to_string()));
+      }
+   }
+
+   fn add_model(&mut self, entry: String, model: ContinueModel) {
+      if let Some(uses) = model.uses {
+         self.import.unmapped(
+            entry,
+            format!("refers to the Continue Hub block '{}'", uses),
+         );
+         return;
+      }
+      let provider = model.provider.unwrap_or_default();
+      let Some(model_id) = model.model.filter(|model| !model.trim().is_empty()) else {
+         self.import.unmapped(entry, "has no model");
+         return;
+      };
+      let name = model
+         .title
+         .or(model.name)
+         .unwrap_or_else(|| model_id.clone());
+
+      let base_url = model
+         .api_base
+         .filter(|url| !url.trim().is_empty())
+         .or_else(|| {
+            CONTINUE_PROVIDER_ENDPOINTS
+               .iter()
+               .find(|(id, _)| *id == provider)
+               .map(|(_, url)| url.to_string())
+         });
+      let provider_id = match (base_url, builtin_provider(&provider)) {
+         (Some(base_url), _) => self.provider_for(&name, &provider, &base_url),
+         (None, Some(builtin)) => {
+            self.import.builtin_models.push(ExternalModel {
+               name,
+               provider: builtin.to_string(),
+               model: model_id,
+            });
+            builtin.to_string()
+         }
+         (None, None) => {
+            self.import.unmapped(
+               entry,
+               format!(
+                  "Continue's '{}' provider has no OpenAI-compatible endpoint Athas knows",
+                  provider
+               ),
+            );
+            return;
+         }
+      };
+      // Ollama runs locally without a key
+      if provider_id != "ollama" {
+         self.add_api_key(&entry, &provider_id, model.api_key.as_deref());
+      }
+   }
+
+   fn add_mcp_server(&mut self, entry: String, server: ContinueMcpServer, index: usize) {
+      let name = server
+         .name
+         .clone()
+         .or_else(|| {
+            let command = server.command.as_deref()?;
+            let file_name = command.rsplit(['/', '\\']).next()?;
+            Some(file_name.to_string())
+         })
+         .unwrap_or_else(|| format!("server-{}", index + 1));
+      let transport = match server.transport.as_deref() {
+         None | Some("stdio") => McpTransport::Stdio,
+         Some("sse") => McpTransport::Sse,
+         Some("streamable-http" | "http") => McpTransport::Http,
+         Some(other) => {
+            self
+               .import
+               .unmapped(entry, format!("uses the unknown transport '{}'", other));
+            return;
+         }
+      };
+      self.import.mcp_servers.push(McpServer {
+         name,
+         command: server.command,
+         args: server.args,
+         env: server.env,
+         transport,
+         url: server.url,
+      });
+   }
+}
+
+/// What of Continue's config at `path`, holding `config`, maps onto Athas
+fn plan_continue(config: &Value, path: &str) -> Plan {
+   let mut plan = Plan {
+      import: ExternalEditorImport::new(ExternalEditor::Continue, vec![path.to_string()]),
+      api_keys: Vec::new(),
+   };
+   plan.import.found = true;
+   let Some(config) = config.as_object() else {
+      return plan;
+   };
+
+   for (key, value) in config {
+      match key.as_str() {
+         "models" => {
+            for (index, model) in value.as_array().into_iter().flatten().enumerate() {
+               let entry = format!("models[{}]", index);
+               match serde_json::from_value::<ContinueModel>(model.clone()) {
+                  Ok(model) => plan.add_model(entry, model),
+                  Err(e) => plan.import.unmapped(entry, e.to_string()),
+               }
+            }
+         }
+         "mcpServers" => {
+            for (index, server) in value.as_array().into_iter().flatten().enumerate() {
+               let entry = format!("mcpServers[{}]", index);
+               match serde_json::from_value::<ContinueMcpServer>(server.clone()) {
+                  Ok(server) => plan.add_mcp_server(entry, server, index),
+                  Err(e) => plan.import.unmapped(entry, e.to_string()),
+               }
+            }
+         }
+         "experimental" => {
+            for (name, value) in value.as_object().into_iter().flatten() {
+               if name != "modelContextProtocolServers" {
+                  plan.import.unmapped(
+                     format!("experimental.{}", name),
+                     "Athas has no equivalent setting",
+                  );
+                  continue;
+               }
+               for (index, server) in value.as_array().into_iter().flatten().enumerate() {
+                  let entry = format!("experimental.modelContextProtocolServers[{}]", index);
+                  let transport = server.get("transport").cloned().unwrap_or_default();
+                  match serde_json::from_value::<ContinueMcpServer>(transport) {
+                     Ok(server) => plan.add_mcp_server(entry, server, index),
+                     Err(e) => plan.import.unmapped(entry, e.to_string()),
+                  }
+               }
+            }
+         }
+         key if CONTINUE_METADATA_KEYS.contains(&key) => {}
+         key => plan.import.unmapped(key, "Athas has no equivalent setting"),
+      }
+   }
+   plan
+}
+
+/// What of Cursor's MCP config at `path`, holding `config`, maps onto Athas
+fn plan_cursor(config: Option<&Value>, path: &str) -> Plan {
+   let mut plan = Plan {
+      import: ExternalEditorImport::new(ExternalEditor::Cursor, vec![path.to_string()]),
+      api_keys: Vec::new(),
+   };
+   let Some(config) = config else {
+      return plan;
+   };
+   plan.import.found = true;
+   plan.import.unmapped(
+      "models",
+      "Cursor keeps its models and API keys in its own database rather than a config file",
+   );
+
+   let servers = McpServersKey {
+      key_path: "mcpServers".to_string(),
+      format: McpServerFormat::Claude,
+   };
+   plan.import.mcp_servers = read_servers(config, &servers);
+   for name in config
+      .get("mcpServers")
+      .and_then(Value::as_object)
+      .into_iter()
+      .flat_map(|servers| servers.keys())
+   {
+      if !plan
+         .import
+         .mcp_servers
+         .iter()
+         .any(|server| &server.name == name)
+      {
+         plan.import.unmapped(
+            format!("mcpServers.{}", name),
+            "is neither a command nor a URL server",
+         );
+      }
+   }
+   plan
+}
+
+/// Read the config of `source`, from the first of its files that exists
+async fn load_plan(
+   locks: &AgentSettingsLocks,
+   source: ExternalEditor,
+) -> Result<Plan, AgentSettingsError> {
+   match source {
+      ExternalEditor::Continue => {
+         let mut looked_in = Vec::new();
+         for spec in CONTINUE_CONFIGS {
+            let path = resolve_settings_path(spec)?;
+            if let Some(file) =
+               read_config_file(locks, &path, ConfigFormat::from_path(spec)).await?
+            {
+               return Ok(plan_continue(&file.value, &path.display().to_string()));
+            }
+            looked_in.push(path.display().to_string());
+         }
+         Ok(Plan {
+            import: ExternalEditorImport::new(source, looked_in),
+            api_keys: Vec::new(),
+         })
+      }
+      ExternalEditor::Cursor => {
+         let path = resolve_settings_path(CURSOR_MCP_CONFIG)?;
+         let file = read_config_file(locks, &path, ConfigFormat::Json).await?;
+         Ok(plan_cursor(
+            file.as_ref().map(|file| &file.value),
+            &path.display().to_string(),
+         ))
+      }
+   }
+}
+
+/// Drop the planned providers that can't be saved or are already registered, and the API keys
+/// already stored, so the report shows only what is written
+fn reconcile(
+   plan: &mut Plan,
+   registered: &[CustomProvider],
+   stored_key: impl Fn(&str) -> Result<bool, String>,
+) {
+   let import = &mut plan.import;
+   let planned = std::mem::take(&mut import.providers);
+   for provider in planned {
+      if registered.iter().any(|existing| existing.id == provider.id) {
+         import.existing_providers.push(provider.id);
+         continue;
+      }
+      match validate_provider(&provider) {
+         Ok(provider) => import.providers.push(provider),
+         Err(e) => import.unmapped(format!("provider {}", provider.id), e.to_string()),
+      }
+   }
+
+   plan
+      .api_keys
+      .retain(|(provider_id, _)| match stored_key(provider_id) {
+         Ok(false) => {
+            import.api_keys.push(provider_id.clone());
+            true
+         }
+         Ok(true) => {
+            import.unmapped(
+               format!("{} API key", provider_id),
+               "an API key is already stored for this provider",
+            );
+            false
+         }
+         Err(e) => {
+            import.unmapped(format!("{} API key", provider_id), e);
+            false
+         }
+      });
+}
+
+/// Sort the planned MCP servers against the target agent's config, writing them in when `apply`
+/// is set. Servers the target already has differently are conflicts left as they are.
+async fn import_mcp_servers(
+   locks: &AgentSettingsLocks,
+   roots: &AgentSettingsRoots,
+   history: &AgentSettingsHistory,
+   agent: &KnownAgent,
+   import: &mut ExternalEditorImport,
+   apply: bool,
+) -> Result<(), AgentSettingsError> {
+   let mut mcp = McpDesktopImport {
+      target_agent_id: agent.id.clone(),
+      source_path: import.source_paths.first().cloned().unwrap_or_default(),
+      found: true,
+      ..McpDesktopImport::default()
+   };
+   let format = ConfigFormat::from_path(&agent.settings_path);
+   if apply {
+      let path = writable_path(roots, agent)?;
+      let options = WriteOptions {
+         journal: Some(Journal {
+            history,
+            agent_id: &agent.id,
+         }),
+         ..WriteOptions::default()
+      };
+      mcp.wrote = update_config_file(locks, &path, format, options, |value| {
+         import_into(value, agent, &import.mcp_servers, false, &mut mcp)
+      })
+      .await?;
+   } else {
+      let path = resolve_settings_path(&agent.settings_path)?;
+      let mut value = read_config_file(locks, &path, format)
+         .await?
+         .map_or_else(|| Value::Object(Default::default()), |file| file.value);
+      import_into(&mut value, agent, &import.mcp_servers, false, &mut mcp)?;
+   }
+   import.mcp = Some(mcp);
+   Ok(())
+}
+
+/// Carry models, providers and MCP servers over from Continue (`~/.continue/config.yaml` or
+/// `config.json`) or Cursor (`~/.cursor/mcp.json`). Models on OpenAI-compatible endpoints become
+/// custom providers, API keys in the source move to the credential store, and MCP servers go to
+/// `target_agent_id`. Without `apply` nothing is written and the result is the plan; either way
+/// entries Athas has no place for are listed in `unmapped`.
+#[command]
+pub async fn import_external_editor_config(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   store: State<'_, CredentialStore>,
+   source: ExternalEditor,
+   target_agent_id: Option<String>,
+   apply: Option<bool>,
+) -> Result<ExternalEditorImport, AgentSettingsError> {
+   let apply = apply.unwrap_or(false);
+   let target = match &target_agent_id {
+      Some(agent_id) => {
+         let agent = known_agent(&locks, agent_id).await?;
+         servers_key(&agent)?;
+         Some(agent)
+      }
+      None => None,
+   };
+   let mut plan = load_plan(&locks, source).await?;
+   if !plan.import.found {
+      log::info!("No {:?} config in {:?}", source, plan.import.source_paths);
+      return Ok(plan.import);
+   }
+
+   let registered = load_custom_providers(&locks).await?;
+   reconcile(&mut plan, &registered, |provider_id| {
+      store.get(provider_id).map(|key| key.is_some())
+   });
+   match &target {
+      Some(agent) => {
+         import_mcp_servers(&locks, &roots, &history, agent, &mut plan.import, apply).await?
+      }
+      None => {
+         let names: Vec<String> = plan
+            .import
+            .mcp_servers
+            .iter()
+            .map(|server| server.name.clone())
+            .collect();
+         for name in names {
+            plan
+               .import
+               .unmapped(format!("MCP server {}", name), "no target agent was given");
+         }
+      }
+   }
+   if !apply {
+      return Ok(plan.import);
+   }
+
+   let settings_path = athas_settings_path()?;
+   for provider in &plan.import.providers {
+      save_provider(&locks, &settings_path, provider, false, None).await?;
+   }
+   for (provider_id, key) in &plan.api_keys {
+      store
+         .set(provider_id, key)
+         .map_err(|message| AgentSettingsError::Credentials { message })?;
+   }
+   plan.import.applied = true;
+
+   log::info!(
+      "Imported {:?} config: {} providers, {} API keys, {} MCP servers, {} unmapped",
+      source,
+      plan.import.providers.len(),
+      plan.import.api_keys.len(),
+      plan.import.mcp.as_ref().map_or(0, |mcp| mcp.imported.len()),
+      plan.import.unmapped.len()
+   );
+   Ok(plan.import)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::{
+      agent_registry::find_known_agent, agent_settings::format::parse_config,
+   };
+   use serde_json::json;
+
+   /// Shaped like the config.yaml Continue writes, with a Hub block and a secret reference
+   const CONTINUE_YAML: &str = r#"name: Local Assistant
+version: 1.0.0
+schema: v1
+models:
+  - name: Claude Sonnet
+    provider: anthropic
+    model: claude-sonnet-4-5
+    apiKey: sk-ant-example
+    roles: [chat, edit]
+  - name: Qwen on OpenRouter
+    provider: openrouter
+    model: qwen/qwen3-coder
+    apiKey: ${{ secrets.OPENROUTER_API_KEY }}
+  - name: Team gateway
+    provider: openai
+    model: gpt-4.1
+    apiBase: https://llm.example.com/v1/
+    apiKey: sk-team-example
+  - name: Gateway mini
+    provider: openai
+    model: gpt-4.1-mini
+    apiBase: https://llm.example.com/v1
+  - name: Local
+    provider: ollama
+    model: qwen2.5-coder:7b
+  - name: Bedrock
+    provider: bedrock
+    model: anthropic.claude-3-5-sonnet
+  - uses: anthropic/claude-4-sonnet
+mcpServers:
+  - name: SQLite
+    command: npx
+    args: ["-y", "mcp-sqlite", "/tmp/db.sqlite"]
+  - name: Docs
+    type: sse
+    url: https://mcp.example.com/sse
+  - name: Broken
+    type: websocket
+    url: ws://localhost:9000
+rules:
+  - Always write tests
+"#;
+
+   /// Shaped like the config.json older Continue versions use
+   const CONTINUE_JSON: &str = r#"{
+  "models": [
+    { "title": "GPT-4o", "provider": "openai", "model": "gpt-4o", "apiKey": "sk-example" },
+    { "title": "Groq Llama", "provider": "groq", "model": "llama-3.3-70b-versatile" }
+  ],
+  "tabAutocompleteModel": { "title": "Codestral", "provider": "mistral", "model": "codestral-latest" },
+  "experimental": {
+    "modelContextProtocolServers": [
+      { "transport": { "type": "stdio", "command": "/usr/local/bin/github-mcp", "args": [] } }
+    ],
+    "quickActions": []
+  }
+}"#;
+
+   fn continue_plan(content: &str, format: ConfigFormat) -> Plan {
+      plan_continue(&parse_config(content, format).unwrap(), "config")
+   }
+
+   fn unmapped_entries(import: &ExternalEditorImport) -> Vec<&str> {
+      import
+         .unmapped
+         .iter()
+         .map(|entry| entry.entry.as_str())
+         .collect()
+   }
+
+   #[test]
+   fn test_continue_yaml_plan() {
+      let plan = continue_plan(CONTINUE_YAML, ConfigFormat::Yaml);
+      let import = &plan.import;
+
+      assert_eq!(
+         import
+            .providers
+            .iter()
+            .map(|provider| (provider.id.as_str(), provider.base_url.as_str()))
+            .collect::<Vec<_>>(),
+         vec![
+            ("openrouter", "https://openrouter.ai/api/v1"),
+            ("team-gateway", "https://llm.example.com/v1"),
+         ]
+      );
+      assert_eq!(
+         import.builtin_models,
+         vec![
+            ExternalModel {
+               name: "Claude Sonnet".into(),
+               provider: "anthropic".into(),
+               model: "claude-sonnet-4-5".into(),
+            },
+            ExternalModel {
+               name: "Local".into(),
+               provider: "ollama".into(),
+               model: "qwen2.5-coder:7b".into(),
+            },
+         ]
+      );
+      assert_eq!(
+         plan.api_keys,
+         vec![
+            ("anthropic".to_string(), "sk-ant-example".to_string()),
+            ("team-gateway".to_string(), "sk-team-example".to_string()),
+         ]
+      );
+      assert_eq!(
+         import
+            .mcp_servers
+            .iter()
+            .map(|server| (server.name.as_str(), server.transport))
+            .collect::<Vec<_>>(),
+         vec![("SQLite", McpTransport::Stdio), ("Docs", McpTransport::Sse)]
+      );
+      assert_eq!(
+         unmapped_entries(import),
+         vec![
+            "mcpServers[2]",
+            "models[1].apiKey",
+            "models[5]",
+            "models[6]",
+            "rules"
+         ]
+      );
+   }
+
+   #[test]
+   fn test_continue_json_plan() {
+      let plan = continue_plan(CONTINUE_JSON, ConfigFormat::Json);
+      let import = &plan.import;
+
+      assert_eq!(import.providers.len(), 1);
+      assert_eq!(import.providers[0].id, "groq");
+      assert_eq!(import.builtin_models[0].model, "gpt-4o");
+      assert_eq!(
+         plan.api_keys,
+         vec![("openai".to_string(), "sk-example".to_string())]
+      );
+      assert_eq!(import.mcp_servers.len(), 1);
+      assert_eq!(import.mcp_servers[0].name, "github-mcp");
+      let mut unmapped = unmapped_entries(import);
+      unmapped.sort_unstable();
+      assert_eq!(
+         unmapped,
+         vec!["experimental.quickActions", "tabAutocompleteModel"]
+      );
+   }
+
+   #[test]
+   fn test_cursor_plan() {
+      let config = json!({
+         "mcpServers": {
+            "github": { "command": "npx", "args": ["-y", "@modelcontextprotocol/server-github"] },
+            "linear": { "url": "https://mcp.linear.app/sse", "type": "sse" },
+            "empty": {},
+         },
+      });
+      let import = plan_cursor(Some(&config), "mcp.json").import;
+      assert!(import.found);
+      assert_eq!(
+         import
+            .mcp_servers
+            .iter()
+            .map(|server| server.name.as_str())
+            .collect::<Vec<_>>(),
+         vec!["github", "linear"]
+      );
+      assert_eq!(
+         unmapped_entries(&import),
+         vec!["models", "mcpServers.empty"]
+      );
+
+      assert!(!plan_cursor(None, "mcp.json").import.found);
+   }
+
+   #[test]
+   fn test_reconcile_skips_registered_providers_and_stored_keys() {
+      let mut plan = continue_plan(CONTINUE_YAML, ConfigFormat::Yaml);
+      let registered = vec![CustomProvider {
+         id: "openrouter".into(),
+         display_name: "OpenRouter".into(),
+         base_url: "https://openrouter.ai/api/v1".into(),
+         api_key_header: None,
+         models_endpoint: None,
+         chat_endpoint: None,
+      }];
+      reconcile(&mut plan, &registered, |provider_id| {
+         Ok(provider_id == "anthropic")
+      });
+
+      assert_eq!(plan.import.existing_providers, vec!["openrouter"]);
+      assert_eq!(plan.import.providers.len(), 1);
+      assert_eq!(plan.import.api_keys, vec!["team-gateway"]);
+      assert_eq!(plan.api_keys.len(), 1);
+      assert!(unmapped_entries(&plan.import).contains(&"anthropic API key"));
+   }
+
+   #[test]
+   fn test_mcp_servers_sorted_against_target() {
+      let plan = continue_plan(CONTINUE_YAML, ConfigFormat::Yaml);
+      let codex = find_known_agent("codex-cli").unwrap();
+      let mut target = json!({ "mcp_servers": { "SQLite": { "command": "sqlite-mcp" } } });
+      let mut mcp = McpDesktopImport::default();
+      import_into(
+         &mut target,
+         &codex,
+         &plan.import.mcp_servers,
+         false,
+         &mut mcp,
+      )
+      .unwrap();
+
+      assert!(mcp.imported.is_empty());
+      assert_eq!(mcp.conflicts[0].name, "SQLite");
+      // Codex has no SSE transport
+      assert_eq!(mcp.skipped[0].name, "Docs");
+   }
+
+   #[test]
+   fn test_provider_id() {
+      assert_eq!(provider_id("Team Gateway (EU)"), "team-gateway-eu");
+      assert_eq!(provider_id("  llama.cpp  "), "llama.cpp");
+      assert_eq!(provider_id("!!!"), "");
+   }
+}
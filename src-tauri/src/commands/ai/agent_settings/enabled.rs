@@ -0,0 +1,187 @@
+use super::{
+   custom_agents::{all_agents, find_agent},
+   detect::detect_binary,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   keys::{format_key_path, get_nested_value, set_nested_value},
+   paths::get_home_dir,
+   platform::{Platform, platform_key},
+   storage::{AgentSettingsLocks, WriteOptions, read_athas_config, update_config_file},
+};
+use crate::commands::ai::agent_registry::KnownAgent;
+use futures_util::future::join_all;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+   collections::HashMap,
+   path::{Path, PathBuf},
+};
+use tauri::{State, command};
+
+/// Athas' own settings, relative to the home directory. Agents' config files are never touched
+/// by enabling or disabling them.
+const ATHAS_SETTINGS_FILE: &str = ".athas/settings.toml";
+
+pub(super) fn athas_settings_path() -> Result<PathBuf, AgentSettingsError> {
+   Ok(get_home_dir()?.join(ATHAS_SETTINGS_FILE))
+}
+
+/// The value at dotted `key` of `~/.athas/settings.toml`, such as `ai.sessions.maxAgeDays`, or
+/// `None` when it isn't set. The current OS' `platform` section overrides the rest of the file.
+pub async fn read_athas_setting(
+   locks: &AgentSettingsLocks,
+   key: &str,
+) -> Result<Option<Value>, AgentSettingsError> {
+   let file = read_athas_config(locks, &athas_settings_path()?, false).await?;
+   Ok(file.and_then(|file| get_nested_value(&file.value, key).cloned()))
+}
+
+/// A known agent as listed in pickers
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentListing {
+   #[serde(flatten)]
+   pub agent: KnownAgent,
+   /// The user's choice, or whether the agent's CLI is installed when they haven't made one
+   pub enabled: bool,
+}
+
+/// Enabled flags the user has set, by agent id. Agents without an entry haven't been chosen.
+async fn load_flags(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+) -> Result<HashMap<String, bool>, AgentSettingsError> {
+   let Some(file) = read_athas_config(locks, path, false).await? else {
+      return Ok(HashMap::new());
+   };
+   let Some(Value::Object(agents)) = file.value.get("agents") else {
+      return Ok(HashMap::new());
+   };
+   Ok(agents
+      .iter()
+      .filter_map(|(id, settings)| {
+         let enabled = settings.get("enabled")?.as_bool()?;
+         Some((id.clone(), enabled))
+      })
+      .collect())
+}
+
+async fn save_flag(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   agent_id: &str,
+   enabled: bool,
+   platform: Option<Platform>,
+) -> Result<bool, AgentSettingsError> {
+   let key = format_key_path(&["agents".into(), agent_id.into(), "enabled".into()]);
+   let key = platform_key(platform, &key);
+   update_config_file(
+      locks,
+      path,
+      ConfigFormat::Toml,
+      WriteOptions::default(),
+      |value| set_nested_value(value, &key, Value::Bool(enabled)),
+   )
+   .await
+}
+
+/// Enabled flags the user has set in `~/.athas/settings.toml`
+pub(super) async fn enabled_flags(
+   locks: &AgentSettingsLocks,
+) -> Result<HashMap<String, bool>, AgentSettingsError> {
+   load_flags(locks, &athas_settings_path()?).await
+}
+
+/// Show or hide an agent in pickers, on `platform` only when given. Only Athas' own settings
+/// file is written.
+#[command]
+pub async fn set_agent_enabled(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   enabled: bool,
+   platform: Option<Platform>,
+) -> Result<(), AgentSettingsError> {
+   if find_agent(&locks, &agent_id).await?.is_none() {
+      return Err(AgentSettingsError::UnknownAgent { agent_id });
+   }
+   if save_flag(
+      &locks,
+      &athas_settings_path()?,
+      &agent_id,
+      enabled,
+      platform,
+   )
+   .await?
+   {
+      log::info!(
+         "{} agent {}",
+         if enabled { "Enabled" } else { "Disabled" },
+         agent_id
+      );
+   }
+   Ok(())
+}
+
+/// List the agents Athas knows, built-in ones first, with their config paths for this platform,
+/// settings keys and whether they are enabled. Agents the user hasn't enabled or disabled are
+/// enabled when their CLI is installed.
+#[command]
+pub async fn list_known_agents(
+   locks: State<'_, AgentSettingsLocks>,
+) -> Result<Vec<AgentListing>, AgentSettingsError> {
+   let flags = enabled_flags(&locks).await?;
+   let listings = all_agents(&locks).await?.into_iter().map(|agent| async {
+      let enabled = match flags.get(&agent.id) {
+         Some(&enabled) => enabled,
+         None => {
+            detect_binary(agent.id.clone(), agent.binary_name.clone())
+               .await
+               .installed
+         }
+      };
+      AgentListing { agent, enabled }
+   });
+   Ok(join_all(listings).await)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[tokio::test]
+   async fn test_enabled_flags_round_trip() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.toml");
+      std::fs::write(&path, "theme = \"dark\"\n").unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      assert!(load_flags(&locks, &path).await.unwrap().is_empty());
+      assert!(
+         save_flag(&locks, &path, "aider", false, None)
+            .await
+            .unwrap()
+      );
+      assert!(
+         save_flag(&locks, &path, "codex-cli", true, None)
+            .await
+            .unwrap()
+      );
+      assert!(
+         !save_flag(&locks, &path, "codex-cli", true, None)
+            .await
+            .unwrap()
+      );
+
+      let flags = load_flags(&locks, &path).await.unwrap();
+      assert_eq!(
+         flags,
+         HashMap::from([
+            ("aider".to_string(), false),
+            ("codex-cli".to_string(), true)
+         ])
+      );
+      let content = std::fs::read_to_string(&path).unwrap();
+      assert!(content.starts_with("theme = \"dark\""));
+      assert!(content.contains("[agents.aider]"));
+   }
+}
@@ -0,0 +1,553 @@
+use super::{
+   error::AgentSettingsError,
+   paths::get_home_dir,
+   storage::{AgentSettingsLocks, write_atomic},
+};
+use crate::commands::ai::credentials::{KeyringBackend, SecretBackend};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chacha20poly1305::{
+   AeadCore, ChaCha20Poly1305, Key, KeyInit, Nonce,
+   aead::{Aead, OsRng, Payload},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+   borrow::Cow,
+   fs, io,
+   path::{Path, PathBuf},
+   sync::Mutex,
+};
+use tauri::{State, command};
+
+/// Athas' own directory, relative to the home directory
+const ATHAS_DIR: &str = ".athas";
+/// Present in the Athas directory while encryption at rest is on
+const ENABLED_MARKER: &str = ".athas-encrypted";
+/// Keychain account holding the key, base64 encoded
+const KEY_ACCOUNT: &str = "config_encryption_key";
+const ENVELOPE_VERSION: u32 = 1;
+const ENVELOPE_AAD: &[u8] = b"athas-config-v1";
+const NONCE_LEN: usize = 12;
+/// Athas' own config files, relative to the Athas directory. They are only read through the
+/// config storage, which decrypts them, unlike the sessions, operation journal and other files
+/// kept there.
+const CONFIG_FILES: [&str; 4] = [
+   "settings.toml",
+   "agents.toml",
+   "models.toml",
+   "pricing.toml",
+];
+/// Directory of the global prompt templates, relative to the Athas directory
+const PROMPTS_DIR: &str = "prompts";
+/// Directory next to a config file that holds its backups, as kept by `backup`
+const BACKUP_DIR: &str = "backups";
+
+/// How an encrypted file is stored on disk
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope {
+   athas_encrypted: u32,
+   nonce: String,
+   ciphertext: String,
+}
+
+fn parse_envelope(content: &str) -> Option<Envelope> {
+   if !content.trim_start().starts_with('{') || !content.contains("\"athasEncrypted\"") {
+      return None;
+   }
+   serde_json::from_str(content).ok()
+}
+
+fn encryption_error(path: &Path, message: impl Into<String>) -> AgentSettingsError {
+   AgentSettingsError::Encryption {
+      path: path.display().to_string(),
+      message: message.into(),
+   }
+}
+
+/// Decrypt `content` if it is an envelope, leaving plaintext as it is. Without `encryption`, an
+/// envelope can't be read.
+pub(super) fn decode(
+   encryption: Option<&ConfigEncryption>,
+   path: &Path,
+   content: String,
+) -> Result<String, AgentSettingsError> {
+   let Some(envelope) = parse_envelope(&content) else {
+      return Ok(content);
+   };
+   match encryption {
+      Some(encryption) => encryption.decrypt(path, &envelope),
+      None => Err(encryption_error(path, "encryption at rest is not set up")),
+   }
+}
+
+/// Optional encryption at rest for the files in `~/.athas`. While it's on, every file Athas
+/// writes there through the config storage is stored as a ChaCha20-Poly1305 envelope whose key
+/// is held in the OS keychain. Reads decrypt envelopes and accept plaintext either way, so
+/// turning it on doesn't break existing files.
+pub struct ConfigEncryption {
+   keychain: Box<dyn SecretBackend>,
+   dir: PathBuf,
+   /// The key once loaded, so the keychain is asked only once
+   cipher: Mutex<Option<ChaCha20Poly1305>>,
+}
+
+impl ConfigEncryption {
+   pub fn new(keychain: Box<dyn SecretBackend>, dir: &Path) -> Self {
+      Self {
+         keychain,
+         dir: dir.to_path_buf(),
+         cipher: Mutex::new(None),
+      }
+   }
+
+   /// Encryption of `~/.athas` with its key in the OS keychain
+   pub fn open() -> Result<Self, AgentSettingsError> {
+      Ok(Self::new(
+         Box::new(KeyringBackend),
+         &get_home_dir()?.join(ATHAS_DIR),
+      ))
+   }
+
+   fn marker(&self) -> PathBuf {
+      self.dir.join(ENABLED_MARKER)
+   }
+
+   pub(super) fn is_enabled(&self) -> bool {
+      self.marker().exists()
+   }
+
+   /// Whether files written to `path` get encrypted while encryption is on
+   fn applies_to(&self, path: &Path) -> bool {
+      path
+         .strip_prefix(&self.dir)
+         .is_ok_and(|relative| is_config_file(relative))
+   }
+
+   /// The key from the keychain, created first when `create` is set and there is none
+   fn cipher(&self, path: &Path, create: bool) -> Result<ChaCha20Poly1305, AgentSettingsError> {
+      let mut cached = self.cipher.lock().unwrap_or_else(|e| e.into_inner());
+      if let Some(cipher) = cached.as_ref() {
+         return Ok(cipher.clone());
+      }
+
+      let stored = self
+         .keychain
+         .get(KEY_ACCOUNT)
+         .map_err(|message| encryption_error(path, message))?;
+      let cipher = match stored {
+         Some(encoded) => {
+            let key = STANDARD
+               .decode(encoded.trim())
+               .ok()
+               .filter(|key| key.len() == 32)
+               .ok_or_else(|| encryption_error(path, "the key in the keychain is invalid"))?;
+            ChaCha20Poly1305::new(Key::from_slice(&key))
+         }
+         None if create => {
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+            self
+               .keychain
+               .set(KEY_ACCOUNT, &STANDARD.encode(key))
+               .map_err(|message| encryption_error(path, message))?;
+            ChaCha20Poly1305::new(&key)
+         }
+         None => {
+            return Err(encryption_error(path, "the keychain has no encryption key"));
+         }
+      };
+      *cached = Some(cipher.clone());
+      Ok(cipher)
+   }
+
+   fn decrypt(&self, path: &Path, envelope: &Envelope) -> Result<String, AgentSettingsError> {
+      if envelope.athas_encrypted != ENVELOPE_VERSION {
+         return Err(encryption_error(
+            path,
+            format!("unsupported version {}", envelope.athas_encrypted),
+         ));
+      }
+      let nonce = STANDARD
+         .decode(&envelope.nonce)
+         .ok()
+         .filter(|nonce| nonce.len() == NONCE_LEN);
+      let ciphertext = STANDARD.decode(&envelope.ciphertext).ok();
+      let (Some(nonce), Some(ciphertext)) = (nonce, ciphertext) else {
+         return Err(encryption_error(path, "the file is corrupted"));
+      };
+      let payload = Payload {
+         msg: &ciphertext,
+         aad: ENVELOPE_AAD,
+      };
+      let plaintext = self
+         .cipher(path, false)?
+         .decrypt(Nonce::from_slice(&nonce), payload)
+         .map_err(|_| encryption_error(path, "wrong key or corrupted file"))?;
+      String::from_utf8(plaintext).map_err(|_| encryption_error(path, "the content is not UTF-8"))
+   }
+
+   fn encrypt(&self, path: &Path, content: &str) -> Result<String, AgentSettingsError> {
+      let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+      let payload = Payload {
+         msg: content.as_bytes(),
+         aad: ENVELOPE_AAD,
+      };
+      let ciphertext = self
+         .cipher(path, true)?
+         .encrypt(&nonce, payload)
+         .map_err(|_| encryption_error(path, "encryption failed"))?;
+      let envelope = Envelope {
+         athas_encrypted: ENVELOPE_VERSION,
+         nonce: STANDARD.encode(nonce),
+         ciphertext: STANDARD.encode(ciphertext),
+      };
+      serde_json::to_string(&envelope)
+         .map(|envelope| envelope + "\n")
+         .map_err(|e| encryption_error(path, e.to_string()))
+   }
+
+   /// What to store on disk for `content` written to `path`
+   pub(super) fn seal<'a>(
+      &self,
+      path: &Path,
+      content: &'a str,
+   ) -> Result<Cow<'a, str>, AgentSettingsError> {
+      if !self.is_enabled() || !self.applies_to(path) {
+         return Ok(Cow::Borrowed(content));
+      }
+      self.encrypt(path, content).map(Cow::Owned)
+   }
+
+   /// What to append for `line` to a log kept in the Athas directory at `path`, such as the
+   /// settings history. Lines are sealed one by one so the log can still be appended to.
+   pub(super) fn seal_line(&self, path: &Path, line: &str) -> Result<String, AgentSettingsError> {
+      if !self.is_enabled() || !path.starts_with(&self.dir) {
+         return Ok(format!("{}\n", line));
+      }
+      self.encrypt(path, line)
+   }
+
+   /// Create the key if needed and encrypt files written from now on
+   fn enable(&self) -> Result<(), AgentSettingsError> {
+      let marker = self.marker();
+      self.cipher(&marker, true)?;
+      fs::create_dir_all(&self.dir).map_err(|e| AgentSettingsError::io(&self.dir, e))?;
+      fs::write(&marker, "").map_err(|e| AgentSettingsError::io(&marker, e))
+   }
+
+   fn disable(&self) -> Result<(), AgentSettingsError> {
+      let marker = self.marker();
+      match fs::remove_file(&marker) {
+         Err(e) if e.kind() != io::ErrorKind::NotFound => Err(AgentSettingsError::io(&marker, e)),
+         _ => Ok(()),
+      }
+   }
+
+   /// Config files and their backups in the Athas directory, in path order. Only the
+   /// directories config files are kept in are looked at.
+   fn config_files(&self) -> Result<Vec<PathBuf>, AgentSettingsError> {
+      let prompts = self.dir.join(PROMPTS_DIR);
+      let dirs = [
+         self.dir.join(BACKUP_DIR),
+         prompts.join(BACKUP_DIR),
+         self.dir.clone(),
+         prompts,
+      ];
+      let mut files = Vec::new();
+      for dir in dirs {
+         let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(AgentSettingsError::io(&dir, e)),
+         };
+         for entry in entries {
+            let entry = entry.map_err(|e| AgentSettingsError::io(&dir, e))?;
+            let file_type = entry
+               .file_type()
+               .map_err(|e| AgentSettingsError::io(&dir, e))?;
+            let path = entry.path();
+            if file_type.is_file() && self.applies_to(&path) {
+               files.push(path);
+            }
+         }
+      }
+      files.sort();
+      Ok(files)
+   }
+}
+
+/// Whether `relative`, a path inside the Athas directory, is one of its config files or a
+/// backup or corrupt copy of one: a file of `CONFIG_FILES` or a prompt template. Sessions, the
+/// operation journal, the credential store, the settings history and everything else kept
+/// there are left alone.
+fn is_config_file(relative: &Path) -> bool {
+   let Some(name) = relative.file_name().and_then(|name| name.to_str()) else {
+      return false;
+   };
+   let name = name.split(".athas-bak.").next().unwrap_or(name);
+   let name = name.split(".corrupt-").next().unwrap_or(name);
+   let mut dir = relative.parent().unwrap_or(Path::new(""));
+   if dir.file_name().is_some_and(|dir| dir == BACKUP_DIR) {
+      dir = dir.parent().unwrap_or(Path::new(""));
+   }
+
+   if dir == Path::new("") {
+      CONFIG_FILES.contains(&name)
+   } else if dir == Path::new(PROMPTS_DIR) {
+      [".toml", ".json"]
+         .iter()
+         .any(|extension| name.len() > extension.len() && name.ends_with(extension))
+   } else {
+      false
+   }
+}
+
+/// Rewrite every config file in the Athas directory so it's encrypted when `encrypt` is set and
+/// plaintext otherwise. Returns how many files changed.
+async fn rewrite_all(
+   locks: &AgentSettingsLocks,
+   encryption: &ConfigEncryption,
+   encrypt: bool,
+) -> Result<usize, AgentSettingsError> {
+   let mut rewritten = 0;
+   for path in encryption.config_files()? {
+      let lock = locks.lock_for(&path).await;
+      let _guard = lock.write().await;
+
+      let stored = fs::read_to_string(&path).map_err(|e| AgentSettingsError::io(&path, e))?;
+      if parse_envelope(&stored).is_some() == encrypt {
+         continue;
+      }
+      let content = decode(Some(encryption), &path, stored)?;
+      let content = if encrypt {
+         encryption.encrypt(&path, &content)?
+      } else {
+         content
+      };
+      write_atomic(&path, &content)?;
+      locks.record_write(&path, &content).await;
+      rewritten += 1;
+   }
+   Ok(rewritten)
+}
+
+fn encryption_state(locks: &AgentSettingsLocks) -> Result<&ConfigEncryption, AgentSettingsError> {
+   locks
+      .encryption()
+      .ok_or_else(|| AgentSettingsError::Encryption {
+         path: ATHAS_DIR.to_string(),
+         message: "encryption at rest is not set up".to_string(),
+      })
+}
+
+/// Whether Athas' own files in `~/.athas` are encrypted at rest
+#[command]
+pub fn get_athas_config_encryption(locks: State<'_, AgentSettingsLocks>) -> bool {
+   locks.encryption().is_some_and(ConfigEncryption::is_enabled)
+}
+
+/// Turn encryption at rest for `~/.athas` on or off. Turning it on creates the key in the
+/// keychain and encrypts files as they are next written; `encrypt_athas_config` encrypts the
+/// existing ones straight away. Turning it off decrypts every file in place first.
+#[command]
+pub async fn set_athas_config_encryption(
+   locks: State<'_, AgentSettingsLocks>,
+   enabled: bool,
+) -> Result<(), AgentSettingsError> {
+   let encryption = encryption_state(&locks)?;
+   if enabled {
+      encryption.enable()?;
+      log::info!("Enabled encryption at rest for Athas settings");
+   } else {
+      let decrypted = rewrite_all(&locks, encryption, false).await?;
+      encryption.disable()?;
+      log::info!(
+         "Disabled encryption at rest for Athas settings, decrypted {} files",
+         decrypted
+      );
+   }
+   Ok(())
+}
+
+/// Turn encryption at rest on and encrypt the existing config files in `~/.athas` in place,
+/// backups included. Returns how many files were encrypted.
+#[command]
+pub async fn encrypt_athas_config(
+   locks: State<'_, AgentSettingsLocks>,
+) -> Result<usize, AgentSettingsError> {
+   let encryption = encryption_state(&locks)?;
+   encryption.enable()?;
+   let encrypted = rewrite_all(&locks, encryption, true).await?;
+   log::info!("Encrypted {} Athas settings files", encrypted);
+   Ok(encrypted)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::{
+      agent_settings::{
+         format::ConfigFormat,
+         keys::set_nested_value,
+         storage::{WriteOptions, read_config_file, update_config_file},
+      },
+      credentials::MockKeychain,
+   };
+   use serde_json::{Value, json};
+   use std::sync::Arc;
+
+   fn locks(dir: &Path, keychain: &Arc<MockKeychain>) -> AgentSettingsLocks {
+      AgentSettingsLocks::with_encryption(ConfigEncryption::new(Box::new(keychain.clone()), dir))
+   }
+
+   #[test]
+   fn test_is_config_file() {
+      for name in [
+         "agents.toml",
+         "settings.toml",
+         "backups/models.toml.athas-bak.20250101T000000",
+         "agents.toml.corrupt-20250101T000000-1",
+         "prompts/review.toml",
+         "prompts/backups/review.toml.athas-bak.20250101T000000",
+      ] {
+         assert!(is_config_file(Path::new(name)), "{}", name);
+      }
+      for name in [
+         "credentials.json",
+         "credentials.vault",
+         "settings-history.jsonl",
+         ".athas-encrypted",
+         "sessions/0b5f.json",
+         "journal/0b5f.json",
+         "extensions/acme/manifest.json",
+         "notes.toml",
+         "prompts/.toml",
+      ] {
+         assert!(!is_config_file(Path::new(name)), "{}", name);
+      }
+   }
+
+   #[tokio::test]
+   async fn test_writes_are_encrypted_once_enabled() {
+      let dir = tempfile::tempdir().unwrap();
+      let keychain = Arc::new(MockKeychain::default());
+      let locks = locks(dir.path(), &keychain);
+      let path = dir.path().join("agents.toml");
+      let outside = tempfile::tempdir().unwrap().path().join("outside.toml");
+      let set_url = |value: &mut Value| {
+         set_nested_value(value, "agents.acme.baseUrl", json!("https://internal"))
+      };
+
+      update_config_file(
+         &locks,
+         &path,
+         ConfigFormat::Toml,
+         WriteOptions::default(),
+         set_url,
+      )
+      .await
+      .unwrap();
+      assert!(
+         fs::read_to_string(&path)
+            .unwrap()
+            .contains("https://internal")
+      );
+
+      locks.encryption().unwrap().enable().unwrap();
+      let set_name = |value: &mut Value| set_nested_value(value, "agents.acme.name", json!("Acme"));
+      update_config_file(
+         &locks,
+         &path,
+         ConfigFormat::Toml,
+         WriteOptions::default(),
+         set_name,
+      )
+      .await
+      .unwrap();
+      let stored = fs::read_to_string(&path).unwrap();
+      assert!(stored.starts_with("{\"athasEncrypted\":1,"));
+      assert!(!stored.contains("internal"));
+
+      let file = read_config_file(&locks, &path, ConfigFormat::Toml)
+         .await
+         .unwrap()
+         .unwrap();
+      assert_eq!(
+         file.value,
+         json!({ "agents": { "acme": { "baseUrl": "https://internal", "name": "Acme" } } })
+      );
+
+      // Without the key the file can't be read, and plaintext elsewhere is left alone
+      let unkeyed = self::locks(dir.path(), &Arc::new(MockKeychain::default()));
+      assert!(matches!(
+         read_config_file(&unkeyed, &path, ConfigFormat::Toml).await,
+         Err(AgentSettingsError::Encryption { .. })
+      ));
+      assert!(matches!(
+         read_config_file(&AgentSettingsLocks::new(), &path, ConfigFormat::Toml).await,
+         Err(AgentSettingsError::Encryption { .. })
+      ));
+      let seal = locks
+         .encryption()
+         .unwrap()
+         .seal(&outside, "a = 1\n")
+         .unwrap();
+      assert_eq!(seal, "a = 1\n");
+
+      // Log lines such as settings history entries are sealed one by one
+      let history = dir.path().join("settings-history.jsonl");
+      let line = locks
+         .encryption()
+         .unwrap()
+         .seal_line(&history, "{\"key\":\"apiKey\"}")
+         .unwrap();
+      assert!(line.ends_with('\n') && !line.trim_end().contains('\n'));
+      assert!(!line.contains("apiKey"));
+      assert_eq!(
+         decode(locks.encryption(), &history, line.trim_end().to_string()).unwrap(),
+         "{\"key\":\"apiKey\"}"
+      );
+      let plain = locks
+         .encryption()
+         .unwrap()
+         .seal_line(&outside, "{}")
+         .unwrap();
+      assert_eq!(plain, "{}\n");
+   }
+
+   #[tokio::test]
+   async fn test_rewrite_existing_files() {
+      let dir = tempfile::tempdir().unwrap();
+      let keychain = Arc::new(MockKeychain::default());
+      let locks = locks(dir.path(), &keychain);
+      let encryption = locks.encryption().unwrap();
+      let agents = dir.path().join("agents.toml");
+      let backup = dir
+         .path()
+         .join("backups/agents.toml.athas-bak.20250101T000000");
+      let index = dir.path().join("credentials.json");
+      let session = dir.path().join("sessions/0b5f.json");
+      let session_meta = "{\"id\":\"0b5f\",\"agentId\":\"claude-code\"}";
+      fs::create_dir_all(backup.parent().unwrap()).unwrap();
+      fs::create_dir_all(session.parent().unwrap()).unwrap();
+      fs::write(&agents, "[agents.acme]\nname = \"Acme\"\n").unwrap();
+      fs::write(&backup, "[agents.acme]\n").unwrap();
+      fs::write(&index, "{\"providers\":{}}").unwrap();
+      fs::write(&session, session_meta).unwrap();
+
+      encryption.enable().unwrap();
+      assert_eq!(rewrite_all(&locks, encryption, true).await.unwrap(), 2);
+      assert_eq!(rewrite_all(&locks, encryption, true).await.unwrap(), 0);
+      assert!(parse_envelope(&fs::read_to_string(&agents).unwrap()).is_some());
+      assert!(parse_envelope(&fs::read_to_string(&backup).unwrap()).is_some());
+      assert_eq!(fs::read_to_string(&index).unwrap(), "{\"providers\":{}}");
+      assert_eq!(fs::read_to_string(&session).unwrap(), session_meta);
+
+      assert_eq!(rewrite_all(&locks, encryption, false).await.unwrap(), 2);
+      encryption.disable().unwrap();
+      assert!(!encryption.is_enabled());
+      assert_eq!(
+         fs::read_to_string(&agents).unwrap(),
+         "[agents.acme]\nname = \"Acme\"\n"
+      );
+   }
+}
@@ -0,0 +1,669 @@
+use super::{format::ConfigFormat, memory::AgentFileScope};
+use serde::Serialize;
+use serde_json::Value;
+use std::{io, path::Path};
+use thiserror::Error;
+
+/// Errors returned by the agent settings commands.
+///
+/// Serialized to the frontend as a tagged object such as
+/// `{ "type": "parse", "format": "toml", "line": 3, "column": 7, "snippet": "...", ... }` so the UI
+/// can tell a missing file from a syntax error or a permission problem.
+#[derive(Debug, Clone, Error, Serialize)]
+#[serde(
+   tag = "type",
+   rename_all = "camelCase",
+   rename_all_fields = "camelCase"
+)]
+pub enum AgentSettingsError {
+   /// A file the operation needs does not exist
+   #[error("File not found: {path}")]
+   NotFound { path: String },
+
+   /// The file content is not valid for its format. `line` and `column` are 1-based and
+   /// `snippet` holds the text of the offending line, when the parser reports a position.
+   #[error("Failed to parse {format}: {message}")]
+   Parse {
+      format: ConfigFormat,
+      line: Option<usize>,
+      column: Option<usize>,
+      snippet: Option<String>,
+      message: String,
+   },
+
+   /// The updated document could not be written back in the file's format
+   #[error("Failed to serialize {format}: {message}")]
+   Serialize {
+      format: ConfigFormat,
+      message: String,
+   },
+
+   /// A filesystem operation failed
+   #[error("Failed to access {path}: {message}")]
+   Io {
+      kind: String,
+      path: String,
+      message: String,
+   },
+
+   /// A config file is over the size limit for reading it whole, usually because the settings
+   /// path points at the wrong file. `size` and `limit` are in bytes.
+   #[error("{path} is {size} bytes, over the {limit} byte limit for settings files")]
+   FileTooLarge { path: String, size: u64, limit: u64 },
+
+   /// A config file's content is not UTF-8 text, as when the settings path names a binary file
+   #[error("{path} is not a text file")]
+   NotText { path: String },
+
+   /// A key path is empty or malformed
+   #[error("Invalid key path '{key}': {message}")]
+   InvalidKeyPath { key: String, message: String },
+
+   /// A JSON Patch operation could not be applied, or its `test` did not hold. `index` is the
+   /// operation's position in the patch; no operation of a failed patch is written.
+   #[error("JSON Patch operation {index} failed: {message}")]
+   Patch { index: usize, message: String },
+
+   /// A settings path is empty or cannot be resolved to a file
+   #[error("Invalid settings path '{path}': {message}")]
+   InvalidPath { path: String, message: String },
+
+   /// A settings path resolves outside the directories Athas is allowed to write to
+   #[error("{path} is not an allowed settings location: {message}")]
+   PathNotAllowed { path: String, message: String },
+
+   /// The file changed on disk since the caller read the version it expected to overwrite.
+   /// `current` is the file's present content, when it exists and parses.
+   #[error("{path} was modified since it was read")]
+   Conflict {
+      path: String,
+      current_version: String,
+      current: Option<Value>,
+   },
+
+   /// A settings bundle is not valid JSON, doesn't match the bundle schema, or has a version
+   /// this build can't import
+   #[error("Invalid settings bundle: {message}")]
+   InvalidBundle { message: String },
+
+   /// The caller left out an agent's settings path or keys and the agent registry doesn't know
+   /// the agent either
+   #[error("Unknown agent '{agent_id}'")]
+   UnknownAgent { agent_id: String },
+
+   /// A custom agent definition is incomplete, collides with another agent, or has a key path
+   /// that doesn't parse
+   #[error("Invalid agent definition '{agent_id}': {message}")]
+   InvalidAgent { agent_id: String, message: String },
+
+   /// No custom provider has this id
+   #[error("Unknown provider '{provider_id}'")]
+   UnknownProvider { provider_id: String },
+
+   /// A custom provider definition has an unusable id or base URL, or collides with another
+   /// provider
+   #[error("Invalid provider definition '{provider_id}': {message}")]
+   InvalidProvider {
+      provider_id: String,
+      message: String,
+   },
+
+   /// The agent's config has no MCP server of this name
+   #[error("{agent_id} has no MCP server '{name}'")]
+   UnknownMcpServer { agent_id: String, name: String },
+
+   /// An MCP server definition is missing its command or URL, or collides with another server
+   #[error("Invalid MCP server '{name}': {message}")]
+   InvalidMcpServer { name: String, message: String },
+
+   /// A `{{keychain:<name>}}` reference in an MCP server's env names a secret that can't be read
+   #[error(
+      "Can't resolve {{{{keychain:{name}}}}} in the env of MCP server '{server}': {message}. Env \
+       values refer to secrets stored in Athas as {{{{keychain:<name>}}}}"
+   )]
+   KeychainReference {
+      server: String,
+      name: String,
+      message: String,
+   },
+
+   /// The agent doesn't have a setting, or doesn't accept the value, that a write without `force`
+   /// tried to set
+   #[error("{agent_id} does not support {setting}: {message}")]
+   UnsupportedSetting {
+      agent_id: String,
+      setting: String,
+      message: String,
+   },
+
+   /// A value is not one the agent accepts for `key`, such as a misspelled reasoning effort
+   #[error("Invalid value {value} for '{key}', expected one of: {}", allowed.join(", "))]
+   InvalidValue {
+      key: String,
+      value: Value,
+      allowed: Vec<String>,
+   },
+
+   /// An agent's CLI binary is not on PATH or in any of the usual install locations
+   #[error("{binary_name} is not installed")]
+   BinaryNotFound {
+      agent_id: String,
+      binary_name: String,
+   },
+
+   /// An agent's CLI did not finish within the time Athas gives it
+   #[error("'{command}' did not finish within {seconds} seconds")]
+   CommandTimedOut {
+      agent_id: String,
+      command: String,
+      seconds: u64,
+   },
+
+   /// The key holds no secret to move: it is missing, empty, not a string or already refers to
+   /// an environment variable
+   #[error("'{key}' does not hold a secret")]
+   SecretNotFound { key: String },
+
+   /// The keychain and the encrypted credentials file both failed
+   #[error("Failed to store credential: {message}")]
+   Credentials { message: String },
+
+   /// An encrypted Athas settings file couldn't be read or written
+   #[error("Encryption failed for {path}: {message}")]
+   Encryption { path: String, message: String },
+
+   /// A proxy or CA bundle setting can't be used
+   #[error("Invalid network setting '{setting}': {message}")]
+   InvalidNetworkConfig { setting: String, message: String },
+
+   /// No settings history entry has this id, possibly because it was rotated out
+   #[error("No settings history entry with id {id}")]
+   HistoryEntryNotFound { id: String },
+
+   /// A history entry's previous value was a secret, which the history only keeps masked
+   #[error("The change to '{key}' can't be undone: its previous value is a secret")]
+   HistoryValueMasked { id: String, key: String },
+
+   /// No prompt template has this id in the scope it was looked for in
+   #[error("Unknown prompt template '{id}'")]
+   UnknownPromptTemplate { id: String },
+
+   /// A prompt template has an unusable id, no title or body, or badly named variables
+   #[error("Invalid prompt template '{id}': {message}")]
+   InvalidPromptTemplate { id: String, message: String },
+
+   /// The agent reads no instructions file in this scope
+   #[error("{agent_id} has no {scope} memory file")]
+   NoMemoryFile {
+      agent_id: String,
+      scope: AgentFileScope,
+   },
+
+   /// A memory file section was asked for by a heading that can't be written
+   #[error("Invalid memory section heading '{heading}': {message}")]
+   InvalidMemorySection { heading: String, message: String },
+
+   /// The agent reads no custom commands in this scope
+   #[error("{agent_id} has no {scope} commands directory")]
+   NoCommandsDir {
+      agent_id: String,
+      scope: AgentFileScope,
+   },
+
+   /// No custom command has this name in the scope it was looked for in
+   #[error("Unknown command '{name}'")]
+   UnknownAgentCommand { name: String },
+
+   /// A custom command has an unusable name or no body, or its name is taken
+   #[error("Invalid command '{name}': {message}")]
+   InvalidAgentCommand { name: String, message: String },
+
+   /// Rendering a prompt template left placeholders no value was given for
+   #[error("Prompt template '{id}' has no value for: {}", placeholders.join(", "))]
+   UnresolvedPlaceholders {
+      id: String,
+      placeholders: Vec<String>,
+   },
+
+   /// Codex's config defines no profile with this name
+   #[error("Unknown Codex profile '{name}'")]
+   UnknownCodexProfile { name: String },
+
+   /// A setting in `[agent_defaults]` that agents can't inherit or whose value has the wrong type
+   #[error("Invalid agent default '{key}': {message}")]
+   InvalidAgentDefault { key: String, message: String },
+
+   /// A `${VAR}` reference in one of Athas' own config files names a variable that isn't set
+   #[error("Environment variable {variable} used in '{key}' is not set")]
+   UnresolvedVariable { variable: String, key: String },
+
+   /// None of a config file's backups parse, so there is nothing to restore it from
+   #[error("{path} has no backup that can be restored")]
+   NoUsableBackup { path: String },
+
+   /// The user's home directory could not be determined
+   #[error("Could not find home directory")]
+   HomeDirUnavailable,
+}
+
+impl AgentSettingsError {
+   /// Wrap an I/O error for `path`, reporting missing files as `NotFound`
+   pub fn io(path: &Path, error: io::Error) -> Self {
+      if error.kind() == io::ErrorKind::NotFound {
+         return AgentSettingsError::NotFound {
+            path: path.display().to_string(),
+         };
+      }
+
+      AgentSettingsError::Io {
+         kind: format!("{:?}", error.kind()),
+         path: path.display().to_string(),
+         message: error.to_string(),
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   #[test]
+   fn test_serialized_shape() {
+      let cases = [
+         (
+            AgentSettingsError::NotFound {
+               path: "/home/me/.codex/config.toml".into(),
+            },
+            json!({ "type": "notFound", "path": "/home/me/.codex/config.toml" }),
+         ),
+         (
+            AgentSettingsError::Parse {
+               format: ConfigFormat::Toml,
+               line: Some(3),
+               column: Some(7),
+               snippet: Some("model \"o3\"".into()),
+               message: "expected `=`".into(),
+            },
+            json!({
+               "type": "parse",
+               "format": "toml",
+               "line": 3,
+               "column": 7,
+               "snippet": "model \"o3\"",
+               "message": "expected `=`",
+            }),
+         ),
+         (
+            AgentSettingsError::Serialize {
+               format: ConfigFormat::Yaml,
+               message: "unsupported".into(),
+            },
+            json!({ "type": "serialize", "format": "yaml", "message": "unsupported" }),
+         ),
+         (
+            AgentSettingsError::io(
+               Path::new("/etc/shadow"),
+               io::Error::from(io::ErrorKind::PermissionDenied),
+            ),
+            json!({
+               "type": "io",
+               "kind": "PermissionDenied",
+               "path": "/etc/shadow",
+               "message": "permission denied",
+            }),
+         ),
+         (
+            AgentSettingsError::FileTooLarge {
+               path: "/home/me/.codex/config.toml".into(),
+               size: 734_003_200,
+               limit: 5_242_880,
+            },
+            json!({
+               "type": "fileTooLarge",
+               "path": "/home/me/.codex/config.toml",
+               "size": 734_003_200,
+               "limit": 5_242_880,
+            }),
+         ),
+         (
+            AgentSettingsError::NotText {
+               path: "/usr/bin/codex".into(),
+            },
+            json!({ "type": "notText", "path": "/usr/bin/codex" }),
+         ),
+         (
+            AgentSettingsError::InvalidKeyPath {
+               key: "a..b".into(),
+               message: "empty segment".into(),
+            },
+            json!({ "type": "invalidKeyPath", "key": "a..b", "message": "empty segment" }),
+         ),
+         (
+            AgentSettingsError::Patch {
+               index: 2,
+               message: "test failed at /model".into(),
+            },
+            json!({ "type": "patch", "index": 2, "message": "test failed at /model" }),
+         ),
+         (
+            AgentSettingsError::InvalidPath {
+               path: "".into(),
+               message: "settings path is empty".into(),
+            },
+            json!({ "type": "invalidPath", "path": "", "message": "settings path is empty" }),
+         ),
+         (
+            AgentSettingsError::PathNotAllowed {
+               path: "/etc/passwd".into(),
+               message: "outside the allowed directories".into(),
+            },
+            json!({
+               "type": "pathNotAllowed",
+               "path": "/etc/passwd",
+               "message": "outside the allowed directories",
+            }),
+         ),
+         (
+            AgentSettingsError::Conflict {
+               path: "/home/me/.codex/config.toml".into(),
+               current_version: "18c2-00000000deadbeef".into(),
+               current: Some(json!({ "model": "gpt-5" })),
+            },
+            json!({
+               "type": "conflict",
+               "path": "/home/me/.codex/config.toml",
+               "currentVersion": "18c2-00000000deadbeef",
+               "current": { "model": "gpt-5" },
+            }),
+         ),
+         (
+            AgentSettingsError::InvalidBundle {
+               message: "unsupported bundle version 2".into(),
+            },
+            json!({ "type": "invalidBundle", "message": "unsupported bundle version 2" }),
+         ),
+         (
+            AgentSettingsError::UnknownAgent {
+               agent_id: "my-agent".into(),
+            },
+            json!({ "type": "unknownAgent", "agentId": "my-agent" }),
+         ),
+         (
+            AgentSettingsError::InvalidAgent {
+               agent_id: "codex-cli".into(),
+               message: "a built-in agent has this id".into(),
+            },
+            json!({
+               "type": "invalidAgent",
+               "agentId": "codex-cli",
+               "message": "a built-in agent has this id",
+            }),
+         ),
+         (
+            AgentSettingsError::UnknownProvider {
+               provider_id: "openrouter".into(),
+            },
+            json!({ "type": "unknownProvider", "providerId": "openrouter" }),
+         ),
+         (
+            AgentSettingsError::InvalidProvider {
+               provider_id: "openai".into(),
+               message: "a built-in provider has this id".into(),
+            },
+            json!({
+               "type": "invalidProvider",
+               "providerId": "openai",
+               "message": "a built-in provider has this id",
+            }),
+         ),
+         (
+            AgentSettingsError::UnknownMcpServer {
+               agent_id: "codex-cli".into(),
+               name: "github".into(),
+            },
+            json!({ "type": "unknownMcpServer", "agentId": "codex-cli", "name": "github" }),
+         ),
+         (
+            AgentSettingsError::InvalidMcpServer {
+               name: "github".into(),
+               message: "a stdio server needs a command".into(),
+            },
+            json!({
+               "type": "invalidMcpServer",
+               "name": "github",
+               "message": "a stdio server needs a command",
+            }),
+         ),
+         (
+            AgentSettingsError::KeychainReference {
+               server: "github".into(),
+               name: "github_token".into(),
+               message: "no secret is stored under this name".into(),
+            },
+            json!({
+               "type": "keychainReference",
+               "server": "github",
+               "name": "github_token",
+               "message": "no secret is stored under this name",
+            }),
+         ),
+         (
+            AgentSettingsError::UnsupportedSetting {
+               agent_id: "claude-code".into(),
+               setting: "reasoningEffort".into(),
+               message: "the agent has no reasoning setting".into(),
+            },
+            json!({
+               "type": "unsupportedSetting",
+               "agentId": "claude-code",
+               "setting": "reasoningEffort",
+               "message": "the agent has no reasoning setting",
+            }),
+         ),
+         (
+            AgentSettingsError::InvalidValue {
+               key: "model_reasoning_effort".into(),
+               value: json!("hgih"),
+               allowed: vec!["low".into(), "high".into()],
+            },
+            json!({
+               "type": "invalidValue",
+               "key": "model_reasoning_effort",
+               "value": "hgih",
+               "allowed": ["low", "high"],
+            }),
+         ),
+         (
+            AgentSettingsError::BinaryNotFound {
+               agent_id: "codex-cli".into(),
+               binary_name: "codex".into(),
+            },
+            json!({ "type": "binaryNotFound", "agentId": "codex-cli", "binaryName": "codex" }),
+         ),
+         (
+            AgentSettingsError::CommandTimedOut {
+               agent_id: "codex-cli".into(),
+               command: "codex --version".into(),
+               seconds: 5,
+            },
+            json!({
+               "type": "commandTimedOut",
+               "agentId": "codex-cli",
+               "command": "codex --version",
+               "seconds": 5,
+            }),
+         ),
+         (
+            AgentSettingsError::SecretNotFound {
+               key: "env.OPENAI_API_KEY".into(),
+            },
+            json!({ "type": "secretNotFound", "key": "env.OPENAI_API_KEY" }),
+         ),
+         (
+            AgentSettingsError::Credentials {
+               message: "no keychain".into(),
+            },
+            json!({ "type": "credentials", "message": "no keychain" }),
+         ),
+         (
+            AgentSettingsError::Encryption {
+               path: "~/.athas/agents.toml".into(),
+               message: "wrong key or corrupted file".into(),
+            },
+            json!({
+               "type": "encryption",
+               "path": "~/.athas/agents.toml",
+               "message": "wrong key or corrupted file",
+            }),
+         ),
+         (
+            AgentSettingsError::HistoryEntryNotFound { id: "1f0c".into() },
+            json!({ "type": "historyEntryNotFound", "id": "1f0c" }),
+         ),
+         (
+            AgentSettingsError::HistoryValueMasked {
+               id: "1f0c".into(),
+               key: "env.ANTHROPIC_API_KEY".into(),
+            },
+            json!({
+               "type": "historyValueMasked",
+               "id": "1f0c",
+               "key": "env.ANTHROPIC_API_KEY",
+            }),
+         ),
+         (
+            AgentSettingsError::UnknownPromptTemplate {
+               id: "review".into(),
+            },
+            json!({ "type": "unknownPromptTemplate", "id": "review" }),
+         ),
+         (
+            AgentSettingsError::InvalidPromptTemplate {
+               id: "review".into(),
+               message: "title is empty".into(),
+            },
+            json!({
+               "type": "invalidPromptTemplate",
+               "id": "review",
+               "message": "title is empty",
+            }),
+         ),
+         (
+            AgentSettingsError::NoMemoryFile {
+               agent_id: "aider".into(),
+               scope: AgentFileScope::Global,
+            },
+            json!({ "type": "noMemoryFile", "agentId": "aider", "scope": "global" }),
+         ),
+         (
+            AgentSettingsError::InvalidMemorySection {
+               heading: "####### Deep".into(),
+               message: "headings have one to six '#'s followed by a space".into(),
+            },
+            json!({
+               "type": "invalidMemorySection",
+               "heading": "####### Deep",
+               "message": "headings have one to six '#'s followed by a space",
+            }),
+         ),
+         (
+            AgentSettingsError::NoCommandsDir {
+               agent_id: "codex-cli".into(),
+               scope: AgentFileScope::Project,
+            },
+            json!({ "type": "noCommandsDir", "agentId": "codex-cli", "scope": "project" }),
+         ),
+         (
+            AgentSettingsError::UnknownAgentCommand {
+               name: "review".into(),
+            },
+            json!({ "type": "unknownAgentCommand", "name": "review" }),
+         ),
+         (
+            AgentSettingsError::InvalidAgentCommand {
+               name: "review".into(),
+               message: "a command in this scope has this name".into(),
+            },
+            json!({
+               "type": "invalidAgentCommand",
+               "name": "review",
+               "message": "a command in this scope has this name",
+            }),
+         ),
+         (
+            AgentSettingsError::UnresolvedPlaceholders {
+               id: "review".into(),
+               placeholders: vec!["focus".into(), "selection".into()],
+            },
+            json!({
+               "type": "unresolvedPlaceholders",
+               "id": "review",
+               "placeholders": ["focus", "selection"],
+            }),
+         ),
+         (
+            AgentSettingsError::UnknownCodexProfile {
+               name: "work".into(),
+            },
+            json!({ "type": "unknownCodexProfile", "name": "work" }),
+         ),
+         (
+            AgentSettingsError::InvalidAgentDefault {
+               key: "model".into(),
+               message: "models are set per agent".into(),
+            },
+            json!({
+               "type": "invalidAgentDefault",
+               "key": "model",
+               "message": "models are set per agent"
+            }),
+         ),
+         (
+            AgentSettingsError::UnresolvedVariable {
+               variable: "LLM_GATEWAY_URL".into(),
+               key: "providers.gateway.baseUrl".into(),
+            },
+            json!({
+               "type": "unresolvedVariable",
+               "variable": "LLM_GATEWAY_URL",
+               "key": "providers.gateway.baseUrl"
+            }),
+         ),
+         (
+            AgentSettingsError::InvalidNetworkConfig {
+               setting: "caBundlePath".into(),
+               message: "corp-ca.pem holds no PEM certificates".into(),
+            },
+            json!({
+               "type": "invalidNetworkConfig",
+               "setting": "caBundlePath",
+               "message": "corp-ca.pem holds no PEM certificates",
+            }),
+         ),
+         (
+            AgentSettingsError::NoUsableBackup {
+               path: "/home/me/.claude/settings.json".into(),
+            },
+            json!({ "type": "noUsableBackup", "path": "/home/me/.claude/settings.json" }),
+         ),
+         (
+            AgentSettingsError::HomeDirUnavailable,
+            json!({ "type": "homeDirUnavailable" }),
+         ),
+      ];
+
+      for (error, expected) in cases {
+         assert_eq!(serde_json::to_value(&error).unwrap(), expected);
+      }
+   }
+
+   #[test]
+   fn test_missing_file_maps_to_not_found() {
+      let error = AgentSettingsError::io(
+         Path::new("settings.json"),
+         io::Error::from(io::ErrorKind::NotFound),
+      );
+      assert!(matches!(error, AgentSettingsError::NotFound { path } if path == "settings.json"));
+   }
+}
@@ -0,0 +1,485 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   keys::{get_nested_value, push_key_name, to_dotted_path},
+   paths::resolve_settings_path,
+   secrets::{REDACTED, is_secret_path, mask_secret},
+   storage::{AgentSettingsLocks, read_config_file},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{State, command};
+
+/// Characters of a string value shown in a key listing before it is cut off
+const PREVIEW_CHARS: usize = 80;
+
+/// Characters of context kept on each side of a search match in its snippet
+const SNIPPET_CONTEXT_CHARS: usize = 30;
+
+/// JSON type of a config value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigValueKind {
+   String,
+   Number,
+   Bool,
+   Array,
+   Object,
+   Null,
+}
+
+impl ConfigValueKind {
+   fn of(value: &Value) -> Self {
+      match value {
+         Value::String(_) => ConfigValueKind::String,
+         Value::Number(_) => ConfigValueKind::Number,
+         Value::Bool(_) => ConfigValueKind::Bool,
+         Value::Array(_) => ConfigValueKind::Array,
+         Value::Object(_) => ConfigValueKind::Object,
+         Value::Null => ConfigValueKind::Null,
+      }
+   }
+}
+
+/// One key of a config file, for browsing its structure
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigKeyEntry {
+   /// Key path in dot notation, with array elements as `[index]` segments
+   pub path: String,
+   pub kind: ConfigValueKind,
+   /// Short rendering of the value. Long strings are cut off, and arrays and objects show only
+   /// their size.
+   pub preview: String,
+   /// Characters in a string, elements in an array or entries in an object
+   pub length: Option<usize>,
+   /// Whether the value is a secret, in which case `preview` only shows a few characters
+   pub masked: bool,
+}
+
+fn preview(value: &Value) -> (String, Option<usize>) {
+   match value {
+      Value::String(text) => {
+         let length = text.chars().count();
+         let preview = if length > PREVIEW_CHARS {
+            format!("{}…", text.chars().take(PREVIEW_CHARS).collect::<String>())
+         } else {
+            text.clone()
+         };
+         (preview, Some(length))
+      }
+      Value::Array(items) => (format!("[{} items]", items.len()), Some(items.len())),
+      Value::Object(map) => (format!("{{{} keys}}", map.len()), Some(map.len())),
+      other => (other.to_string(), None),
+   }
+}
+
+/// Call `visit` with the path and value of every key under `value`, parents before their
+/// children. `depth` is the depth of `value`'s children, which are visited only up to
+/// `max_depth`.
+pub(super) fn walk(
+   value: &Value,
+   path: &str,
+   depth: u32,
+   max_depth: Option<u32>,
+   visit: &mut impl FnMut(&str, &Value),
+) {
+   if max_depth.is_some_and(|max_depth| depth > max_depth) {
+      return;
+   }
+   let children: Vec<(String, &Value)> = match value {
+      Value::Object(map) => map
+         .iter()
+         .map(|(name, child)| {
+            let mut child_path = path.to_string();
+            push_key_name(&mut child_path, name);
+            (child_path, child)
+         })
+         .collect(),
+      Value::Array(items) => items
+         .iter()
+         .enumerate()
+         .map(|(index, child)| (format!("{}[{}]", path, index), child))
+         .collect(),
+      _ => return,
+   };
+
+   for (child_path, child) in children {
+      visit(&child_path, child);
+      walk(child, &child_path, depth + 1, max_depth, visit);
+   }
+}
+
+/// Flatten `value` into one entry per key
+fn collect_keys(value: &Value, path: &str, max_depth: Option<u32>) -> Vec<ConfigKeyEntry> {
+   let mut entries = Vec::new();
+   walk(value, path, 1, max_depth, &mut |path, value| {
+      let masked = value.is_string() && is_secret_path(path);
+      let (preview, length) = match value {
+         Value::String(text) if masked => (mask_secret(text), Some(text.chars().count())),
+         _ => preview(value),
+      };
+      entries.push(ConfigKeyEntry {
+         path: path.to_string(),
+         kind: ConfigValueKind::of(value),
+         preview,
+         length,
+         masked,
+      });
+   });
+   entries
+}
+
+/// List the keys of an agent's config file for a settings explorer. With `prefix`, only keys
+/// under that path are listed, and `max_depth` limits how many levels below it are included
+/// (1 lists just the direct children). A missing file or prefix lists nothing.
+#[command]
+pub async fn list_agent_config_keys(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   settings_path: String,
+   prefix: Option<String>,
+   max_depth: Option<u32>,
+) -> Result<Vec<ConfigKeyEntry>, AgentSettingsError> {
+   let prefix = prefix
+      .filter(|prefix| !prefix.is_empty())
+      .map(|prefix| to_dotted_path(&prefix))
+      .transpose()?;
+   let path = resolve_settings_path(&settings_path)?;
+   let Some(file) =
+      read_config_file(&locks, &path, ConfigFormat::from_path(&settings_path)).await?
+   else {
+      return Ok(Vec::new());
+   };
+
+   let root = match &prefix {
+      Some(prefix) => get_nested_value(&file.value, prefix),
+      None => Some(&file.value),
+   };
+   let entries = root
+      .map(|root| collect_keys(root, prefix.as_deref().unwrap_or_default(), max_depth))
+      .unwrap_or_default();
+
+   log::debug!(
+      "Listed {} config keys for agent {}",
+      entries.len(),
+      agent_id
+   );
+   Ok(entries)
+}
+
+/// An agent config file to search
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentConfigFile {
+   pub agent_id: String,
+   pub settings_path: String,
+}
+
+/// A key whose path or string value contains the search query
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigSearchMatch {
+   pub agent_id: String,
+   pub key_path: String,
+   /// The part of the value around the match, or a preview of the value when only the key path
+   /// matched. Values of secret-looking keys are redacted.
+   pub value_snippet: String,
+}
+
+/// A config file that could not be searched
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigSearchWarning {
+   pub agent_id: String,
+   pub settings_path: String,
+   pub error: AgentSettingsError,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigSearchResults {
+   pub matches: Vec<ConfigSearchMatch>,
+   pub warnings: Vec<ConfigSearchWarning>,
+}
+
+/// Character index of `query` in `text`, if it occurs
+fn find_chars(text: &str, query: &str, case_sensitive: bool) -> Option<usize> {
+   if case_sensitive {
+      return Some(text[..text.find(query)?].chars().count());
+   }
+   let text = text.to_lowercase();
+   let index = text.find(&query.to_lowercase())?;
+   Some(text[..index].chars().count())
+}
+
+/// The text around a match starting at character `start`, with an ellipsis where it was cut
+fn snippet(text: &str, start: usize, query_chars: usize) -> String {
+   let from = start.saturating_sub(SNIPPET_CONTEXT_CHARS);
+   let to = start + query_chars + SNIPPET_CONTEXT_CHARS;
+   let length = text.chars().count();
+   format!(
+      "{}{}{}",
+      if from > 0 { "…" } else { "" },
+      text.chars().skip(from).take(to - from).collect::<String>(),
+      if to < length { "…" } else { "" }
+   )
+}
+
+/// Search one parsed config file for `query` in key paths and string values
+fn search_value(
+   agent_id: &str,
+   value: &Value,
+   query: &str,
+   case_sensitive: bool,
+) -> Vec<ConfigSearchMatch> {
+   let query_chars = query.chars().count();
+   let mut matches = Vec::new();
+   walk(value, "", 1, None, &mut |path, value| {
+      let value_match = value
+         .as_str()
+         .and_then(|text| Some((text, find_chars(text, query, case_sensitive)?)));
+      if value_match.is_none() && find_chars(path, query, case_sensitive).is_none() {
+         return;
+      }
+
+      let value_snippet = match value_match {
+         _ if is_secret_path(path) => REDACTED.to_string(),
+         Some((text, start)) => snippet(text, start, query_chars),
+         None => preview(value).0,
+      };
+      matches.push(ConfigSearchMatch {
+         agent_id: agent_id.to_string(),
+         key_path: path.to_string(),
+         value_snippet,
+      });
+   });
+   matches
+}
+
+/// Search the config files of several agents for a substring in key paths and string values,
+/// such as an endpoint URL. Files that can't be read or parsed are reported as warnings and the
+/// search goes on; missing files are skipped.
+#[command]
+pub async fn search_agent_configs(
+   locks: State<'_, AgentSettingsLocks>,
+   entries: Vec<AgentConfigFile>,
+   query: String,
+   case_sensitive: Option<bool>,
+) -> Result<ConfigSearchResults, AgentSettingsError> {
+   let mut results = ConfigSearchResults {
+      matches: Vec::new(),
+      warnings: Vec::new(),
+   };
+   if query.is_empty() {
+      return Ok(results);
+   }
+
+   for entry in entries {
+      let file = match resolve_settings_path(&entry.settings_path) {
+         Ok(path) => {
+            read_config_file(&locks, &path, ConfigFormat::from_path(&entry.settings_path)).await
+         }
+         Err(e) => Err(e),
+      };
+      match file {
+         Ok(Some(file)) => results.matches.extend(search_value(
+            &entry.agent_id,
+            &file.value,
+            &query,
+            case_sensitive.unwrap_or(false),
+         )),
+         Ok(None) => {}
+         Err(error) => results.warnings.push(ConfigSearchWarning {
+            agent_id: entry.agent_id,
+            settings_path: entry.settings_path,
+            error,
+         }),
+      }
+   }
+
+   log::debug!(
+      "Found {} matches for config search with {} warnings",
+      results.matches.len(),
+      results.warnings.len()
+   );
+   Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+   use tauri::Manager;
+
+   fn list(value: &Value, path: &str, max_depth: Option<u32>) -> Vec<(String, ConfigValueKind)> {
+      let mut entries: Vec<_> = collect_keys(value, path, max_depth)
+         .into_iter()
+         .map(|entry| (entry.path, entry.kind))
+         .collect();
+      entries.sort();
+      entries
+   }
+
+   #[test]
+   fn test_keys_are_flattened_in_dot_notation() {
+      let value = json!({
+         "model": "o3",
+         "model_providers": { "openai": { "base_url": "https://api.openai.com/v1" } },
+         "mcpServers": { "my.server": { "args": ["--port", 8080] } },
+         "hide": null,
+      });
+
+      assert_eq!(
+         list(&value, "", None),
+         vec![
+            ("hide".into(), ConfigValueKind::Null),
+            ("mcpServers".into(), ConfigValueKind::Object),
+            (r#"mcpServers."my.server""#.into(), ConfigValueKind::Object),
+            (
+               r#"mcpServers."my.server".args"#.into(),
+               ConfigValueKind::Array
+            ),
+            (
+               r#"mcpServers."my.server".args[0]"#.into(),
+               ConfigValueKind::String
+            ),
+            (
+               r#"mcpServers."my.server".args[1]"#.into(),
+               ConfigValueKind::Number
+            ),
+            ("model".into(), ConfigValueKind::String),
+            ("model_providers".into(), ConfigValueKind::Object),
+            ("model_providers.openai".into(), ConfigValueKind::Object),
+            (
+               "model_providers.openai.base_url".into(),
+               ConfigValueKind::String
+            ),
+         ]
+      );
+      for (path, _) in list(&value, "", None) {
+         assert!(get_nested_value(&value, &path).is_some(), "{}", path);
+      }
+   }
+
+   #[test]
+   fn test_prefix_and_depth() {
+      let value = json!({ "a": { "b": { "c": 1 }, "d": [true] } });
+      let a = get_nested_value(&value, "a").unwrap();
+
+      assert_eq!(
+         list(a, "a", Some(1)),
+         vec![
+            ("a.b".into(), ConfigValueKind::Object),
+            ("a.d".into(), ConfigValueKind::Array),
+         ]
+      );
+      assert_eq!(list(a, "a", None).len(), 4);
+   }
+
+   #[test]
+   fn test_secret_previews_are_masked() {
+      let value = json!({ "env": { "OPENAI_API_KEY": "sk-proj-0123456789abcd", "DEBUG": "1" } });
+      let entries = collect_keys(&value, "", None);
+      let key = entries
+         .iter()
+         .find(|entry| entry.path == "env.OPENAI_API_KEY")
+         .unwrap();
+      assert!(key.masked);
+      assert_eq!(key.preview, "sk-...abcd");
+      assert_eq!(key.length, Some(22));
+      let debug = entries
+         .iter()
+         .find(|entry| entry.path == "env.DEBUG")
+         .unwrap();
+      assert!(!debug.masked);
+      assert_eq!(debug.preview, "1");
+   }
+
+   #[test]
+   fn test_long_strings_are_truncated() {
+      let long = "x".repeat(500);
+      let (text, length) = preview(&json!(long));
+      assert_eq!(text.chars().count(), PREVIEW_CHARS + 1);
+      assert_eq!(length, Some(500));
+      assert_eq!(preview(&json!(["a", "b"])), ("[2 items]".into(), Some(2)));
+      assert_eq!(preview(&json!(0.5)), ("0.5".into(), None));
+   }
+
+   #[test]
+   fn test_search_matches_values_and_keys() {
+      let value = json!({
+         "model_providers": {
+            "proxy": { "base_url": "https://proxy.internal.example.com/v1", "name": "Proxy" },
+         },
+         "env": { "PROXY_TOKEN": "proxy-secret-123" },
+         "model": "o3",
+      });
+
+      let mut matches: Vec<_> = search_value("codex", &value, "PROXY", false)
+         .into_iter()
+         .map(|found| (found.key_path, found.value_snippet))
+         .collect();
+      matches.sort();
+      assert_eq!(
+         matches,
+         vec![
+            ("env.PROXY_TOKEN".into(), REDACTED.into()),
+            ("model_providers.proxy".into(), "{2 keys}".into()),
+            (
+               "model_providers.proxy.base_url".into(),
+               "https://proxy.internal.example.com/v1".into()
+            ),
+            ("model_providers.proxy.name".into(), "Proxy".into()),
+         ]
+      );
+      assert_eq!(search_value("codex", &value, "PROXY", true).len(), 1);
+   }
+
+   #[test]
+   fn test_snippet_is_cut_around_the_match() {
+      let text = format!("{}needle{}", "a".repeat(100), "b".repeat(100));
+      let start = find_chars(&text, "NEEDLE", false).unwrap();
+      assert_eq!(start, 100);
+      assert_eq!(
+         snippet(&text, start, 6),
+         format!("…{}needle{}…", "a".repeat(30), "b".repeat(30))
+      );
+   }
+
+   #[tokio::test]
+   async fn test_search_reports_unparseable_files() {
+      let dir = tempfile::tempdir().unwrap();
+      let good = dir.path().join("good.json");
+      let bad = dir.path().join("bad.toml");
+      std::fs::write(&good, r#"{ "endpoint": "https://api.example.com" }"#).unwrap();
+      std::fs::write(&bad, "endpoint = ").unwrap();
+      let file = |agent_id: &str, path: &std::path::Path| AgentConfigFile {
+         agent_id: agent_id.into(),
+         settings_path: path.display().to_string(),
+      };
+
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+      let results = search_agent_configs(
+         app.state(),
+         vec![
+            file("bad", &bad),
+            file("good", &good),
+            file("missing", &dir.path().join("missing.json")),
+         ],
+         "example".into(),
+         None,
+      )
+      .await
+      .unwrap();
+
+      assert_eq!(results.matches.len(), 1);
+      assert_eq!(results.matches[0].agent_id, "good");
+      assert_eq!(results.warnings.len(), 1);
+      assert!(matches!(
+         results.warnings[0].error,
+         AgentSettingsError::Parse { .. }
+      ));
+   }
+}
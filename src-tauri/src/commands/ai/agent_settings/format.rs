@@ -0,0 +1,1216 @@
+use super::error::AgentSettingsError;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// On-disk format of an agent config file, detected from its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+   Json,
+   Toml,
+   Yaml,
+}
+
+impl fmt::Display for ConfigFormat {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      let name = match self {
+         ConfigFormat::Json => "JSON",
+         ConfigFormat::Toml => "TOML",
+         ConfigFormat::Yaml => "YAML",
+      };
+      f.write_str(name)
+   }
+}
+
+impl ConfigFormat {
+   pub(super) fn from_path(path: &str) -> Self {
+      if is_toml_file(path) {
+         ConfigFormat::Toml
+      } else if is_yaml_file(path) {
+         ConfigFormat::Yaml
+      } else {
+         ConfigFormat::Json
+      }
+   }
+}
+
+fn is_toml_file(path: &str) -> bool {
+   path.ends_with(".toml")
+}
+
+fn is_yaml_file(path: &str) -> bool {
+   path.ends_with(".yml") || path.ends_with(".yaml")
+}
+
+fn toml_to_json(toml: toml::Value) -> Value {
+   match toml {
+      toml::Value::String(s) => Value::String(s),
+      toml::Value::Integer(i) => Value::Number(i.into()),
+      toml::Value::Float(f) => serde_json::Number::from_f64(f)
+         .map(Value::Number)
+         .unwrap_or(Value::Null),
+      toml::Value::Boolean(b) => Value::Bool(b),
+      toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+      toml::Value::Array(arr) => Value::Array(arr.into_iter().map(toml_to_json).collect()),
+      toml::Value::Table(table) => Value::Object(
+         table
+            .into_iter()
+            .map(|(k, v)| (k, toml_to_json(v)))
+            .collect(),
+      ),
+   }
+}
+
+/// Convert a JSON value to TOML. TOML has no null, so null values (including table entries and
+/// array elements) are dropped instead of being invented as empty strings.
+fn json_to_toml(json: Value) -> Option<toml::Value> {
+   let value = match json {
+      Value::Null => return None,
+      Value::Bool(b) => toml::Value::Boolean(b),
+      Value::Number(n) => {
+         if let Some(i) = n.as_i64() {
+            toml::Value::Integer(i)
+         } else {
+            toml::Value::Float(n.as_f64().unwrap_or(0.0))
+         }
+      }
+      Value::String(s) => toml::Value::String(s),
+      Value::Array(arr) => toml::Value::Array(arr.into_iter().filter_map(json_to_toml).collect()),
+      Value::Object(obj) => {
+         // TOML requires plain values to be emitted before any sub-tables of the same table
+         let (tables, values): (Vec<_>, Vec<_>) = obj
+            .into_iter()
+            .filter_map(|(k, v)| json_to_toml(v).map(|v| (k, v)))
+            .partition(|(_, v)| is_toml_table_like(v));
+         toml::Value::Table(values.into_iter().chain(tables).collect())
+      }
+   };
+   Some(value)
+}
+
+/// Whether a value is rendered as a `[table]` or `[[array.of.tables]]` section
+fn is_toml_table_like(value: &toml::Value) -> bool {
+   match value {
+      toml::Value::Table(_) => true,
+      toml::Value::Array(arr) => !arr.is_empty() && arr.iter().all(toml::Value::is_table),
+      _ => false,
+   }
+}
+
+fn yaml_to_json(yaml: serde_yaml::Value) -> Value {
+   match yaml {
+      serde_yaml::Value::Null => Value::Null,
+      serde_yaml::Value::Bool(b) => Value::Bool(b),
+      serde_yaml::Value::Number(n) => {
+         if let Some(i) = n.as_i64() {
+            Value::Number(i.into())
+         } else if let Some(u) = n.as_u64() {
+            Value::Number(u.into())
+         } else {
+            n.as_f64()
+               .and_then(serde_json::Number::from_f64)
+               .map(Value::Number)
+               .unwrap_or(Value::Null)
+         }
+      }
+      serde_yaml::Value::String(s) => Value::String(s),
+      serde_yaml::Value::Sequence(seq) => Value::Array(seq.into_iter().map(yaml_to_json).collect()),
+      serde_yaml::Value::Mapping(map) => Value::Object(
+         map.into_iter()
+            .map(|(k, v)| (yaml_key_to_string(k), yaml_to_json(v)))
+            .collect(),
+      ),
+      // Tags such as `!env` carry no meaning for the settings we manage, keep the inner value
+      serde_yaml::Value::Tagged(tagged) => yaml_to_json(tagged.value),
+   }
+}
+
+fn yaml_key_to_string(key: serde_yaml::Value) -> String {
+   match key {
+      serde_yaml::Value::String(s) => s,
+      serde_yaml::Value::Bool(b) => b.to_string(),
+      serde_yaml::Value::Number(n) => n.to_string(),
+      serde_yaml::Value::Null => "null".to_string(),
+      other => serde_yaml::to_string(&other)
+         .map(|s| s.trim_end().to_string())
+         .unwrap_or_default(),
+   }
+}
+
+fn json_to_yaml(json: Value) -> serde_yaml::Value {
+   match json {
+      Value::Null => serde_yaml::Value::Null,
+      Value::Bool(b) => serde_yaml::Value::Bool(b),
+      Value::Number(n) => {
+         if let Some(i) = n.as_i64() {
+            serde_yaml::Value::Number(i.into())
+         } else if let Some(u) = n.as_u64() {
+            serde_yaml::Value::Number(u.into())
+         } else {
+            serde_yaml::Value::Number(n.as_f64().unwrap_or(0.0).into())
+         }
+      }
+      Value::String(s) => serde_yaml::Value::String(s),
+      Value::Array(arr) => serde_yaml::Value::Sequence(arr.into_iter().map(json_to_yaml).collect()),
+      Value::Object(obj) => serde_yaml::Value::Mapping(
+         obj.into_iter()
+            .map(|(k, v)| (serde_yaml::Value::String(k), json_to_yaml(v)))
+            .collect(),
+      ),
+   }
+}
+
+/// Reconcile a YAML value with the desired JSON view. Mapping entries keep the order and key
+/// spelling they had, new ones go at the end, and unchanged values are kept as they were
+/// written, so a rewrite only moves what the edit touched. Comments can't be carried over.
+fn sync_yaml_value(existing: serde_yaml::Value, desired: Value) -> serde_yaml::Value {
+   if yaml_to_json(existing.clone()) == desired {
+      return existing;
+   }
+   match (existing, desired) {
+      (serde_yaml::Value::Mapping(mapping), Value::Object(mut desired)) => {
+         let mut synced = serde_yaml::Mapping::new();
+         for (key, value) in mapping {
+            if let Some(wanted) = desired.remove(&yaml_key_to_string(key.clone())) {
+               synced.insert(key, sync_yaml_value(value, wanted));
+            }
+         }
+         for (key, value) in desired {
+            synced.insert(serde_yaml::Value::String(key), json_to_yaml(value));
+         }
+         serde_yaml::Value::Mapping(synced)
+      }
+      (_, desired) => json_to_yaml(desired),
+   }
+}
+
+/// Turn JSONC (JSON with `//` / `/* */` comments and trailing commas) into plain JSON.
+///
+/// Comments and trailing commas are replaced with whitespace rather than removed, so byte
+/// offsets and line numbers in parse errors still point at the user's original file.
+pub(super) fn strip_jsonc(content: &str) -> String {
+   let mut out: Vec<u8> = content.as_bytes().to_vec();
+   let bytes = content.as_bytes();
+   let mut i = 0;
+   let mut in_string = false;
+
+   while i < bytes.len() {
+      let byte = bytes[i];
+      if in_string {
+         match byte {
+            b'\\' => i += 1,
+            b'"' => in_string = false,
+            _ => {}
+         }
+         i += 1;
+         continue;
+      }
+
+      match (byte, bytes.get(i + 1)) {
+         (b'"', _) => in_string = true,
+         (b'/', Some(b'/')) => {
+            while i < bytes.len() && bytes[i] != b'\n' {
+               out[i] = b' ';
+               i += 1;
+            }
+            continue;
+         }
+         (b'/', Some(b'*')) => {
+            let end = content[i + 2..]
+               .find("*/")
+               .map(|offset| i + 2 + offset + 2)
+               .unwrap_or(bytes.len());
+            for j in i..end {
+               if bytes[j] != b'\n' {
+                  out[j] = b' ';
+               }
+            }
+            i = end;
+            continue;
+         }
+         _ => {}
+      }
+      i += 1;
+   }
+
+   // Second pass over the comment-free text: blank out commas directly followed by a closer
+   let mut in_string = false;
+   let mut i = 0;
+   while i < out.len() {
+      let byte = out[i];
+      if in_string {
+         match byte {
+            b'\\' => i += 1,
+            b'"' => in_string = false,
+            _ => {}
+         }
+      } else if byte == b'"' {
+         in_string = true;
+      } else if byte == b',' {
+         let next = out[i + 1..].iter().find(|b| !b.is_ascii_whitespace());
+         if matches!(next, Some(b'}') | Some(b']')) {
+            out[i] = b' ';
+         }
+      }
+      i += 1;
+   }
+
+   // Only ASCII bytes outside of strings were replaced with spaces, so this stays valid UTF-8
+   String::from_utf8(out).unwrap_or_else(|_| content.to_string())
+}
+
+/// UTF-8 byte order mark, which some Windows editors put at the start of the files they save
+const BOM: char = '\u{feff}';
+
+/// `content` without a leading byte order mark, which none of the parsers accept
+pub(super) fn strip_bom(content: &str) -> &str {
+   content.strip_prefix(BOM).unwrap_or(content)
+}
+
+/// Whether most line breaks in `content` are `\r\n`
+fn uses_crlf(content: &str) -> bool {
+   let crlf = content.matches("\r\n").count();
+   let lf = content.matches('\n').count() - crlf;
+   crlf > lf
+}
+
+/// Lay out freshly serialized text like the `original` content it replaces: with the original's
+/// byte order mark and its dominant line ending
+fn match_text_style(serialized: String, original: Option<&str>) -> String {
+   let Some(original) = original else {
+      return serialized;
+   };
+   let mut text = if uses_crlf(original) {
+      serialized.replace("\r\n", "\n").replace('\n', "\r\n")
+   } else {
+      serialized
+   };
+   if original.starts_with(BOM) {
+      text.insert(0, BOM);
+   }
+   text
+}
+
+/// One level of indentation in a JSON document: a tab if its lines are indented with tabs, else
+/// the smallest run of spaces any line is indented by. `None` when no line is indented.
+fn json_indent(content: &str) -> Option<&str> {
+   let indents = content.lines().filter_map(|line| {
+      let indent = &line[..line.len() - line.trim_start().len()];
+      (!indent.is_empty() && indent.len() < line.len()).then_some(indent)
+   });
+   let mut smallest: Option<&str> = None;
+   for indent in indents {
+      if indent.starts_with('\t') {
+         return Some("\t");
+      }
+      let spaces = &indent[..indent.len() - indent.trim_start_matches(' ').len()];
+      if !spaces.is_empty() && smallest.is_none_or(|smallest| spaces.len() < smallest.len()) {
+         smallest = Some(spaces);
+      }
+   }
+   smallest
+}
+
+pub(super) fn parse_config(
+   content: &str,
+   format: ConfigFormat,
+) -> Result<Value, AgentSettingsError> {
+   let content = strip_bom(content);
+   match format {
+      // Stripping JSONC keeps line breaks in place, so positions match the original content
+      ConfigFormat::Json => serde_json::from_str(&strip_jsonc(content)).map_err(|e| {
+         let position = (e.line() > 0).then(|| (e.line(), e.column()));
+         parse_error(format, content, position, e.to_string())
+      }),
+      ConfigFormat::Toml => toml::from_str::<toml::Value>(content)
+         .map(toml_to_json)
+         .map_err(|e| {
+            let position = e.span().map(|span| line_column(content, span.start));
+            parse_error(format, content, position, e.message().to_string())
+         }),
+      ConfigFormat::Yaml => {
+         // An empty YAML document is a valid (empty) config
+         if content.trim().is_empty() {
+            return Ok(Value::Object(Map::new()));
+         }
+         serde_yaml::from_str::<serde_yaml::Value>(content)
+            .map(yaml_to_json)
+            .map_err(|e| {
+               let position = e
+                  .location()
+                  .map(|location| (location.line(), location.column()));
+               parse_error(format, content, position, e.to_string())
+            })
+      }
+   }
+}
+
+/// Parse `content` as leniently as possible, to salvage what a file that fails `parse_config`
+/// still holds. NUL padding left by an interrupted write is dropped, and for JSON anything after
+/// the first complete document is ignored.
+pub(super) fn parse_config_lenient(content: &str, format: ConfigFormat) -> Option<Value> {
+   let content = strip_bom(content).trim_end_matches(|c: char| c == '\0' || c.is_whitespace());
+   if let Ok(value) = parse_config(content, format) {
+      return Some(value);
+   }
+   match format {
+      ConfigFormat::Json => serde_json::Deserializer::from_str(&strip_jsonc(content))
+         .into_iter::<Value>()
+         .next()?
+         .ok(),
+      ConfigFormat::Toml | ConfigFormat::Yaml => None,
+   }
+}
+
+/// Build a parse error pointing at a 1-based `(line, column)` position in `content`
+fn parse_error(
+   format: ConfigFormat,
+   content: &str,
+   position: Option<(usize, usize)>,
+   message: String,
+) -> AgentSettingsError {
+   AgentSettingsError::Parse {
+      format,
+      line: position.map(|(line, _)| line),
+      column: position.map(|(_, column)| column),
+      snippet: position.and_then(|(line, _)| line_snippet(content, line)),
+      message,
+   }
+}
+
+/// 1-based line and column of a byte offset, with the column counted in characters
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+   let offset = offset.min(content.len());
+   let before = content.get(..offset).unwrap_or(content);
+   let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+   let line = before.matches('\n').count() + 1;
+   let column = before[line_start..].chars().count() + 1;
+   (line, column)
+}
+
+/// Text of the 1-based `line` in `content`, without its line ending
+pub(super) fn line_snippet(content: &str, line: usize) -> Option<String> {
+   content
+      .lines()
+      .nth(line.checked_sub(1)?)
+      .map(|text| text.trim_end_matches('\r').to_string())
+}
+
+fn toml_edit_value_to_json(value: &toml_edit::Value) -> Value {
+   match value {
+      toml_edit::Value::String(s) => Value::String(s.value().clone()),
+      toml_edit::Value::Integer(i) => Value::Number((*i.value()).into()),
+      toml_edit::Value::Float(f) => serde_json::Number::from_f64(*f.value())
+         .map(Value::Number)
+         .unwrap_or(Value::Null),
+      toml_edit::Value::Boolean(b) => Value::Bool(*b.value()),
+      toml_edit::Value::Datetime(dt) => Value::String(dt.value().to_string()),
+      toml_edit::Value::Array(arr) => {
+         Value::Array(arr.iter().map(toml_edit_value_to_json).collect())
+      }
+      toml_edit::Value::InlineTable(table) => Value::Object(
+         table
+            .iter()
+            .map(|(k, v)| (k.to_string(), toml_edit_value_to_json(v)))
+            .collect(),
+      ),
+   }
+}
+
+fn toml_edit_item_to_json(item: &toml_edit::Item) -> Option<Value> {
+   match item {
+      toml_edit::Item::None => None,
+      toml_edit::Item::Value(value) => Some(toml_edit_value_to_json(value)),
+      toml_edit::Item::Table(table) => Some(Value::Object(
+         table
+            .iter()
+            .filter_map(|(k, v)| toml_edit_item_to_json(v).map(|v| (k.to_string(), v)))
+            .collect(),
+      )),
+      toml_edit::Item::ArrayOfTables(tables) => Some(Value::Array(
+         tables
+            .iter()
+            .map(|table| toml_edit_item_to_json(&toml_edit::Item::Table(table.clone())))
+            .collect::<Option<Vec<_>>>()?,
+      )),
+   }
+}
+
+fn json_to_toml_edit_value(json: &Value) -> Option<toml_edit::Value> {
+   let value = match json {
+      Value::Null => return None,
+      Value::Bool(b) => toml_edit::Value::from(*b),
+      Value::Number(n) => match n.as_i64() {
+         Some(i) => toml_edit::Value::from(i),
+         None => toml_edit::Value::from(n.as_f64().unwrap_or(0.0)),
+      },
+      Value::String(s) => toml_edit::Value::from(s.as_str()),
+      Value::Array(arr) => {
+         toml_edit::Value::Array(arr.iter().filter_map(json_to_toml_edit_value).collect())
+      }
+      Value::Object(obj) => toml_edit::Value::InlineTable(
+         obj.iter()
+            .filter_map(|(k, v)| json_to_toml_edit_value(v).map(|v| (k.as_str(), v)))
+            .collect(),
+      ),
+   };
+   Some(value)
+}
+
+/// Build a fresh TOML item for a key that did not exist before. Outside of inline tables,
+/// objects become `[table]` sections and arrays of objects become `[[array.of.tables]]`,
+/// matching what a full `toml::to_string_pretty` rewrite would produce.
+fn json_to_toml_edit_item(json: &Value, inline: bool) -> Option<toml_edit::Item> {
+   if inline {
+      return json_to_toml_edit_value(json).map(toml_edit::Item::Value);
+   }
+
+   match json {
+      Value::Object(obj) => {
+         let mut table = toml_edit::Table::new();
+         for (key, value) in obj {
+            if let Some(item) = json_to_toml_edit_item(value, false) {
+               table.insert(key, item);
+            }
+         }
+         // A table holding only sub-tables gets no header of its own, so a new profile is
+         // written as `[profiles.work]` rather than an empty `[profiles]` followed by it
+         let only_tables = !table.is_empty() && table.iter().all(|(_, item)| item.is_table_like());
+         table.set_implicit(only_tables);
+         Some(toml_edit::Item::Table(table))
+      }
+      Value::Array(arr) if !arr.is_empty() && arr.iter().all(Value::is_object) => {
+         let mut tables = toml_edit::ArrayOfTables::new();
+         for value in arr {
+            if let Some(toml_edit::Item::Table(table)) = json_to_toml_edit_item(value, false) {
+               tables.push(table);
+            }
+         }
+         Some(toml_edit::Item::ArrayOfTables(tables))
+      }
+      _ => json_to_toml_edit_value(json).map(toml_edit::Item::Value),
+   }
+}
+
+/// Reconcile a TOML table with the desired JSON view, touching only entries that changed so
+/// comments, whitespace and key order of everything else survive the rewrite.
+fn sync_toml_table(
+   table: &mut dyn toml_edit::TableLike,
+   desired: &Map<String, Value>,
+   inline: bool,
+) {
+   // A desired null means "remove", unless the existing value also reads as null (e.g. `nan`)
+   let stale: Vec<String> = table
+      .iter()
+      .filter(|(key, item)| match desired.get(*key) {
+         None => true,
+         Some(Value::Null) => toml_edit_item_to_json(item) != Some(Value::Null),
+         Some(_) => false,
+      })
+      .map(|(key, _)| key.to_string())
+      .collect();
+   for key in stale {
+      table.remove(&key);
+   }
+
+   for (key, value) in desired {
+      match table.get_mut(key) {
+         Some(item) => sync_toml_item(item, value, inline),
+         None => {
+            if let Some(item) = json_to_toml_edit_item(value, inline) {
+               table.insert(key, item);
+            }
+         }
+      }
+   }
+}
+
+fn sync_toml_item(item: &mut toml_edit::Item, desired: &Value, inline: bool) {
+   if toml_edit_item_to_json(item).as_ref() == Some(desired) {
+      return;
+   }
+
+   let inline = inline || item.is_value();
+   if let Value::Object(map) = desired
+      && let Some(table) = item.as_table_like_mut()
+   {
+      sync_toml_table(table, map, inline);
+      return;
+   }
+
+   if let Value::Array(values) = desired
+      && let toml_edit::Item::ArrayOfTables(tables) = item
+      && values.iter().all(Value::is_object)
+   {
+      let existing = tables.len();
+      for (index, value) in values.iter().enumerate() {
+         match (tables.get_mut(index), value) {
+            (Some(table), Value::Object(map)) => sync_toml_table(table, map, false),
+            (None, _) => {
+               if let Some(toml_edit::Item::Table(table)) = json_to_toml_edit_item(value, false) {
+                  tables.push(table);
+               }
+            }
+            _ => {}
+         }
+      }
+      for index in (values.len()..existing).rev() {
+         tables.remove(index);
+      }
+      return;
+   }
+
+   // Replace the value but keep the whitespace and trailing comment that surrounded it
+   let decor = item.as_value().map(|value| value.decor().clone());
+   let mut replacement = json_to_toml_edit_item(desired, inline).unwrap_or_default();
+   if let (Some(decor), Some(value)) = (decor, replacement.as_value_mut()) {
+      *value.decor_mut() = decor;
+   }
+   *item = replacement;
+}
+
+/// Serialize `value` for writing. When the original file content is available, TOML output is
+/// produced by editing the original document in place instead of regenerating it, and the output
+/// keeps the original's byte order mark and line endings.
+pub(super) fn serialize_config(
+   value: Value,
+   format: ConfigFormat,
+   original: Option<&str>,
+) -> Result<String, AgentSettingsError> {
+   let serialized = serialize_value(value, format, original.map(strip_bom))?;
+   Ok(match_text_style(serialized, original))
+}
+
+fn serialize_value(
+   value: Value,
+   format: ConfigFormat,
+   original: Option<&str>,
+) -> Result<String, AgentSettingsError> {
+   let serialize_error = |message: String| AgentSettingsError::Serialize { format, message };
+   match format {
+      ConfigFormat::Json => {
+         let indent = original.and_then(json_indent).unwrap_or("  ");
+         let mut out = Vec::new();
+         let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+         let mut serializer = serde_json::Serializer::with_formatter(&mut out, formatter);
+         value
+            .serialize(&mut serializer)
+            .map_err(|e| serialize_error(e.to_string()))?;
+         let mut text = String::from_utf8(out).map_err(|e| serialize_error(e.to_string()))?;
+         if original.is_some_and(|content| content.ends_with('\n')) {
+            text.push('\n');
+         }
+         Ok(text)
+      }
+      ConfigFormat::Toml => {
+         let document = original.and_then(|content| content.parse::<toml_edit::DocumentMut>().ok());
+         match (document, &value) {
+            (Some(mut document), Value::Object(map)) => {
+               sync_toml_table(document.as_table_mut(), map, false);
+               Ok(document.to_string())
+            }
+            _ => toml::to_string_pretty(
+               &json_to_toml(value).unwrap_or(toml::Value::Table(toml::map::Map::new())),
+            )
+            .map_err(|e| serialize_error(e.to_string())),
+         }
+      }
+      ConfigFormat::Yaml => {
+         let yaml = match original.and_then(|content| serde_yaml::from_str(content).ok()) {
+            Some(existing) => sync_yaml_value(existing, value),
+            None => json_to_yaml(value),
+         };
+         serde_yaml::to_string(&yaml).map_err(|e| serialize_error(e.to_string()))
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_settings::keys::{get_nested_value, set_nested_value};
+
+   #[test]
+   fn test_detects_yaml_extensions() {
+      assert_eq!(
+         ConfigFormat::from_path(".aider.conf.yml"),
+         ConfigFormat::Yaml
+      );
+      assert_eq!(
+         ConfigFormat::from_path("agent/config.yaml"),
+         ConfigFormat::Yaml
+      );
+      assert_eq!(
+         ConfigFormat::from_path(".codex/config.toml"),
+         ConfigFormat::Toml
+      );
+      assert_eq!(
+         ConfigFormat::from_path(".claude/settings.json"),
+         ConfigFormat::Json
+      );
+   }
+
+   #[test]
+   fn test_yaml_nested_round_trip() {
+      let content = "model: gpt-4o\nagent:\n  reasoning: low\n  retries: 3\n";
+      let mut value = parse_config(content, ConfigFormat::Yaml).unwrap();
+
+      assert_eq!(
+         get_nested_value(&value, "agent.reasoning"),
+         Some(&Value::String("low".into()))
+      );
+
+      set_nested_value(&mut value, "agent.reasoning", Value::String("high".into())).unwrap();
+      set_nested_value(&mut value, "ui.preview", Value::Bool(true)).unwrap();
+
+      let written = serialize_config(value, ConfigFormat::Yaml, None).unwrap();
+      let reparsed = parse_config(&written, ConfigFormat::Yaml).unwrap();
+
+      assert_eq!(
+         get_nested_value(&reparsed, "model"),
+         Some(&Value::String("gpt-4o".into()))
+      );
+      assert_eq!(
+         get_nested_value(&reparsed, "agent.reasoning"),
+         Some(&Value::String("high".into()))
+      );
+      assert_eq!(
+         get_nested_value(&reparsed, "agent.retries"),
+         Some(&Value::from(3))
+      );
+      assert_eq!(
+         get_nested_value(&reparsed, "ui.preview"),
+         Some(&Value::Bool(true))
+      );
+   }
+
+   /// A representative Aider config: flat dashed keys, boolean flags, lists and quoted strings
+   const AIDER_YAML: &str = r#"model: sonnet
+weak-model: haiku
+auto-commits: false
+dark-mode: true
+map-tokens: 1024
+read:
+- CONVENTIONS.md
+- docs/style.md
+set-env:
+- OLLAMA_API_BASE=http://127.0.0.1:11434
+editor-model: '2024'
+"#;
+
+   #[test]
+   fn test_yaml_aider_round_trip_keeps_order() {
+      let mut value = parse_config(AIDER_YAML, ConfigFormat::Yaml).unwrap();
+      assert_eq!(
+         get_nested_value(&value, "weak-model"),
+         Some(&Value::String("haiku".into()))
+      );
+      assert_eq!(
+         get_nested_value(&value, "auto-commits"),
+         Some(&Value::Bool(false))
+      );
+      assert_eq!(
+         get_nested_value(&value, "editor-model"),
+         Some(&Value::String("2024".into()))
+      );
+
+      set_nested_value(
+         &mut value,
+         "weak-model",
+         Value::String("gpt-4o-mini".into()),
+      )
+      .unwrap();
+      set_nested_value(&mut value, "auto-commits", Value::Bool(true)).unwrap();
+      set_nested_value(&mut value, "reasoning-effort", Value::String("high".into())).unwrap();
+      let written = serialize_config(value, ConfigFormat::Yaml, Some(AIDER_YAML)).unwrap();
+      assert_eq!(
+         written,
+         AIDER_YAML
+            .replace("weak-model: haiku", "weak-model: gpt-4o-mini")
+            .replace("auto-commits: false", "auto-commits: true")
+            + "reasoning-effort: high\n"
+      );
+
+      let value = parse_config(AIDER_YAML, ConfigFormat::Yaml).unwrap();
+      let written = serialize_config(value, ConfigFormat::Yaml, Some(AIDER_YAML)).unwrap();
+      assert_eq!(written, AIDER_YAML);
+   }
+
+   #[test]
+   fn test_yaml_non_string_keys_and_empty_document() {
+      let value = parse_config("1: one\ntrue: yes\n", ConfigFormat::Yaml).unwrap();
+      assert_eq!(
+         get_nested_value(&value, "1"),
+         Some(&Value::String("one".into()))
+      );
+      assert_eq!(
+         get_nested_value(&value, "true"),
+         Some(&Value::String("yes".into()))
+      );
+
+      let empty = parse_config("", ConfigFormat::Yaml).unwrap();
+      assert_eq!(empty, Value::Object(Map::new()));
+   }
+
+   const COMMENTED_JSON: &str = r#"{
+   // Claude Code settings
+   /* Previously:
+      "model": "claude-3-opus",
+   */
+   "model": "claude-sonnet-4", // current default
+   "env": {
+      "URL": "https://example.com/*not-a-comment*/", // strings are left alone
+      "PATH": "//also/not/a/comment",
+   },
+   "permissions": ["Read", "Edit",],
+}
+"#;
+
+   #[test]
+   fn test_jsonc_comments_and_trailing_commas() {
+      let value = parse_config(COMMENTED_JSON, ConfigFormat::Json).unwrap();
+
+      assert_eq!(
+         get_nested_value(&value, "model"),
+         Some(&Value::String("claude-sonnet-4".into()))
+      );
+      assert_eq!(
+         get_nested_value(&value, "env.URL"),
+         Some(&Value::String(
+            "https://example.com/*not-a-comment*/".into()
+         ))
+      );
+      assert_eq!(
+         get_nested_value(&value, "env.PATH"),
+         Some(&Value::String("//also/not/a/comment".into()))
+      );
+      assert_eq!(
+         get_nested_value(&value, "permissions"),
+         Some(&serde_json::json!(["Read", "Edit"]))
+      );
+   }
+
+   #[test]
+   fn test_jsonc_model_only_inside_comment_is_ignored() {
+      let content = "{\n  /* \"model\": \"hidden\" */\n  // \"model\": \"also-hidden\",\n  \
+                     \"other\": \"x\\\"y\",\n}";
+      let value = parse_config(content, ConfigFormat::Json).unwrap();
+      assert_eq!(get_nested_value(&value, "model"), None);
+      assert_eq!(
+         get_nested_value(&value, "other"),
+         Some(&Value::String("x\"y".into()))
+      );
+   }
+
+   #[test]
+   fn test_jsonc_rewrite_produces_plain_json() {
+      let mut value = parse_config(COMMENTED_JSON, ConfigFormat::Json).unwrap();
+      set_nested_value(&mut value, "model", Value::String("claude-opus-4".into())).unwrap();
+
+      let written = serialize_config(value, ConfigFormat::Json, None).unwrap();
+      let reparsed: Value = serde_json::from_str(&written).unwrap();
+      assert_eq!(
+         get_nested_value(&reparsed, "model"),
+         Some(&Value::String("claude-opus-4".into()))
+      );
+      assert_eq!(
+         get_nested_value(&reparsed, "env.PATH"),
+         Some(&Value::String("//also/not/a/comment".into()))
+      );
+   }
+
+   /// `content` as saved by an editor that writes a byte order mark when `bom` and `\r\n` line
+   /// endings when `crlf`
+   fn in_style(content: &str, bom: bool, crlf: bool) -> String {
+      let content = if crlf {
+         content.replace('\n', "\r\n")
+      } else {
+         content.to_string()
+      };
+      if bom {
+         format!("\u{feff}{}", content)
+      } else {
+         content
+      }
+   }
+
+   #[test]
+   fn test_rewrite_keeps_bom_and_line_endings() {
+      let json = "{\n  \"model\": \"o3\",\n  \"other\": true\n}";
+      let toml = "# Codex\nmodel = \"o3\"\n\n[profiles.work]\nmodel = \"o4\"\n";
+      for (format, content) in [(ConfigFormat::Json, json), (ConfigFormat::Toml, toml)] {
+         for (bom, crlf) in [(true, true), (true, false), (false, false)] {
+            let original = in_style(content, bom, crlf);
+            let mut value = parse_config(&original, format).unwrap();
+            assert_eq!(get_nested_value(&value, "model"), Some(&Value::from("o3")));
+            set_nested_value(&mut value, "model", Value::from("gpt-5")).unwrap();
+
+            let written = serialize_config(value, format, Some(&original)).unwrap();
+            let expected = in_style(&content.replacen("\"o3\"", "\"gpt-5\"", 1), bom, crlf);
+            assert_eq!(
+               written, expected,
+               "{} with bom={} crlf={}",
+               format, bom, crlf
+            );
+         }
+      }
+   }
+
+   /// Lines of `written` that differ from `original`, which must have as many lines
+   fn changed_lines(original: &str, written: &str) -> Vec<usize> {
+      assert_eq!(original.lines().count(), written.lines().count());
+      original
+         .lines()
+         .zip(written.lines())
+         .enumerate()
+         .filter(|(_, (before, after))| before != after)
+         .map(|(index, _)| index)
+         .collect()
+   }
+
+   #[test]
+   fn test_json_rewrite_keeps_indentation() {
+      let four_spaces = r#"{
+    "env": {
+        "DEBUG": "1"
+    },
+    "model": "o3",
+    "permissions": [
+        "read"
+    ]
+}
+"#;
+      let tabs = four_spaces
+         .replace("        ", "\t\t")
+         .replace("    ", "\t");
+      for original in [four_spaces.to_string(), tabs] {
+         let mut value = parse_config(&original, ConfigFormat::Json).unwrap();
+         set_nested_value(&mut value, "model", Value::from("gpt-5")).unwrap();
+
+         let written = serialize_config(value, ConfigFormat::Json, Some(&original)).unwrap();
+         assert_eq!(changed_lines(&original, &written), [4]);
+         assert!(written.ends_with("}\n"));
+      }
+   }
+
+   #[test]
+   fn test_json_rewrite_keeps_key_order() {
+      let original = r#"{
+  "permissions": {
+    "allow": [
+      "Bash"
+    ]
+  },
+  "model": "o3",
+  "env": {
+    "DEBUG": "1"
+  },
+  "$schema": "https://json.schemastore.org/claude-code-settings.json"
+}
+"#;
+      let mut value = parse_config(original, ConfigFormat::Json).unwrap();
+      set_nested_value(&mut value, "model", Value::from("gpt-5")).unwrap();
+
+      let written = serialize_config(value, ConfigFormat::Json, Some(original)).unwrap();
+      assert_eq!(changed_lines(original, &written), [6]);
+   }
+
+   #[test]
+   fn test_json_indentation_defaults() {
+      let value = serde_json::json!({ "model": "o3" });
+      let new_file = serialize_config(value.clone(), ConfigFormat::Json, None).unwrap();
+      assert_eq!(new_file, "{\n  \"model\": \"o3\"\n}");
+      let minified =
+         serialize_config(value, ConfigFormat::Json, Some("{\"model\":\"o4\"}")).unwrap();
+      assert_eq!(minified, new_file);
+      assert_eq!(
+         json_indent("{\n   \"a\": {\n      \"b\": 1\n   }\n}"),
+         Some("   ")
+      );
+   }
+
+   #[test]
+   fn test_lenient_parse_salvages_damaged_json() {
+      let duplicated = "{\n  // theme\n  \"model\": \"o3\",\n}\n}\n";
+      assert!(parse_config(duplicated, ConfigFormat::Json).is_err());
+      assert_eq!(
+         parse_config_lenient(duplicated, ConfigFormat::Json),
+         Some(serde_json::json!({ "model": "o3" }))
+      );
+      let padded = "model = \"o3\"\n\0\0\0";
+      assert!(parse_config(padded, ConfigFormat::Toml).is_err());
+      assert!(parse_config_lenient(padded, ConfigFormat::Toml).is_some());
+      assert_eq!(
+         parse_config_lenient("{\"model\": ", ConfigFormat::Json),
+         None
+      );
+   }
+
+   #[test]
+   fn test_line_ending_follows_the_majority() {
+      assert!(uses_crlf("a\r\nb\r\nc\n"));
+      assert!(!uses_crlf("a\nb\nc\r\n"));
+      assert!(!uses_crlf("no line breaks"));
+      let written = match_text_style("{\n}".to_string(), Some("{\r\n}"));
+      assert_eq!(written, "{\r\n}");
+   }
+
+   const COMMENTED_TOML: &str = r#"# Codex configuration
+model = "gpt-5" # default model
+approval_policy = "on-request"
+
+# Reasoning settings
+model_reasoning_effort = "medium"
+
+[mcp_servers.docs]
+# Local docs server
+command = "npx"
+args = ["-y", "docs-mcp"]
+
+[profiles.work]
+model = "o3"
+"#;
+
+   fn update_toml(content: &str, key: &str, new_value: Value) -> String {
+      let mut value = parse_config(content, ConfigFormat::Toml).unwrap();
+      set_nested_value(&mut value, key, new_value).unwrap();
+      serialize_config(value, ConfigFormat::Toml, Some(content)).unwrap()
+   }
+
+   #[test]
+   fn test_toml_update_preserves_comments_and_tables() {
+      let written = update_toml(COMMENTED_TOML, "model", Value::String("gpt-5-codex".into()));
+      assert_eq!(
+         written,
+         COMMENTED_TOML.replace(
+            "model = \"gpt-5\" # default model",
+            "model = \"gpt-5-codex\" # default model"
+         )
+      );
+   }
+
+   #[test]
+   fn test_toml_update_nested_and_new_keys() {
+      let written = update_toml(
+         COMMENTED_TOML,
+         "profiles.work.model",
+         Value::String("o4-mini".into()),
+      );
+      assert!(written.contains("[profiles.work]\nmodel = \"o4-mini\"\n"));
+      assert!(written.contains("# Local docs server\ncommand = \"npx\""));
+
+      let written = update_toml(COMMENTED_TOML, "tools.web_search", Value::Bool(true));
+      assert!(written.starts_with("# Codex configuration\nmodel = \"gpt-5\" # default model\n"));
+      let reparsed = parse_config(&written, ConfigFormat::Toml).unwrap();
+      assert_eq!(
+         get_nested_value(&reparsed, "tools.web_search"),
+         Some(&Value::Bool(true))
+      );
+      assert_eq!(
+         get_nested_value(&reparsed, "mcp_servers.docs.args"),
+         Some(&serde_json::json!(["-y", "docs-mcp"]))
+      );
+   }
+
+   #[test]
+   fn test_toml_new_profile_tables() {
+      let content = "model = \"gpt-5\"\n\n[profiles.oss]\nmodel_provider = \
+                     \"ollama\"\n\n[mcp_servers.docs]\ncommand = \"npx\"\n";
+      let written = update_toml(content, "profiles.work.model", Value::String("o3".into()));
+      assert_eq!(
+         written,
+         content.replace(
+            "[mcp_servers.docs]",
+            "[profiles.work]\nmodel = \"o3\"\n\n[mcp_servers.docs]"
+         )
+      );
+
+      let written = update_toml(
+         "model = \"gpt-5\"\n",
+         "profiles.work.model",
+         Value::String("o3".into()),
+      );
+      assert_eq!(
+         written,
+         "model = \"gpt-5\"\n\n[profiles.work]\nmodel = \"o3\"\n"
+      );
+   }
+
+   /// Asserts every `key = value` line precedes the first section header of the document
+   fn assert_values_before_tables(content: &str, key: &str) {
+      let value_line = content
+         .lines()
+         .position(|line| line.starts_with(&format!("{} = ", key)))
+         .unwrap_or_else(|| panic!("missing {} in:\n{}", key, content));
+      let first_table = content
+         .lines()
+         .position(|line| line.starts_with('['))
+         .expect("missing table header");
+      assert!(
+         value_line < first_table,
+         "{} emitted after a table:\n{}",
+         key,
+         content
+      );
+   }
+
+   #[test]
+   fn test_toml_scalars_after_tables_serialize() {
+      // "mcp_servers" sorts before "model", so the table is encountered before the scalar
+      let value = serde_json::json!({
+         "mcp_servers": { "foo": { "command": "npx", "env": { "A": "1" } } },
+         "model": "o3",
+         "profiles": [{ "name": "work", "nested": { "x": 1 }, "model": "o4-mini" }],
+         "zeta": 1,
+      });
+
+      let written = serialize_config(value.clone(), ConfigFormat::Toml, None).unwrap();
+      assert_values_before_tables(&written, "model");
+      assert_values_before_tables(&written, "zeta");
+      assert_eq!(parse_config(&written, ConfigFormat::Toml).unwrap(), value);
+   }
+
+   #[test]
+   fn test_toml_new_scalar_in_document_with_tables() {
+      let content = "[mcp_servers.foo]\ncommand = \"npx\"\n";
+      let written = update_toml(content, "model", Value::String("o3".into()));
+      assert_values_before_tables(&written, "model");
+
+      let reparsed = parse_config(&written, ConfigFormat::Toml).unwrap();
+      assert_eq!(
+         reparsed,
+         serde_json::json!({ "model": "o3", "mcp_servers": { "foo": { "command": "npx" } } })
+      );
+   }
+
+   #[test]
+   fn test_json_to_toml_drops_nulls() {
+      let value = serde_json::json!({
+         "model": "o3",
+         "reasoningEffort": null,
+         "nested": { "keep": true, "drop": null, "deeper": { "gone": null } },
+         "list": ["a", null, "b"],
+      });
+
+      let toml = json_to_toml(value.clone()).unwrap();
+      assert_eq!(json_to_toml(Value::Null), None);
+      assert_eq!(
+         toml_to_json(toml),
+         serde_json::json!({
+            "model": "o3",
+            "nested": { "keep": true, "deeper": {} },
+            "list": ["a", "b"],
+         })
+      );
+
+      let written = serialize_config(value, ConfigFormat::Toml, None).unwrap();
+      assert!(!written.contains("reasoningEffort"));
+      assert!(!written.contains("\"\""));
+   }
+
+   #[test]
+   fn test_toml_update_with_null_removes_key() {
+      let content = "model = \"o3\"\nmodel_reasoning_effort = \"high\"\n\n[tools]\nweb = \
+                     true\nlist = [1, 2]\n";
+      let mut value = parse_config(content, ConfigFormat::Toml).unwrap();
+      set_nested_value(&mut value, "model_reasoning_effort", Value::Null).unwrap();
+      set_nested_value(&mut value, "tools.web", Value::Null).unwrap();
+      set_nested_value(&mut value, "tools.list", serde_json::json!([1, null, 3])).unwrap();
+
+      let written = serialize_config(value, ConfigFormat::Toml, Some(content)).unwrap();
+      assert_eq!(written, "model = \"o3\"\n\n[tools]\nlist = [1, 3]\n");
+   }
+
+   #[test]
+   fn test_toml_untouched_values_keep_their_types() {
+      let content = r#"model = "o3"
+timeout = 30.0
+ratio = 1e3
+max_id = 9223372036854775807
+min_id = -9223372036854775808
+hex = 0xff
+not_a_number = nan
+last_sync = 2024-06-01T12:00:00Z
+local_day = 2024-06-01
+
+[history]
+started = 1979-05-27T07:32:00-08:00
+limit = 10.0
+"#;
+
+      let written = update_toml(content, "model", Value::String("o4-mini".into()));
+      assert_eq!(
+         written,
+         content.replace("model = \"o3\"", "model = \"o4-mini\"")
+      );
+
+      let reparsed: toml::Value = toml::from_str(&written).unwrap();
+      assert!(reparsed["timeout"].is_float());
+      assert_eq!(reparsed["max_id"].as_integer(), Some(i64::MAX));
+      assert_eq!(reparsed["min_id"].as_integer(), Some(i64::MIN));
+      assert!(reparsed["last_sync"].is_datetime());
+      assert!(reparsed["history"]["started"].is_datetime());
+      assert!(reparsed["history"]["limit"].is_float());
+   }
+
+   #[test]
+   fn test_json_floats_with_zero_fraction_stay_floats() {
+      let value = serde_json::json!({ "temperature": 1.0, "max_tokens": 4096 });
+      let written = serialize_config(value, ConfigFormat::Toml, None).unwrap();
+      let reparsed: toml::Value = toml::from_str(&written).unwrap();
+      assert!(reparsed["temperature"].is_float());
+      assert!(reparsed["max_tokens"].is_integer());
+
+      let content = "temperature = 0.5\n";
+      let written = update_toml(content, "temperature", serde_json::json!(2.0));
+      assert_eq!(written, "temperature = 2.0\n");
+   }
+
+   fn parse_position(content: &str, format: ConfigFormat) -> (usize, usize, String) {
+      match parse_config(content, format) {
+         Err(AgentSettingsError::Parse {
+            line: Some(line),
+            column: Some(column),
+            snippet: Some(snippet),
+            ..
+         }) => (line, column, snippet),
+         other => panic!("expected a located parse error, got {:?}", other),
+      }
+   }
+
+   #[test]
+   fn test_toml_parse_error_location() {
+      let content = "# comment\nmodel = \"o3\"\nreasoning = high\n";
+      let (line, column, snippet) = parse_position(content, ConfigFormat::Toml);
+      assert_eq!(line, 3);
+      assert_eq!(column, 13);
+      assert_eq!(snippet, "reasoning = high");
+   }
+
+   #[test]
+   fn test_json_parse_error_location_survives_comments() {
+      let content = "{\n  // the model\n  \"model\": \"o3\"\n  \"preview\": true\n}";
+      let (line, _, snippet) = parse_position(content, ConfigFormat::Json);
+      assert_eq!(line, 4);
+      assert_eq!(snippet, "  \"preview\": true");
+   }
+
+   #[test]
+   fn test_yaml_parse_error_location() {
+      let content = "model: o3\nreasoning: low: high\n";
+      let (line, _, snippet) = parse_position(content, ConfigFormat::Yaml);
+      assert_eq!(line, 2);
+      assert_eq!(snippet, "reasoning: low: high");
+   }
+
+   #[test]
+   fn test_line_column_counts_characters() {
+      let content = "a = \"é\"\nb = 1";
+      assert_eq!(line_column(content, 0), (1, 1));
+      assert_eq!(line_column(content, content.find('b').unwrap()), (2, 1));
+      assert_eq!(line_column(content, content.find('"').unwrap() + 3), (1, 7));
+      assert_eq!(line_snippet("one\r\ntwo", 1), Some("one".to_string()));
+      assert_eq!(line_snippet("one", 0), None);
+   }
+}
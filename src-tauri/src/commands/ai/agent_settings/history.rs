@@ -0,0 +1,549 @@
+use super::{
+   encryption::{ConfigEncryption, decode},
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   keys::{delete_nested_value, format_key_path, set_nested_value},
+   paths::{AgentSettingsRoots, get_home_dir},
+   secrets::mask_secret_values,
+   settings::AgentSettingsWriteResult,
+   storage::{AgentSettingsLocks, WriteOptions, run_blocking, update_config_file},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+   collections::BTreeSet,
+   fs,
+   io::{self, Write},
+   path::{Path, PathBuf},
+};
+use tauri::{State, command};
+use tokio::sync::Mutex;
+
+/// Journal file name inside `~/.athas`
+const HISTORY_FILE: &str = "settings-history.jsonl";
+
+/// Size at which the journal is rotated. One older generation is kept next to it as
+/// `settings-history.1.jsonl`.
+const MAX_HISTORY_BYTES: u64 = 1024 * 1024;
+
+/// Number of entries `get_agent_settings_history` returns when no limit is given
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+/// One key changed by a write Athas made to an agent's config file. A missing value means the
+/// key was absent on that side of the change. Secrets are kept masked, as `get_agent_config_values`
+/// returns them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsHistoryEntry {
+   pub id: String,
+   pub timestamp: DateTime<Utc>,
+   pub agent_id: String,
+   /// Resolved path of the config file
+   pub path: String,
+   pub key: String,
+   pub old_value: Option<Value>,
+   pub new_value: Option<Value>,
+   /// Whether secrets in `old_value` were masked, so the change can't be undone
+   #[serde(default)]
+   pub old_value_masked: bool,
+}
+
+/// A key whose value differs between two versions of a document
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct KeyChange {
+   pub key: String,
+   pub old_value: Option<Value>,
+   pub new_value: Option<Value>,
+}
+
+/// Append-only journal of the changes Athas makes to agent config files
+#[derive(Debug)]
+pub struct AgentSettingsHistory {
+   /// Journal file, `None` when there is no home directory to keep it in
+   path: Option<PathBuf>,
+   max_bytes: u64,
+   lock: Mutex<()>,
+}
+
+impl AgentSettingsHistory {
+   pub fn new() -> Self {
+      let path = get_home_dir()
+         .ok()
+         .map(|home| home.join(".athas").join(HISTORY_FILE));
+      Self::at(path, MAX_HISTORY_BYTES)
+   }
+
+   /// History kept in `path` instead of `~/.athas`, rotated at `max_bytes`
+   pub(super) fn at(path: Option<PathBuf>, max_bytes: u64) -> Self {
+      Self {
+         path,
+         max_bytes,
+         lock: Mutex::new(()),
+      }
+   }
+
+   /// Append one entry per change, rotating the journal first if it would grow past its cap.
+   /// Entries are sealed when encryption at rest is on.
+   async fn record(
+      &self,
+      encryption: Option<&ConfigEncryption>,
+      agent_id: &str,
+      config_path: &Path,
+      changes: Vec<KeyChange>,
+   ) -> Result<(), AgentSettingsError> {
+      let Some(path) = &self.path else {
+         return Ok(());
+      };
+      if changes.is_empty() {
+         return Ok(());
+      }
+
+      let timestamp = Utc::now();
+      let mut lines = String::new();
+      for change in changes {
+         let (old_value, old_value_masked) = masked(&change.key, change.old_value);
+         let (new_value, _) = masked(&change.key, change.new_value);
+         let entry = SettingsHistoryEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp,
+            agent_id: agent_id.to_string(),
+            path: config_path.display().to_string(),
+            key: change.key,
+            old_value,
+            new_value,
+            old_value_masked,
+         };
+         let line = serde_json::to_string(&entry).map_err(|e| AgentSettingsError::Serialize {
+            format: ConfigFormat::Json,
+            message: e.to_string(),
+         })?;
+         match encryption {
+            Some(encryption) => lines.push_str(&encryption.seal_line(path, &line)?),
+            None => {
+               lines.push_str(&line);
+               lines.push('\n');
+            }
+         }
+      }
+
+      let _guard = self.lock.lock().await;
+      let path = path.clone();
+      let max_bytes = self.max_bytes;
+      run_blocking(move || append(&path, &lines, max_bytes)).await
+   }
+
+   /// All entries still in the journal, oldest first. Lines that don't parse or can't be
+   /// decrypted are skipped.
+   async fn entries(
+      &self,
+      encryption: Option<&ConfigEncryption>,
+   ) -> Result<Vec<SettingsHistoryEntry>, AgentSettingsError> {
+      let Some(path) = &self.path else {
+         return Ok(Vec::new());
+      };
+      let _guard = self.lock.lock().await;
+
+      let mut entries = Vec::new();
+      for file in [rotated_path(path), path.clone()] {
+         let content = match tokio::fs::read_to_string(&file).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(AgentSettingsError::io(&file, e)),
+         };
+         entries.extend(content.lines().filter_map(|line| {
+            let line = decode(encryption, &file, line.to_string()).ok()?;
+            serde_json::from_str::<SettingsHistoryEntry>(&line).ok()
+         }));
+      }
+      Ok(entries)
+   }
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+   path.with_extension("1.jsonl")
+}
+
+/// Append `lines` to the journal at `path`, first moving it to its rotated generation if it
+/// would grow past `max_bytes`
+fn append(path: &Path, lines: &str, max_bytes: u64) -> Result<(), AgentSettingsError> {
+   if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(|e| AgentSettingsError::io(parent, e))?;
+   }
+   let size = fs::metadata(path)
+      .map(|metadata| metadata.len())
+      .unwrap_or(0);
+   if size > 0 && size + lines.len() as u64 > max_bytes {
+      let rotated = rotated_path(path);
+      fs::rename(path, &rotated).map_err(|e| AgentSettingsError::io(path, e))?;
+   }
+   fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(path)
+      .and_then(|mut file| file.write_all(lines.as_bytes()))
+      .map_err(|e| AgentSettingsError::io(path, e))
+}
+
+/// `value` with the secrets under `key` masked, and whether there were any
+fn masked(key: &str, value: Option<Value>) -> (Option<Value>, bool) {
+   let Some(mut value) = value else {
+      return (None, false);
+   };
+   let original = value.clone();
+   mask_secret_values(key, &mut value);
+   let masked = value != original;
+   (Some(value), masked)
+}
+
+/// Records the changes of a write in the settings history on behalf of `agent_id`
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Journal<'a> {
+   pub history: &'a AgentSettingsHistory,
+   pub agent_id: &'a str,
+}
+
+impl Journal<'_> {
+   /// Record the changes of a write that has landed. The write already happened, so a journal
+   /// failure is logged rather than returned.
+   pub(super) async fn record(
+      self,
+      locks: &AgentSettingsLocks,
+      path: &Path,
+      changes: Vec<KeyChange>,
+   ) {
+      let encryption = locks.encryption();
+      if let Err(e) = self
+         .history
+         .record(encryption, self.agent_id, path, changes)
+         .await
+      {
+         log::warn!(
+            "Failed to record settings history for {}: {}",
+            path.display(),
+            e
+         );
+      }
+   }
+}
+
+/// Keys whose value differs between `before` and `after`. Objects are compared key by key and
+/// anything else, arrays included, as a whole. A change to a non-object document root has no
+/// key path and is not reported.
+pub(super) fn diff_values(before: &Value, after: &Value) -> Vec<KeyChange> {
+   let mut changes = Vec::new();
+   diff_into(Some(before), Some(after), &mut Vec::new(), &mut changes);
+   changes
+}
+
+fn diff_into(
+   before: Option<&Value>,
+   after: Option<&Value>,
+   names: &mut Vec<String>,
+   changes: &mut Vec<KeyChange>,
+) {
+   match (before, after) {
+      (Some(Value::Object(before)), Some(Value::Object(after))) => {
+         let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+         for key in keys {
+            names.push(key.clone());
+            diff_into(before.get(key), after.get(key), names, changes);
+            names.pop();
+         }
+      }
+      _ if before == after || names.is_empty() => {}
+      _ => changes.push(KeyChange {
+         key: format_key_path(names),
+         old_value: before.cloned(),
+         new_value: after.cloned(),
+      }),
+   }
+}
+
+/// Changes Athas made to an agent's config files, newest first
+#[command]
+pub async fn get_agent_settings_history(
+   locks: State<'_, AgentSettingsLocks>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   limit: Option<usize>,
+) -> Result<Vec<SettingsHistoryEntry>, AgentSettingsError> {
+   Ok(history
+      .entries(locks.encryption())
+      .await?
+      .into_iter()
+      .rev()
+      .filter(|entry| entry.agent_id == agent_id)
+      .take(limit.unwrap_or(DEFAULT_HISTORY_LIMIT))
+      .collect())
+}
+
+/// Put a key back to the value it had before a recorded change. The undo is an ordinary write,
+/// so it shows up in the history itself and can be undone in turn. Changes that replaced a
+/// secret can't be undone, since the history only has the secret masked.
+#[command]
+pub async fn undo_agent_settings_change(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   entry_id: String,
+   follow_symlinks: Option<bool>,
+) -> Result<AgentSettingsWriteResult, AgentSettingsError> {
+   let entry = history
+      .entries(locks.encryption())
+      .await?
+      .into_iter()
+      .find(|entry| entry.id == entry_id)
+      .ok_or(AgentSettingsError::HistoryEntryNotFound { id: entry_id })?;
+   if entry.old_value_masked {
+      return Err(AgentSettingsError::HistoryValueMasked {
+         id: entry.id,
+         key: entry.key,
+      });
+   }
+   let path = roots.check(Path::new(&entry.path), follow_symlinks.unwrap_or(false))?;
+
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &entry.agent_id,
+      }),
+      ..WriteOptions::default()
+   };
+   let wrote = update_config_file(
+      &locks,
+      &path,
+      ConfigFormat::from_path(&entry.path),
+      options,
+      |value| match entry.old_value.clone() {
+         Some(old_value) => set_nested_value(value, &entry.key, old_value),
+         None => delete_nested_value(value, &entry.key, false).map(|_| ()),
+      },
+   )
+   .await?;
+
+   if wrote {
+      log::info!("Undid change to {} for agent {}", entry.key, entry.agent_id);
+   }
+   Ok(AgentSettingsWriteResult {
+      wrote,
+      warnings: Vec::new(),
+      pending: false,
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+   use tauri::Manager;
+
+   #[test]
+   fn test_diff_reports_leaf_changes() {
+      let before = json!({
+         "model": "sonnet",
+         "mcpServers": { "my.server": { "command": "a" } },
+         "permissions": { "allow": ["Read"] },
+         "theme": "dark",
+      });
+      let after = json!({
+         "model": "opus",
+         "mcpServers": { "my.server": { "command": "b" } },
+         "permissions": { "allow": ["Read", "Bash"] },
+         "env": { "DEBUG": "1" },
+         "theme": "dark",
+      });
+
+      let changes: Vec<_> = diff_values(&before, &after)
+         .into_iter()
+         .map(|change| (change.key, change.old_value, change.new_value))
+         .collect();
+      assert_eq!(
+         changes,
+         vec![
+            ("env".into(), None, Some(json!({ "DEBUG": "1" }))),
+            (
+               r#"mcpServers."my.server".command"#.into(),
+               Some(json!("a")),
+               Some(json!("b"))
+            ),
+            ("model".into(), Some(json!("sonnet")), Some(json!("opus"))),
+            (
+               "permissions.allow".into(),
+               Some(json!(["Read"])),
+               Some(json!(["Read", "Bash"]))
+            ),
+         ]
+      );
+   }
+
+   #[tokio::test]
+   async fn test_journal_rotates_at_cap() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join(HISTORY_FILE);
+      let history = AgentSettingsHistory::at(Some(path.clone()), 400);
+      let change = |n: u64| KeyChange {
+         key: "max_tokens".into(),
+         old_value: Some(json!(n)),
+         new_value: Some(json!(n + 1)),
+      };
+
+      for n in 0..6 {
+         history
+            .record(None, "codex", Path::new("/c.toml"), vec![change(n)])
+            .await
+            .unwrap();
+      }
+
+      assert!(rotated_path(&path).exists());
+      assert!(fs::metadata(&path).unwrap().len() <= 400);
+      let entries = history.entries(None).await.unwrap();
+      assert!(entries.len() < 6);
+      assert_eq!(entries.last().unwrap().new_value, Some(json!(6)));
+   }
+
+   #[tokio::test]
+   async fn test_undo_restores_and_is_recorded() {
+      let dir = tempfile::tempdir().unwrap();
+      let config = dir.path().join("settings.json");
+      std::fs::write(&config, r#"{ "model": "sonnet" }"#).unwrap();
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+      let roots = AgentSettingsRoots::new();
+      roots.allow(dir.path().to_path_buf());
+      app.manage(roots);
+      app.manage(AgentSettingsHistory::at(
+         Some(dir.path().join("history.jsonl")),
+         MAX_HISTORY_BYTES,
+      ));
+
+      let history: State<AgentSettingsHistory> = app.state();
+      let options = WriteOptions {
+         journal: Some(Journal {
+            history: &history,
+            agent_id: "claude",
+         }),
+         ..WriteOptions::default()
+      };
+      update_config_file(
+         &app.state::<AgentSettingsLocks>(),
+         &config,
+         ConfigFormat::Json,
+         options,
+         |value| {
+            set_nested_value(value, "model", json!("opus"))?;
+            set_nested_value(value, "theme", json!("dark"))
+         },
+      )
+      .await
+      .unwrap();
+
+      let entries = get_agent_settings_history(app.state(), app.state(), "claude".into(), None)
+         .await
+         .unwrap();
+      assert_eq!(entries.len(), 2);
+      let model_change = entries.iter().find(|entry| entry.key == "model").unwrap();
+      let theme_change = entries.iter().find(|entry| entry.key == "theme").unwrap();
+
+      for entry in [model_change, theme_change] {
+         undo_agent_settings_change(
+            app.state(),
+            app.state(),
+            app.state(),
+            entry.id.clone(),
+            None,
+         )
+         .await
+         .unwrap();
+      }
+
+      let value: Value = serde_json::from_str(&fs::read_to_string(&config).unwrap()).unwrap();
+      assert_eq!(value, json!({ "model": "sonnet" }));
+      let entries = get_agent_settings_history(app.state(), app.state(), "claude".into(), Some(2))
+         .await
+         .unwrap();
+      assert_eq!(entries[0].key, "theme");
+      assert_eq!(entries[0].new_value, None);
+      assert_eq!(entries[1].new_value, Some(json!("sonnet")));
+      assert!(
+         get_agent_settings_history(app.state(), app.state(), "codex".into(), None)
+            .await
+            .unwrap()
+            .is_empty()
+      );
+   }
+
+   #[tokio::test]
+   async fn test_secrets_are_masked_and_not_undone() {
+      let dir = tempfile::tempdir().unwrap();
+      let config = dir.path().join("settings.json");
+      let journal = dir.path().join("history.jsonl");
+      std::fs::write(
+         &config,
+         r#"{ "env": { "ANTHROPIC_API_KEY": "sk-ant-0123456789abcdef" } }"#,
+      )
+      .unwrap();
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+      let roots = AgentSettingsRoots::new();
+      roots.allow(dir.path().to_path_buf());
+      app.manage(roots);
+      app.manage(AgentSettingsHistory::at(
+         Some(journal.clone()),
+         MAX_HISTORY_BYTES,
+      ));
+
+      let history: State<AgentSettingsHistory> = app.state();
+      let options = WriteOptions {
+         journal: Some(Journal {
+            history: &history,
+            agent_id: "claude",
+         }),
+         ..WriteOptions::default()
+      };
+      update_config_file(
+         &app.state::<AgentSettingsLocks>(),
+         &config,
+         ConfigFormat::Json,
+         options,
+         |value| {
+            set_nested_value(
+               value,
+               "env.ANTHROPIC_API_KEY",
+               json!("sk-ant-fedcba9876543210"),
+            )
+         },
+      )
+      .await
+      .unwrap();
+
+      let stored = fs::read_to_string(&journal).unwrap();
+      assert!(!stored.contains("0123456789abcdef") && !stored.contains("fedcba9876543210"));
+      let entries = get_agent_settings_history(app.state(), app.state(), "claude".into(), None)
+         .await
+         .unwrap();
+      assert_eq!(entries[0].key, "env.ANTHROPIC_API_KEY");
+      assert!(entries[0].old_value_masked);
+      assert_eq!(
+         entries[0].new_value.as_ref().unwrap()["masked"],
+         json!(true)
+      );
+
+      assert!(matches!(
+         undo_agent_settings_change(
+            app.state(),
+            app.state(),
+            app.state(),
+            entries[0].id.clone(),
+            None,
+         )
+         .await,
+         Err(AgentSettingsError::HistoryValueMasked { .. })
+      ));
+      assert!(
+         fs::read_to_string(&config)
+            .unwrap()
+            .contains("sk-ant-fedcba9876543210")
+      );
+   }
+}
@@ -0,0 +1,177 @@
+use super::{error::AgentSettingsError, keys::push_key_name, paths::is_variable_name};
+use serde_json::Value;
+
+/// The value of environment variable `name`, for the `env` parameters below
+pub(super) fn env_var(name: &str) -> Option<String> {
+   std::env::var(name).ok()
+}
+
+/// Expand `${VAR}` and `${VAR:-default}` references in `text`, the value at `key`. The default
+/// is used when the variable is unset or empty. A variable without a value or default is an
+/// `UnresolvedVariable` error; a `$` that doesn't start a well-formed reference is kept as-is.
+pub(super) fn interpolate_str(
+   text: &str,
+   key: &str,
+   env: &impl Fn(&str) -> Option<String>,
+) -> Result<String, AgentSettingsError> {
+   let mut expanded = String::with_capacity(text.len());
+   let mut rest = text;
+   while let Some(start) = rest.find("${") {
+      expanded.push_str(&rest[..start]);
+      let after = &rest[start + 2..];
+      let Some(end) = after.find('}') else {
+         expanded.push_str(&rest[start..]);
+         return Ok(expanded);
+      };
+      let (name, default) = match after[..end].split_once(":-") {
+         Some((name, default)) => (name, Some(default)),
+         None => (&after[..end], None),
+      };
+      if !is_variable_name(name) {
+         expanded.push_str("${");
+         rest = after;
+         continue;
+      }
+
+      let value = env(name).filter(|value| default.is_none() || !value.is_empty());
+      match value.or(default.map(String::from)) {
+         Some(value) => expanded.push_str(&value),
+         None => {
+            return Err(AgentSettingsError::UnresolvedVariable {
+               variable: name.to_string(),
+               key: key.to_string(),
+            });
+         }
+      }
+      rest = &after[end + 1..];
+   }
+   expanded.push_str(rest);
+   Ok(expanded)
+}
+
+/// Expand variable references in every string of `value`, as [`interpolate_str`] does, naming
+/// the key path of the string in errors
+pub(super) fn interpolate_env(
+   value: &mut Value,
+   env: &impl Fn(&str) -> Option<String>,
+) -> Result<(), AgentSettingsError> {
+   interpolate_at(value, &mut String::new(), env)
+}
+
+/// Like [`interpolate_env`], for a `value` found at `key` of its document
+pub(super) fn interpolate_env_at(
+   value: &mut Value,
+   key: &str,
+   env: &impl Fn(&str) -> Option<String>,
+) -> Result<(), AgentSettingsError> {
+   interpolate_at(value, &mut key.to_string(), env)
+}
+
+fn interpolate_at(
+   value: &mut Value,
+   path: &mut String,
+   env: &impl Fn(&str) -> Option<String>,
+) -> Result<(), AgentSettingsError> {
+   let len = path.len();
+   match value {
+      Value::String(text) if text.contains("${") => *text = interpolate_str(text, path, env)?,
+      Value::Array(items) => {
+         for (index, item) in items.iter_mut().enumerate() {
+            path.push_str(&format!("[{}]", index));
+            interpolate_at(item, path, env)?;
+            path.truncate(len);
+         }
+      }
+      Value::Object(map) => {
+         for (name, item) in map.iter_mut() {
+            push_key_name(path, name);
+            interpolate_at(item, path, env)?;
+            path.truncate(len);
+         }
+      }
+      _ => {}
+   }
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   fn env(name: &str) -> Option<String> {
+      match name {
+         "LLM_GATEWAY_URL" => Some("https://llm.example.com".into()),
+         "EMPTY" => Some(String::new()),
+         _ => None,
+      }
+   }
+
+   #[test]
+   fn test_interpolate_str() {
+      let expand = |text: &str| interpolate_str(text, "key", &env).unwrap();
+      assert_eq!(
+         expand("${LLM_GATEWAY_URL}/v1"),
+         "https://llm.example.com/v1"
+      );
+      assert_eq!(
+         expand("${UNSET:-http://localhost:8080}/v1"),
+         "http://localhost:8080/v1"
+      );
+      assert_eq!(expand("${EMPTY:-fallback}"), "fallback");
+      assert_eq!(expand("${EMPTY}"), "");
+      assert_eq!(expand("${UNSET:-}"), "");
+      assert_eq!(
+         expand("${LLM_GATEWAY_URL:-unused}"),
+         "https://llm.example.com"
+      );
+      // Not references
+      assert_eq!(expand("$LLM_GATEWAY_URL and $5"), "$LLM_GATEWAY_URL and $5");
+      assert_eq!(
+         expand("${1abc} ${{ secrets.X }}"),
+         "${1abc} ${{ secrets.X }}"
+      );
+      assert_eq!(expand("cost: ${"), "cost: ${");
+   }
+
+   #[test]
+   fn test_unresolved_variable_names_key_path() {
+      let mut value = json!({
+         "providers": {
+            "gateway": { "baseUrl": "https://example.com", "headers": ["x", "${TOKEN}"] }
+         }
+      });
+      let err = interpolate_env(&mut value, &env).unwrap_err();
+      assert!(matches!(
+         err,
+         AgentSettingsError::UnresolvedVariable { ref variable, ref key }
+            if variable == "TOKEN" && key == "providers.gateway.headers[1]"
+      ));
+   }
+
+   #[test]
+   fn test_interpolate_env_expands_nested_strings() {
+      let mut value = json!({
+         "providers": {
+            "gateway.internal": {
+               "baseUrl": "${LLM_GATEWAY_URL}/v1",
+               "models": ["${MODEL:-gpt-5}"],
+               "timeout": 30
+            }
+         }
+      });
+      interpolate_env(&mut value, &env).unwrap();
+      assert_eq!(
+         value,
+         json!({
+            "providers": {
+               "gateway.internal": {
+                  "baseUrl": "https://llm.example.com/v1",
+                  "models": ["gpt-5"],
+                  "timeout": 30
+               }
+            }
+         })
+      );
+   }
+}
@@ -0,0 +1,699 @@
+use super::error::AgentSettingsError;
+use serde_json::{Map, Value};
+
+/// One step of a parsed key path
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+   /// A dotted segment such as `model` or `0`. A numeric key indexes into an array when the
+   /// value it is applied to is one, and is an ordinary object key otherwise.
+   Key(String),
+   /// A bracketed index such as `[0]`, which always addresses an array element
+   Index(usize),
+   /// The JSON Pointer `-` segment: one past the last element of an array
+   End,
+}
+
+impl Segment {
+   /// Index this segment addresses in an array of `len` elements
+   fn array_index(&self, len: usize) -> Option<usize> {
+      match self {
+         Segment::Key(key) => key.parse().ok(),
+         Segment::Index(index) => Some(*index),
+         Segment::End => Some(len),
+      }
+   }
+
+   /// Empty container created when a path walks through a missing value
+   fn empty_container(&self) -> Value {
+      match self {
+         Segment::Key(_) => Value::Object(Map::new()),
+         Segment::Index(_) | Segment::End => Value::Array(Vec::new()),
+      }
+   }
+}
+
+fn invalid_key(key: &str, message: impl Into<String>) -> AgentSettingsError {
+   AgentSettingsError::InvalidKeyPath {
+      key: key.to_string(),
+      message: message.into(),
+   }
+}
+
+/// Parse a key path. Paths starting with `/` are JSON Pointers (RFC 6901) such as
+/// `/mcpServers/my.server/command`; anything else uses dot notation.
+fn parse_key_path(key: &str) -> Result<Vec<Segment>, AgentSettingsError> {
+   if key.starts_with('/') {
+      parse_json_pointer(key)
+   } else {
+      parse_dotted_path(key)
+   }
+}
+
+/// Parse a JSON Pointer into segments
+fn parse_json_pointer(key: &str) -> Result<Vec<Segment>, AgentSettingsError> {
+   key.split('/')
+      .skip(1)
+      .map(|token| match token {
+         "-" => Ok(Segment::End),
+         token => unescape_pointer_token(token)
+            .map(Segment::Key)
+            .ok_or_else(|| invalid_key(key, "'~' must be followed by '0' or '1'")),
+      })
+      .collect()
+}
+
+/// Unescape one JSON Pointer reference token, turning `~1` into `/` and `~0` into `~`. Returns
+/// `None` for a `~` not followed by `0` or `1`.
+pub(super) fn unescape_pointer_token(token: &str) -> Option<String> {
+   let mut name = String::new();
+   let mut chars = token.chars();
+   while let Some(c) = chars.next() {
+      if c != '~' {
+         name.push(c);
+         continue;
+      }
+      match chars.next() {
+         Some('0') => name.push('~'),
+         Some('1') => name.push('/'),
+         _ => return None,
+      }
+   }
+   Some(name)
+}
+
+/// Parse a dotted key path like `agent.reasoning`, `models.0.model` or `models[0].model`.
+///
+/// A segment containing dots can be written in double quotes (`mcpServers."my.server".command`,
+/// with `\"` and `\\` escapes inside the quotes) or with backslash-escaped dots
+/// (`mcpServers.my\.server.command`).
+fn parse_dotted_path(key: &str) -> Result<Vec<Segment>, AgentSettingsError> {
+   if key.is_empty() {
+      return Err(invalid_key(key, "key path is empty"));
+   }
+
+   let mut segments = Vec::new();
+   let mut chars = key.chars().peekable();
+   loop {
+      let mut name = String::new();
+      let mut quoted = false;
+
+      if chars.peek() == Some(&'"') {
+         chars.next();
+         quoted = true;
+         loop {
+            match chars.next() {
+               Some('"') => break,
+               Some('\\') => match chars.next() {
+                  Some(escaped) => name.push(escaped),
+                  None => return Err(invalid_key(key, "key path ends with a bare backslash")),
+               },
+               Some(c) => name.push(c),
+               None => return Err(invalid_key(key, "unterminated quoted segment")),
+            }
+         }
+      } else {
+         while let Some(&c) = chars.peek() {
+            match c {
+               '.' | '[' => break,
+               '\\' => {
+                  chars.next();
+                  match chars.next() {
+                     Some(escaped) => name.push(escaped),
+                     None => return Err(invalid_key(key, "key path ends with a bare backslash")),
+                  }
+               }
+               _ => {
+                  name.push(c);
+                  chars.next();
+               }
+            }
+         }
+      }
+
+      if quoted || !name.is_empty() {
+         segments.push(Segment::Key(name));
+      } else if chars.peek() != Some(&'[') {
+         return Err(invalid_key(key, "key path contains an empty segment"));
+      }
+
+      while chars.peek() == Some(&'[') {
+         chars.next();
+         let mut digits = String::new();
+         let mut closed = false;
+         for c in chars.by_ref() {
+            if c == ']' {
+               closed = true;
+               break;
+            }
+            digits.push(c);
+         }
+         let (true, Ok(index)) = (closed, digits.parse()) else {
+            return Err(invalid_key(
+               key,
+               format!("malformed array index '[{}'", digits),
+            ));
+         };
+         segments.push(Segment::Index(index));
+      }
+
+      match chars.next() {
+         None => break,
+         Some('.') if chars.peek().is_none() => {
+            return Err(invalid_key(key, "key path contains an empty segment"));
+         }
+         Some('.') => {}
+         Some(c) => {
+            return Err(invalid_key(
+               key,
+               format!("unexpected '{}' after a segment", c),
+            ));
+         }
+      }
+   }
+   Ok(segments)
+}
+
+/// Reject key paths that can't address a setting, such as `""`, `model..name` or `models[x]`
+pub(super) fn validate_key_path(key: &str) -> Result<(), AgentSettingsError> {
+   parse_key_path(key).map(|_| ())
+}
+
+/// Names of the object keys a key path walks through, leaving out array indices
+pub(super) fn key_names(key: &str) -> Result<Vec<String>, AgentSettingsError> {
+   Ok(parse_key_path(key)?
+      .into_iter()
+      .filter_map(|segment| match segment {
+         Segment::Key(name) => Some(name),
+         Segment::Index(_) | Segment::End => None,
+      })
+      .collect())
+}
+
+/// Build a dotted key path from object key names, quoting names that contain dots, brackets,
+/// quotes or backslashes so the path parses back to the same names
+pub(super) fn format_key_path(names: &[String]) -> String {
+   let mut path = String::new();
+   for name in names {
+      push_key_name(&mut path, name);
+   }
+   path
+}
+
+/// Append an object key name to a dotted key path, quoting it when needed
+pub(super) fn push_key_name(path: &mut String, name: &str) {
+   let plain = !name.is_empty()
+      && !name.contains(['.', '[', ']', '"', '\\'])
+      && (!path.is_empty() || !name.starts_with('/'));
+   if !path.is_empty() {
+      path.push('.');
+   }
+   if plain {
+      path.push_str(name);
+   } else {
+      path.push('"');
+      path.push_str(&name.replace('\\', "\\\\").replace('"', "\\\""));
+      path.push('"');
+   }
+}
+
+/// Rewrite a key path in dot notation with bracketed array indices, so JSON Pointers and dotted
+/// paths addressing the same value come out the same
+pub(super) fn to_dotted_path(key: &str) -> Result<String, AgentSettingsError> {
+   let mut path = String::new();
+   for segment in parse_key_path(key)? {
+      match segment {
+         Segment::Key(name) => push_key_name(&mut path, &name),
+         Segment::Index(index) => path.push_str(&format!("[{}]", index)),
+         Segment::End => return Err(invalid_key(key, "'-' does not address an existing value")),
+      }
+   }
+   Ok(path)
+}
+
+/// Look up a key path. Missing keys and out-of-range array indices read as `None`.
+pub(super) fn get_nested_value<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+   parse_key_path(key)
+      .ok()?
+      .iter()
+      .try_fold(value, |current, segment| match (current, segment) {
+         (Value::Array(items), _) => items.get(segment.array_index(items.len())?),
+         (Value::Object(map), Segment::Key(key)) => map.get(key),
+         _ => None,
+      })
+}
+
+/// Set a key path, creating missing objects and arrays along the way. An array index may point
+/// one past the last element to append; anything further out is an error instead of padding.
+pub(super) fn set_nested_value(
+   value: &mut Value,
+   key: &str,
+   new_value: Value,
+) -> Result<(), AgentSettingsError> {
+   let segments = parse_key_path(key)?;
+
+   let (last, parents) = segments
+      .split_last()
+      .expect("parsed key paths have at least one segment");
+
+   let mut current = value;
+   for (index, segment) in parents.iter().enumerate() {
+      let next = &segments[index + 1];
+      current = child_slot(current, segment, key)?.or_insert_with(|| next.empty_container());
+   }
+   child_slot(current, last, key)?.set(new_value);
+   Ok(())
+}
+
+/// Remove the value at a key path, returning whether anything was removed. Array elements are
+/// removed rather than nulled. With `prune_empty_parents`, objects left empty by the removal are
+/// removed as well, up to (but not including) the document root.
+pub(super) fn delete_nested_value(
+   value: &mut Value,
+   key: &str,
+   prune_empty_parents: bool,
+) -> Result<bool, AgentSettingsError> {
+   let segments = parse_key_path(key)?;
+   Ok(remove_path(value, &segments, prune_empty_parents))
+}
+
+fn remove_path(current: &mut Value, segments: &[Segment], prune_empty_parents: bool) -> bool {
+   let Some((segment, rest)) = segments.split_first() else {
+      return false;
+   };
+   if rest.is_empty() {
+      return remove_child(current, segment);
+   }
+
+   let Some(child) = child_mut(current, segment) else {
+      return false;
+   };
+   let removed = remove_path(child, rest, prune_empty_parents);
+   if removed && prune_empty_parents && child.as_object().is_some_and(Map::is_empty) {
+      remove_child(current, segment);
+   }
+   removed
+}
+
+fn child_mut<'a>(current: &'a mut Value, segment: &Segment) -> Option<&'a mut Value> {
+   match (current, segment) {
+      (Value::Array(items), _) => {
+         let index = segment.array_index(items.len())?;
+         items.get_mut(index)
+      }
+      (Value::Object(map), Segment::Key(key)) => map.get_mut(key),
+      _ => None,
+   }
+}
+
+fn remove_child(current: &mut Value, segment: &Segment) -> bool {
+   match (current, segment) {
+      (Value::Array(items), _) => match segment.array_index(items.len()) {
+         Some(index) if index < items.len() => {
+            items.remove(index);
+            true
+         }
+         _ => false,
+      },
+      (Value::Object(map), Segment::Key(key)) => map.shift_remove(key).is_some(),
+      _ => false,
+   }
+}
+
+/// Where a segment points inside its parent: an existing element, the end of an array, or an
+/// object entry
+enum Slot<'a> {
+   Element(&'a mut Value),
+   Append(&'a mut Vec<Value>),
+   Entry(serde_json::map::Entry<'a>),
+}
+
+impl<'a> Slot<'a> {
+   fn or_insert_with(self, default: impl FnOnce() -> Value) -> &'a mut Value {
+      match self {
+         Slot::Element(value) => value,
+         Slot::Append(items) => {
+            items.push(default());
+            items.last_mut().expect("an element was just pushed")
+         }
+         Slot::Entry(entry) => entry.or_insert_with(default),
+      }
+   }
+
+   fn set(self, value: Value) {
+      match self {
+         Slot::Element(slot) => *slot = value,
+         Slot::Append(items) => items.push(value),
+         Slot::Entry(entry) => *entry.or_insert(Value::Null) = value,
+      }
+   }
+}
+
+fn child_slot<'a>(
+   current: &'a mut Value,
+   segment: &Segment,
+   key: &str,
+) -> Result<Slot<'a>, AgentSettingsError> {
+   if let Value::Array(items) = current {
+      let Some(index) = segment.array_index(items.len()) else {
+         return Err(invalid_key(key, "expected an array index"));
+      };
+      if index > items.len() {
+         return Err(invalid_key(
+            key,
+            format!(
+               "index {} is out of range for an array of length {}",
+               index,
+               items.len()
+            ),
+         ));
+      }
+      return Ok(if index == items.len() {
+         Slot::Append(items)
+      } else {
+         Slot::Element(&mut items[index])
+      });
+   }
+
+   match segment {
+      Segment::Key(name) => Ok(Slot::Entry(ensure_object(current).entry(name.clone()))),
+      Segment::Index(_) | Segment::End if current.is_null() => {
+         *current = Value::Array(Vec::new());
+         child_slot(current, segment, key)
+      }
+      Segment::Index(_) | Segment::End => Err(invalid_key(
+         key,
+         "cannot index into a value that is not an array",
+      )),
+   }
+}
+
+fn ensure_object(value: &mut Value) -> &mut Map<String, Value> {
+   if !value.is_object() {
+      *value = Value::Object(Map::new());
+   }
+   match value {
+      Value::Object(map) => map,
+      _ => unreachable!("value was just replaced with an object"),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_settings::format::{
+      ConfigFormat, parse_config, serialize_config,
+   };
+   use serde_json::json;
+
+   #[test]
+   fn test_validate_key_path() {
+      for key in [
+         "model",
+         "agent.reasoning",
+         "models.0.model",
+         "models[0].model",
+         "[1]",
+      ] {
+         assert!(
+            validate_key_path(key).is_ok(),
+            "{:?} should be accepted",
+            key
+         );
+      }
+
+      for key in [
+         "",
+         ".model",
+         "model.",
+         "agent..reasoning",
+         "models[x]",
+         "models[0",
+      ] {
+         assert!(
+            matches!(
+               validate_key_path(key),
+               Err(AgentSettingsError::InvalidKeyPath { .. })
+            ),
+            "{:?} should be rejected",
+            key
+         );
+      }
+   }
+
+   #[test]
+   fn test_parse_quoted_and_escaped_segments() {
+      let key = |name: &str| Segment::Key(name.to_string());
+      assert_eq!(
+         parse_key_path(r#"mcpServers."my.server".command"#).unwrap(),
+         vec![key("mcpServers"), key("my.server"), key("command")]
+      );
+      assert_eq!(
+         parse_key_path(r"mcpServers.my\.server.command").unwrap(),
+         vec![key("mcpServers"), key("my.server"), key("command")]
+      );
+      assert_eq!(
+         parse_key_path(r#""say \"hi\"".models[0]"#).unwrap(),
+         vec![key("say \"hi\""), key("models"), Segment::Index(0)]
+      );
+      assert_eq!(parse_key_path(r#""""#).unwrap(), vec![key("")]);
+
+      for bad in [r#""open"#, r"trailing\", r#""a"b"#, "models[0]x"] {
+         assert!(parse_key_path(bad).is_err(), "{:?} should be rejected", bad);
+      }
+   }
+
+   #[test]
+   fn test_format_key_path_round_trips() {
+      for names in [
+         vec!["model"],
+         vec!["mcpServers", "my.server", "command"],
+         vec!["/abs", "say \"hi\"", r"back\slash", "", "[0]"],
+      ] {
+         let names: Vec<String> = names.into_iter().map(String::from).collect();
+         let key = format_key_path(&names);
+         assert_eq!(key_names(&key).unwrap(), names, "{}", key);
+      }
+      assert_eq!(format_key_path(&["a".into(), "b.c".into()]), r#"a."b.c""#);
+   }
+
+   #[test]
+   fn test_dotted_key_is_distinct_from_nested_path() {
+      let content = r#"{ "a.b": "literal", "a": { "b": "nested" } }"#;
+      let mut value = parse_config(content, ConfigFormat::Json).unwrap();
+
+      assert_eq!(get_nested_value(&value, "a.b"), Some(&json!("nested")));
+      assert_eq!(
+         get_nested_value(&value, r#""a.b""#),
+         Some(&json!("literal"))
+      );
+      assert_eq!(get_nested_value(&value, r"a\.b"), Some(&json!("literal")));
+
+      set_nested_value(&mut value, r#""a.b""#, json!("literal 2")).unwrap();
+      set_nested_value(&mut value, "a.b", json!("nested 2")).unwrap();
+      set_nested_value(&mut value, r#"providers."gpt-4.1".enabled"#, json!(true)).unwrap();
+
+      assert_eq!(
+         value,
+         json!({
+            "a.b": "literal 2",
+            "a": { "b": "nested 2" },
+            "providers": { "gpt-4.1": { "enabled": true } },
+         })
+      );
+   }
+
+   #[test]
+   fn test_json_pointer_matches_dot_notation() {
+      let value = json!({
+         "model": { "name": "o3" },
+         "mcpServers": { "my.server": { "command": "npx" } },
+         "models": [{ "model": "gpt-4o" }, { "model": "o4-mini" }],
+         "a/b": { "c~d": 1 },
+      });
+
+      let pairs = [
+         ("/model/name", "model.name"),
+         (
+            "/mcpServers/my.server/command",
+            r#"mcpServers."my.server".command"#,
+         ),
+         ("/models/1/model", "models[1].model"),
+         ("/a~1b/c~0d", r#""a/b"."c~d""#),
+      ];
+      for (pointer, dotted) in pairs {
+         assert!(
+            get_nested_value(&value, pointer).is_some(),
+            "{} should resolve",
+            pointer
+         );
+         assert_eq!(
+            get_nested_value(&value, pointer),
+            get_nested_value(&value, dotted),
+            "{} and {} should agree",
+            pointer,
+            dotted
+         );
+      }
+      assert_eq!(get_nested_value(&value, "/models/2/model"), None);
+      assert_eq!(get_nested_value(&value, "/models/-"), None);
+   }
+
+   #[test]
+   fn test_set_with_json_pointer() {
+      let mut by_pointer = json!({ "models": [{ "model": "gpt-4o" }] });
+      let mut by_dots = by_pointer.clone();
+
+      set_nested_value(
+         &mut by_pointer,
+         "/mcpServers/my.server/command",
+         json!("npx"),
+      )
+      .unwrap();
+      set_nested_value(&mut by_pointer, "/models/0/model", json!("o3")).unwrap();
+      set_nested_value(&mut by_pointer, "/models/-", json!({ "model": "o4-mini" })).unwrap();
+
+      set_nested_value(&mut by_dots, r"mcpServers.my\.server.command", json!("npx")).unwrap();
+      set_nested_value(&mut by_dots, "models.0.model", json!("o3")).unwrap();
+      set_nested_value(&mut by_dots, "models[1]", json!({ "model": "o4-mini" })).unwrap();
+
+      assert_eq!(by_pointer, by_dots);
+      assert!(set_nested_value(&mut by_pointer, "/models/5/model", json!("x")).is_err());
+      assert!(validate_key_path("/bad~2escape").is_err());
+   }
+
+   #[test]
+   fn test_get_indexes_into_arrays() {
+      let value = json!({
+         "models": [{ "title": "Fast", "model": "gpt-4o-mini" }, { "model": "o3" }],
+         "byIndex": { "0": "object key" },
+      });
+
+      assert_eq!(
+         get_nested_value(&value, "models.0.model"),
+         Some(&json!("gpt-4o-mini"))
+      );
+      assert_eq!(
+         get_nested_value(&value, "models[1].model"),
+         Some(&json!("o3"))
+      );
+      assert_eq!(
+         get_nested_value(&value, "byIndex.0"),
+         Some(&json!("object key"))
+      );
+      assert_eq!(get_nested_value(&value, "models.2.model"), None);
+      assert_eq!(get_nested_value(&value, "models.first"), None);
+      assert_eq!(get_nested_value(&value, "byIndex[0]"), None);
+   }
+
+   #[test]
+   fn test_set_extends_arrays_by_one_only() {
+      let mut value = json!({ "models": [{ "model": "o3" }] });
+
+      set_nested_value(&mut value, "models.0.model", json!("o4-mini")).unwrap();
+      set_nested_value(&mut value, "models[1].model", json!("gpt-5")).unwrap();
+      set_nested_value(&mut value, "tools[0]", json!("bash")).unwrap();
+      assert_eq!(
+         value,
+         json!({
+            "models": [{ "model": "o4-mini" }, { "model": "gpt-5" }],
+            "tools": ["bash"],
+         })
+      );
+
+      let before = value.clone();
+      assert!(matches!(
+         set_nested_value(&mut value, "models.5.model", json!("x")),
+         Err(AgentSettingsError::InvalidKeyPath { .. })
+      ));
+      assert!(set_nested_value(&mut value, "models.name", json!("x")).is_err());
+      assert_eq!(value, before);
+   }
+
+   #[test]
+   fn test_delete_leaves_and_prunes_parents() {
+      let original = json!({
+         "model": "o3",
+         "tools": { "web": { "enabled": true } },
+         "models": [{ "model": "a" }, { "model": "b" }],
+      });
+
+      let mut value = original.clone();
+      assert!(delete_nested_value(&mut value, "tools.web.enabled", false).unwrap());
+      assert_eq!(value["tools"], json!({ "web": {} }));
+
+      let mut value = original.clone();
+      assert!(delete_nested_value(&mut value, "tools.web.enabled", true).unwrap());
+      assert!(value.get("tools").is_none());
+      assert_eq!(value["model"], json!("o3"));
+
+      let mut value = original.clone();
+      assert!(delete_nested_value(&mut value, "models.0", false).unwrap());
+      assert_eq!(value["models"], json!([{ "model": "b" }]));
+
+      let mut value = original.clone();
+      for missing in ["nope", "tools.web.missing", "models.5", "model.inner"] {
+         assert!(!delete_nested_value(&mut value, missing, true).unwrap());
+      }
+      assert_eq!(value, original);
+   }
+
+   #[test]
+   fn test_delete_from_toml_file() {
+      let content =
+         "model = \"o3\"\nmodel_reasoning_effort = \"high\" # override\n\n[tools]\nweb = true\n";
+      let mut value = parse_config(content, ConfigFormat::Toml).unwrap();
+
+      delete_nested_value(&mut value, "model_reasoning_effort", false).unwrap();
+      delete_nested_value(&mut value, "tools.web", true).unwrap();
+
+      let written = serialize_config(value, ConfigFormat::Toml, Some(content)).unwrap();
+      assert_eq!(written, "model = \"o3\"\n");
+   }
+
+   #[test]
+   fn test_array_paths_in_json_file() {
+      let content = r#"{ "models": [{ "title": "Main", "model": "gpt-4o" }] }"#;
+      let mut value = parse_config(content, ConfigFormat::Json).unwrap();
+
+      set_nested_value(&mut value, "models.0.model", json!("o3")).unwrap();
+      set_nested_value(&mut value, "models.1.title", json!("Backup")).unwrap();
+
+      let written = serialize_config(value, ConfigFormat::Json, Some(content)).unwrap();
+      let reparsed = parse_config(&written, ConfigFormat::Json).unwrap();
+      assert_eq!(
+         get_nested_value(&reparsed, "models.0.title"),
+         Some(&json!("Main"))
+      );
+      assert_eq!(
+         get_nested_value(&reparsed, "models.0.model"),
+         Some(&json!("o3"))
+      );
+      assert_eq!(
+         get_nested_value(&reparsed, "models[1].title"),
+         Some(&json!("Backup"))
+      );
+   }
+
+   #[test]
+   fn test_array_paths_in_toml_file() {
+      let content = "# Providers\n[[models]]\ntitle = \"Main\" # primary\nmodel = \"gpt-4o\"\n";
+      let mut value = parse_config(content, ConfigFormat::Toml).unwrap();
+
+      set_nested_value(&mut value, "models.0.model", json!("o3")).unwrap();
+      set_nested_value(&mut value, "models.1.title", json!("Backup")).unwrap();
+
+      let written = serialize_config(value, ConfigFormat::Toml, Some(content)).unwrap();
+      assert!(written.contains("title = \"Main\" # primary"));
+      assert_eq!(written.matches("[[models]]").count(), 2);
+
+      let reparsed = parse_config(&written, ConfigFormat::Toml).unwrap();
+      assert_eq!(
+         get_nested_value(&reparsed, "models.0.model"),
+         Some(&json!("o3"))
+      );
+      assert_eq!(
+         get_nested_value(&reparsed, "models[1].title"),
+         Some(&json!("Backup"))
+      );
+   }
+}
@@ -0,0 +1,630 @@
+use super::{
+   custom_agents::find_agent,
+   defaults::{AgentSettingsOrigins, applicable_defaults, load_agent_defaults, with_defaults},
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   keys::{format_key_path, get_nested_value},
+   mcp::known_agent,
+   paths::resolve_settings_path,
+   settings::{AgentSettings, SettingsKeys},
+   storage::{AgentSettingsLocks, read_config_file},
+};
+use crate::commands::ai::agent_registry::KnownAgent;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, path::Path};
+use tauri::{State, command};
+
+/// Which layer of an agent's configuration a write goes to. `project` is the file checked into
+/// a repository (`.claude/settings.json`) and `local` the per-user file next to it
+/// (`.claude/settings.local.json`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SettingsScope {
+   #[default]
+   Global,
+   Project,
+   Local,
+}
+
+impl SettingsScope {
+   /// Pick the settings path a write in this scope goes to
+   pub(super) fn select<'a>(
+      self,
+      global_path: &'a str,
+      project_path: Option<&'a str>,
+      local_path: Option<&'a str>,
+   ) -> Result<&'a str, AgentSettingsError> {
+      let (path, name) = match self {
+         SettingsScope::Global => return Ok(global_path),
+         SettingsScope::Project => (project_path, "project"),
+         SettingsScope::Local => (local_path, "local"),
+      };
+      path.ok_or_else(|| AgentSettingsError::InvalidPath {
+         path: String::new(),
+         message: format!("no {} settings path was given", name),
+      })
+   }
+}
+
+/// Settings path of the layer each effective setting was read from
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsSources {
+   pub model: Option<String>,
+   pub preview_features: Option<String>,
+   pub reasoning_effort: Option<String>,
+   pub temperature: Option<String>,
+   pub max_tokens: Option<String>,
+   pub allowed_tools: Option<String>,
+}
+
+/// Settings merged across the global config and any project layers
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectiveAgentSettings {
+   pub settings: AgentSettings,
+   pub sources: AgentSettingsSources,
+   /// Whether each setting comes from the agent's files or the defaults every agent inherits
+   pub origins: AgentSettingsOrigins,
+}
+
+/// One of the files an agent's settings are layered from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsLayer {
+   pub layer: SettingsScope,
+   pub settings_path: String,
+   pub exists: bool,
+}
+
+/// An agent's settings as it resolves them from its global, project and local files
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayeredAgentSettings {
+   /// Lowest precedence first
+   pub layers: Vec<SettingsLayer>,
+   /// Every layer's content merged
+   pub effective: Value,
+   /// Layer each value of `effective` comes from, by key path. Objects are broken down into
+   /// their values; arrays are reported whole, as a layer replaces them whole.
+   pub provenance: BTreeMap<String, SettingsScope>,
+   pub settings: AgentSettings,
+   pub sources: AgentSettingsSources,
+}
+
+/// Merge `overlay` into `base`. Objects are merged key by key and anything else in `overlay`
+/// replaces what `base` had.
+pub(super) fn deep_merge(base: &mut Value, overlay: Value) {
+   match (base, overlay) {
+      (Value::Object(base), Value::Object(overlay)) => {
+         for (key, value) in overlay {
+            match base.get_mut(&key) {
+               Some(existing) => deep_merge(existing, value),
+               None => {
+                  base.insert(key, value);
+               }
+            }
+         }
+      }
+      (base, overlay) => *base = overlay,
+   }
+}
+
+/// Layers' content merged, lowest precedence first
+fn merge_layers<'a>(layers: impl IntoIterator<Item = &'a Value>) -> Value {
+   let mut merged = Value::Object(Default::default());
+   for value in layers {
+      deep_merge(&mut merged, value.clone());
+   }
+   merged
+}
+
+/// Merge `layers` (settings path and parsed content, lowest precedence first) and read the
+/// settings from the result, noting for each setting the last layer that set it
+fn effective_settings(keys: &SettingsKeys, layers: &[(String, Value)]) -> EffectiveAgentSettings {
+   let source = |key: Option<&String>| {
+      let key = key?;
+      layers
+         .iter()
+         .rev()
+         .find(|(_, value)| get_nested_value(value, key).is_some())
+         .map(|(settings_path, _)| settings_path.clone())
+   };
+   let sources = AgentSettingsSources {
+      model: source(Some(&keys.model)),
+      preview_features: source(keys.preview.as_ref()),
+      reasoning_effort: source(keys.reasoning.as_ref()),
+      temperature: source(keys.temperature.as_ref()),
+      max_tokens: source(keys.max_tokens.as_ref()),
+      allowed_tools: source(keys.tools.as_ref()),
+   };
+
+   let merged = merge_layers(layers.iter().map(|(_, value)| value));
+   let settings = keys.read(&merged);
+
+   // Only report a source for settings that were actually read, not ones with an unusable value
+   let sources = AgentSettingsSources {
+      model: sources.model.filter(|_| settings.model.is_some()),
+      preview_features: sources
+         .preview_features
+         .filter(|_| settings.preview_features.is_some()),
+      reasoning_effort: sources
+         .reasoning_effort
+         .filter(|_| settings.reasoning_effort.is_some()),
+      temperature: sources
+         .temperature
+         .filter(|_| settings.temperature.is_some()),
+      max_tokens: sources.max_tokens.filter(|_| settings.max_tokens.is_some()),
+      allowed_tools: sources
+         .allowed_tools
+         .filter(|_| settings.allowed_tools.is_some()),
+   };
+
+   let origins = with_defaults(settings.clone(), AgentSettings::default()).origins;
+   EffectiveAgentSettings {
+      settings,
+      sources,
+      origins,
+   }
+}
+
+async fn load_layers(
+   locks: &AgentSettingsLocks,
+   settings_paths: impl IntoIterator<Item = String>,
+) -> Result<Vec<(String, Value)>, AgentSettingsError> {
+   let mut layers = Vec::new();
+   for settings_path in settings_paths {
+      let path = resolve_settings_path(&settings_path)?;
+      let format = ConfigFormat::from_path(&settings_path);
+      if let Some(file) = read_config_file(locks, &path, format).await? {
+         layers.push((settings_path, file.value));
+      }
+   }
+   Ok(layers)
+}
+
+/// Read an agent's settings from its global config file overlaid with project layers such as
+/// `.claude/settings.json` and `.claude/settings.local.json`. Later `project_paths` take
+/// precedence, and layers that don't exist are skipped. Settings none of the files set are
+/// inherited from `[agent_defaults]` in `~/.athas/settings.toml`, leaving out those the agent
+/// has no key for or doesn't support.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_effective_agent_settings(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   global_path: String,
+   project_paths: Vec<String>,
+   model_key: String,
+   preview_key: Option<String>,
+   reasoning_key: Option<String>,
+   temperature_key: Option<String>,
+   max_tokens_key: Option<String>,
+   tools_key: Option<String>,
+) -> Result<EffectiveAgentSettings, AgentSettingsError> {
+   let keys = SettingsKeys {
+      model: model_key,
+      preview: preview_key,
+      reasoning: reasoning_key,
+      temperature: temperature_key,
+      max_tokens: max_tokens_key,
+      tools: tools_key,
+   };
+   keys.validate()?;
+
+   let layers = load_layers(&locks, std::iter::once(global_path).chain(project_paths)).await?;
+   let agent = find_agent(&locks, &agent_id).await?;
+   let defaults = applicable_defaults(
+      load_agent_defaults(&locks).await?,
+      &keys,
+      agent.as_ref().map(|agent| &agent.capabilities),
+   );
+   log::debug!(
+      "Loaded {} settings layers for agent {}",
+      layers.len(),
+      agent_id
+   );
+   let effective = effective_settings(&keys, &layers);
+   let inherited = with_defaults(effective.settings, defaults);
+   Ok(EffectiveAgentSettings {
+      settings: inherited.settings,
+      sources: effective.sources,
+      origins: inherited.origins,
+   })
+}
+
+/// Paths of `agent`'s project and local settings files in `workspace_path`, for agents that
+/// read them
+pub(super) fn project_layer_paths(
+   agent: &KnownAgent,
+   workspace_path: &str,
+) -> (Option<String>, Option<String>) {
+   let Some(project_settings) = &agent.project_settings else {
+      return (None, None);
+   };
+   let in_workspace = |path: &str| Path::new(workspace_path).join(path).display().to_string();
+   (
+      Some(in_workspace(&project_settings.project_path)),
+      project_settings.local_path.as_deref().map(in_workspace),
+   )
+}
+
+/// The files `agent` layers its settings from, lowest precedence first. Without a workspace only
+/// the global one applies.
+fn agent_layers(agent: &KnownAgent, workspace_path: Option<&str>) -> Vec<(SettingsScope, String)> {
+   let mut layers = vec![(SettingsScope::Global, agent.settings_path.clone())];
+   if let Some(workspace_path) = workspace_path {
+      let (project_path, local_path) = project_layer_paths(agent, workspace_path);
+      layers.extend(project_path.map(|path| (SettingsScope::Project, path)));
+      layers.extend(local_path.map(|path| (SettingsScope::Local, path)));
+   }
+   layers
+}
+
+fn value_at<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+   path
+      .iter()
+      .try_fold(value, |value, key| value.as_object()?.get(key))
+}
+
+/// Record in `provenance` the layer each value under `path` of `merged` comes from: the last one
+/// that has it
+fn record_provenance(
+   merged: &Value,
+   layers: &[(SettingsScope, Value)],
+   path: &mut Vec<String>,
+   provenance: &mut BTreeMap<String, SettingsScope>,
+) {
+   if let Value::Object(map) = merged
+      && !map.is_empty()
+   {
+      for (key, value) in map {
+         path.push(key.clone());
+         record_provenance(value, layers, path, provenance);
+         path.pop();
+      }
+      return;
+   }
+   if path.is_empty() {
+      return;
+   }
+   if let Some((layer, _)) = layers
+      .iter()
+      .rev()
+      .find(|(_, value)| value_at(value, path).is_some())
+   {
+      provenance.insert(format_key_path(path), *layer);
+   }
+}
+
+/// Resolve `agent`'s settings from the files it reads, the way Claude Code documents it: later
+/// layers win, objects are merged key by key, and arrays and other values are replaced
+async fn load_layered(
+   locks: &AgentSettingsLocks,
+   agent: &KnownAgent,
+   workspace_path: Option<&str>,
+) -> Result<LayeredAgentSettings, AgentSettingsError> {
+   let mut layers = Vec::new();
+   let mut loaded = Vec::new();
+   for (layer, settings_path) in agent_layers(agent, workspace_path) {
+      let path = resolve_settings_path(&settings_path)?;
+      let file = read_config_file(locks, &path, ConfigFormat::from_path(&settings_path)).await?;
+      layers.push(SettingsLayer {
+         layer,
+         settings_path: settings_path.clone(),
+         exists: file.is_some(),
+      });
+      if let Some(file) = file {
+         loaded.push((layer, settings_path, file.value));
+      }
+   }
+
+   let by_path: Vec<(String, Value)> = loaded
+      .iter()
+      .map(|(_, settings_path, value)| (settings_path.clone(), value.clone()))
+      .collect();
+   let EffectiveAgentSettings {
+      settings, sources, ..
+   } = effective_settings(&SettingsKeys::for_agent(agent), &by_path);
+   let by_layer: Vec<(SettingsScope, Value)> = loaded
+      .into_iter()
+      .map(|(layer, _, value)| (layer, value))
+      .collect();
+   let effective = merge_layers(by_layer.iter().map(|(_, value)| value));
+   let mut provenance = BTreeMap::new();
+   record_provenance(&effective, &by_layer, &mut Vec::new(), &mut provenance);
+
+   Ok(LayeredAgentSettings {
+      layers,
+      effective,
+      provenance,
+      settings,
+      sources,
+   })
+}
+
+/// Read an agent's settings the way the agent resolves them, from the layers the registry lists
+/// for it: its global config, then the workspace's project and local files. Reports the merged
+/// config, the settings Athas manages and the layer each value comes from.
+#[command]
+pub async fn get_layered_agent_settings(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   workspace_path: Option<String>,
+) -> Result<LayeredAgentSettings, AgentSettingsError> {
+   let agent = known_agent(&locks, &agent_id).await?;
+   let layered = load_layered(&locks, &agent, workspace_path.as_deref()).await?;
+   log::debug!(
+      "Resolved {} settings layers for agent {}",
+      layered.layers.iter().filter(|layer| layer.exists).count(),
+      agent_id
+   );
+   Ok(layered)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   fn keys() -> SettingsKeys {
+      SettingsKeys {
+         model: "model".into(),
+         reasoning: Some("options.reasoning".into()),
+         tools: Some("permissions.allow".into()),
+         temperature: Some("temperature".into()),
+         ..SettingsKeys::default()
+      }
+   }
+
+   #[test]
+   fn test_later_layers_win() {
+      let layers = vec![
+         (
+            "global.json".to_string(),
+            json!({ "model": "sonnet", "options": { "reasoning": "low", "verbose": true } }),
+         ),
+         (
+            "project.json".to_string(),
+            json!({ "model": "opus", "permissions": { "allow": ["Read"] } }),
+         ),
+         (
+            "local.json".to_string(),
+            json!({ "options": { "reasoning": "high" }, "permissions": { "allow": ["Bash"] } }),
+         ),
+      ];
+
+      let effective = effective_settings(&keys(), &layers);
+
+      assert_eq!(effective.settings.model.as_deref(), Some("opus"));
+      assert_eq!(effective.settings.reasoning_effort.as_deref(), Some("high"));
+      assert_eq!(
+         effective.settings.allowed_tools,
+         Some(vec!["Bash".to_string()])
+      );
+      assert_eq!(
+         effective.sources,
+         AgentSettingsSources {
+            model: Some("project.json".into()),
+            reasoning_effort: Some("local.json".into()),
+            allowed_tools: Some("local.json".into()),
+            ..AgentSettingsSources::default()
+         }
+      );
+   }
+
+   #[test]
+   fn test_deep_merge_keeps_sibling_keys() {
+      let mut base = json!({ "options": { "reasoning": "low", "verbose": true }, "model": "a" });
+      deep_merge(
+         &mut base,
+         json!({ "options": { "reasoning": "high" }, "model": { "name": "b" } }),
+      );
+      assert_eq!(
+         base,
+         json!({ "options": { "reasoning": "high", "verbose": true }, "model": { "name": "b" } })
+      );
+   }
+
+   #[test]
+   fn test_unusable_value_has_no_source() {
+      let layers = vec![
+         ("global.json".to_string(), json!({ "temperature": 0.2 })),
+         ("project.json".to_string(), json!({ "temperature": "warm" })),
+      ];
+
+      let effective = effective_settings(&keys(), &layers);
+
+      assert_eq!(effective.settings.temperature, None);
+      assert_eq!(effective.sources.temperature, None);
+   }
+
+   #[tokio::test]
+   async fn test_missing_layers_are_skipped() {
+      let dir = tempfile::tempdir().unwrap();
+      let global = dir.path().join("config.json");
+      std::fs::write(&global, r#"{ "model": "sonnet" }"#).unwrap();
+      let project = dir.path().join("project/.claude/settings.json");
+
+      let layers = load_layers(
+         &AgentSettingsLocks::new(),
+         [global.display().to_string(), project.display().to_string()],
+      )
+      .await
+      .unwrap();
+
+      assert_eq!(layers.len(), 1);
+      assert_eq!(layers[0].0, global.display().to_string());
+   }
+
+   #[tokio::test]
+   async fn test_layered_settings_follow_claude_semantics() {
+      let dir = tempfile::tempdir().unwrap();
+      let global = dir.path().join("home/.claude/settings.json");
+      let workspace = dir.path().join("repo");
+      let fixtures = [
+         (
+            global.clone(),
+            json!({
+               "model": "sonnet",
+               "permissions": { "allow": ["Read", "Grep"], "deny": ["WebFetch"] },
+               "env": { "DEBUG": "0", "LOG_LEVEL": "info" },
+            }),
+         ),
+         (
+            workspace.join(".claude/settings.json"),
+            json!({
+               "model": "opus",
+               "permissions": { "allow": ["Bash(bun test:*)"] },
+               "env": { "DEBUG": "1" },
+            }),
+         ),
+         (
+            workspace.join(".claude/settings.local.json"),
+            json!({ "model": "haiku", "env": { "LOG_LEVEL": "trace" } }),
+         ),
+      ];
+      for (path, content) in &fixtures {
+         std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+         std::fs::write(path, content.to_string()).unwrap();
+      }
+      let agent = KnownAgent {
+         settings_path: global.display().to_string(),
+         ..crate::commands::ai::agent_registry::find_known_agent("claude-code").unwrap()
+      };
+
+      let layered = load_layered(
+         &AgentSettingsLocks::new(),
+         &agent,
+         Some(&workspace.display().to_string()),
+      )
+      .await
+      .unwrap();
+
+      assert_eq!(
+         layered
+            .layers
+            .iter()
+            .map(|layer| (layer.layer, layer.exists))
+            .collect::<Vec<_>>(),
+         vec![
+            (SettingsScope::Global, true),
+            (SettingsScope::Project, true),
+            (SettingsScope::Local, true),
+         ]
+      );
+      assert_eq!(
+         layered.effective,
+         json!({
+            "model": "haiku",
+            "permissions": { "allow": ["Bash(bun test:*)"], "deny": ["WebFetch"] },
+            "env": { "DEBUG": "1", "LOG_LEVEL": "trace" },
+         })
+      );
+      assert_eq!(
+         layered.provenance,
+         BTreeMap::from([
+            ("env.DEBUG".to_string(), SettingsScope::Project),
+            ("env.LOG_LEVEL".to_string(), SettingsScope::Local),
+            ("model".to_string(), SettingsScope::Local),
+            ("permissions.allow".to_string(), SettingsScope::Project),
+            ("permissions.deny".to_string(), SettingsScope::Global),
+         ])
+      );
+      assert_eq!(layered.settings.model.as_deref(), Some("haiku"));
+      assert_eq!(
+         layered.sources.model,
+         Some(fixtures[2].0.display().to_string())
+      );
+
+      // Without a workspace only the global file is read
+      let global_only = load_layered(&AgentSettingsLocks::new(), &agent, None)
+         .await
+         .unwrap();
+      assert_eq!(global_only.layers.len(), 1);
+      assert_eq!(global_only.effective, fixtures[0].1);
+   }
+
+   #[tokio::test]
+   async fn test_layered_gemini_settings() {
+      let dir = tempfile::tempdir().unwrap();
+      let global = dir.path().join(".gemini/settings.json");
+      let workspace = dir.path().join("repo");
+      let project = workspace.join(".gemini/settings.json");
+      // Shaped like the settings Gemini CLI writes itself
+      let fixtures = [
+         (
+            &global,
+            json!({
+               "general": { "previewFeatures": false, "vimMode": true },
+               "model": { "name": "gemini-2.5-pro" },
+               "security": { "auth": { "selectedType": "oauth-personal" } },
+               "ui": { "theme": "GitHub" },
+            }),
+         ),
+         (
+            &project,
+            json!({
+               "general": { "previewFeatures": true },
+               "model": { "name": "gemini-2.5-flash" },
+               "context": { "fileName": ["GEMINI.md", "AGENTS.md"] },
+            }),
+         ),
+      ];
+      for (path, content) in &fixtures {
+         std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+         std::fs::write(path, content.to_string()).unwrap();
+      }
+      let agent = KnownAgent {
+         settings_path: global.display().to_string(),
+         ..crate::commands::ai::agent_registry::find_known_agent("gemini-cli").unwrap()
+      };
+
+      let layered = load_layered(
+         &AgentSettingsLocks::new(),
+         &agent,
+         Some(&workspace.display().to_string()),
+      )
+      .await
+      .unwrap();
+
+      assert_eq!(layered.layers.len(), 2);
+      assert_eq!(layered.settings.model.as_deref(), Some("gemini-2.5-flash"));
+      assert_eq!(layered.settings.preview_features, Some(true));
+      assert_eq!(layered.sources.model, Some(project.display().to_string()));
+      assert_eq!(
+         layered.effective["security"]["auth"]["selectedType"],
+         json!("oauth-personal")
+      );
+      assert_eq!(layered.effective["general"]["vimMode"], json!(true));
+      assert_eq!(
+         layered.provenance.get("general.previewFeatures"),
+         Some(&SettingsScope::Project)
+      );
+      assert_eq!(
+         layered.provenance.get("security.auth.selectedType"),
+         Some(&SettingsScope::Global)
+      );
+   }
+
+   #[test]
+   fn test_scope_selects_path() {
+      let select = |scope: SettingsScope, project: Option<&'static str>| {
+         scope.select("~/.claude/settings.json", project, None)
+      };
+
+      assert_eq!(
+         select(SettingsScope::Global, None).unwrap(),
+         "~/.claude/settings.json"
+      );
+      assert_eq!(
+         select(SettingsScope::Project, Some("/repo/.claude/settings.json")).unwrap(),
+         "/repo/.claude/settings.json"
+      );
+      assert!(matches!(
+         select(SettingsScope::Local, Some("/repo/.claude/settings.json")),
+         Err(AgentSettingsError::InvalidPath { .. })
+      ));
+   }
+}
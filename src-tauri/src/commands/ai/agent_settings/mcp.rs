@@ -0,0 +1,920 @@
+use super::{
+   custom_agents::find_agent,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   keys::{delete_nested_value, get_nested_value, push_key_name, set_nested_value},
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   secrets::keychain_references,
+   storage::{AgentSettingsLocks, WriteOptions, read_config_file, update_config_file},
+};
+use crate::commands::ai::{
+   agent_registry::{KnownAgent, McpServerFormat, McpServersKey},
+   credentials::CredentialStore,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::{
+   collections::{BTreeMap, HashMap},
+   path::{Path, PathBuf},
+};
+use tauri::{State, command};
+
+/// How Athas talks to an MCP server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum McpTransport {
+   /// A process started from `command`, spoken to over stdin and stdout
+   #[default]
+   Stdio,
+   /// A remote server streaming events from `url`
+   Sse,
+   /// A remote server answering streamable HTTP requests at `url`
+   Http,
+}
+
+/// One MCP server, independent of how the agent's config lays it out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpServer {
+   pub name: String,
+   /// Program a stdio server is started with
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub command: Option<String>,
+   #[serde(default)]
+   pub args: Vec<String>,
+   /// Values can refer to secrets in the credential store as `{{keychain:<name>}}`, which are
+   /// saved as written and only resolved when Athas starts the server
+   #[serde(default)]
+   pub env: HashMap<String, String>,
+   #[serde(default)]
+   pub transport: McpTransport,
+   /// Address of an SSE or HTTP server
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub url: Option<String>,
+}
+
+fn invalid_server(name: &str, message: impl Into<String>) -> AgentSettingsError {
+   AgentSettingsError::InvalidMcpServer {
+      name: name.to_string(),
+      message: message.into(),
+   }
+}
+
+/// The definition as it is saved, or why it can't be: stdio servers need a command, remote
+/// ones an http(s) URL, and keychain references in the env must be well formed
+pub(super) fn validate_server(server: &McpServer) -> Result<McpServer, AgentSettingsError> {
+   let name = server.name.trim();
+   if name.is_empty() {
+      return Err(invalid_server(name, "name is empty"));
+   }
+   for (variable, value) in &server.env {
+      if let Err(e) = keychain_references(value) {
+         return Err(invalid_server(name, format!("env {}: {}", variable, e)));
+      }
+   }
+   let non_empty = |value: &Option<String>| {
+      value
+         .as_deref()
+         .map(str::trim)
+         .filter(|value| !value.is_empty())
+         .map(str::to_string)
+   };
+   let (command, url) = match server.transport {
+      McpTransport::Stdio => {
+         let command = non_empty(&server.command)
+            .ok_or_else(|| invalid_server(name, "a stdio server needs a command"))?;
+         (Some(command), None)
+      }
+      McpTransport::Sse | McpTransport::Http => {
+         let url = non_empty(&server.url)
+            .ok_or_else(|| invalid_server(name, "a remote server needs a URL"))?;
+         if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(invalid_server(
+               name,
+               "URL must start with http:// or https://",
+            ));
+         }
+         (None, Some(url))
+      }
+   };
+   Ok(McpServer {
+      name: name.to_string(),
+      command,
+      url,
+      ..server.clone()
+   })
+}
+
+/// Fields of a server entry Athas writes in `format`. Anything else in an entry, such as a
+/// timeout, is the agent's and left alone on update.
+fn managed_fields(format: McpServerFormat) -> &'static [&'static str] {
+   match format {
+      McpServerFormat::Claude => &["type", "command", "args", "env", "url"],
+      McpServerFormat::Codex => &["command", "args", "env", "url"],
+      McpServerFormat::Gemini => &["command", "args", "env", "url", "httpUrl"],
+      McpServerFormat::OpenCode => &["type", "command", "environment", "url"],
+   }
+}
+
+/// `env` in key order, so rewriting a server doesn't reorder its variables
+fn env_value(env: &HashMap<String, String>) -> Value {
+   json!(env.iter().collect::<BTreeMap<_, _>>())
+}
+
+/// The fields `server` is written as in `format`, or `None` for an SSE server in Codex, which
+/// can't connect to one. Empty argument lists and environments are left out.
+fn server_fields(format: McpServerFormat, server: &McpServer) -> Option<Map<String, Value>> {
+   let mut fields = Map::new();
+   let url = json!(server.url);
+   match (format, server.transport) {
+      (McpServerFormat::OpenCode, McpTransport::Stdio) => {
+         let command = server.command.iter().chain(&server.args);
+         fields.insert("type".into(), json!("local"));
+         fields.insert("command".into(), json!(command.collect::<Vec<_>>()));
+         if !server.env.is_empty() {
+            fields.insert("environment".into(), env_value(&server.env));
+         }
+      }
+      (_, McpTransport::Stdio) => {
+         fields.insert("command".into(), json!(server.command));
+         if !server.args.is_empty() {
+            fields.insert("args".into(), json!(server.args));
+         }
+         if !server.env.is_empty() {
+            fields.insert("env".into(), env_value(&server.env));
+         }
+      }
+      (McpServerFormat::Claude, transport) => {
+         let transport = if transport == McpTransport::Sse {
+            "sse"
+         } else {
+            "http"
+         };
+         fields.insert("type".into(), json!(transport));
+         fields.insert("url".into(), url);
+      }
+      (McpServerFormat::Codex, McpTransport::Sse) => return None,
+      (McpServerFormat::Gemini, McpTransport::Http) => {
+         fields.insert("httpUrl".into(), url);
+      }
+      (McpServerFormat::Codex | McpServerFormat::Gemini, _) => {
+         fields.insert("url".into(), url);
+      }
+      // OpenCode connects to remote servers over either transport
+      (McpServerFormat::OpenCode, _) => {
+         fields.insert("type".into(), json!("remote"));
+         fields.insert("url".into(), url);
+      }
+   }
+   Some(fields)
+}
+
+fn string_list(value: Option<&Value>) -> Vec<String> {
+   value
+      .and_then(Value::as_array)
+      .into_iter()
+      .flatten()
+      .filter_map(|item| item.as_str().map(String::from))
+      .collect()
+}
+
+fn string_map(value: Option<&Value>) -> HashMap<String, String> {
+   value
+      .and_then(Value::as_object)
+      .into_iter()
+      .flatten()
+      .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+      .collect()
+}
+
+/// Read the entry for the server `name` laid out in `format`. `None` when it is neither a
+/// command nor a URL.
+fn parse_server(format: McpServerFormat, name: &str, entry: &Value) -> Option<McpServer> {
+   let entry = entry.as_object()?;
+   let text = |field: &str| entry.get(field).and_then(Value::as_str).map(String::from);
+   let remote = |transport: McpTransport, url: String| McpServer {
+      name: name.to_string(),
+      command: None,
+      args: Vec::new(),
+      env: HashMap::new(),
+      transport,
+      url: Some(url),
+   };
+
+   if format == McpServerFormat::OpenCode {
+      if text("type").as_deref() == Some("remote") {
+         return Some(remote(McpTransport::Http, text("url")?));
+      }
+      let mut command = string_list(entry.get("command")).into_iter();
+      return Some(McpServer {
+         name: name.to_string(),
+         command: Some(command.next()?),
+         args: command.collect(),
+         env: string_map(entry.get("environment")),
+         transport: McpTransport::Stdio,
+         url: None,
+      });
+   }
+
+   let remote_server = match format {
+      McpServerFormat::Claude => match text("type").as_deref() {
+         Some("sse") => Some(McpTransport::Sse),
+         Some("http" | "streamable-http") => Some(McpTransport::Http),
+         _ => None,
+      }
+      .map(|transport| (transport, text("url"))),
+      McpServerFormat::Gemini => text("httpUrl")
+         .map(|url| (McpTransport::Http, Some(url)))
+         .or_else(|| text("url").map(|url| (McpTransport::Sse, Some(url)))),
+      _ => text("url").map(|url| (McpTransport::Http, Some(url))),
+   };
+   if let Some((transport, url)) = remote_server {
+      return Some(remote(transport, url?));
+   }
+   Some(McpServer {
+      name: name.to_string(),
+      command: Some(text("command")?),
+      args: string_list(entry.get("args")),
+      env: string_map(entry.get("env")),
+      transport: McpTransport::Stdio,
+      url: None,
+   })
+}
+
+fn server_key(servers: &McpServersKey, name: &str) -> String {
+   let mut key = servers.key_path.clone();
+   push_key_name(&mut key, name);
+   key
+}
+
+/// Servers in `value` under `servers`, in name order. Entries that aren't server definitions
+/// are skipped with a warning.
+pub(super) fn read_servers(value: &Value, servers: &McpServersKey) -> Vec<McpServer> {
+   let Some(Value::Object(entries)) = get_nested_value(value, &servers.key_path) else {
+      return Vec::new();
+   };
+   let mut loaded: Vec<McpServer> = entries
+      .iter()
+      .filter_map(|(name, entry)| {
+         let server = parse_server(servers.format, name, entry);
+         if server.is_none() {
+            log::warn!("Skipping MCP server {} without a command or URL", name);
+         }
+         server
+      })
+      .collect();
+   loaded.sort_by(|a, b| a.name.cmp(&b.name));
+   loaded
+}
+
+/// The agent's MCP servers key, or an `UnsupportedSetting` error for agents without one
+pub(super) fn servers_key(agent: &KnownAgent) -> Result<&McpServersKey, AgentSettingsError> {
+   agent
+      .mcp_servers
+      .as_ref()
+      .ok_or_else(|| AgentSettingsError::UnsupportedSetting {
+         agent_id: agent.id.clone(),
+         setting: "mcpServers".to_string(),
+         message: format!("{} has no MCP servers", agent.name),
+      })
+}
+
+pub(super) async fn load_from(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   format: ConfigFormat,
+   servers: &McpServersKey,
+) -> Result<Vec<McpServer>, AgentSettingsError> {
+   Ok(read_config_file(locks, path, format)
+      .await?
+      .map(|file| read_servers(&file.value, servers))
+      .unwrap_or_default())
+}
+
+/// How `server` reads back once written in `format`, or `None` when the format can't hold it.
+/// Servers that come back different would be rewritten by every sync.
+pub(super) fn translate(format: McpServerFormat, server: &McpServer) -> Option<McpServer> {
+   let fields = server_fields(format, server)?;
+   parse_server(format, &server.name, &Value::Object(fields))
+}
+
+/// Write `server`, already validated, into the agent's config `value`. With `replace`, the
+/// server must already exist, and fields of its entry Athas doesn't manage are kept; otherwise it
+/// must not exist.
+pub(super) fn put_server(
+   value: &mut Value,
+   agent: &KnownAgent,
+   server: &McpServer,
+   replace: bool,
+) -> Result<(), AgentSettingsError> {
+   let servers = servers_key(agent)?;
+   let fields = server_fields(servers.format, server).ok_or_else(|| {
+      AgentSettingsError::UnsupportedSetting {
+         agent_id: agent.id.clone(),
+         setting: "mcpServers".to_string(),
+         message: format!("{} can't connect to SSE servers", agent.name),
+      }
+   })?;
+   let key = server_key(servers, &server.name);
+
+   let existing = get_nested_value(value, &key).and_then(Value::as_object);
+   let mut entry = match (replace, existing) {
+      (true, None) => {
+         return Err(AgentSettingsError::UnknownMcpServer {
+            agent_id: agent.id.clone(),
+            name: server.name.clone(),
+         });
+      }
+      (false, Some(_)) => {
+         return Err(invalid_server(
+            &server.name,
+            "a server with this name exists",
+         ));
+      }
+      (true, Some(existing)) => existing.clone(),
+      (false, None) => Map::new(),
+   };
+   for field in managed_fields(servers.format) {
+      entry.remove(*field);
+   }
+   entry.extend(fields);
+   set_nested_value(value, &key, Value::Object(entry))
+}
+
+/// Remove the server `name` from the config `value`, returning whether it was there
+pub(super) fn delete_server(
+   value: &mut Value,
+   servers: &McpServersKey,
+   name: &str,
+) -> Result<bool, AgentSettingsError> {
+   delete_nested_value(value, &server_key(servers, name), false)
+}
+
+/// Write `server` into the agent's config at `path`, returning it as saved. See `put_server`.
+async fn save_to(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   agent: &KnownAgent,
+   server: &McpServer,
+   replace: bool,
+   options: WriteOptions<'_>,
+) -> Result<McpServer, AgentSettingsError> {
+   servers_key(agent)?;
+   let server = validate_server(server)?;
+   update_config_file(
+      locks,
+      path,
+      ConfigFormat::from_path(&agent.settings_path),
+      options,
+      |value| put_server(value, agent, &server, replace),
+   )
+   .await?;
+   Ok(server)
+}
+
+async fn remove_from(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   agent: &KnownAgent,
+   name: &str,
+   options: WriteOptions<'_>,
+) -> Result<bool, AgentSettingsError> {
+   let servers = servers_key(agent)?;
+   if !path.exists() {
+      return Ok(false);
+   }
+   let mut removed = false;
+   update_config_file(
+      locks,
+      path,
+      ConfigFormat::from_path(&agent.settings_path),
+      options,
+      |value| {
+         removed = delete_server(value, servers, name)?;
+         Ok(())
+      },
+   )
+   .await?;
+   Ok(removed)
+}
+
+pub(super) async fn known_agent(
+   locks: &AgentSettingsLocks,
+   agent_id: &str,
+) -> Result<KnownAgent, AgentSettingsError> {
+   find_agent(locks, agent_id)
+      .await?
+      .ok_or_else(|| AgentSettingsError::UnknownAgent {
+         agent_id: agent_id.to_string(),
+      })
+}
+
+pub(super) fn writable_path(
+   roots: &AgentSettingsRoots,
+   agent: &KnownAgent,
+) -> Result<PathBuf, AgentSettingsError> {
+   roots.check(&resolve_settings_path(&agent.settings_path)?, false)
+}
+
+/// The server's env with every `{{keychain:<name>}}` reference replaced by the secret stored
+/// under that name, for starting the server from Athas
+pub(super) fn materialize_env(
+   server: &McpServer,
+   store: &CredentialStore,
+) -> Result<HashMap<String, String>, AgentSettingsError> {
+   let server = validate_server(server)?;
+   let mut env = HashMap::with_capacity(server.env.len());
+   for (variable, value) in &server.env {
+      let mut materialized = String::with_capacity(value.len());
+      let mut copied = 0;
+      for reference in keychain_references(value).unwrap_or_default() {
+         let unresolved = |message: String| AgentSettingsError::KeychainReference {
+            server: server.name.clone(),
+            name: reference.name.to_string(),
+            message,
+         };
+         let secret = store
+            .get(reference.name)
+            .map_err(unresolved)?
+            .ok_or_else(|| unresolved("no secret is stored under this name".to_string()))?;
+         materialized.push_str(&value[copied..reference.range.start]);
+         materialized.push_str(&secret);
+         copied = reference.range.end;
+      }
+      materialized.push_str(&value[copied..]);
+      env.insert(variable.clone(), materialized);
+   }
+   Ok(env)
+}
+
+/// The MCP servers in an agent's config, in name order, read from wherever the agent keeps them
+#[command]
+pub async fn list_mcp_servers(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+) -> Result<Vec<McpServer>, AgentSettingsError> {
+   let agent = known_agent(&locks, &agent_id).await?;
+   let servers = servers_key(&agent)?;
+   load_from(
+      &locks,
+      &resolve_settings_path(&agent.settings_path)?,
+      ConfigFormat::from_path(&agent.settings_path),
+      servers,
+   )
+   .await
+}
+
+/// Add an MCP server to an agent's config, laid out the way the agent reads it. A server with
+/// the same name is an `InvalidMcpServer` error.
+#[command]
+pub async fn add_mcp_server(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   server: McpServer,
+) -> Result<McpServer, AgentSettingsError> {
+   let agent = known_agent(&locks, &agent_id).await?;
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &agent_id,
+      }),
+      ..WriteOptions::default()
+   };
+   let path = writable_path(&roots, &agent)?;
+   let server = save_to(&locks, &path, &agent, &server, false, options).await?;
+   log::info!("Added MCP server {} to agent {}", server.name, agent_id);
+   Ok(server)
+}
+
+/// Replace an MCP server in an agent's config. Settings of the entry Athas doesn't manage, such
+/// as timeouts, are kept.
+#[command]
+pub async fn update_mcp_server(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   server: McpServer,
+) -> Result<McpServer, AgentSettingsError> {
+   let agent = known_agent(&locks, &agent_id).await?;
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &agent_id,
+      }),
+      ..WriteOptions::default()
+   };
+   let path = writable_path(&roots, &agent)?;
+   let server = save_to(&locks, &path, &agent, &server, true, options).await?;
+   log::info!("Updated MCP server {} of agent {}", server.name, agent_id);
+   Ok(server)
+}
+
+/// Remove an MCP server from an agent's config. Removing one that doesn't exist is an
+/// `UnknownMcpServer` error.
+#[command]
+pub async fn remove_mcp_server(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   name: String,
+) -> Result<(), AgentSettingsError> {
+   let agent = known_agent(&locks, &agent_id).await?;
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &agent_id,
+      }),
+      ..WriteOptions::default()
+   };
+   let path = writable_path(&roots, &agent)?;
+   if !remove_from(&locks, &path, &agent, &name, options).await? {
+      return Err(AgentSettingsError::UnknownMcpServer { agent_id, name });
+   }
+   log::info!("Removed MCP server {} from agent {}", name, agent_id);
+   Ok(())
+}
+
+/// The env of an agent's MCP server with its `{{keychain:<name>}}` references resolved from the
+/// credential store, for when Athas starts the server itself. The agent's config keeps the
+/// references as written.
+#[command]
+pub async fn materialize_mcp_env(
+   locks: State<'_, AgentSettingsLocks>,
+   credentials: State<'_, CredentialStore>,
+   agent_id: String,
+   server_name: String,
+) -> Result<HashMap<String, String>, AgentSettingsError> {
+   let agent = known_agent(&locks, &agent_id).await?;
+   let servers = load_from(
+      &locks,
+      &resolve_settings_path(&agent.settings_path)?,
+      ConfigFormat::from_path(&agent.settings_path),
+      servers_key(&agent)?,
+   )
+   .await?;
+   let server = servers
+      .into_iter()
+      .find(|server| server.name == server_name)
+      .ok_or(AgentSettingsError::UnknownMcpServer {
+         agent_id,
+         name: server_name,
+      })?;
+   materialize_env(&server, &credentials)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::{agent_registry::find_known_agent, credentials::MockKeychain};
+
+   fn github() -> McpServer {
+      McpServer {
+         name: "github".into(),
+         command: Some("npx".into()),
+         args: vec!["-y".into(), "@modelcontextprotocol/server-github".into()],
+         env: HashMap::from([
+            ("GITHUB_TOKEN".to_string(), "${GITHUB_TOKEN}".to_string()),
+            ("LOG_LEVEL".to_string(), "debug".to_string()),
+         ]),
+         transport: McpTransport::Stdio,
+         url: None,
+      }
+   }
+
+   fn remote(transport: McpTransport) -> McpServer {
+      McpServer {
+         name: "docs".into(),
+         command: None,
+         args: Vec::new(),
+         env: HashMap::new(),
+         transport,
+         url: Some("https://mcp.example.com/mcp".into()),
+      }
+   }
+
+   /// A known agent with its config moved to `dir`
+   fn agent_in(agent_id: &str, dir: &Path) -> (KnownAgent, PathBuf) {
+      let mut agent = find_known_agent(agent_id).unwrap();
+      let file_name = Path::new(&agent.settings_path).file_name().unwrap();
+      let path = dir.join(file_name);
+      agent.settings_path = path.display().to_string();
+      (agent, path)
+   }
+
+   #[tokio::test]
+   async fn test_round_trip_in_json_and_toml() {
+      let dir = tempfile::tempdir().unwrap();
+      let locks = AgentSettingsLocks::new();
+      for agent_id in ["claude-code", "codex-cli", "gemini-cli", "opencode"] {
+         let (agent, path) = agent_in(agent_id, dir.path());
+         let format = ConfigFormat::from_path(&agent.settings_path);
+         let servers = agent.mcp_servers.clone().unwrap();
+         let options = WriteOptions::default();
+
+         save_to(&locks, &path, &agent, &github(), false, options)
+            .await
+            .unwrap();
+         assert_eq!(
+            load_from(&locks, &path, format, &servers).await.unwrap(),
+            vec![github()],
+            "{}",
+            agent_id
+         );
+
+         let updated = McpServer {
+            args: vec!["--read-only".into()],
+            env: HashMap::new(),
+            ..github()
+         };
+         save_to(&locks, &path, &agent, &updated, true, options)
+            .await
+            .unwrap();
+         assert_eq!(
+            load_from(&locks, &path, format, &servers).await.unwrap(),
+            vec![updated],
+            "{}",
+            agent_id
+         );
+
+         assert!(
+            remove_from(&locks, &path, &agent, "github", options)
+               .await
+               .unwrap()
+         );
+         assert!(
+            !remove_from(&locks, &path, &agent, "github", options)
+               .await
+               .unwrap()
+         );
+         assert!(
+            load_from(&locks, &path, format, &servers)
+               .await
+               .unwrap()
+               .is_empty()
+         );
+      }
+
+      let codex = std::fs::read_to_string(dir.path().join("config.toml")).unwrap();
+      assert!(!codex.contains("github"));
+   }
+
+   #[tokio::test]
+   async fn test_agent_layouts() {
+      let dir = tempfile::tempdir().unwrap();
+      let locks = AgentSettingsLocks::new();
+      let options = WriteOptions::default();
+
+      let (codex, codex_path) = agent_in("codex-cli", dir.path());
+      std::fs::write(
+         &codex_path,
+         "model = \"gpt-5\"\n\n[mcp_servers.github]\ncommand = \"old\"\nstartup_timeout_sec = 20\n",
+      )
+      .unwrap();
+      save_to(&locks, &codex_path, &codex, &github(), true, options)
+         .await
+         .unwrap();
+      let content = std::fs::read_to_string(&codex_path).unwrap();
+      let value: toml::Value = toml::from_str(&content).unwrap();
+      let entry = &value["mcp_servers"]["github"];
+      assert_eq!(entry["command"].as_str(), Some("npx"));
+      assert_eq!(entry["args"].as_array().unwrap().len(), 2);
+      assert_eq!(entry["env"]["LOG_LEVEL"].as_str(), Some("debug"));
+      // Settings Athas doesn't manage survive an update
+      assert_eq!(entry["startup_timeout_sec"].as_integer(), Some(20));
+      assert!(matches!(
+         save_to(
+            &locks,
+            &codex_path,
+            &codex,
+            &remote(McpTransport::Sse),
+            false,
+            options
+         )
+         .await,
+         Err(AgentSettingsError::UnsupportedSetting { .. })
+      ));
+
+      let (opencode, opencode_path) = agent_in("opencode", dir.path());
+      save_to(&locks, &opencode_path, &opencode, &github(), false, options)
+         .await
+         .unwrap();
+      let value: Value =
+         serde_json::from_str(&std::fs::read_to_string(&opencode_path).unwrap()).unwrap();
+      assert_eq!(
+         value["mcp"]["github"],
+         json!({
+            "type": "local",
+            "command": ["npx", "-y", "@modelcontextprotocol/server-github"],
+            "environment": { "GITHUB_TOKEN": "${GITHUB_TOKEN}", "LOG_LEVEL": "debug" },
+         })
+      );
+
+      let (gemini, gemini_path) = agent_in("gemini-cli", dir.path());
+      for transport in [McpTransport::Sse, McpTransport::Http] {
+         let server = McpServer {
+            name: format!("{:?}", transport),
+            ..remote(transport)
+         };
+         save_to(&locks, &gemini_path, &gemini, &server, false, options)
+            .await
+            .unwrap();
+      }
+      let value: Value =
+         serde_json::from_str(&std::fs::read_to_string(&gemini_path).unwrap()).unwrap();
+      assert_eq!(
+         value["mcpServers"]["Http"],
+         json!({ "httpUrl": "https://mcp.example.com/mcp" })
+      );
+      let servers = gemini.mcp_servers.clone().unwrap();
+      let loaded = load_from(&locks, &gemini_path, ConfigFormat::Json, &servers)
+         .await
+         .unwrap();
+      assert_eq!(
+         loaded.iter().map(|s| s.transport).collect::<Vec<_>>(),
+         vec![McpTransport::Http, McpTransport::Sse]
+      );
+
+      let (aider, aider_path) = agent_in("aider", dir.path());
+      assert!(matches!(
+         save_to(&locks, &aider_path, &aider, &github(), false, options).await,
+         Err(AgentSettingsError::UnsupportedSetting { .. })
+      ));
+   }
+
+   #[tokio::test]
+   async fn test_gemini_settings_keep_their_own_fields() {
+      let dir = tempfile::tempdir().unwrap();
+      let locks = AgentSettingsLocks::new();
+      let (gemini, path) = agent_in("gemini-cli", dir.path());
+      // Shaped like the settings Gemini CLI writes itself
+      let content = json!({
+         "model": { "name": "gemini-2.5-pro" },
+         "security": { "auth": { "selectedType": "gemini-api-key" } },
+         "mcpServers": {
+            "github": {
+               "command": "npx",
+               "args": ["-y", "@modelcontextprotocol/server-github"],
+               "env": { "GITHUB_PERSONAL_ACCESS_TOKEN": "$GITHUB_TOKEN" },
+               "cwd": "./tools",
+               "timeout": 30000,
+               "trust": false,
+               "includeTools": ["create_issue", "search_code"],
+            },
+            "docs": {
+               "httpUrl": "https://mcp.example.com/mcp",
+               "headers": { "Authorization": "Bearer $DOCS_TOKEN" },
+            },
+            "events": { "url": "https://mcp.example.com/sse" },
+         },
+      });
+      std::fs::write(&path, content.to_string()).unwrap();
+      let servers = gemini.mcp_servers.clone().unwrap();
+
+      let loaded = load_from(&locks, &path, ConfigFormat::Json, &servers)
+         .await
+         .unwrap();
+      assert_eq!(
+         loaded
+            .iter()
+            .map(|server| (server.name.as_str(), server.transport))
+            .collect::<Vec<_>>(),
+         vec![
+            ("docs", McpTransport::Http),
+            ("events", McpTransport::Sse),
+            ("github", McpTransport::Stdio),
+         ]
+      );
+      assert_eq!(
+         loaded[2].args,
+         vec!["-y", "@modelcontextprotocol/server-github"]
+      );
+
+      let updated = McpServer {
+         args: vec![
+            "-y".into(),
+            "@modelcontextprotocol/server-github@latest".into(),
+         ],
+         ..loaded[2].clone()
+      };
+      save_to(
+         &locks,
+         &path,
+         &gemini,
+         &updated,
+         true,
+         WriteOptions::default(),
+      )
+      .await
+      .unwrap();
+      let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+      let entry = &value["mcpServers"]["github"];
+      assert_eq!(
+         entry["args"][1],
+         json!("@modelcontextprotocol/server-github@latest")
+      );
+      assert_eq!(entry["cwd"], json!("./tools"));
+      assert_eq!(entry["timeout"], json!(30000));
+      assert_eq!(entry["trust"], json!(false));
+      assert_eq!(
+         entry["includeTools"],
+         json!(["create_issue", "search_code"])
+      );
+      assert_eq!(value["mcpServers"]["docs"], content["mcpServers"]["docs"]);
+      assert_eq!(value["security"], content["security"]);
+   }
+
+   #[tokio::test]
+   async fn test_conflicts_and_validation() {
+      let dir = tempfile::tempdir().unwrap();
+      let locks = AgentSettingsLocks::new();
+      let options = WriteOptions::default();
+      let (claude, path) = agent_in("claude-code", dir.path());
+
+      assert!(matches!(
+         save_to(&locks, &path, &claude, &github(), true, options).await,
+         Err(AgentSettingsError::UnknownMcpServer { .. })
+      ));
+      save_to(&locks, &path, &claude, &github(), false, options)
+         .await
+         .unwrap();
+      assert!(matches!(
+         save_to(&locks, &path, &claude, &github(), false, options).await,
+         Err(AgentSettingsError::InvalidMcpServer { .. })
+      ));
+
+      let no_command = McpServer {
+         command: Some(" ".into()),
+         ..github()
+      };
+      let no_url = McpServer {
+         url: None,
+         ..remote(McpTransport::Http)
+      };
+      let bad_url = McpServer {
+         url: Some("mcp.example.com".into()),
+         ..remote(McpTransport::Sse)
+      };
+      for server in [no_command, no_url, bad_url] {
+         assert!(matches!(
+            validate_server(&server),
+            Err(AgentSettingsError::InvalidMcpServer { .. })
+         ));
+      }
+   }
+
+   #[test]
+   fn test_keychain_references_in_env() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = CredentialStore::new(Box::new(MockKeychain::unavailable()), dir.path());
+      store.set("github_token", "ghp_0123456789").unwrap();
+      let server = McpServer {
+         env: HashMap::from([
+            (
+               "GITHUB_TOKEN".to_string(),
+               "{{keychain:github_token}}".to_string(),
+            ),
+            (
+               "AUTH_HEADER".to_string(),
+               "Bearer {{keychain:github_token}}".to_string(),
+            ),
+            ("LOG_LEVEL".to_string(), "debug".to_string()),
+         ]),
+         ..github()
+      };
+      // Saved as written
+      assert_eq!(validate_server(&server).unwrap().env, server.env);
+
+      let env = materialize_env(&server, &store).unwrap();
+      assert_eq!(env["GITHUB_TOKEN"], "ghp_0123456789");
+      assert_eq!(env["AUTH_HEADER"], "Bearer ghp_0123456789");
+      assert_eq!(env["LOG_LEVEL"], "debug");
+
+      let missing = McpServer {
+         env: HashMap::from([(
+            "LINEAR_API_KEY".to_string(),
+            "{{keychain:linear}}".to_string(),
+         )]),
+         ..github()
+      };
+      let error = materialize_env(&missing, &store).unwrap_err();
+      assert!(matches!(
+         &error,
+         AgentSettingsError::KeychainReference { name, .. } if name == "linear"
+      ));
+      assert!(error.to_string().contains("as {{keychain:<name>}}"));
+
+      let malformed = McpServer {
+         env: HashMap::from([("TOKEN".to_string(), "{{keychain:Linear".to_string())]),
+         ..github()
+      };
+      assert!(matches!(
+         validate_server(&malformed),
+         Err(AgentSettingsError::InvalidMcpServer { .. })
+      ));
+   }
+}
@@ -0,0 +1,287 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   mcp::{
+      McpServer, known_agent, put_server, read_servers, servers_key, translate, validate_server,
+      writable_path,
+   },
+   mcp_sync::McpSkippedServer,
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   storage::{AgentSettingsLocks, WriteOptions, read_config_file, update_config_file},
+};
+use crate::commands::ai::agent_registry::{KnownAgent, McpServerFormat, McpServersKey};
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::HashMap, path::Path};
+use tauri::{State, command};
+
+/// Claude Desktop's config: `~/Library/Application Support/Claude` on macOS, `%APPDATA%\Claude`
+/// on Windows and `~/.config/Claude` on Linux
+const CLAUDE_DESKTOP_CONFIG: &str = "{config}/Claude/claude_desktop_config.json";
+
+/// A Claude Desktop server whose name the target agent already uses for a different server
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpImportConflict {
+   pub name: String,
+   pub existing: McpServer,
+   pub incoming: McpServer,
+}
+
+/// What importing from Claude Desktop did to the target agent
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpDesktopImport {
+   pub target_agent_id: String,
+   /// Where Claude Desktop's config was looked for
+   pub source_path: String,
+   /// Whether that config exists. When it doesn't, nothing else is set.
+   pub found: bool,
+   pub imported: Vec<String>,
+   /// Conflicting servers replaced because `overwrite` was set
+   pub overwritten: Vec<String>,
+   /// Servers the target already has exactly as Claude Desktop defines them
+   pub unchanged: Vec<String>,
+   /// Conflicting servers left as they are
+   pub conflicts: Vec<McpImportConflict>,
+   pub skipped: Vec<McpSkippedServer>,
+   /// Requested servers Claude Desktop doesn't have
+   pub missing: Vec<String>,
+   pub wrote: bool,
+}
+
+/// Claude Desktop lays servers out exactly like Claude Code
+fn desktop_servers_key() -> McpServersKey {
+   McpServersKey {
+      key_path: "mcpServers".to_string(),
+      format: McpServerFormat::Claude,
+   }
+}
+
+/// The servers in the Claude Desktop config at `path`, or `None` when there is no such file
+async fn read_desktop_servers(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+) -> Result<Option<Vec<McpServer>>, AgentSettingsError> {
+   Ok(read_config_file(locks, path, ConfigFormat::Json)
+      .await?
+      .map(|file| read_servers(&file.value, &desktop_servers_key())))
+}
+
+/// Narrow `desktop` down to `names`, recording requested names it doesn't have in `missing`
+fn select(
+   desktop: Vec<McpServer>,
+   names: Option<&[String]>,
+   missing: &mut Vec<String>,
+) -> Vec<McpServer> {
+   let Some(names) = names else {
+      return desktop;
+   };
+   missing.extend(
+      names
+         .iter()
+         .filter(|name| !desktop.iter().any(|server| &server.name == *name))
+         .cloned(),
+   );
+   desktop
+      .into_iter()
+      .filter(|server| names.contains(&server.name))
+      .collect()
+}
+
+/// Write the `desktop` servers into `target`, the target agent's config, sorting each into
+/// `import`. A server the target already has differently is a conflict, replaced only with
+/// `overwrite`.
+pub(super) fn import_into(
+   target: &mut Value,
+   agent: &KnownAgent,
+   desktop: &[McpServer],
+   overwrite: bool,
+   import: &mut McpDesktopImport,
+) -> Result<(), AgentSettingsError> {
+   let servers = servers_key(agent)?;
+   let current: HashMap<String, McpServer> = read_servers(target, servers)
+      .into_iter()
+      .map(|server| (server.name.clone(), server))
+      .collect();
+
+   for server in desktop {
+      let server = match validate_server(server) {
+         Ok(server) => server,
+         Err(e) => {
+            import.skipped.push(McpSkippedServer {
+               name: server.name.clone(),
+               reason: e.to_string(),
+            });
+            continue;
+         }
+      };
+      let Some(translated) = translate(servers.format, &server) else {
+         import.skipped.push(McpSkippedServer {
+            name: server.name.clone(),
+            reason: format!("{} can't connect to SSE servers", agent.name),
+         });
+         continue;
+      };
+      match current.get(&server.name) {
+         None => {
+            put_server(target, agent, &server, false)?;
+            import.imported.push(server.name.clone());
+         }
+         Some(existing) if *existing == translated => import.unchanged.push(server.name.clone()),
+         Some(_) if overwrite => {
+            put_server(target, agent, &server, true)?;
+            import.overwritten.push(server.name.clone());
+         }
+         Some(existing) => import.conflicts.push(McpImportConflict {
+            name: server.name.clone(),
+            existing: existing.clone(),
+            incoming: translated,
+         }),
+      }
+   }
+   Ok(())
+}
+
+/// Copy MCP servers from Claude Desktop's `claude_desktop_config.json` into an agent's config,
+/// all of them or only `server_names`. A target server of the same name that differs is reported
+/// as a conflict and left alone unless `overwrite` is set. Without a Claude Desktop config the
+/// result has `found` unset rather than being an error.
+#[command]
+pub async fn import_mcp_servers_from_claude_desktop(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   target_agent_id: String,
+   server_names: Option<Vec<String>>,
+   overwrite: Option<bool>,
+) -> Result<McpDesktopImport, AgentSettingsError> {
+   let agent = known_agent(&locks, &target_agent_id).await?;
+   servers_key(&agent)?;
+   let source = resolve_settings_path(CLAUDE_DESKTOP_CONFIG)?;
+   let mut import = McpDesktopImport {
+      target_agent_id: target_agent_id.clone(),
+      source_path: source.display().to_string(),
+      ..McpDesktopImport::default()
+   };
+   let Some(desktop) = read_desktop_servers(&locks, &source).await? else {
+      log::info!("No Claude Desktop config at {}", source.display());
+      return Ok(import);
+   };
+   import.found = true;
+   let desktop = select(desktop, server_names.as_deref(), &mut import.missing);
+
+   let path = writable_path(&roots, &agent)?;
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &target_agent_id,
+      }),
+      ..WriteOptions::default()
+   };
+   let overwrite = overwrite.unwrap_or(false);
+   // Sorted against the content read under the write lock, so the report matches what's written
+   let wrote = update_config_file(
+      &locks,
+      &path,
+      ConfigFormat::from_path(&agent.settings_path),
+      options,
+      |value| import_into(value, &agent, &desktop, overwrite, &mut import),
+   )
+   .await?;
+   import.wrote = wrote;
+
+   log::info!(
+      "Imported {} MCP servers from Claude Desktop into agent {} ({} conflicts)",
+      import.imported.len() + import.overwritten.len(),
+      target_agent_id,
+      import.conflicts.len()
+   );
+   Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_registry::find_known_agent;
+   use serde_json::json;
+
+   const DESKTOP_CONFIG: &str = r#"{
+      "mcpServers": {
+         "filesystem": {
+            "command": "npx",
+            "args": ["-y", "@modelcontextprotocol/server-filesystem", "/Users/me/code"]
+         },
+         "github": {
+            "command": "github-mcp",
+            "env": { "GITHUB_TOKEN": "ghp_example" }
+         },
+         "docs": { "type": "sse", "url": "https://mcp.example.com/sse" }
+      },
+      "globalShortcut": "Ctrl+Space"
+   }"#;
+
+   async fn desktop_servers() -> Vec<McpServer> {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("claude_desktop_config.json");
+      let locks = AgentSettingsLocks::new();
+      assert!(read_desktop_servers(&locks, &path).await.unwrap().is_none());
+
+      std::fs::write(&path, DESKTOP_CONFIG).unwrap();
+      read_desktop_servers(&locks, &path).await.unwrap().unwrap()
+   }
+
+   #[tokio::test]
+   async fn test_import_reports_conflicts() {
+      let desktop = desktop_servers().await;
+      let names: Vec<&str> = desktop.iter().map(|server| server.name.as_str()).collect();
+      assert_eq!(names, ["docs", "filesystem", "github"]);
+
+      let codex = find_known_agent("codex-cli").unwrap();
+      let mut target = json!({
+         "mcp_servers": {
+            "github": { "command": "gh-mcp-fork" },
+         },
+      });
+      let mut import = McpDesktopImport::default();
+      import_into(&mut target, &codex, &desktop, false, &mut import).unwrap();
+      assert_eq!(import.imported, vec!["filesystem"]);
+      assert_eq!(import.conflicts.len(), 1);
+      assert_eq!(import.conflicts[0].name, "github");
+      assert_eq!(
+         import.conflicts[0].existing.command.as_deref(),
+         Some("gh-mcp-fork")
+      );
+      assert_eq!(
+         import.conflicts[0].incoming.command.as_deref(),
+         Some("github-mcp")
+      );
+      // Codex has no SSE transport
+      assert_eq!(import.skipped[0].name, "docs");
+      assert_eq!(
+         target["mcp_servers"]["github"],
+         json!({ "command": "gh-mcp-fork" })
+      );
+
+      let mut import = McpDesktopImport::default();
+      import_into(&mut target, &codex, &desktop, true, &mut import).unwrap();
+      assert_eq!(import.unchanged, vec!["filesystem"]);
+      assert_eq!(import.overwritten, vec!["github"]);
+      assert_eq!(
+         target["mcp_servers"]["github"],
+         json!({ "command": "github-mcp", "env": { "GITHUB_TOKEN": "ghp_example" } })
+      );
+   }
+
+   #[tokio::test]
+   async fn test_select_named_servers() {
+      let desktop = desktop_servers().await;
+      let mut missing = Vec::new();
+      let names = vec!["github".to_string(), "slack".to_string()];
+      let selected = select(desktop, Some(&names), &mut missing);
+      assert_eq!(selected.len(), 1);
+      assert_eq!(selected[0].name, "github");
+      assert_eq!(missing, vec!["slack"]);
+   }
+}
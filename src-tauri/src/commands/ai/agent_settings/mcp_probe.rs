@@ -0,0 +1,458 @@
+use super::{
+   error::AgentSettingsError,
+   mcp::{McpServer, McpTransport, materialize_env, validate_server},
+};
+use crate::{commands::ai::credentials::CredentialStore, features::ai::acp::find_binary};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+   collections::VecDeque,
+   path::PathBuf,
+   process::Stdio,
+   sync::{Arc, Mutex},
+   time::Duration,
+};
+use tauri::{State, command};
+use tokio::{
+   io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+   process::{ChildStdin, ChildStdout, Command},
+};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_TIMEOUT: Duration = Duration::from_secs(120);
+/// MCP revision Athas offers; servers answer with the one they speak
+const PROTOCOL_VERSION: &str = "2025-06-18";
+/// After its stdin closes, how long a server gets to exit before it is killed
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+/// How long stderr is drained after the server exits, in case a child it started holds it open
+const STDERR_DRAIN: Duration = Duration::from_millis(200);
+const STDERR_TAIL_LINES: usize = 20;
+/// Pages of `tools/list` followed before the rest are left out
+const MAX_TOOL_PAGES: usize = 20;
+
+/// Name and version a server reports in its `initialize` result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpServerInfo {
+   pub name: String,
+   #[serde(default)]
+   pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpTool {
+   pub name: String,
+   #[serde(default)]
+   pub description: Option<String>,
+}
+
+/// Why testing a server failed. `stage` is the request the server was answering: `initialize`
+/// or `tools/list`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(
+   tag = "type",
+   rename_all = "camelCase",
+   rename_all_fields = "camelCase"
+)]
+pub enum McpTestFailure {
+   /// The command couldn't be started, usually because it isn't installed
+   Spawn { command: String, message: String },
+   /// The server didn't answer in time
+   Timeout { stage: String, timeout_ms: u64 },
+   /// The server exited before answering. `code` is `None` when it was killed by a signal.
+   Exited { stage: String, code: Option<i32> },
+   /// The server wrote something other than the MCP messages expected, or answered with an error
+   Protocol { stage: String, message: String },
+}
+
+/// What testing an MCP server found
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpServerTest {
+   pub ok: bool,
+   pub server_info: Option<McpServerInfo>,
+   pub tools: Vec<McpTool>,
+   /// Last lines the server wrote to stderr, where most servers report missing configuration
+   pub stderr_tail: String,
+   pub error: Option<McpTestFailure>,
+}
+
+/// How one exchange with the server went wrong, before it's known which stage it was in
+enum Failure {
+   Exited,
+   Protocol(String),
+}
+
+/// The JSON-RPC side of the stdio transport: one message per line each way
+struct Session {
+   stdin: ChildStdin,
+   stdout: Lines<BufReader<ChildStdout>>,
+   next_id: u64,
+}
+
+impl Session {
+   async fn send(&mut self, message: Value) -> Result<(), Failure> {
+      let mut line = message.to_string();
+      line.push('\n');
+      self
+         .stdin
+         .write_all(line.as_bytes())
+         .await
+         .map_err(|_| Failure::Exited)?;
+      self.stdin.flush().await.map_err(|_| Failure::Exited)
+   }
+
+   async fn notify(&mut self, method: &str) -> Result<(), Failure> {
+      self
+         .send(json!({ "jsonrpc": "2.0", "method": method }))
+         .await
+   }
+
+   /// Send a request and wait for its response, skipping notifications and turning down requests
+   /// the server makes in the meantime
+   async fn request(&mut self, method: &str, params: Value) -> Result<Value, Failure> {
+      self.next_id += 1;
+      let id = self.next_id;
+      self
+         .send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+         .await?;
+
+      loop {
+         let Some(line) = self.stdout.next_line().await.map_err(|_| Failure::Exited)? else {
+            return Err(Failure::Exited);
+         };
+         let line = line.trim();
+         if line.is_empty() {
+            continue;
+         }
+         let message: Value = serde_json::from_str(line).map_err(|_| {
+            let snippet: String = line.chars().take(120).collect();
+            Failure::Protocol(format!("not a JSON-RPC message: {}", snippet))
+         })?;
+
+         if let Some(request_method) = message.get("method").and_then(Value::as_str) {
+            if let Some(request_id) = message.get("id") {
+               let reply = if request_method == "ping" {
+                  json!({ "jsonrpc": "2.0", "id": request_id, "result": {} })
+               } else {
+                  json!({
+                     "jsonrpc": "2.0",
+                     "id": request_id,
+                     "error": { "code": -32601, "message": "Method not found" },
+                  })
+               };
+               self.send(reply).await?;
+            }
+            continue;
+         }
+         if message.get("id").and_then(Value::as_u64) != Some(id) {
+            continue;
+         }
+         if let Some(error) = message.get("error") {
+            let detail = error
+               .get("message")
+               .and_then(Value::as_str)
+               .unwrap_or("unknown error");
+            return Err(Failure::Protocol(format!("{} failed: {}", method, detail)));
+         }
+         return message
+            .get("result")
+            .cloned()
+            .ok_or_else(|| Failure::Protocol(format!("{} response has no result", method)));
+      }
+   }
+}
+
+/// The program to start for `command`. Bare names are looked up like agent binaries, so servers
+/// installed with npm or cargo are found even when Athas was launched without the shell's PATH.
+async fn resolve_program(command: &str) -> PathBuf {
+   if command.contains(['/', '\\']) {
+      return PathBuf::from(command);
+   }
+   let name = command.to_string();
+   tokio::task::spawn_blocking(move || find_binary(&name))
+      .await
+      .ok()
+      .flatten()
+      .unwrap_or_else(|| PathBuf::from(command))
+}
+
+/// Keep the last lines the server writes to stderr in `tail`
+fn collect_stderr(
+   stderr: tokio::process::ChildStderr,
+   tail: Arc<Mutex<VecDeque<String>>>,
+) -> tokio::task::JoinHandle<()> {
+   tokio::spawn(async move {
+      let mut lines = BufReader::new(stderr).lines();
+      while let Ok(Some(line)) = lines.next_line().await {
+         let mut tail = tail.lock().unwrap_or_else(|e| e.into_inner());
+         if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+         }
+         tail.push_back(line);
+      }
+   })
+}
+
+fn malformed(what: &str, e: serde_json::Error) -> Failure {
+   Failure::Protocol(format!("malformed {}: {}", what, e))
+}
+
+/// Start the stdio server `server` describes, initialize an MCP session, list its tools and shut
+/// it down, all within `timeout`
+async fn probe(server: &McpServer, timeout: Duration) -> McpServerTest {
+   let command = server.command.clone().unwrap_or_default();
+   let mut test = McpServerTest {
+      ok: false,
+      server_info: None,
+      tools: Vec::new(),
+      stderr_tail: String::new(),
+      error: None,
+   };
+
+   let spawned = Command::new(resolve_program(&command).await)
+      .args(&server.args)
+      .envs(&server.env)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .kill_on_drop(true)
+      .spawn();
+   let mut child = match spawned {
+      Ok(child) => child,
+      Err(e) => {
+         test.error = Some(McpTestFailure::Spawn {
+            command,
+            message: e.to_string(),
+         });
+         return test;
+      }
+   };
+   let tail = Arc::new(Mutex::new(VecDeque::new()));
+   let stderr = child
+      .stderr
+      .take()
+      .map(|stderr| collect_stderr(stderr, tail.clone()));
+   let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+      unreachable!("stdin and stdout are piped");
+   };
+   let mut session = Session {
+      stdin,
+      stdout: BufReader::new(stdout).lines(),
+      next_id: 0,
+   };
+
+   let mut stage = "initialize";
+   let exchange = tokio::time::timeout(timeout, async {
+      let params = json!({
+         "protocolVersion": PROTOCOL_VERSION,
+         "capabilities": {},
+         "clientInfo": { "name": "Athas", "version": env!("CARGO_PKG_VERSION") },
+      });
+      let initialized = session.request("initialize", params).await?;
+      if let Some(info) = initialized.get("serverInfo") {
+         test.server_info =
+            Some(serde_json::from_value(info.clone()).map_err(|e| malformed("serverInfo", e))?);
+      }
+      session.notify("notifications/initialized").await?;
+
+      // Servers without tools don't advertise the capability and needn't answer the request
+      if initialized.pointer("/capabilities/tools").is_none() {
+         return Ok(());
+      }
+      stage = "tools/list";
+      let mut cursor: Option<String> = None;
+      for _ in 0..MAX_TOOL_PAGES {
+         let params = match &cursor {
+            Some(cursor) => json!({ "cursor": cursor }),
+            None => json!({}),
+         };
+         let page = session.request("tools/list", params).await?;
+         let tools: Vec<McpTool> =
+            serde_json::from_value(page.get("tools").cloned().unwrap_or(Value::Null))
+               .map_err(|e| malformed("tool list", e))?;
+         test.tools.extend(tools);
+         cursor = page
+            .get("nextCursor")
+            .and_then(Value::as_str)
+            .map(String::from);
+         if cursor.is_none() {
+            break;
+         }
+      }
+      Ok(())
+   })
+   .await;
+
+   // Closing stdin is how the stdio transport asks a server to exit
+   drop(session);
+   let status = match tokio::time::timeout(SHUTDOWN_GRACE, child.wait()).await {
+      Ok(status) => status.ok(),
+      Err(_) => {
+         let _ = child.kill().await;
+         None
+      }
+   };
+   if let Some(stderr) = stderr {
+      let abort = stderr.abort_handle();
+      if tokio::time::timeout(STDERR_DRAIN, stderr).await.is_err() {
+         abort.abort();
+      }
+   }
+   test.stderr_tail = Vec::from(tail.lock().unwrap_or_else(|e| e.into_inner()).clone()).join("\n");
+
+   let stage = stage.to_string();
+   test.error = match exchange {
+      Ok(Ok(())) => None,
+      Ok(Err(Failure::Exited)) => Some(McpTestFailure::Exited {
+         stage,
+         code: status.and_then(|status| status.code()),
+      }),
+      Ok(Err(Failure::Protocol(message))) => Some(McpTestFailure::Protocol { stage, message }),
+      Err(_) => Some(McpTestFailure::Timeout {
+         stage,
+         timeout_ms: timeout.as_millis() as u64,
+      }),
+   };
+   test.ok = test.error.is_none();
+   test
+}
+
+/// Start an MCP server from its definition, run the initialize handshake over stdio and list the
+/// tools it offers, then shut it down, so a typoed command or a missing variable shows up before
+/// the definition is saved. Keychain references in the env are resolved first. The whole test is
+/// given `timeout_ms`, 10 seconds by default. Only stdio servers can be tested.
+#[command]
+pub async fn test_mcp_server(
+   credentials: State<'_, CredentialStore>,
+   server: McpServer,
+   timeout_ms: Option<u64>,
+) -> Result<McpServerTest, AgentSettingsError> {
+   let server = validate_server(&server)?;
+   if server.transport != McpTransport::Stdio {
+      return Err(AgentSettingsError::InvalidMcpServer {
+         name: server.name,
+         message: "only stdio servers can be tested".to_string(),
+      });
+   }
+   let timeout = timeout_ms
+      .map(Duration::from_millis)
+      .unwrap_or(DEFAULT_TIMEOUT)
+      .min(MAX_TIMEOUT);
+
+   let env = materialize_env(&server, &credentials)?;
+   let test = probe(
+      &McpServer {
+         env,
+         ..server.clone()
+      },
+      timeout,
+   )
+   .await;
+   log::info!(
+      "Tested MCP server {}: ok={} tools={}",
+      server.name,
+      test.ok,
+      test.tools.len()
+   );
+   Ok(test)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+   use super::*;
+   use std::collections::HashMap;
+
+   /// A stdio server running `script` in `sh`
+   fn shell_server(script: &str) -> McpServer {
+      McpServer {
+         name: "fake".into(),
+         command: Some("sh".into()),
+         args: vec!["-c".into(), script.into()],
+         env: HashMap::new(),
+         transport: McpTransport::Stdio,
+         url: None,
+      }
+   }
+
+   const INITIALIZED: &str = r#"{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2025-06-18","capabilities":{"tools":{}},"serverInfo":{"name":"fake","version":"1.0.0"}}}"#;
+
+   #[tokio::test]
+   async fn test_lists_tools() {
+      let tools = r#"{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"search","description":"Search issues","inputSchema":{}},{"name":"$TOOL","inputSchema":{}}]}}"#;
+      let script = format!(
+         "read line; echo '{}'; read line; read line; echo \"starting up\" >&2; echo \
+          '{{\"jsonrpc\":\"2.0\",\"method\":\"notifications/message\"}}'; echo '{}' | sed \
+          \"s/\\$TOOL/$TOOL/\"; read line",
+         INITIALIZED, tools
+      );
+      let server = McpServer {
+         env: HashMap::from([("TOOL".to_string(), "create_issue".to_string())]),
+         ..shell_server(&script)
+      };
+
+      let test = probe(&server, Duration::from_secs(5)).await;
+      assert_eq!(test.error, None);
+      assert!(test.ok);
+      assert_eq!(
+         test.server_info,
+         Some(McpServerInfo {
+            name: "fake".into(),
+            version: Some("1.0.0".into()),
+         })
+      );
+      assert_eq!(
+         test.tools,
+         vec![
+            McpTool {
+               name: "search".into(),
+               description: Some("Search issues".into()),
+            },
+            McpTool {
+               name: "create_issue".into(),
+               description: None,
+            },
+         ]
+      );
+      assert_eq!(test.stderr_tail, "starting up");
+   }
+
+   #[tokio::test]
+   async fn test_failures() {
+      let missing = McpServer {
+         command: Some("athas-no-such-mcp-server".into()),
+         ..shell_server("")
+      };
+      let test = probe(&missing, Duration::from_secs(5)).await;
+      assert!(matches!(test.error, Some(McpTestFailure::Spawn { .. })));
+
+      let exits = shell_server("echo 'GITHUB_TOKEN is not set' >&2; exit 3");
+      let test = probe(&exits, Duration::from_secs(5)).await;
+      assert_eq!(
+         test.error,
+         Some(McpTestFailure::Exited {
+            stage: "initialize".into(),
+            code: Some(3),
+         })
+      );
+      assert_eq!(test.stderr_tail, "GITHUB_TOKEN is not set");
+
+      let hangs = shell_server(&format!("read line; echo '{}'; sleep 5", INITIALIZED));
+      let test = probe(&hangs, Duration::from_millis(300)).await;
+      assert_eq!(
+         test.error,
+         Some(McpTestFailure::Timeout {
+            stage: "tools/list".into(),
+            timeout_ms: 300,
+         })
+      );
+
+      let chatty = shell_server("read line; echo 'Server listening on stdio'; read line");
+      let test = probe(&chatty, Duration::from_secs(5)).await;
+      assert!(matches!(
+         test.error,
+         Some(McpTestFailure::Protocol { ref stage, .. }) if stage == "initialize"
+      ));
+   }
+}
@@ -0,0 +1,357 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   mcp::{
+      McpServer, delete_server, known_agent, load_from, put_server, read_servers, servers_key,
+      translate, validate_server, writable_path,
+   },
+   operations::JournaledOperation,
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   storage::{AgentSettingsLocks, WriteOptions, read_config_file, update_config_file},
+};
+use crate::commands::ai::agent_registry::KnownAgent;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tauri::{State, command};
+
+/// How a sync treats the servers a target already has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum McpSyncMode {
+   /// Targets end up with exactly the source's servers: missing ones are added, differing ones
+   /// replaced and extra ones removed
+   Mirror,
+   /// Missing servers are added. Servers a target already has are never changed or removed.
+   Additive,
+}
+
+/// A source server a target didn't take, and why
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpSkippedServer {
+   pub name: String,
+   pub reason: String,
+}
+
+/// What a sync did, or would do, to one target agent
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpSyncTarget {
+   pub agent_id: String,
+   pub added: Vec<String>,
+   pub updated: Vec<String>,
+   pub removed: Vec<String>,
+   pub unchanged: Vec<String>,
+   pub skipped: Vec<McpSkippedServer>,
+   /// Whether the target's config was written. Always false for a dry run.
+   pub wrote: bool,
+   /// Why the target couldn't be synced. Other targets are synced regardless.
+   pub error: Option<AgentSettingsError>,
+}
+
+/// Work out how `mode` brings the servers in `target`, the target agent's config, in line with
+/// `source`. Each source server is compared in the form the target would read it back in, so
+/// layout differences between agents don't count as changes.
+fn plan(
+   target: &Value,
+   agent: &KnownAgent,
+   source: &[McpServer],
+   mode: McpSyncMode,
+) -> Result<McpSyncTarget, AgentSettingsError> {
+   let servers = servers_key(agent)?;
+   let current: HashMap<String, McpServer> = read_servers(target, servers)
+      .into_iter()
+      .map(|server| (server.name.clone(), server))
+      .collect();
+   let mut sync = McpSyncTarget {
+      agent_id: agent.id.clone(),
+      ..McpSyncTarget::default()
+   };
+   let skipped = |name: &str, reason: String| McpSkippedServer {
+      name: name.to_string(),
+      reason,
+   };
+
+   for server in source {
+      let translated = match validate_server(server) {
+         Ok(server) => translate(servers.format, &server),
+         Err(e) => {
+            sync.skipped.push(skipped(&server.name, e.to_string()));
+            continue;
+         }
+      };
+      let Some(translated) = translated else {
+         let reason = format!("{} can't connect to SSE servers", agent.name);
+         sync.skipped.push(skipped(&server.name, reason));
+         continue;
+      };
+      match current.get(&server.name) {
+         None => sync.added.push(server.name.clone()),
+         Some(existing) if *existing == translated => sync.unchanged.push(server.name.clone()),
+         Some(_) if mode == McpSyncMode::Additive => {
+            let reason = "defined differently in the target, which an additive sync leaves alone";
+            sync.skipped.push(skipped(&server.name, reason.to_string()));
+         }
+         Some(_) => sync.updated.push(server.name.clone()),
+      }
+   }
+
+   if mode == McpSyncMode::Mirror {
+      let names: HashSet<&str> = source.iter().map(|server| server.name.as_str()).collect();
+      let mut removed: Vec<String> = current
+         .into_keys()
+         .filter(|name| !names.contains(name.as_str()))
+         .collect();
+      removed.sort();
+      sync.removed = removed;
+   }
+   Ok(sync)
+}
+
+/// Make the changes `sync` planned to the target config `value`
+fn apply(
+   value: &mut Value,
+   agent: &KnownAgent,
+   source: &[McpServer],
+   sync: &McpSyncTarget,
+) -> Result<(), AgentSettingsError> {
+   let servers = servers_key(agent)?;
+   for server in source {
+      let replace = sync.updated.contains(&server.name);
+      if replace || sync.added.contains(&server.name) {
+         put_server(value, agent, &validate_server(server)?, replace)?;
+      }
+   }
+   for name in &sync.removed {
+      delete_server(value, servers, name)?;
+   }
+   Ok(())
+}
+
+async fn sync_target(
+   locks: &AgentSettingsLocks,
+   roots: &AgentSettingsRoots,
+   history: &AgentSettingsHistory,
+   target_id: &str,
+   source: &[McpServer],
+   mode: McpSyncMode,
+   dry_run: bool,
+) -> Result<McpSyncTarget, AgentSettingsError> {
+   let agent = known_agent(locks, target_id).await?;
+   servers_key(&agent)?;
+   let format = ConfigFormat::from_path(&agent.settings_path);
+   if dry_run {
+      let path = resolve_settings_path(&agent.settings_path)?;
+      let value = read_config_file(locks, &path, format)
+         .await?
+         .map(|file| file.value)
+         .unwrap_or(Value::Null);
+      return plan(&value, &agent, source, mode);
+   }
+
+   let path = writable_path(roots, &agent)?;
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history,
+         agent_id: target_id,
+      }),
+      ..WriteOptions::default()
+   };
+   // Planned against the content read under the write lock, so the plan is what gets written
+   let mut planned = None;
+   let wrote = update_config_file(locks, &path, format, options, |value| {
+      let sync = plan(value, &agent, source, mode)?;
+      apply(value, &agent, source, &sync)?;
+      planned = Some(sync);
+      Ok(())
+   })
+   .await?;
+   let mut sync = planned.unwrap_or_default();
+   sync.wrote = wrote;
+   Ok(sync)
+}
+
+/// Bring the MCP servers of `target_agents` in line with those of `source_agent`, translating
+/// each definition into the layout the target reads, such as Codex's TOML tables from Claude's
+/// JSON objects. With `dry_run`, nothing is written and the results are the plan. A target that
+/// fails reports its error without stopping the others. The targets' files are journaled, so a
+/// sync interrupted halfway can be rolled back with `recover_pending_operations`.
+#[command]
+pub async fn sync_mcp_servers(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   source_agent: String,
+   target_agents: Vec<String>,
+   mode: McpSyncMode,
+   dry_run: Option<bool>,
+) -> Result<Vec<McpSyncTarget>, AgentSettingsError> {
+   let dry_run = dry_run.unwrap_or(false);
+   let source = known_agent(&locks, &source_agent).await?;
+   let source_servers = load_from(
+      &locks,
+      &resolve_settings_path(&source.settings_path)?,
+      ConfigFormat::from_path(&source.settings_path),
+      servers_key(&source)?,
+   )
+   .await?;
+
+   let operation = if dry_run {
+      None
+   } else {
+      // Targets whose file can't be found fail below without writing anything
+      let mut paths = Vec::new();
+      for target_id in &target_agents {
+         if let Ok(agent) = known_agent(&locks, target_id).await
+            && let Ok(path) = writable_path(&roots, &agent)
+            && !paths.contains(&path)
+         {
+            paths.push(path);
+         }
+      }
+      Some(JournaledOperation::begin(&locks, "sync_mcp_servers", &paths).await?)
+   };
+
+   let mut results = Vec::with_capacity(target_agents.len());
+   for target_id in &target_agents {
+      let result = sync_target(
+         &locks,
+         &roots,
+         &history,
+         target_id,
+         &source_servers,
+         mode,
+         dry_run,
+      )
+      .await;
+      results.push(result.unwrap_or_else(|e| McpSyncTarget {
+         agent_id: target_id.clone(),
+         error: Some(e),
+         ..McpSyncTarget::default()
+      }));
+   }
+   if let Some(operation) = operation {
+      operation.complete()?;
+   }
+
+   log::info!(
+      "Synced {} MCP servers from agent {} to {:?} ({:?}, dry run: {})",
+      source_servers.len(),
+      source_agent,
+      target_agents,
+      mode,
+      dry_run
+   );
+   Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::{agent_registry::find_known_agent, agent_settings::mcp::McpTransport};
+   use serde_json::json;
+
+   fn stdio(name: &str, command: &str) -> McpServer {
+      McpServer {
+         name: name.into(),
+         command: Some(command.into()),
+         args: vec!["--stdio".into()],
+         env: HashMap::from([("TOKEN".to_string(), "${TOKEN}".to_string())]),
+         transport: McpTransport::Stdio,
+         url: None,
+      }
+   }
+
+   fn sse(name: &str) -> McpServer {
+      McpServer {
+         name: name.into(),
+         command: None,
+         args: Vec::new(),
+         env: HashMap::new(),
+         transport: McpTransport::Sse,
+         url: Some("https://mcp.example.com/sse".into()),
+      }
+   }
+
+   fn synced(target: &mut Value, agent_id: &str, mode: McpSyncMode) -> McpSyncTarget {
+      let agent = find_known_agent(agent_id).unwrap();
+      let source = vec![
+         stdio("github", "github-mcp"),
+         stdio("linear", "linear-mcp"),
+         sse("docs"),
+      ];
+      let sync = plan(target, &agent, &source, mode).unwrap();
+      apply(target, &agent, &source, &sync).unwrap();
+      sync
+   }
+
+   #[test]
+   fn test_mirror_into_codex() {
+      let mut codex = json!({
+         "model": "gpt-5",
+         "mcp_servers": {
+            "github": { "command": "github-mcp", "args": ["--stdio"], "env": { "TOKEN": "${TOKEN}" } },
+            "linear": { "command": "old-linear", "startup_timeout_sec": 20 },
+            "sentry": { "command": "sentry-mcp" },
+         },
+      });
+      let sync = synced(&mut codex, "codex-cli", McpSyncMode::Mirror);
+      assert_eq!(sync.added, Vec::<String>::new());
+      assert_eq!(sync.updated, vec!["linear"]);
+      assert_eq!(sync.unchanged, vec!["github"]);
+      assert_eq!(sync.removed, vec!["sentry"]);
+      // Codex has no SSE transport
+      assert_eq!(sync.skipped.len(), 1);
+      assert_eq!(sync.skipped[0].name, "docs");
+
+      assert_eq!(
+         codex["mcp_servers"],
+         json!({
+            "github": { "command": "github-mcp", "args": ["--stdio"], "env": { "TOKEN": "${TOKEN}" } },
+            "linear": {
+               "command": "linear-mcp",
+               "args": ["--stdio"],
+               "env": { "TOKEN": "${TOKEN}" },
+               "startup_timeout_sec": 20,
+            },
+         })
+      );
+      // Mirroring again finds nothing to do
+      let again = synced(&mut codex, "codex-cli", McpSyncMode::Mirror);
+      assert!(again.added.is_empty() && again.updated.is_empty() && again.removed.is_empty());
+   }
+
+   #[test]
+   fn test_additive_never_touches_existing() {
+      let mut claude = json!({
+         "mcpServers": {
+            "linear": { "command": "old-linear" },
+            "sentry": { "command": "sentry-mcp" },
+         },
+      });
+      let sync = synced(&mut claude, "claude-code", McpSyncMode::Additive);
+      assert_eq!(sync.added, vec!["github", "docs"]);
+      assert!(sync.updated.is_empty() && sync.removed.is_empty());
+      assert_eq!(sync.skipped[0].name, "linear");
+      assert_eq!(
+         claude["mcpServers"]["linear"],
+         json!({ "command": "old-linear" })
+      );
+      assert_eq!(
+         claude["mcpServers"]["sentry"],
+         json!({ "command": "sentry-mcp" })
+      );
+      assert_eq!(
+         claude["mcpServers"]["docs"],
+         json!({ "type": "sse", "url": "https://mcp.example.com/sse" })
+      );
+
+      let aider = find_known_agent("aider").unwrap();
+      assert!(matches!(
+         plan(&json!({}), &aider, &[], McpSyncMode::Mirror),
+         Err(AgentSettingsError::UnsupportedSetting { .. })
+      ));
+   }
+}
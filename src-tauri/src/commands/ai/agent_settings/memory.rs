@@ -0,0 +1,570 @@
+use super::{
+   error::AgentSettingsError,
+   mcp::known_agent,
+   paths::resolve_settings_path,
+   storage::{AgentSettingsLocks, write_atomic},
+};
+use crate::commands::ai::agent_registry::KnownAgent;
+use serde::{Deserialize, Serialize};
+use std::{
+   fmt, fs,
+   io::ErrorKind,
+   path::{Path, PathBuf},
+};
+use tauri::{State, command};
+
+/// Level of a section added without `#`s in its heading
+const DEFAULT_SECTION_LEVEL: usize = 2;
+
+/// Which of an agent's files to use: the workspace's, such as `CLAUDE.md` at its root, or the
+/// one the agent reads in every project, such as `~/.claude/CLAUDE.md`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentFileScope {
+   #[default]
+   Project,
+   Global,
+}
+
+impl fmt::Display for AgentFileScope {
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      f.write_str(match self {
+         AgentFileScope::Project => "project",
+         AgentFileScope::Global => "global",
+      })
+   }
+}
+
+/// Where a section that isn't in the file yet is added
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemorySectionPosition {
+   /// Before the first section at its level or deeper, below any title and introduction
+   Start,
+   #[default]
+   End,
+}
+
+/// A part of an instructions file under one Markdown heading, including the sections nested
+/// under it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentMemorySection {
+   /// `None` for the text before the first heading
+   pub heading: Option<String>,
+   /// Number of `#`s, 0 for the text before the first heading
+   pub level: usize,
+   /// Line the section starts on, from 1
+   pub line: usize,
+   /// Text between the heading and the next heading at the same level or higher
+   pub content: String,
+}
+
+/// An agent's instructions file, whole and split into sections
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentMemory {
+   pub agent_id: String,
+   pub scope: AgentFileScope,
+   pub path: String,
+   pub exists: bool,
+   pub content: String,
+   pub sections: Vec<AgentMemorySection>,
+}
+
+/// An ATX heading line, with byte offsets of where the line starts and where the next one does
+#[derive(Debug)]
+struct Heading<'a> {
+   level: usize,
+   text: &'a str,
+   start: usize,
+   body_start: usize,
+}
+
+/// Level and text of `line` if it is a heading. Up to three spaces may come before the `#`s,
+/// and a closing run of `#`s isn't part of the text.
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+   let line = line.trim_end_matches(['\n', '\r']);
+   let indent = line.len() - line.trim_start_matches(' ').len();
+   if indent > 3 {
+      return None;
+   }
+   let marker = &line[indent..];
+   let level = marker.len() - marker.trim_start_matches('#').len();
+   let rest = &marker[level..];
+   if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+      return None;
+   }
+   let text = rest.trim();
+   let text = match text.trim_end_matches('#') {
+      stripped if stripped.is_empty() || stripped.ends_with([' ', '\t']) => stripped.trim_end(),
+      _ => text,
+   };
+   Some((level, text))
+}
+
+/// The headings of `content`, skipping `#` lines inside fenced code blocks
+fn headings(content: &str) -> Vec<Heading<'_>> {
+   let mut headings = Vec::new();
+   let mut fence: Option<&str> = None;
+   let mut offset = 0;
+   for line in content.split_inclusive('\n') {
+      let start = offset;
+      offset += line.len();
+      let trimmed = line.trim_start();
+      match fence {
+         Some(marker) => {
+            if trimmed.starts_with(marker) {
+               fence = None;
+            }
+            continue;
+         }
+         None => {
+            if let Some(marker) = ["```", "~~~"]
+               .into_iter()
+               .find(|marker| trimmed.starts_with(marker))
+            {
+               fence = Some(marker);
+               continue;
+            }
+         }
+      }
+      if let Some((level, text)) = parse_heading(line) {
+         headings.push(Heading {
+            level,
+            text,
+            start,
+            body_start: offset,
+         });
+      }
+   }
+   headings
+}
+
+/// Where the section under `headings[index]` ends: at the next heading at its level or higher
+fn section_end(content: &str, headings: &[Heading], index: usize) -> usize {
+   let level = headings[index].level;
+   headings[index + 1..]
+      .iter()
+      .find(|heading| heading.level <= level)
+      .map_or(content.len(), |heading| heading.start)
+}
+
+fn line_of(content: &str, offset: usize) -> usize {
+   content[..offset].matches('\n').count() + 1
+}
+
+fn parse_sections(content: &str) -> Vec<AgentMemorySection> {
+   let headings = headings(content);
+   let preamble_end = headings
+      .first()
+      .map_or(content.len(), |heading| heading.start);
+   let mut sections = Vec::new();
+   if !content[..preamble_end].trim().is_empty() {
+      sections.push(AgentMemorySection {
+         heading: None,
+         level: 0,
+         line: 1,
+         content: content[..preamble_end].to_string(),
+      });
+   }
+   for (index, heading) in headings.iter().enumerate() {
+      let end = section_end(content, &headings, index);
+      sections.push(AgentMemorySection {
+         heading: Some(heading.text.to_string()),
+         level: heading.level,
+         line: line_of(content, heading.start),
+         content: content[heading.body_start.min(end)..end].to_string(),
+      });
+   }
+   sections
+}
+
+/// Level and text of the heading a section is asked for by, such as `## Commands` or `Commands`
+fn requested_heading(heading: &str) -> Result<(usize, &str), AgentSettingsError> {
+   let heading = heading.trim();
+   let (level, text) = match parse_heading(heading) {
+      Some((level, text)) => (level, text),
+      None if heading.starts_with('#') => {
+         return Err(AgentSettingsError::InvalidMemorySection {
+            heading: heading.to_string(),
+            message: "headings have one to six '#'s followed by a space".to_string(),
+         });
+      }
+      None => (DEFAULT_SECTION_LEVEL, heading),
+   };
+   if text.is_empty() || text.contains('\n') {
+      return Err(AgentSettingsError::InvalidMemorySection {
+         heading: heading.to_string(),
+         message: "heading must be one non-empty line".to_string(),
+      });
+   }
+   Ok((level, text))
+}
+
+/// `content` with the section under `heading` holding `body`. An existing section keeps its
+/// heading line and only its body is replaced; text outside it is left byte for byte. A missing
+/// section is added at `position`. Headings are matched ignoring case.
+fn replace_section(
+   content: &str,
+   heading: &str,
+   body: &str,
+   position: MemorySectionPosition,
+) -> Result<String, AgentSettingsError> {
+   let (level, text) = requested_heading(heading)?;
+   let body = body.trim_matches(['\n', '\r']);
+   let headings = headings(content);
+
+   if let Some(index) = headings
+      .iter()
+      .position(|existing| existing.text.eq_ignore_ascii_case(text))
+   {
+      let end = section_end(content, &headings, index);
+      let body_start = headings[index].body_start.min(end);
+      let mut updated = content[..body_start].to_string();
+      if !updated.ends_with('\n') {
+         updated.push('\n');
+      }
+      if !body.is_empty() {
+         updated.push('\n');
+         updated.push_str(body);
+         updated.push('\n');
+      }
+      if end < content.len() {
+         updated.push('\n');
+      }
+      updated.push_str(&content[end..]);
+      return Ok(updated);
+   }
+
+   let mut section = format!("{} {}\n", "#".repeat(level), text);
+   if !body.is_empty() {
+      section.push('\n');
+      section.push_str(body);
+      section.push('\n');
+   }
+   let before = match position {
+      // A title at a higher level keeps its place at the top
+      MemorySectionPosition::Start => headings
+         .iter()
+         .find(|existing| existing.level >= level)
+         .map(|existing| existing.start),
+      MemorySectionPosition::End => None,
+   };
+   Ok(match before {
+      Some(offset) => format!("{}{}\n{}", &content[..offset], section, &content[offset..]),
+      None => {
+         let separator = if content.is_empty() || content.ends_with("\n\n") {
+            ""
+         } else if content.ends_with('\n') {
+            "\n"
+         } else {
+            "\n\n"
+         };
+         format!("{}{}{}", content, separator, section)
+      }
+   })
+}
+
+/// What a new instructions file starts with, before the section it is created for
+fn memory_template(agent: &KnownAgent, path: &Path, scope: AgentFileScope) -> String {
+   let file_name = path
+      .file_name()
+      .map(|name| name.to_string_lossy().into_owned())
+      .unwrap_or_default();
+   let applies_to = match scope {
+      AgentFileScope::Project => "when working in this repository",
+      AgentFileScope::Global => "in every project",
+   };
+   format!(
+      "# {}\n\nInstructions for {} {}.\n",
+      file_name, agent.name, applies_to
+   )
+}
+
+/// The instructions file of `agent` in `scope`
+fn memory_path(
+   agent: &KnownAgent,
+   scope: AgentFileScope,
+   workspace_path: Option<&str>,
+) -> Result<PathBuf, AgentSettingsError> {
+   let no_memory_file = || AgentSettingsError::NoMemoryFile {
+      agent_id: agent.id.clone(),
+      scope,
+   };
+   let memory_files = agent.memory_files.as_ref().ok_or_else(no_memory_file)?;
+   match scope {
+      AgentFileScope::Project => {
+         let workspace_path = workspace_path.ok_or_else(|| AgentSettingsError::InvalidPath {
+            path: String::new(),
+            message: "no workspace was given for the project memory file".to_string(),
+         })?;
+         Ok(Path::new(workspace_path).join(&memory_files.project_path))
+      }
+      AgentFileScope::Global => {
+         let global_path = memory_files
+            .global_path
+            .as_deref()
+            .ok_or_else(no_memory_file)?;
+         resolve_settings_path(global_path)
+      }
+   }
+}
+
+fn read_memory(path: &Path) -> Result<Option<String>, AgentSettingsError> {
+   match fs::read_to_string(path) {
+      Ok(content) => Ok(Some(content)),
+      Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(AgentSettingsError::io(path, e)),
+   }
+}
+
+fn memory_listing(
+   agent_id: &str,
+   scope: AgentFileScope,
+   path: &Path,
+   content: Option<String>,
+) -> AgentMemory {
+   let exists = content.is_some();
+   let content = content.unwrap_or_default();
+   AgentMemory {
+      agent_id: agent_id.to_string(),
+      scope,
+      path: path.display().to_string(),
+      exists,
+      sections: parse_sections(&content),
+      content,
+   }
+}
+
+/// Set the section under `heading` of the file at `path` to `content`, creating the file from
+/// `template` if it doesn't exist. Returns the file's new content.
+async fn update_section_at(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   template: impl FnOnce() -> String,
+   heading: &str,
+   content: &str,
+   position: MemorySectionPosition,
+) -> Result<String, AgentSettingsError> {
+   let lock = locks.lock_for(path).await;
+   let _guard = lock.write().await;
+
+   let original = read_memory(path)?;
+   let base = match &original {
+      Some(original) => original.clone(),
+      None => template(),
+   };
+   let updated = replace_section(&base, heading, content, position)?;
+   if original.as_deref() == Some(updated.as_str()) {
+      return Ok(updated);
+   }
+   if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(|e| AgentSettingsError::io(parent, e))?;
+   }
+   write_atomic(path, &updated)?;
+   locks.record_write(path, &updated).await;
+   Ok(updated)
+}
+
+/// An agent's instructions file (`CLAUDE.md`, `AGENTS.md`, `.cursorrules`, ...) split into
+/// sections by its Markdown headings. A file that doesn't exist yet has no sections.
+#[command]
+pub async fn get_agent_memory(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   scope: Option<AgentFileScope>,
+   workspace_path: Option<String>,
+) -> Result<AgentMemory, AgentSettingsError> {
+   let scope = scope.unwrap_or_default();
+   let agent = known_agent(&locks, &agent_id).await?;
+   let path = memory_path(&agent, scope, workspace_path.as_deref())?;
+   let lock = locks.lock_for(&path).await;
+   let _guard = lock.read().await;
+   Ok(memory_listing(&agent_id, scope, &path, read_memory(&path)?))
+}
+
+/// Replace the body of one section of an agent's instructions file, or add the section at
+/// `position` if the file has none under `heading`. The rest of the file is kept byte for
+/// byte. A missing file is created with a short header naming the agent.
+#[command]
+pub async fn update_agent_memory_section(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   heading: String,
+   content: String,
+   position: Option<MemorySectionPosition>,
+   scope: Option<AgentFileScope>,
+   workspace_path: Option<String>,
+) -> Result<AgentMemory, AgentSettingsError> {
+   let scope = scope.unwrap_or_default();
+   let agent = known_agent(&locks, &agent_id).await?;
+   let path = memory_path(&agent, scope, workspace_path.as_deref())?;
+   let updated = update_section_at(
+      &locks,
+      &path,
+      || memory_template(&agent, &path, scope),
+      &heading,
+      &content,
+      position.unwrap_or_default(),
+   )
+   .await?;
+   log::info!("Updated section '{}' of {}", heading.trim(), path.display());
+   Ok(memory_listing(&agent_id, scope, &path, Some(updated)))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_registry::find_known_agent;
+
+   const MEMORY: &str = "# CLAUDE.md\n\nNotes for agents.\n\n## Commands\n\n- `bun \
+                         dev`\n\n```sh\n# not a heading\n```\n\n### Tests\n\n- `cargo test`\n\n## \
+                         Style   \nThree-space indents. ##\n";
+
+   #[test]
+   fn test_parses_sections() {
+      let sections = parse_sections(MEMORY);
+      let outline: Vec<_> = sections
+         .iter()
+         .map(|section| (section.heading.as_deref(), section.level, section.line))
+         .collect();
+      assert_eq!(
+         outline,
+         vec![
+            (Some("CLAUDE.md"), 1, 1),
+            (Some("Commands"), 2, 5),
+            (Some("Tests"), 3, 13),
+            (Some("Style"), 2, 17),
+         ]
+      );
+      assert!(sections[1].content.contains("### Tests"));
+      assert_eq!(sections[2].content, "\n- `cargo test`\n\n");
+      assert_eq!(sections[3].content, "Three-space indents. ##\n");
+
+      let preamble = parse_sections("Read me first.\n# Title\n");
+      assert_eq!(preamble[0].heading, None);
+      assert_eq!(preamble[0].content, "Read me first.\n");
+      assert!(parse_sections("").is_empty());
+   }
+
+   #[test]
+   fn test_replaces_only_the_section() {
+      let updated = replace_section(
+         MEMORY,
+         "tests",
+         "- `cargo nextest run`\n",
+         Default::default(),
+      )
+      .unwrap();
+      assert_eq!(
+         updated,
+         MEMORY.replace("- `cargo test`", "- `cargo nextest run`")
+      );
+
+      // Replacing a section replaces the sections nested in it
+      let updated =
+         replace_section(MEMORY, "## Commands", "- `bun check`", Default::default()).unwrap();
+      let (before, after) = MEMORY.split_once("## Commands\n").unwrap();
+      let (_, rest) = after.split_once("## Style").unwrap();
+      assert_eq!(
+         updated,
+         format!("{}## Commands\n\n- `bun check`\n\n## Style{}", before, rest)
+      );
+
+      for heading in ["", "####### Deep", "Two\nlines"] {
+         assert!(matches!(
+            replace_section(MEMORY, heading, "x", Default::default()),
+            Err(AgentSettingsError::InvalidMemorySection { .. })
+         ));
+      }
+   }
+
+   #[test]
+   fn test_adds_missing_sections() {
+      let at_end = replace_section(
+         MEMORY,
+         "Architecture",
+         "Tauri app.",
+         MemorySectionPosition::End,
+      )
+      .unwrap();
+      assert_eq!(
+         at_end,
+         format!("{}\n## Architecture\n\nTauri app.\n", MEMORY)
+      );
+
+      let at_start = replace_section(
+         MEMORY,
+         "Architecture",
+         "Tauri app.",
+         MemorySectionPosition::Start,
+      )
+      .unwrap();
+      assert_eq!(
+         at_start,
+         MEMORY.replace(
+            "## Commands",
+            "## Architecture\n\nTauri app.\n\n## Commands"
+         )
+      );
+
+      assert_eq!(
+         replace_section("", "# Title", "", MemorySectionPosition::Start).unwrap(),
+         "# Title\n"
+      );
+      assert_eq!(
+         replace_section("No newline", "Notes", "x", MemorySectionPosition::End).unwrap(),
+         "No newline\n\n## Notes\n\nx\n"
+      );
+   }
+
+   #[tokio::test]
+   async fn test_creates_missing_files_from_the_template() {
+      let dir = tempfile::tempdir().unwrap();
+      let agent = find_known_agent("claude-code").unwrap();
+      let workspace = dir.path().display().to_string();
+      let path = memory_path(&agent, AgentFileScope::Project, Some(&workspace)).unwrap();
+      assert_eq!(path, dir.path().join("CLAUDE.md"));
+      assert!(matches!(
+         memory_path(&agent, AgentFileScope::Project, None),
+         Err(AgentSettingsError::InvalidPath { .. })
+      ));
+      let aider = find_known_agent("aider").unwrap();
+      assert!(matches!(
+         memory_path(&aider, AgentFileScope::Global, None),
+         Err(AgentSettingsError::NoMemoryFile { .. })
+      ));
+
+      let locks = AgentSettingsLocks::new();
+      let template = || memory_template(&agent, &path, AgentFileScope::Project);
+      let created = update_section_at(
+         &locks,
+         &path,
+         template,
+         "Commands",
+         "- `bun dev`",
+         Default::default(),
+      )
+      .await
+      .unwrap();
+      assert_eq!(
+         created,
+         "# CLAUDE.md\n\nInstructions for Claude Code when working in this repository.\n\n## \
+          Commands\n\n- `bun dev`\n"
+      );
+      assert_eq!(fs::read_to_string(&path).unwrap(), created);
+
+      let updated = update_section_at(
+         &locks,
+         &path,
+         template,
+         "Commands",
+         "- `bun check`",
+         Default::default(),
+      )
+      .await
+      .unwrap();
+      assert_eq!(updated, created.replace("bun dev", "bun check"));
+   }
+}
@@ -0,0 +1,265 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   keys::{delete_nested_value, get_nested_value, set_nested_value, validate_key_path},
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   storage::{AgentSettingsLocks, WriteOptions, update_config_file},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{State, command};
+
+/// Built-in conversions a migration can apply to the value it moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationTransform {
+   /// `"High"` becomes `"high"`
+   Lowercase,
+   /// `"true"`, `"yes"`, `"on"` and `"1"` become `true`; `"false"`, `"no"`, `"off"` and `"0"`
+   /// become `false`
+   StringToBool,
+   /// `"Read"` becomes `["Read"]`. Arrays are left as they are.
+   WrapInArray,
+}
+
+impl MigrationTransform {
+   fn apply(self, value: Value) -> Result<Value, String> {
+      match (self, value) {
+         (MigrationTransform::Lowercase, Value::String(text)) => {
+            Ok(Value::String(text.to_lowercase()))
+         }
+         (MigrationTransform::StringToBool, Value::String(text)) => {
+            match text.trim().to_lowercase().as_str() {
+               "true" | "yes" | "on" | "1" => Ok(Value::Bool(true)),
+               "false" | "no" | "off" | "0" => Ok(Value::Bool(false)),
+               _ => Err(format!("'{}' is not a boolean", text)),
+            }
+         }
+         (MigrationTransform::WrapInArray, value @ Value::Array(_)) => Ok(value),
+         (MigrationTransform::WrapInArray, value) => Ok(Value::Array(vec![value])),
+         (_, value) => Err(format!("expected a string, found {}", value)),
+      }
+   }
+}
+
+/// Move the value at `from_key` to `to_key`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationStep {
+   pub from_key: String,
+   pub to_key: String,
+   pub transform: Option<MigrationTransform>,
+   /// Replace a value already at `to_key` instead of skipping the step
+   #[serde(default)]
+   pub overwrite: bool,
+}
+
+/// What happened to one step of a migration
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum MigrationStepStatus {
+   Applied,
+   /// Nothing to move: `from_key` is not set
+   SourceMissing,
+   /// `to_key` already has a value and the step doesn't overwrite
+   DestinationExists,
+   /// The transform can't convert the value, which is left where it was
+   TransformFailed {
+      message: String,
+   },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationStepReport {
+   pub from_key: String,
+   pub to_key: String,
+   #[serde(flatten)]
+   pub status: MigrationStepStatus,
+}
+
+/// Apply `steps` in order to a document. Each step sees the result of the ones before it.
+fn migrate(
+   document: &mut Value,
+   steps: Vec<MigrationStep>,
+) -> Result<Vec<MigrationStepReport>, AgentSettingsError> {
+   let mut reports = Vec::new();
+   for step in steps {
+      let status = match get_nested_value(document, &step.from_key).cloned() {
+         None => MigrationStepStatus::SourceMissing,
+         Some(_) if !step.overwrite && get_nested_value(document, &step.to_key).is_some() => {
+            MigrationStepStatus::DestinationExists
+         }
+         Some(value) => match step.transform.map_or(Ok(value.clone()), |t| t.apply(value)) {
+            Err(message) => MigrationStepStatus::TransformFailed { message },
+            Ok(value) => {
+               delete_nested_value(document, &step.from_key, true)?;
+               set_nested_value(document, &step.to_key, value)?;
+               MigrationStepStatus::Applied
+            }
+         },
+      };
+      reports.push(MigrationStepReport {
+         from_key: step.from_key,
+         to_key: step.to_key,
+         status,
+      });
+   }
+   Ok(reports)
+}
+
+/// Move settings from old keys to new ones after an agent renames them, such as flat keys that
+/// moved under a table. All steps land in one atomic write, and the report says which were
+/// applied and why the others were skipped.
+#[command]
+pub async fn migrate_agent_settings(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   settings_path: String,
+   migrations: Vec<MigrationStep>,
+   follow_symlinks: Option<bool>,
+) -> Result<Vec<MigrationStepReport>, AgentSettingsError> {
+   migrations
+      .iter()
+      .flat_map(|step| [&step.from_key, &step.to_key])
+      .try_for_each(|key| validate_key_path(key))?;
+   let path = roots.check(
+      &resolve_settings_path(&settings_path)?,
+      follow_symlinks.unwrap_or(false),
+   )?;
+   if !path.exists() {
+      return migrate(&mut Value::Null, migrations);
+   }
+
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &agent_id,
+      }),
+      ..WriteOptions::default()
+   };
+   let mut reports = Vec::new();
+   update_config_file(
+      &locks,
+      &path,
+      ConfigFormat::from_path(&settings_path),
+      options,
+      |value| {
+         reports = migrate(value, migrations)?;
+         Ok(())
+      },
+   )
+   .await?;
+
+   let applied = reports
+      .iter()
+      .filter(|report| report.status == MigrationStepStatus::Applied)
+      .count();
+   log::info!(
+      "Migrated settings for agent {}: {} of {} steps applied",
+      agent_id,
+      applied,
+      reports.len()
+   );
+   Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   fn step(from_key: &str, to_key: &str, transform: Option<MigrationTransform>) -> MigrationStep {
+      MigrationStep {
+         from_key: from_key.into(),
+         to_key: to_key.into(),
+         transform,
+         overwrite: false,
+      }
+   }
+
+   #[test]
+   fn test_migration_moves_and_reports() {
+      let mut document = json!({
+         "model_reasoning_effort": "High",
+         "hide_agent_reasoning": "yes",
+         "sandbox": { "mode": "workspace-write" },
+         "tools": { "web_search": true },
+      });
+
+      let reports = migrate(
+         &mut document,
+         vec![
+            step(
+               "model_reasoning_effort",
+               "reasoning.effort",
+               Some(MigrationTransform::Lowercase),
+            ),
+            step(
+               "hide_agent_reasoning",
+               "reasoning.hidden",
+               Some(MigrationTransform::StringToBool),
+            ),
+            step("sandbox.mode", "sandbox_mode", None),
+            step("missing", "anything", None),
+            step(
+               "tools.web_search",
+               "reasoning.effort",
+               Some(MigrationTransform::WrapInArray),
+            ),
+         ],
+      )
+      .unwrap();
+
+      assert_eq!(
+         document,
+         json!({
+            "reasoning": { "effort": "high", "hidden": true },
+            "sandbox_mode": "workspace-write",
+            "tools": { "web_search": true },
+         })
+      );
+      let statuses: Vec<_> = reports.into_iter().map(|report| report.status).collect();
+      assert_eq!(
+         statuses,
+         vec![
+            MigrationStepStatus::Applied,
+            MigrationStepStatus::Applied,
+            MigrationStepStatus::Applied,
+            MigrationStepStatus::SourceMissing,
+            MigrationStepStatus::DestinationExists,
+         ]
+      );
+   }
+
+   #[test]
+   fn test_failed_transform_leaves_value_in_place() {
+      let mut document = json!({ "preview": "sometimes" });
+      let mut overwrite = step(
+         "preview",
+         "features.preview",
+         Some(MigrationTransform::StringToBool),
+      );
+      overwrite.overwrite = true;
+
+      let reports = migrate(&mut document, vec![overwrite]).unwrap();
+
+      assert_eq!(document, json!({ "preview": "sometimes" }));
+      assert!(matches!(
+         reports[0].status,
+         MigrationStepStatus::TransformFailed { .. }
+      ));
+      assert_eq!(
+         serde_json::to_value(&reports[0]).unwrap(),
+         json!({
+            "fromKey": "preview",
+            "toKey": "features.preview",
+            "status": "transformFailed",
+            "message": "'sometimes' is not a boolean",
+         })
+      );
+   }
+}
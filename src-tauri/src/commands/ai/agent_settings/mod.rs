@@ -0,0 +1,99 @@
+mod backup;
+mod bundle;
+mod codex_profiles;
+mod copy;
+mod custom_agents;
+mod custom_providers;
+mod defaults;
+mod detect;
+mod doctor;
+mod editor_import;
+mod enabled;
+mod encryption;
+mod error;
+mod explore;
+mod format;
+mod history;
+mod interpolate;
+mod keys;
+mod layers;
+mod mcp;
+mod mcp_import;
+mod mcp_probe;
+mod mcp_sync;
+mod memory;
+mod migrate;
+mod models;
+mod network;
+mod ollama;
+mod operations;
+mod patch;
+mod paths;
+mod platform;
+mod preview;
+mod pricing;
+mod prompt_render;
+mod prompts;
+mod provider_credentials;
+mod recover;
+mod reset;
+mod secret_migration;
+mod secrets;
+mod settings;
+mod slash_commands;
+mod storage;
+mod version;
+mod watcher;
+mod write_buffer;
+
+pub use backup::*;
+pub use bundle::{export_agent_settings_bundle, import_agent_settings_bundle};
+pub use codex_profiles::{get_codex_profile, list_codex_profiles, set_active_codex_profile};
+pub use copy::copy_agent_settings;
+pub use custom_agents::{add_custom_agent, remove_custom_agent, update_custom_agent};
+pub use custom_providers::{
+   add_custom_provider, find_custom_provider, list_custom_providers, remove_custom_provider,
+   update_custom_provider,
+};
+pub use defaults::{get_agent_defaults, get_agent_settings_with_defaults, set_agent_default};
+pub use detect::detect_installed_agents;
+pub use doctor::diagnose_agent;
+pub use editor_import::import_external_editor_config;
+pub use enabled::{list_known_agents, read_athas_setting, set_agent_enabled};
+pub use encryption::{
+   ConfigEncryption, encrypt_athas_config, get_athas_config_encryption, set_athas_config_encryption,
+};
+pub use explore::{list_agent_config_keys, search_agent_configs};
+pub use history::{AgentSettingsHistory, get_agent_settings_history, undo_agent_settings_change};
+pub use layers::*;
+pub use mcp::{
+   add_mcp_server, list_mcp_servers, materialize_mcp_env, remove_mcp_server, update_mcp_server,
+};
+pub use mcp_import::import_mcp_servers_from_claude_desktop;
+pub use mcp_probe::test_mcp_server;
+pub use mcp_sync::sync_mcp_servers;
+pub use memory::{get_agent_memory, update_agent_memory_section};
+pub use migrate::migrate_agent_settings;
+pub use models::list_agent_models;
+pub use network::{get_network_config, load_network_config, set_network_config};
+pub use ollama::configure_agent_for_ollama;
+pub use operations::{
+   announce_pending_operations, list_pending_operations, recover_pending_operations,
+};
+pub use patch::patch_agent_settings;
+pub use paths::{AgentSettingsRoots, resolve_agent_config_path};
+pub use preview::preview_agent_settings_change;
+pub use pricing::{estimate_cost, estimate_usage, get_pricing_table, pricing_table};
+pub use prompt_render::render_prompt_template;
+pub use prompts::{delete_prompt_template, list_prompt_templates, save_prompt_template};
+pub use provider_credentials::resolve_provider_credential;
+pub use recover::recover_agent_settings;
+pub use reset::reset_agent_settings;
+pub use secret_migration::{migrate_secret_to_keychain, scan_agent_configs_for_secrets};
+pub use secrets::redact_secrets;
+pub use settings::*;
+pub use slash_commands::{delete_agent_command, list_agent_commands, save_agent_command};
+pub use storage::{AgentSettingsLocks, write_atomic};
+pub use version::get_agent_version;
+pub use watcher::*;
+pub use write_buffer::{SettingsWriteBuffer, flush_pending_settings_writes, flush_pending_writes};
@@ -0,0 +1,188 @@
+use super::{
+   custom_agents::find_agent,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   paths::get_home_dir,
+   storage::{AgentSettingsLocks, read_config_file},
+};
+use crate::commands::ai::{
+   agent_registry::KnownAgent,
+   model_catalog::{AgentModel, ModelProvider, provider_models, push_unique},
+};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tauri::{State, command};
+
+/// Where user-added models are kept, relative to the home directory
+const USER_MODELS_FILE: &str = ".athas/models.toml";
+
+/// A `[[models]]` entry of the user models file. Without `agentId` or `provider` the model is
+/// offered for every agent.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserModel {
+   #[serde(flatten)]
+   model: AgentModel,
+   agent_id: Option<String>,
+   provider: Option<ModelProvider>,
+}
+
+impl UserModel {
+   fn applies_to(&self, agent: &KnownAgent) -> bool {
+      let agent_matches = self.agent_id.as_ref().is_none_or(|id| *id == agent.id);
+      let provider_matches = self
+         .provider
+         .is_none_or(|provider| agent.providers.contains(&provider));
+      agent_matches && provider_matches
+   }
+}
+
+/// User-added models in the file at `path`. Entries that don't parse are skipped with a warning.
+async fn load_user_models(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+) -> Result<Vec<UserModel>, AgentSettingsError> {
+   let Some(file) = read_config_file(locks, path, ConfigFormat::Toml).await? else {
+      return Ok(Vec::new());
+   };
+   let Some(entries) = file
+      .value
+      .get("models")
+      .and_then(|models| models.as_array())
+   else {
+      return Ok(Vec::new());
+   };
+
+   Ok(entries
+      .iter()
+      .filter_map(
+         |entry| match serde_json::from_value::<UserModel>(entry.clone()) {
+            Ok(model) => Some(model),
+            Err(e) => {
+               log::warn!("Skipping model entry in {}: {}", path.display(), e);
+               None
+            }
+         },
+      )
+      .collect())
+}
+
+/// The agent's own models, then its providers' catalogs, then user models. A user model with
+/// the id of a listed one replaces it.
+fn build_catalog(agent: &KnownAgent, user_models: Vec<UserModel>) -> Vec<AgentModel> {
+   let mut catalog = Vec::new();
+   let shipped = agent
+      .models
+      .iter()
+      .cloned()
+      .chain(agent.providers.iter().flat_map(|&p| provider_models(p)));
+   for model in shipped {
+      push_unique(&mut catalog, model);
+   }
+
+   for user_model in user_models
+      .into_iter()
+      .filter(|user_model| user_model.applies_to(agent))
+   {
+      match catalog.iter_mut().find(|m| m.id == user_model.model.id) {
+         Some(existing) => *existing = user_model.model,
+         None => catalog.push(user_model.model),
+      }
+   }
+   catalog
+}
+
+async fn catalog_from(
+   locks: &AgentSettingsLocks,
+   agent: &KnownAgent,
+   user_models_path: &Path,
+) -> Result<Vec<AgentModel>, AgentSettingsError> {
+   Ok(build_catalog(
+      agent,
+      load_user_models(locks, user_models_path).await?,
+   ))
+}
+
+fn user_models_path() -> Result<PathBuf, AgentSettingsError> {
+   Ok(get_home_dir()?.join(USER_MODELS_FILE))
+}
+
+/// Warning for a model id the agent's catalog doesn't list. Agents can accept ids Athas doesn't
+/// know, so this never fails the write.
+pub(super) async fn check_model(
+   locks: &AgentSettingsLocks,
+   agent: &KnownAgent,
+   model: &str,
+) -> Result<Option<String>, AgentSettingsError> {
+   let catalog = catalog_from(locks, agent, &user_models_path()?).await?;
+   Ok((!catalog.iter().any(|m| m.id == model))
+      .then(|| format!("'{}' is not a known model for {}", model, agent.name)))
+}
+
+/// List the models an agent can be configured with: built-in catalogs for its providers, models
+/// from a custom agent's definition, and user-added models from `~/.athas/models.toml`
+#[command]
+pub async fn list_agent_models(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+) -> Result<Vec<AgentModel>, AgentSettingsError> {
+   let Some(agent) = find_agent(&locks, &agent_id).await? else {
+      return Err(AgentSettingsError::UnknownAgent { agent_id });
+   };
+   catalog_from(&locks, &agent, &user_models_path()?).await
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_registry::find_known_agent;
+
+   #[tokio::test]
+   async fn test_user_models_merge_into_catalog() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("models.toml");
+      std::fs::write(
+         &path,
+         r#"
+[[models]]
+id = "gpt-5"
+displayName = "GPT-5 (team proxy)"
+contextWindow = 272000
+supportsReasoning = true
+
+[[models]]
+id = "local-llama"
+displayName = "Local Llama"
+agentId = "codex-cli"
+
+[[models]]
+id = "gemini-exp"
+displayName = "Gemini Experimental"
+provider = "google"
+
+[[models]]
+displayName = "Missing id"
+"#,
+      )
+      .unwrap();
+      let locks = AgentSettingsLocks::new();
+      let codex = find_known_agent("codex-cli").unwrap();
+
+      let catalog = catalog_from(&locks, &codex, &path).await.unwrap();
+      let ids: Vec<_> = catalog.iter().map(|model| model.id.as_str()).collect();
+
+      assert_eq!(ids.first(), Some(&"gpt-5-codex"));
+      assert!(ids.contains(&"local-llama"));
+      assert!(!ids.contains(&"gemini-exp"));
+      assert!(!ids.iter().any(|id| id.starts_with("claude")));
+      let gpt5 = catalog.iter().find(|model| model.id == "gpt-5").unwrap();
+      assert_eq!(gpt5.display_name, "GPT-5 (team proxy)");
+      assert_eq!(gpt5.context_window, Some(272_000));
+
+      let missing = dir.path().join("none.toml");
+      let claude = find_known_agent("claude-code").unwrap();
+      let catalog = catalog_from(&locks, &claude, &missing).await.unwrap();
+      assert_eq!(catalog[0].id, "opus");
+      assert!(catalog.iter().any(|model| model.id == "claude-sonnet-4-5"));
+   }
+}
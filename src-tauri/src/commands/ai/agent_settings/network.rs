@@ -0,0 +1,259 @@
+use super::{
+   enabled::athas_settings_path,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   keys::get_nested_value,
+   storage::{AgentSettingsLocks, WriteOptions, read_config_file, update_config_file},
+};
+use crate::commands::ai::providers::{
+   NetworkConfig, ProviderError, ProviderHttpClient, RetrySettings,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{path::Path, time::Duration};
+use tauri::{State, command};
+
+/// Table of `~/.athas/settings.toml` the explicit network settings are kept in
+const NETWORK_TABLE: &str = "network";
+const MAX_RETRIES_KEY: &str = "ai.network.maxRetries";
+const MAX_BACKOFF_KEY: &str = "ai.network.maxBackoffMs";
+/// More retries than this would keep a failing request going for minutes
+const MAX_RETRIES_LIMIT: u64 = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkSettings {
+   /// What the user has set
+   pub overrides: NetworkConfig,
+   /// What requests use: the overrides, with unset proxies taken from the environment
+   pub effective: NetworkConfig,
+}
+
+impl NetworkSettings {
+   fn of(http: &ProviderHttpClient) -> Self {
+      let overrides = http.overrides();
+      Self {
+         effective: overrides.effective(),
+         overrides,
+      }
+   }
+}
+
+async fn load_overrides(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+) -> Result<NetworkConfig, AgentSettingsError> {
+   let Some(file) = read_config_file(locks, path, ConfigFormat::Toml).await? else {
+      return Ok(NetworkConfig::default());
+   };
+   let Some(table) = file.value.get(NETWORK_TABLE) else {
+      return Ok(NetworkConfig::default());
+   };
+   serde_json::from_value(table.clone()).map_err(|e| AgentSettingsError::Parse {
+      format: ConfigFormat::Toml,
+      message: format!("[{}] in {}: {}", NETWORK_TABLE, path.display(), e),
+      line: None,
+      column: None,
+      snippet: None,
+   })
+}
+
+/// The retry settings under `ai.network`. Values that aren't usable are logged and the defaults
+/// used instead.
+fn retry_settings(value: &Value) -> RetrySettings {
+   let mut settings = RetrySettings::default();
+   let number = |key: &str, max: u64| {
+      let value = get_nested_value(value, key)?;
+      match value.as_u64().filter(|number| *number <= max) {
+         Some(number) => Some(number),
+         None => {
+            log::warn!(
+               "Ignoring {} = {}: expected an integer up to {}",
+               key,
+               value,
+               max
+            );
+            None
+         }
+      }
+   };
+   if let Some(retries) = number(MAX_RETRIES_KEY, MAX_RETRIES_LIMIT) {
+      settings.max_retries = Some(retries as u32);
+   }
+   if let Some(backoff) = number(MAX_BACKOFF_KEY, u32::MAX as u64) {
+      settings.max_backoff = Duration::from_millis(backoff);
+   }
+   settings
+}
+
+async fn load_retry_settings(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+) -> Result<RetrySettings, AgentSettingsError> {
+   let file = read_config_file(locks, path, ConfigFormat::Toml).await?;
+   Ok(file.map_or_else(RetrySettings::default, |file| retry_settings(&file.value)))
+}
+
+async fn save_overrides(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   config: &NetworkConfig,
+) -> Result<bool, AgentSettingsError> {
+   let table = serde_json::to_value(config.normalized()).unwrap_or_default();
+   update_config_file(
+      locks,
+      path,
+      ConfigFormat::Toml,
+      WriteOptions::default(),
+      |value| {
+         let Value::Object(settings) = value else {
+            *value = serde_json::json!({ NETWORK_TABLE: table });
+            return Ok(());
+         };
+         if config.is_empty() {
+            settings.shift_remove(NETWORK_TABLE);
+         } else {
+            settings.insert(NETWORK_TABLE.to_string(), table);
+         }
+         Ok(())
+      },
+   )
+   .await
+}
+
+fn invalid_config(error: ProviderError) -> AgentSettingsError {
+   match error {
+      ProviderError::InvalidNetworkConfig { setting, message } => {
+         AgentSettingsError::InvalidNetworkConfig { setting, message }
+      }
+      other => AgentSettingsError::InvalidNetworkConfig {
+         setting: NETWORK_TABLE.to_string(),
+         message: other.to_string(),
+      },
+   }
+}
+
+/// Apply the network and retry settings saved in `~/.athas/settings.toml` to the shared client.
+/// Settings that can't be used are logged and the environment's or the defaults used instead, so
+/// a bad proxy URL can't keep Athas from starting.
+pub async fn load_network_config(locks: &AgentSettingsLocks, http: &ProviderHttpClient) {
+   let path = match athas_settings_path() {
+      Ok(path) => path,
+      Err(e) => {
+         log::warn!("Ignoring saved network settings: {}", e);
+         return;
+      }
+   };
+   match load_retry_settings(locks, &path).await {
+      Ok(retry) => http.set_retry_settings(retry),
+      Err(e) => log::warn!("Ignoring saved retry settings: {}", e),
+   }
+   let result = load_overrides(locks, &path).await;
+   match result.and_then(|config| http.configure(config).map_err(invalid_config)) {
+      Ok(()) => {}
+      Err(e) => log::warn!("Ignoring saved network settings: {}", e),
+   }
+}
+
+/// The proxy and CA settings provider requests are made with, as set by the user and as in
+/// effect once environment variables are taken into account
+#[command]
+pub fn get_network_config(http: State<'_, ProviderHttpClient>) -> NetworkSettings {
+   NetworkSettings::of(&http)
+}
+
+/// Set the proxy and CA settings for provider requests, saving them to `~/.athas/settings.toml`.
+/// Unset settings fall back to `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY`. Settings that can't be
+/// used, such as a malformed proxy URL or a CA bundle without certificates, are an
+/// `InvalidNetworkConfig` error and nothing is saved. The retry settings under `ai.network` are
+/// read again too.
+#[command]
+pub async fn set_network_config(
+   locks: State<'_, AgentSettingsLocks>,
+   http: State<'_, ProviderHttpClient>,
+   config: NetworkConfig,
+) -> Result<NetworkSettings, AgentSettingsError> {
+   http.configure(config.clone()).map_err(invalid_config)?;
+   let path = athas_settings_path()?;
+   if save_overrides(&locks, &path, &config).await? {
+      log::info!("Updated network settings");
+   }
+   http.set_retry_settings(load_retry_settings(&locks, &path).await?);
+   Ok(NetworkSettings::of(&http))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[tokio::test]
+   async fn test_overrides_round_trip() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.toml");
+      std::fs::write(&path, "[agents.aider]\nenabled = false\n").unwrap();
+      let locks = AgentSettingsLocks::new();
+      assert!(load_overrides(&locks, &path).await.unwrap().is_empty());
+
+      let config = NetworkConfig {
+         https_proxy: Some("http://corp-proxy:8080".into()),
+         no_proxy: Some("localhost,.corp".into()),
+         ca_bundle_path: Some(" ".into()),
+         ..NetworkConfig::default()
+      };
+      assert!(save_overrides(&locks, &path, &config).await.unwrap());
+      let content = std::fs::read_to_string(&path).unwrap();
+      assert!(content.contains("[network]"));
+      assert!(content.contains("httpsProxy = \"http://corp-proxy:8080\""));
+      assert!(!content.contains("caBundlePath"));
+      assert!(content.contains("enabled = false"));
+      assert_eq!(
+         load_overrides(&locks, &path).await.unwrap(),
+         config.normalized()
+      );
+
+      assert!(
+         save_overrides(&locks, &path, &NetworkConfig::default())
+            .await
+            .unwrap()
+      );
+      let content = std::fs::read_to_string(&path).unwrap();
+      assert!(!content.contains("network"));
+      assert!(content.contains("enabled = false"));
+   }
+
+   #[test]
+   fn test_retry_settings() {
+      let value = serde_json::json!({
+         "ai": { "network": { "maxRetries": 5, "maxBackoffMs": 2000 } },
+      });
+      assert_eq!(
+         retry_settings(&value),
+         RetrySettings {
+            max_retries: Some(5),
+            max_backoff: Duration::from_millis(2000),
+         }
+      );
+      assert_eq!(
+         retry_settings(&serde_json::json!({ "network": {} })),
+         RetrySettings::default()
+      );
+      let value = serde_json::json!({
+         "ai": { "network": { "maxRetries": -1, "maxBackoffMs": "2s" } },
+      });
+      assert_eq!(retry_settings(&value), RetrySettings::default());
+      let value = serde_json::json!({ "ai": { "network": { "maxRetries": 0 } } });
+      assert_eq!(retry_settings(&value).max_retries, Some(0));
+   }
+
+   #[tokio::test]
+   async fn test_invalid_saved_table() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.toml");
+      std::fs::write(&path, "[network]\nhttpsProxy = 8080\n").unwrap();
+      let locks = AgentSettingsLocks::new();
+      assert!(matches!(
+         load_overrides(&locks, &path).await,
+         Err(AgentSettingsError::Parse { .. })
+      ));
+   }
+}
@@ -0,0 +1,187 @@
+use super::{
+   custom_agents::find_agent,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   keys::get_nested_value,
+   patch::{ArrayStrategy, WriteMode},
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   settings::apply_values,
+   storage::{AgentSettingsLocks, WriteOptions, update_config_file},
+};
+use crate::commands::ai::{agent_registry::KnownAgent, providers::ollama_base_url};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use tauri::{State, command};
+
+/// Variable aider reads the Ollama server's address from
+const AIDER_OLLAMA_ENV: &str = "OLLAMA_API_BASE";
+
+/// The values that point `agent` at `model` on the Ollama server at `base_url`, given the
+/// agent's current config. `None` for agents that can't use Ollama.
+fn ollama_values(
+   agent: &KnownAgent,
+   config: &Value,
+   base_url: &str,
+   model: &str,
+) -> Option<HashMap<String, Value>> {
+   let openai_url = format!("{}/v1", base_url);
+   let values = match agent.id.as_str() {
+      "codex-cli" => HashMap::from([
+         (agent.model_key.clone(), json!(model)),
+         ("model_provider".to_string(), json!("ollama")),
+         (
+            "model_providers.ollama".to_string(),
+            json!({ "name": "Ollama", "base_url": openai_url }),
+         ),
+      ]),
+      "opencode" => HashMap::from([
+         (agent.model_key.clone(), json!(format!("ollama/{}", model))),
+         (
+            "provider.ollama".to_string(),
+            json!({
+               "npm": "@ai-sdk/openai-compatible",
+               "name": "Ollama",
+               "options": { "baseURL": openai_url },
+               "models": { model: { "name": model } },
+            }),
+         ),
+      ]),
+      "aider" => {
+         // Other variables aider sets are kept, and an earlier Ollama address replaced
+         let prefix = format!("{}=", AIDER_OLLAMA_ENV);
+         let mut env: Vec<Value> = get_nested_value(config, "set-env")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|entry| {
+               !entry
+                  .as_str()
+                  .is_some_and(|entry| entry.starts_with(&prefix))
+            })
+            .cloned()
+            .collect();
+         env.push(json!(format!("{}{}", prefix, base_url)));
+         HashMap::from([
+            (
+               agent.model_key.clone(),
+               json!(format!("ollama_chat/{}", model)),
+            ),
+            ("set-env".to_string(), Value::Array(env)),
+         ])
+      }
+      _ => return None,
+   };
+   Some(values)
+}
+
+/// Point an agent at a model served by the local Ollama server, writing the server's address and
+/// the model into the agent's config. Codex CLI, aider and OpenCode are supported; other agents
+/// are an `UnsupportedSetting` error. Returns the keys whose value changed.
+#[command]
+pub async fn configure_agent_for_ollama(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   model: String,
+   port: Option<u16>,
+) -> Result<Vec<String>, AgentSettingsError> {
+   let Some(agent) = find_agent(&locks, &agent_id).await? else {
+      return Err(AgentSettingsError::UnknownAgent { agent_id });
+   };
+   let path = roots.check(&resolve_settings_path(&agent.settings_path)?, false)?;
+   let format = ConfigFormat::from_path(&agent.settings_path);
+   let base_url = ollama_base_url(port);
+
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &agent_id,
+      }),
+      ..WriteOptions::default()
+   };
+   let mut changed = Vec::new();
+   update_config_file(&locks, &path, format, options, |value| {
+      let values = ollama_values(&agent, value, &base_url, &model).ok_or_else(|| {
+         AgentSettingsError::UnsupportedSetting {
+            agent_id: agent.id.clone(),
+            setting: "ollama".to_string(),
+            message: format!("{} can't be pointed at an Ollama server", agent.name),
+         }
+      })?;
+      changed = apply_values(value, values, WriteMode::Merge, ArrayStrategy::Replace)?;
+      Ok(())
+   })
+   .await?;
+
+   log::info!(
+      "Configured agent {} for Ollama model {}: changed={:?}",
+      agent_id,
+      model,
+      changed
+   );
+   Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_registry::find_known_agent;
+
+   fn configure(agent_id: &str, mut config: Value) -> Value {
+      let agent = find_known_agent(agent_id).unwrap();
+      let values =
+         ollama_values(&agent, &config, "http://localhost:11434", "qwen2.5-coder").unwrap();
+      apply_values(
+         &mut config,
+         values,
+         WriteMode::Merge,
+         ArrayStrategy::Replace,
+      )
+      .unwrap();
+      config
+   }
+
+   #[test]
+   fn test_values_per_agent() {
+      assert_eq!(
+         configure("codex-cli", json!({ "model": "gpt-5" })),
+         json!({
+            "model": "qwen2.5-coder",
+            "model_provider": "ollama",
+            "model_providers": {
+               "ollama": { "name": "Ollama", "base_url": "http://localhost:11434/v1" },
+            },
+         })
+      );
+
+      let opencode = configure(
+         "opencode",
+         json!({ "provider": { "ollama": { "models": { "llama3.1:8b": {} } } } }),
+      );
+      assert_eq!(opencode["model"], "ollama/qwen2.5-coder");
+      assert_eq!(
+         opencode["provider"]["ollama"]["options"]["baseURL"],
+         "http://localhost:11434/v1"
+      );
+      let models = opencode["provider"]["ollama"]["models"]
+         .as_object()
+         .unwrap();
+      assert!(models.contains_key("llama3.1:8b") && models.contains_key("qwen2.5-coder"));
+
+      assert_eq!(
+         configure(
+            "aider",
+            json!({ "set-env": ["OLLAMA_API_BASE=http://other:11434", "AIDER_DARK_MODE=true"] }),
+         ),
+         json!({
+            "model": "ollama_chat/qwen2.5-coder",
+            "set-env": ["AIDER_DARK_MODE=true", "OLLAMA_API_BASE=http://localhost:11434"],
+         })
+      );
+
+      let claude = find_known_agent("claude-code").unwrap();
+      assert!(ollama_values(&claude, &json!({}), "http://localhost:11434", "llama3").is_none());
+   }
+}
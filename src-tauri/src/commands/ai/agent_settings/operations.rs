@@ -0,0 +1,392 @@
+use super::{
+   backup::{remove_created, was_created},
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   paths::get_home_dir,
+   storage::{AgentSettingsLocks, content_hash, write_atomic},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+   fs, io,
+   path::{Path, PathBuf},
+};
+use tauri::{AppHandle, Emitter, State, command};
+
+/// Event emitted on startup when multi-file operations were interrupted before they finished,
+/// carrying the pending operations so the user can choose to roll them back or keep the files
+pub const AGENT_SETTINGS_INTERRUPTED_EVENT: &str = "agent-settings-operations-interrupted";
+
+/// Directory inside `~/.athas` the intent records of multi-file operations are kept in
+const JOURNAL_DIR: &str = "journal";
+
+/// One file a multi-file operation may write
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournaledFile {
+   pub path: String,
+   /// Hash of the content before the operation, `None` when the file didn't exist
+   pub original_hash: Option<String>,
+   /// Copy of the content before the operation, `None` when the file didn't exist
+   pub backup_path: Option<String>,
+}
+
+/// Intent record of a multi-file operation, written before its first write and removed once
+/// its last write has landed. A record that is still there on startup means the operation was
+/// interrupted, and the files it lists may be half updated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingOperation {
+   pub id: String,
+   /// What the operation was, such as `sync_mcp_servers`
+   pub operation: String,
+   pub started_at: DateTime<Utc>,
+   pub files: Vec<JournaledFile>,
+}
+
+/// A multi-file operation in progress. Dropping it without calling `complete` leaves its record
+/// behind, as a crash would.
+pub(super) struct JournaledOperation {
+   record_path: PathBuf,
+   backup_dir: PathBuf,
+}
+
+fn journal_dir() -> Result<PathBuf, AgentSettingsError> {
+   Ok(get_home_dir()?.join(".athas").join(JOURNAL_DIR))
+}
+
+fn record_path(journal_dir: &Path, id: &str) -> PathBuf {
+   journal_dir.join(format!("{}.json", id))
+}
+
+fn hash(content: &str) -> String {
+   format!("{:016x}", content_hash(content))
+}
+
+/// Content of the file at `path`, or `None` when it doesn't exist
+fn read_current(path: &Path) -> Result<Option<String>, AgentSettingsError> {
+   match fs::read_to_string(path) {
+      Ok(content) => Ok(Some(content)),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(AgentSettingsError::io(path, e)),
+   }
+}
+
+/// Copy the current content of `paths` into `backup_dir`, one file per path
+async fn back_up(
+   locks: &AgentSettingsLocks,
+   backup_dir: &Path,
+   paths: &[PathBuf],
+) -> Result<Vec<JournaledFile>, AgentSettingsError> {
+   let mut files = Vec::with_capacity(paths.len());
+   for (index, path) in paths.iter().enumerate() {
+      let lock = locks.lock_for(path).await;
+      let _guard = lock.read().await;
+      let original = read_current(path)?;
+      let backup_path = match &original {
+         Some(content) => {
+            let backup = backup_dir.join(index.to_string());
+            write_atomic(&backup, content)?;
+            Some(backup.display().to_string())
+         }
+         None => None,
+      };
+      files.push(JournaledFile {
+         path: path.display().to_string(),
+         original_hash: original.as_deref().map(hash),
+         backup_path,
+      });
+   }
+   Ok(files)
+}
+
+impl JournaledOperation {
+   /// Record the intent to write `paths` in `~/.athas/journal/`, backing up their current
+   /// content, before an operation that writes them one after another
+   pub(super) async fn begin(
+      locks: &AgentSettingsLocks,
+      operation: &str,
+      paths: &[PathBuf],
+   ) -> Result<Self, AgentSettingsError> {
+      Self::begin_in(locks, &journal_dir()?, operation, paths).await
+   }
+
+   async fn begin_in(
+      locks: &AgentSettingsLocks,
+      journal_dir: &Path,
+      operation: &str,
+      paths: &[PathBuf],
+   ) -> Result<Self, AgentSettingsError> {
+      let id = uuid::Uuid::new_v4().to_string();
+      let backup_dir = journal_dir.join(&id);
+      fs::create_dir_all(&backup_dir).map_err(|e| AgentSettingsError::io(&backup_dir, e))?;
+
+      let files = match back_up(locks, &backup_dir, paths).await {
+         Ok(files) => files,
+         Err(e) => {
+            let _ = fs::remove_dir_all(&backup_dir);
+            return Err(e);
+         }
+      };
+      let record = PendingOperation {
+         id,
+         operation: operation.to_string(),
+         started_at: Utc::now(),
+         files,
+      };
+      let content =
+         serde_json::to_string_pretty(&record).map_err(|e| AgentSettingsError::Serialize {
+            format: ConfigFormat::Json,
+            message: e.to_string(),
+         })?;
+      let record_path = record_path(journal_dir, &record.id);
+      write_atomic(&record_path, &content)?;
+      Ok(Self {
+         record_path,
+         backup_dir,
+      })
+   }
+
+   /// Mark the operation as finished by removing its record, then its backups
+   pub(super) fn complete(self) -> Result<(), AgentSettingsError> {
+      match fs::remove_file(&self.record_path) {
+         Ok(()) => {}
+         Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+         Err(e) => return Err(AgentSettingsError::io(&self.record_path, e)),
+      }
+      if let Err(e) = fs::remove_dir_all(&self.backup_dir) {
+         log::warn!(
+            "Failed to remove operation backups in {}: {}",
+            self.backup_dir.display(),
+            e
+         );
+      }
+      Ok(())
+   }
+}
+
+/// Operations whose records are still in `journal_dir`, oldest first. Records that can't be
+/// read are skipped with a warning.
+fn pending_in(journal_dir: &Path) -> Result<Vec<PendingOperation>, AgentSettingsError> {
+   let entries = match fs::read_dir(journal_dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(AgentSettingsError::io(journal_dir, e)),
+   };
+
+   let mut pending = Vec::new();
+   for entry in entries.flatten() {
+      let path = entry.path();
+      if path.extension().is_none_or(|extension| extension != "json") {
+         continue;
+      }
+      let record = fs::read_to_string(&path)
+         .map_err(|e| e.to_string())
+         .and_then(|content| {
+            serde_json::from_str::<PendingOperation>(&content).map_err(|e| e.to_string())
+         });
+      match record {
+         Ok(record) => pending.push(record),
+         Err(e) => log::warn!("Skipping operation record {}: {}", path.display(), e),
+      }
+   }
+   pending.sort_by_key(|operation| operation.started_at);
+   Ok(pending)
+}
+
+/// Put `file` back the way it was before the operation: restored from its backup, or removed
+/// if the operation created it
+async fn roll_back(
+   locks: &AgentSettingsLocks,
+   file: &JournaledFile,
+) -> Result<(), AgentSettingsError> {
+   let path = Path::new(&file.path);
+   let lock = locks.lock_for(path).await;
+   let _guard = lock.write().await;
+
+   let current = read_current(path)?;
+   if current.as_deref().map(hash) == file.original_hash {
+      return Ok(());
+   }
+   match &file.backup_path {
+      Some(backup) => {
+         let backup = Path::new(backup);
+         let original =
+            fs::read_to_string(backup).map_err(|e| AgentSettingsError::io(backup, e))?;
+         write_atomic(path, &original)?;
+      }
+      None if was_created(path) => remove_created(path)?,
+      None => fs::remove_file(path).map_err(|e| AgentSettingsError::io(path, e))?,
+   }
+   log::info!("Rolled back {}", path.display());
+   Ok(())
+}
+
+/// Settle the interrupted operations in `journal_dir`, rolling their files back unless
+/// `rollback` is false, and remove their records
+async fn recover_in(
+   locks: &AgentSettingsLocks,
+   journal_dir: &Path,
+   rollback: bool,
+) -> Result<Vec<PendingOperation>, AgentSettingsError> {
+   let pending = pending_in(journal_dir)?;
+   for operation in &pending {
+      if rollback {
+         for file in &operation.files {
+            roll_back(locks, file).await?;
+         }
+      }
+      JournaledOperation {
+         record_path: record_path(journal_dir, &operation.id),
+         backup_dir: journal_dir.join(&operation.id),
+      }
+      .complete()?;
+      log::info!(
+         "Recovered interrupted {} operation {} (rolled back: {})",
+         operation.operation,
+         operation.id,
+         rollback
+      );
+   }
+   Ok(pending)
+}
+
+/// Emit `AGENT_SETTINGS_INTERRUPTED_EVENT` if operations were interrupted by a crash or quit,
+/// for the frontend to offer `recover_pending_operations`. Failures are logged.
+pub fn announce_pending_operations(app_handle: &AppHandle) {
+   match journal_dir().and_then(|dir| pending_in(&dir)) {
+      Ok(pending) if !pending.is_empty() => {
+         log::warn!(
+            "{} agent settings operations were interrupted",
+            pending.len()
+         );
+         let _ = app_handle.emit(AGENT_SETTINGS_INTERRUPTED_EVENT, &pending);
+      }
+      Ok(_) => {}
+      Err(e) => log::warn!("Failed to check for interrupted operations: {}", e),
+   }
+}
+
+/// Multi-file operations that were interrupted before they finished
+#[command]
+pub async fn list_pending_operations() -> Result<Vec<PendingOperation>, AgentSettingsError> {
+   pending_in(&journal_dir()?)
+}
+
+/// Settle the operations that were interrupted before they finished. By default every file they
+/// touched is restored to its content from before the operation; with `rollback` false the files
+/// are kept as they are. Either way the operations are no longer pending afterwards.
+#[command]
+pub async fn recover_pending_operations(
+   locks: State<'_, AgentSettingsLocks>,
+   rollback: Option<bool>,
+) -> Result<Vec<PendingOperation>, AgentSettingsError> {
+   recover_in(&locks, &journal_dir()?, rollback.unwrap_or(true)).await
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_settings::storage::{WriteOptions, update_config_file};
+   use serde_json::Value;
+
+   #[tokio::test]
+   async fn test_completed_operation_leaves_nothing_pending() {
+      let dir = tempfile::tempdir().unwrap();
+      let journal = dir.path().join("journal");
+      let path = dir.path().join("settings.json");
+      fs::write(&path, "{}").unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      let operation =
+         JournaledOperation::begin_in(&locks, &journal, "test", std::slice::from_ref(&path))
+            .await
+            .unwrap();
+      let pending = pending_in(&journal).unwrap();
+      assert_eq!(pending.len(), 1);
+      assert_eq!(pending[0].files[0].original_hash, Some(hash("{}")));
+
+      operation.complete().unwrap();
+      assert!(pending_in(&journal).unwrap().is_empty());
+      assert_eq!(fs::read_dir(&journal).unwrap().count(), 0);
+   }
+
+   #[tokio::test]
+   async fn test_aborted_operation_is_rolled_back() {
+      let dir = tempfile::tempdir().unwrap();
+      let journal = dir.path().join("journal");
+      let claude = dir.path().join("claude/settings.json");
+      let codex = dir.path().join("codex/config.json");
+      let created = dir.path().join("gemini/settings.json");
+      fs::create_dir_all(claude.parent().unwrap()).unwrap();
+      fs::create_dir_all(codex.parent().unwrap()).unwrap();
+      fs::write(&claude, "{\"model\": \"opus\"}").unwrap();
+      fs::write(&codex, "{\"model\": \"o3\"}").unwrap();
+
+      let locks = std::sync::Arc::new(AgentSettingsLocks::new());
+      let (written, wait_for_write) = tokio::sync::oneshot::channel();
+      let task = {
+         let locks = locks.clone();
+         let journal = journal.clone();
+         let paths = vec![claude.clone(), created.clone(), codex.clone()];
+         tokio::spawn(async move {
+            let _operation = JournaledOperation::begin_in(&locks, &journal, "test", &paths)
+               .await
+               .unwrap();
+            for path in &paths[..2] {
+               update_config_file(
+                  &locks,
+                  path,
+                  ConfigFormat::Json,
+                  WriteOptions::default(),
+                  |value| {
+                     value["model"] = Value::String("sonnet".into());
+                     Ok(())
+                  },
+               )
+               .await
+               .unwrap();
+            }
+            let _ = written.send(());
+            // Interrupted before the last file
+            std::future::pending::<()>().await;
+         })
+      };
+      wait_for_write.await.unwrap();
+      task.abort();
+      let _ = task.await;
+      assert!(fs::read_to_string(&claude).unwrap().contains("sonnet"));
+      assert!(created.exists());
+
+      let recovered = recover_in(&locks, &journal, true).await.unwrap();
+      assert_eq!(recovered.len(), 1);
+      assert_eq!(
+         fs::read_to_string(&claude).unwrap(),
+         "{\"model\": \"opus\"}"
+      );
+      assert_eq!(fs::read_to_string(&codex).unwrap(), "{\"model\": \"o3\"}");
+      assert!(!created.exists());
+      assert!(!was_created(&created));
+      assert!(pending_in(&journal).unwrap().is_empty());
+   }
+
+   #[tokio::test]
+   async fn test_recovery_can_keep_the_files() {
+      let dir = tempfile::tempdir().unwrap();
+      let journal = dir.path().join("journal");
+      let path = dir.path().join("settings.json");
+      fs::write(&path, "{}").unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      drop(
+         JournaledOperation::begin_in(&locks, &journal, "test", std::slice::from_ref(&path))
+            .await
+            .unwrap(),
+      );
+      fs::write(&path, "{\"model\": \"opus\"}").unwrap();
+
+      assert_eq!(recover_in(&locks, &journal, false).await.unwrap().len(), 1);
+      assert_eq!(fs::read_to_string(&path).unwrap(), "{\"model\": \"opus\"}");
+      assert!(pending_in(&journal).unwrap().is_empty());
+   }
+}
@@ -0,0 +1,508 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   keys::unescape_pointer_token,
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   storage::{AgentSettingsLocks, WriteOptions, update_config_file},
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tauri::{State, command};
+
+/// How a value written with `set_agent_config_values` combines with the one already there
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteMode {
+   /// Replace the existing value
+   #[default]
+   Set,
+   /// Apply the value as an RFC 7386 JSON Merge Patch to the existing value
+   Merge,
+}
+
+/// What happens when an array is written over an existing array
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrayStrategy {
+   /// The new array replaces the old one
+   #[default]
+   Replace,
+   /// Items of the new array that aren't in the old one are appended to it, for lists such as
+   /// tool allowlists
+   Union,
+}
+
+/// Apply `patch` to `target` following RFC 7386: objects are merged member by member, a null
+/// member removes that key, and anything else replaces the target. With `ArrayStrategy::Union`
+/// an array patch over an existing array adds its missing items instead of replacing it.
+pub(super) fn merge_patch(target: &mut Value, patch: Value, arrays: ArrayStrategy) {
+   match (target, patch) {
+      (target, Value::Object(patch)) => {
+         if !target.is_object() {
+            *target = Value::Object(Map::new());
+         }
+         let Value::Object(map) = target else {
+            unreachable!("target was just made an object");
+         };
+         for (key, value) in patch {
+            if value.is_null() {
+               map.shift_remove(&key);
+            } else {
+               merge_patch(map.entry(key).or_insert(Value::Null), value, arrays);
+            }
+         }
+      }
+      (Value::Array(existing), Value::Array(items)) if arrays == ArrayStrategy::Union => {
+         union_into(existing, items);
+      }
+      (target, patch) => *target = patch,
+   }
+}
+
+/// Combine `value` with the `existing` value at its key according to `mode` and `arrays`
+pub(super) fn combine(
+   existing: Option<Value>,
+   value: Value,
+   mode: WriteMode,
+   arrays: ArrayStrategy,
+) -> Value {
+   match (mode, existing) {
+      (WriteMode::Merge, existing) => {
+         let mut merged = existing.unwrap_or(Value::Null);
+         merge_patch(&mut merged, value, arrays);
+         merged
+      }
+      (WriteMode::Set, Some(Value::Array(mut existing))) if arrays == ArrayStrategy::Union => {
+         match value {
+            Value::Array(items) => {
+               union_into(&mut existing, items);
+               Value::Array(existing)
+            }
+            value => value,
+         }
+      }
+      (WriteMode::Set, _) => value,
+   }
+}
+
+fn union_into(existing: &mut Vec<Value>, items: Vec<Value>) {
+   for item in items {
+      if !existing.contains(&item) {
+         existing.push(item);
+      }
+   }
+}
+
+/// One RFC 6902 JSON Patch operation. Paths are JSON Pointers into the parsed document.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+   Add { path: String, value: Value },
+   Remove { path: String },
+   Replace { path: String, value: Value },
+   Move { from: String, path: String },
+   Copy { from: String, path: String },
+   Test { path: String, value: Value },
+}
+
+/// Split a JSON Pointer into its parent pointer and unescaped last token. The root pointer `""`
+/// has no parent.
+fn split_pointer(pointer: &str) -> Result<Option<(&str, String)>, String> {
+   if pointer.is_empty() {
+      return Ok(None);
+   }
+   let Some((parent, token)) = pointer
+      .rsplit_once('/')
+      .filter(|_| pointer.starts_with('/'))
+   else {
+      return Err(format!("'{}' is not a JSON Pointer", pointer));
+   };
+   let token = unescape_pointer_token(token)
+      .ok_or_else(|| format!("'{}' has a '~' not followed by '0' or '1'", pointer))?;
+   Ok(Some((parent, token)))
+}
+
+/// Parse an array index token. RFC 6901 allows only plain decimal digits without leading zeros.
+fn parse_index(token: &str, pointer: &str) -> Result<usize, String> {
+   token
+      .parse::<usize>()
+      .ok()
+      .filter(|index| index.to_string() == token)
+      .ok_or_else(|| format!("'{}' is not a valid array index in '{}'", token, pointer))
+}
+
+fn parent_mut<'a>(
+   document: &'a mut Value,
+   parent: &str,
+   pointer: &str,
+) -> Result<&'a mut Value, String> {
+   document
+      .pointer_mut(parent)
+      .ok_or_else(|| format!("parent of '{}' does not exist", pointer))
+}
+
+fn add(document: &mut Value, pointer: &str, value: Value) -> Result<(), String> {
+   let Some((parent, token)) = split_pointer(pointer)? else {
+      *document = value;
+      return Ok(());
+   };
+   match parent_mut(document, parent, pointer)? {
+      Value::Object(map) => {
+         map.insert(token, value);
+      }
+      Value::Array(items) if token == "-" => items.push(value),
+      Value::Array(items) => {
+         let index = parse_index(&token, pointer)?;
+         if index > items.len() {
+            return Err(format!("index {} is past the end of '{}'", index, parent));
+         }
+         items.insert(index, value);
+      }
+      _ => return Err(format!("parent of '{}' is not an object or array", pointer)),
+   }
+   Ok(())
+}
+
+fn remove(document: &mut Value, pointer: &str) -> Result<Value, String> {
+   let Some((parent, token)) = split_pointer(pointer)? else {
+      return Err("cannot remove the whole document".into());
+   };
+   let missing = || format!("'{}' does not exist", pointer);
+   match parent_mut(document, parent, pointer)? {
+      Value::Object(map) => map.shift_remove(&token).ok_or_else(missing),
+      Value::Array(items) => {
+         let index = parse_index(&token, pointer)?;
+         (index < items.len())
+            .then(|| items.remove(index))
+            .ok_or_else(missing)
+      }
+      _ => Err(missing()),
+   }
+}
+
+fn apply_operation(document: &mut Value, operation: PatchOperation) -> Result<(), String> {
+   match operation {
+      PatchOperation::Add { path, value } => add(document, &path, value),
+      PatchOperation::Remove { path } => remove(document, &path).map(|_| ()),
+      PatchOperation::Replace { path, value } => {
+         let target = document
+            .pointer_mut(&path)
+            .ok_or_else(|| format!("'{}' does not exist", path))?;
+         *target = value;
+         Ok(())
+      }
+      PatchOperation::Move { from, path } => {
+         if path.starts_with(&from) && path[from.len()..].starts_with('/') {
+            return Err(format!(
+               "cannot move '{}' into its own child '{}'",
+               from, path
+            ));
+         }
+         let value = remove(document, &from)?;
+         add(document, &path, value)
+      }
+      PatchOperation::Copy { from, path } => {
+         let value = document
+            .pointer(&from)
+            .cloned()
+            .ok_or_else(|| format!("'{}' does not exist", from))?;
+         add(document, &path, value)
+      }
+      PatchOperation::Test { path, value } => match document.pointer(&path) {
+         Some(actual) if *actual == value => Ok(()),
+         Some(actual) => Err(format!(
+            "test failed: '{}' is {} rather than {}",
+            path, actual, value
+         )),
+         None => Err(format!("test failed: '{}' does not exist", path)),
+      },
+   }
+}
+
+/// Apply a JSON Patch. Operations run in order against a copy of the document, which only
+/// replaces `document` once every operation has succeeded.
+pub(super) fn apply_patch(
+   document: &mut Value,
+   patch: Vec<PatchOperation>,
+) -> Result<(), AgentSettingsError> {
+   let mut patched = document.clone();
+   for (index, operation) in patch.into_iter().enumerate() {
+      apply_operation(&mut patched, operation)
+         .map_err(|message| AgentSettingsError::Patch { index, message })?;
+   }
+   *document = patched;
+   Ok(())
+}
+
+/// Apply an RFC 6902 JSON Patch to an agent's config file as one write. If any operation fails,
+/// including a `test`, nothing is written. Returns the patched document.
+#[command]
+pub async fn patch_agent_settings(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   settings_path: String,
+   patch: Vec<PatchOperation>,
+   follow_symlinks: Option<bool>,
+) -> Result<Value, AgentSettingsError> {
+   let path = roots.check(
+      &resolve_settings_path(&settings_path)?,
+      follow_symlinks.unwrap_or(false),
+   )?;
+   let format = ConfigFormat::from_path(&settings_path);
+
+   let operations = patch.len();
+   let mut document = Value::Null;
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &agent_id,
+      }),
+      ..WriteOptions::default()
+   };
+   update_config_file(&locks, &path, format, options, |value| {
+      apply_patch(value, patch)?;
+      document = value.clone();
+      Ok(())
+   })
+   .await?;
+
+   log::info!(
+      "Applied {} patch operations for agent {}",
+      operations,
+      agent_id
+   );
+   Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   #[test]
+   fn test_rfc_7386_examples() {
+      let cases = [
+         (
+            json!({ "a": "b" }),
+            json!({ "a": "c" }),
+            json!({ "a": "c" }),
+         ),
+         (
+            json!({ "a": "b" }),
+            json!({ "b": "c" }),
+            json!({ "a": "b", "b": "c" }),
+         ),
+         (json!({ "a": "b" }), json!({ "a": null }), json!({})),
+         (
+            json!({ "a": "b", "b": "c" }),
+            json!({ "a": null }),
+            json!({ "b": "c" }),
+         ),
+         (
+            json!({ "a": ["b"] }),
+            json!({ "a": "c" }),
+            json!({ "a": "c" }),
+         ),
+         (
+            json!({ "a": "c" }),
+            json!({ "a": ["b"] }),
+            json!({ "a": ["b"] }),
+         ),
+         (
+            json!({ "a": { "b": "c" } }),
+            json!({ "a": { "b": "d", "c": null } }),
+            json!({ "a": { "b": "d" } }),
+         ),
+         (
+            json!({ "a": [{ "b": "c" }] }),
+            json!({ "a": [1] }),
+            json!({ "a": [1] }),
+         ),
+         (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+         (json!({ "a": "b" }), json!(["c"]), json!(["c"])),
+         (json!({ "a": "foo" }), json!(null), json!(null)),
+         (
+            json!({ "e": null }),
+            json!({ "a": 1 }),
+            json!({ "e": null, "a": 1 }),
+         ),
+         (
+            json!([1, 2]),
+            json!({ "a": "b", "c": null }),
+            json!({ "a": "b" }),
+         ),
+         (
+            json!({}),
+            json!({ "a": { "bb": { "ccc": null } } }),
+            json!({ "a": { "bb": {} } }),
+         ),
+      ];
+
+      for (target, patch, expected) in cases {
+         let mut merged = target.clone();
+         merge_patch(&mut merged, patch.clone(), ArrayStrategy::Replace);
+         assert_eq!(merged, expected, "{} + {}", target, patch);
+      }
+   }
+
+   #[test]
+   fn test_union_appends_missing_items() {
+      let mut target = json!({ "permissions": { "allow": ["Read", "Edit"] } });
+      merge_patch(
+         &mut target,
+         json!({ "permissions": { "allow": ["Edit", "Bash"] } }),
+         ArrayStrategy::Union,
+      );
+      assert_eq!(
+         target,
+         json!({ "permissions": { "allow": ["Read", "Edit", "Bash"] } })
+      );
+
+      assert_eq!(
+         combine(
+            Some(json!(["Read"])),
+            json!(["Read", "Bash"]),
+            WriteMode::Set,
+            ArrayStrategy::Union
+         ),
+         json!(["Read", "Bash"])
+      );
+      assert_eq!(
+         combine(
+            Some(json!(["Read"])),
+            json!("Bash"),
+            WriteMode::Set,
+            ArrayStrategy::Union
+         ),
+         json!("Bash")
+      );
+   }
+
+   fn patch(operations: Value) -> Vec<PatchOperation> {
+      serde_json::from_value(operations).unwrap()
+   }
+
+   #[test]
+   fn test_json_patch_operations() {
+      let mut document = json!({
+         "mcpServers": { "old": { "command": "old-mcp" } },
+         "permissions": { "allow": ["Read", "Bash", "Edit"] },
+      });
+
+      apply_patch(
+         &mut document,
+         patch(json!([
+            { "op": "test", "path": "/mcpServers/old/command", "value": "old-mcp" },
+            { "op": "move", "from": "/mcpServers/old", "path": "/mcpServers/renamed" },
+            { "op": "remove", "path": "/permissions/allow/1" },
+            { "op": "add", "path": "/permissions/allow/0", "value": "Grep" },
+            { "op": "add", "path": "/permissions/allow/-", "value": "Write" },
+            { "op": "copy", "from": "/permissions/allow", "path": "/permissions/ask" },
+            { "op": "replace", "path": "/mcpServers/renamed/command", "value": "new-mcp" },
+         ])),
+      )
+      .unwrap();
+
+      assert_eq!(
+         document,
+         json!({
+            "mcpServers": { "renamed": { "command": "new-mcp" } },
+            "permissions": {
+               "allow": ["Grep", "Read", "Edit", "Write"],
+               "ask": ["Grep", "Read", "Edit", "Write"],
+            },
+         })
+      );
+   }
+
+   #[test]
+   fn test_failed_patch_changes_nothing() {
+      let original = json!({ "model": "o3", "tools": ["Read"] });
+
+      for (operations, failing) in [
+         (
+            json!([
+               { "op": "replace", "path": "/model", "value": "gpt-5" },
+               { "op": "test", "path": "/model", "value": "o3" },
+            ]),
+            1,
+         ),
+         (json!([{ "op": "remove", "path": "/missing" }]), 0),
+         (json!([{ "op": "add", "path": "/a/b", "value": 1 }]), 0),
+         (
+            json!([{ "op": "add", "path": "/tools/5", "value": "x" }]),
+            0,
+         ),
+         (
+            json!([{ "op": "add", "path": "/tools/01", "value": "x" }]),
+            0,
+         ),
+         (
+            json!([{ "op": "replace", "path": "/missing", "value": 1 }]),
+            0,
+         ),
+         (
+            json!([{ "op": "move", "from": "/tools", "path": "/tools/0" }]),
+            0,
+         ),
+         (json!([{ "op": "add", "path": "model", "value": 1 }]), 0),
+      ] {
+         let mut document = original.clone();
+         let result = apply_patch(&mut document, patch(operations.clone()));
+         assert!(
+            matches!(result, Err(AgentSettingsError::Patch { index, .. }) if index == failing),
+            "{}: {:?}",
+            operations,
+            result
+         );
+         assert_eq!(document, original);
+      }
+   }
+
+   #[test]
+   fn test_patch_root_and_escaped_keys() {
+      let mut document = json!({ "a": 1 });
+      apply_patch(
+         &mut document,
+         patch(json!([
+            { "op": "add", "path": "", "value": { "m~n": 1 } },
+            { "op": "add", "path": "/a~1b", "value": 2 },
+            { "op": "test", "path": "/m~0n", "value": 1 },
+         ])),
+      )
+      .unwrap();
+      assert_eq!(document, json!({ "m~n": 1, "a/b": 2 }));
+   }
+
+   #[tokio::test]
+   async fn test_failed_patch_leaves_file_untouched() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("config.toml");
+      let content = "model = \"o3\"\n";
+      std::fs::write(&path, content).unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      let result = update_config_file(
+         &locks,
+         &path,
+         ConfigFormat::Toml,
+         WriteOptions::default(),
+         |value| {
+            apply_patch(
+               value,
+               patch(json!([
+                  { "op": "replace", "path": "/model", "value": "gpt-5" },
+                  { "op": "test", "path": "/model", "value": "o3" },
+               ])),
+            )
+         },
+      )
+      .await;
+
+      assert!(matches!(result, Err(AgentSettingsError::Patch { .. })));
+      assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+   }
+}
@@ -0,0 +1,693 @@
+use super::error::AgentSettingsError;
+use serde::Serialize;
+use std::{
+   fs, io,
+   path::{Component, Path, PathBuf},
+   sync::Mutex,
+};
+use tauri::command;
+
+/// Environment variable that stands in for the home directory in every settings command, so
+/// portable installs can keep agent configs inside a folder of their own. Tests give each app
+/// its own home with `AgentSettingsRoots::with_home` instead, as the variable is process-wide.
+const HOME_OVERRIDE_ENV: &str = "ATHAS_HOME_OVERRIDE";
+
+fn home_override() -> Option<PathBuf> {
+   std::env::var_os(HOME_OVERRIDE_ENV)
+      .filter(|home| !home.is_empty())
+      .map(PathBuf::from)
+}
+
+pub(super) fn get_home_dir() -> Result<PathBuf, AgentSettingsError> {
+   home_override()
+      .or_else(dirs::home_dir)
+      .ok_or(AgentSettingsError::HomeDirUnavailable)
+}
+
+/// Well-known directories a settings path can start with, written `{config}`, `{data}` or
+/// `{home}`. `config` and `data` follow the platform convention: the XDG directories on Linux,
+/// `~/Library/Application Support` on macOS and `%APPDATA%` on Windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RootDir {
+   Config,
+   Data,
+   Home,
+}
+
+impl RootDir {
+   fn from_name(name: &str) -> Option<Self> {
+      match name {
+         "config" => Some(RootDir::Config),
+         "data" => Some(RootDir::Data),
+         "home" => Some(RootDir::Home),
+         _ => None,
+      }
+   }
+
+   /// Where the directory sits relative to home on this platform, which is where `dirs` finds it
+   /// unless the XDG variables move it
+   fn home_relative(self) -> &'static str {
+      match self {
+         RootDir::Config | RootDir::Data if cfg!(target_os = "macos") => {
+            "Library/Application Support"
+         }
+         RootDir::Config | RootDir::Data if cfg!(windows) => r"AppData\Roaming",
+         RootDir::Config => ".config",
+         RootDir::Data => ".local/share",
+         RootDir::Home => "",
+      }
+   }
+
+   /// Where the directory is for a home directory of `home`, laid out the way the platform
+   /// lays it out under a real home
+   fn under(self, home: PathBuf) -> PathBuf {
+      match self {
+         RootDir::Home => home,
+         dir => home.join(dir.home_relative()),
+      }
+   }
+
+   /// Look the directory up for the current platform and user. With a home override the
+   /// directories are placed under the override the way they would be under a real home.
+   pub(super) fn locate(self) -> Result<PathBuf, AgentSettingsError> {
+      if let Some(home) = home_override() {
+         return Ok(self.under(home));
+      }
+
+      match self {
+         RootDir::Config => dirs::config_dir().ok_or(AgentSettingsError::HomeDirUnavailable),
+         RootDir::Data => dirs::data_dir().ok_or(AgentSettingsError::HomeDirUnavailable),
+         RootDir::Home => get_home_dir(),
+      }
+   }
+}
+
+/// Expand a leading `{config}`, `{data}` or `{home}` root or `~` to that directory, and `$VAR` /
+/// `${VAR}` references to the values of environment variables, plus `%VAR%` when `percent_vars`
+/// is set (Windows syntax). A `$` or `%` that does not start a reference is kept as-is. Roots are
+/// located with `root` and variables looked up with `env`; a variable that is not set is an error
+/// rather than being left in the path.
+pub(super) fn expand_path(
+   settings_path: &str,
+   root: impl Fn(RootDir) -> Result<PathBuf, AgentSettingsError>,
+   env: impl Fn(&str) -> Option<String>,
+   percent_vars: bool,
+) -> Result<String, AgentSettingsError> {
+   let invalid = |message: String| AgentSettingsError::InvalidPath {
+      path: settings_path.to_string(),
+      message,
+   };
+   let lookup = |name: &str| {
+      env(name).ok_or_else(|| invalid(format!("environment variable {} is not set", name)))
+   };
+
+   let mut expanded = String::with_capacity(settings_path.len());
+   let mut rest = settings_path;
+   if let Some(after) = rest.strip_prefix('~')
+      && (after.is_empty() || after.starts_with(['/', '\\']))
+   {
+      expanded.push_str(&root(RootDir::Home)?.to_string_lossy());
+      rest = after;
+   } else if let Some(braced) = rest.strip_prefix('{')
+      && let Some(end) = braced.find('}')
+   {
+      let name = &braced[..end];
+      let dir = RootDir::from_name(name).ok_or_else(|| {
+         invalid(format!(
+            "unknown root '{{{}}}', expected {{config}}, {{data}} or {{home}}",
+            name
+         ))
+      })?;
+      expanded.push_str(&root(dir)?.to_string_lossy());
+      rest = &braced[end + 1..];
+   }
+
+   while let Some(start) = rest.find(|c| c == '$' || (percent_vars && c == '%')) {
+      expanded.push_str(&rest[..start]);
+      let sigil = &rest[start..start + 1];
+      let after = &rest[start + 1..];
+
+      if sigil == "%" {
+         // `%VAR%`; a lone `%` is an ordinary character in a file name
+         match after.find('%') {
+            Some(end) if is_variable_name(&after[..end]) => {
+               expanded.push_str(&lookup(&after[..end])?);
+               rest = &after[end + 1..];
+            }
+            _ => {
+               expanded.push('%');
+               rest = after;
+            }
+         }
+      } else if let Some(braced) = after.strip_prefix('{') {
+         let end = braced
+            .find('}')
+            .ok_or_else(|| invalid("unterminated '${' in settings path".into()))?;
+         let name = &braced[..end];
+         if !is_variable_name(name) {
+            return Err(invalid(format!("invalid variable name '{}'", name)));
+         }
+         expanded.push_str(&lookup(name)?);
+         rest = &braced[end + 1..];
+      } else {
+         let end = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+         if is_variable_name(&after[..end]) {
+            expanded.push_str(&lookup(&after[..end])?);
+            rest = &after[end..];
+         } else {
+            expanded.push('$');
+            rest = after;
+         }
+      }
+   }
+   expanded.push_str(rest);
+
+   Ok(expanded)
+}
+
+pub(super) fn is_variable_name(name: &str) -> bool {
+   let mut chars = name.chars();
+   matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+      && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Resolve the `settings_path` a command was given to the file it refers to. Roots, `~` and
+/// environment variables are expanded first, then absolute paths are used as-is and relative
+/// paths are taken relative to the user's home directory.
+pub(super) fn resolve_settings_path(settings_path: &str) -> Result<PathBuf, AgentSettingsError> {
+   resolve_against(settings_path, RootDir::locate)
+}
+
+/// Like [`resolve_settings_path`], with the root directories supplied by the caller. `root` is
+/// only called when the path needs it, so absolute paths work even when there is no home
+/// directory.
+pub(super) fn resolve_against(
+   settings_path: &str,
+   root: impl Fn(RootDir) -> Result<PathBuf, AgentSettingsError>,
+) -> Result<PathBuf, AgentSettingsError> {
+   let empty = || AgentSettingsError::InvalidPath {
+      path: settings_path.to_string(),
+      message: "settings path is empty".into(),
+   };
+   if settings_path.trim().is_empty() {
+      return Err(empty());
+   }
+
+   let expanded = expand_path(
+      settings_path,
+      &root,
+      |name| std::env::var_os(name).map(|value| value.to_string_lossy().into_owned()),
+      cfg!(windows),
+   )?;
+   if expanded.trim().is_empty() {
+      return Err(empty());
+   }
+
+   let path = Path::new(&expanded);
+   // On Windows `\foo` has a root but no drive, so it is not `is_absolute`, but joining it to the
+   // home directory would still discard the home directory
+   if path.is_absolute() || path.has_root() {
+      return Ok(path.to_path_buf());
+   }
+
+   Ok(root(RootDir::Home)?.join(path))
+}
+
+/// Directories the settings commands may write under. Only the home directory is allowed by
+/// default. The app can allow more when it sets up its state, but nothing the frontend sends
+/// can, so a buggy or compromised webview cannot widen it.
+pub struct AgentSettingsRoots {
+   roots: Mutex<Vec<PathBuf>>,
+   /// Home directory settings paths are resolved against in place of the user's, when set
+   home: Option<PathBuf>,
+}
+
+impl Default for AgentSettingsRoots {
+   fn default() -> Self {
+      Self {
+         roots: Mutex::new(get_home_dir().into_iter().collect()),
+         home: None,
+      }
+   }
+}
+
+impl AgentSettingsRoots {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   /// Roots for a home directory of `home` instead of the user's: settings paths resolve
+   /// against it and writes are allowed under it. Unlike `ATHAS_HOME_OVERRIDE`, this only
+   /// affects the commands given this state, so tests can each have a home of their own.
+   pub fn with_home(home: PathBuf) -> Self {
+      Self {
+         roots: Mutex::new(vec![home.clone()]),
+         home: Some(home),
+      }
+   }
+
+   /// Resolve a settings path as `resolve_settings_path` does, against this state's home
+   pub(super) fn resolve(&self, settings_path: &str) -> Result<PathBuf, AgentSettingsError> {
+      match &self.home {
+         Some(home) => resolve_against(settings_path, |dir| Ok(dir.under(home.clone()))),
+         None => resolve_settings_path(settings_path),
+      }
+   }
+
+   /// Allow writes anywhere under `root`
+   pub fn allow(&self, root: PathBuf) {
+      self
+         .roots
+         .lock()
+         .unwrap_or_else(|e| e.into_inner())
+         .push(root);
+   }
+
+   /// Check that `path` is strictly inside one of the allowed roots, returning it with `.` and
+   /// `..` segments resolved. Unless `follow_symlinks` is set, the path must also stay inside
+   /// once symlinks are resolved, so a link under home cannot redirect a write elsewhere.
+   pub(super) fn check(
+      &self,
+      path: &Path,
+      follow_symlinks: bool,
+   ) -> Result<PathBuf, AgentSettingsError> {
+      let not_allowed = |message: &str| AgentSettingsError::PathNotAllowed {
+         path: path.display().to_string(),
+         message: message.to_string(),
+      };
+      let roots = self.roots.lock().unwrap_or_else(|e| e.into_inner()).clone();
+      let inside = |path: &Path, roots: &[PathBuf]| {
+         roots
+            .iter()
+            .any(|root| path.starts_with(root) && path != root)
+      };
+
+      let normalized = normalize(path);
+      let canonical_roots: Vec<PathBuf> = roots
+         .iter()
+         .filter_map(|root| canonicalize_existing(root).ok())
+         .collect();
+      let lexical_roots: Vec<PathBuf> = roots.iter().map(|root| normalize(root)).collect();
+      if !inside(&normalized, &lexical_roots) && !inside(&normalized, &canonical_roots) {
+         return Err(not_allowed("outside the allowed directories"));
+      }
+
+      if !follow_symlinks && !inside(&canonicalize_existing(&normalized)?, &canonical_roots) {
+         return Err(not_allowed(
+            "a symlink points outside the allowed directories; pass followSymlinks to allow it",
+         ));
+      }
+
+      Ok(normalized)
+   }
+}
+
+/// Resolve `.` and `..` segments without touching the filesystem. `..` at the root stays at the
+/// root, as it does for the OS.
+fn normalize(path: &Path) -> PathBuf {
+   let mut normalized = PathBuf::new();
+   for component in path.components() {
+      match component {
+         Component::CurDir => {}
+         Component::ParentDir => {
+            normalized.pop();
+         }
+         component => normalized.push(component),
+      }
+   }
+   normalized
+}
+
+/// Canonicalize the longest existing prefix of `path` and append the segments that don't exist
+/// yet, giving the location a write would actually land at.
+fn canonicalize_existing(path: &Path) -> Result<PathBuf, AgentSettingsError> {
+   let mut missing = Vec::new();
+   let mut current = path;
+   loop {
+      match fs::canonicalize(current) {
+         Ok(canonical) => {
+            return Ok(missing
+               .iter()
+               .rev()
+               .fold(canonical, |path, name| path.join(name)));
+         }
+         Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            match (current.file_name(), current.parent()) {
+               (Some(name), Some(parent)) => {
+                  missing.push(name);
+                  current = parent;
+               }
+               _ => return Err(AgentSettingsError::io(current, error)),
+            }
+         }
+         Err(error) => return Err(AgentSettingsError::io(current, error)),
+      }
+   }
+}
+
+/// Where an agent config path spec points on this machine
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedConfigPath {
+   pub path: String,
+   pub exists: bool,
+}
+
+/// Resolve a config path spec such as `{config}/opencode/config.json` or `~/.codex/config.toml`
+/// to an absolute path for the current platform
+#[command]
+pub async fn resolve_agent_config_path(
+   agent_id: String,
+   spec: String,
+) -> Result<ResolvedConfigPath, AgentSettingsError> {
+   let path = resolve_settings_path(&spec)?;
+   let exists = path.exists();
+   log::debug!(
+      "Resolved config path for agent {}: {} (exists: {})",
+      agent_id,
+      path.display(),
+      exists
+   );
+
+   Ok(ResolvedConfigPath {
+      path: path.display().to_string(),
+      exists,
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn test_root(dir: RootDir) -> Result<PathBuf, AgentSettingsError> {
+      Ok(PathBuf::from(match dir {
+         RootDir::Config => "/home/me/.config",
+         RootDir::Data => "/home/me/.local/share",
+         RootDir::Home => "/home/me",
+      }))
+   }
+
+   fn resolve(settings_path: &str) -> Result<PathBuf, AgentSettingsError> {
+      resolve_against(settings_path, test_root)
+   }
+
+   #[test]
+   fn test_relative_path_joins_home() {
+      assert_eq!(
+         resolve(".codex/config.toml").unwrap(),
+         Path::new("/home/me").join(".codex/config.toml")
+      );
+   }
+
+   #[test]
+   fn test_empty_path_is_rejected() {
+      for settings_path in ["", "   "] {
+         assert!(matches!(
+            resolve(settings_path),
+            Err(AgentSettingsError::InvalidPath { .. })
+         ));
+      }
+   }
+
+   #[test]
+   fn test_absolute_path_does_not_need_home() {
+      let absolute = if cfg!(windows) {
+         r"C:\config.toml"
+      } else {
+         "/config.toml"
+      };
+      let resolved = resolve_against(absolute, |_| Err(AgentSettingsError::HomeDirUnavailable));
+      assert_eq!(resolved.unwrap(), Path::new(absolute));
+
+      assert!(matches!(
+         resolve_against("config.toml", |_| Err(
+            AgentSettingsError::HomeDirUnavailable
+         )),
+         Err(AgentSettingsError::HomeDirUnavailable)
+      ));
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn test_unix_paths() {
+      assert_eq!(
+         resolve("/etc/agent/config.toml").unwrap(),
+         Path::new("/etc/agent/config.toml")
+      );
+      // Windows syntax has no special meaning on Unix, so it names a file under home
+      assert_eq!(
+         resolve(r"C:\agent\config.toml").unwrap(),
+         Path::new("/home/me").join(r"C:\agent\config.toml")
+      );
+   }
+
+   #[cfg(windows)]
+   #[test]
+   fn test_windows_paths() {
+      for absolute in [
+         r"C:\Users\me\.codex\config.toml",
+         "C:/Users/me/.codex/config.toml",
+         r"\\server\share\config.toml",
+         r"\agent\config.toml",
+      ] {
+         assert_eq!(resolve(absolute).unwrap(), Path::new(absolute));
+      }
+      assert_eq!(
+         resolve(r".codex\config.toml").unwrap(),
+         Path::new("/home/me").join(r".codex\config.toml")
+      );
+   }
+
+   fn expand(settings_path: &str, percent_vars: bool) -> Result<String, AgentSettingsError> {
+      let env = |name: &str| match name {
+         "XDG_CONFIG_HOME" => Some("/home/me/.config".to_string()),
+         "APPDATA" => Some(r"C:\Users\me\AppData\Roaming".to_string()),
+         "EMPTY" => Some(String::new()),
+         _ => None,
+      };
+      expand_path(settings_path, test_root, env, percent_vars)
+   }
+
+   #[test]
+   fn test_expand_tilde() {
+      assert_eq!(expand("~", false).unwrap(), "/home/me");
+      assert_eq!(
+         expand("~/Library/Application Support/agent.json", false).unwrap(),
+         "/home/me/Library/Application Support/agent.json"
+      );
+      assert_eq!(
+         expand(r"~\.codex\config.toml", false).unwrap(),
+         r"/home/me\.codex\config.toml"
+      );
+      // Only a leading `~` followed by a separator means home
+      assert_eq!(expand("~agent/config", false).unwrap(), "~agent/config");
+      assert_eq!(expand("backup~/config", false).unwrap(), "backup~/config");
+   }
+
+   #[test]
+   fn test_expand_roots() {
+      assert_eq!(
+         expand("{config}/opencode/config.json", false).unwrap(),
+         "/home/me/.config/opencode/config.json"
+      );
+      assert_eq!(
+         expand("{data}/agent/settings.json", false).unwrap(),
+         "/home/me/.local/share/agent/settings.json"
+      );
+      assert_eq!(
+         expand("{home}/.aider.conf.yml", false).unwrap(),
+         "/home/me/.aider.conf.yml"
+      );
+      // Roots are only recognized at the start, and the remainder still expands variables
+      assert_eq!(
+         expand("{config}/$XDG_CONFIG_HOME", false).unwrap(),
+         "/home/me/.config//home/me/.config"
+      );
+      assert_eq!(expand("a/{config}/b", false).unwrap(), "a/{config}/b");
+   }
+
+   #[test]
+   fn test_expand_variables() {
+      assert_eq!(
+         expand("$XDG_CONFIG_HOME/opencode/config.json", false).unwrap(),
+         "/home/me/.config/opencode/config.json"
+      );
+      assert_eq!(
+         expand("${XDG_CONFIG_HOME}opencode.json", false).unwrap(),
+         "/home/me/.configopencode.json"
+      );
+      assert_eq!(expand("a$EMPTY/b", false).unwrap(), "a/b");
+      assert_eq!(
+         expand("price$5/50%.json", false).unwrap(),
+         "price$5/50%.json"
+      );
+      assert_eq!(
+         expand(r"%APPDATA%\agent\config.json", true).unwrap(),
+         r"C:\Users\me\AppData\Roaming\agent\config.json"
+      );
+      assert_eq!(expand("%APPDATA%/x", false).unwrap(), "%APPDATA%/x");
+      assert_eq!(expand("100% done.json", true).unwrap(), "100% done.json");
+   }
+
+   #[test]
+   fn test_expand_errors() {
+      for (settings_path, percent_vars, expected) in [
+         ("$UNSET_VAR/config.json", false, "UNSET_VAR is not set"),
+         ("${UNSET_VAR}/config.json", false, "UNSET_VAR is not set"),
+         ("%UNSET_VAR%/config.json", true, "UNSET_VAR is not set"),
+         ("${XDG_CONFIG_HOME/config.json", false, "unterminated"),
+         ("${1abc}/config.json", false, "invalid variable name"),
+         ("{cache}/config.json", false, "unknown root '{cache}'"),
+      ] {
+         match expand(settings_path, percent_vars) {
+            Err(AgentSettingsError::InvalidPath { path, message }) => {
+               assert_eq!(path, settings_path);
+               assert!(message.contains(expected), "{}: {}", settings_path, message);
+            }
+            other => panic!("{}: unexpected {:?}", settings_path, other),
+         }
+      }
+   }
+
+   #[test]
+   fn test_resolve_reads_environment() {
+      // SAFETY: the variable names are unique to this test, so no other thread reads or writes them
+      unsafe {
+         std::env::set_var("ATHAS_TEST_AGENT_DIR", "/opt/agent");
+         std::env::remove_var("ATHAS_TEST_UNSET_DIR");
+      }
+
+      assert_eq!(
+         resolve("$ATHAS_TEST_AGENT_DIR/config.toml").unwrap(),
+         Path::new("/opt/agent/config.toml")
+      );
+      assert!(matches!(
+         resolve("$ATHAS_TEST_UNSET_DIR/config.toml"),
+         Err(AgentSettingsError::InvalidPath { .. })
+      ));
+
+      // SAFETY: as above
+      unsafe { std::env::set_var("ATHAS_TEST_EMPTY_DIR", "") };
+      assert!(matches!(
+         resolve("$ATHAS_TEST_EMPTY_DIR"),
+         Err(AgentSettingsError::InvalidPath { .. })
+      ));
+   }
+
+   #[tokio::test]
+   async fn test_resolve_agent_config_path_reports_existence() {
+      let dir = tempfile::tempdir().unwrap();
+      let existing = dir.path().join("config.json");
+      std::fs::write(&existing, "{}").unwrap();
+
+      let resolved = resolve_agent_config_path("test".into(), existing.display().to_string())
+         .await
+         .unwrap();
+      assert!(resolved.exists);
+      assert_eq!(resolved.path, existing.display().to_string());
+
+      let missing = dir.path().join("missing.json").display().to_string();
+      let resolved = resolve_agent_config_path("test".into(), missing)
+         .await
+         .unwrap();
+      assert!(!resolved.exists);
+   }
+
+   fn roots_in(dir: &Path) -> AgentSettingsRoots {
+      AgentSettingsRoots {
+         roots: Mutex::new(vec![dir.join("home")]),
+         home: None,
+      }
+   }
+
+   #[test]
+   fn test_check_allows_paths_under_root() {
+      let dir = tempfile::tempdir().unwrap();
+      fs::create_dir(dir.path().join("home")).unwrap();
+      let roots = roots_in(dir.path());
+
+      let nested = dir.path().join("home/.config/agent/config.json");
+      assert_eq!(roots.check(&nested, false).unwrap(), nested);
+      assert_eq!(
+         roots
+            .check(&dir.path().join("home/a/../b.json"), false)
+            .unwrap(),
+         dir.path().join("home/b.json")
+      );
+      // The root itself is a directory, not a settings file
+      assert!(matches!(
+         roots.check(&dir.path().join("home"), false),
+         Err(AgentSettingsError::PathNotAllowed { .. })
+      ));
+   }
+
+   #[test]
+   fn test_check_rejects_escapes() {
+      let dir = tempfile::tempdir().unwrap();
+      fs::create_dir(dir.path().join("home")).unwrap();
+      let roots = roots_in(dir.path());
+
+      for path in [
+         dir.path().join("home/../outside.json"),
+         dir.path().join("home/.config/../../../etc/passwd"),
+         dir.path().join("other/config.json"),
+         PathBuf::from("/etc/passwd"),
+      ] {
+         assert!(
+            matches!(
+               roots.check(&path, false),
+               Err(AgentSettingsError::PathNotAllowed { .. })
+            ),
+            "{} should be rejected",
+            path.display()
+         );
+      }
+
+      roots.allow(dir.path().join("other"));
+      assert!(
+         roots
+            .check(&dir.path().join("other/config.json"), false)
+            .is_ok()
+      );
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn test_check_symlink_targets() {
+      use std::os::unix::fs::symlink;
+
+      let dir = tempfile::tempdir().unwrap();
+      fs::create_dir_all(dir.path().join("home/.config")).unwrap();
+      fs::create_dir(dir.path().join("outside")).unwrap();
+      fs::write(dir.path().join("outside/real.json"), "{}").unwrap();
+      fs::write(dir.path().join("home/.config/real.json"), "{}").unwrap();
+      symlink(
+         dir.path().join("outside/real.json"),
+         dir.path().join("home/escape.json"),
+      )
+      .unwrap();
+      symlink(dir.path().join("outside"), dir.path().join("home/linked")).unwrap();
+      symlink(
+         dir.path().join("home/.config/real.json"),
+         dir.path().join("home/inside.json"),
+      )
+      .unwrap();
+      let roots = roots_in(dir.path());
+
+      for link in ["home/escape.json", "home/linked/new.json"] {
+         let path = dir.path().join(link);
+         assert!(matches!(
+            roots.check(&path, false),
+            Err(AgentSettingsError::PathNotAllowed { .. })
+         ));
+         assert_eq!(roots.check(&path, true).unwrap(), path);
+      }
+      assert!(
+         roots
+            .check(&dir.path().join("home/inside.json"), false)
+            .is_ok()
+      );
+   }
+}
@@ -0,0 +1,178 @@
+use super::layers::deep_merge;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Table of Athas' own config files holding per-OS overrides, one section per platform, such as
+/// `[platform.macos.agents.aider]`
+const PLATFORM_TABLE: &str = "platform";
+
+/// An OS with its own section in Athas' config files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+   Macos,
+   Linux,
+   Windows,
+}
+
+impl Platform {
+   /// The platform Athas is running on, `None` on an OS without a section
+   pub(super) fn current() -> Option<Self> {
+      if cfg!(target_os = "macos") {
+         Some(Self::Macos)
+      } else if cfg!(target_os = "linux") {
+         Some(Self::Linux)
+      } else if cfg!(windows) {
+         Some(Self::Windows)
+      } else {
+         None
+      }
+   }
+
+   fn name(self) -> &'static str {
+      match self {
+         Self::Macos => "macos",
+         Self::Linux => "linux",
+         Self::Windows => "windows",
+      }
+   }
+}
+
+/// Dotted `key` inside `platform`'s section, or `key` itself for the base document
+pub(super) fn platform_key(platform: Option<Platform>, key: &str) -> String {
+   match platform {
+      Some(platform) => format!("{}.{}.{}", PLATFORM_TABLE, platform.name(), key),
+      None => key.to_string(),
+   }
+}
+
+/// The document as seen on `platform`: the `platform` table is dropped and the section for
+/// `platform` merged over what is left. Tables are merged key by key, and any other value in the
+/// section, arrays included, replaces the base one.
+pub(super) fn resolve_platform(mut value: Value, platform: Option<Platform>) -> Value {
+   let Some(Value::Object(mut sections)) = value
+      .as_object_mut()
+      .and_then(|root| root.remove(PLATFORM_TABLE))
+   else {
+      return value;
+   };
+   if let Some(section) = platform.and_then(|platform| sections.remove(platform.name())) {
+      deep_merge(&mut value, section);
+   }
+   value
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   fn document() -> Value {
+      json!({
+         "agents": {
+            "acme": { "binary_name": "acme", "version_args": ["--version"] }
+         },
+         "theme": "dark",
+         "platform": {
+            "macos": {
+               "agents": { "acme": { "binary_name": "/opt/homebrew/bin/acme" } }
+            },
+            "linux": {
+               "agents": { "acme": { "version_args": ["version"] } },
+               "theme": "light"
+            },
+            "windows": {
+               "agents": { "acme": "acme.exe" }
+            }
+         }
+      })
+   }
+
+   #[test]
+   fn test_resolve_macos_section() {
+      assert_eq!(
+         resolve_platform(document(), Some(Platform::Macos)),
+         json!({
+            "agents": {
+               "acme": {
+                  "binary_name": "/opt/homebrew/bin/acme",
+                  "version_args": ["--version"]
+               }
+            },
+            "theme": "dark"
+         })
+      );
+   }
+
+   #[test]
+   fn test_resolve_linux_section_replaces_arrays_and_scalars() {
+      assert_eq!(
+         resolve_platform(document(), Some(Platform::Linux)),
+         json!({
+            "agents": {
+               "acme": { "binary_name": "acme", "version_args": ["version"] }
+            },
+            "theme": "light"
+         })
+      );
+   }
+
+   #[test]
+   fn test_resolve_windows_section_replaces_table_with_value() {
+      assert_eq!(
+         resolve_platform(document(), Some(Platform::Windows)),
+         json!({ "agents": { "acme": "acme.exe" }, "theme": "dark" })
+      );
+   }
+
+   #[test]
+   fn test_resolve_without_section() {
+      let expected = json!({
+         "agents": {
+            "acme": { "binary_name": "acme", "version_args": ["--version"] }
+         },
+         "theme": "dark"
+      });
+      assert_eq!(resolve_platform(document(), None), expected);
+
+      let mut only_macos = document();
+      only_macos["platform"] = json!({ "macos": { "theme": "light" } });
+      assert_eq!(
+         resolve_platform(only_macos, Some(Platform::Linux)),
+         expected
+      );
+
+      let plain = json!({ "theme": "dark" });
+      assert_eq!(
+         resolve_platform(plain.clone(), Some(Platform::Macos)),
+         plain
+      );
+   }
+
+   #[test]
+   fn test_platform_key() {
+      assert_eq!(platform_key(None, "agents.acme"), "agents.acme");
+      assert_eq!(
+         platform_key(Some(Platform::Windows), "agents.\"acme.internal\""),
+         "platform.windows.agents.\"acme.internal\""
+      );
+   }
+
+   #[cfg(target_os = "macos")]
+   #[test]
+   fn test_current_platform() {
+      assert_eq!(Platform::current(), Some(Platform::Macos));
+   }
+
+   #[cfg(target_os = "linux")]
+   #[test]
+   fn test_current_platform() {
+      assert_eq!(Platform::current(), Some(Platform::Linux));
+   }
+
+   #[cfg(windows)]
+   #[test]
+   fn test_current_platform() {
+      assert_eq!(Platform::current(), Some(Platform::Windows));
+   }
+}
@@ -0,0 +1,202 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   keys::{get_nested_value, validate_key_path},
+   patch::{ArrayStrategy, WriteMode},
+   paths::resolve_settings_path,
+   settings::apply_values,
+   storage::{AgentSettingsLocks, preview_config_update},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::HashMap, io, path::Path};
+use tauri::{State, command};
+
+/// One key whose value a previewed change would alter. A missing side means the key is absent.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingChange {
+   pub key: String,
+   pub old_value: Option<Value>,
+   pub new_value: Option<Value>,
+}
+
+/// What `set_agent_config_values` would do to a config file
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsPreview {
+   /// Content of the file now, `None` when it doesn't exist
+   pub old_content: Option<String>,
+   /// Content the file would have, `None` when it would not be created
+   pub new_content: Option<String>,
+   /// Unified diff from the old to the new content, empty when nothing would change
+   pub diff: String,
+   pub changes: Vec<SettingChange>,
+}
+
+/// Unified diff between two versions of the file at `path`
+fn unified_diff(path: &Path, old: &str, new: &str) -> Result<String, AgentSettingsError> {
+   if old == new {
+      return Ok(String::new());
+   }
+
+   let to_error =
+      |e: git2::Error| AgentSettingsError::io(path, io::Error::other(e.message().to_string()));
+   let name = path.file_name().map(Path::new);
+   let mut patch = git2::Patch::from_buffers(old.as_bytes(), name, new.as_bytes(), name, None)
+      .map_err(to_error)?;
+   let buf = patch.to_buf().map_err(to_error)?;
+   Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// Show what `set_agent_config_values` would write for the same arguments, without writing:
+/// the file's content before and after, a unified diff, and the old and new value of each key
+/// that would change.
+#[command]
+pub async fn preview_agent_settings_change(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   settings_path: String,
+   values: HashMap<String, Value>,
+   mode: Option<WriteMode>,
+   arrays: Option<ArrayStrategy>,
+) -> Result<AgentSettingsPreview, AgentSettingsError> {
+   values.keys().try_for_each(|key| validate_key_path(key))?;
+   let path = resolve_settings_path(&settings_path)?;
+   let format = ConfigFormat::from_path(&settings_path);
+
+   let mut changes = Vec::new();
+   let preview = preview_config_update(&locks, &path, format, |value| {
+      let before = value.clone();
+      let changed = apply_values(
+         value,
+         values,
+         mode.unwrap_or_default(),
+         arrays.unwrap_or_default(),
+      )?;
+      changes = changed
+         .into_iter()
+         .map(|key| SettingChange {
+            old_value: get_nested_value(&before, &key).cloned(),
+            new_value: get_nested_value(value, &key).cloned(),
+            key,
+         })
+         .collect();
+      Ok(())
+   })
+   .await?;
+
+   let diff = unified_diff(
+      &path,
+      preview.original.as_deref().unwrap_or_default(),
+      preview.content.as_deref().unwrap_or_default(),
+   )?;
+   log::debug!(
+      "Previewed {} setting changes for agent {}",
+      changes.len(),
+      agent_id
+   );
+
+   Ok(AgentSettingsPreview {
+      old_content: preview.original,
+      new_content: preview.content,
+      diff,
+      changes,
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_settings::storage::update_config_file;
+   use serde_json::json;
+   use tauri::Manager;
+
+   #[tokio::test]
+   async fn test_preview_matches_the_real_write() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("config.toml");
+      let content = "# Codex config\nmodel = \"o3\"\napproval_policy = \"on-request\"\n";
+      std::fs::write(&path, content).unwrap();
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+
+      let values = HashMap::from([
+         ("model".to_string(), json!("gpt-5")),
+         ("model_reasoning_effort".to_string(), json!("high")),
+         ("approval_policy".to_string(), json!("on-request")),
+      ]);
+      let preview = preview_agent_settings_change(
+         app.state(),
+         "codex".into(),
+         path.display().to_string(),
+         values.clone(),
+         None,
+         None,
+      )
+      .await
+      .unwrap();
+
+      assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+      assert_eq!(preview.old_content.as_deref(), Some(content));
+      assert!(preview.diff.contains("-model = \"o3\"\n"));
+      assert!(preview.diff.contains("+model = \"gpt-5\"\n"));
+      assert!(
+         preview
+            .diff
+            .contains("+model_reasoning_effort = \"high\"\n")
+      );
+      assert!(!preview.diff.contains("-approval_policy"));
+      assert_eq!(
+         preview.changes,
+         vec![
+            SettingChange {
+               key: "model".into(),
+               old_value: Some(json!("o3")),
+               new_value: Some(json!("gpt-5")),
+            },
+            SettingChange {
+               key: "model_reasoning_effort".into(),
+               old_value: None,
+               new_value: Some(json!("high")),
+            },
+         ]
+      );
+
+      let locks = AgentSettingsLocks::new();
+      update_config_file(
+         &locks,
+         &path,
+         ConfigFormat::Toml,
+         Default::default(),
+         |value| apply_values(value, values, WriteMode::Set, ArrayStrategy::Replace).map(|_| ()),
+      )
+      .await
+      .unwrap();
+      assert_eq!(std::fs::read_to_string(&path).ok(), preview.new_content);
+   }
+
+   #[tokio::test]
+   async fn test_preview_of_no_change() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("missing/config.json");
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+
+      let preview = preview_agent_settings_change(
+         app.state(),
+         "test".into(),
+         path.display().to_string(),
+         HashMap::from([("model".to_string(), Value::Null)]),
+         None,
+         None,
+      )
+      .await
+      .unwrap();
+
+      assert_eq!(preview.old_content, None);
+      assert_eq!(preview.new_content, None);
+      assert_eq!(preview.diff, "");
+      assert!(preview.changes.is_empty());
+   }
+}
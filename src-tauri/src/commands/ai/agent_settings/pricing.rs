@@ -0,0 +1,423 @@
+use super::{
+   custom_providers::load_custom_providers,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   paths::get_home_dir,
+   storage::{AgentSettingsLocks, read_config_file},
+};
+use crate::commands::ai::model_catalog::ModelProvider;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::{State, command};
+
+/// Where the user's price overrides are kept, relative to the home directory
+const USER_PRICING_FILE: &str = ".athas/pricing.toml";
+
+/// Built-in list prices in USD per million tokens: model, provider, input, output. Tiered prices
+/// use the lowest tier.
+const BUILT_IN_PRICES: &[(&str, ModelProvider, f64, f64)] = &[
+   ("claude-opus-4-1", ModelProvider::Anthropic, 15.0, 75.0),
+   ("claude-opus-4", ModelProvider::Anthropic, 15.0, 75.0),
+   ("claude-sonnet-4-5", ModelProvider::Anthropic, 3.0, 15.0),
+   ("claude-sonnet-4", ModelProvider::Anthropic, 3.0, 15.0),
+   ("claude-haiku-4-5", ModelProvider::Anthropic, 1.0, 5.0),
+   ("claude-3-5-haiku", ModelProvider::Anthropic, 0.8, 4.0),
+   ("gpt-5", ModelProvider::OpenAi, 1.25, 10.0),
+   ("gpt-5-codex", ModelProvider::OpenAi, 1.25, 10.0),
+   ("gpt-5-mini", ModelProvider::OpenAi, 0.25, 2.0),
+   ("gpt-5-nano", ModelProvider::OpenAi, 0.05, 0.4),
+   ("o3", ModelProvider::OpenAi, 2.0, 8.0),
+   ("o4-mini", ModelProvider::OpenAi, 1.1, 4.4),
+   ("gpt-4.1", ModelProvider::OpenAi, 2.0, 8.0),
+   ("gpt-4.1-mini", ModelProvider::OpenAi, 0.4, 1.6),
+   ("gpt-4o", ModelProvider::OpenAi, 2.5, 10.0),
+   ("gpt-4o-mini", ModelProvider::OpenAi, 0.15, 0.6),
+   ("gemini-2.5-pro", ModelProvider::Google, 1.25, 10.0),
+   ("gemini-2.5-flash", ModelProvider::Google, 0.3, 2.5),
+   ("gemini-2.5-flash-lite", ModelProvider::Google, 0.1, 0.4),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PriceSource {
+   #[default]
+   BuiltIn,
+   /// `~/.athas/pricing.toml`
+   PricingFile,
+}
+
+/// What a model costs, in USD per million tokens. Also the shape of a `[[models]]` entry of the
+/// pricing file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPrice {
+   #[serde(alias = "id")]
+   pub model: String,
+   /// Id of the built-in or custom provider the price is charged by
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub provider: Option<String>,
+   pub input_per_million: f64,
+   pub output_per_million: f64,
+   #[serde(skip_deserializing)]
+   pub source: PriceSource,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CostEstimate {
+   pub usd: f64,
+   pub input_usd: f64,
+   pub output_usd: f64,
+   /// The price the estimate was made with
+   pub price: ModelPrice,
+}
+
+fn built_in_prices() -> Vec<ModelPrice> {
+   BUILT_IN_PRICES
+      .iter()
+      .map(|&(model, provider, input, output)| ModelPrice {
+         model: model.to_string(),
+         provider: Some(provider.id().to_string()),
+         input_per_million: input,
+         output_per_million: output,
+         source: PriceSource::BuiltIn,
+      })
+      .collect()
+}
+
+/// Price overrides in the file at `path`. Entries that don't parse, have a negative price or name
+/// a provider that is neither built in nor among `custom_providers` are skipped with a warning.
+async fn load_user_prices(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   custom_providers: &[String],
+) -> Result<Vec<ModelPrice>, AgentSettingsError> {
+   let known_provider = |id: &str| {
+      ModelProvider::from_id(id).is_some() || custom_providers.iter().any(|custom| custom == id)
+   };
+   let Some(file) = read_config_file(locks, path, ConfigFormat::Toml).await? else {
+      return Ok(Vec::new());
+   };
+   let Some(entries) = file
+      .value
+      .get("models")
+      .and_then(|models| models.as_array())
+   else {
+      return Ok(Vec::new());
+   };
+
+   Ok(entries
+      .iter()
+      .filter_map(
+         |entry| match serde_json::from_value::<ModelPrice>(entry.clone()) {
+            Ok(price)
+               if price.input_per_million >= 0.0
+                  && price.output_per_million >= 0.0
+                  && price.provider.as_deref().is_none_or(known_provider) =>
+            {
+               Some(ModelPrice {
+                  source: PriceSource::PricingFile,
+                  ..price
+               })
+            }
+            Ok(price) if !price.provider.as_deref().is_none_or(known_provider) => {
+               log::warn!(
+                  "Skipping price for {} in {}: unknown provider '{}'",
+                  price.model,
+                  path.display(),
+                  price.provider.unwrap_or_default()
+               );
+               None
+            }
+            Ok(price) => {
+               log::warn!(
+                  "Skipping price for {} in {}: prices can't be negative",
+                  price.model,
+                  path.display()
+               );
+               None
+            }
+            Err(e) => {
+               log::warn!("Skipping price entry in {}: {}", path.display(), e);
+               None
+            }
+         },
+      )
+      .collect())
+}
+
+/// Built-in prices with the user's overrides applied. An override for a listed model replaces
+/// its price, unless it is for another provider: a custom provider resells a model at a price of
+/// its own. Overrides that name no provider keep the listed one.
+async fn pricing_table_from(
+   locks: &AgentSettingsLocks,
+   user_pricing_path: &Path,
+   custom_providers: &[String],
+) -> Result<Vec<ModelPrice>, AgentSettingsError> {
+   let mut table = built_in_prices();
+   for price in load_user_prices(locks, user_pricing_path, custom_providers).await? {
+      let replaces = |p: &ModelPrice| {
+         p.model == price.model && (price.provider.is_none() || p.provider == price.provider)
+      };
+      match table.iter_mut().find(|p| replaces(p)) {
+         Some(existing) => {
+            *existing = ModelPrice {
+               provider: price.provider.or(existing.provider.take()),
+               ..price
+            }
+         }
+         None => table.push(price),
+      }
+   }
+   Ok(table)
+}
+
+fn user_pricing_path() -> Result<PathBuf, AgentSettingsError> {
+   Ok(get_home_dir()?.join(USER_PRICING_FILE))
+}
+
+/// Built-in prices with the overrides from `~/.athas/pricing.toml` applied
+pub async fn pricing_table(
+   locks: &AgentSettingsLocks,
+) -> Result<Vec<ModelPrice>, AgentSettingsError> {
+   let custom_providers: Vec<String> = load_custom_providers(locks)
+      .await?
+      .into_iter()
+      .map(|provider| provider.id)
+      .collect();
+   pricing_table_from(locks, &user_pricing_path()?, &custom_providers).await
+}
+
+/// Whether `rest`, following a listed model id, names a snapshot of that model, such as
+/// `-20250929`, `-2025-04-14`, `-latest` or a cloud suffix like `@20250929`
+fn is_snapshot_suffix(rest: &str) -> bool {
+   if rest.starts_with(['@', ':']) || rest == "-latest" {
+      return true;
+   }
+   let Some(date) = rest.strip_prefix('-') else {
+      return false;
+   };
+   date.chars().all(|c| c.is_ascii_digit() || c == '-')
+      && date.chars().filter(char::is_ascii_digit).count() >= 6
+}
+
+/// The price of `model` among the entries `listed` accepts: an exact entry, else the longest
+/// entry `model` is a snapshot of. A provider prefix like `anthropic/` is ignored.
+fn find_listed_price<'a>(
+   table: &'a [ModelPrice],
+   model: &str,
+   listed: impl Fn(&ModelPrice) -> bool,
+) -> Option<&'a ModelPrice> {
+   let model = model.trim().to_ascii_lowercase();
+   let model = model.rsplit('/').next().unwrap_or(&model);
+   let entries = table.iter().filter(|p| listed(p));
+   if let Some(price) = entries
+      .clone()
+      .find(|p| p.model.eq_ignore_ascii_case(model))
+   {
+      return Some(price);
+   }
+   entries
+      .filter(|p| {
+         model
+            .strip_prefix(&p.model.to_ascii_lowercase())
+            .is_some_and(is_snapshot_suffix)
+      })
+      .max_by_key(|p| p.model.len())
+}
+
+fn find_price<'a>(table: &'a [ModelPrice], model: &str) -> Option<&'a ModelPrice> {
+   find_listed_price(table, model, |_| true)
+}
+
+/// The price of `model` charged by `provider`, or by any provider when it has none listed
+fn find_provider_price<'a>(
+   table: &'a [ModelPrice],
+   model: &str,
+   provider: Option<&str>,
+) -> Option<&'a ModelPrice> {
+   provider
+      .and_then(|provider| {
+         find_listed_price(table, model, |p| p.provider.as_deref() == Some(provider))
+      })
+      .or_else(|| find_price(table, model))
+}
+
+fn estimate(price: &ModelPrice, input_tokens: u64, output_tokens: u64) -> CostEstimate {
+   let input_usd = input_tokens as f64 * price.input_per_million / 1_000_000.0;
+   let output_usd = output_tokens as f64 * price.output_per_million / 1_000_000.0;
+   CostEstimate {
+      usd: input_usd + output_usd,
+      input_usd,
+      output_usd,
+      price: price.clone(),
+   }
+}
+
+/// What `input_tokens` and `output_tokens` of `model` cost at the prices in `table`, from any
+/// provider that lists the model, or `None` when none does
+pub fn estimate_usage(
+   table: &[ModelPrice],
+   model: &str,
+   input_tokens: u64,
+   output_tokens: u64,
+) -> Option<CostEstimate> {
+   find_price(table, model).map(|price| estimate(price, input_tokens, output_tokens))
+}
+
+/// Estimate what a request to `model` costs in USD at list prices. With `provider`, the price that
+/// provider charges is used when one is listed. `None` for models without a known price, so the
+/// UI can tell "unknown" from "free".
+#[command]
+pub async fn estimate_cost(
+   locks: State<'_, AgentSettingsLocks>,
+   model: String,
+   input_tokens: u64,
+   output_tokens: u64,
+   provider: Option<String>,
+) -> Result<Option<CostEstimate>, AgentSettingsError> {
+   let table = pricing_table(&locks).await?;
+   Ok(find_provider_price(&table, &model, provider.as_deref())
+      .map(|price| estimate(price, input_tokens, output_tokens)))
+}
+
+/// The prices cost estimates are made with: built-in list prices and overrides from
+/// `~/.athas/pricing.toml`
+#[command]
+pub async fn get_pricing_table(
+   locks: State<'_, AgentSettingsLocks>,
+) -> Result<Vec<ModelPrice>, AgentSettingsError> {
+   pricing_table(&locks).await
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_find_price() {
+      let table = built_in_prices();
+      let cases = [
+         ("claude-sonnet-4-5", Some("claude-sonnet-4-5")),
+         ("claude-sonnet-4-5-20250929", Some("claude-sonnet-4-5")),
+         ("claude-sonnet-4-20250514", Some("claude-sonnet-4")),
+         ("claude-opus-4-1@20250805", Some("claude-opus-4-1")),
+         ("openai/GPT-5-mini", Some("gpt-5-mini")),
+         ("gpt-4.1-2025-04-14", Some("gpt-4.1")),
+         ("gemini-2.5-flash-lite", Some("gemini-2.5-flash-lite")),
+         ("claude-sonnet-4-6", None),
+         ("gpt-5-turbo", None),
+         ("llama3.1:8b", None),
+      ];
+      for (model, expected) in cases {
+         assert_eq!(
+            find_price(&table, model).map(|price| price.model.as_str()),
+            expected,
+            "{}",
+            model
+         );
+      }
+   }
+
+   #[test]
+   fn test_estimate() {
+      let table = built_in_prices();
+      let price = find_price(&table, "claude-sonnet-4-5").unwrap();
+      let cost = estimate(price, 200_000, 10_000);
+      assert!((cost.input_usd - 0.6).abs() < 1e-9);
+      assert!((cost.output_usd - 0.15).abs() < 1e-9);
+      assert!((cost.usd - 0.75).abs() < 1e-9);
+      assert_eq!(cost.price.source, PriceSource::BuiltIn);
+   }
+
+   #[tokio::test]
+   async fn test_pricing_file_overrides() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("pricing.toml");
+      std::fs::write(
+         &path,
+         r#"
+[[models]]
+model = "gpt-5"
+inputPerMillion = 1.0
+outputPerMillion = 8.0
+
+[[models]]
+id = "team-proxy-large"
+provider = "openai"
+inputPerMillion = 0.5
+outputPerMillion = 1.5
+
+[[models]]
+model = "gemini-2.5-pro"
+inputPerMillion = -1.0
+outputPerMillion = 10.0
+
+[[models]]
+model = "missing-output"
+inputPerMillion = 1.0
+
+[[models]]
+model = "gpt-5"
+provider = "openrouter"
+inputPerMillion = 1.5
+outputPerMillion = 12.0
+
+[[models]]
+model = "llama-3.3-70b"
+provider = "groq"
+inputPerMillion = 0.6
+outputPerMillion = 0.8
+"#,
+      )
+      .unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      let table = pricing_table_from(&locks, &path, &["openrouter".to_string()])
+         .await
+         .unwrap();
+      let gpt5 = find_price(&table, "gpt-5").unwrap();
+      assert_eq!(gpt5.source, PriceSource::PricingFile);
+      assert_eq!(gpt5.output_per_million, 8.0);
+      assert_eq!(
+         table
+            .iter()
+            .filter(|p| p.model == "gpt-5" && p.provider.as_deref() == Some("openai"))
+            .count(),
+         1
+      );
+
+      let proxy = find_price(&table, "team-proxy-large").unwrap();
+      assert_eq!(proxy.provider.as_deref(), Some("openai"));
+      assert!((estimate(proxy, 2_000_000, 0).usd - 1.0).abs() < 1e-9);
+
+      let gemini = find_price(&table, "gemini-2.5-pro").unwrap();
+      assert_eq!(gemini.source, PriceSource::BuiltIn);
+      assert!(find_price(&table, "missing-output").is_none());
+      // Unregistered providers' prices are skipped
+      assert!(find_price(&table, "llama-3.3-70b").is_none());
+
+      let resold = find_provider_price(&table, "openai/gpt-5", Some("openrouter")).unwrap();
+      assert_eq!(resold.output_per_million, 12.0);
+      let direct = find_provider_price(&table, "gpt-5", Some("openai")).unwrap();
+      assert_eq!(direct.output_per_million, 8.0);
+      let fallback = find_provider_price(&table, "gpt-5-mini", Some("openrouter")).unwrap();
+      assert_eq!(fallback.provider.as_deref(), Some("openai"));
+
+      let serialized = serde_json::to_value(proxy).unwrap();
+      assert_eq!(
+         serialized,
+         serde_json::json!({
+            "model": "team-proxy-large",
+            "provider": "openai",
+            "inputPerMillion": 0.5,
+            "outputPerMillion": 1.5,
+            "source": "pricingFile",
+         })
+      );
+
+      let missing = dir.path().join("none.toml");
+      let table = pricing_table_from(&locks, &missing, &[]).await.unwrap();
+      assert_eq!(table.len(), BUILT_IN_PRICES.len());
+   }
+}
@@ -0,0 +1,210 @@
+use super::{
+   error::AgentSettingsError,
+   prompts::{PromptTemplate, find_prompt_template},
+   storage::AgentSettingsLocks,
+};
+use crate::commands::ai::token_count::{TokenCount, TokenCounter, count_blocking};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::{State, command};
+
+/// Values of the placeholders every template can use without declaring them, taken from the
+/// editor by the frontend. Unset ones can't be rendered.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptBuiltinContext {
+   pub selection: Option<String>,
+   pub file_path: Option<String>,
+   pub language: Option<String>,
+   pub workspace_name: Option<String>,
+}
+
+impl PromptBuiltinContext {
+   fn get(&self, name: &str) -> Option<&str> {
+      match name {
+         "selection" => self.selection.as_deref(),
+         "file_path" => self.file_path.as_deref(),
+         "language" => self.language.as_deref(),
+         "workspace_name" => self.workspace_name.as_deref(),
+         _ => None,
+      }
+   }
+}
+
+/// A template's body with its placeholders filled in
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedPrompt {
+   pub text: String,
+   /// Tokens `text` takes up, or `None` if they couldn't be counted
+   pub token_count: Option<TokenCount>,
+}
+
+/// `body` with each `{{name}}` replaced by `resolve(name)`, or the names that resolved to
+/// nothing, each once in the order they first appear. `\{{` is a literal `{{`, and a `{{`
+/// without a closing `}}` is left as it is.
+fn substitute(body: &str, resolve: impl Fn(&str) -> Option<String>) -> Result<String, Vec<String>> {
+   let mut text = String::with_capacity(body.len());
+   let mut unresolved: Vec<String> = Vec::new();
+   let mut rest = body;
+   while let Some(start) = rest.find("{{") {
+      if rest[..start].ends_with('\\') {
+         text.push_str(&rest[..start - 1]);
+         text.push_str("{{");
+         rest = &rest[start + 2..];
+         continue;
+      }
+      text.push_str(&rest[..start]);
+      let inner = &rest[start + 2..];
+      let Some(end) = inner.find("}}") else {
+         text.push_str(&rest[start..]);
+         rest = "";
+         break;
+      };
+      let name = inner[..end].trim();
+      match resolve(name) {
+         Some(value) => text.push_str(&value),
+         None => {
+            if !unresolved.iter().any(|seen| seen == name) {
+               unresolved.push(name.to_string());
+            }
+         }
+      }
+      rest = &inner[end + 2..];
+   }
+   text.push_str(rest);
+
+   if unresolved.is_empty() {
+      Ok(text)
+   } else {
+      Err(unresolved)
+   }
+}
+
+/// `template`'s body with its placeholders filled from `variables`, then `builtins`, then the
+/// defaults the template declares
+fn render(
+   template: &PromptTemplate,
+   variables: &HashMap<String, String>,
+   builtins: &PromptBuiltinContext,
+) -> Result<String, AgentSettingsError> {
+   let resolve = |name: &str| {
+      variables
+         .get(name)
+         .map(String::as_str)
+         .or_else(|| builtins.get(name))
+         .or_else(|| {
+            template
+               .variables
+               .iter()
+               .find(|variable| variable.name == name)
+               .and_then(|variable| variable.default.as_deref())
+         })
+         .map(str::to_string)
+   };
+   substitute(&template.body, resolve).map_err(|placeholders| {
+      AgentSettingsError::UnresolvedPlaceholders {
+         id: template.id.clone(),
+         placeholders,
+      }
+   })
+}
+
+/// Fill in prompt template `id`, looked up in the workspace before `~/.athas/prompts/`, and count
+/// the tokens of the result for `model`. A placeholder with no value in `variables`,
+/// `builtin_context` or the template's defaults is an `UnresolvedPlaceholders` error naming it.
+#[command]
+pub async fn render_prompt_template(
+   locks: State<'_, AgentSettingsLocks>,
+   counter: State<'_, TokenCounter>,
+   id: String,
+   variables: Option<HashMap<String, String>>,
+   builtin_context: Option<PromptBuiltinContext>,
+   workspace_path: Option<String>,
+   model: Option<String>,
+) -> Result<RenderedPrompt, AgentSettingsError> {
+   let template = find_prompt_template(&locks, workspace_path.as_deref(), &id)
+      .await?
+      .ok_or_else(|| AgentSettingsError::UnknownPromptTemplate { id: id.clone() })?;
+   let text = render(
+      &template,
+      &variables.unwrap_or_default(),
+      &builtin_context.unwrap_or_default(),
+   )?;
+
+   let token_count =
+      match count_blocking(&counter, vec![text.clone()], model.unwrap_or_default()).await {
+         Ok(counts) => counts.into_iter().next(),
+         Err(e) => {
+            log::warn!("Failed to count tokens of prompt template {}: {}", id, e);
+            None
+         }
+      };
+   Ok(RenderedPrompt { text, token_count })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_settings::prompts::PromptVariable;
+
+   fn template(body: &str) -> PromptTemplate {
+      PromptTemplate {
+         id: "review".into(),
+         title: "Review".into(),
+         body: body.into(),
+         variables: vec![PromptVariable {
+            name: "focus".into(),
+            description: None,
+            default: Some("bugs".into()),
+         }],
+         tags: Vec::new(),
+      }
+   }
+
+   #[test]
+   fn test_renders_variables_builtins_and_defaults() {
+      let builtins = PromptBuiltinContext {
+         selection: Some("let x = 1;".into()),
+         file_path: Some("src/main.rs".into()),
+         language: Some("rust".into()),
+         workspace_name: None,
+      };
+      let body = "Review {{file_path}} ({{ language }}) for {{focus}}:\n{{selection}}";
+      assert_eq!(
+         render(&template(body), &HashMap::new(), &builtins).unwrap(),
+         "Review src/main.rs (rust) for bugs:\nlet x = 1;"
+      );
+
+      let variables = HashMap::from([
+         ("focus".to_string(), "naming".to_string()),
+         ("language".to_string(), "Rust 2024".to_string()),
+      ]);
+      assert_eq!(
+         render(&template(body), &variables, &builtins).unwrap(),
+         "Review src/main.rs (Rust 2024) for naming:\nlet x = 1;"
+      );
+   }
+
+   #[test]
+   fn test_lists_unresolved_placeholders() {
+      let body = "{{workspace_name}}: {{ticket}} {{selection}} {{ticket}}";
+      match render(&template(body), &HashMap::new(), &Default::default()) {
+         Err(AgentSettingsError::UnresolvedPlaceholders { id, placeholders }) => {
+            assert_eq!(id, "review");
+            assert_eq!(placeholders, vec!["workspace_name", "ticket", "selection"]);
+         }
+         other => panic!("expected unresolved placeholders, got {:?}", other),
+      }
+   }
+
+   #[test]
+   fn test_escaped_and_unclosed_braces_are_kept() {
+      let resolve = |name: &str| (name == "name").then(|| "Athas".to_string());
+      assert_eq!(
+         substitute(r"Hi {{name}}, write \{{name}} or {{ to", resolve).unwrap(),
+         "Hi Athas, write {{name}} or {{ to"
+      );
+      assert_eq!(substitute("", resolve).unwrap(), "");
+   }
+}
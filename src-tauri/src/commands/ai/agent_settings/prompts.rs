@@ -0,0 +1,526 @@
+use super::{
+   backup::{remove_created, was_created},
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   paths::get_home_dir,
+   storage::{AgentSettingsLocks, WriteOptions, read_config_file, update_config_file},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+   collections::BTreeMap,
+   fs,
+   io::ErrorKind,
+   path::{Path, PathBuf},
+};
+use tauri::{State, command};
+
+/// Directory prompt templates are kept in, relative to the home directory for global templates
+/// and to the workspace for project ones. Each template is a file named by its id.
+const PROMPTS_DIR: &str = ".athas/prompts";
+/// Extensions templates are read from, in the order they are looked for. New templates are
+/// written as TOML.
+const TEMPLATE_FORMATS: [(&str, ConfigFormat); 2] =
+   [("toml", ConfigFormat::Toml), ("json", ConfigFormat::Json)];
+
+/// Where a prompt template is kept. Project templates live in the workspace's
+/// `.athas/prompts/` and shadow global templates with the same id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptScope {
+   #[default]
+   Global,
+   Project,
+}
+
+/// A placeholder a template's body uses, written `{{name}}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptVariable {
+   pub name: String,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub description: Option<String>,
+   /// Value used when none is given
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub default: Option<String>,
+}
+
+/// A reusable prompt. The id is the name of the file it is kept in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptTemplate {
+   pub id: String,
+   pub title: String,
+   pub body: String,
+   #[serde(default)]
+   pub variables: Vec<PromptVariable>,
+   #[serde(default)]
+   pub tags: Vec<String>,
+}
+
+/// A template as listed, with where it was read from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptTemplateListing {
+   #[serde(flatten)]
+   pub template: PromptTemplate,
+   pub origin: PromptScope,
+   pub path: String,
+   /// Whether a global template with the same id is hidden by this project one
+   pub shadows_global: bool,
+}
+
+fn invalid_template(id: &str, message: impl Into<String>) -> AgentSettingsError {
+   AgentSettingsError::InvalidPromptTemplate {
+      id: id.to_string(),
+      message: message.into(),
+   }
+}
+
+/// Ids name files, so they are kept to lowercase letters, digits, `-` and `_`
+fn is_valid_id(id: &str) -> bool {
+   !id.is_empty()
+      && id
+         .chars()
+         .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_'))
+}
+
+/// Variable names are what goes between the braces of a placeholder
+fn is_valid_variable_name(name: &str) -> bool {
+   name
+      .chars()
+      .next()
+      .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+      && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The template as it is saved, or why it can't be: whitespace is trimmed, a title and body are
+/// required, variable names must be usable in placeholders and appear once, and blank or
+/// repeated tags are dropped
+fn validate_template(template: &PromptTemplate) -> Result<PromptTemplate, AgentSettingsError> {
+   let id = template.id.trim();
+   if !is_valid_id(id) {
+      return Err(invalid_template(
+         id,
+         "ids may only hold lowercase letters, digits, '-' and '_'",
+      ));
+   }
+   let title = template.title.trim();
+   if title.is_empty() {
+      return Err(invalid_template(id, "title is empty"));
+   }
+   if template.body.trim().is_empty() {
+      return Err(invalid_template(id, "body is empty"));
+   }
+
+   let mut variables: Vec<PromptVariable> = Vec::new();
+   for variable in &template.variables {
+      let name = variable.name.trim();
+      if !is_valid_variable_name(name) {
+         return Err(invalid_template(
+            id,
+            format!("'{}' is not a valid variable name", name),
+         ));
+      }
+      if variables.iter().any(|seen| seen.name == name) {
+         return Err(invalid_template(
+            id,
+            format!("variable '{}' is declared twice", name),
+         ));
+      }
+      let description = variable
+         .description
+         .as_deref()
+         .map(str::trim)
+         .filter(|description| !description.is_empty())
+         .map(str::to_string);
+      variables.push(PromptVariable {
+         name: name.to_string(),
+         description,
+         default: variable.default.clone(),
+      });
+   }
+
+   let mut tags: Vec<String> = Vec::new();
+   for tag in &template.tags {
+      let tag = tag.trim();
+      if !tag.is_empty() && !tags.iter().any(|seen| seen == tag) {
+         tags.push(tag.to_string());
+      }
+   }
+
+   Ok(PromptTemplate {
+      id: id.to_string(),
+      title: title.to_string(),
+      body: template.body.clone(),
+      variables,
+      tags,
+   })
+}
+
+fn global_dir() -> Result<PathBuf, AgentSettingsError> {
+   Ok(get_home_dir()?.join(PROMPTS_DIR))
+}
+
+fn project_dir(workspace_path: &str) -> PathBuf {
+   Path::new(workspace_path).join(PROMPTS_DIR)
+}
+
+/// Directory of the templates in `scope`. Project templates need a workspace.
+fn scope_dir(
+   scope: PromptScope,
+   workspace_path: Option<&str>,
+) -> Result<PathBuf, AgentSettingsError> {
+   match (scope, workspace_path) {
+      (PromptScope::Global, _) => global_dir(),
+      (PromptScope::Project, Some(workspace_path)) => Ok(project_dir(workspace_path)),
+      (PromptScope::Project, None) => Err(AgentSettingsError::InvalidPath {
+         path: String::new(),
+         message: "no workspace was given for project prompt templates".to_string(),
+      }),
+   }
+}
+
+/// The file template `id` is kept in within `dir`, if it exists
+fn find_template_file(dir: &Path, id: &str) -> Option<(PathBuf, ConfigFormat)> {
+   TEMPLATE_FORMATS
+      .into_iter()
+      .find_map(|(extension, format)| {
+         let path = dir.join(format!("{}.{}", id, extension));
+         path.is_file().then_some((path, format))
+      })
+}
+
+/// The template at `path`, named by its file
+async fn load_template(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   id: &str,
+   format: ConfigFormat,
+) -> Result<Option<PromptTemplate>, AgentSettingsError> {
+   let Some(file) = read_config_file(locks, path, format).await? else {
+      return Ok(None);
+   };
+   let mut definition = file.value;
+   if let Value::Object(map) = &mut definition {
+      map.insert("id".to_string(), Value::String(id.to_string()));
+   }
+   serde_json::from_value(definition)
+      .map(Some)
+      .map_err(|e| invalid_template(id, e.to_string()))
+}
+
+/// Templates in `dir` by id. A missing directory holds none, and files that don't parse as
+/// templates are skipped with a warning. When a template is kept in both formats, the TOML file
+/// wins.
+async fn load_dir(
+   locks: &AgentSettingsLocks,
+   dir: &Path,
+) -> Result<BTreeMap<String, (PromptTemplate, PathBuf)>, AgentSettingsError> {
+   let entries = match fs::read_dir(dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+      Err(e) => return Err(AgentSettingsError::io(dir, e)),
+   };
+   let mut ids: Vec<String> = Vec::new();
+   for entry in entries {
+      let path = entry.map_err(|e| AgentSettingsError::io(dir, e))?.path();
+      let is_template = path
+         .extension()
+         .and_then(|extension| extension.to_str())
+         .is_some_and(|extension| {
+            TEMPLATE_FORMATS
+               .iter()
+               .any(|(known, _)| *known == extension)
+         });
+      let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+         continue;
+      };
+      if is_template && is_valid_id(id) && !ids.iter().any(|seen| seen == id) {
+         ids.push(id.to_string());
+      }
+   }
+
+   let mut templates = BTreeMap::new();
+   for id in ids {
+      let Some((path, format)) = find_template_file(dir, &id) else {
+         continue;
+      };
+      match load_template(locks, &path, &id, format).await {
+         Ok(Some(template)) => {
+            templates.insert(id, (template, path));
+         }
+         Ok(None) => {}
+         Err(e) => log::warn!("Skipping prompt template {}: {}", path.display(), e),
+      }
+   }
+   Ok(templates)
+}
+
+/// Global templates merged with the project ones in `project`, which replace global templates
+/// with the same id, in id order
+async fn list_from(
+   locks: &AgentSettingsLocks,
+   global: &Path,
+   project: Option<&Path>,
+) -> Result<Vec<PromptTemplateListing>, AgentSettingsError> {
+   let listing = |origin: PromptScope, shadows_global: bool| {
+      move |(template, path): (PromptTemplate, PathBuf)| PromptTemplateListing {
+         template,
+         origin,
+         path: path.display().to_string(),
+         shadows_global,
+      }
+   };
+   let mut merged: BTreeMap<String, PromptTemplateListing> = load_dir(locks, global)
+      .await?
+      .into_iter()
+      .map(|(id, found)| (id, listing(PromptScope::Global, false)(found)))
+      .collect();
+   if let Some(project) = project {
+      for (id, found) in load_dir(locks, project).await? {
+         let shadows_global = merged.contains_key(&id);
+         merged.insert(id, listing(PromptScope::Project, shadows_global)(found));
+      }
+   }
+   Ok(merged.into_values().collect())
+}
+
+/// Write `template` into `dir`, keeping the format of the file it is already kept in
+async fn save_to(
+   locks: &AgentSettingsLocks,
+   dir: &Path,
+   template: &PromptTemplate,
+) -> Result<(PromptTemplate, PathBuf), AgentSettingsError> {
+   let template = validate_template(template)?;
+   let (path, format) = find_template_file(dir, &template.id).unwrap_or_else(|| {
+      (
+         dir.join(format!("{}.toml", template.id)),
+         ConfigFormat::Toml,
+      )
+   });
+   let mut definition =
+      serde_json::to_value(&template).map_err(|e| AgentSettingsError::Serialize {
+         format,
+         message: e.to_string(),
+      })?;
+   if let Value::Object(map) = &mut definition {
+      // The id is the file name
+      map.remove("id");
+   }
+
+   update_config_file(locks, &path, format, WriteOptions::default(), |value| {
+      *value = definition;
+      Ok(())
+   })
+   .await?;
+   Ok((template, path))
+}
+
+/// Remove template `id` from `dir`, returning whether there was one
+async fn remove_from(
+   locks: &AgentSettingsLocks,
+   dir: &Path,
+   id: &str,
+) -> Result<bool, AgentSettingsError> {
+   let Some((path, _)) = find_template_file(dir, id) else {
+      return Ok(false);
+   };
+   let lock = locks.lock_for(&path).await;
+   let _guard = lock.write().await;
+   if was_created(&path) {
+      remove_created(&path)?;
+   } else {
+      fs::remove_file(&path).map_err(|e| AgentSettingsError::io(&path, e))?;
+   }
+   Ok(true)
+}
+
+/// Template `id`, from the workspace's `.athas/prompts/` if it is there and from
+/// `~/.athas/prompts/` otherwise
+pub(super) async fn find_prompt_template(
+   locks: &AgentSettingsLocks,
+   workspace_path: Option<&str>,
+   id: &str,
+) -> Result<Option<PromptTemplate>, AgentSettingsError> {
+   if !is_valid_id(id) {
+      return Ok(None);
+   }
+   let dirs = workspace_path
+      .map(project_dir)
+      .into_iter()
+      .chain(std::iter::once(global_dir()?));
+   for dir in dirs {
+      if let Some((path, format)) = find_template_file(&dir, id) {
+         return load_template(locks, &path, id, format).await;
+      }
+   }
+   Ok(None)
+}
+
+/// Prompt templates from `~/.athas/prompts/`, and from the workspace's `.athas/prompts/` when a
+/// workspace is open. Project templates replace global ones with the same id.
+#[command]
+pub async fn list_prompt_templates(
+   locks: State<'_, AgentSettingsLocks>,
+   workspace_path: Option<String>,
+) -> Result<Vec<PromptTemplateListing>, AgentSettingsError> {
+   let project = workspace_path.as_deref().map(project_dir);
+   list_from(&locks, &global_dir()?, project.as_deref()).await
+}
+
+/// Create or replace a prompt template, globally by default or in the workspace with
+/// `scope: "project"`
+#[command]
+pub async fn save_prompt_template(
+   locks: State<'_, AgentSettingsLocks>,
+   template: PromptTemplate,
+   scope: Option<PromptScope>,
+   workspace_path: Option<String>,
+) -> Result<PromptTemplateListing, AgentSettingsError> {
+   let origin = scope.unwrap_or_default();
+   let dir = scope_dir(origin, workspace_path.as_deref())?;
+   let (template, path) = save_to(&locks, &dir, &template).await?;
+   let shadows_global =
+      origin == PromptScope::Project && find_template_file(&global_dir()?, &template.id).is_some();
+   log::info!(
+      "Saved prompt template {} to {}",
+      template.id,
+      path.display()
+   );
+   Ok(PromptTemplateListing {
+      template,
+      origin,
+      path: path.display().to_string(),
+      shadows_global,
+   })
+}
+
+/// Delete a prompt template from one scope, the global one by default. A global template a
+/// deleted project one shadowed is listed again. Deleting one that doesn't exist is an
+/// `UnknownPromptTemplate` error.
+#[command]
+pub async fn delete_prompt_template(
+   locks: State<'_, AgentSettingsLocks>,
+   id: String,
+   scope: Option<PromptScope>,
+   workspace_path: Option<String>,
+) -> Result<(), AgentSettingsError> {
+   let dir = scope_dir(scope.unwrap_or_default(), workspace_path.as_deref())?;
+   if !is_valid_id(&id) || !remove_from(&locks, &dir, &id).await? {
+      return Err(AgentSettingsError::UnknownPromptTemplate { id });
+   }
+   log::info!("Deleted prompt template {}", id);
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn template(id: &str, title: &str) -> PromptTemplate {
+      PromptTemplate {
+         id: id.into(),
+         title: title.into(),
+         body: "Review {{file_path}} for {{focus}}".into(),
+         variables: vec![PromptVariable {
+            name: "focus".into(),
+            description: Some("What to look for".into()),
+            default: None,
+         }],
+         tags: vec!["review".into()],
+      }
+   }
+
+   #[tokio::test]
+   async fn test_project_templates_shadow_global_ones() {
+      let dir = tempfile::tempdir().unwrap();
+      let global = dir.path().join("home/.athas/prompts");
+      let project = dir.path().join("repo/.athas/prompts");
+      let locks = AgentSettingsLocks::new();
+
+      save_to(&locks, &global, &template("review", "Review"))
+         .await
+         .unwrap();
+      save_to(&locks, &global, &template("explain", "Explain"))
+         .await
+         .unwrap();
+      assert!(global.join("review.toml").is_file());
+
+      // Templates written by hand as JSON are read, and saving keeps them JSON
+      fs::create_dir_all(&project).unwrap();
+      fs::write(
+         project.join("review.json"),
+         r#"{ "title": "Team review", "body": "Check {{file_path}}" }"#,
+      )
+      .unwrap();
+      fs::write(project.join("broken.toml"), "title = ").unwrap();
+      fs::write(project.join("notes.txt"), "not a template").unwrap();
+
+      let listed = list_from(&locks, &global, Some(&project)).await.unwrap();
+      let summary: Vec<_> = listed
+         .iter()
+         .map(|listing| {
+            (
+               listing.template.id.as_str(),
+               listing.template.title.as_str(),
+               listing.origin,
+               listing.shadows_global,
+            )
+         })
+         .collect();
+      assert_eq!(
+         summary,
+         vec![
+            ("explain", "Explain", PromptScope::Global, false),
+            ("review", "Team review", PromptScope::Project, true),
+         ]
+      );
+      assert!(listed[1].template.variables.is_empty());
+
+      let (saved, path) = save_to(&locks, &project, &template("review", "  Team review "))
+         .await
+         .unwrap();
+      assert_eq!(saved.title, "Team review");
+      assert_eq!(path, project.join("review.json"));
+      assert!(!project.join("review.toml").exists());
+
+      assert!(remove_from(&locks, &project, "review").await.unwrap());
+      assert!(!remove_from(&locks, &project, "review").await.unwrap());
+      let listed = list_from(&locks, &global, Some(&project)).await.unwrap();
+      assert_eq!(listed[1].origin, PromptScope::Global);
+      assert_eq!(listed[1].template, template("review", "Review"));
+   }
+
+   #[test]
+   fn test_validation() {
+      let mut duplicate = template("review", "Review");
+      duplicate.tags = vec![" review ".into(), "review".into(), "".into()];
+      assert_eq!(
+         validate_template(&duplicate).unwrap().tags,
+         vec!["review".to_string()]
+      );
+
+      let mut invalid = vec![
+         template("Review", "Review"),
+         template("../review", "Review"),
+         template("review", " "),
+      ];
+      let mut bad_variable = template("review", "Review");
+      bad_variable.variables[0].name = "focus area".into();
+      invalid.push(bad_variable);
+      let mut repeated = template("review", "Review");
+      repeated.variables.push(repeated.variables[0].clone());
+      invalid.push(repeated);
+      for template in invalid {
+         assert!(
+            matches!(
+               validate_template(&template),
+               Err(AgentSettingsError::InvalidPromptTemplate { .. })
+            ),
+            "{:?}",
+            template
+         );
+      }
+   }
+}
@@ -0,0 +1,274 @@
+use super::{
+   custom_agents::{all_agents, find_agent},
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   keys::get_nested_value,
+   paths::resolve_settings_path,
+   secret_migration::secret_value,
+   secrets::mask_secret,
+   storage::{AgentSettingsLocks, read_config_file},
+};
+use crate::commands::ai::{
+   agent_registry::KnownAgent,
+   credentials::{CredentialSource, CredentialStore, api_key_env_var},
+   model_catalog::ModelProvider,
+};
+use serde::Serialize;
+use tauri::{State, command};
+
+/// Where a provider's API key was found
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum CredentialOrigin {
+   /// Stored by Athas
+   #[serde(rename_all = "camelCase")]
+   Athas { source: CredentialSource },
+   #[serde(rename_all = "camelCase")]
+   EnvVar { name: String },
+   /// Written in plaintext in an agent's own config
+   #[serde(rename_all = "camelCase")]
+   AgentConfig {
+      agent_id: String,
+      settings_path: String,
+      key_path: String,
+   },
+}
+
+/// The first place a provider's API key was found. The key itself is never included.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedCredential {
+   pub origin: CredentialOrigin,
+   /// Masked key such as `sk-...abcd`
+   pub preview: String,
+}
+
+/// Variables the provider's key is read from, in order
+fn env_var_names(provider: &str) -> Vec<String> {
+   match ModelProvider::from_id(provider) {
+      Some(provider) => provider
+         .api_key_env_vars()
+         .iter()
+         .map(|name| name.to_string())
+         .collect(),
+      None => vec![api_key_env_var(provider)],
+   }
+}
+
+/// The key at one of `agent`'s key paths for `provider`. Config files that are missing or don't
+/// parse are skipped.
+async fn agent_config_key(
+   locks: &AgentSettingsLocks,
+   agent: &KnownAgent,
+   provider: &str,
+) -> Option<(CredentialOrigin, String)> {
+   // Collected up front, as a borrowing iterator held across the read would keep the command's
+   // future from being `Send`
+   let key_paths: Vec<_> = agent
+      .api_key_paths
+      .iter()
+      .filter(|path| path.provider == provider)
+      .collect();
+   if key_paths.is_empty() {
+      return None;
+   }
+
+   let format = ConfigFormat::from_path(&agent.settings_path);
+   let file = match resolve_settings_path(&agent.settings_path) {
+      Ok(path) => read_config_file(locks, &path, format).await,
+      Err(e) => Err(e),
+   };
+   let value = match file {
+      Ok(file) => file?.value,
+      Err(e) => {
+         log::debug!("Skipping {} while resolving credentials: {}", agent.id, e);
+         return None;
+      }
+   };
+   key_paths.into_iter().find_map(|path| {
+      let key = get_nested_value(&value, &path.key_path).and_then(secret_value)?;
+      let origin = CredentialOrigin::AgentConfig {
+         agent_id: agent.id.clone(),
+         settings_path: agent.settings_path.clone(),
+         key_path: path.key_path.clone(),
+      };
+      Some((origin, key.to_string()))
+   })
+}
+
+/// Find `provider`'s key in the credential store, then its environment variables, then the
+/// config files of `agents`
+async fn resolve(
+   locks: &AgentSettingsLocks,
+   store: &CredentialStore,
+   provider: &str,
+   agents: &[KnownAgent],
+   env: impl Fn(&str) -> Option<String>,
+) -> Result<Option<ResolvedCredential>, AgentSettingsError> {
+   let found = |origin, key: &str| {
+      Some(ResolvedCredential {
+         origin,
+         preview: mask_secret(key),
+      })
+   };
+
+   let source = store
+      .source(provider)
+      .map_err(|message| AgentSettingsError::Credentials { message })?;
+   let stored = store
+      .get(provider)
+      .map_err(|message| AgentSettingsError::Credentials { message })?;
+   if let (Some(source), Some(key)) = (source, stored) {
+      return Ok(found(CredentialOrigin::Athas { source }, &key));
+   }
+
+   for name in env_var_names(provider) {
+      if let Some(key) = env(&name).filter(|key| !key.trim().is_empty()) {
+         return Ok(found(CredentialOrigin::EnvVar { name }, key.trim()));
+      }
+   }
+
+   for agent in agents {
+      if let Some((origin, key)) = agent_config_key(locks, agent, provider).await {
+         return Ok(found(origin, &key));
+      }
+   }
+   Ok(None)
+}
+
+/// Find where `provider`'s API key would come from: Athas' credential store, then the
+/// provider's environment variables, then the key paths in agents' own configs. Only the
+/// config of `agent_id` is searched when given, otherwise every known agent's. Returns `None`
+/// when no key was found.
+#[command]
+pub async fn resolve_provider_credential(
+   locks: State<'_, AgentSettingsLocks>,
+   credentials: State<'_, CredentialStore>,
+   provider: String,
+   agent_id: Option<String>,
+) -> Result<Option<ResolvedCredential>, AgentSettingsError> {
+   let agents = match agent_id {
+      Some(agent_id) => match find_agent(&locks, &agent_id).await? {
+         Some(agent) => vec![agent],
+         None => return Err(AgentSettingsError::UnknownAgent { agent_id }),
+      },
+      None => all_agents(&locks).await?,
+   };
+   let resolved = resolve(&locks, &credentials, &provider, &agents, |name| {
+      std::env::var(name).ok()
+   })
+   .await?;
+   log::debug!(
+      "Resolved credential for provider {}: {:?}",
+      provider,
+      resolved.as_ref().map(|resolved| &resolved.origin)
+   );
+   Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::{agent_registry::find_known_agent, credentials::MockKeychain};
+   use std::collections::HashMap;
+
+   #[tokio::test]
+   async fn test_resolution_order() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = CredentialStore::new(
+         Box::new(MockKeychain::unavailable()),
+         &dir.path().join("store"),
+      );
+      let locks = AgentSettingsLocks::new();
+
+      let config = dir.path().join("aider.conf.yml");
+      std::fs::write(&config, "anthropic-api-key: sk-ant-from-config-1234\n").unwrap();
+      let mut aider = find_known_agent("aider").unwrap();
+      aider.settings_path = config.to_string_lossy().into_owned();
+      let agents = [find_known_agent("codex-cli").unwrap(), aider];
+
+      let mut env = HashMap::from([("ANTHROPIC_API_KEY", "")]);
+      let resolved = resolve(&locks, &store, "anthropic", &agents, |name| {
+         env.get(name).map(|key| key.to_string())
+      })
+      .await
+      .unwrap()
+      .unwrap();
+      assert_eq!(
+         resolved.origin,
+         CredentialOrigin::AgentConfig {
+            agent_id: "aider".into(),
+            settings_path: agents[1].settings_path.clone(),
+            key_path: "anthropic-api-key".into(),
+         }
+      );
+      assert_eq!(resolved.preview, "sk-...1234");
+
+      env.insert("ANTHROPIC_API_KEY", "sk-ant-from-env-5678");
+      let resolved = resolve(&locks, &store, "anthropic", &agents, |name| {
+         env.get(name).map(|key| key.to_string())
+      })
+      .await
+      .unwrap()
+      .unwrap();
+      assert_eq!(
+         resolved.origin,
+         CredentialOrigin::EnvVar {
+            name: "ANTHROPIC_API_KEY".into()
+         }
+      );
+
+      store.set("anthropic", "sk-ant-stored-9012").unwrap();
+      let resolved = resolve(&locks, &store, "anthropic", &agents, |name| {
+         env.get(name).map(|key| key.to_string())
+      })
+      .await
+      .unwrap()
+      .unwrap();
+      assert_eq!(
+         resolved.origin,
+         CredentialOrigin::Athas {
+            source: CredentialSource::EncryptedFile
+         }
+      );
+      assert!(!serde_json::to_string(&resolved).unwrap().contains("stored"));
+      assert_eq!(
+         serde_json::to_value(&resolved).unwrap(),
+         serde_json::json!({
+            "origin": { "type": "athas", "source": "encryptedFile" },
+            "preview": "sk-...9012",
+         })
+      );
+   }
+
+   #[tokio::test]
+   async fn test_unresolved_provider() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = CredentialStore::new(Box::new(MockKeychain::unavailable()), dir.path());
+      let locks = AgentSettingsLocks::new();
+      let mut aider = find_known_agent("aider").unwrap();
+      aider.settings_path = dir
+         .path()
+         .join("missing.yml")
+         .to_string_lossy()
+         .into_owned();
+
+      let env = |name: &str| (name == "GROQ_API_KEY").then(|| "gsk_0123456789abcdef".to_string());
+      assert!(
+         resolve(&locks, &store, "openai", &[aider], env)
+            .await
+            .unwrap()
+            .is_none()
+      );
+      assert_eq!(
+         resolve(&locks, &store, "groq", &[], env)
+            .await
+            .unwrap()
+            .unwrap()
+            .origin,
+         CredentialOrigin::EnvVar {
+            name: "GROQ_API_KEY".into()
+         }
+      );
+   }
+}
@@ -0,0 +1,314 @@
+use super::{
+   backup::{AgentSettingsBackup, list_backups, quarantine_corrupt_file},
+   custom_agents::find_agent,
+   encryption::decode,
+   error::AgentSettingsError,
+   format::{ConfigFormat, parse_config, parse_config_lenient},
+   history::{AgentSettingsHistory, Journal, diff_values},
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   settings::SettingsKeys,
+   storage::{AgentSettingsLocks, WriteOptions, check_size, into_text, write_atomic, write_config},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{fs, path::Path};
+use tauri::{State, command};
+
+/// How `recover_agent_settings` brings back a config file that fails to parse. Both move the
+/// broken file aside as `<name>.corrupt-<timestamp>` first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryStrategy {
+   /// Write a fresh file holding only the settings Athas manages, carried over from the broken
+   /// file when a lenient parse can still read them
+   QuarantineAndReset,
+   /// Put back a backup from the rotation: the one named, or the newest that parses
+   RestoreBackup,
+}
+
+/// What a `recover_agent_settings` call found and did
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsRecovery {
+   /// Why the file can't be read, `None` when it parses and there was nothing to recover
+   pub error: Option<AgentSettingsError>,
+   /// Whether a lenient parse, which ignores NUL padding and anything after the first JSON
+   /// document, reads the file. When it does, resetting keeps the settings Athas manages.
+   pub parses_leniently: bool,
+   /// Names of the backups that parse, newest first
+   pub usable_backups: Vec<String>,
+   /// Whether the file was replaced. False for a dry run and for a file that parses.
+   pub recovered: bool,
+   /// Where the broken file was moved
+   pub quarantined_path: Option<String>,
+   /// The backup that was put back, for `restore_backup`
+   pub restored_backup: Option<String>,
+   /// Settings carried over into the fresh file, for `quarantine_and_reset`
+   pub kept_keys: Vec<String>,
+}
+
+/// The config file's content as text when it can be read as such, and the error it fails to
+/// read or parse with
+async fn diagnose(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   format: ConfigFormat,
+) -> Result<(Option<String>, Option<AgentSettingsError>), AgentSettingsError> {
+   let metadata = tokio::fs::metadata(path)
+      .await
+      .map_err(|e| AgentSettingsError::io(path, e))?;
+   if let Err(error) = check_size(path, &metadata, None) {
+      return Ok((None, Some(error)));
+   }
+   let bytes = tokio::fs::read(path)
+      .await
+      .map_err(|e| AgentSettingsError::io(path, e))?;
+   let content =
+      match into_text(path, bytes).and_then(|text| decode(locks.encryption(), path, text)) {
+         Ok(content) => content,
+         Err(error) => return Ok((None, Some(error))),
+      };
+   let error = parse_config(&content, format).err();
+   Ok((Some(content), error))
+}
+
+/// Backups of the config file at `path` that parse, newest first
+fn usable_backups(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   format: ConfigFormat,
+) -> Result<Vec<AgentSettingsBackup>, AgentSettingsError> {
+   Ok(list_backups(path)?
+      .into_iter()
+      .filter(|backup| {
+         fs::read_to_string(&backup.path)
+            .ok()
+            .and_then(|content| decode(locks.encryption(), path, content).ok())
+            .is_some_and(|content| parse_config(&content, format).is_ok())
+      })
+      .collect())
+}
+
+/// Recover an agent's config file that fails to parse, is too large or isn't text, so the
+/// settings commands can read and write it again. The file is first diagnosed and the result
+/// reports whether a lenient parse reads it and which backups parse, for the UI to suggest the
+/// least destructive strategy; with `dry_run` nothing else happens. Otherwise the broken file is
+/// moved aside as `<name>.corrupt-<timestamp>` and replaced as `strategy` says. A file that
+/// parses is left alone.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn recover_agent_settings(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   settings_path: String,
+   strategy: RecoveryStrategy,
+   backup_name: Option<String>,
+   dry_run: Option<bool>,
+   follow_symlinks: Option<bool>,
+) -> Result<AgentSettingsRecovery, AgentSettingsError> {
+   let path = roots.check(
+      &resolve_settings_path(&settings_path)?,
+      follow_symlinks.unwrap_or(false),
+   )?;
+   let format = ConfigFormat::from_path(&settings_path);
+   let agent = find_agent(&locks, &agent_id).await?;
+
+   let lock = locks.lock_for(&path).await;
+   let _guard = lock.write().await;
+
+   let (content, error) = diagnose(&locks, &path, format).await?;
+   let salvaged = content
+      .as_deref()
+      .and_then(|content| parse_config_lenient(content, format));
+   let backups = usable_backups(&locks, &path, format)?;
+   let mut recovery = AgentSettingsRecovery {
+      error,
+      parses_leniently: salvaged.is_some(),
+      usable_backups: backups.iter().map(|backup| backup.name.clone()).collect(),
+      ..AgentSettingsRecovery::default()
+   };
+   if recovery.error.is_none() || dry_run.unwrap_or(false) {
+      return Ok(recovery);
+   }
+
+   match strategy {
+      RecoveryStrategy::QuarantineAndReset => {
+         let mut value = Value::Object(Map::new());
+         if let (Some(agent), Some(salvaged)) = (&agent, &salvaged) {
+            let keys = SettingsKeys::for_agent(agent);
+            let settings = keys.read(salvaged);
+            recovery.kept_keys = keys.written_keys(&settings);
+            keys.write(&mut value, settings)?;
+         }
+         let quarantined = quarantine_corrupt_file(&path)?;
+         let written = write_config(
+            &locks,
+            &path,
+            value.clone(),
+            format,
+            content.as_deref(),
+            WriteOptions::default(),
+         )
+         .await;
+         let stored = match written {
+            Ok(stored) => stored,
+            Err(error) => {
+               // Put the broken file back rather than leave no file at all
+               let _ = fs::rename(&quarantined, &path);
+               return Err(error);
+            }
+         };
+         locks.record_write(&path, &stored).await;
+         Journal {
+            history: &history,
+            agent_id: &agent_id,
+         }
+         .record(
+            &locks,
+            &path,
+            diff_values(
+               salvaged.as_ref().unwrap_or(&Value::Object(Map::new())),
+               &value,
+            ),
+         )
+         .await;
+         recovery.quarantined_path = Some(quarantined.display().to_string());
+      }
+      RecoveryStrategy::RestoreBackup => {
+         let backup = match &backup_name {
+            Some(name) => list_backups(&path)?
+               .into_iter()
+               .find(|backup| &backup.name == name)
+               .ok_or_else(|| AgentSettingsError::NotFound { path: name.clone() })?,
+            None => {
+               backups
+                  .into_iter()
+                  .next()
+                  .ok_or_else(|| AgentSettingsError::NoUsableBackup {
+                     path: path.display().to_string(),
+                  })?
+            }
+         };
+         let stored = fs::read_to_string(&backup.path)
+            .map_err(|e| AgentSettingsError::io(Path::new(&backup.path), e))?;
+         let quarantined = quarantine_corrupt_file(&path)?;
+         if let Err(error) = write_atomic(&path, &stored) {
+            let _ = fs::rename(&quarantined, &path);
+            return Err(error);
+         }
+         locks.record_write(&path, &stored).await;
+         recovery.quarantined_path = Some(quarantined.display().to_string());
+         recovery.restored_backup = Some(backup.name);
+      }
+   }
+
+   recovery.recovered = true;
+   log::warn!(
+      "Recovered unreadable settings of agent {} with {:?}, broken file kept at {}",
+      agent_id,
+      strategy,
+      recovery.quarantined_path.as_deref().unwrap_or_default()
+   );
+   Ok(recovery)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_settings::backup::create_backup;
+   use tauri::Manager;
+
+   fn mock_app(root: &Path) -> tauri::App<tauri::test::MockRuntime> {
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+      let roots = AgentSettingsRoots::new();
+      roots.allow(root.to_path_buf());
+      app.manage(roots);
+      app.manage(AgentSettingsHistory::at(None, 0));
+      app
+   }
+
+   fn quarantined_files(dir: &Path) -> Vec<String> {
+      fs::read_dir(dir)
+         .unwrap()
+         .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+         .filter(|name| name.contains(".corrupt-"))
+         .collect()
+   }
+
+   #[tokio::test]
+   async fn test_recovery_strategies() {
+      let root = tempfile::tempdir().unwrap();
+      let app = mock_app(root.path());
+      let dir = root.path().join(".claude");
+      fs::create_dir_all(&dir).unwrap();
+      let path = dir.join("settings.json");
+      let settings_path = path.to_string_lossy().to_string();
+      let recover = |strategy, dry_run| {
+         recover_agent_settings(
+            app.state(),
+            app.state(),
+            app.state(),
+            "claude-code".into(),
+            settings_path.clone(),
+            strategy,
+            None,
+            Some(dry_run),
+            None,
+         )
+      };
+
+      // A file that parses is left alone
+      fs::write(&path, "{\"model\": \"opus\"}").unwrap();
+      let result = recover(RecoveryStrategy::QuarantineAndReset, false)
+         .await
+         .unwrap();
+      assert!(result.error.is_none() && !result.recovered);
+
+      create_backup(&path, "{\"model\": \"sonnet\"}", 5).unwrap();
+      let broken = "{\"model\": \"opus\", \"theme\": \"dark\"}\n}";
+      fs::write(&path, broken).unwrap();
+      let result = recover(RecoveryStrategy::QuarantineAndReset, true)
+         .await
+         .unwrap();
+      assert!(matches!(
+         result.error,
+         Some(AgentSettingsError::Parse { .. })
+      ));
+      assert!(result.parses_leniently);
+      assert_eq!(result.usable_backups.len(), 1);
+      assert_eq!(fs::read_to_string(&path).unwrap(), broken);
+
+      let result = recover(RecoveryStrategy::QuarantineAndReset, false)
+         .await
+         .unwrap();
+      assert!(result.recovered);
+      assert_eq!(result.kept_keys, ["model"]);
+      let value: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+      assert_eq!(value, serde_json::json!({ "model": "opus" }));
+      let quarantined = quarantined_files(&dir);
+      assert_eq!(quarantined.len(), 1);
+      assert_eq!(
+         fs::read_to_string(dir.join(&quarantined[0])).unwrap(),
+         broken
+      );
+
+      fs::write(&path, [0xff, 0xfe, 0x00, 0x01]).unwrap();
+      let result = recover(RecoveryStrategy::RestoreBackup, false)
+         .await
+         .unwrap();
+      assert!(matches!(
+         result.error,
+         Some(AgentSettingsError::NotText { .. })
+      ));
+      assert!(!result.parses_leniently);
+      assert!(result.restored_backup.is_some());
+      assert_eq!(
+         fs::read_to_string(&path).unwrap(),
+         "{\"model\": \"sonnet\"}"
+      );
+      assert_eq!(quarantined_files(&dir).len(), 2);
+   }
+}
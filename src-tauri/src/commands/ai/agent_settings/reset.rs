@@ -0,0 +1,168 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   keys::{delete_nested_value, set_nested_value, validate_key_path},
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   storage::{AgentSettingsLocks, WriteOptions, update_config_file},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use tauri::{State, command};
+
+/// What a `reset_agent_settings` call did
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsResetResult {
+   /// Whether the config file changed at all
+   pub wrote: bool,
+   /// Whether the file was deleted because it was left empty and Athas had created it
+   pub removed_file: bool,
+}
+
+/// Return each of `keys` to its default: the value in `defaults` when there is one, otherwise
+/// the key is deleted (pruning objects it leaves empty) so the agent uses its built-in default.
+/// Keys that only appear in `defaults` are set as well. Returns whether anything changed.
+fn reset_keys(
+   value: &mut Value,
+   keys: Vec<String>,
+   mut defaults: HashMap<String, Value>,
+) -> Result<bool, AgentSettingsError> {
+   let before = value.clone();
+   let keys: BTreeSet<String> = keys.into_iter().chain(defaults.keys().cloned()).collect();
+   for key in keys {
+      match defaults.remove(&key).filter(|default| !default.is_null()) {
+         Some(default) => set_nested_value(value, &key, default)?,
+         None => {
+            delete_nested_value(value, &key, true)?;
+         }
+      }
+   }
+   Ok(*value != before)
+}
+
+/// Reset the settings Athas manages for an agent without touching keys the user set by hand.
+/// With `remove_if_empty`, a file Athas created that ends up empty is deleted instead.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn reset_agent_settings(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   settings_path: String,
+   keys: Vec<String>,
+   defaults: Option<HashMap<String, Value>>,
+   remove_if_empty: Option<bool>,
+   follow_symlinks: Option<bool>,
+) -> Result<AgentSettingsResetResult, AgentSettingsError> {
+   let defaults = defaults.unwrap_or_default();
+   keys
+      .iter()
+      .chain(defaults.keys())
+      .try_for_each(|key| validate_key_path(key))?;
+   let path = roots.check(
+      &resolve_settings_path(&settings_path)?,
+      follow_symlinks.unwrap_or(false),
+   )?;
+   let format = ConfigFormat::from_path(&settings_path);
+
+   let options = WriteOptions {
+      remove_if_empty: remove_if_empty.unwrap_or(false),
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &agent_id,
+      }),
+      ..WriteOptions::default()
+   };
+   let wrote = update_config_file(&locks, &path, format, options, |value| {
+      reset_keys(value, keys, defaults).map(|_| ())
+   })
+   .await?;
+   let removed_file = wrote && !path.exists();
+
+   if wrote {
+      log::info!(
+         "Reset settings for agent {}{}",
+         agent_id,
+         if removed_file {
+            " and removed the file"
+         } else {
+            ""
+         }
+      );
+   }
+   Ok(AgentSettingsResetResult {
+      wrote,
+      removed_file,
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+   use std::fs;
+
+   #[test]
+   fn test_reset_keeps_unrelated_keys() {
+      let mut value = json!({
+         "model": "opus",
+         "reasoning": { "effort": "high" },
+         "permissions": { "allow": ["Read"] },
+         "theme": "dark",
+      });
+
+      let changed = reset_keys(
+         &mut value,
+         vec!["model".into(), "reasoning.effort".into(), "preview".into()],
+         HashMap::from([("permissions.allow".to_string(), json!([]))]),
+      )
+      .unwrap();
+
+      assert!(changed);
+      assert_eq!(
+         value,
+         json!({ "permissions": { "allow": [] }, "theme": "dark" })
+      );
+      assert!(!reset_keys(&mut value, vec!["model".into()], HashMap::new()).unwrap());
+   }
+
+   #[tokio::test]
+   async fn test_reset_removes_only_files_athas_created() {
+      let dir = tempfile::tempdir().unwrap();
+      let locks = AgentSettingsLocks::new();
+      let created = dir.path().join("created.json");
+      let users = dir.path().join("users.json");
+      fs::write(&users, r#"{ "model": "opus" }"#).unwrap();
+
+      let set_model = |value: &mut Value| set_nested_value(value, "model", json!("sonnet"));
+      for path in [&created, &users] {
+         update_config_file(
+            &locks,
+            path,
+            ConfigFormat::Json,
+            WriteOptions::default(),
+            set_model,
+         )
+         .await
+         .unwrap();
+      }
+
+      let options = WriteOptions {
+         remove_if_empty: true,
+         ..WriteOptions::default()
+      };
+      for path in [&created, &users] {
+         update_config_file(&locks, path, ConfigFormat::Json, options, |value| {
+            reset_keys(value, vec!["model".into()], HashMap::new()).map(|_| ())
+         })
+         .await
+         .unwrap();
+      }
+
+      assert!(!created.exists());
+      assert_eq!(fs::read_to_string(&users).unwrap().trim(), "{}");
+   }
+}
@@ -0,0 +1,409 @@
+use super::{
+   custom_agents::find_agent,
+   doctor::env_references,
+   error::AgentSettingsError,
+   explore::{AgentConfigFile, ConfigSearchWarning, walk},
+   format::ConfigFormat,
+   keys::{delete_nested_value, get_nested_value, set_nested_value, validate_key_path},
+   paths::{AgentSettingsRoots, resolve_settings_path},
+   secrets::{is_secret_path, keychain_references, mask_secret},
+   storage::{AgentSettingsLocks, WriteOptions, read_config_file, update_config_file},
+};
+use crate::commands::ai::{
+   agent_registry::SecretStrategy,
+   credentials::{CredentialSource, CredentialStore, api_key_env_var},
+   model_catalog::ModelProvider,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use tauri::{State, command};
+
+/// A plaintext secret in an agent's config file. The value itself is never included.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretFinding {
+   pub agent_id: String,
+   pub settings_path: String,
+   pub key_path: String,
+   /// Masked value such as `sk-...abcd`
+   pub preview: String,
+   /// Provider the key seems to belong to, guessed from its key path and format
+   pub provider: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretScanResults {
+   pub findings: Vec<SecretFinding>,
+   pub warnings: Vec<ConfigSearchWarning>,
+}
+
+/// What a `migrate_secret_to_keychain` call did
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretMigrationResult {
+   pub source: CredentialSource,
+   /// Variable the agent should read the key from
+   pub env_var: String,
+   /// What the value in the config was replaced with, `None` when the key was removed
+   pub replacement: Option<String>,
+}
+
+/// The value as a secret Athas could move: a non-empty string that doesn't already refer to an
+/// environment variable or to a secret in the credential store
+pub(super) fn secret_value(value: &Value) -> Option<&str> {
+   let text = value.as_str()?.trim();
+   let mut references = BTreeSet::new();
+   env_references(value, &mut references);
+   let in_keychain = !matches!(keychain_references(text), Ok(found) if found.is_empty());
+   (!text.is_empty() && references.is_empty() && !in_keychain).then_some(text)
+}
+
+fn guess_provider(key_path: &str, secret: &str) -> Option<String> {
+   let key_path = key_path.to_ascii_lowercase();
+   let provider = if key_path.contains("anthropic") || secret.starts_with("sk-ant-") {
+      ModelProvider::Anthropic
+   } else if key_path.contains("gemini")
+      || key_path.contains("google")
+      || secret.starts_with("AIza")
+   {
+      ModelProvider::Google
+   } else if key_path.contains("openai")
+      || (secret.starts_with("sk-") && !secret.starts_with("sk-or-"))
+   {
+      ModelProvider::OpenAi
+   } else {
+      return None;
+   };
+   Some(provider.id().to_string())
+}
+
+/// Plaintext secrets under secret-looking keys anywhere in `value`
+fn scan_value(agent_id: &str, settings_path: &str, value: &Value) -> Vec<SecretFinding> {
+   let mut findings = Vec::new();
+   walk(value, "", 1, None, &mut |path, value| {
+      let Some(secret) = secret_value(value).filter(|_| is_secret_path(path)) else {
+         return;
+      };
+      findings.push(SecretFinding {
+         agent_id: agent_id.to_string(),
+         settings_path: settings_path.to_string(),
+         key_path: path.to_string(),
+         preview: mask_secret(secret),
+         provider: guess_provider(path, secret),
+      });
+   });
+   findings
+}
+
+/// Store the secret at `key_path` under `provider`, then replace it in `value` according to
+/// `strategy`. Returns where the secret was stored.
+fn move_secret(
+   value: &mut Value,
+   key_path: &str,
+   provider: &str,
+   strategy: &SecretStrategy,
+   store: &CredentialStore,
+) -> Result<CredentialSource, AgentSettingsError> {
+   let secret = get_nested_value(value, key_path)
+      .and_then(secret_value)
+      .ok_or_else(|| AgentSettingsError::SecretNotFound {
+         key: key_path.to_string(),
+      })?;
+   let source = store
+      .set(provider, secret)
+      .map_err(|message| AgentSettingsError::Credentials { message })?;
+   match strategy.replacement(&api_key_env_var(provider)) {
+      Some(reference) => set_nested_value(value, key_path, Value::String(reference))?,
+      None => {
+         delete_nested_value(value, key_path, true)?;
+      }
+   }
+   Ok(source)
+}
+
+/// Put the key stored for `provider` back to `previous` after a migration that stored a new one
+/// failed to write the config, removing it when there was none
+fn restore_credential(store: &CredentialStore, provider: &str, previous: Option<&str>) {
+   let restored = match previous {
+      Some(previous) => store.set(provider, previous).map(|_| ()),
+      None => store.delete(provider).map(|_| ()),
+   };
+   if let Err(error) = restored {
+      log::warn!(
+         "Failed to restore the key stored for provider '{}' after a failed migration: {}",
+         provider,
+         error
+      );
+   }
+}
+
+/// Look through agent config files for API keys, tokens and other secrets stored in plaintext.
+/// Only key paths and masked previews are returned; values that already refer to an
+/// environment variable are not reported. Unreadable files are reported as warnings and missing
+/// ones are skipped.
+#[command]
+pub async fn scan_agent_configs_for_secrets(
+   locks: State<'_, AgentSettingsLocks>,
+   entries: Vec<AgentConfigFile>,
+) -> Result<SecretScanResults, AgentSettingsError> {
+   let mut results = SecretScanResults {
+      findings: Vec::new(),
+      warnings: Vec::new(),
+   };
+   for entry in entries {
+      let file = match resolve_settings_path(&entry.settings_path) {
+         Ok(path) => {
+            read_config_file(&locks, &path, ConfigFormat::from_path(&entry.settings_path)).await
+         }
+         Err(e) => Err(e),
+      };
+      match file {
+         Ok(Some(file)) => results.findings.extend(scan_value(
+            &entry.agent_id,
+            &entry.settings_path,
+            &file.value,
+         )),
+         Ok(None) => {}
+         Err(error) => results.warnings.push(ConfigSearchWarning {
+            agent_id: entry.agent_id,
+            settings_path: entry.settings_path,
+            error,
+         }),
+      }
+   }
+
+   log::debug!(
+      "Found {} plaintext secrets in agent configs",
+      results.findings.len()
+   );
+   Ok(results)
+}
+
+/// Move a plaintext secret from an agent's config into the credential store under `provider`,
+/// replacing any key already stored for it. In the config the value becomes an environment
+/// variable reference for agents that expand them and is removed for the others. The write is
+/// deliberately neither backed up nor recorded in the settings history, as both would keep a
+/// copy of the secret. The secret is stored before the config is written, so it is never only
+/// in memory; if the write fails, the credential store goes back to what it held before.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn migrate_secret_to_keychain(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   credentials: State<'_, CredentialStore>,
+   agent_id: String,
+   settings_path: String,
+   key_path: String,
+   provider: String,
+   follow_symlinks: Option<bool>,
+) -> Result<SecretMigrationResult, AgentSettingsError> {
+   validate_key_path(&key_path)?;
+   let Some(agent) = find_agent(&locks, &agent_id).await? else {
+      return Err(AgentSettingsError::UnknownAgent { agent_id });
+   };
+   let path = roots.check(
+      &resolve_settings_path(&settings_path)?,
+      follow_symlinks.unwrap_or(false),
+   )?;
+   let format = ConfigFormat::from_path(&settings_path);
+   let previous = credentials
+      .get(&provider)
+      .map_err(|message| AgentSettingsError::Credentials { message })?;
+
+   let mut source = None;
+   let written = update_config_file(&locks, &path, format, WriteOptions::default(), |value| {
+      source = Some(move_secret(
+         value,
+         &key_path,
+         &provider,
+         &agent.secret_strategy,
+         &credentials,
+      )?);
+      Ok(())
+   })
+   .await;
+   if let Err(error) = written {
+      if source.is_some() {
+         restore_credential(&credentials, &provider, previous.as_deref());
+      }
+      return Err(error);
+   }
+   let Some(source) = source else {
+      return Err(AgentSettingsError::SecretNotFound { key: key_path });
+   };
+
+   let env_var = api_key_env_var(&provider);
+   log::info!(
+      "Moved secret at {} of agent {} to {}",
+      key_path,
+      agent_id,
+      source.describe()
+   );
+   Ok(SecretMigrationResult {
+      source,
+      replacement: agent.secret_strategy.replacement(&env_var),
+      env_var,
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::{
+      agent_registry::find_known_agent, agent_settings::encryption::ConfigEncryption,
+      credentials::MockKeychain,
+   };
+   use serde_json::json;
+   use tauri::Manager;
+
+   #[test]
+   fn test_scan_reports_masked_plaintext_secrets() {
+      let config = json!({
+         "env": {
+            "ANTHROPIC_API_KEY": "sk-ant-REDACTED",
+            "OPENAI_API_KEY": "${OPENAI_API_KEY}",
+            "DISABLE_TELEMETRY": "1",
+         },
+         "provider": { "openrouter": { "options": { "apiKey": "sk-or-v1-aaaabbbbccccdddd" } } },
+         "mcpServers": {
+            "github": { "headers": { "Authorization": "Bearer ghp_x" } },
+            "linear": { "env": { "LINEAR_API_KEY": "{{keychain:linear}}" } },
+         },
+         "model_providers": { "proxy": { "env_key": "PROXY_KEY" } },
+         "token": "",
+         "max_tokens": 4096,
+      });
+
+      let findings = scan_value("opencode", "~/.config/opencode/opencode.json", &config);
+      let found: Vec<_> = findings
+         .iter()
+         .map(|f| {
+            (
+               f.key_path.as_str(),
+               f.preview.as_str(),
+               f.provider.as_deref(),
+            )
+         })
+         .collect();
+      assert_eq!(
+         found,
+         vec![
+            ("env.ANTHROPIC_API_KEY", "sk-...wxyz", Some("anthropic")),
+            ("mcpServers.github.headers.Authorization", "...", None),
+            ("provider.openrouter.options.apiKey", "sk-...dddd", None),
+         ]
+      );
+      let serialized = serde_json::to_string(&findings).unwrap();
+      assert!(!serialized.contains("0123456789"));
+      assert!(!serialized.contains("ghp_"));
+   }
+
+   #[test]
+   fn test_move_secret_follows_agent_strategy() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = CredentialStore::new(Box::new(MockKeychain::unavailable()), dir.path());
+      let opencode = find_known_agent("opencode").unwrap();
+      let claude = find_known_agent("claude-code").unwrap();
+
+      let mut config = json!({
+         "provider": { "anthropic": { "options": { "apiKey": "sk-ant-secret" } } },
+      });
+      let source = move_secret(
+         &mut config,
+         "provider.anthropic.options.apiKey",
+         "anthropic",
+         &opencode.secret_strategy,
+         &store,
+      )
+      .unwrap();
+      assert_eq!(source, CredentialSource::EncryptedFile);
+      assert_eq!(
+         config,
+         json!({
+            "provider": { "anthropic": { "options": { "apiKey": "{env:ANTHROPIC_API_KEY}" } } },
+         })
+      );
+      assert_eq!(
+         store.get("anthropic").unwrap().as_deref(),
+         Some("sk-ant-secret")
+      );
+
+      // Already a reference now, so there is nothing left to move
+      assert!(matches!(
+         move_secret(
+            &mut config,
+            "provider.anthropic.options.apiKey",
+            "anthropic",
+            &opencode.secret_strategy,
+            &store,
+         ),
+         Err(AgentSettingsError::SecretNotFound { .. })
+      ));
+
+      let mut config = json!({ "env": { "ANTHROPIC_API_KEY": "sk-ant-other" }, "model": "opus" });
+      move_secret(
+         &mut config,
+         "env.ANTHROPIC_API_KEY",
+         "anthropic",
+         &claude.secret_strategy,
+         &store,
+      )
+      .unwrap();
+      assert_eq!(config, json!({ "model": "opus" }));
+      assert_eq!(
+         store.get("anthropic").unwrap().as_deref(),
+         Some("sk-ant-other")
+      );
+   }
+
+   #[tokio::test]
+   async fn test_failed_write_restores_credential_store() {
+      let dir = tempfile::tempdir().unwrap();
+      // One of Athas' own config files, so it is encrypted once encryption at rest is on
+      let config = dir.path().join("settings.toml");
+      let content = "[env]\nANTHROPIC_API_KEY = \"sk-ant-plaintext\"\n";
+      std::fs::write(&config, content).unwrap();
+      // Encryption at rest is on but its key can't be loaded, so the write fails after the
+      // secret was stored
+      std::fs::write(dir.path().join(".athas-encrypted"), "").unwrap();
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::with_encryption(ConfigEncryption::new(
+         Box::new(MockKeychain::unavailable()),
+         dir.path(),
+      )));
+      app.manage(AgentSettingsRoots::with_home(dir.path().to_path_buf()));
+      let store_dir = tempfile::tempdir().unwrap();
+      app.manage(CredentialStore::new(
+         Box::new(MockKeychain::unavailable()),
+         store_dir.path(),
+      ));
+      let migrate = || {
+         migrate_secret_to_keychain(
+            app.state(),
+            app.state(),
+            app.state(),
+            "claude-code".into(),
+            config.to_string_lossy().into_owned(),
+            "env.ANTHROPIC_API_KEY".into(),
+            "anthropic".into(),
+            None,
+         )
+      };
+      let stored = || app.state::<CredentialStore>().get("anthropic").unwrap();
+
+      assert!(matches!(
+         migrate().await,
+         Err(AgentSettingsError::Encryption { .. })
+      ));
+      assert_eq!(stored(), None);
+
+      app.state::<CredentialStore>()
+         .set("anthropic", "sk-ant-earlier")
+         .unwrap();
+      assert!(migrate().await.is_err());
+      assert_eq!(stored().as_deref(), Some("sk-ant-earlier"));
+      assert_eq!(std::fs::read_to_string(&config).unwrap(), content);
+   }
+}
@@ -0,0 +1,1502 @@
+use super::{
+   backup::DEFAULT_BACKUP_RETENTION,
+   codex_profiles::profile_key,
+   custom_agents::find_agent,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   keys::{
+      delete_nested_value, get_nested_value, set_nested_value, to_dotted_path, validate_key_path,
+   },
+   layers::{SettingsScope, project_layer_paths},
+   models::check_model,
+   patch::{ArrayStrategy, WriteMode, combine},
+   paths::AgentSettingsRoots,
+   secrets::{mask_secret_values, redacted},
+   storage::{
+      AgentSettingsLocks, ConfigFile, ConfigFileMeta, MISSING_VERSION, ReadOptions, WriteOptions,
+      read_config_file, read_config_file_with, update_config_file,
+   },
+   write_buffer::{buffer_set, flush_path, flush_pending_writes},
+};
+use crate::commands::ai::agent_registry::{AgentCapabilities, KnownAgent};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+   collections::HashMap,
+   path::{Path, PathBuf},
+};
+use tauri::{AppHandle, Runtime, State, command};
+
+/// Settings Athas manages inside an agent's own config file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettings {
+   pub model: Option<String>,
+   pub preview_features: Option<bool>,
+   pub reasoning_effort: Option<String>,
+   pub temperature: Option<f64>,
+   pub max_tokens: Option<u64>,
+   pub allowed_tools: Option<Vec<String>>,
+   /// Version token of the file the settings were read from, to pass back as
+   /// `expected_version` when saving. Ignored when writing.
+   pub version: Option<String>,
+   /// The file the settings were read from, as of the read
+   #[serde(skip_deserializing)]
+   pub meta: Option<ConfigFileMeta>,
+}
+
+impl AgentSettings {
+   /// Whether no setting has a value, as when a settings panel closes without changes
+   fn is_empty(&self) -> bool {
+      self.model.is_none()
+         && self.preview_features.is_none()
+         && self.reasoning_effort.is_none()
+         && self.temperature.is_none()
+         && self.max_tokens.is_none()
+         && self.allowed_tools.is_none()
+   }
+}
+
+/// Where each managed setting lives in an agent's config file. Settings without a key are
+/// neither read nor written.
+#[derive(Debug, Clone, Default)]
+pub(super) struct SettingsKeys {
+   pub model: String,
+   pub preview: Option<String>,
+   pub reasoning: Option<String>,
+   pub temperature: Option<String>,
+   pub max_tokens: Option<String>,
+   pub tools: Option<String>,
+}
+
+impl SettingsKeys {
+   /// The keys the registry lists for `agent`
+   pub(super) fn for_agent(agent: &KnownAgent) -> Self {
+      Self {
+         model: agent.model_key.clone(),
+         preview: agent.preview_key.clone(),
+         reasoning: agent.reasoning_key.clone(),
+         ..Self::default()
+      }
+   }
+
+   /// The same keys inside profile `name`'s table, where Codex looks for per-profile overrides
+   fn in_profile(self, name: &str) -> Result<Self, AgentSettingsError> {
+      let prefix = profile_key(name);
+      let nest =
+         |key: &str| Ok::<_, AgentSettingsError>(format!("{}.{}", prefix, to_dotted_path(key)?));
+      let nest_optional = |key: Option<String>| key.as_deref().map(nest).transpose();
+      Ok(Self {
+         model: nest(&self.model)?,
+         preview: nest_optional(self.preview)?,
+         reasoning: nest_optional(self.reasoning)?,
+         temperature: nest_optional(self.temperature)?,
+         max_tokens: nest_optional(self.max_tokens)?,
+         tools: nest_optional(self.tools)?,
+      })
+   }
+
+   pub(super) fn validate(&self) -> Result<(), AgentSettingsError> {
+      std::iter::once(self.model.as_str())
+         .chain(self.preview.as_deref())
+         .chain(self.reasoning.as_deref())
+         .chain(self.temperature.as_deref())
+         .chain(self.max_tokens.as_deref())
+         .chain(self.tools.as_deref())
+         .try_for_each(validate_key_path)
+   }
+
+   pub(super) fn read(&self, value: &Value) -> AgentSettings {
+      let lookup =
+         |key: &Option<String>| key.as_deref().and_then(|key| get_nested_value(value, key));
+
+      AgentSettings {
+         model: get_nested_value(value, &self.model)
+            .and_then(|v| v.as_str())
+            .map(String::from),
+         preview_features: lookup(&self.preview).and_then(|v| v.as_bool()),
+         reasoning_effort: lookup(&self.reasoning)
+            .and_then(|v| v.as_str())
+            .map(String::from),
+         temperature: lookup(&self.temperature).and_then(|v| v.as_f64()),
+         max_tokens: lookup(&self.max_tokens).and_then(as_whole_number),
+         allowed_tools: lookup(&self.tools).and_then(as_string_list),
+         version: None,
+         meta: None,
+      }
+   }
+
+   /// Read the settings from a config file in `format`, or the defaults when it doesn't exist
+   pub(super) fn read_file(
+      &self,
+      file: Option<&ConfigFile>,
+      format: ConfigFormat,
+   ) -> AgentSettings {
+      match file {
+         Some(file) => AgentSettings {
+            version: Some(file.version.clone()),
+            meta: Some(file.meta.clone()),
+            ..self.read(&file.value)
+         },
+         None => AgentSettings {
+            version: Some(MISSING_VERSION.to_string()),
+            meta: Some(ConfigFileMeta::missing(format)),
+            ..AgentSettings::default()
+         },
+      }
+   }
+
+   /// The settings that are both present in `settings` and have a key, with their keys
+   fn updates(&self, settings: AgentSettings) -> Vec<(String, Value)> {
+      let updates = [
+         (Some(&self.model), settings.model.map(Value::String)),
+         (
+            self.preview.as_ref(),
+            settings.preview_features.map(Value::Bool),
+         ),
+         (
+            self.reasoning.as_ref(),
+            settings.reasoning_effort.map(Value::String),
+         ),
+         (
+            self.temperature.as_ref(),
+            settings.temperature.map(Value::from),
+         ),
+         (
+            self.max_tokens.as_ref(),
+            settings.max_tokens.map(Value::from),
+         ),
+         (self.tools.as_ref(), settings.allowed_tools.map(Value::from)),
+      ];
+      updates
+         .into_iter()
+         .filter_map(|(key, new_value)| Some((key?.clone(), new_value?)))
+         .collect()
+   }
+
+   /// Keys a write of `settings` sets
+   pub(super) fn written_keys(&self, settings: &AgentSettings) -> Vec<String> {
+      self
+         .updates(settings.clone())
+         .into_iter()
+         .map(|(key, _)| key)
+         .collect()
+   }
+
+   /// Write every setting that is both present in `settings` and has a key
+   pub(super) fn write(
+      &self,
+      value: &mut Value,
+      settings: AgentSettings,
+   ) -> Result<(), AgentSettingsError> {
+      self
+         .updates(settings)
+         .into_iter()
+         .try_for_each(|(key, new_value)| set_nested_value(value, &key, new_value))
+   }
+}
+
+/// What a `set_agent_settings` call did
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsWriteResult {
+   /// False when there was nothing to change, in which case the file was left untouched (and not
+   /// created if it didn't exist)
+   pub wrote: bool,
+   /// Problems that didn't stop the write, such as a model id missing from the catalog
+   pub warnings: Vec<String>,
+   /// True when the write was buffered to be merged with later sets to the same file. A failure
+   /// to write it is reported through `agent-settings-write-failed`.
+   pub pending: bool,
+}
+
+/// Which agent's settings to read or write, and where. For agents in the registry the settings
+/// path, model, preview and reasoning keys can be left out and are filled in from the registry;
+/// any that are given take precedence. `get_agent_settings`, `get_agent_settings_batch` and
+/// `set_agent_settings` all take one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsRequest {
+   pub agent_id: String,
+   #[serde(default)]
+   pub settings_path: Option<String>,
+   #[serde(default)]
+   pub model_key: Option<String>,
+   pub preview_key: Option<String>,
+   pub reasoning_key: Option<String>,
+   pub temperature_key: Option<String>,
+   pub max_tokens_key: Option<String>,
+   pub tools_key: Option<String>,
+}
+
+impl AgentSettingsRequest {
+   /// The settings path and keys of the request, with what it leaves out taken from `known`, the
+   /// agent's registry entry
+   fn resolve(
+      &self,
+      known: Option<&KnownAgent>,
+   ) -> Result<(String, SettingsKeys), AgentSettingsError> {
+      let unknown = || AgentSettingsError::UnknownAgent {
+         agent_id: self.agent_id.clone(),
+      };
+      let settings_path = match &self.settings_path {
+         Some(settings_path) => settings_path.clone(),
+         None => known.ok_or_else(unknown)?.settings_path.clone(),
+      };
+      let keys = SettingsKeys {
+         model: match &self.model_key {
+            Some(model_key) => model_key.clone(),
+            None => known.ok_or_else(unknown)?.model_key.clone(),
+         },
+         preview: self
+            .preview_key
+            .clone()
+            .or_else(|| known?.preview_key.clone()),
+         reasoning: self
+            .reasoning_key
+            .clone()
+            .or_else(|| known?.reasoning_key.clone()),
+         temperature: self.temperature_key.clone(),
+         max_tokens: self.max_tokens_key.clone(),
+         tools: self.tools_key.clone(),
+      };
+      keys.validate()?;
+      Ok((settings_path, keys))
+   }
+}
+
+/// Read a non-negative whole number, accepting floats without a fractional part (`4096.0`)
+fn as_whole_number(value: &Value) -> Option<u64> {
+   value.as_u64().or_else(|| {
+      value
+         .as_f64()
+         .filter(|f| f.fract() == 0.0 && *f >= 0.0 && *f <= u64::MAX as f64)
+         .map(|f| f as u64)
+   })
+}
+
+/// Read an array of strings, skipping entries of any other type
+fn as_string_list(value: &Value) -> Option<Vec<String>> {
+   value.as_array().map(|items| {
+      items
+         .iter()
+         .filter_map(|item| item.as_str().map(String::from))
+         .collect()
+   })
+}
+
+/// Read the model, preview, reasoning, sampling and tool settings from an agent's config file,
+/// after writing out any set still buffered for it. With `bypass_cache`, the file is re-read
+/// even if it looks unchanged since the last read. Files over `max_file_bytes`, 5 MB by default,
+/// are refused with `FileTooLarge` before they are read.
+#[command]
+pub async fn get_agent_settings<R: Runtime>(
+   app_handle: AppHandle<R>,
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   request: AgentSettingsRequest,
+   bypass_cache: Option<bool>,
+   max_file_bytes: Option<u64>,
+) -> Result<AgentSettings, AgentSettingsError> {
+   let known = find_agent(&locks, &request.agent_id).await?;
+   let (settings_path, keys) = request.resolve(known.as_ref())?;
+   let path = roots.resolve(&settings_path)?;
+   flush_path(&app_handle, &path).await;
+
+   let format = ConfigFormat::from_path(&settings_path);
+   let options = ReadOptions {
+      bypass_cache: bypass_cache.unwrap_or(false),
+      max_file_bytes,
+   };
+   let file = read_config_file_with(&locks, &path, format, options).await?;
+   let settings = keys.read_file(file.as_ref(), format);
+   log::debug!(
+      "Loaded settings for agent {}: model={:?}, preview={:?}, reasoning={:?}",
+      request.agent_id,
+      redacted(Some(&keys.model), &settings.model),
+      redacted(keys.preview.as_deref(), &settings.preview_features),
+      redacted(keys.reasoning.as_deref(), &settings.reasoning_effort)
+   );
+
+   Ok(settings)
+}
+
+/// Read settings for several agents at once, after writing out any buffered sets. Each distinct
+/// config file is read and parsed once, and a failure for one entry is reported in its slot
+/// without failing the others.
+#[command]
+pub async fn get_agent_settings_batch<R: Runtime>(
+   app_handle: AppHandle<R>,
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   requests: Vec<AgentSettingsRequest>,
+) -> Result<Vec<Result<AgentSettings, AgentSettingsError>>, AgentSettingsError> {
+   flush_pending_writes(&app_handle).await;
+   Ok(load_settings_batch(&locks, &roots, &requests).await)
+}
+
+async fn load_settings_batch(
+   locks: &AgentSettingsLocks,
+   roots: &AgentSettingsRoots,
+   requests: &[AgentSettingsRequest],
+) -> Vec<Result<AgentSettings, AgentSettingsError>> {
+   let targets = requests.iter().map(|request| async move {
+      let known = find_agent(locks, &request.agent_id).await?;
+      let (settings_path, keys) = request.resolve(known.as_ref())?;
+      Ok::<_, AgentSettingsError>((roots.resolve(&settings_path)?, keys))
+   });
+   let resolved = join_all(targets).await;
+   let mut paths: Vec<&PathBuf> = resolved
+      .iter()
+      .filter_map(|target| target.as_ref().ok().map(|(path, _)| path))
+      .collect();
+   paths.sort_unstable();
+   paths.dedup();
+
+   let reads = paths.iter().map(|path| async move {
+      let format = ConfigFormat::from_path(&path.to_string_lossy());
+      (*path, read_config_file(locks, path, format).await)
+   });
+   let documents: HashMap<&PathBuf, Result<Option<ConfigFile>, AgentSettingsError>> =
+      join_all(reads).await.into_iter().collect();
+
+   let results = requests
+      .iter()
+      .zip(&resolved)
+      .map(|(request, target)| {
+         let result = match target {
+            Ok((path, keys)) => match &documents[path] {
+               Ok(file) => {
+                  let format = ConfigFormat::from_path(&path.to_string_lossy());
+                  Ok(keys.read_file(file.as_ref(), format))
+               }
+               Err(error) => Err(error.clone()),
+            },
+            Err(error) => Err(error.clone()),
+         };
+         if let Err(error) = &result {
+            log::warn!(
+               "Failed to load settings for agent {}: {}",
+               request.agent_id,
+               error
+            );
+         }
+         result
+      })
+      .collect();
+
+   log::debug!(
+      "Loaded settings for {} agents from {} files",
+      requests.len(),
+      paths.len()
+   );
+   results
+}
+
+async fn load_config_values(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   settings_path: &str,
+   keys: Vec<String>,
+   reveal_secrets: bool,
+   options: ReadOptions,
+) -> Result<HashMap<String, Value>, AgentSettingsError> {
+   keys.iter().try_for_each(|key| validate_key_path(key))?;
+   let format = ConfigFormat::from_path(settings_path);
+   let Some(file) = read_config_file_with(locks, path, format, options).await? else {
+      return Ok(HashMap::new());
+   };
+   Ok(lookup_values(&file.value, keys, reveal_secrets))
+}
+
+/// Read arbitrary values from an agent's config file in one pass. Keys that are absent from the
+/// file are left out of the result, while keys explicitly set to null map to `null`. Secrets
+/// come back as `{ masked: true, preview, length }`; `reveal_agent_config_secret` is the only
+/// way to read one. With `bypass_cache`, the file is re-read even if it looks unchanged, and
+/// `max_file_bytes` replaces the 5 MB limit on its size.
+#[command]
+pub async fn get_agent_config_values(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   agent_id: String,
+   settings_path: String,
+   keys: Vec<String>,
+   bypass_cache: Option<bool>,
+   max_file_bytes: Option<u64>,
+) -> Result<HashMap<String, Value>, AgentSettingsError> {
+   let path = roots.resolve(&settings_path)?;
+   let options = ReadOptions {
+      bypass_cache: bypass_cache.unwrap_or(false),
+      max_file_bytes,
+   };
+   let values = load_config_values(&locks, &path, &settings_path, keys, false, options).await?;
+   log::debug!(
+      "Loaded {} config values for agent {}",
+      values.len(),
+      agent_id
+   );
+   Ok(values)
+}
+
+/// Whether `path` is one of the files `agent` keeps its settings in: its global one, or a project
+/// or local file in any workspace
+fn is_agent_settings_file(roots: &AgentSettingsRoots, agent: &KnownAgent, path: &Path) -> bool {
+   if roots
+      .resolve(&agent.settings_path)
+      .is_ok_and(|global| global == path)
+   {
+      return true;
+   }
+   agent.project_settings.as_ref().is_some_and(|project| {
+      std::iter::once(&project.project_path)
+         .chain(&project.local_path)
+         .any(|relative| path.ends_with(relative))
+   })
+}
+
+/// Read one value from an agent's config file without masking secrets, for a "show" button
+/// next to a masked field. `None` when the key is absent. `settings_path` must be one of the
+/// agent's own settings files and inside the allowed directories, so this can't read secrets
+/// out of any other file.
+#[command]
+pub async fn reveal_agent_config_secret(
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   agent_id: String,
+   settings_path: String,
+   key_path: String,
+) -> Result<Option<Value>, AgentSettingsError> {
+   let agent =
+      find_agent(&locks, &agent_id)
+         .await?
+         .ok_or_else(|| AgentSettingsError::UnknownAgent {
+            agent_id: agent_id.clone(),
+         })?;
+   let path = roots.check(&roots.resolve(&settings_path)?, false)?;
+   if !is_agent_settings_file(&roots, &agent, &path) {
+      return Err(AgentSettingsError::PathNotAllowed {
+         path: path.display().to_string(),
+         message: format!("not a settings file of agent {}", agent_id),
+      });
+   }
+   let mut values = load_config_values(
+      &locks,
+      &path,
+      &settings_path,
+      vec![key_path.clone()],
+      true,
+      ReadOptions::default(),
+   )
+   .await?;
+   log::info!("Revealed {} of agent {}", key_path, agent_id);
+   Ok(values.remove(&key_path))
+}
+
+/// Drop every cached parse of a config file, so the next read of each goes to disk
+#[command]
+pub async fn clear_settings_cache(
+   locks: State<'_, AgentSettingsLocks>,
+) -> Result<(), AgentSettingsError> {
+   locks.clear_cache();
+   log::debug!("Cleared the agent settings cache");
+   Ok(())
+}
+
+/// Look up `keys`, masking secrets unless `reveal_secrets` is set
+fn lookup_values(value: &Value, keys: Vec<String>, reveal_secrets: bool) -> HashMap<String, Value> {
+   keys
+      .into_iter()
+      .filter_map(|key| {
+         let mut found = get_nested_value(value, &key)?.clone();
+         if !reveal_secrets {
+            mask_secret_values(&key, &mut found);
+         }
+         Some((key, found))
+      })
+      .collect()
+}
+
+/// Reject settings the agent's capabilities rule out, such as a reasoning effort for an agent
+/// without one
+fn check_capabilities(
+   agent_id: &str,
+   capabilities: &AgentCapabilities,
+   settings: &AgentSettings,
+) -> Result<(), AgentSettingsError> {
+   let unsupported = |setting: &str, message: String| AgentSettingsError::UnsupportedSetting {
+      agent_id: agent_id.to_string(),
+      setting: setting.to_string(),
+      message,
+   };
+
+   if settings.reasoning_effort.is_some() && !capabilities.supports_reasoning {
+      return Err(unsupported(
+         "reasoningEffort",
+         "the agent has no reasoning setting".into(),
+      ));
+   }
+   if settings.preview_features.is_some() && !capabilities.supports_preview {
+      return Err(unsupported(
+         "previewFeatures",
+         "the agent has no preview features setting".into(),
+      ));
+   }
+   if settings.temperature.is_some() && !capabilities.supports_temperature {
+      return Err(unsupported(
+         "temperature",
+         "the agent has no temperature setting".into(),
+      ));
+   }
+   Ok(())
+}
+
+/// Reject a reasoning effort outside the values the agent accepts, which would otherwise stop the
+/// agent from starting
+fn check_values(
+   keys: &SettingsKeys,
+   capabilities: &AgentCapabilities,
+   settings: &AgentSettings,
+) -> Result<(), AgentSettingsError> {
+   if let (Some(key), Some(effort)) = (&keys.reasoning, &settings.reasoning_effort)
+      && !capabilities.reasoning_values.is_empty()
+      && !capabilities.reasoning_values.contains(effort)
+   {
+      return Err(AgentSettingsError::InvalidValue {
+         key: key.clone(),
+         value: Value::String(effort.clone()),
+         allowed: capabilities.reasoning_values.clone(),
+      });
+   }
+   Ok(())
+}
+
+/// How `set_agent_settings` writes. Everything is off or unset when left out.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SetAgentSettingsOptions {
+   pub backup: bool,
+   /// Backups kept with `backup`, 5 when not given
+   pub backup_retention: Option<usize>,
+   pub overwrite_on_parse_error: bool,
+   pub expected_version: Option<String>,
+   pub follow_symlinks: bool,
+   pub layer: SettingsScope,
+   pub project_path: Option<String>,
+   pub local_path: Option<String>,
+   pub workspace_path: Option<String>,
+   pub profile: Option<String>,
+   pub force: bool,
+   pub agent_version: Option<String>,
+   pub validate_model: bool,
+   pub skip_validation: bool,
+   pub coalesce: bool,
+   pub max_file_bytes: Option<u64>,
+}
+
+/// Write the model, preview, reasoning, sampling and tool settings into an agent's config file,
+/// at the keys `request` names. The `version` of `settings` is ignored; pass it as the
+/// `expected_version` option instead.
+///
+/// The `layer` option picks the file that receives the write: the request's `settings_path` for
+/// `global`, or `project_path` / `local_path`. Those default to the agent's project files in
+/// `workspace_path` when the registry lists them. With `profile`, the keys are written under
+/// `profiles.<profile>` instead of the top level, as Codex reads per-profile overrides there.
+///
+/// Settings a known agent doesn't support are rejected, using the capabilities of
+/// `agent_version` when given, unless `force` is set, and so is a reasoning effort outside the
+/// agent's allowed values unless `skip_validation` is set. With `validate_model`, a model missing
+/// from the agent's catalog adds a warning to the result but is still written, since catalogs lag
+/// behind agent releases.
+///
+/// With `coalesce`, the write is buffered and merged with other sets to the same file until the
+/// coalescing window (`ai.agentSettings.writeCoalesceMs`, 300ms by default) has passed, and the
+/// result is returned as pending. Writes that need `backup`, `expected_version` or
+/// `overwrite_on_parse_error` are never buffered, and write out anything pending for the file
+/// first.
+///
+/// An existing file over `max_file_bytes`, 5 MB by default, is refused with `FileTooLarge`.
+#[command]
+pub async fn set_agent_settings<R: Runtime>(
+   app_handle: AppHandle<R>,
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   request: AgentSettingsRequest,
+   settings: AgentSettings,
+   options: Option<SetAgentSettingsOptions>,
+) -> Result<AgentSettingsWriteResult, AgentSettingsError> {
+   let SetAgentSettingsOptions {
+      backup,
+      backup_retention,
+      overwrite_on_parse_error,
+      expected_version,
+      follow_symlinks,
+      layer,
+      project_path,
+      local_path,
+      workspace_path,
+      profile,
+      force,
+      agent_version,
+      validate_model,
+      skip_validation,
+      coalesce,
+      max_file_bytes,
+   } = options.unwrap_or_default();
+   let agent_id = request.agent_id.as_str();
+   let agent = find_agent(&locks, agent_id).await?;
+   let (settings_path, keys) = request.resolve(agent.as_ref())?;
+   let keys = match profile.as_deref() {
+      Some(profile) => keys.in_profile(profile)?,
+      None => keys,
+   };
+   if settings.is_empty() {
+      return Ok(AgentSettingsWriteResult {
+         wrote: false,
+         warnings: Vec::new(),
+         pending: false,
+      });
+   }
+   let mut warnings = Vec::new();
+   if let Some(agent) = &agent {
+      let capabilities = agent.capabilities_for(agent_version.as_deref());
+      if !force {
+         check_capabilities(agent_id, capabilities, &settings)?;
+      }
+      if !skip_validation {
+         check_values(&keys, capabilities, &settings)?;
+      }
+      if validate_model && let Some(model) = &settings.model {
+         warnings.extend(check_model(&locks, agent, model).await?);
+      }
+   }
+
+   let (registry_project_path, registry_local_path) = match (&agent, workspace_path.as_deref()) {
+      (Some(agent), Some(workspace_path)) => project_layer_paths(agent, workspace_path),
+      _ => (None, None),
+   };
+   let project_path = project_path.or(registry_project_path);
+   let local_path = local_path.or(registry_local_path);
+   let settings_path = layer.select(
+      &settings_path,
+      project_path.as_deref(),
+      local_path.as_deref(),
+   )?;
+   let path = roots.check(&roots.resolve(settings_path)?, follow_symlinks)?;
+   let format = ConfigFormat::from_path(settings_path);
+   let direct = backup || expected_version.is_some() || overwrite_on_parse_error;
+   if coalesce && !direct {
+      buffer_set(
+         &app_handle,
+         &path,
+         agent_id,
+         format,
+         max_file_bytes,
+         keys,
+         settings,
+      )
+      .await;
+      return Ok(AgentSettingsWriteResult {
+         wrote: false,
+         warnings,
+         pending: true,
+      });
+   }
+   flush_path(&app_handle, &path).await;
+
+   let options = WriteOptions {
+      backup_retention: backup.then(|| backup_retention.unwrap_or(DEFAULT_BACKUP_RETENTION)),
+      overwrite_on_parse_error,
+      expected_version: expected_version.as_deref(),
+      journal: Some(Journal {
+         history: &history,
+         agent_id,
+      }),
+      max_file_bytes,
+      ..WriteOptions::default()
+   };
+
+   let wrote = update_config_file(&locks, &path, format, options, |value| {
+      keys.write(value, settings)
+   })
+   .await?;
+
+   if wrote {
+      log::info!("Saved settings for agent {}", agent_id);
+   }
+   Ok(AgentSettingsWriteResult {
+      wrote,
+      warnings,
+      pending: false,
+   })
+}
+
+/// Write several values into an agent's config file in a single read-modify-write pass. Values
+/// keep their JSON types, and a null value deletes the key. In `merge` mode each value is applied
+/// as a JSON Merge Patch to what the key already holds, so writing `{"new": {...}}` to
+/// `mcpServers` adds a server without touching the others. Returns the keys whose stored value
+/// actually changed. An existing file over `max_file_bytes`, 5 MB by default, is refused. Sets
+/// still buffered for the file are written out first, so they can't land over these values.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn set_agent_config_values<R: Runtime>(
+   app_handle: AppHandle<R>,
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   settings_path: String,
+   values: HashMap<String, Value>,
+   follow_symlinks: Option<bool>,
+   mode: Option<WriteMode>,
+   arrays: Option<ArrayStrategy>,
+   max_file_bytes: Option<u64>,
+) -> Result<Vec<String>, AgentSettingsError> {
+   values.keys().try_for_each(|key| validate_key_path(key))?;
+   let path = roots.check(
+      &roots.resolve(&settings_path)?,
+      follow_symlinks.unwrap_or(false),
+   )?;
+   let format = ConfigFormat::from_path(&settings_path);
+   flush_path(&app_handle, &path).await;
+
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &agent_id,
+      }),
+      max_file_bytes,
+      ..WriteOptions::default()
+   };
+   let mut changed = Vec::new();
+   update_config_file(&locks, &path, format, options, |value| {
+      changed = apply_values(
+         value,
+         values,
+         mode.unwrap_or_default(),
+         arrays.unwrap_or_default(),
+      )?;
+      Ok(())
+   })
+   .await?;
+
+   log::info!(
+      "Saved config values for agent {}: changed={:?}",
+      agent_id,
+      changed
+   );
+   Ok(changed)
+}
+
+/// Apply `values` to a document in key order, returning the keys whose value changed
+pub(super) fn apply_values(
+   value: &mut Value,
+   values: HashMap<String, Value>,
+   mode: WriteMode,
+   arrays: ArrayStrategy,
+) -> Result<Vec<String>, AgentSettingsError> {
+   let mut values: Vec<_> = values.into_iter().collect();
+   values.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+   let mut changed = Vec::new();
+   for (key, new_value) in values {
+      let before = get_nested_value(value, &key).cloned();
+      if new_value.is_null() {
+         delete_nested_value(value, &key, false)?;
+      } else {
+         let new_value = combine(before.clone(), new_value, mode, arrays);
+         set_nested_value(value, &key, new_value)?;
+      }
+      if get_nested_value(value, &key) != before.as_ref() {
+         changed.push(key);
+      }
+   }
+   Ok(changed)
+}
+
+/// Remove a key from an agent's config file so the agent falls back to its own default.
+/// Deleting a key that isn't set, or from a file that doesn't exist, succeeds without changes.
+/// Sets still buffered for the file are written out first.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn delete_agent_setting<R: Runtime>(
+   app_handle: AppHandle<R>,
+   locks: State<'_, AgentSettingsLocks>,
+   roots: State<'_, AgentSettingsRoots>,
+   history: State<'_, AgentSettingsHistory>,
+   agent_id: String,
+   settings_path: String,
+   key: String,
+   prune_empty_parents: Option<bool>,
+   follow_symlinks: Option<bool>,
+) -> Result<(), AgentSettingsError> {
+   validate_key_path(&key)?;
+   let path = roots.check(
+      &roots.resolve(&settings_path)?,
+      follow_symlinks.unwrap_or(false),
+   )?;
+   flush_path(&app_handle, &path).await;
+   if !tokio::fs::try_exists(&path).await.unwrap_or(false) {
+      return Ok(());
+   }
+
+   let format = ConfigFormat::from_path(&settings_path);
+   let prune_empty_parents = prune_empty_parents.unwrap_or(false);
+   let options = WriteOptions {
+      journal: Some(Journal {
+         history: &history,
+         agent_id: &agent_id,
+      }),
+      ..WriteOptions::default()
+   };
+   let mut removed = false;
+   update_config_file(&locks, &path, format, options, |value| {
+      removed = delete_nested_value(value, &key, prune_empty_parents)?;
+      Ok(())
+   })
+   .await?;
+
+   if removed {
+      log::info!("Deleted setting {} for agent {}", key, agent_id);
+   }
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::{
+      agent_registry::find_known_agent,
+      agent_settings::{
+         format::{parse_config, serialize_config},
+         write_buffer::SettingsWriteBuffer,
+      },
+   };
+   use serde_json::json;
+   use tauri::Manager;
+
+   #[test]
+   fn test_lookup_values_distinguishes_null_from_absent() {
+      let value = json!({
+         "model": "o3",
+         "sandbox_mode": null,
+         "tools": { "web_search": true },
+         "models": [{ "model": "gpt-4o" }],
+      });
+      let keys = [
+         "model",
+         "sandbox_mode",
+         "missing",
+         "tools.web_search",
+         "models.0.model",
+      ];
+
+      let values = lookup_values(
+         &value,
+         keys.iter().map(|key| key.to_string()).collect(),
+         false,
+      );
+
+      assert_eq!(values.len(), 4);
+      assert_eq!(values["model"], json!("o3"));
+      assert_eq!(values["sandbox_mode"], Value::Null);
+      assert!(!values.contains_key("missing"));
+      assert_eq!(values["tools.web_search"], json!(true));
+      assert_eq!(values["models.0.model"], json!("gpt-4o"));
+   }
+
+   #[test]
+   fn test_lookup_values_masks_secrets() {
+      let value = json!({
+         "model": "o3",
+         "model_providers": { "proxy": { "api_key": "sk-proxy-0123456789abcd" } },
+      });
+      let keys = vec!["model".to_string(), "model_providers".to_string()];
+
+      let masked = lookup_values(&value, keys.clone(), false);
+      assert_eq!(masked["model"], json!("o3"));
+      assert_eq!(
+         masked["model_providers"]["proxy"]["api_key"],
+         json!({ "masked": true, "preview": "sk-...abcd", "length": 23 })
+      );
+      let revealed = lookup_values(&value, keys, true);
+      assert_eq!(revealed["model_providers"], value["model_providers"]);
+   }
+
+   #[test]
+   fn test_apply_values_reports_changed_keys() {
+      let mut value = json!({
+         "model": "o3",
+         "approval_policy": "on-request",
+         "model_reasoning_effort": "high",
+      });
+      let values = HashMap::from([
+         ("model".to_string(), json!("o3")),
+         ("approval_policy".to_string(), json!("never")),
+         ("model_reasoning_effort".to_string(), Value::Null),
+         ("missing".to_string(), Value::Null),
+         ("tools.web_search".to_string(), json!(true)),
+         (
+            "limits".to_string(),
+            json!({ "max_tokens": 4096, "stop": ["\n\n"] }),
+         ),
+      ]);
+
+      let changed =
+         apply_values(&mut value, values, WriteMode::Set, ArrayStrategy::Replace).unwrap();
+
+      assert_eq!(
+         changed,
+         vec![
+            "approval_policy",
+            "limits",
+            "model_reasoning_effort",
+            "tools.web_search"
+         ]
+      );
+      assert_eq!(
+         value,
+         json!({
+            "model": "o3",
+            "approval_policy": "never",
+            "tools": { "web_search": true },
+            "limits": { "max_tokens": 4096, "stop": ["\n\n"] },
+         })
+      );
+   }
+
+   #[test]
+   fn test_apply_values_to_toml_keeps_native_types() {
+      let content = "model = \"o3\"\n";
+      let mut value = parse_config(content, ConfigFormat::Toml).unwrap();
+      let values = HashMap::from([
+         ("max_tokens".to_string(), json!(4096)),
+         ("temperature".to_string(), json!(0.5)),
+         ("hide_agent_reasoning".to_string(), json!(true)),
+         ("profiles.fast.model".to_string(), json!("o4-mini")),
+      ]);
+
+      apply_values(&mut value, values, WriteMode::Set, ArrayStrategy::Replace).unwrap();
+      let written = serialize_config(value, ConfigFormat::Toml, Some(content)).unwrap();
+
+      let parsed: toml::Value = toml::from_str(&written).unwrap();
+      assert_eq!(parsed["max_tokens"].as_integer(), Some(4096));
+      assert_eq!(parsed["temperature"].as_float(), Some(0.5));
+      assert_eq!(parsed["hide_agent_reasoning"].as_bool(), Some(true));
+      assert_eq!(
+         parsed["profiles"]["fast"]["model"].as_str(),
+         Some("o4-mini")
+      );
+   }
+
+   #[test]
+   fn test_merge_mode_preserves_existing_entries() {
+      let mut value = json!({
+         "mcpServers": {
+            "github": { "command": "gh-mcp", "args": ["--stdio"] },
+         },
+         "permissions": { "allow": ["Read"] },
+      });
+      let values = HashMap::from([
+         (
+            "mcpServers".to_string(),
+            json!({ "new": { "command": "new-mcp" } }),
+         ),
+         (
+            "permissions".to_string(),
+            json!({ "allow": ["Read", "Bash"], "deny": null }),
+         ),
+      ]);
+
+      let changed =
+         apply_values(&mut value, values, WriteMode::Merge, ArrayStrategy::Union).unwrap();
+
+      assert_eq!(changed, vec!["mcpServers", "permissions"]);
+      assert_eq!(
+         value,
+         json!({
+            "mcpServers": {
+               "github": { "command": "gh-mcp", "args": ["--stdio"] },
+               "new": { "command": "new-mcp" },
+            },
+            "permissions": { "allow": ["Read", "Bash"] },
+         })
+      );
+
+      // Without merge mode the object is replaced wholesale
+      let values = HashMap::from([("mcpServers".to_string(), json!({ "only": {} }))]);
+      apply_values(&mut value, values, WriteMode::Set, ArrayStrategy::Replace).unwrap();
+      assert_eq!(value["mcpServers"], json!({ "only": {} }));
+   }
+
+   fn all_keys() -> SettingsKeys {
+      SettingsKeys {
+         model: "model".into(),
+         preview: Some("preview".into()),
+         reasoning: Some("reasoning.effort".into()),
+         temperature: Some("temperature".into()),
+         max_tokens: Some("max_output_tokens".into()),
+         tools: Some("allowed_tools".into()),
+      }
+   }
+
+   #[test]
+   fn test_read_numbers_and_lists() {
+      let value = json!({
+         "model": "o3",
+         "temperature": 1,
+         "max_output_tokens": 4096.0,
+         "allowed_tools": ["Read", "Edit", 3],
+      });
+
+      let settings = all_keys().read(&value);
+      assert_eq!(settings.temperature, Some(1.0));
+      assert_eq!(settings.max_tokens, Some(4096));
+      assert_eq!(
+         settings.allowed_tools,
+         Some(vec!["Read".to_string(), "Edit".to_string()])
+      );
+
+      let fractional = all_keys().read(&json!({ "temperature": 0.7, "max_output_tokens": 0.7 }));
+      assert_eq!(fractional.temperature, Some(0.7));
+      assert_eq!(fractional.max_tokens, None);
+   }
+
+   #[test]
+   fn test_check_capabilities() {
+      let codex = find_known_agent("codex-cli").unwrap();
+      let reasoning = |effort: &str| AgentSettings {
+         reasoning_effort: Some(effort.into()),
+         ..AgentSettings::default()
+      };
+
+      assert!(check_capabilities("codex-cli", &codex.capabilities, &reasoning("high")).is_ok());
+      let claude = find_known_agent("claude-code").unwrap();
+      let preview = AgentSettings {
+         model: Some("opus".into()),
+         preview_features: Some(true),
+         ..AgentSettings::default()
+      };
+      let Err(AgentSettingsError::UnsupportedSetting { setting, .. }) =
+         check_capabilities("claude-code", &claude.capabilities, &preview)
+      else {
+         panic!("preview features should be unsupported");
+      };
+      assert_eq!(setting, "previewFeatures");
+      assert!(matches!(
+         check_capabilities("claude-code", &claude.capabilities, &reasoning("high")),
+         Err(AgentSettingsError::UnsupportedSetting { .. })
+      ));
+   }
+
+   #[test]
+   fn test_check_reasoning_values() {
+      let codex = find_known_agent("codex-cli").unwrap();
+      let keys = SettingsKeys {
+         model: "model".into(),
+         reasoning: Some("model_reasoning_effort".into()),
+         ..SettingsKeys::default()
+      };
+      let reasoning = |effort: &str| AgentSettings {
+         reasoning_effort: Some(effort.into()),
+         ..AgentSettings::default()
+      };
+
+      assert!(check_values(&keys, &codex.capabilities, &reasoning("xhigh")).is_ok());
+      let Err(AgentSettingsError::InvalidValue {
+         key,
+         value,
+         allowed,
+      }) = check_values(&keys, &codex.capabilities, &reasoning("hgih"))
+      else {
+         panic!("a misspelled effort should be rejected");
+      };
+      assert_eq!(key, "model_reasoning_effort");
+      assert_eq!(value, json!("hgih"));
+      assert_eq!(allowed, codex.capabilities.reasoning_values);
+
+      let old = codex.capabilities_for(Some("0.50.0"));
+      assert!(matches!(
+         check_values(&keys, old, &reasoning("xhigh")),
+         Err(AgentSettingsError::InvalidValue { .. })
+      ));
+   }
+
+   #[test]
+   fn test_write_numbers_as_native_toml_types() {
+      let content = "model = \"o3\"\ntemperature = 1\n";
+      let mut value = parse_config(content, ConfigFormat::Toml).unwrap();
+      let settings = AgentSettings {
+         temperature: Some(0.7),
+         max_tokens: Some(8192),
+         allowed_tools: Some(vec!["Read".into(), "Bash".into()]),
+         ..AgentSettings::default()
+      };
+
+      all_keys().write(&mut value, settings).unwrap();
+      let written = serialize_config(value, ConfigFormat::Toml, Some(content)).unwrap();
+
+      assert!(written.contains("temperature = 0.7\n"));
+      assert!(written.contains("max_output_tokens = 8192\n"));
+      assert!(written.contains("allowed_tools = [\"Read\", \"Bash\"]\n"));
+      assert!(written.contains("model = \"o3\""));
+
+      let reread = all_keys().read(&parse_config(&written, ConfigFormat::Toml).unwrap());
+      assert_eq!(reread.temperature, Some(0.7));
+      assert_eq!(reread.max_tokens, Some(8192));
+   }
+
+   #[test]
+   fn test_aider_dashed_keys_round_trip() {
+      let content =
+         "model: sonnet\nweak-model: haiku\nauto-commits: false\nreasoning-effort: low\n";
+      let keys = SettingsKeys::for_agent(&find_known_agent("aider").unwrap());
+      keys.validate().unwrap();
+      let mut value = parse_config(content, ConfigFormat::Yaml).unwrap();
+      assert_eq!(keys.read(&value).reasoning_effort.as_deref(), Some("low"));
+
+      let settings = AgentSettings {
+         model: Some("opus".into()),
+         reasoning_effort: Some("high".into()),
+         ..AgentSettings::default()
+      };
+      keys.write(&mut value, settings).unwrap();
+      let written = serialize_config(value, ConfigFormat::Yaml, Some(content)).unwrap();
+      assert_eq!(
+         written,
+         "model: opus\nweak-model: haiku\nauto-commits: false\nreasoning-effort: high\n"
+      );
+   }
+
+   #[tokio::test]
+   async fn test_batch_reports_errors_per_entry() {
+      let home = tempfile::tempdir().unwrap();
+      std::fs::create_dir(home.path().join(".codex")).unwrap();
+      std::fs::write(home.path().join(".codex/config.toml"), "model = \"o3\"\n").unwrap();
+      std::fs::write(home.path().join("broken.json"), "{ \"model\": ").unwrap();
+
+      let request = |agent_id: &str, settings_path: &str, model_key: &str| AgentSettingsRequest {
+         agent_id: agent_id.into(),
+         settings_path: Some(settings_path.into()),
+         model_key: Some(model_key.into()),
+         preview_key: None,
+         reasoning_key: None,
+         temperature_key: None,
+         max_tokens_key: None,
+         tools_key: None,
+      };
+      let requests = [
+         request("codex", ".codex/config.toml", "model"),
+         request("broken", "broken.json", "model"),
+         request("missing", "missing.json", "model"),
+         request("codex-again", ".codex/config.toml", "model"),
+         request("bad-key", ".codex/config.toml", "model..name"),
+         request("empty-path", "", "model"),
+      ];
+
+      let roots = AgentSettingsRoots::with_home(home.path().to_path_buf());
+      let results = load_settings_batch(&AgentSettingsLocks::new(), &roots, &requests).await;
+
+      assert_eq!(results.len(), 6);
+      assert_eq!(results[0].as_ref().unwrap().model.as_deref(), Some("o3"));
+      assert!(matches!(results[1], Err(AgentSettingsError::Parse { .. })));
+      assert_eq!(results[2].as_ref().unwrap().model, None);
+      assert_eq!(results[3].as_ref().unwrap().model.as_deref(), Some("o3"));
+      assert!(matches!(
+         results[4],
+         Err(AgentSettingsError::InvalidKeyPath { .. })
+      ));
+      assert!(matches!(
+         results[5],
+         Err(AgentSettingsError::InvalidPath { .. })
+      ));
+   }
+
+   #[tokio::test]
+   async fn test_round_trip_with_home_override() {
+      let home = tempfile::tempdir().unwrap();
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+      app.manage(AgentSettingsRoots::with_home(home.path().to_path_buf()));
+      app.manage(AgentSettingsHistory::at(
+         Some(home.path().join(".athas/settings-history.jsonl")),
+         u64::MAX,
+      ));
+      app.manage(SettingsWriteBuffer::new());
+
+      for (settings_path, reasoning_key) in [
+         (".claude/settings.json", "reasoning.effort"),
+         (".codex/config.toml", "model_reasoning_effort"),
+      ] {
+         let request = AgentSettingsRequest {
+            agent_id: "test".into(),
+            settings_path: Some(settings_path.into()),
+            model_key: Some("model".into()),
+            preview_key: None,
+            reasoning_key: Some(reasoning_key.into()),
+            temperature_key: None,
+            max_tokens_key: None,
+            tools_key: None,
+         };
+         let get = || {
+            get_agent_settings(
+               app.handle().clone(),
+               app.state(),
+               app.state(),
+               request.clone(),
+               None,
+               None,
+            )
+         };
+
+         let set = |model: Option<&str>, reasoning: Option<&str>, version: Option<String>| {
+            set_agent_settings(
+               app.handle().clone(),
+               app.state(),
+               app.state(),
+               app.state(),
+               request.clone(),
+               AgentSettings {
+                  model: model.map(String::from),
+                  reasoning_effort: reasoning.map(String::from),
+                  ..AgentSettings::default()
+               },
+               Some(SetAgentSettingsOptions {
+                  expected_version: version,
+                  ..SetAgentSettingsOptions::default()
+               }),
+            )
+         };
+
+         let before = get().await.unwrap();
+         assert_eq!(before.model, None);
+         assert_eq!(before.version.as_deref(), Some(MISSING_VERSION));
+
+         assert!(!set(None, None, None).await.unwrap().wrote);
+         assert!(!home.path().join(settings_path).parent().unwrap().exists());
+
+         let result = set(Some("o3"), Some("high"), before.version).await.unwrap();
+         assert!(result.wrote);
+
+         let after = get().await.unwrap();
+         assert_eq!(after.model.as_deref(), Some("o3"), "{}", settings_path);
+         assert_eq!(after.reasoning_effort.as_deref(), Some("high"));
+         assert!(home.path().join(settings_path).is_file());
+
+         let unchanged = set(Some("o3"), None, after.version).await.unwrap();
+         assert!(!unchanged.wrote);
+      }
+      let journal = std::fs::read_to_string(home.path().join(".athas/settings-history.jsonl"));
+      assert_eq!(journal.unwrap().lines().count(), 4);
+
+      // Registry agents can be read by id alone
+      let get_known = |agent_id: &str| {
+         get_agent_settings(
+            app.handle().clone(),
+            app.state(),
+            app.state(),
+            AgentSettingsRequest {
+               agent_id: agent_id.into(),
+               settings_path: None,
+               model_key: None,
+               preview_key: None,
+               reasoning_key: None,
+               temperature_key: None,
+               max_tokens_key: None,
+               tools_key: None,
+            },
+            None,
+            None,
+         )
+      };
+      let codex = get_known("codex-cli").await.unwrap();
+      assert_eq!(codex.model.as_deref(), Some("o3"));
+      assert_eq!(codex.reasoning_effort.as_deref(), Some("high"));
+      assert!(matches!(
+         get_known("test").await,
+         Err(AgentSettingsError::UnknownAgent { .. })
+      ));
+   }
+
+   #[tokio::test]
+   async fn test_buffered_sets_are_written_before_other_access() {
+      let home = tempfile::tempdir().unwrap();
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+      app.manage(AgentSettingsRoots::with_home(home.path().to_path_buf()));
+      app.manage(AgentSettingsHistory::at(None, 0));
+      app.manage(SettingsWriteBuffer::new());
+
+      let request = AgentSettingsRequest {
+         agent_id: "test".into(),
+         settings_path: Some(".claude/settings.json".into()),
+         model_key: Some("model".into()),
+         preview_key: None,
+         reasoning_key: None,
+         temperature_key: None,
+         max_tokens_key: None,
+         tools_key: None,
+      };
+      let set_coalesced = |model: &str| {
+         set_agent_settings(
+            app.handle().clone(),
+            app.state(),
+            app.state(),
+            app.state(),
+            request.clone(),
+            AgentSettings {
+               model: Some(model.into()),
+               ..AgentSettings::default()
+            },
+            Some(SetAgentSettingsOptions {
+               coalesce: true,
+               ..SetAgentSettingsOptions::default()
+            }),
+         )
+      };
+      let get = || {
+         get_agent_settings(
+            app.handle().clone(),
+            app.state(),
+            app.state(),
+            request.clone(),
+            None,
+            None,
+         )
+      };
+
+      assert!(set_coalesced("o3").await.unwrap().pending);
+      assert_eq!(get().await.unwrap().model.as_deref(), Some("o3"));
+
+      assert!(set_coalesced("older").await.unwrap().pending);
+      let changed = set_agent_config_values(
+         app.handle().clone(),
+         app.state(),
+         app.state(),
+         app.state(),
+         "test".into(),
+         ".claude/settings.json".into(),
+         HashMap::from([("model".to_string(), json!("newer"))]),
+         None,
+         None,
+         None,
+         None,
+      )
+      .await
+      .unwrap();
+      assert_eq!(changed, ["model"]);
+      assert!(flush_pending_writes(app.handle()).await.is_empty());
+      assert_eq!(get().await.unwrap().model.as_deref(), Some("newer"));
+   }
+
+   #[tokio::test]
+   async fn test_reveal_only_reads_the_agents_own_files() {
+      let home = tempfile::tempdir().unwrap();
+      let outside = tempfile::tempdir().unwrap();
+      std::fs::create_dir(home.path().join(".claude")).unwrap();
+      let content = r#"{ "env": { "ANTHROPIC_API_KEY": "sk-ant-reveal-0123456789" } }"#;
+      for path in [
+         home.path().join(".claude/settings.json"),
+         home.path().join("notes.json"),
+         outside.path().join("settings.json"),
+      ] {
+         std::fs::write(path, content).unwrap();
+      }
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+      app.manage(AgentSettingsRoots::with_home(home.path().to_path_buf()));
+      let reveal = |agent_id: &str, settings_path: String| {
+         reveal_agent_config_secret(
+            app.state(),
+            app.state(),
+            agent_id.into(),
+            settings_path,
+            "env.ANTHROPIC_API_KEY".into(),
+         )
+      };
+
+      let revealed = reveal("claude-code", ".claude/settings.json".into()).await;
+      assert_eq!(revealed.unwrap(), Some(json!("sk-ant-reveal-0123456789")));
+      for settings_path in [
+         "notes.json".to_string(),
+         outside.path().join("settings.json").display().to_string(),
+      ] {
+         assert!(matches!(
+            reveal("claude-code", settings_path).await,
+            Err(AgentSettingsError::PathNotAllowed { .. })
+         ));
+      }
+      assert!(matches!(
+         reveal("not-an-agent", ".claude/settings.json".into()).await,
+         Err(AgentSettingsError::UnknownAgent { .. })
+      ));
+   }
+
+   /// Keeps every log message, so tests can check what would reach the log file
+   struct CaptureLogger(std::sync::Mutex<Vec<String>>);
+
+   impl log::Log for CaptureLogger {
+      fn enabled(&self, _: &log::Metadata) -> bool {
+         true
+      }
+
+      fn log(&self, record: &log::Record) {
+         self.0.lock().unwrap().push(record.args().to_string());
+      }
+
+      fn flush(&self) {
+      }
+   }
+
+   static LOGGER: CaptureLogger = CaptureLogger(std::sync::Mutex::new(Vec::new()));
+
+   #[tokio::test]
+   async fn test_secret_values_are_not_logged() {
+      log::set_logger(&LOGGER).unwrap();
+      log::set_max_level(log::LevelFilter::Trace);
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.json");
+      std::fs::write(
+         &path,
+         r#"{ "provider": { "apiKey": "sk-log-test-0123456789" }, "effort": "high" }"#,
+      )
+      .unwrap();
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+      app.manage(AgentSettingsRoots::new());
+      app.manage(AgentSettingsHistory::at(None, 0));
+      app.manage(SettingsWriteBuffer::new());
+
+      let settings = get_agent_settings(
+         app.handle().clone(),
+         app.state(),
+         app.state(),
+         AgentSettingsRequest {
+            agent_id: "log-test".into(),
+            settings_path: Some(path.to_string_lossy().into_owned()),
+            model_key: Some("provider.apiKey".into()),
+            preview_key: None,
+            reasoning_key: Some("effort".into()),
+            temperature_key: None,
+            max_tokens_key: None,
+            tools_key: None,
+         },
+         None,
+         None,
+      )
+      .await
+      .unwrap();
+      assert_eq!(settings.model.as_deref(), Some("sk-log-test-0123456789"));
+
+      let logs = LOGGER.0.lock().unwrap();
+      let message = logs
+         .iter()
+         .find(|message| message.contains("agent log-test"))
+         .unwrap();
+      assert!(message.contains("model=[redacted]"), "{}", message);
+      assert!(message.contains("reasoning=Some(\"high\")"), "{}", message);
+      assert!(!logs.iter().any(|message| message.contains("sk-log-test")));
+   }
+}
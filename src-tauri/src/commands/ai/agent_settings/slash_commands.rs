@@ -0,0 +1,587 @@
+use super::{
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   mcp::known_agent,
+   memory::AgentFileScope,
+   paths::resolve_settings_path,
+   storage::{AgentSettingsLocks, write_atomic},
+};
+use crate::commands::ai::agent_registry::KnownAgent;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value as YamlValue};
+use std::{
+   fs,
+   io::ErrorKind,
+   path::{Path, PathBuf},
+};
+use tauri::{State, command};
+
+/// Subdirectory of a commands directory deleted commands are moved to. Agents only read `.md`
+/// files, and trashed ones are renamed so they no longer end in `.md`.
+const TRASH_DIR: &str = ".trash";
+/// Characters of a command's body shown in listings
+const PREVIEW_CHARS: usize = 200;
+/// Frontmatter keys Athas edits. Any others a command file has are kept as they are.
+const DESCRIPTION_KEY: &str = "description";
+const ARGUMENT_HINT_KEY: &str = "argument-hint";
+
+/// A custom slash command: a Markdown prompt the agent runs as `/name`, with the description
+/// and argument hint it shows in its command menu
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentCommand {
+   pub name: String,
+   #[serde(default)]
+   pub description: Option<String>,
+   #[serde(default)]
+   pub argument_hint: Option<String>,
+   pub body: String,
+}
+
+/// A command as listed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentCommandListing {
+   pub name: String,
+   pub description: Option<String>,
+   pub argument_hint: Option<String>,
+   pub scope: AgentFileScope,
+   pub path: String,
+   /// Start of the body, cut at `PREVIEW_CHARS`
+   pub body_preview: String,
+}
+
+fn invalid_command(name: &str, message: impl Into<String>) -> AgentSettingsError {
+   AgentSettingsError::InvalidAgentCommand {
+      name: name.to_string(),
+      message: message.into(),
+   }
+}
+
+/// Names become file names and are typed after a `/`, so they are kept to letters, digits, `-`
+/// and `_`
+fn validate_name(name: &str) -> Result<&str, AgentSettingsError> {
+   let name = name.trim();
+   let valid = !name.is_empty()
+      && !name.starts_with('-')
+      && name
+         .chars()
+         .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+   if valid {
+      Ok(name)
+   } else {
+      Err(invalid_command(
+         name,
+         "names may only hold letters, digits, '-' and '_'",
+      ))
+   }
+}
+
+/// The YAML between the `---` lines opening `content`, if it has any, and the rest of it
+fn split_frontmatter(content: &str) -> (Option<&str>, &str) {
+   let mut lines = content.split_inclusive('\n');
+   if lines.next().map(str::trim_end) != Some("---") {
+      return (None, content);
+   }
+   let yaml_start = content.find('\n').map_or(content.len(), |end| end + 1);
+   let mut offset = yaml_start;
+   for line in lines {
+      if line.trim_end() == "---" {
+         return (
+            Some(&content[yaml_start..offset]),
+            &content[offset + line.len()..],
+         );
+      }
+      offset += line.len();
+   }
+   (None, content)
+}
+
+/// Frontmatter as a mapping. Frontmatter that isn't a YAML mapping is treated as empty.
+fn parse_frontmatter(yaml: &str) -> Mapping {
+   match serde_yaml::from_str::<Option<Mapping>>(yaml) {
+      Ok(mapping) => mapping.unwrap_or_default(),
+      Err(e) => {
+         log::warn!("Ignoring unparseable command frontmatter: {}", e);
+         Mapping::new()
+      }
+   }
+}
+
+fn frontmatter_string(frontmatter: &Mapping, key: &str) -> Option<String> {
+   frontmatter
+      .get(key)
+      .and_then(YamlValue::as_str)
+      .map(str::trim)
+      .filter(|value| !value.is_empty())
+      .map(str::to_string)
+}
+
+fn set_frontmatter_string(frontmatter: &mut Mapping, key: &str, value: Option<&str>) {
+   match value.map(str::trim).filter(|value| !value.is_empty()) {
+      // Replacing a value keeps its place among the others
+      Some(value) => {
+         frontmatter.insert(YamlValue::from(key), YamlValue::from(value));
+      }
+      None => {
+         frontmatter.shift_remove(key);
+      }
+   }
+}
+
+/// The file `command` is saved as. Frontmatter of the file it replaces is kept apart from the
+/// keys Athas edits.
+fn render_command(
+   existing: Option<&str>,
+   command: &AgentCommand,
+) -> Result<String, AgentSettingsError> {
+   let mut frontmatter = existing
+      .and_then(|content| split_frontmatter(content).0)
+      .map(parse_frontmatter)
+      .unwrap_or_default();
+   set_frontmatter_string(
+      &mut frontmatter,
+      DESCRIPTION_KEY,
+      command.description.as_deref(),
+   );
+   set_frontmatter_string(
+      &mut frontmatter,
+      ARGUMENT_HINT_KEY,
+      command.argument_hint.as_deref(),
+   );
+
+   let mut content = String::new();
+   if !frontmatter.is_empty() {
+      let yaml =
+         serde_yaml::to_string(&frontmatter).map_err(|e| AgentSettingsError::Serialize {
+            format: ConfigFormat::Yaml,
+            message: e.to_string(),
+         })?;
+      content.push_str("---\n");
+      content.push_str(&yaml);
+      content.push_str("---\n\n");
+   }
+   content.push_str(command.body.trim_matches(['\n', '\r']));
+   content.push('\n');
+   Ok(content)
+}
+
+fn preview(body: &str) -> String {
+   let body = body.trim();
+   if body.chars().count() <= PREVIEW_CHARS {
+      return body.to_string();
+   }
+   let mut preview: String = body.chars().take(PREVIEW_CHARS - 1).collect();
+   preview.push('…');
+   preview
+}
+
+/// The directory of `agent`'s commands in `scope`
+fn commands_dir(
+   agent: &KnownAgent,
+   scope: AgentFileScope,
+   workspace_path: Option<&str>,
+) -> Result<PathBuf, AgentSettingsError> {
+   let no_commands_dir = || AgentSettingsError::NoCommandsDir {
+      agent_id: agent.id.clone(),
+      scope,
+   };
+   let dirs = agent.command_dirs.as_ref().ok_or_else(no_commands_dir)?;
+   match scope {
+      AgentFileScope::Project => {
+         let project_path = dirs.project_path.as_deref().ok_or_else(no_commands_dir)?;
+         let workspace_path = workspace_path.ok_or_else(|| AgentSettingsError::InvalidPath {
+            path: String::new(),
+            message: "no workspace was given for project commands".to_string(),
+         })?;
+         Ok(Path::new(workspace_path).join(project_path))
+      }
+      AgentFileScope::Global => {
+         let global_path = dirs.global_path.as_deref().ok_or_else(no_commands_dir)?;
+         resolve_settings_path(global_path)
+      }
+   }
+}
+
+/// Command files in `dir` by name, in name order. A missing directory has none.
+fn command_files(dir: &Path) -> Result<Vec<(String, PathBuf)>, AgentSettingsError> {
+   let entries = match fs::read_dir(dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(AgentSettingsError::io(dir, e)),
+   };
+   let mut files = Vec::new();
+   for entry in entries {
+      let path = entry.map_err(|e| AgentSettingsError::io(dir, e))?.path();
+      if !path.is_file() || path.extension().and_then(|extension| extension.to_str()) != Some("md")
+      {
+         continue;
+      }
+      if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+         files.push((name.to_string(), path.clone()));
+      }
+   }
+   files.sort();
+   Ok(files)
+}
+
+/// The file of command `name` in `dir`. Names are compared ignoring case, as they are on the
+/// file systems macOS and Windows use by default.
+fn find_command_file(dir: &Path, name: &str) -> Result<Option<PathBuf>, AgentSettingsError> {
+   Ok(command_files(dir)?
+      .into_iter()
+      .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+      .map(|(_, path)| path))
+}
+
+fn list_dir(
+   dir: &Path,
+   scope: AgentFileScope,
+) -> Result<Vec<AgentCommandListing>, AgentSettingsError> {
+   let mut listings = Vec::new();
+   for (name, path) in command_files(dir)? {
+      let content = match fs::read_to_string(&path) {
+         Ok(content) => content,
+         Err(e) => {
+            log::warn!("Skipping command {}: {}", path.display(), e);
+            continue;
+         }
+      };
+      let (yaml, body) = split_frontmatter(&content);
+      let frontmatter = yaml.map(parse_frontmatter).unwrap_or_default();
+      listings.push(AgentCommandListing {
+         name,
+         description: frontmatter_string(&frontmatter, DESCRIPTION_KEY),
+         argument_hint: frontmatter_string(&frontmatter, ARGUMENT_HINT_KEY),
+         scope,
+         path: path.display().to_string(),
+         body_preview: preview(body),
+      });
+   }
+   Ok(listings)
+}
+
+/// Whether `a` and `b` are the same file, which a rename that only changes case leaves them as
+/// on a case-insensitive file system
+fn same_file(a: &Path, b: &Path) -> bool {
+   matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Write `command` into `dir`. `previous_name` is the name of the command being edited, which
+/// is renamed if `command` has another; without one, no command may have the name yet.
+async fn save_to(
+   locks: &AgentSettingsLocks,
+   dir: &Path,
+   command: &AgentCommand,
+   previous_name: Option<&str>,
+) -> Result<PathBuf, AgentSettingsError> {
+   let name = validate_name(&command.name)?;
+   if command.body.trim().is_empty() {
+      return Err(invalid_command(name, "body is empty"));
+   }
+   let lock = locks.lock_for(dir).await;
+   let _guard = lock.write().await;
+
+   let previous = match previous_name {
+      Some(previous_name) => Some(find_command_file(dir, previous_name)?.ok_or_else(|| {
+         AgentSettingsError::UnknownAgentCommand {
+            name: previous_name.to_string(),
+         }
+      })?),
+      None => None,
+   };
+   if let Some(existing) = find_command_file(dir, name)?
+      && previous.as_ref() != Some(&existing)
+   {
+      return Err(invalid_command(
+         name,
+         "a command in this scope has this name",
+      ));
+   }
+
+   let existing = match &previous {
+      Some(previous) => {
+         Some(fs::read_to_string(previous).map_err(|e| AgentSettingsError::io(previous, e))?)
+      }
+      None => None,
+   };
+   let content = render_command(
+      existing.as_deref(),
+      &AgentCommand {
+         name: name.to_string(),
+         ..command.clone()
+      },
+   )?;
+   let path = dir.join(format!("{}.md", name));
+   fs::create_dir_all(dir).map_err(|e| AgentSettingsError::io(dir, e))?;
+   write_atomic(&path, &content)?;
+   if let Some(previous) = previous
+      && previous != path
+      && !same_file(&previous, &path)
+   {
+      fs::remove_file(&previous).map_err(|e| AgentSettingsError::io(&previous, e))?;
+   }
+   Ok(path)
+}
+
+/// Move command `name` of `dir` into its trash directory, returning where it went, or `None`
+/// if there is no such command
+async fn trash_from(
+   locks: &AgentSettingsLocks,
+   dir: &Path,
+   name: &str,
+) -> Result<Option<PathBuf>, AgentSettingsError> {
+   let lock = locks.lock_for(dir).await;
+   let _guard = lock.write().await;
+   let Some(path) = find_command_file(dir, name)? else {
+      return Ok(None);
+   };
+   let trash = dir.join(TRASH_DIR);
+   fs::create_dir_all(&trash).map_err(|e| AgentSettingsError::io(&trash, e))?;
+   let file_name = path
+      .file_name()
+      .map(|name| name.to_string_lossy().into_owned())
+      .unwrap_or_default();
+   let timestamp = chrono::Local::now().format("%Y%m%dT%H%M%S").to_string();
+   let mut trashed = trash.join(format!("{}.deleted-{}", file_name, timestamp));
+   let mut attempt = 1;
+   while trashed.exists() {
+      attempt += 1;
+      trashed = trash.join(format!("{}.deleted-{}-{}", file_name, timestamp, attempt));
+   }
+   fs::rename(&path, &trashed).map_err(|e| AgentSettingsError::io(&path, e))?;
+   Ok(Some(trashed))
+}
+
+/// An agent's custom slash commands in `scope`, or in both scopes when none is given, project
+/// commands first. Scopes the agent has no directory for are skipped unless asked for.
+#[command]
+pub async fn list_agent_commands(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   scope: Option<AgentFileScope>,
+   workspace_path: Option<String>,
+) -> Result<Vec<AgentCommandListing>, AgentSettingsError> {
+   let agent = known_agent(&locks, &agent_id).await?;
+   let mut listings = Vec::new();
+   let scopes = match scope {
+      Some(scope) => vec![scope],
+      None => vec![AgentFileScope::Project, AgentFileScope::Global],
+   };
+   for each in scopes {
+      let dir = match commands_dir(&agent, each, workspace_path.as_deref()) {
+         Ok(dir) => dir,
+         Err(AgentSettingsError::NoCommandsDir { .. } | AgentSettingsError::InvalidPath { .. })
+            if scope.is_none() =>
+         {
+            continue;
+         }
+         Err(e) => return Err(e),
+      };
+      listings.extend(list_dir(&dir, each)?);
+   }
+   Ok(listings)
+}
+
+/// Create a custom slash command, or edit the one named `previous_name`, renaming it if
+/// `command` has another name. Frontmatter keys Athas doesn't edit, such as Claude Code's
+/// `allowed-tools`, are kept.
+#[command]
+pub async fn save_agent_command(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   command: AgentCommand,
+   previous_name: Option<String>,
+   scope: Option<AgentFileScope>,
+   workspace_path: Option<String>,
+) -> Result<AgentCommandListing, AgentSettingsError> {
+   let scope = scope.unwrap_or_default();
+   let agent = known_agent(&locks, &agent_id).await?;
+   let dir = commands_dir(&agent, scope, workspace_path.as_deref())?;
+   let path = save_to(&locks, &dir, &command, previous_name.as_deref()).await?;
+   log::info!("Saved {} command {}", agent_id, path.display());
+   Ok(AgentCommandListing {
+      name: command.name.trim().to_string(),
+      description: command
+         .description
+         .map(|description| description.trim().to_string())
+         .filter(|description| !description.is_empty()),
+      argument_hint: command
+         .argument_hint
+         .map(|hint| hint.trim().to_string())
+         .filter(|hint| !hint.is_empty()),
+      scope,
+      path: path.display().to_string(),
+      body_preview: preview(&command.body),
+   })
+}
+
+/// Delete a custom slash command by moving it to the `.trash` directory next to it, from where
+/// it can be restored by hand
+#[command]
+pub async fn delete_agent_command(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+   name: String,
+   scope: Option<AgentFileScope>,
+   workspace_path: Option<String>,
+) -> Result<(), AgentSettingsError> {
+   let agent = known_agent(&locks, &agent_id).await?;
+   let dir = commands_dir(&agent, scope.unwrap_or_default(), workspace_path.as_deref())?;
+   let Some(trashed) = trash_from(&locks, &dir, &name).await? else {
+      return Err(AgentSettingsError::UnknownAgentCommand { name });
+   };
+   log::info!(
+      "Moved {} command {} to {}",
+      agent_id,
+      name,
+      trashed.display()
+   );
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_registry::find_known_agent;
+
+   fn command(name: &str, body: &str) -> AgentCommand {
+      AgentCommand {
+         name: name.into(),
+         description: Some("Review the staged changes".into()),
+         argument_hint: None,
+         body: body.into(),
+      }
+   }
+
+   #[test]
+   fn test_frontmatter_round_trip() {
+      let existing =
+         "---\nallowed-tools: Bash(git diff:*)\ndescription: Old\nmodel: haiku\n---\n\nOld body\n";
+      let (yaml, body) = split_frontmatter(existing);
+      assert_eq!(
+         yaml,
+         Some("allowed-tools: Bash(git diff:*)\ndescription: Old\nmodel: haiku\n")
+      );
+      assert_eq!(body, "\nOld body\n");
+
+      let rendered =
+         render_command(Some(existing), &command("review", "Review $ARGUMENTS\n\n")).unwrap();
+      assert_eq!(
+         rendered,
+         "---\nallowed-tools: Bash(git diff:*)\ndescription: Review the staged changes\nmodel: \
+          haiku\n---\n\nReview $ARGUMENTS\n"
+      );
+
+      let plain = AgentCommand {
+         description: None,
+         ..command("review", "Review")
+      };
+      assert_eq!(render_command(None, &plain).unwrap(), "Review\n");
+      assert_eq!(
+         split_frontmatter("--- not\nfrontmatter"),
+         (None, "--- not\nfrontmatter")
+      );
+      assert_eq!(
+         split_frontmatter("---\nunclosed: true\n"),
+         (None, "---\nunclosed: true\n")
+      );
+   }
+
+   #[test]
+   fn test_name_validation() {
+      assert!(validate_name("review-pr_2").is_ok());
+      for name in ["", "../review", "review.md", "my command", "-review"] {
+         assert!(
+            matches!(
+               validate_name(name),
+               Err(AgentSettingsError::InvalidAgentCommand { .. })
+            ),
+            "{}",
+            name
+         );
+      }
+   }
+
+   #[tokio::test]
+   async fn test_command_crud() {
+      let dir = tempfile::tempdir().unwrap();
+      let commands = dir.path().join(".claude/commands");
+      let locks = AgentSettingsLocks::new();
+
+      let path = save_to(
+         &locks,
+         &commands,
+         &command("review", "Review the diff"),
+         None,
+      )
+      .await
+      .unwrap();
+      assert_eq!(path, commands.join("review.md"));
+      assert!(matches!(
+         save_to(&locks, &commands, &command("Review", "Again"), None).await,
+         Err(AgentSettingsError::InvalidAgentCommand { .. })
+      ));
+
+      fs::write(commands.join("notes.txt"), "not a command").unwrap();
+      let listed = list_dir(&commands, AgentFileScope::Project).unwrap();
+      assert_eq!(listed.len(), 1);
+      assert_eq!(
+         listed[0].description.as_deref(),
+         Some("Review the staged changes")
+      );
+      assert_eq!(listed[0].body_preview, "Review the diff");
+
+      // Renaming replaces the file
+      let renamed = save_to(
+         &locks,
+         &commands,
+         &command("review-pr", "Review the PR"),
+         Some("review"),
+      )
+      .await
+      .unwrap();
+      assert!(renamed.is_file());
+      assert!(!path.exists());
+      assert!(matches!(
+         save_to(&locks, &commands, &command("x", "x"), Some("review")).await,
+         Err(AgentSettingsError::UnknownAgentCommand { .. })
+      ));
+
+      let trashed = trash_from(&locks, &commands, "review-pr")
+         .await
+         .unwrap()
+         .unwrap();
+      assert!(trashed.starts_with(commands.join(TRASH_DIR)));
+      assert!(
+         fs::read_to_string(&trashed)
+            .unwrap()
+            .contains("Review the PR")
+      );
+      assert!(
+         list_dir(&commands, AgentFileScope::Project)
+            .unwrap()
+            .is_empty()
+      );
+      assert!(
+         trash_from(&locks, &commands, "review-pr")
+            .await
+            .unwrap()
+            .is_none()
+      );
+   }
+
+   #[test]
+   fn test_commands_dirs() {
+      let codex = find_known_agent("codex-cli").unwrap();
+      assert!(matches!(
+         commands_dir(&codex, AgentFileScope::Project, Some("/repo")),
+         Err(AgentSettingsError::NoCommandsDir { .. })
+      ));
+      let claude = find_known_agent("claude-code").unwrap();
+      assert_eq!(
+         commands_dir(&claude, AgentFileScope::Project, Some("/repo")).unwrap(),
+         Path::new("/repo").join(".claude/commands")
+      );
+   }
+}
@@ -0,0 +1,1275 @@
+use super::{
+   backup::{
+      create_backup, mark_created, preserve_corrupt_file, remove_created, was_created_async,
+   },
+   encryption::{ConfigEncryption, decode},
+   error::AgentSettingsError,
+   format::{ConfigFormat, parse_config, serialize_config, strip_jsonc},
+   history::{Journal, diff_values},
+   interpolate::{env_var, interpolate_env},
+   platform::{Platform, resolve_platform},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{
+   borrow::Cow,
+   collections::HashMap,
+   fs,
+   hash::{DefaultHasher, Hash, Hasher},
+   io,
+   io::Write,
+   path::{Path, PathBuf},
+   sync::{Arc, Mutex},
+   time::{Duration, SystemTime},
+};
+use tokio::sync::RwLock;
+
+/// Version token reported for a config file that does not exist
+pub(super) const MISSING_VERSION: &str = "missing";
+
+/// Config content at least this large is parsed and serialized on the blocking thread pool.
+/// Smaller files are handled inline, where handing them over would cost more than it saves.
+const OFFLOAD_BYTES: usize = 256 * 1024;
+
+/// Size limit for a config file when the caller sets none. Larger files are refused before they
+/// are read, as a settings path pointing at the wrong file would otherwise be read whole.
+pub(super) const DEFAULT_MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// How long after a file's modification time its cached parse can be trusted. Filesystems
+/// record modification times in coarse ticks, so a second write within the tick of the first can
+/// leave both the time and the size unchanged. Parses taken this soon after the file changed are
+/// redone on the next read instead, as git does for racily clean index entries.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// Top-level key that marks a config file as created by Athas, as an alternative to the
+/// `.athas-created` sidecar kept next to its backups
+const ATHAS_MARKER_KEY: &str = "_athas";
+
+/// Options controlling how a config file is written
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct WriteOptions<'a> {
+   /// When set, back up the previous content first and keep this many backups
+   pub backup_retention: Option<usize>,
+   /// Replace an existing file that fails to parse instead of failing. The unparseable content
+   /// is preserved as `<name>.corrupt-<timestamp>` first.
+   pub overwrite_on_parse_error: bool,
+   /// Version token the caller last read. When set, the write fails with `Conflict` if the
+   /// file has changed since.
+   pub expected_version: Option<&'a str>,
+   /// Delete the file instead of leaving an empty document behind, if Athas created it
+   pub remove_if_empty: bool,
+   /// Record the keys the write changes in the settings history
+   pub journal: Option<Journal<'a>>,
+   /// Size limit for the existing file, `DEFAULT_MAX_FILE_BYTES` when unset
+   pub max_file_bytes: Option<u64>,
+}
+
+/// Options controlling how a config file is read
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct ReadOptions {
+   /// Read the file from disk even if its parse is cached. The fresh parse is cached either way.
+   pub bypass_cache: bool,
+   /// Size limit for the file, `DEFAULT_MAX_FILE_BYTES` when unset
+   pub max_file_bytes: Option<u64>,
+}
+
+/// A parsed config file together with the version token of the content it was parsed from
+#[derive(Debug, Clone)]
+pub(super) struct ConfigFile {
+   pub value: Value,
+   pub version: String,
+   pub meta: ConfigFileMeta,
+}
+
+/// What is known about a config file on disk, for showing when it last changed and warning
+/// before overwriting one the user or the agent created
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigFileMeta {
+   pub exists: bool,
+   pub modified: Option<DateTime<Utc>>,
+   /// Size on disk in bytes, of the encrypted content for encrypted files
+   pub size: Option<u64>,
+   /// Whether Athas created the file, as recorded by its `.athas-created` sidecar or a
+   /// top-level `_athas` key
+   pub created_by_athas: bool,
+   pub format: ConfigFormat,
+}
+
+impl ConfigFileMeta {
+   /// Metadata reported for a config file that does not exist
+   pub(super) fn missing(format: ConfigFormat) -> Self {
+      Self {
+         exists: false,
+         modified: None,
+         size: None,
+         created_by_athas: false,
+         format,
+      }
+   }
+}
+
+/// Metadata of a config file from its filesystem `metadata`, `None` when it doesn't exist, and
+/// whether Athas' sidecar marks it as `created`
+pub(super) fn meta_from(
+   format: ConfigFormat,
+   metadata: Option<&fs::Metadata>,
+   created: bool,
+   value: Option<&Value>,
+) -> ConfigFileMeta {
+   let has_marker_key = value.is_some_and(|value| value.get(ATHAS_MARKER_KEY).is_some());
+   ConfigFileMeta {
+      exists: metadata.is_some(),
+      modified: metadata
+         .and_then(|metadata| metadata.modified().ok())
+         .map(DateTime::<Utc>::from),
+      size: metadata.map(fs::Metadata::len),
+      created_by_athas: metadata.is_some() && (has_marker_key || created),
+      format,
+   }
+}
+
+/// A parsed config file kept in memory for as long as its modification time and size stay the
+/// same
+#[derive(Debug, Clone)]
+struct CachedParse {
+   format: ConfigFormat,
+   modified: SystemTime,
+   size: u64,
+   /// When the parsed content was read
+   read_at: SystemTime,
+   value: Value,
+   version: String,
+}
+
+impl CachedParse {
+   /// Whether the file, now with `metadata`, can still be taken to have the cached content
+   fn is_fresh(&self, format: ConfigFormat, metadata: &fs::Metadata) -> bool {
+      self.format == format
+         && metadata.len() == self.size
+         && metadata.modified().ok() == Some(self.modified)
+         && self
+            .read_at
+            .duration_since(self.modified)
+            .is_ok_and(|age| age >= RACY_WINDOW)
+   }
+}
+
+/// What a config update would do, computed without writing
+#[derive(Debug, Clone)]
+pub(super) struct ConfigPreview {
+   /// Content of the file now, `None` when it doesn't exist
+   pub original: Option<String>,
+   /// Content the file would have afterwards, `None` when it doesn't exist and wouldn't be
+   /// created
+   pub content: Option<String>,
+}
+
+/// Per-file locks serializing read-modify-write cycles on agent config files, so concurrent
+/// `set_agent_settings` calls for the same file cannot clobber each other's changes.
+#[derive(Default)]
+pub struct AgentSettingsLocks {
+   locks: Mutex<HashMap<PathBuf, Arc<RwLock<()>>>>,
+   /// Hash of the content Athas last wrote to each file, so watchers can tell their own writes
+   /// apart from external edits
+   written: Mutex<HashMap<PathBuf, u64>>,
+   /// Parsed config files, so repeated reads of an unchanged file skip reading and parsing it
+   parsed: Mutex<HashMap<PathBuf, CachedParse>>,
+   /// Encryption at rest for Athas' own files, when set up
+   encryption: Option<ConfigEncryption>,
+}
+
+impl AgentSettingsLocks {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   pub fn with_encryption(encryption: ConfigEncryption) -> Self {
+      Self {
+         encryption: Some(encryption),
+         ..Self::default()
+      }
+   }
+
+   pub(super) fn encryption(&self) -> Option<&ConfigEncryption> {
+      self.encryption.as_ref()
+   }
+
+   /// What to store on disk for `content` written to `path`, encrypted when it's one of Athas'
+   /// own files and encryption at rest is on
+   fn seal<'a>(&self, path: &Path, content: &'a str) -> Result<Cow<'a, str>, AgentSettingsError> {
+      match &self.encryption {
+         Some(encryption) => encryption.seal(path, content),
+         None => Ok(Cow::Borrowed(content)),
+      }
+   }
+
+   pub(super) async fn lock_for(&self, path: &Path) -> Arc<RwLock<()>> {
+      self.lock_at(file_key(path).await)
+   }
+
+   fn lock_at(&self, key: PathBuf) -> Arc<RwLock<()>> {
+      let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+      locks.entry(key).or_default().clone()
+   }
+
+   /// Note that Athas wrote `content` to `path`, dropping any cached parse of what was there
+   pub(super) async fn record_write(&self, path: &Path, content: &str) {
+      self.record_at(file_key(path).await, content);
+   }
+
+   fn record_at(&self, key: PathBuf, content: &str) {
+      self.invalidate(&key);
+      let mut written = self.written.lock().unwrap_or_else(|e| e.into_inner());
+      written.insert(key, content_hash(content));
+   }
+
+   fn cached_parse(
+      &self,
+      key: &Path,
+      format: ConfigFormat,
+      metadata: &fs::Metadata,
+   ) -> Option<CachedParse> {
+      let parsed = self.parsed.lock().unwrap_or_else(|e| e.into_inner());
+      parsed
+         .get(key)
+         .filter(|cached| cached.is_fresh(format, metadata))
+         .cloned()
+   }
+
+   fn cache_parse(&self, key: PathBuf, cached: CachedParse) {
+      let mut parsed = self.parsed.lock().unwrap_or_else(|e| e.into_inner());
+      parsed.insert(key, cached);
+   }
+
+   fn invalidate(&self, key: &Path) {
+      let mut parsed = self.parsed.lock().unwrap_or_else(|e| e.into_inner());
+      parsed.remove(key);
+   }
+
+   /// Forget every cached parse, so the next read of each file goes to disk
+   pub(super) fn clear_cache(&self) {
+      let mut parsed = self.parsed.lock().unwrap_or_else(|e| e.into_inner());
+      parsed.clear();
+   }
+
+   /// Whether `content` is exactly what Athas last wrote to `path`. Resolves the path with
+   /// blocking calls, so it is for the watcher's thread, not the async runtime.
+   pub(super) fn is_own_write(&self, path: &Path, content: &str) -> bool {
+      let written = self.written.lock().unwrap_or_else(|e| e.into_inner());
+      written.get(&lock_key(path)) == Some(&content_hash(content))
+   }
+}
+
+pub(super) fn content_hash(content: &str) -> u64 {
+   let mut hasher = DefaultHasher::new();
+   content.hash(&mut hasher);
+   hasher.finish()
+}
+
+/// Version token for `content` of a file last modified at `modified`, combining the two so both
+/// touched-but-equal and same-second edits are told apart
+pub(super) fn version_from(modified: Option<SystemTime>, content: &str) -> String {
+   let modified = modified
+      .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+      .map(|since_epoch| since_epoch.as_nanos())
+      .unwrap_or_default();
+   format!("{:x}-{:016x}", modified, content_hash(content))
+}
+
+/// Version token of the file at `path`, read through `tokio::fs`, or `MISSING_VERSION` when
+/// there is no `content`
+async fn current_version(path: &Path, content: Option<&str>) -> String {
+   let Some(content) = content else {
+      return MISSING_VERSION.to_string();
+   };
+   let modified = tokio::fs::metadata(path)
+      .await
+      .and_then(|metadata| metadata.modified())
+      .ok();
+   version_from(modified, content)
+}
+
+/// Whether `error` from opening a config file means there is no file to open
+fn is_missing(error: &io::Error) -> bool {
+   matches!(
+      error.kind(),
+      io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+   )
+}
+
+/// Fail with `FileTooLarge` when the file behind `metadata` is over `limit` bytes, or over
+/// `DEFAULT_MAX_FILE_BYTES` without one
+pub(super) fn check_size(
+   path: &Path,
+   metadata: &fs::Metadata,
+   limit: Option<u64>,
+) -> Result<(), AgentSettingsError> {
+   let limit = limit.unwrap_or(DEFAULT_MAX_FILE_BYTES);
+   if metadata.len() > limit {
+      return Err(AgentSettingsError::FileTooLarge {
+         path: path.display().to_string(),
+         size: metadata.len(),
+         limit,
+      });
+   }
+   Ok(())
+}
+
+/// Content read from the config file at `path` as text, failing with `NotText` when it isn't
+/// UTF-8
+pub(super) fn into_text(path: &Path, bytes: Vec<u8>) -> Result<String, AgentSettingsError> {
+   String::from_utf8(bytes).map_err(|_| AgentSettingsError::NotText {
+      path: path.display().to_string(),
+   })
+}
+
+/// Read the config file at `path` as text, after its size passed `check_size`
+async fn read_text(path: &Path) -> Result<String, AgentSettingsError> {
+   let bytes = tokio::fs::read(path)
+      .await
+      .map_err(|e| AgentSettingsError::io(path, e))?;
+   into_text(path, bytes)
+}
+
+/// Metadata of the config file at `path`, `None` when it doesn't exist
+async fn metadata_of(path: &Path) -> Result<Option<fs::Metadata>, AgentSettingsError> {
+   match tokio::fs::metadata(path).await {
+      Ok(metadata) => Ok(Some(metadata)),
+      Err(e) if is_missing(&e) => Ok(None),
+      Err(e) => Err(AgentSettingsError::io(path, e)),
+   }
+}
+
+/// Run blocking `work` on the blocking thread pool, re-raising a panic in it
+pub(super) async fn run_blocking<T, F>(work: F) -> T
+where
+   F: FnOnce() -> T + Send + 'static,
+   T: Send + 'static,
+{
+   match tokio::task::spawn_blocking(work).await {
+      Ok(result) => result,
+      Err(error) => std::panic::resume_unwind(error.into_panic()),
+   }
+}
+
+/// Parse or serialize content of `len` bytes with `work`, on the blocking thread pool when the
+/// content is large enough to hold up the async runtime and inline otherwise
+async fn offload<T, F>(len: usize, work: F) -> T
+where
+   F: FnOnce() -> T + Send + 'static,
+   T: Send + 'static,
+{
+   if len < OFFLOAD_BYTES {
+      work()
+   } else {
+      run_blocking(work).await
+   }
+}
+
+/// Canonical identity of a config file, so different spellings of the same path share a lock.
+/// Files that don't exist yet are keyed by their canonicalized parent directory.
+fn lock_key(path: &Path) -> PathBuf {
+   if let Ok(canonical) = fs::canonicalize(path) {
+      return canonical;
+   }
+   match (path.parent(), path.file_name()) {
+      (Some(parent), Some(name)) => fs::canonicalize(parent)
+         .map(|parent| parent.join(name))
+         .unwrap_or_else(|_| path.to_path_buf()),
+      _ => path.to_path_buf(),
+   }
+}
+
+/// `lock_key` of `path`, resolved on the blocking thread pool
+async fn file_key(path: &Path) -> PathBuf {
+   let path = path.to_path_buf();
+   run_blocking(move || lock_key(&path)).await
+}
+
+/// Replace the file at `path` with `content` without ever exposing a partially written file.
+///
+/// The content is written to a temporary file in the same directory and then renamed over the
+/// target (`MoveFileEx` with replace semantics on Windows), so a crash or full disk leaves either
+/// the old or the new config behind. The original file's permissions are carried over, and a
+/// symlinked config is replaced at its target rather than turned into a regular file.
+pub fn write_atomic(path: &Path, content: &str) -> Result<(), AgentSettingsError> {
+   let target = if path.is_symlink() {
+      fs::canonicalize(path).map_err(|e| AgentSettingsError::io(path, e))?
+   } else {
+      path.to_path_buf()
+   };
+   let dir = target.parent().ok_or_else(|| {
+      AgentSettingsError::io(
+         &target,
+         io::Error::new(io::ErrorKind::InvalidInput, "path has no parent directory"),
+      )
+   })?;
+
+   let mut temp = tempfile::Builder::new()
+      .prefix(".athas-")
+      .suffix(".tmp")
+      .tempfile_in(dir)
+      .map_err(|e| AgentSettingsError::io(dir, e))?;
+   temp
+      .write_all(content.as_bytes())
+      .and_then(|_| temp.as_file().sync_all())
+      .map_err(|e| AgentSettingsError::io(temp.path(), e))?;
+
+   if let Ok(metadata) = fs::metadata(&target) {
+      temp
+         .as_file()
+         .set_permissions(metadata.permissions())
+         .map_err(|e| AgentSettingsError::io(&target, e))?;
+   }
+
+   temp
+      .persist(&target)
+      .map_err(|e| AgentSettingsError::io(&target, e.error))?;
+   Ok(())
+}
+
+/// Serialize `value` for a file whose current content is `original`, off the async runtime
+/// when the file is large
+async fn serialize_offloaded(
+   value: Value,
+   format: ConfigFormat,
+   original: Option<&str>,
+) -> Result<String, AgentSettingsError> {
+   let original = original.map(String::from);
+   let len = original.as_ref().map_or(0, String::len);
+   offload(len, move || {
+      serialize_config(value, format, original.as_deref())
+   })
+   .await
+}
+
+/// Serialize `value` and atomically replace the config at `path` with it, returning the content
+/// as stored, encrypted if `locks` encrypts the file. Serialization happens before anything
+/// touches the disk, so a failure leaves the existing file untouched. The backup and the write
+/// run on the blocking thread pool.
+pub(super) async fn write_config(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   value: Value,
+   format: ConfigFormat,
+   original: Option<&str>,
+   options: WriteOptions<'_>,
+) -> Result<String, AgentSettingsError> {
+   let content = serialize_offloaded(value, format, original).await?;
+
+   let backup = match (options.backup_retention, original) {
+      (Some(retention), Some(original)) if original != content => {
+         Some((locks.seal(path, original)?.into_owned(), retention))
+      }
+      _ => None,
+   };
+   let stored = locks.seal(path, &content)?.into_owned();
+   let path = path.to_path_buf();
+   run_blocking(move || {
+      if let Some((original, retention)) = backup {
+         create_backup(&path, &original, retention)?;
+      }
+      write_atomic(&path, &stored).map(|_| stored)
+   })
+   .await
+}
+
+/// Read and parse a config file under its shared lock. Returns `None` when the file is missing.
+/// A file whose modification time and size haven't changed since it was last parsed is served
+/// from the cache in `locks`.
+pub(super) async fn read_config_file(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   format: ConfigFormat,
+) -> Result<Option<ConfigFile>, AgentSettingsError> {
+   read_config_file_with(locks, path, format, ReadOptions::default()).await
+}
+
+/// As `read_config_file`, with `options` to bypass the cache or change the size limit
+pub(super) async fn read_config_file_with(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   format: ConfigFormat,
+   options: ReadOptions,
+) -> Result<Option<ConfigFile>, AgentSettingsError> {
+   let key = file_key(path).await;
+   let lock = locks.lock_at(key.clone());
+   let _guard = lock.read().await;
+
+   // Taken before the read, so a write landing in between shows up as a change next time
+   let Some(metadata) = metadata_of(path).await? else {
+      return Ok(None);
+   };
+   check_size(path, &metadata, options.max_file_bytes)?;
+   let created = was_created_async(path).await;
+   if !options.bypass_cache
+      && let Some(cached) = locks.cached_parse(&key, format, &metadata)
+   {
+      return Ok(Some(ConfigFile {
+         meta: meta_from(format, Some(&metadata), created, Some(&cached.value)),
+         version: cached.version,
+         value: cached.value,
+      }));
+   }
+
+   let read_at = SystemTime::now();
+   let content = read_text(path).await?;
+   let content = decode(locks.encryption(), path, content)?;
+   let modified = metadata.modified().ok();
+   let (value, version) = offload(content.len(), move || {
+      let value = parse_config(&content, format)?;
+      Ok::<_, AgentSettingsError>((value, version_from(modified, &content)))
+   })
+   .await?;
+   if let Some(modified) = modified {
+      locks.cache_parse(
+         key,
+         CachedParse {
+            format,
+            modified,
+            size: metadata.len(),
+            read_at,
+            value: value.clone(),
+            version: version.clone(),
+         },
+      );
+   }
+   Ok(Some(ConfigFile {
+      meta: meta_from(format, Some(&metadata), created, Some(&value)),
+      version,
+      value,
+   }))
+}
+
+/// Read one of Athas' own TOML config files, such as `~/.athas/settings.toml`, with the
+/// `platform` section for the current OS merged over the rest of the document. With
+/// `expand_env`, `${VAR}` and `${VAR:-default}` references in its strings are expanded too.
+/// Agents' config files are never read this way, and writes keep the references as written.
+pub(super) async fn read_athas_config(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   expand_env: bool,
+) -> Result<Option<ConfigFile>, AgentSettingsError> {
+   let Some(file) = read_config_file(locks, path, ConfigFormat::Toml).await? else {
+      return Ok(None);
+   };
+   let mut value = resolve_platform(file.value, Platform::current());
+   if expand_env {
+      interpolate_env(&mut value, &env_var)?;
+   }
+   Ok(Some(ConfigFile { value, ..file }))
+}
+
+/// Content of the file at `path` as text, decrypted if needed, or `None` when it doesn't exist.
+/// Fails with `FileTooLarge` over `max_file_bytes`.
+async fn read_original(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   max_file_bytes: Option<u64>,
+) -> Result<Option<String>, AgentSettingsError> {
+   let Some(metadata) = metadata_of(path).await? else {
+      return Ok(None);
+   };
+   check_size(path, &metadata, max_file_bytes)?;
+   let content = read_text(path).await?;
+   decode(locks.encryption(), path, content).map(Some)
+}
+
+/// Parse the content an update starts from, or an empty document for a missing file
+fn parse_original(
+   path: &Path,
+   original: Option<&str>,
+   format: ConfigFormat,
+) -> Result<Value, AgentSettingsError> {
+   let Some(content) = original else {
+      return Ok(Value::Object(Map::new()));
+   };
+   if format == ConfigFormat::Json && strip_jsonc(content) != content {
+      log::warn!(
+         "Comments and trailing commas in {} will not be preserved on save",
+         path.display()
+      );
+   }
+   parse_config(content, format)
+}
+
+/// As `parse_original`, off the async runtime when the content is large. The content is handed
+/// back along with the result.
+async fn parse_original_offloaded(
+   path: &Path,
+   original: Option<String>,
+   format: ConfigFormat,
+) -> (Option<String>, Result<Value, AgentSettingsError>) {
+   let path = path.to_path_buf();
+   let len = original.as_ref().map_or(0, String::len);
+   offload(len, move || {
+      let parsed = parse_original(&path, original.as_deref(), format);
+      (original, parsed)
+   })
+   .await
+}
+
+/// Work out what `update_config_file` would write for `update` without writing anything. The
+/// new content goes through the same serialization as a real write.
+pub(super) async fn preview_config_update<F>(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   format: ConfigFormat,
+   update: F,
+) -> Result<ConfigPreview, AgentSettingsError>
+where
+   F: FnOnce(&mut Value) -> Result<(), AgentSettingsError>,
+{
+   let lock = locks.lock_for(path).await;
+   let _guard = lock.read().await;
+
+   let original = read_original(locks, path, None).await?;
+   let (original, parsed) = parse_original_offloaded(path, original, format).await;
+   let mut value = parsed?;
+   let before = value.clone();
+   update(&mut value)?;
+
+   let content = if value == before {
+      original.clone()
+   } else {
+      Some(serialize_offloaded(value, format, original.as_deref()).await?)
+   };
+   Ok(ConfigPreview { original, content })
+}
+
+/// Apply `update` to a config file as one read-modify-write cycle, holding the file's exclusive
+/// lock from the read until the write has landed. When `update` leaves the document as it was,
+/// nothing is written and no file or directory is created. Returns whether the file was written.
+pub(super) async fn update_config_file<F>(
+   locks: &AgentSettingsLocks,
+   path: &Path,
+   format: ConfigFormat,
+   options: WriteOptions<'_>,
+   update: F,
+) -> Result<bool, AgentSettingsError>
+where
+   F: FnOnce(&mut Value) -> Result<(), AgentSettingsError>,
+{
+   let key = file_key(path).await;
+   let lock = locks.lock_at(key.clone());
+   let _guard = lock.write().await;
+
+   let original = read_original(locks, path, options.max_file_bytes).await?;
+
+   if let Some(expected) = options.expected_version {
+      let current_version = current_version(path, original.as_deref()).await;
+      if current_version != expected {
+         return Err(AgentSettingsError::Conflict {
+            path: path.display().to_string(),
+            current_version,
+            current: original
+               .as_deref()
+               .and_then(|content| parse_config(content, format).ok()),
+         });
+      }
+   }
+
+   // A corrupt file that was set aside has to be replaced even if the update adds nothing
+   let mut replacing_corrupt = false;
+   let (original, parsed) = parse_original_offloaded(path, original, format).await;
+   let mut value = match parsed {
+      Ok(value) => value,
+      Err(error @ AgentSettingsError::Parse { .. }) if options.overwrite_on_parse_error => {
+         let content = original.as_deref().unwrap_or_default();
+         let sealed = locks.seal(path, content)?.into_owned();
+         let corrupt = path.to_path_buf();
+         let preserved = run_blocking(move || preserve_corrupt_file(&corrupt, &sealed)).await?;
+         log::warn!(
+            "Overwriting unparseable {} ({}), original kept at {}",
+            path.display(),
+            error,
+            preserved.display()
+         );
+         replacing_corrupt = true;
+         Value::Object(Map::new())
+      }
+      Err(error) => return Err(error),
+   };
+
+   let before = value.clone();
+   update(&mut value)?;
+   if value == before && !replacing_corrupt {
+      return Ok(false);
+   }
+   let journal = options
+      .journal
+      .map(|journal| (journal, diff_values(&before, &value)));
+   if options.remove_if_empty
+      && original.is_some()
+      && value.as_object().is_some_and(Map::is_empty)
+      && was_created_async(path).await
+   {
+      let created = path.to_path_buf();
+      run_blocking(move || remove_created(&created)).await?;
+      log::info!(
+         "Removed {}, which Athas created and is now empty",
+         path.display()
+      );
+      if let Some((journal, changes)) = journal {
+         journal.record(locks, path, changes).await;
+      }
+      return Ok(true);
+   }
+
+   if let Some(parent) = path.parent() {
+      tokio::fs::create_dir_all(parent)
+         .await
+         .map_err(|e| AgentSettingsError::io(parent, e))?;
+   }
+   let stored = write_config(locks, path, value, format, original.as_deref(), options).await?;
+   locks.record_at(key, &stored);
+   if original.is_none() {
+      let created = path.to_path_buf();
+      run_blocking(move || mark_created(&created)).await?;
+   }
+   if let Some((journal, changes)) = journal {
+      journal.record(locks, path, changes).await;
+   }
+   Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::agent_settings::keys::{get_nested_value, set_nested_value};
+   use std::time::Instant;
+
+   fn dir_entries(dir: &Path) -> Vec<String> {
+      let mut names: Vec<String> = fs::read_dir(dir)
+         .unwrap()
+         .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+         .collect();
+      names.sort();
+      names
+   }
+
+   #[test]
+   fn test_write_atomic_replaces_content() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("config.toml");
+      fs::write(&path, "model = \"o3\"\n").unwrap();
+
+      write_atomic(&path, "model = \"o4-mini\"\n").unwrap();
+
+      assert_eq!(fs::read_to_string(&path).unwrap(), "model = \"o4-mini\"\n");
+      assert_eq!(dir_entries(dir.path()), vec!["config.toml"]);
+   }
+
+   #[tokio::test]
+   async fn test_failed_serialization_leaves_original_untouched() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("config.toml");
+      let original = "# keep me\nmodel = \"o3\"\n";
+      fs::write(&path, original).unwrap();
+
+      // A bare array cannot be the root of a TOML document
+      let result = write_config(
+         &AgentSettingsLocks::new(),
+         &path,
+         serde_json::json!(["a"]),
+         ConfigFormat::Toml,
+         None,
+         WriteOptions::default(),
+      )
+      .await;
+
+      assert!(matches!(
+         result,
+         Err(AgentSettingsError::Serialize {
+            format: ConfigFormat::Toml,
+            ..
+         })
+      ));
+      assert_eq!(fs::read_to_string(&path).unwrap(), original);
+      assert_eq!(dir_entries(dir.path()), vec!["config.toml"]);
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn test_write_atomic_preserves_permissions_and_symlinks() {
+      use std::os::unix::fs::{PermissionsExt, symlink};
+
+      let dir = tempfile::tempdir().unwrap();
+      let target = dir.path().join("real.json");
+      let link = dir.path().join("settings.json");
+      fs::write(&target, "{}").unwrap();
+      fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+      symlink(&target, &link).unwrap();
+
+      write_atomic(&link, "{\"model\": \"x\"}").unwrap();
+
+      assert!(link.is_symlink());
+      assert_eq!(fs::read_to_string(&target).unwrap(), "{\"model\": \"x\"}");
+      let mode = fs::metadata(&target).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, 0o600);
+   }
+
+   #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+   async fn test_concurrent_updates_are_serialized() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.json");
+      let locks = Arc::new(AgentSettingsLocks::new());
+
+      let tasks: Vec<_> = (0..32)
+         .map(|i| {
+            let locks = locks.clone();
+            let path = path.clone();
+            tokio::spawn(async move {
+               update_config_file(
+                  &locks,
+                  &path,
+                  ConfigFormat::Json,
+                  WriteOptions::default(),
+                  |value| set_nested_value(value, &format!("keys.key{}", i), Value::from(i)),
+               )
+               .await
+            })
+         })
+         .collect();
+      for task in tasks {
+         task.await.unwrap().unwrap();
+      }
+
+      let value = read_config_file(&locks, &path, ConfigFormat::Json)
+         .await
+         .unwrap()
+         .unwrap()
+         .value;
+      for i in 0..32 {
+         assert_eq!(
+            get_nested_value(&value, &format!("keys.key{}", i)),
+            Some(&Value::from(i))
+         );
+      }
+   }
+
+   #[tokio::test]
+   async fn test_unchanged_update_writes_nothing() {
+      let dir = tempfile::tempdir().unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      let missing = dir.path().join("someagent/config.json");
+      let wrote = update_config_file(
+         &locks,
+         &missing,
+         ConfigFormat::Json,
+         WriteOptions::default(),
+         |_| Ok(()),
+      )
+      .await
+      .unwrap();
+      assert!(!wrote);
+      assert!(!dir.path().join("someagent").exists());
+
+      let existing = dir.path().join("settings.json");
+      let content = "{\n  // keep me\n  \"model\": \"o3\",\n}\n";
+      fs::write(&existing, content).unwrap();
+      let wrote = update_config_file(
+         &locks,
+         &existing,
+         ConfigFormat::Json,
+         WriteOptions::default(),
+         |value| set_nested_value(value, "model", Value::String("o3".into())),
+      )
+      .await
+      .unwrap();
+      assert!(!wrote);
+      assert_eq!(fs::read_to_string(&existing).unwrap(), content);
+   }
+
+   #[tokio::test]
+   async fn test_read_reports_file_meta() {
+      let dir = tempfile::tempdir().unwrap();
+      let locks = AgentSettingsLocks::new();
+      let read_meta = |path: PathBuf| {
+         let locks = &locks;
+         async move {
+            read_config_file(locks, &path, ConfigFormat::Json)
+               .await
+               .unwrap()
+               .map(|file| file.meta)
+         }
+      };
+
+      let user_file = dir.path().join("settings.json");
+      fs::write(&user_file, "{\"model\": \"o3\"}").unwrap();
+      let meta = read_meta(user_file).await.unwrap();
+      assert!(meta.exists && !meta.created_by_athas);
+      assert_eq!(meta.size, Some(15));
+      assert_eq!(meta.format, ConfigFormat::Json);
+      assert!(meta.modified.is_some());
+
+      let marked = dir.path().join("marked.json");
+      fs::write(&marked, "{\"_athas\": {}}").unwrap();
+      assert!(read_meta(marked).await.unwrap().created_by_athas);
+
+      let created = dir.path().join("agent/config.json");
+      update_config_file(
+         &locks,
+         &created,
+         ConfigFormat::Json,
+         WriteOptions::default(),
+         |value| set_nested_value(value, "model", Value::String("o3".into())),
+      )
+      .await
+      .unwrap();
+      assert!(read_meta(created.clone()).await.unwrap().created_by_athas);
+      assert!(!fs::read_to_string(&created).unwrap().contains("_athas"));
+
+      assert!(read_meta(dir.path().join("missing.json")).await.is_none());
+   }
+
+   fn set_modified(path: &Path, modified: SystemTime) {
+      fs::File::options()
+         .write(true)
+         .open(path)
+         .unwrap()
+         .set_modified(modified)
+         .unwrap();
+   }
+
+   async fn read_model(locks: &AgentSettingsLocks, path: &Path, use_cache: bool) -> Value {
+      let options = ReadOptions {
+         bypass_cache: !use_cache,
+         ..ReadOptions::default()
+      };
+      let file = read_config_file_with(locks, path, ConfigFormat::Json, options)
+         .await
+         .unwrap()
+         .unwrap();
+      file.value["model"].clone()
+   }
+
+   #[tokio::test]
+   async fn test_unchanged_files_are_served_from_cache() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.json");
+      let modified = SystemTime::now() - Duration::from_secs(60);
+      fs::write(&path, "{\"model\": \"o3\"}").unwrap();
+      set_modified(&path, modified);
+      let locks = AgentSettingsLocks::new();
+      assert_eq!(read_model(&locks, &path, true).await, "o3");
+
+      // Same size and modification time, so taken to be unchanged
+      fs::write(&path, "{\"model\": \"o4\"}").unwrap();
+      set_modified(&path, modified);
+      assert_eq!(read_model(&locks, &path, true).await, "o3");
+      assert_eq!(read_model(&locks, &path, false).await, "o4");
+      assert_eq!(read_model(&locks, &path, true).await, "o4");
+
+      fs::write(&path, "{\"model\": \"o5\"}").unwrap();
+      set_modified(&path, modified);
+      locks.clear_cache();
+      assert_eq!(read_model(&locks, &path, true).await, "o5");
+
+      fs::write(&path, "{\"model\": \"gpt-5\"}").unwrap();
+      set_modified(&path, modified);
+      assert_eq!(read_model(&locks, &path, true).await, "gpt-5");
+      fs::write(&path, "{\"model\": \"gpt-4\"}").unwrap();
+      set_modified(&path, modified + Duration::from_secs(1));
+      assert_eq!(read_model(&locks, &path, true).await, "gpt-4");
+   }
+
+   #[tokio::test]
+   async fn test_same_tick_writes_are_not_served_stale() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.json");
+      fs::write(&path, "{\"model\": \"o3\"}").unwrap();
+      let modified = fs::metadata(&path).unwrap().modified().unwrap();
+      let locks = AgentSettingsLocks::new();
+      assert_eq!(read_model(&locks, &path, true).await, "o3");
+
+      // A second write within the filesystem's timestamp tick, which can't be told apart by
+      // time and size alone
+      fs::write(&path, "{\"model\": \"o4\"}").unwrap();
+      set_modified(&path, modified);
+      assert_eq!(read_model(&locks, &path, true).await, "o4");
+   }
+
+   #[tokio::test]
+   async fn test_own_writes_invalidate_the_cache() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.json");
+      let modified = SystemTime::now() - Duration::from_secs(60);
+      fs::write(&path, "{\"model\": \"o3\"}").unwrap();
+      set_modified(&path, modified);
+      let locks = AgentSettingsLocks::new();
+      assert_eq!(read_model(&locks, &path, true).await, "o3");
+
+      fs::write(&path, "{\"model\": \"o4\"}").unwrap();
+      set_modified(&path, modified);
+      locks.record_write(&path, "{\"model\": \"o4\"}").await;
+      assert_eq!(read_model(&locks, &path, true).await, "o4");
+   }
+
+   #[tokio::test]
+   async fn test_oversized_and_binary_files_are_refused() {
+      let dir = tempfile::tempdir().unwrap();
+      let large = dir.path().join("settings.json");
+      fs::write(&large, format!("{{\"notes\": \"{}\"}}", "x".repeat(2048))).unwrap();
+      let locks = AgentSettingsLocks::new();
+      let limited = |max_file_bytes| ReadOptions {
+         max_file_bytes: Some(max_file_bytes),
+         ..ReadOptions::default()
+      };
+
+      let result = read_config_file_with(&locks, &large, ConfigFormat::Json, limited(1024)).await;
+      assert!(matches!(
+         result,
+         Err(AgentSettingsError::FileTooLarge {
+            size: 2061,
+            limit: 1024,
+            ..
+         })
+      ));
+      let write = WriteOptions {
+         max_file_bytes: Some(1024),
+         ..WriteOptions::default()
+      };
+      let result = update_config_file(&locks, &large, ConfigFormat::Json, write, |value| {
+         set_nested_value(value, "model", Value::from("o3"))
+      })
+      .await;
+      assert!(matches!(
+         result,
+         Err(AgentSettingsError::FileTooLarge { .. })
+      ));
+      let file = read_config_file_with(&locks, &large, ConfigFormat::Json, limited(4096))
+         .await
+         .unwrap();
+      assert!(file.is_some());
+
+      let binary = dir.path().join("config.toml");
+      fs::write(&binary, [0x7f, b'E', b'L', b'F', 0xff, 0xfe, 0x00]).unwrap();
+      let result = read_config_file(&locks, &binary, ConfigFormat::Toml).await;
+      assert!(matches!(result, Err(AgentSettingsError::NotText { .. })));
+      let write = WriteOptions {
+         overwrite_on_parse_error: true,
+         ..WriteOptions::default()
+      };
+      let result = update_config_file(&locks, &binary, ConfigFormat::Toml, write, |value| {
+         set_nested_value(value, "model", Value::from("o3"))
+      })
+      .await;
+      assert!(matches!(result, Err(AgentSettingsError::NotText { .. })));
+      assert_eq!(fs::read(&binary).unwrap().len(), 7);
+   }
+
+   #[tokio::test]
+   async fn test_only_large_content_is_offloaded() {
+      let runtime_thread = std::thread::current().id();
+      let small = offload(OFFLOAD_BYTES - 1, || std::thread::current().id()).await;
+      let large = offload(OFFLOAD_BYTES, || std::thread::current().id()).await;
+      assert_eq!(small, runtime_thread);
+      assert_ne!(large, runtime_thread);
+   }
+
+   /// Timing-dependent, so left out of normal runs: `cargo test -- --ignored`
+   #[tokio::test]
+   #[ignore = "benchmark"]
+   async fn test_large_reads_do_not_starve_other_commands() {
+      let dir = tempfile::tempdir().unwrap();
+      let large = dir.path().join("large.json");
+      let small = dir.path().join("small.json");
+      let servers: Map<String, Value> = (0..60_000)
+         .map(|i| {
+            let server = serde_json::json!({
+               "command": "npx",
+               "args": ["-y", format!("@example/server-{}", i)],
+               "env": {"TOKEN": "x".repeat(32)},
+            });
+            (format!("server-{}", i), server)
+         })
+         .collect();
+      let content = serde_json::json!({ "mcpServers": servers }).to_string();
+      assert!(content.len() > 5 * 1024 * 1024);
+      fs::write(&large, content).unwrap();
+      fs::write(&small, "{\"model\": \"o3\"}").unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      let started = Instant::now();
+      let large_read = async {
+         let options = ReadOptions {
+            bypass_cache: true,
+            max_file_bytes: Some(64 * 1024 * 1024),
+         };
+         let file = read_config_file_with(&locks, &large, ConfigFormat::Json, options)
+            .await
+            .unwrap();
+         assert!(file.is_some());
+         started.elapsed()
+      };
+      let small_reads = async {
+         let mut slowest = Duration::ZERO;
+         for _ in 0..20 {
+            let start = Instant::now();
+            assert_eq!(read_model(&locks, &small, false).await, "o3");
+            slowest = slowest.max(start.elapsed());
+         }
+         (started.elapsed(), slowest)
+      };
+      let (large_done, (small_done, slowest)) = tokio::join!(large_read, small_reads);
+
+      assert!(
+         small_done < large_done,
+         "small reads waited for the large one: {:?} for 20 small reads, slowest {:?}, against \
+          {:?} for the large one",
+         small_done,
+         slowest,
+         large_done
+      );
+   }
+
+   #[tokio::test]
+   async fn test_lock_is_shared_across_path_spellings() {
+      let dir = tempfile::tempdir().unwrap();
+      fs::create_dir(dir.path().join("sub")).unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      let direct = locks.lock_for(&dir.path().join("config.toml")).await;
+      let indirect = locks.lock_for(&dir.path().join("sub/../config.toml")).await;
+      assert!(Arc::ptr_eq(&direct, &indirect));
+   }
+
+   #[tokio::test]
+   async fn test_update_refuses_to_overwrite_corrupt_config() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("config.toml");
+      let corrupt = "model = \"o3\"\n[profiles\nmodel = \"o4\"\n";
+      fs::write(&path, corrupt).unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      let result = update_config_file(
+         &locks,
+         &path,
+         ConfigFormat::Toml,
+         WriteOptions::default(),
+         |value| set_nested_value(value, "model", Value::String("gpt-5".into())),
+      )
+      .await;
+
+      assert!(matches!(result, Err(AgentSettingsError::Parse { .. })));
+      assert_eq!(fs::read_to_string(&path).unwrap(), corrupt);
+      assert_eq!(dir_entries(dir.path()), vec!["config.toml"]);
+   }
+
+   #[tokio::test]
+   async fn test_update_can_overwrite_corrupt_config_keeping_a_copy() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("config.toml");
+      let corrupt = "model = \"o3\"\n[profiles\nmodel = \"o4\"\n";
+      fs::write(&path, corrupt).unwrap();
+      let locks = AgentSettingsLocks::new();
+      let options = WriteOptions {
+         overwrite_on_parse_error: true,
+         ..WriteOptions::default()
+      };
+
+      update_config_file(&locks, &path, ConfigFormat::Toml, options, |value| {
+         set_nested_value(value, "model", Value::String("gpt-5".into()))
+      })
+      .await
+      .unwrap();
+
+      assert_eq!(fs::read_to_string(&path).unwrap(), "model = \"gpt-5\"\n");
+      let entries = dir_entries(dir.path());
+      assert_eq!(entries.len(), 2);
+      let preserved = entries
+         .iter()
+         .find(|name| name.starts_with("config.toml.corrupt-"))
+         .expect("corrupt original should be preserved");
+      assert_eq!(
+         fs::read_to_string(dir.path().join(preserved)).unwrap(),
+         corrupt
+      );
+   }
+
+   #[tokio::test]
+   async fn test_expected_version_detects_external_changes() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("config.toml");
+      fs::write(&path, "model = \"o3\"\n").unwrap();
+      let locks = AgentSettingsLocks::new();
+
+      let read = read_config_file(&locks, &path, ConfigFormat::Toml)
+         .await
+         .unwrap()
+         .unwrap();
+
+      // The agent's own CLI rewrites the file between Athas' read and write
+      fs::write(&path, "model = \"gpt-5\"\n").unwrap();
+
+      let options = WriteOptions {
+         expected_version: Some(&read.version),
+         ..WriteOptions::default()
+      };
+      let result = update_config_file(&locks, &path, ConfigFormat::Toml, options, |value| {
+         set_nested_value(value, "model", Value::String("o4-mini".into()))
+      })
+      .await;
+
+      match result {
+         Err(AgentSettingsError::Conflict {
+            current_version,
+            current,
+            ..
+         }) => {
+            assert_ne!(current_version, read.version);
+            assert_eq!(current, Some(serde_json::json!({ "model": "gpt-5" })));
+         }
+         other => panic!("expected a conflict, got {:?}", other),
+      }
+      assert_eq!(fs::read_to_string(&path).unwrap(), "model = \"gpt-5\"\n");
+
+      // Without an expected version the write goes through as before
+      update_config_file(
+         &locks,
+         &path,
+         ConfigFormat::Toml,
+         WriteOptions::default(),
+         |value| set_nested_value(value, "model", Value::String("o4-mini".into())),
+      )
+      .await
+      .unwrap();
+      assert_eq!(fs::read_to_string(&path).unwrap(), "model = \"o4-mini\"\n");
+   }
+
+   #[tokio::test]
+   async fn test_expected_version_matches_unchanged_and_missing_files() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.json");
+      let locks = AgentSettingsLocks::new();
+
+      let options = WriteOptions {
+         expected_version: Some(MISSING_VERSION),
+         ..WriteOptions::default()
+      };
+      update_config_file(&locks, &path, ConfigFormat::Json, options, |value| {
+         set_nested_value(value, "model", Value::String("sonnet".into()))
+      })
+      .await
+      .unwrap();
+
+      let read = read_config_file(&locks, &path, ConfigFormat::Json)
+         .await
+         .unwrap()
+         .unwrap();
+      let options = WriteOptions {
+         expected_version: Some(&read.version),
+         ..WriteOptions::default()
+      };
+      update_config_file(&locks, &path, ConfigFormat::Json, options, |value| {
+         set_nested_value(value, "model", Value::String("opus".into()))
+      })
+      .await
+      .unwrap();
+      assert!(fs::read_to_string(&path).unwrap().contains("\"opus\""));
+   }
+}
@@ -0,0 +1,147 @@
+use super::{
+   custom_agents::find_agent, detect::detect_binary, error::AgentSettingsError,
+   storage::AgentSettingsLocks,
+};
+use serde::Serialize;
+use std::{path::Path, process::Stdio, time::Duration};
+use tauri::{State, command};
+use tokio::process::Command;
+
+/// How long an agent CLI gets to print its version
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentVersion {
+   /// Version parsed from the output, such as `0.46.0` or `1.2.3-beta.1`
+   pub version: Option<String>,
+   /// What the CLI printed, trimmed
+   pub raw_output: String,
+}
+
+/// Find the first version-like token in `output`: at least two dot-separated numbers, optionally
+/// prefixed with `v` and followed by a pre-release suffix
+pub(super) fn parse_version(output: &str) -> Option<String> {
+   output
+      .split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ',' | '/' | '@'))
+      .map(|token| token.strip_prefix(['v', 'V']).unwrap_or(token))
+      .find_map(|token| {
+         let numeric_end = token
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(token.len());
+         let numeric = token[..numeric_end].trim_end_matches('.');
+         let parts: Vec<_> = numeric.split('.').collect();
+         if parts.len() < 2 || parts.iter().any(|part| part.is_empty()) {
+            return None;
+         }
+
+         let suffix = &token[numeric.len()..];
+         let suffix = match suffix.strip_prefix(['-', '+']) {
+            Some(rest) if rest.starts_with(|c: char| c.is_ascii_alphanumeric()) => {
+               let end = rest
+                  .find(|c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-')
+                  .unwrap_or(rest.len());
+               &suffix[..=end]
+            }
+            _ => "",
+         };
+         Some(format!("{}{}", numeric, suffix.trim_end_matches('.')))
+      })
+}
+
+/// Run `binary` with `args` and return what it printed. Some CLIs print their version to stderr,
+/// which is used when stdout is empty.
+pub(super) async fn run_version_command(
+   agent_id: &str,
+   binary: &Path,
+   args: &[String],
+) -> Result<String, AgentSettingsError> {
+   let mut command = Command::new(binary);
+   command
+      .args(args)
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .kill_on_drop(true);
+
+   let output = match tokio::time::timeout(VERSION_TIMEOUT, command.output()).await {
+      Ok(output) => output.map_err(|e| AgentSettingsError::io(binary, e))?,
+      Err(_) => {
+         return Err(AgentSettingsError::CommandTimedOut {
+            agent_id: agent_id.to_string(),
+            command: std::iter::once(binary.display().to_string())
+               .chain(args.iter().cloned())
+               .collect::<Vec<_>>()
+               .join(" "),
+            seconds: VERSION_TIMEOUT.as_secs(),
+         });
+      }
+   };
+
+   let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+   if !stdout.is_empty() {
+      return Ok(stdout);
+   }
+   Ok(String::from_utf8_lossy(&output.stderr).trim().to_string())
+}
+
+/// Run an agent's CLI with its version arguments and parse the version it reports, so the UI can
+/// warn about releases too old for the config keys Athas writes
+#[command]
+pub async fn get_agent_version(
+   locks: State<'_, AgentSettingsLocks>,
+   agent_id: String,
+) -> Result<AgentVersion, AgentSettingsError> {
+   let Some(agent) = find_agent(&locks, &agent_id).await? else {
+      return Err(AgentSettingsError::UnknownAgent { agent_id });
+   };
+   let Some(binary_path) = detect_binary(agent.id, agent.binary_name.clone())
+      .await
+      .binary_path
+   else {
+      return Err(AgentSettingsError::BinaryNotFound {
+         agent_id,
+         binary_name: agent.binary_name,
+      });
+   };
+
+   let raw_output =
+      run_version_command(&agent_id, Path::new(&binary_path), &agent.version_args).await?;
+   let version = parse_version(&raw_output);
+   log::debug!("Agent {} reports version {:?}", agent_id, version);
+   Ok(AgentVersion {
+      version,
+      raw_output,
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_parse_version() {
+      let cases = [
+         ("1.0.98 (Claude Code)", Some("1.0.98")),
+         ("codex-cli 0.46.0", Some("0.46.0")),
+         ("aider 0.86.1.dev12+g1a2b3c", Some("0.86.1")),
+         ("gemini v0.9.0-preview.2\n", Some("0.9.0-preview.2")),
+         ("opencode 0.15.", Some("0.15")),
+         ("version 2", None),
+         ("", None),
+      ];
+      for (output, expected) in cases {
+         assert_eq!(parse_version(output).as_deref(), expected, "{}", output);
+      }
+   }
+
+   #[tokio::test]
+   async fn test_run_version_command() {
+      let cargo = crate::features::ai::acp::find_binary("cargo").unwrap();
+      let output = run_version_command("cargo", &cargo, &["--version".into()])
+         .await
+         .unwrap();
+      assert!(output.starts_with("cargo "));
+      assert!(parse_version(&output).is_some());
+   }
+}
@@ -0,0 +1,658 @@
+use super::{
+   backup::was_created,
+   encryption::decode,
+   error::AgentSettingsError,
+   format::{ConfigFormat, parse_config},
+   paths::resolve_settings_path,
+   settings::{AgentSettings, SettingsKeys},
+   storage::{AgentSettingsLocks, ConfigFile, check_size, into_text, meta_from, version_from},
+};
+use notify::RecursiveMode;
+use notify_debouncer_mini::{DebounceEventResult, Debouncer, new_debouncer};
+use serde::Serialize;
+use std::{
+   collections::{HashMap, HashSet},
+   fs, io,
+   path::{Path, PathBuf},
+   sync::{Arc, Mutex},
+   time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager, State, command};
+
+/// Event emitted when a watched agent config file changes on disk
+pub const AGENT_SETTINGS_CHANGED_EVENT: &str = "agent-settings-changed";
+
+/// Editors commonly touch a file several times per save (write, rename, chmod); events for the
+/// same path within this window collapse into one
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Who caused a config file change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeSource {
+   /// Written by Athas itself, e.g. through `set_agent_settings`
+   #[serde(rename = "self")]
+   SelfWrite,
+   /// Edited outside of Athas, by the user's editor or the agent's own CLI
+   External,
+}
+
+/// Payload of the `agent-settings-changed` event. Exactly one of `settings` and `error` is set.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentSettingsChangedEvent {
+   pub agent_id: String,
+   pub settings_path: String,
+   pub source: ChangeSource,
+   pub settings: Option<AgentSettings>,
+   pub error: Option<AgentSettingsError>,
+}
+
+/// A watched config file, as reported by `list_watched_settings`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchedSettingsFile {
+   /// The file with symlinks resolved
+   pub path: String,
+   pub agents: Vec<WatchedSettingsAgent>,
+}
+
+/// An agent watching a config file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchedSettingsAgent {
+   pub agent_id: String,
+   pub settings_path: String,
+   /// Where the agent looks for the file, which differs from the file's path for a symlink
+   pub location: String,
+   /// Number of `watch_agent_settings` calls not yet undone by `unwatch_agent_settings`
+   pub watchers: usize,
+}
+
+/// An agent whose config file is being watched
+struct WatchedAgent {
+   agent_id: String,
+   settings_path: String,
+   keys: SettingsKeys,
+   /// The config file's path with its directory canonicalized but the file itself left as named,
+   /// so a symlink that is replaced by a regular file can be followed
+   location: PathBuf,
+   watchers: usize,
+}
+
+#[derive(Default)]
+struct WatchState {
+   /// Watched config files by their path with symlinks resolved, so the same physical file is
+   /// only tracked once however it is named
+   files: HashMap<PathBuf, Vec<WatchedAgent>>,
+}
+
+impl WatchState {
+   /// Directories to watch: those of the files and of the locations agents name them by
+   fn directories(&self) -> HashSet<PathBuf> {
+      self
+         .files
+         .iter()
+         .flat_map(|(file, agents)| {
+            std::iter::once(file).chain(agents.iter().map(|agent| &agent.location))
+         })
+         .filter_map(|path| path.parent().map(Path::to_path_buf))
+         .collect()
+   }
+
+   /// Register `agent` as watching `file`, counting it once more if it already does
+   fn add(&mut self, file: PathBuf, agent: WatchedAgent) {
+      let agents = self.files.entry(file).or_default();
+      match agents
+         .iter_mut()
+         .find(|watched| watched.agent_id == agent.agent_id && watched.location == agent.location)
+      {
+         Some(watched) => {
+            watched.watchers += agent.watchers;
+            watched.settings_path = agent.settings_path;
+            watched.keys = agent.keys;
+         }
+         None => agents.push(agent),
+      }
+   }
+
+   /// Undo one `add` of `agent_id` for the file at `location`. Returns whether it was watched.
+   fn remove(&mut self, agent_id: &str, location: &Path) -> bool {
+      let is_agent =
+         |agent: &WatchedAgent| agent.agent_id == agent_id && agent.location == location;
+      let Some((file, agents)) = self
+         .files
+         .iter_mut()
+         .find(|(_, agents)| agents.iter().any(is_agent))
+      else {
+         return false;
+      };
+      if let Some(agent) = agents.iter_mut().find(|agent| is_agent(agent)) {
+         agent.watchers -= 1;
+      }
+      agents.retain(|agent| agent.watchers > 0);
+      if agents.is_empty() {
+         let file = file.clone();
+         self.files.remove(&file);
+      }
+      true
+   }
+
+   /// The watched file an event for `path` concerns: the file itself, or one an agent names by
+   /// `path`
+   fn file_for(&self, path: &Path) -> Option<PathBuf> {
+      if self.files.contains_key(path) {
+         return Some(path.to_path_buf());
+      }
+      self
+         .files
+         .iter()
+         .find(|(_, agents)| agents.iter().any(|agent| agent.location == path))
+         .map(|(file, _)| file.clone())
+   }
+
+   /// Resolve the locations of `file`'s agents again after it changed, moving those whose
+   /// location now leads to a different file, as when a symlink is replaced by a regular file on
+   /// save. Returns the files the agents are watching afterwards.
+   fn relocate(&mut self, file: &Path) -> Vec<PathBuf> {
+      let Some(agents) = self.files.remove(file) else {
+         return Vec::new();
+      };
+      let mut files = Vec::new();
+      for agent in agents {
+         let resolved = resolve_location(&agent.location);
+         if !files.contains(&resolved) {
+            files.push(resolved.clone());
+         }
+         self.add(resolved, agent);
+      }
+      files
+   }
+
+   fn list(&self) -> Vec<WatchedSettingsFile> {
+      let mut files: Vec<WatchedSettingsFile> = self
+         .files
+         .iter()
+         .map(|(file, agents)| WatchedSettingsFile {
+            path: file.display().to_string(),
+            agents: agents
+               .iter()
+               .map(|agent| WatchedSettingsAgent {
+                  agent_id: agent.agent_id.clone(),
+                  settings_path: agent.settings_path.clone(),
+                  location: agent.location.display().to_string(),
+                  watchers: agent.watchers,
+               })
+               .collect(),
+         })
+         .collect();
+      files.sort_by(|a, b| a.path.cmp(&b.path));
+      files
+   }
+}
+
+type SettingsDebouncer = Debouncer<notify::RecommendedWatcher>;
+
+/// Watches agent config files and emits `agent-settings-changed` when they change.
+///
+/// A single watcher serves every agent. Config files are replaced by rename on every atomic
+/// save, which drops a watch placed on the file itself, so the containing directories are
+/// watched instead and events are filtered by path. Watching the same file again only counts
+/// another watcher, and it stays watched until each of them has unwatched it.
+pub struct AgentSettingsWatcher {
+   app_handle: AppHandle,
+   debouncer: Arc<Mutex<Option<SettingsDebouncer>>>,
+   state: Arc<Mutex<WatchState>>,
+}
+
+impl AgentSettingsWatcher {
+   pub fn new(app_handle: AppHandle) -> Self {
+      Self {
+         app_handle,
+         debouncer: Arc::new(Mutex::new(None)),
+         state: Arc::new(Mutex::new(WatchState::default())),
+      }
+   }
+
+   fn watch(
+      &self,
+      agent_id: String,
+      settings_path: String,
+      path: &Path,
+      keys: SettingsKeys,
+   ) -> Result<(), AgentSettingsError> {
+      let location = watch_location(path)?;
+
+      let mut debouncer = self.debouncer.lock().unwrap_or_else(|e| e.into_inner());
+      if debouncer.is_none() {
+         *debouncer = Some(self.create_debouncer()?);
+      }
+
+      let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+      let before = state.directories();
+      state.add(
+         resolve_location(&location),
+         WatchedAgent {
+            agent_id: agent_id.clone(),
+            settings_path,
+            keys,
+            location: location.clone(),
+            watchers: 1,
+         },
+      );
+      if let Err(error) = sync_directories(debouncer.as_mut(), &before, &state.directories()) {
+         state.remove(&agent_id, &location);
+         return Err(error);
+      }
+      Ok(())
+   }
+
+   fn unwatch(&self, agent_id: &str, path: &Path) -> Result<(), AgentSettingsError> {
+      let location = watch_location(path)?;
+
+      // Same lock order as `watch`: debouncer first, then state
+      let mut debouncer = self.debouncer.lock().unwrap_or_else(|e| e.into_inner());
+      let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+      let before = state.directories();
+      if !state.remove(agent_id, &location) {
+         return Ok(());
+      }
+      sync_directories(debouncer.as_mut(), &before, &state.directories())
+   }
+
+   fn list(&self) -> Vec<WatchedSettingsFile> {
+      self.state.lock().unwrap_or_else(|e| e.into_inner()).list()
+   }
+
+   fn create_debouncer(&self) -> Result<SettingsDebouncer, AgentSettingsError> {
+      let app_handle = self.app_handle.clone();
+      let debouncer = Arc::downgrade(&self.debouncer);
+      let state = self.state.clone();
+
+      new_debouncer(DEBOUNCE_INTERVAL, move |result: DebounceEventResult| {
+         let Ok(events) = result else {
+            return;
+         };
+         let Some(debouncer) = debouncer.upgrade() else {
+            return;
+         };
+
+         let mut debouncer = debouncer.lock().unwrap_or_else(|e| e.into_inner());
+         let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+         let before = state.directories();
+         let mut changed: Vec<PathBuf> = Vec::new();
+         for event in events {
+            let Some(file) = state.file_for(&event.path) else {
+               continue;
+            };
+            for file in state.relocate(&file) {
+               if !changed.contains(&file) {
+                  changed.push(file);
+               }
+            }
+         }
+         if let Err(error) = sync_directories(debouncer.as_mut(), &before, &state.directories()) {
+            log::warn!("Failed to update agent settings watches: {}", error);
+         }
+         drop(debouncer);
+
+         let locks = app_handle.try_state::<AgentSettingsLocks>();
+         for file in changed {
+            let Some(agents) = state.files.get(&file) else {
+               continue;
+            };
+            for event in change_events(&file, agents, locks.as_deref()) {
+               log::debug!(
+                  "Agent settings for {} changed on disk ({:?})",
+                  event.agent_id,
+                  event.source
+               );
+               let _ = app_handle.emit(AGENT_SETTINGS_CHANGED_EVENT, &event);
+            }
+         }
+      })
+      .map_err(|e| watch_error(Path::new(""), e))
+   }
+}
+
+/// Start watching the directories in `after` but not `before` and stop watching those in
+/// `before` but not `after`
+fn sync_directories(
+   debouncer: Option<&mut SettingsDebouncer>,
+   before: &HashSet<PathBuf>,
+   after: &HashSet<PathBuf>,
+) -> Result<(), AgentSettingsError> {
+   let Some(debouncer) = debouncer else {
+      return Ok(());
+   };
+   for dir in before.difference(after) {
+      debouncer
+         .watcher()
+         .unwatch(dir)
+         .map_err(|e| watch_error(dir, e))?;
+   }
+   for dir in after.difference(before) {
+      debouncer
+         .watcher()
+         .watch(dir, RecursiveMode::NonRecursive)
+         .map_err(|e| watch_error(dir, e))?;
+   }
+   Ok(())
+}
+
+/// Path events for a config file will be reported under: the file in its canonicalized
+/// directory, with the file itself left as named even if it is a symlink
+fn watch_location(path: &Path) -> Result<PathBuf, AgentSettingsError> {
+   let invalid = || {
+      AgentSettingsError::io(
+         path,
+         io::Error::new(io::ErrorKind::InvalidInput, "not a file path"),
+      )
+   };
+   let parent = path.parent().ok_or_else(invalid)?;
+   let name = path.file_name().ok_or_else(invalid)?;
+   let dir = fs::canonicalize(parent).map_err(|e| AgentSettingsError::io(parent, e))?;
+   Ok(dir.join(name))
+}
+
+/// The file `location` leads to with symlinks resolved, or `location` itself when it doesn't
+/// exist yet
+fn resolve_location(location: &Path) -> PathBuf {
+   fs::canonicalize(location).unwrap_or_else(|_| location.to_path_buf())
+}
+
+fn watch_error(path: &Path, error: notify::Error) -> AgentSettingsError {
+   match error.kind {
+      notify::ErrorKind::Io(error) => AgentSettingsError::io(path, error),
+      kind => AgentSettingsError::Io {
+         kind: "Other".to_string(),
+         path: path.display().to_string(),
+         message: format!("{:?}", kind),
+      },
+   }
+}
+
+/// Content of a changed config file with the metadata taken before reading it, or `None` when it
+/// was removed. Files over the default size limit or that aren't text are refused as the read
+/// commands refuse them.
+fn read_changed(path: &Path) -> Result<Option<(String, fs::Metadata)>, AgentSettingsError> {
+   let metadata = match fs::metadata(path) {
+      Ok(metadata) => metadata,
+      Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(error) => return Err(AgentSettingsError::io(path, error)),
+   };
+   check_size(path, &metadata, None)?;
+   let bytes = fs::read(path).map_err(|e| AgentSettingsError::io(path, e))?;
+   into_text(path, bytes).map(|content| Some((content, metadata)))
+}
+
+/// Build the events for a changed config file, reading it once for all agents that use it
+fn change_events(
+   path: &Path,
+   agents: &[WatchedAgent],
+   locks: Option<&AgentSettingsLocks>,
+) -> Vec<AgentSettingsChangedEvent> {
+   let content = match read_changed(path) {
+      Ok(content) => content,
+      Err(error) => {
+         return agents
+            .iter()
+            .map(|agent| agent.event(ChangeSource::External, Err(error.clone())))
+            .collect();
+      }
+   };
+
+   let source = match (&content, locks) {
+      (Some((content, _)), Some(locks)) if locks.is_own_write(path, content) => {
+         ChangeSource::SelfWrite
+      }
+      _ => ChangeSource::External,
+   };
+   let format = ConfigFormat::from_path(&path.to_string_lossy());
+   let encryption = locks.and_then(AgentSettingsLocks::encryption);
+   let file = match content {
+      Some((content, metadata)) => decode(encryption, path, content).and_then(|content| {
+         parse_config(&content, format).map(|value| {
+            Some(ConfigFile {
+               meta: meta_from(format, Some(&metadata), was_created(path), Some(&value)),
+               version: version_from(metadata.modified().ok(), &content),
+               value,
+            })
+         })
+      }),
+      None => Ok(None),
+   };
+
+   agents
+      .iter()
+      .map(|agent| {
+         let settings = match &file {
+            Ok(file) => Ok(agent.keys.read_file(file.as_ref(), format)),
+            Err(error) => Err(error.clone()),
+         };
+         agent.event(source, settings)
+      })
+      .collect()
+}
+
+impl WatchedAgent {
+   fn event(
+      &self,
+      source: ChangeSource,
+      settings: Result<AgentSettings, AgentSettingsError>,
+   ) -> AgentSettingsChangedEvent {
+      let (settings, error) = match settings {
+         Ok(settings) => (Some(settings), None),
+         Err(error) => (None, Some(error)),
+      };
+      AgentSettingsChangedEvent {
+         agent_id: self.agent_id.clone(),
+         settings_path: self.settings_path.clone(),
+         source,
+         settings,
+         error,
+      }
+   }
+}
+
+/// Start emitting `agent-settings-changed` for an agent whose config file changes on disk. The
+/// key parameters select which settings the event carries, as for `get_agent_settings`.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn watch_agent_settings(
+   watcher: State<'_, AgentSettingsWatcher>,
+   agent_id: String,
+   settings_path: String,
+   model_key: String,
+   preview_key: Option<String>,
+   reasoning_key: Option<String>,
+   temperature_key: Option<String>,
+   max_tokens_key: Option<String>,
+   tools_key: Option<String>,
+) -> Result<(), AgentSettingsError> {
+   let keys = SettingsKeys {
+      model: model_key,
+      preview: preview_key,
+      reasoning: reasoning_key,
+      temperature: temperature_key,
+      max_tokens: max_tokens_key,
+      tools: tools_key,
+   };
+   keys.validate()?;
+   let path = resolve_settings_path(&settings_path)?;
+
+   log::info!(
+      "Watching settings for agent {}: {}",
+      agent_id,
+      path.display()
+   );
+   watcher.watch(agent_id, settings_path, &path, keys)
+}
+
+/// Stop watching an agent's config file. Each `watch_agent_settings` call needs its own
+/// `unwatch_agent_settings`, so panels watching the same agent don't cancel each other.
+#[command]
+pub async fn unwatch_agent_settings(
+   watcher: State<'_, AgentSettingsWatcher>,
+   agent_id: String,
+   settings_path: String,
+) -> Result<(), AgentSettingsError> {
+   let path = resolve_settings_path(&settings_path)?;
+   watcher.unwatch(&agent_id, &path)
+}
+
+/// The config files being watched and the agents watching them, for debugging
+#[command]
+pub async fn list_watched_settings(
+   watcher: State<'_, AgentSettingsWatcher>,
+) -> Result<Vec<WatchedSettingsFile>, AgentSettingsError> {
+   Ok(watcher.list())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::{Value, json};
+
+   fn watched(agent_id: &str, model_key: &str) -> WatchedAgent {
+      WatchedAgent {
+         agent_id: agent_id.into(),
+         settings_path: ".codex/config.toml".into(),
+         keys: SettingsKeys {
+            model: model_key.into(),
+            ..SettingsKeys::default()
+         },
+         location: PathBuf::from("/home/user/.codex/config.toml"),
+         watchers: 1,
+      }
+   }
+
+   #[tokio::test]
+   async fn test_change_events_flag_own_writes() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("config.toml");
+      let locks = AgentSettingsLocks::new();
+      let agents = [
+         watched("codex", "model"),
+         watched("codex-fast", "profiles.fast.model"),
+      ];
+
+      fs::write(&path, "model = \"o3\"\n").unwrap();
+      locks.record_write(&path, "model = \"o3\"\n").await;
+      let events = change_events(&path, &agents, Some(&locks));
+      assert_eq!(events.len(), 2);
+      assert_eq!(events[0].source, ChangeSource::SelfWrite);
+      assert_eq!(
+         events[0].settings.as_ref().unwrap().model.as_deref(),
+         Some("o3")
+      );
+
+      fs::write(
+         &path,
+         "model = \"gpt-5\"\n[profiles.fast]\nmodel = \"o4-mini\"\n",
+      )
+      .unwrap();
+      let events = change_events(&path, &agents, Some(&locks));
+      assert_eq!(events[0].source, ChangeSource::External);
+      assert_eq!(
+         events[0].settings.as_ref().unwrap().model.as_deref(),
+         Some("gpt-5")
+      );
+      assert_eq!(
+         events[1].settings.as_ref().unwrap().model.as_deref(),
+         Some("o4-mini")
+      );
+   }
+
+   #[test]
+   fn test_change_events_for_broken_and_deleted_files() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.json");
+      let agents = [watched("claude", "model")];
+
+      fs::write(&path, "{ \"model\": ").unwrap();
+      let events = change_events(&path, &agents, None);
+      assert!(events[0].settings.is_none());
+      assert!(matches!(
+         events[0].error,
+         Some(AgentSettingsError::Parse { .. })
+      ));
+
+      fs::remove_file(&path).unwrap();
+      let events = change_events(&path, &agents, None);
+      assert_eq!(events[0].settings.as_ref().unwrap().model, None);
+      assert!(events[0].error.is_none());
+   }
+
+   #[test]
+   fn test_watchers_are_counted() {
+      let mut state = WatchState::default();
+      let file = PathBuf::from("/home/user/.codex/config.toml");
+      state.add(file.clone(), watched("codex", "model"));
+      state.add(file.clone(), watched("codex", "model"));
+      state.add(file.clone(), watched("codex-fast", "profiles.fast.model"));
+      let listed = state.list();
+      assert_eq!(listed.len(), 1);
+      assert_eq!(listed[0].agents[0].watchers, 2);
+
+      assert!(state.remove("codex", &file));
+      assert_eq!(state.list()[0].agents[0].watchers, 1);
+      assert!(state.remove("codex", &file));
+      assert!(state.remove("codex-fast", &file));
+      assert!(!state.remove("codex", &file));
+      assert!(state.list().is_empty());
+      assert!(state.directories().is_empty());
+   }
+
+   #[cfg(unix)]
+   #[test]
+   fn test_symlinks_share_a_watch_and_are_followed_when_replaced() {
+      let dir = tempfile::tempdir().unwrap();
+      let dotfiles = dir.path().join("dotfiles");
+      let home = dir.path().join("home");
+      fs::create_dir_all(&dotfiles).unwrap();
+      fs::create_dir_all(&home).unwrap();
+      let target = dotfiles.join("config.toml");
+      let link = home.join("config.toml");
+      fs::write(&target, "model = \"o3\"\n").unwrap();
+      std::os::unix::fs::symlink(&target, &link).unwrap();
+
+      let mut state = WatchState::default();
+      for (agent_id, path) in [("codex", &link), ("codex-dotfiles", &target)] {
+         let location = watch_location(path).unwrap();
+         state.add(
+            resolve_location(&location),
+            WatchedAgent {
+               agent_id: agent_id.into(),
+               location,
+               ..watched(agent_id, "model")
+            },
+         );
+      }
+      let real = fs::canonicalize(&target).unwrap();
+      assert_eq!(state.files.len(), 1);
+      assert!(state.files.contains_key(&real));
+      assert_eq!(state.directories().len(), 2);
+
+      // Saved by an editor that replaces the link with a regular file
+      let link_location = watch_location(&link).unwrap();
+      fs::remove_file(&link).unwrap();
+      fs::write(&link, "model = \"gpt-5\"\n").unwrap();
+      assert_eq!(state.file_for(&link_location), Some(real.clone()));
+      let files = state.relocate(&real);
+      assert_eq!(files, vec![link_location.clone(), real.clone()]);
+      assert_eq!(state.files[&link_location][0].agent_id, "codex");
+      assert_eq!(state.files[&real][0].agent_id, "codex-dotfiles");
+   }
+
+   #[test]
+   fn test_event_payload_shape() {
+      let event =
+         watched("codex", "model").event(ChangeSource::SelfWrite, Ok(AgentSettings::default()));
+      let payload = serde_json::to_value(&event).unwrap();
+      assert_eq!(payload["agentId"], json!("codex"));
+      assert_eq!(payload["settingsPath"], json!(".codex/config.toml"));
+      assert_eq!(payload["source"], json!("self"));
+      assert_eq!(payload["error"], Value::Null);
+   }
+}
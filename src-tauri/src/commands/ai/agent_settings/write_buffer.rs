@@ -0,0 +1,340 @@
+use super::{
+   enabled::read_athas_setting,
+   error::AgentSettingsError,
+   format::ConfigFormat,
+   history::{AgentSettingsHistory, Journal},
+   settings::{AgentSettings, SettingsKeys},
+   storage::{AgentSettingsLocks, WriteOptions, update_config_file},
+};
+use serde::Serialize;
+use std::{
+   collections::HashMap,
+   path::{Path, PathBuf},
+   sync::Mutex,
+   time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager, Runtime, command};
+
+/// Event emitted when a buffered settings write fails after its `set_agent_settings` call
+/// already returned
+pub const AGENT_SETTINGS_WRITE_FAILED_EVENT: &str = "agent-settings-write-failed";
+
+/// Athas setting holding how long, in milliseconds, sets to the same file are buffered
+const COALESCE_WINDOW_KEY: &str = "ai.agentSettings.writeCoalesceMs";
+const DEFAULT_COALESCE_WINDOW: Duration = Duration::from_millis(300);
+
+/// A buffered write that failed when it was flushed
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedSettingsWrite {
+   pub agent_id: String,
+   pub path: String,
+   /// Keys of every set merged into the write, none of which were saved
+   pub keys: Vec<String>,
+   pub error: AgentSettingsError,
+}
+
+/// One `set_agent_settings` call waiting to be written
+struct BufferedSet {
+   keys: SettingsKeys,
+   settings: AgentSettings,
+}
+
+/// The sets buffered for one file on behalf of one agent, applied in order when flushed
+struct PendingWrite {
+   format: ConfigFormat,
+   /// Size limit for the existing file, as given by the latest set
+   max_file_bytes: Option<u64>,
+   sets: Vec<BufferedSet>,
+}
+
+impl PendingWrite {
+   /// Keys the sets write, each listed once
+   fn keys(&self) -> Vec<String> {
+      let mut keys: Vec<String> = Vec::new();
+      for set in &self.sets {
+         for key in set.keys.written_keys(&set.settings) {
+            if !keys.contains(&key) {
+               keys.push(key);
+            }
+         }
+      }
+      keys
+   }
+}
+
+/// Settings writes held back so that bursts of sets to the same file, such as from a dragged
+/// slider, land as a single write
+#[derive(Default)]
+pub struct SettingsWriteBuffer {
+   pending: Mutex<HashMap<(PathBuf, String), PendingWrite>>,
+}
+
+impl SettingsWriteBuffer {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   /// Buffer a set, returning true when nothing was pending for the file before, in which case
+   /// the caller schedules its flush
+   fn push(
+      &self,
+      path: &Path,
+      agent_id: &str,
+      format: ConfigFormat,
+      max_file_bytes: Option<u64>,
+      keys: SettingsKeys,
+      settings: AgentSettings,
+   ) -> bool {
+      let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+      let key = (path.to_path_buf(), agent_id.to_string());
+      let first = !pending.contains_key(&key);
+      let write = pending.entry(key).or_insert_with(|| PendingWrite {
+         format,
+         max_file_bytes,
+         sets: Vec::new(),
+      });
+      write.max_file_bytes = max_file_bytes;
+      write.sets.push(BufferedSet { keys, settings });
+      first
+   }
+
+   /// Remove the writes pending for `path`, or for every file when `path` is `None`
+   fn take(&self, path: Option<&Path>) -> Vec<(PathBuf, String, PendingWrite)> {
+      let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+      let keys: Vec<(PathBuf, String)> = pending
+         .keys()
+         .filter(|(pending_path, _)| path.is_none_or(|path| pending_path == path))
+         .cloned()
+         .collect();
+      keys
+         .into_iter()
+         .filter_map(|key| {
+            let write = pending.remove(&key)?;
+            Some((key.0, key.1, write))
+         })
+         .collect()
+   }
+}
+
+/// How long sets are buffered, from `ai.agentSettings.writeCoalesceMs`
+async fn coalesce_window(locks: &AgentSettingsLocks) -> Duration {
+   match read_athas_setting(locks, COALESCE_WINDOW_KEY).await {
+      Ok(Some(value)) => value
+         .as_u64()
+         .map(Duration::from_millis)
+         .unwrap_or(DEFAULT_COALESCE_WINDOW),
+      Ok(None) => DEFAULT_COALESCE_WINDOW,
+      Err(e) => {
+         log::warn!("Failed to read the settings write coalescing window: {}", e);
+         DEFAULT_COALESCE_WINDOW
+      }
+   }
+}
+
+/// Buffer a set of `settings` into the file at `path`, to be written together with any other
+/// sets to the same file once the coalescing window has passed
+pub(super) async fn buffer_set<R: Runtime>(
+   app_handle: &AppHandle<R>,
+   path: &Path,
+   agent_id: &str,
+   format: ConfigFormat,
+   max_file_bytes: Option<u64>,
+   keys: SettingsKeys,
+   settings: AgentSettings,
+) {
+   let buffer = app_handle.state::<SettingsWriteBuffer>();
+   if !buffer.push(path, agent_id, format, max_file_bytes, keys, settings) {
+      return;
+   }
+
+   let window = coalesce_window(&app_handle.state::<AgentSettingsLocks>()).await;
+   let app_handle = app_handle.clone();
+   let path = path.to_path_buf();
+   tauri::async_runtime::spawn(async move {
+      tokio::time::sleep(window).await;
+      flush_path(&app_handle, &path).await;
+   });
+}
+
+/// Write one file's buffered sets in a single read-modify-write pass
+async fn write_pending(
+   locks: &AgentSettingsLocks,
+   history: &AgentSettingsHistory,
+   path: &Path,
+   agent_id: &str,
+   write: PendingWrite,
+) -> Result<bool, AgentSettingsError> {
+   let options = WriteOptions {
+      journal: Some(Journal { history, agent_id }),
+      max_file_bytes: write.max_file_bytes,
+      ..WriteOptions::default()
+   };
+   update_config_file(locks, path, write.format, options, |value| {
+      write
+         .sets
+         .into_iter()
+         .try_for_each(|set| set.keys.write(value, set.settings))
+   })
+   .await
+}
+
+/// Write the given buffered sets, emitting `AGENT_SETTINGS_WRITE_FAILED_EVENT` for each file
+/// that fails and returning those failures
+async fn flush<R: Runtime>(
+   app_handle: &AppHandle<R>,
+   writes: Vec<(PathBuf, String, PendingWrite)>,
+) -> Vec<FailedSettingsWrite> {
+   let locks = app_handle.state::<AgentSettingsLocks>();
+   let history = app_handle.state::<AgentSettingsHistory>();
+   let mut failures = Vec::new();
+   for (path, agent_id, write) in writes {
+      let keys = write.keys();
+      match write_pending(&locks, &history, &path, &agent_id, write).await {
+         Ok(true) => log::info!("Saved buffered settings for agent {}", agent_id),
+         Ok(false) => {}
+         Err(error) => {
+            log::warn!(
+               "Failed to save buffered settings for agent {}: {}",
+               agent_id,
+               error
+            );
+            let failure = FailedSettingsWrite {
+               agent_id,
+               path: path.display().to_string(),
+               keys,
+               error,
+            };
+            let _ = app_handle.emit(AGENT_SETTINGS_WRITE_FAILED_EVENT, &failure);
+            failures.push(failure);
+         }
+      }
+   }
+   failures
+}
+
+/// Write the sets buffered for `path` now
+pub(super) async fn flush_path<R: Runtime>(
+   app_handle: &AppHandle<R>,
+   path: &Path,
+) -> Vec<FailedSettingsWrite> {
+   let writes = app_handle.state::<SettingsWriteBuffer>().take(Some(path));
+   flush(app_handle, writes).await
+}
+
+/// Write every buffered set now, as the app does before it exits
+pub async fn flush_pending_writes<R: Runtime>(
+   app_handle: &AppHandle<R>,
+) -> Vec<FailedSettingsWrite> {
+   let writes = app_handle.state::<SettingsWriteBuffer>().take(None);
+   flush(app_handle, writes).await
+}
+
+/// Write every buffered settings change now instead of when its coalescing window ends.
+/// Returns the writes that failed, which are also reported through
+/// `agent-settings-write-failed`.
+#[command]
+pub async fn flush_pending_settings_writes<R: Runtime>(
+   app_handle: AppHandle<R>,
+) -> Result<Vec<FailedSettingsWrite>, AgentSettingsError> {
+   Ok(flush_pending_writes(&app_handle).await)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   fn mock_app() -> tauri::App<tauri::test::MockRuntime> {
+      let app = tauri::test::mock_app();
+      app.manage(AgentSettingsLocks::new());
+      app.manage(AgentSettingsHistory::at(None, 0));
+      app.manage(SettingsWriteBuffer::new());
+      app
+   }
+
+   fn keys() -> SettingsKeys {
+      SettingsKeys {
+         model: "model".into(),
+         temperature: Some("sampling.temperature".into()),
+         ..SettingsKeys::default()
+      }
+   }
+
+   #[tokio::test]
+   async fn test_sets_to_one_file_are_merged() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join("settings.json");
+      let app = mock_app();
+
+      for temperature in [0.2, 0.4, 0.6] {
+         let settings = AgentSettings {
+            temperature: Some(temperature),
+            ..AgentSettings::default()
+         };
+         buffer_set(
+            app.handle(),
+            &path,
+            "test",
+            ConfigFormat::Json,
+            None,
+            keys(),
+            settings,
+         )
+         .await;
+      }
+      let settings = AgentSettings {
+         model: Some("sonnet".into()),
+         ..AgentSettings::default()
+      };
+      buffer_set(
+         app.handle(),
+         &path,
+         "test",
+         ConfigFormat::Json,
+         None,
+         keys(),
+         settings,
+      )
+      .await;
+      assert!(!path.exists());
+
+      assert!(flush_pending_writes(app.handle()).await.is_empty());
+      let value: serde_json::Value =
+         serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+      assert_eq!(
+         value,
+         json!({"model": "sonnet", "sampling": {"temperature": 0.6}})
+      );
+      assert!(flush_pending_writes(app.handle()).await.is_empty());
+   }
+
+   #[tokio::test]
+   async fn test_failed_flush_reports_keys() {
+      let dir = tempfile::tempdir().unwrap();
+      std::fs::write(dir.path().join("blocker"), "").unwrap();
+      let path = dir.path().join("blocker").join("settings.json");
+      let app = mock_app();
+
+      let settings = AgentSettings {
+         model: Some("sonnet".into()),
+         temperature: Some(0.5),
+         ..AgentSettings::default()
+      };
+      buffer_set(
+         app.handle(),
+         &path,
+         "test",
+         ConfigFormat::Json,
+         None,
+         keys(),
+         settings,
+      )
+      .await;
+
+      let failures = flush_pending_writes(app.handle()).await;
+      assert_eq!(failures.len(), 1);
+      assert_eq!(failures[0].agent_id, "test");
+      assert_eq!(failures[0].keys, ["model", "sampling.temperature"]);
+   }
+}
@@ -0,0 +1,835 @@
+use crate::commands::ai::model_catalog::ModelProvider;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chacha20poly1305::{
+   AeadCore, ChaCha20Poly1305, Key, KeyInit, Nonce,
+   aead::{Aead, OsRng, Payload},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+   collections::{BTreeMap, HashSet},
+   fs,
+   io::{ErrorKind, Write},
+   path::{Path, PathBuf},
+   sync::Mutex,
+};
+use tauri::{State, command};
+
+const KEYCHAIN_SERVICE: &str = "com.code.athas";
+/// Where credential files are kept, relative to the home directory
+const CREDENTIALS_DIR: &str = ".athas";
+/// Which providers have a stored key and where. Holds no secrets.
+const INDEX_FILE: &str = "credentials.json";
+const VAULT_FILE: &str = "credentials.vault";
+const VAULT_KEY_FILE: &str = "credentials.key";
+const VAULT_VERSION: u32 = 1;
+const VAULT_AAD: &[u8] = b"athas-credentials-v1";
+const NONCE_LEN: usize = 12;
+/// Prefix of the accounts `store_ai_provider_token` kept provider tokens under through
+/// `secure_storage`, before this store existed
+const LEGACY_ACCOUNT_PREFIX: &str = "ai_token_";
+
+/// Where a provider's API key is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CredentialSource {
+   Keychain,
+   EncryptedFile,
+}
+
+impl CredentialSource {
+   pub fn describe(self) -> &'static str {
+      match self {
+         CredentialSource::Keychain => "the keychain",
+         CredentialSource::EncryptedFile => "the encrypted credentials file",
+      }
+   }
+}
+
+/// Somewhere secrets can be kept by account name
+pub trait SecretBackend: Send + Sync {
+   fn get(&self, account: &str) -> Result<Option<String>, String>;
+   fn set(&self, account: &str, secret: &str) -> Result<(), String>;
+   /// Remove the secret. Succeeds when there is none.
+   fn delete(&self, account: &str) -> Result<(), String>;
+}
+
+/// The OS keychain: Keychain on macOS, Secret Service on Linux, Credential Manager on Windows
+pub struct KeyringBackend;
+
+impl KeyringBackend {
+   fn entry(account: &str) -> Result<keyring::Entry, String> {
+      keyring::Entry::new(KEYCHAIN_SERVICE, account)
+         .map_err(|e| format!("Failed to initialize keychain entry: {e}"))
+   }
+}
+
+impl SecretBackend for KeyringBackend {
+   fn get(&self, account: &str) -> Result<Option<String>, String> {
+      match Self::entry(account)?.get_password() {
+         Ok(secret) => Ok(Some(secret)),
+         Err(keyring::Error::NoEntry) => Ok(None),
+         Err(e) => Err(format!("Failed to read from keychain: {e}")),
+      }
+   }
+
+   fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+      Self::entry(account)?
+         .set_password(secret)
+         .map_err(|e| format!("Failed to write to keychain: {e}"))
+   }
+
+   fn delete(&self, account: &str) -> Result<(), String> {
+      match Self::entry(account)?.delete_credential() {
+         Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+         Err(e) => Err(format!("Failed to remove from keychain: {e}")),
+      }
+   }
+}
+
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+   version: u32,
+   nonce: String,
+   ciphertext: String,
+}
+
+/// Secrets encrypted with ChaCha20-Poly1305 in a file, for machines without a keychain such as
+/// headless Linux. The key is kept in a separate owner-only file, so this protects against the
+/// vault being copied or shared on its own, not against someone who can read the home directory.
+pub struct EncryptedFileBackend {
+   path: PathBuf,
+   key_path: PathBuf,
+}
+
+impl EncryptedFileBackend {
+   pub fn new(path: PathBuf, key_path: PathBuf) -> Self {
+      Self { path, key_path }
+   }
+
+   fn load_key(&self) -> Result<Option<ChaCha20Poly1305>, String> {
+      match fs::read(&self.key_path) {
+         Ok(bytes) if bytes.len() == 32 => Ok(Some(ChaCha20Poly1305::new(Key::from_slice(&bytes)))),
+         Ok(_) => Err(format!(
+            "{} is not a valid credentials key",
+            self.key_path.display()
+         )),
+         Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+         Err(e) => Err(format!("Failed to read {}: {e}", self.key_path.display())),
+      }
+   }
+
+   fn load_or_create_key(&self) -> Result<ChaCha20Poly1305, String> {
+      if let Some(cipher) = self.load_key()? {
+         return Ok(cipher);
+      }
+      let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+      write_private(&self.key_path, &key)?;
+      Ok(ChaCha20Poly1305::new(&key))
+   }
+
+   fn load(&self) -> Result<BTreeMap<String, String>, String> {
+      let content = match fs::read_to_string(&self.path) {
+         Ok(content) => content,
+         Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+         Err(e) => return Err(format!("Failed to read {}: {e}", self.path.display())),
+      };
+      let vault: VaultFile = serde_json::from_str(&content)
+         .map_err(|e| format!("Failed to parse {}: {e}", self.path.display()))?;
+      if vault.version != VAULT_VERSION {
+         return Err(format!(
+            "{} has unsupported version {}",
+            self.path.display(),
+            vault.version
+         ));
+      }
+      let Some(cipher) = self.load_key()? else {
+         return Err(format!(
+            "{} is missing, so {} can't be decrypted",
+            self.key_path.display(),
+            self.path.display()
+         ));
+      };
+
+      let nonce = STANDARD
+         .decode(&vault.nonce)
+         .ok()
+         .filter(|nonce| nonce.len() == NONCE_LEN);
+      let ciphertext = STANDARD.decode(&vault.ciphertext).ok();
+      let (Some(nonce), Some(ciphertext)) = (nonce, ciphertext) else {
+         return Err(format!("{} is corrupted", self.path.display()));
+      };
+      let payload = Payload {
+         msg: &ciphertext,
+         aad: VAULT_AAD,
+      };
+      let plaintext = cipher
+         .decrypt(Nonce::from_slice(&nonce), payload)
+         .map_err(|_| {
+            format!(
+               "Failed to decrypt {}: wrong key or corrupted file",
+               self.path.display()
+            )
+         })?;
+      serde_json::from_slice(&plaintext)
+         .map_err(|e| format!("Failed to parse decrypted {}: {e}", self.path.display()))
+   }
+
+   fn save(&self, secrets: &BTreeMap<String, String>) -> Result<(), String> {
+      if secrets.is_empty() {
+         return match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+               Err(format!("Failed to remove {}: {e}", self.path.display()))
+            }
+            _ => Ok(()),
+         };
+      }
+
+      let cipher = self.load_or_create_key()?;
+      let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+      let plaintext = serde_json::to_vec(secrets).map_err(|e| e.to_string())?;
+      let payload = Payload {
+         msg: &plaintext,
+         aad: VAULT_AAD,
+      };
+      let ciphertext = cipher
+         .encrypt(&nonce, payload)
+         .map_err(|_| "Failed to encrypt credentials".to_string())?;
+      let vault = VaultFile {
+         version: VAULT_VERSION,
+         nonce: STANDARD.encode(nonce),
+         ciphertext: STANDARD.encode(ciphertext),
+      };
+      let content = serde_json::to_string_pretty(&vault).map_err(|e| e.to_string())?;
+      write_private(&self.path, content.as_bytes())
+   }
+}
+
+impl SecretBackend for EncryptedFileBackend {
+   fn get(&self, account: &str) -> Result<Option<String>, String> {
+      Ok(self.load()?.remove(account))
+   }
+
+   fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+      let mut secrets = self.load()?;
+      secrets.insert(account.to_string(), secret.to_string());
+      self.save(&secrets)
+   }
+
+   fn delete(&self, account: &str) -> Result<(), String> {
+      let mut secrets = self.load()?;
+      if secrets.remove(account).is_some() {
+         self.save(&secrets)?;
+      }
+      Ok(())
+   }
+}
+
+/// Replace `path` with `content` atomically. Temporary files are created readable by the owner
+/// only, and the rename keeps those permissions.
+fn write_private(path: &Path, content: &[u8]) -> Result<(), String> {
+   let dir = path.parent().unwrap_or(Path::new("."));
+   fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+   let mut file = tempfile::NamedTempFile::new_in(dir)
+      .map_err(|e| format!("Failed to create temporary file: {e}"))?;
+   file
+      .write_all(content)
+      .map_err(|e| format!("Failed to write temporary file: {e}"))?;
+   file
+      .persist(path)
+      .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+   Ok(())
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CredentialIndex {
+   #[serde(default)]
+   providers: BTreeMap<String, CredentialSource>,
+}
+
+/// A provider as listed in the credentials panel
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialProvider {
+   pub id: String,
+   pub builtin: bool,
+   /// Environment variables agents read the provider's key from
+   pub env_vars: Vec<String>,
+   /// Where the key is stored, if Athas has one
+   pub source: Option<CredentialSource>,
+}
+
+/// Provider API keys, kept in the OS keychain and in an encrypted file when the keychain can't be
+/// used. An index file records where each provider's key went, so listing providers doesn't
+/// touch the keychain beyond moving in the tokens older versions saved.
+pub struct CredentialStore {
+   keychain: Box<dyn SecretBackend>,
+   fallback: EncryptedFileBackend,
+   index_path: PathBuf,
+   /// Where older versions kept provider tokens, read once per provider missing from the index
+   legacy: Option<Box<dyn SecretBackend>>,
+   legacy_checked: Mutex<HashSet<String>>,
+   lock: Mutex<()>,
+}
+
+impl CredentialStore {
+   pub fn new(keychain: Box<dyn SecretBackend>, dir: &Path) -> Self {
+      Self {
+         keychain,
+         fallback: EncryptedFileBackend::new(dir.join(VAULT_FILE), dir.join(VAULT_KEY_FILE)),
+         index_path: dir.join(INDEX_FILE),
+         legacy: None,
+         legacy_checked: Mutex::new(HashSet::new()),
+         lock: Mutex::new(()),
+      }
+   }
+
+   /// Also pick up the tokens older versions saved under `ai_token_<id>` in `legacy`. Each is
+   /// moved into this store the first time its provider is looked up.
+   pub fn with_legacy(self, legacy: Box<dyn SecretBackend>) -> Self {
+      Self {
+         legacy: Some(legacy),
+         ..self
+      }
+   }
+
+   /// The store backed by the OS keychain and `~/.athas`
+   pub fn open() -> Result<Self, String> {
+      let home = dirs::home_dir().ok_or("Could not determine home directory")?;
+      Ok(Self::new(
+         Box::new(KeyringBackend),
+         &home.join(CREDENTIALS_DIR),
+      ))
+   }
+
+   fn account(provider: &str) -> String {
+      format!("api_key_{}", provider)
+   }
+
+   /// Accounts older versions kept the provider's token under. The frontend called Google
+   /// `gemini`.
+   fn legacy_accounts(provider: &str) -> Vec<String> {
+      let mut accounts = vec![format!("{}{}", LEGACY_ACCOUNT_PREFIX, provider)];
+      if provider == ModelProvider::Google.id() {
+         accounts.push(format!("{}gemini", LEGACY_ACCOUNT_PREFIX));
+      }
+      accounts
+   }
+
+   fn load_index(&self) -> Result<CredentialIndex, String> {
+      match fs::read_to_string(&self.index_path) {
+         Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {e}", self.index_path.display())),
+         Err(e) if e.kind() == ErrorKind::NotFound => Ok(CredentialIndex::default()),
+         Err(e) => Err(format!("Failed to read {}: {e}", self.index_path.display())),
+      }
+   }
+
+   fn save_index(&self, index: &CredentialIndex) -> Result<(), String> {
+      let content = serde_json::to_string_pretty(index).map_err(|e| e.to_string())?;
+      write_private(&self.index_path, content.as_bytes())
+   }
+
+   fn lock(&self) -> std::sync::MutexGuard<'_, ()> {
+      self
+         .lock
+         .lock()
+         .unwrap_or_else(|poisoned| poisoned.into_inner())
+   }
+
+   /// Store `key` for `provider`, in the keychain when possible
+   pub fn set(&self, provider: &str, key: &str) -> Result<CredentialSource, String> {
+      validate_provider_id(provider)?;
+      let key = key.trim();
+      if key.is_empty() {
+         return Err("API key is empty".to_string());
+      }
+      let _guard = self.lock();
+      let mut index = self.load_index()?;
+      let source = self.store(&mut index, provider, key)?;
+      self.save_index(&index)?;
+      self.forget_legacy(provider);
+      Ok(source)
+   }
+
+   /// Write the key and record it in `index`, which the caller saves. Called with the lock held.
+   fn store(
+      &self,
+      index: &mut CredentialIndex,
+      provider: &str,
+      key: &str,
+   ) -> Result<CredentialSource, String> {
+      let account = Self::account(provider);
+      let source = match self.keychain.set(&account, key) {
+         Ok(()) => CredentialSource::Keychain,
+         Err(error) => {
+            log::warn!(
+               "Keychain unavailable for provider '{}', using the encrypted credentials file: {}",
+               provider,
+               error
+            );
+            self.fallback.set(&account, key)?;
+            CredentialSource::EncryptedFile
+         }
+      };
+      let previous = index.providers.insert(provider.to_string(), source);
+      if previous == Some(CredentialSource::EncryptedFile)
+         && source == CredentialSource::Keychain
+         && let Err(error) = self.fallback.delete(&account)
+      {
+         log::warn!(
+            "Failed to remove old key for provider '{}' from {}: {}",
+            provider,
+            self.fallback.path.display(),
+            error
+         );
+      }
+      Ok(source)
+   }
+
+   /// Move a token an older version saved for `provider` into the store, when `index` has no key
+   /// for it. Each provider is only looked for once. Called with the lock held.
+   fn migrate_legacy(&self, index: &mut CredentialIndex, provider: &str) -> Result<(), String> {
+      let Some(legacy) = &self.legacy else {
+         return Ok(());
+      };
+      if index.providers.contains_key(provider) {
+         return Ok(());
+      }
+      let first_check = self
+         .legacy_checked
+         .lock()
+         .unwrap_or_else(|poisoned| poisoned.into_inner())
+         .insert(provider.to_string());
+      if !first_check {
+         return Ok(());
+      }
+
+      for account in Self::legacy_accounts(provider) {
+         let token = match legacy.get(&account) {
+            Ok(token) => token.filter(|token| !token.trim().is_empty()),
+            Err(error) => {
+               log::debug!("Failed to read legacy token {}: {}", account, error);
+               None
+            }
+         };
+         let Some(token) = token else {
+            continue;
+         };
+         let source = self.store(index, provider, token.trim())?;
+         self.save_index(index)?;
+         self.forget_legacy(provider);
+         log::info!(
+            "Moved the API key an older version saved for {} into {}",
+            provider,
+            source.describe()
+         );
+         return Ok(());
+      }
+      Ok(())
+   }
+
+   /// Remove the provider's legacy tokens, so one can't come back after the key is replaced or
+   /// deleted
+   fn forget_legacy(&self, provider: &str) {
+      let Some(legacy) = &self.legacy else {
+         return;
+      };
+      self
+         .legacy_checked
+         .lock()
+         .unwrap_or_else(|poisoned| poisoned.into_inner())
+         .insert(provider.to_string());
+      for account in Self::legacy_accounts(provider) {
+         if let Err(error) = legacy.delete(&account) {
+            log::warn!("Failed to remove legacy token {}: {}", account, error);
+         }
+      }
+   }
+
+   pub fn get(&self, provider: &str) -> Result<Option<String>, String> {
+      let _guard = self.lock();
+      let account = Self::account(provider);
+      let mut index = self.load_index()?;
+      self.migrate_legacy(&mut index, provider)?;
+      match index.providers.get(provider) {
+         Some(CredentialSource::Keychain) => self.keychain.get(&account),
+         Some(CredentialSource::EncryptedFile) => self.fallback.get(&account),
+         None => Ok(None),
+      }
+   }
+
+   /// Remove the provider's key. Returns whether one was stored.
+   pub fn delete(&self, provider: &str) -> Result<bool, String> {
+      let _guard = self.lock();
+      let mut index = self.load_index()?;
+      self.migrate_legacy(&mut index, provider)?;
+      let account = Self::account(provider);
+      match index.providers.remove(provider) {
+         Some(CredentialSource::Keychain) => self.keychain.delete(&account)?,
+         Some(CredentialSource::EncryptedFile) => self.fallback.delete(&account)?,
+         None => return Ok(false),
+      }
+      self.save_index(&index)?;
+      Ok(true)
+   }
+
+   /// Where the provider's key is stored, without reading it once any token an older version
+   /// saved for it has been moved into the store
+   pub fn source(&self, provider: &str) -> Result<Option<CredentialSource>, String> {
+      let _guard = self.lock();
+      let mut index = self.load_index()?;
+      self.migrate_legacy(&mut index, provider)?;
+      Ok(index.providers.get(provider).copied())
+   }
+
+   /// Built-in providers, then others with a stored key. Tokens older versions saved for the
+   /// built-in providers are moved into the store first.
+   pub fn providers(&self) -> Result<Vec<CredentialProvider>, String> {
+      let mut stored = {
+         let _guard = self.lock();
+         let mut index = self.load_index()?;
+         for provider in ModelProvider::ALL {
+            self.migrate_legacy(&mut index, provider.id())?;
+         }
+         index.providers
+      };
+      let mut providers: Vec<_> = ModelProvider::ALL
+         .into_iter()
+         .map(|provider| CredentialProvider {
+            id: provider.id().to_string(),
+            builtin: true,
+            env_vars: provider
+               .api_key_env_vars()
+               .iter()
+               .map(|name| name.to_string())
+               .collect(),
+            source: stored.remove(provider.id()),
+         })
+         .collect();
+      providers.extend(stored.into_iter().map(|(id, source)| CredentialProvider {
+         id,
+         builtin: false,
+         env_vars: Vec::new(),
+         source: Some(source),
+      }));
+      Ok(providers)
+   }
+}
+
+/// Environment variable agents usually read the provider's key from: the first of a built-in
+/// provider's variables, otherwise `<ID>_API_KEY`
+pub fn api_key_env_var(provider: &str) -> String {
+   match ModelProvider::from_id(provider) {
+      Some(provider) => provider.api_key_env_vars()[0].to_string(),
+      None => {
+         let name: String = provider
+            .chars()
+            .map(|c| {
+               if c.is_ascii_alphanumeric() {
+                  c.to_ascii_uppercase()
+               } else {
+                  '_'
+               }
+            })
+            .collect();
+         format!("{}_API_KEY", name)
+      }
+   }
+}
+
+/// Provider ids name keychain entries, so they are kept to lowercase letters, digits, `-`, `_`
+/// and `.`
+pub(crate) fn validate_provider_id(provider: &str) -> Result<(), String> {
+   let valid = !provider.is_empty()
+      && provider
+         .chars()
+         .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.'));
+   if valid {
+      Ok(())
+   } else {
+      Err(format!("Invalid provider id '{}'", provider))
+   }
+}
+
+/// Store a provider's API key in the OS keychain, or in the encrypted credentials file when no
+/// keychain is available. Returns where it was stored.
+#[command]
+pub async fn set_api_key(
+   store: State<'_, CredentialStore>,
+   provider: String,
+   key: String,
+) -> Result<CredentialSource, String> {
+   let source = store.set(&provider, &key)?;
+   log::info!("Stored API key for {} in {}", provider, source.describe());
+   Ok(source)
+}
+
+#[command]
+pub async fn get_api_key(
+   store: State<'_, CredentialStore>,
+   provider: String,
+) -> Result<Option<String>, String> {
+   store.get(&provider)
+}
+
+#[command]
+pub async fn delete_api_key(
+   store: State<'_, CredentialStore>,
+   provider: String,
+) -> Result<bool, String> {
+   let deleted = store.delete(&provider)?;
+   if deleted {
+      log::info!("Deleted API key for {}", provider);
+   }
+   Ok(deleted)
+}
+
+/// List built-in providers and any other provider with a stored key, with where the key is
+/// stored. Key values are never included.
+#[command]
+pub async fn list_credential_providers(
+   store: State<'_, CredentialStore>,
+) -> Result<Vec<CredentialProvider>, String> {
+   store.providers()
+}
+
+/// In-memory keychain for tests, which can be switched off to act like a headless machine
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MockKeychain {
+   pub(crate) entries: Mutex<std::collections::HashMap<String, String>>,
+   pub(crate) unavailable: bool,
+}
+
+#[cfg(test)]
+impl MockKeychain {
+   /// A machine without a keychain, so stored secrets go to the encrypted file
+   pub(crate) fn unavailable() -> std::sync::Arc<Self> {
+      std::sync::Arc::new(Self {
+         unavailable: true,
+         ..Self::default()
+      })
+   }
+}
+
+#[cfg(test)]
+impl SecretBackend for std::sync::Arc<MockKeychain> {
+   fn get(&self, account: &str) -> Result<Option<String>, String> {
+      if self.unavailable {
+         return Err("no keychain".to_string());
+      }
+      Ok(self.entries.lock().unwrap().get(account).cloned())
+   }
+
+   fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+      if self.unavailable {
+         return Err("no keychain".to_string());
+      }
+      let mut entries = self.entries.lock().unwrap();
+      entries.insert(account.to_string(), secret.to_string());
+      Ok(())
+   }
+
+   fn delete(&self, account: &str) -> Result<(), String> {
+      if self.unavailable {
+         return Err("no keychain".to_string());
+      }
+      self.entries.lock().unwrap().remove(account);
+      Ok(())
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::sync::Arc;
+
+   fn store_with(keychain: &Arc<MockKeychain>, dir: &Path) -> CredentialStore {
+      CredentialStore::new(Box::new(keychain.clone()), dir)
+   }
+
+   #[test]
+   fn test_keychain_is_preferred() {
+      let dir = tempfile::tempdir().unwrap();
+      let keychain = Arc::new(MockKeychain::default());
+      let store = store_with(&keychain, dir.path());
+
+      assert_eq!(
+         store.set("openai", "sk-test-1234").unwrap(),
+         CredentialSource::Keychain
+      );
+      assert_eq!(
+         keychain.entries.lock().unwrap().get("api_key_openai"),
+         Some(&"sk-test-1234".to_string())
+      );
+      assert!(!dir.path().join(VAULT_FILE).exists());
+      assert_eq!(
+         store.get("openai").unwrap().as_deref(),
+         Some("sk-test-1234")
+      );
+
+      let index = fs::read_to_string(dir.path().join(INDEX_FILE)).unwrap();
+      assert!(!index.contains("sk-test"));
+
+      assert!(store.delete("openai").unwrap());
+      assert!(!store.delete("openai").unwrap());
+      assert_eq!(store.get("openai").unwrap(), None);
+      assert!(keychain.entries.lock().unwrap().is_empty());
+   }
+
+   #[test]
+   fn test_legacy_tokens_are_moved_in() {
+      let dir = tempfile::tempdir().unwrap();
+      let keychain = Arc::new(MockKeychain::default());
+      let legacy = Arc::new(MockKeychain::default());
+      for (account, token) in [
+         ("ai_token_openai", "sk-legacy"),
+         ("ai_token_gemini", "AIza-legacy"),
+         ("ai_token_groq", "gsk-legacy"),
+      ] {
+         let mut entries = legacy.entries.lock().unwrap();
+         entries.insert(account.to_string(), token.to_string());
+      }
+      let store = store_with(&keychain, dir.path()).with_legacy(Box::new(legacy.clone()));
+
+      let providers = store.providers().unwrap();
+      let source_of = |id: &str| {
+         providers
+            .iter()
+            .find(|provider| provider.id == id)
+            .unwrap()
+            .source
+      };
+      assert_eq!(source_of("openai"), Some(CredentialSource::Keychain));
+      assert_eq!(source_of("google"), Some(CredentialSource::Keychain));
+      assert_eq!(source_of("anthropic"), None);
+      assert_eq!(store.get("openai").unwrap().as_deref(), Some("sk-legacy"));
+      assert_eq!(store.get("google").unwrap().as_deref(), Some("AIza-legacy"));
+      assert_eq!(
+         keychain.entries.lock().unwrap().get("api_key_google"),
+         Some(&"AIza-legacy".to_string())
+      );
+
+      // Deleting a provider whose token was never moved in removes the old token too
+      assert!(store.delete("groq").unwrap());
+      assert_eq!(store.get("groq").unwrap(), None);
+      assert!(legacy.entries.lock().unwrap().is_empty());
+   }
+
+   #[test]
+   fn test_fallback_when_keychain_is_unavailable() {
+      let dir = tempfile::tempdir().unwrap();
+      let headless = MockKeychain::unavailable();
+      let store = store_with(&headless, dir.path());
+
+      assert_eq!(
+         store.set("anthropic", "sk-ant-secret").unwrap(),
+         CredentialSource::EncryptedFile
+      );
+      store.set("groq", "gsk-secret").unwrap();
+      let vault = fs::read_to_string(dir.path().join(VAULT_FILE)).unwrap();
+      assert!(!vault.contains("secret"));
+
+      let reopened = store_with(&headless, dir.path());
+      assert_eq!(
+         reopened.get("anthropic").unwrap().as_deref(),
+         Some("sk-ant-secret")
+      );
+
+      // Once a keychain is available the key moves out of the file
+      let keychain = Arc::new(MockKeychain::default());
+      let store = store_with(&keychain, dir.path());
+      assert_eq!(
+         store.set("anthropic", "sk-ant-new").unwrap(),
+         CredentialSource::Keychain
+      );
+      assert_eq!(
+         store.get("anthropic").unwrap().as_deref(),
+         Some("sk-ant-new")
+      );
+      assert_eq!(store.get("groq").unwrap().as_deref(), Some("gsk-secret"));
+      assert!(store.delete("groq").unwrap());
+      assert!(!dir.path().join(VAULT_FILE).exists());
+   }
+
+   #[test]
+   fn test_encrypted_file_rejects_wrong_key_and_tampering() {
+      let dir = tempfile::tempdir().unwrap();
+      let path = dir.path().join(VAULT_FILE);
+      let key_path = dir.path().join(VAULT_KEY_FILE);
+      let backend = EncryptedFileBackend::new(path.clone(), key_path.clone());
+      backend.set("api_key_openai", "sk-test").unwrap();
+      assert_eq!(
+         backend.get("api_key_openai").unwrap().as_deref(),
+         Some("sk-test")
+      );
+
+      let mut vault: serde_json::Value =
+         serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+      let mut ciphertext = STANDARD
+         .decode(vault["ciphertext"].as_str().unwrap())
+         .unwrap();
+      ciphertext[0] ^= 1;
+      vault["ciphertext"] = STANDARD.encode(ciphertext).into();
+      fs::write(&path, vault.to_string()).unwrap();
+      assert!(
+         backend
+            .get("api_key_openai")
+            .unwrap_err()
+            .contains("decrypt")
+      );
+
+      fs::remove_file(&key_path).unwrap();
+      assert!(
+         backend
+            .get("api_key_openai")
+            .unwrap_err()
+            .contains("missing")
+      );
+   }
+
+   #[test]
+   fn test_list_providers() {
+      let dir = tempfile::tempdir().unwrap();
+      let keychain = Arc::new(MockKeychain::default());
+      let store = store_with(&keychain, dir.path());
+      store.set("openrouter", "sk-or").unwrap();
+      store.set("google", "AIza").unwrap();
+
+      let providers = store.providers().unwrap();
+      let listed: Vec<_> = providers
+         .iter()
+         .map(|p| (p.id.as_str(), p.builtin, p.source))
+         .collect();
+      assert_eq!(
+         listed,
+         vec![
+            ("anthropic", true, None),
+            ("openai", true, None),
+            ("google", true, Some(CredentialSource::Keychain)),
+            ("openrouter", false, Some(CredentialSource::Keychain)),
+         ]
+      );
+      assert_eq!(
+         providers[2].env_vars,
+         vec!["GEMINI_API_KEY", "GOOGLE_API_KEY"]
+      );
+   }
+
+   #[test]
+   fn test_api_key_env_var() {
+      assert_eq!(api_key_env_var("google"), "GEMINI_API_KEY");
+      assert_eq!(api_key_env_var("open-router"), "OPEN_ROUTER_API_KEY");
+   }
+
+   #[test]
+   fn test_rejects_invalid_input() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = store_with(&Arc::new(MockKeychain::default()), dir.path());
+      assert!(store.set("", "key").is_err());
+      assert!(store.set("../openai", "key").is_err());
+      assert!(store.set("OpenAI", "key").is_err());
+      assert_eq!(store.set("openai", "  ").unwrap_err(), "API key is empty");
+      assert!(!dir.path().join(INDEX_FILE).exists());
+   }
+}
@@ -1,11 +1,23 @@
 pub mod acp;
+pub mod agent_registry;
+pub mod agent_settings;
 pub mod auth;
 pub mod chat_history;
 pub mod claude;
+pub mod credentials;
+pub mod model_catalog;
+pub mod providers;
+pub mod sessions;
+pub mod token_count;
 pub mod tokens;
 
 pub use acp::*;
+pub use agent_settings::*;
 pub use auth::*;
 pub use chat_history::*;
 pub use claude::*;
+pub use credentials::*;
+pub use providers::*;
+pub use sessions::*;
+pub use token_count::*;
 pub use tokens::*;
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+
+/// Model vendors whose catalogs Athas ships
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelProvider {
+   Anthropic,
+   OpenAi,
+   Google,
+}
+
+impl ModelProvider {
+   pub const ALL: [ModelProvider; 3] = [
+      ModelProvider::Anthropic,
+      ModelProvider::OpenAi,
+      ModelProvider::Google,
+   ];
+
+   /// The provider's id as serialized, used to name its stored credentials
+   pub fn id(self) -> &'static str {
+      match self {
+         ModelProvider::Anthropic => "anthropic",
+         ModelProvider::OpenAi => "openai",
+         ModelProvider::Google => "google",
+      }
+   }
+
+   pub fn from_id(id: &str) -> Option<Self> {
+      Self::ALL.into_iter().find(|provider| provider.id() == id)
+   }
+
+   /// Environment variables the provider's API key is usually read from
+   pub fn api_key_env_vars(self) -> &'static [&'static str] {
+      match self {
+         ModelProvider::Anthropic => &["ANTHROPIC_API_KEY"],
+         ModelProvider::OpenAi => &["OPENAI_API_KEY"],
+         ModelProvider::Google => &["GEMINI_API_KEY", "GOOGLE_API_KEY"],
+      }
+   }
+}
+
+/// Built-in form of `AgentModel`
+pub(crate) struct ModelDefinition {
+   pub id: &'static str,
+   pub display_name: &'static str,
+   pub context_window: Option<u64>,
+   pub supports_reasoning: bool,
+}
+
+const ANTHROPIC_MODELS: &[ModelDefinition] = &[
+   ModelDefinition {
+      id: "claude-opus-4-1",
+      display_name: "Claude Opus 4.1",
+      context_window: Some(200_000),
+      supports_reasoning: true,
+   },
+   ModelDefinition {
+      id: "claude-sonnet-4-5",
+      display_name: "Claude Sonnet 4.5",
+      context_window: Some(200_000),
+      supports_reasoning: true,
+   },
+   ModelDefinition {
+      id: "claude-haiku-4-5",
+      display_name: "Claude Haiku 4.5",
+      context_window: Some(200_000),
+      supports_reasoning: true,
+   },
+   ModelDefinition {
+      id: "claude-sonnet-4",
+      display_name: "Claude Sonnet 4",
+      context_window: Some(200_000),
+      supports_reasoning: true,
+   },
+];
+
+const OPENAI_MODELS: &[ModelDefinition] = &[
+   ModelDefinition {
+      id: "gpt-5-codex",
+      display_name: "GPT-5 Codex",
+      context_window: Some(400_000),
+      supports_reasoning: true,
+   },
+   ModelDefinition {
+      id: "gpt-5",
+      display_name: "GPT-5",
+      context_window: Some(400_000),
+      supports_reasoning: true,
+   },
+   ModelDefinition {
+      id: "gpt-5-mini",
+      display_name: "GPT-5 mini",
+      context_window: Some(400_000),
+      supports_reasoning: true,
+   },
+   ModelDefinition {
+      id: "o3",
+      display_name: "o3",
+      context_window: Some(200_000),
+      supports_reasoning: true,
+   },
+   ModelDefinition {
+      id: "o4-mini",
+      display_name: "o4-mini",
+      context_window: Some(200_000),
+      supports_reasoning: true,
+   },
+   ModelDefinition {
+      id: "gpt-4.1",
+      display_name: "GPT-4.1",
+      context_window: Some(1_047_576),
+      supports_reasoning: false,
+   },
+];
+
+const GOOGLE_MODELS: &[ModelDefinition] = &[
+   ModelDefinition {
+      id: "gemini-2.5-pro",
+      display_name: "Gemini 2.5 Pro",
+      context_window: Some(1_048_576),
+      supports_reasoning: true,
+   },
+   ModelDefinition {
+      id: "gemini-2.5-flash",
+      display_name: "Gemini 2.5 Flash",
+      context_window: Some(1_048_576),
+      supports_reasoning: true,
+   },
+   ModelDefinition {
+      id: "gemini-2.5-flash-lite",
+      display_name: "Gemini 2.5 Flash-Lite",
+      context_window: Some(1_048_576),
+      supports_reasoning: true,
+   },
+];
+
+/// A model an agent can be configured to use
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentModel {
+   /// Value to write to the agent's model key
+   pub id: String,
+   pub display_name: String,
+   #[serde(skip_serializing_if = "Option::is_none")]
+   pub context_window: Option<u64>,
+   #[serde(default)]
+   pub supports_reasoning: bool,
+}
+
+impl From<&ModelDefinition> for AgentModel {
+   fn from(definition: &ModelDefinition) -> Self {
+      Self {
+         id: definition.id.to_string(),
+         display_name: definition.display_name.to_string(),
+         context_window: definition.context_window,
+         supports_reasoning: definition.supports_reasoning,
+      }
+   }
+}
+
+pub fn provider_models(provider: ModelProvider) -> Vec<AgentModel> {
+   let models = match provider {
+      ModelProvider::Anthropic => ANTHROPIC_MODELS,
+      ModelProvider::OpenAi => OPENAI_MODELS,
+      ModelProvider::Google => GOOGLE_MODELS,
+   };
+   models.iter().map(AgentModel::from).collect()
+}
+
+/// Append `model` unless a model with the same id is already listed
+pub fn push_unique(models: &mut Vec<AgentModel>, model: AgentModel) {
+   if !models.iter().any(|existing| existing.id == model.id) {
+      models.push(model);
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::collections::HashSet;
+
+   #[test]
+   fn test_provider_catalogs_have_unique_ids() {
+      for provider in ModelProvider::ALL {
+         let models = provider_models(provider);
+         let ids: HashSet<_> = models.iter().map(|model| model.id.as_str()).collect();
+         assert!(!models.is_empty());
+         assert_eq!(ids.len(), models.len(), "{:?}", provider);
+      }
+   }
+
+   #[test]
+   fn test_provider_ids_match_serde() {
+      for provider in ModelProvider::ALL {
+         assert_eq!(
+            serde_json::to_value(provider).unwrap(),
+            serde_json::json!(provider.id())
+         );
+         assert_eq!(ModelProvider::from_id(provider.id()), Some(provider));
+      }
+      assert_eq!(ModelProvider::from_id("groq"), None);
+   }
+}
@@ -0,0 +1,941 @@
+use super::{
+   custom::{self, CustomProvider},
+   error::{ProviderError, error_message},
+   http::{ANTHROPIC_VERSION, ApiKey},
+   models::{normalize_base_url, stored_key_allowed, v1_url},
+   network::ProviderHttpClient,
+   rate_limit::RateLimitRecorder,
+   retry::{RetryPolicy, send_with_retry},
+   sse::SseParser,
+   validate::api_base,
+};
+use crate::commands::ai::{
+   agent_settings::AgentSettingsLocks, credentials::CredentialStore, model_catalog::ModelProvider,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+   collections::HashMap,
+   sync::{Arc, Mutex, MutexGuard},
+};
+use tauri::{AppHandle, Emitter, State, WebviewWindow, async_runtime::JoinHandle, command};
+
+/// Event carrying each piece of a streamed completion
+pub const COMPLETION_CHUNK_EVENT: &str = "completion-chunk";
+
+/// Anthropic requires a limit on the response; used when the request doesn't set one
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+/// Retries of a request the provider turned away. Once the response has started it isn't retried,
+/// as the text already streamed can't be taken back.
+const COMPLETION_RETRIES: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+   System,
+   User,
+   Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+   pub role: ChatRole,
+   pub content: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionRequest {
+   /// `anthropic` for the Messages API. Anything else is sent in OpenAI's chat completions format,
+   /// its API key read from the credential stored under this id.
+   pub provider: String,
+   pub model: String,
+   pub messages: Vec<ChatMessage>,
+   pub temperature: Option<f64>,
+   pub max_tokens: Option<u32>,
+   /// Server to send the request to instead of the provider's own API, such as a local
+   /// OpenAI-compatible one. The provider's stored key is only sent along when this is the
+   /// provider's own base URL.
+   pub base_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionUsage {
+   pub input_tokens: u64,
+   pub output_tokens: u64,
+}
+
+/// Payload of `completion-chunk`. The last chunk of a request has `done` set, along with the
+/// token usage, why it failed or whether it was cancelled.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionChunk {
+   pub request_id: String,
+   pub delta: String,
+   pub done: bool,
+   pub usage: Option<CompletionUsage>,
+   pub error: Option<ProviderError>,
+   pub cancelled: bool,
+}
+
+impl CompletionChunk {
+   fn delta(request_id: &str, delta: String) -> Self {
+      Self {
+         request_id: request_id.to_string(),
+         delta,
+         done: false,
+         usage: None,
+         error: None,
+         cancelled: false,
+      }
+   }
+
+   fn last(request_id: &str, result: Result<Option<CompletionUsage>, ProviderError>) -> Self {
+      let (usage, error) = match result {
+         Ok(usage) => (usage, None),
+         Err(error) => (None, Some(error)),
+      };
+      Self {
+         done: true,
+         usage,
+         error,
+         ..Self::delta(request_id, String::new())
+      }
+   }
+
+   fn cancelled(request_id: &str) -> Self {
+      Self {
+         done: true,
+         cancelled: true,
+         ..Self::delta(request_id, String::new())
+      }
+   }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompletionApi {
+   Anthropic,
+   OpenAi,
+}
+
+/// Where a completion request goes and the key it's sent with
+#[derive(Debug, Clone, PartialEq)]
+struct Endpoint {
+   api: CompletionApi,
+   url: String,
+   api_key: Option<String>,
+   /// Header the key goes in instead of `Authorization`, for custom providers that want one
+   api_key_header: Option<String>,
+}
+
+/// The endpoint for `provider`. Its own API needs a key; a server at `base_url` may not, and
+/// doesn't get the stored one unless it is the provider's own.
+fn resolve_endpoint(
+   provider: &str,
+   base_url: Option<&str>,
+   api_key: Option<String>,
+   custom: Option<&CustomProvider>,
+) -> Result<Endpoint, ProviderError> {
+   let api_key = match base_url {
+      Some(base_url) if api_key.is_some() && !stored_key_allowed(provider, base_url, custom) => {
+         log::warn!(
+            "Not sending the key stored for {} to {}, which isn't its base URL",
+            provider,
+            base_url
+         );
+         None
+      }
+      _ => api_key,
+   };
+   if let Some(custom) = custom {
+      let custom = match base_url {
+         Some(base_url) => CustomProvider {
+            base_url: normalize_base_url(base_url)?,
+            ..custom.clone()
+         },
+         None => custom.clone(),
+      };
+      let api_key_header = api_key
+         .as_deref()
+         .and_then(|key| custom.api_key(key).header.map(str::to_string));
+      return Ok(Endpoint {
+         api: CompletionApi::OpenAi,
+         url: custom.chat_url(),
+         api_key,
+         api_key_header,
+      });
+   }
+   let known = ModelProvider::from_id(provider);
+   let api = match known {
+      Some(ModelProvider::Anthropic) => CompletionApi::Anthropic,
+      _ => CompletionApi::OpenAi,
+   };
+   let url = match (base_url, known) {
+      (Some(base_url), _) => {
+         let base_url = normalize_base_url(base_url)?;
+         match api {
+            CompletionApi::Anthropic => v1_url(&base_url, "messages"),
+            CompletionApi::OpenAi => v1_url(&base_url, "chat/completions"),
+         }
+      }
+      (None, Some(ModelProvider::Anthropic)) => {
+         format!("{}/v1/messages", api_base(ModelProvider::Anthropic))
+      }
+      (None, Some(ModelProvider::OpenAi)) => {
+         format!("{}/v1/chat/completions", api_base(ModelProvider::OpenAi))
+      }
+      // Gemini's OpenAI-compatible endpoint
+      (None, Some(ModelProvider::Google)) => format!(
+         "{}/v1beta/openai/chat/completions",
+         api_base(ModelProvider::Google)
+      ),
+      (None, None) => {
+         return Err(ProviderError::UnknownProvider {
+            provider: provider.to_string(),
+         });
+      }
+   };
+   if base_url.is_none() && api_key.is_none() {
+      return Err(ProviderError::MissingCredential {
+         provider: provider.to_string(),
+      });
+   }
+   Ok(Endpoint {
+      api,
+      url,
+      api_key,
+      api_key_header: None,
+   })
+}
+
+/// The streaming request body in the format of `api`. Anthropic takes system prompts apart from
+/// the conversation.
+fn request_body(api: CompletionApi, request: &CompletionRequest) -> Value {
+   let mut body = match api {
+      CompletionApi::Anthropic => {
+         let (system, messages): (Vec<&ChatMessage>, Vec<&ChatMessage>) = request
+            .messages
+            .iter()
+            .partition(|message| message.role == ChatRole::System);
+         let mut body = json!({
+            "model": request.model,
+            "max_tokens": request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            "messages": messages,
+            "stream": true,
+         });
+         if !system.is_empty() {
+            let system: Vec<&str> = system.iter().map(|m| m.content.as_str()).collect();
+            body["system"] = json!(system.join("\n\n"));
+         }
+         body
+      }
+      CompletionApi::OpenAi => {
+         let mut body = json!({
+            "model": request.model,
+            "messages": request.messages,
+            "stream": true,
+            "stream_options": { "include_usage": true },
+         });
+         if let Some(max_tokens) = request.max_tokens {
+            body["max_tokens"] = json!(max_tokens);
+         }
+         body
+      }
+   };
+   if let Some(temperature) = request.temperature {
+      body["temperature"] = json!(temperature);
+   }
+   body
+}
+
+fn validate_request(request: &CompletionRequest) -> Result<(), ProviderError> {
+   let invalid = |message: &str| {
+      Err(ProviderError::InvalidRequest {
+         message: message.to_string(),
+      })
+   };
+   if request.model.trim().is_empty() {
+      return invalid("no model given");
+   }
+   if !request.messages.iter().any(|m| m.role != ChatRole::System) {
+      return invalid("no messages to send");
+   }
+   Ok(())
+}
+
+/// What one event of the stream amounts to
+#[derive(Debug, PartialEq)]
+enum StreamEvent {
+   Delta(String),
+   Done,
+   Skip,
+}
+
+/// Decode the `data` of one event, recording token usage as it's reported
+fn decode_event(
+   api: CompletionApi,
+   url: &str,
+   data: &str,
+   usage: &mut Option<CompletionUsage>,
+) -> Result<StreamEvent, ProviderError> {
+   if api == CompletionApi::OpenAi && data.trim() == "[DONE]" {
+      return Ok(StreamEvent::Done);
+   }
+   let value: Value = serde_json::from_str(data).map_err(|e| ProviderError::MalformedResponse {
+      url: url.to_string(),
+      message: e.to_string(),
+   })?;
+   if value.get("error").is_some() {
+      return Err(ProviderError::Stream {
+         url: url.to_string(),
+         message: error_message(data).unwrap_or_else(|| "unknown error".to_string()),
+      });
+   }
+   let count = |value: &Value, key: &str| value.get(key).and_then(Value::as_u64);
+
+   match api {
+      CompletionApi::Anthropic => match value.get("type").and_then(Value::as_str) {
+         Some("message_start") => {
+            if let Some(reported) = value.pointer("/message/usage") {
+               let usage = usage.get_or_insert_default();
+               usage.input_tokens = count(reported, "input_tokens").unwrap_or_default();
+               usage.output_tokens = count(reported, "output_tokens").unwrap_or_default();
+            }
+            Ok(StreamEvent::Skip)
+         }
+         Some("content_block_delta") => Ok(value
+            .pointer("/delta/text")
+            .and_then(Value::as_str)
+            .map_or(StreamEvent::Skip, |text| {
+               StreamEvent::Delta(text.to_string())
+            })),
+         Some("message_delta") => {
+            if let Some(output) = value.get("usage").and_then(|u| count(u, "output_tokens")) {
+               usage.get_or_insert_default().output_tokens = output;
+            }
+            Ok(StreamEvent::Skip)
+         }
+         Some("message_stop") => Ok(StreamEvent::Done),
+         _ => Ok(StreamEvent::Skip),
+      },
+      CompletionApi::OpenAi => {
+         if let Some(reported) = value.get("usage").filter(|u| u.is_object()) {
+            *usage = Some(CompletionUsage {
+               input_tokens: count(reported, "prompt_tokens").unwrap_or_default(),
+               output_tokens: count(reported, "completion_tokens").unwrap_or_default(),
+            });
+         }
+         Ok(value
+            .pointer("/choices/0/delta/content")
+            .and_then(Value::as_str)
+            .filter(|text| !text.is_empty())
+            .map_or(StreamEvent::Skip, |text| {
+               StreamEvent::Delta(text.to_string())
+            }))
+      }
+   }
+}
+
+/// Send the request and pass each piece of text to `on_delta` as it arrives, returning the token
+/// usage once the provider marks the response complete. Only getting the response started is
+/// retried.
+async fn stream_completion(
+   client: &reqwest::Client,
+   endpoint: &Endpoint,
+   body: &Value,
+   retry: &RetryPolicy,
+   rate_limits: Option<&RateLimitRecorder>,
+   mut on_delta: impl FnMut(String),
+) -> Result<Option<CompletionUsage>, ProviderError> {
+   let url = endpoint.url.as_str();
+   let body = body.to_string();
+   let mut response = send_with_retry(retry, rate_limits, url, || {
+      let request = client
+         .post(url)
+         .header(reqwest::header::CONTENT_TYPE, "application/json")
+         .body(body.clone());
+      match (endpoint.api, &endpoint.api_key) {
+         (CompletionApi::Anthropic, Some(key)) => request
+            .header("x-api-key", key)
+            .header("anthropic-version", ANTHROPIC_VERSION),
+         (CompletionApi::Anthropic, None) => request.header("anthropic-version", ANTHROPIC_VERSION),
+         (CompletionApi::OpenAi, Some(key)) => ApiKey {
+            key,
+            header: endpoint.api_key_header.as_deref(),
+         }
+         .apply(request),
+         (CompletionApi::OpenAi, None) => request,
+      }
+   })
+   .await?;
+   let status = response.status();
+   if !status.is_success() {
+      let body = response
+         .text()
+         .await
+         .map_err(|e| ProviderError::request(url, e))?;
+      return Err(ProviderError::status(url, status, &body));
+   }
+
+   let mut parser = SseParser::default();
+   let mut usage = None;
+   loop {
+      let chunk = response
+         .chunk()
+         .await
+         .map_err(|e| ProviderError::request(url, e))?;
+      let events = match &chunk {
+         Some(bytes) => parser.push(bytes),
+         None => parser.finish(),
+      };
+      for event in events {
+         match decode_event(endpoint.api, url, &event.data, &mut usage)? {
+            StreamEvent::Delta(text) => on_delta(text),
+            StreamEvent::Done => return Ok(usage),
+            StreamEvent::Skip => {}
+         }
+      }
+      if chunk.is_none() {
+         return Err(ProviderError::Stream {
+            url: url.to_string(),
+            message: "the response ended before it was complete".to_string(),
+         });
+      }
+   }
+}
+
+struct InFlight {
+   /// Label of the window that started the request
+   window: String,
+   task: JoinHandle<()>,
+}
+
+/// Completions being streamed, by request id
+#[derive(Clone, Default)]
+pub struct CompletionRequests {
+   in_flight: Arc<Mutex<HashMap<String, InFlight>>>,
+}
+
+impl CompletionRequests {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   fn lock(&self) -> MutexGuard<'_, HashMap<String, InFlight>> {
+      self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+   }
+
+   /// Stream a completion in the background, passing every chunk to `emit`. Only one of the
+   /// request finishing and it being cancelled sends the last chunk, and no chunk follows it.
+   #[allow(clippy::too_many_arguments)]
+   fn start(
+      &self,
+      window: &str,
+      client: reqwest::Client,
+      endpoint: Endpoint,
+      body: Value,
+      retry: RetryPolicy,
+      rate_limits: Option<RateLimitRecorder>,
+      emit: impl Fn(CompletionChunk) + Send + Sync + 'static,
+   ) -> String {
+      let request_id = uuid::Uuid::new_v4().to_string();
+      let requests = self.clone();
+      let id = request_id.clone();
+
+      // Held until the request is registered, so its chunks can't be checked for before that
+      let mut in_flight = self.lock();
+      let task = tauri::async_runtime::spawn(async move {
+         let result = stream_completion(
+            &client,
+            &endpoint,
+            &body,
+            &retry,
+            rate_limits.as_ref(),
+            |delta| {
+               if requests.lock().contains_key(&id) {
+                  emit(CompletionChunk::delta(&id, delta));
+               }
+            },
+         )
+         .await;
+         if let Err(e) = &result {
+            log::warn!("Completion {} failed: {}", id, e);
+         }
+         if requests.lock().remove(&id).is_some() {
+            emit(CompletionChunk::last(&id, result));
+         }
+      });
+      in_flight.insert(
+         request_id.clone(),
+         InFlight {
+            window: window.to_string(),
+            task,
+         },
+      );
+      request_id
+   }
+
+   /// Abort a request, closing its HTTP stream. False when it wasn't in flight.
+   pub fn cancel(&self, request_id: &str) -> bool {
+      let Some(request) = self.lock().remove(request_id) else {
+         return false;
+      };
+      request.task.abort();
+      true
+   }
+
+   /// Abort every request started from the window labelled `window`, returning how many there
+   /// were
+   pub fn cancel_window(&self, window: &str) -> usize {
+      let mut in_flight = self.lock();
+      let ids: Vec<String> = in_flight
+         .iter()
+         .filter(|(_, request)| request.window == window)
+         .map(|(id, _)| id.clone())
+         .collect();
+      for id in &ids {
+         if let Some(request) = in_flight.remove(id) {
+            request.task.abort();
+         }
+      }
+      ids.len()
+   }
+}
+
+/// Ask a model for a short answer and wait for all of it, for Athas' own use of a model rather
+/// than a chat the user sees. Sent like `start_completion`, but nothing is emitted.
+pub async fn complete(
+   http: &ProviderHttpClient,
+   credentials: &CredentialStore,
+   locks: &AgentSettingsLocks,
+   request: &CompletionRequest,
+) -> Result<String, ProviderError> {
+   validate_request(request)?;
+   let api_key = credentials
+      .get(&request.provider)
+      .map_err(|message| ProviderError::Credentials { message })?;
+   let custom = custom::find(locks, &request.provider).await?;
+   let endpoint = resolve_endpoint(
+      &request.provider,
+      request.base_url.as_deref(),
+      api_key,
+      custom.as_ref(),
+   )?;
+   let body = request_body(endpoint.api, request);
+   let mut text = String::new();
+   stream_completion(
+      &http.client(),
+      &endpoint,
+      &body,
+      &http.retry_policy(COMPLETION_RETRIES),
+      Some(&http.rate_limits().recorder(&request.provider)),
+      |delta| text.push_str(&delta),
+   )
+   .await?;
+   Ok(text)
+}
+
+/// Ask a model directly, streaming its answer as `completion-chunk` events. Returns the request
+/// id the events carry as soon as the request is under way; failures after that arrive as the
+/// last event. Requests are aborted when the window that started them closes. Custom providers
+/// are called at their chat endpoint.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_completion(
+   app: AppHandle,
+   window: WebviewWindow,
+   http: State<'_, ProviderHttpClient>,
+   requests: State<'_, CompletionRequests>,
+   credentials: State<'_, CredentialStore>,
+   locks: State<'_, AgentSettingsLocks>,
+   request: CompletionRequest,
+) -> Result<String, ProviderError> {
+   validate_request(&request)?;
+   let api_key = credentials
+      .get(&request.provider)
+      .map_err(|message| ProviderError::Credentials { message })?;
+   let custom = custom::find(&locks, &request.provider).await?;
+   let endpoint = resolve_endpoint(
+      &request.provider,
+      request.base_url.as_deref(),
+      api_key,
+      custom.as_ref(),
+   )?;
+   let body = request_body(endpoint.api, &request);
+
+   let url = endpoint.url.clone();
+   let request_id = requests.start(
+      window.label(),
+      http.client(),
+      endpoint,
+      body,
+      http.retry_policy(COMPLETION_RETRIES),
+      Some(http.rate_limits().recorder(&request.provider)),
+      move |chunk| {
+         let _ = app.emit(COMPLETION_CHUNK_EVENT, chunk);
+      },
+   );
+   log::info!(
+      "Started completion {} with {} at {}",
+      request_id,
+      request.model,
+      url
+   );
+   Ok(request_id)
+}
+
+/// Stop a streaming completion. Its last `completion-chunk` event has `cancelled` set. Returns
+/// false when the request had already finished.
+#[command]
+pub fn cancel_completion(
+   app: AppHandle,
+   requests: State<'_, CompletionRequests>,
+   request_id: String,
+) -> bool {
+   if !requests.cancel(&request_id) {
+      return false;
+   }
+   let _ = app.emit(
+      COMPLETION_CHUNK_EVENT,
+      CompletionChunk::cancelled(&request_id),
+   );
+   log::info!("Cancelled completion {}", request_id);
+   true
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::providers::{http::serve_once, network::test_client};
+
+   fn request(provider: &str, messages: Vec<ChatMessage>) -> CompletionRequest {
+      CompletionRequest {
+         provider: provider.to_string(),
+         model: "test-model".to_string(),
+         messages,
+         temperature: Some(0.2),
+         max_tokens: None,
+         base_url: None,
+      }
+   }
+
+   fn message(role: ChatRole, content: &str) -> ChatMessage {
+      ChatMessage {
+         role,
+         content: content.to_string(),
+      }
+   }
+
+   #[test]
+   fn test_resolve_endpoint() {
+      let endpoint = resolve_endpoint("anthropic", None, Some("sk-ant".into()), None).unwrap();
+      assert_eq!(endpoint.api, CompletionApi::Anthropic);
+      assert_eq!(endpoint.url, "https://api.anthropic.com/v1/messages");
+
+      let endpoint =
+         resolve_endpoint("vllm", Some("http://localhost:8000/v1/"), None, None).unwrap();
+      assert_eq!(endpoint.api, CompletionApi::OpenAi);
+      assert_eq!(endpoint.url, "http://localhost:8000/v1/chat/completions");
+
+      assert!(matches!(
+         resolve_endpoint("openai", None, None, None),
+         Err(ProviderError::MissingCredential { .. })
+      ));
+      assert!(matches!(
+         resolve_endpoint("vllm", None, None, None),
+         Err(ProviderError::UnknownProvider { .. })
+      ));
+
+      let gateway = CustomProvider {
+         id: "gateway".into(),
+         display_name: "Gateway".into(),
+         base_url: "https://llm.internal.example.com".into(),
+         api_key_header: Some("api-key".into()),
+         models_endpoint: None,
+         chat_endpoint: Some("/openai/chat".into()),
+      };
+      let endpoint = resolve_endpoint("gateway", None, Some("gw".into()), Some(&gateway)).unwrap();
+      assert_eq!(endpoint.api, CompletionApi::OpenAi);
+      assert_eq!(endpoint.url, "https://llm.internal.example.com/openai/chat");
+      assert_eq!(endpoint.api_key_header.as_deref(), Some("api-key"));
+      let endpoint = resolve_endpoint("gateway", None, None, Some(&gateway)).unwrap();
+      assert_eq!(endpoint.api_key, None);
+   }
+
+   #[test]
+   fn test_stored_key_stays_with_its_base_url() {
+      let endpoint = resolve_endpoint(
+         "anthropic",
+         Some("https://attacker.example"),
+         Some("sk-ant".into()),
+         None,
+      )
+      .unwrap();
+      assert_eq!(endpoint.url, "https://attacker.example/v1/messages");
+      assert_eq!(endpoint.api_key, None);
+      let endpoint = resolve_endpoint(
+         "anthropic",
+         Some("https://api.anthropic.com/"),
+         Some("sk-ant".into()),
+         None,
+      )
+      .unwrap();
+      assert_eq!(endpoint.api_key.as_deref(), Some("sk-ant"));
+
+      let gateway = CustomProvider {
+         id: "gateway".into(),
+         display_name: "Gateway".into(),
+         base_url: "https://llm.internal.example.com".into(),
+         api_key_header: None,
+         models_endpoint: None,
+         chat_endpoint: None,
+      };
+      let endpoint = resolve_endpoint(
+         "gateway",
+         Some("https://attacker.example"),
+         Some("gw".into()),
+         Some(&gateway),
+      )
+      .unwrap();
+      assert_eq!(endpoint.api_key, None);
+      assert_eq!(endpoint.api_key_header, None);
+   }
+
+   #[test]
+   fn test_request_bodies() {
+      let request = request(
+         "anthropic",
+         vec![
+            message(ChatRole::System, "Be brief."),
+            message(ChatRole::User, "Hi"),
+         ],
+      );
+      assert_eq!(
+         request_body(CompletionApi::Anthropic, &request),
+         json!({
+            "model": "test-model",
+            "max_tokens": 4096,
+            "system": "Be brief.",
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "temperature": 0.2,
+            "stream": true,
+         })
+      );
+      let body = request_body(CompletionApi::OpenAi, &request);
+      assert_eq!(
+         body["messages"][0],
+         json!({ "role": "system", "content": "Be brief." })
+      );
+      assert_eq!(body["stream_options"]["include_usage"], true);
+      assert!(body.get("max_tokens").is_none());
+
+      let only_system = CompletionRequest {
+         messages: vec![message(ChatRole::System, "Be brief.")],
+         ..request
+      };
+      assert!(matches!(
+         validate_request(&only_system),
+         Err(ProviderError::InvalidRequest { .. })
+      ));
+   }
+
+   /// A `text/event-stream` body of `events`, given as their lines
+   fn sse(events: &[&str]) -> String {
+      events
+         .iter()
+         .map(|event| format!("{}\n\n", event))
+         .collect()
+   }
+
+   async fn stream(
+      api: CompletionApi,
+      body: &str,
+   ) -> (Vec<String>, Result<Option<CompletionUsage>, ProviderError>) {
+      let (base_url, _) = serve_once("200 OK", body).await;
+      let endpoint = Endpoint {
+         api,
+         url: format!("{}/v1/stream", base_url),
+         api_key: Some("sk-test".into()),
+         api_key_header: None,
+      };
+      let mut deltas = Vec::new();
+      let result = stream_completion(
+         &test_client(),
+         &endpoint,
+         &json!({}),
+         &RetryPolicy::NONE,
+         None,
+         |delta| deltas.push(delta),
+      )
+      .await;
+      (deltas, result)
+   }
+
+   #[tokio::test]
+   async fn test_stream_anthropic() {
+      let body = sse(&[
+         r#"event: message_start
+data: {"type":"message_start","message":{"usage":{"input_tokens":12,"output_tokens":1}}}"#,
+         r#"event: ping
+data: {"type":"ping"}"#,
+         r#"event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#,
+         r#"event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"lo"}}"#,
+         r#"event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":5}}"#,
+         r#"event: message_stop
+data: {"type":"message_stop"}"#,
+      ]);
+      let (deltas, result) = stream(CompletionApi::Anthropic, &body).await;
+      assert_eq!(deltas, vec!["Hel", "lo"]);
+      assert_eq!(
+         result.unwrap(),
+         Some(CompletionUsage {
+            input_tokens: 12,
+            output_tokens: 5,
+         })
+      );
+
+      let body = sse(&[
+         r#"event: content_block_delta
+data: {"type":"content_block_delta","delta":{"type":"text_delta","text":"Hi"}}"#,
+         r#"event: error
+data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+      ]);
+      let (deltas, result) = stream(CompletionApi::Anthropic, &body).await;
+      assert_eq!(deltas, vec!["Hi"]);
+      assert!(
+         matches!(result, Err(ProviderError::Stream { message, .. }) if message == "Overloaded")
+      );
+   }
+
+   #[tokio::test]
+   async fn test_stream_openai() {
+      let body = sse(&[
+         r#"data: {"choices":[{"delta":{"role":"assistant","content":""}}]}"#,
+         r#"data: {"choices":[{"delta":{"content":"4"}}]}"#,
+         r#"data: {"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":1}}"#,
+         "data: [DONE]",
+      ]);
+      let (deltas, result) = stream(CompletionApi::OpenAi, &body).await;
+      assert_eq!(deltas, vec!["4"]);
+      assert_eq!(
+         result.unwrap(),
+         Some(CompletionUsage {
+            input_tokens: 9,
+            output_tokens: 1,
+         })
+      );
+
+      // Cut off before `[DONE]`
+      let body = sse(&[r#"data: {"choices":[{"delta":{"content":"4"}}]}"#]);
+      let (deltas, result) = stream(CompletionApi::OpenAi, &body).await;
+      assert_eq!(deltas, vec!["4"]);
+      assert!(matches!(result, Err(ProviderError::Stream { .. })));
+   }
+
+   #[tokio::test]
+   async fn test_failure_is_the_last_chunk() {
+      let (base_url, _) = serve_once("401 Unauthorized", "{}").await;
+      let endpoint = Endpoint {
+         api: CompletionApi::OpenAi,
+         url: format!("{}/v1/chat/completions", base_url),
+         api_key: None,
+         api_key_header: None,
+      };
+      let requests = CompletionRequests::new();
+      let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+      let id = requests.start(
+         "main",
+         test_client(),
+         endpoint,
+         json!({}),
+         RetryPolicy::NONE,
+         None,
+         move |chunk| {
+            let _ = sender.send(chunk);
+         },
+      );
+
+      let chunk = receiver.recv().await.unwrap();
+      assert_eq!(chunk.request_id, id);
+      assert!(chunk.done && !chunk.cancelled);
+      assert!(matches!(
+         chunk.error,
+         Some(ProviderError::Unauthorized { status: 401, .. })
+      ));
+      assert!(receiver.recv().await.is_none());
+      assert!(!requests.cancel(&id));
+   }
+
+   #[tokio::test]
+   async fn test_cancel() {
+      // A server that accepts the request and never answers
+      let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let url = format!(
+         "http://{}/v1/chat/completions",
+         listener.local_addr().unwrap()
+      );
+      let _server = tokio::spawn(async move {
+         let _connection = listener.accept().await;
+         std::future::pending::<()>().await;
+      });
+      let endpoint = Endpoint {
+         api: CompletionApi::OpenAi,
+         url,
+         api_key: None,
+         api_key_header: None,
+      };
+
+      let requests = CompletionRequests::new();
+      let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+      let first = requests.start(
+         "main",
+         test_client(),
+         endpoint.clone(),
+         json!({}),
+         RetryPolicy::NONE,
+         None,
+         {
+            let sender = sender.clone();
+            move |chunk| {
+               let _ = sender.send(chunk);
+            }
+         },
+      );
+      requests.start(
+         "remote-1",
+         test_client(),
+         endpoint.clone(),
+         json!({}),
+         RetryPolicy::NONE,
+         None,
+         {
+            let sender = sender.clone();
+            move |chunk| {
+               let _ = sender.send(chunk);
+            }
+         },
+      );
+      requests.start(
+         "remote-1",
+         test_client(),
+         endpoint,
+         json!({}),
+         RetryPolicy::NONE,
+         None,
+         move |chunk| {
+            let _ = sender.send(chunk);
+         },
+      );
+
+      assert!(requests.cancel(&first));
+      assert!(!requests.cancel(&first));
+      assert_eq!(requests.cancel_window("remote-1"), 2);
+      assert!(requests.lock().is_empty());
+      // Aborted tasks drop their senders without sending a last chunk
+      assert!(receiver.recv().await.is_none());
+   }
+}
@@ -0,0 +1,188 @@
+use super::{
+   error::ProviderError,
+   http::ApiKey,
+   models::{normalize_base_url, v1_url},
+};
+use crate::commands::ai::{
+   agent_settings::{AgentSettingsLocks, find_custom_provider},
+   model_catalog::ModelProvider,
+};
+use reqwest::header::{AUTHORIZATION, HeaderName};
+use serde::{Deserialize, Serialize};
+
+/// Whether `id` names a built-in provider, counting Ollama, which custom ones can't shadow
+pub fn is_built_in_provider(id: &str) -> bool {
+   ModelProvider::from_id(id).is_some() || id == "ollama"
+}
+
+/// An OpenAI-compatible provider the user has registered, such as OpenRouter, Groq or an internal
+/// gateway. Its API key is stored under its id like a built-in provider's.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomProvider {
+   pub id: String,
+   pub display_name: String,
+   pub base_url: String,
+   /// Header the API key is sent in. `Authorization`, the default, sends it as a bearer token;
+   /// any other header sends it as it is.
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub api_key_header: Option<String>,
+   /// Path of the model list under the base URL, `/v1/models` by default
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub models_endpoint: Option<String>,
+   /// Path of chat completions under the base URL, `/v1/chat/completions` by default
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub chat_endpoint: Option<String>,
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+   value
+      .as_deref()
+      .map(str::trim)
+      .filter(|value| !value.is_empty())
+      .map(str::to_string)
+}
+
+impl CustomProvider {
+   /// The definition with whitespace trimmed, blank settings unset and the base URL normalized.
+   /// Fails when the base URL or the header can't be used.
+   pub fn normalized(&self) -> Result<Self, ProviderError> {
+      let api_key_header = non_empty(&self.api_key_header);
+      if let Some(header) = &api_key_header {
+         HeaderName::from_bytes(header.as_bytes()).map_err(|e| ProviderError::InvalidRequest {
+            message: format!("'{}' is not a valid header name: {}", header, e),
+         })?;
+      }
+      Ok(Self {
+         id: self.id.trim().to_string(),
+         display_name: self.display_name.trim().to_string(),
+         base_url: normalize_base_url(&self.base_url)?,
+         api_key_header,
+         models_endpoint: non_empty(&self.models_endpoint),
+         chat_endpoint: non_empty(&self.chat_endpoint),
+      })
+   }
+
+   fn url(&self, endpoint: &Option<String>, default_path: &str) -> String {
+      match endpoint {
+         Some(path) => format!(
+            "{}/{}",
+            self.base_url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+         ),
+         None => v1_url(&self.base_url, default_path),
+      }
+   }
+
+   pub fn models_url(&self) -> String {
+      self.url(&self.models_endpoint, "models")
+   }
+
+   pub fn chat_url(&self) -> String {
+      self.url(&self.chat_endpoint, "chat/completions")
+   }
+
+   pub fn embeddings_url(&self) -> String {
+      v1_url(&self.base_url, "embeddings")
+   }
+
+   /// `key` as this provider expects it
+   pub(super) fn api_key<'a>(&'a self, key: &'a str) -> ApiKey<'a> {
+      let header = self
+         .api_key_header
+         .as_deref()
+         .filter(|header| !header.eq_ignore_ascii_case(AUTHORIZATION.as_str()));
+      ApiKey { key, header }
+   }
+}
+
+/// The custom provider registered as `id`. Built-in ids never name one.
+pub(super) async fn find(
+   locks: &AgentSettingsLocks,
+   id: &str,
+) -> Result<Option<CustomProvider>, ProviderError> {
+   if is_built_in_provider(id) {
+      return Ok(None);
+   }
+   find_custom_provider(locks, id)
+      .await
+      .map_err(|e| ProviderError::Settings {
+         message: e.to_string(),
+      })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn provider(base_url: &str) -> CustomProvider {
+      CustomProvider {
+         id: "gateway".into(),
+         display_name: "Gateway".into(),
+         base_url: base_url.into(),
+         api_key_header: None,
+         models_endpoint: None,
+         chat_endpoint: None,
+      }
+   }
+
+   #[test]
+   fn test_urls() {
+      let openrouter = provider("https://openrouter.ai/api/v1/")
+         .normalized()
+         .unwrap();
+      assert_eq!(openrouter.base_url, "https://openrouter.ai/api/v1");
+      assert_eq!(
+         openrouter.models_url(),
+         "https://openrouter.ai/api/v1/models"
+      );
+      assert_eq!(
+         openrouter.chat_url(),
+         "https://openrouter.ai/api/v1/chat/completions"
+      );
+      assert_eq!(
+         openrouter.embeddings_url(),
+         "https://openrouter.ai/api/v1/embeddings"
+      );
+
+      let gateway = CustomProvider {
+         api_key_header: Some(" api-key ".into()),
+         models_endpoint: Some("/openai/models".into()),
+         chat_endpoint: Some(" ".into()),
+         ..provider("https://llm.internal.example.com")
+      }
+      .normalized()
+      .unwrap();
+      assert_eq!(
+         gateway.models_url(),
+         "https://llm.internal.example.com/openai/models"
+      );
+      assert_eq!(
+         gateway.chat_url(),
+         "https://llm.internal.example.com/v1/chat/completions"
+      );
+      assert_eq!(gateway.api_key("k").header, Some("api-key"));
+
+      let bearer = CustomProvider {
+         api_key_header: Some("Authorization".into()),
+         ..provider("https://api.groq.com/openai")
+      };
+      assert_eq!(bearer.api_key("k"), ApiKey::bearer("k"));
+   }
+
+   #[test]
+   fn test_invalid_definitions() {
+      assert!(matches!(
+         provider("openrouter.ai").normalized(),
+         Err(ProviderError::InvalidUrl { .. })
+      ));
+      let bad_header = CustomProvider {
+         api_key_header: Some("api key".into()),
+         ..provider("https://openrouter.ai/api/v1")
+      };
+      assert!(matches!(
+         bad_header.normalized(),
+         Err(ProviderError::InvalidRequest { .. })
+      ));
+   }
+}
@@ -0,0 +1,504 @@
+use super::{
+   custom::{self, CustomProvider},
+   embeddings_cache::EmbeddingsCache,
+   error::ProviderError,
+   http::{ApiKey, post_json},
+   network::ProviderHttpClient,
+   ollama::ollama_base_url,
+   rate_limit::RateLimitRecorder,
+   retry::RetryPolicy,
+   validate::api_base,
+};
+use crate::commands::ai::{
+   agent_settings::AgentSettingsLocks, credentials::CredentialStore, model_catalog::ModelProvider,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+use tauri::{State, command};
+
+const EMBEDDINGS_TIMEOUT: Duration = Duration::from_secs(60);
+/// Embedding the same input twice gives the same vector, so failures are retried freely
+const EMBEDDINGS_RETRIES: u32 = 3;
+/// Inputs per request. OpenAI takes up to 2048, but smaller batches fail and retry cheaper.
+const MAX_BATCH_INPUTS: usize = 256;
+/// Characters per request, about 75k tokens, well under OpenAI's 300k-token request limit
+const MAX_BATCH_CHARS: usize = 300_000;
+
+/// Tokens the provider counted for the inputs it embedded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingUsage {
+   #[serde(default, alias = "prompt_tokens")]
+   pub prompt_tokens: u64,
+   #[serde(default, alias = "total_tokens")]
+   pub total_tokens: u64,
+}
+
+impl EmbeddingUsage {
+   fn add(total: &mut Option<Self>, usage: Option<Self>) {
+      if let Some(usage) = usage {
+         let total = total.get_or_insert_default();
+         total.prompt_tokens += usage.prompt_tokens;
+         total.total_tokens += usage.total_tokens;
+      }
+   }
+}
+
+/// One vector per input, in input order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Embeddings {
+   pub vectors: Vec<Vec<f32>>,
+   /// Summed over the requests made. `None` when none were, or the provider doesn't report
+   /// usage, as Ollama doesn't.
+   pub usage: Option<EmbeddingUsage>,
+   /// How many vectors came from the cache rather than the provider
+   pub cached: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmbeddingsApi {
+   /// `POST /v1/embeddings`, which takes a batch of inputs
+   OpenAi,
+   /// `POST /api/embeddings`, which takes one
+   Ollama,
+}
+
+/// Where embedding requests go and the key they're sent with
+#[derive(Debug, Clone, PartialEq)]
+struct EmbeddingsEndpoint {
+   api: EmbeddingsApi,
+   url: String,
+   api_key: Option<String>,
+   api_key_header: Option<String>,
+}
+
+impl EmbeddingsEndpoint {
+   fn api_key(&self) -> Option<ApiKey<'_>> {
+      let key = self.api_key.as_deref()?;
+      Some(ApiKey {
+         key,
+         header: self.api_key_header.as_deref(),
+      })
+   }
+}
+
+/// The embeddings endpoint of `provider`. Ollama runs on this machine and needs no key; the
+/// other built-in providers do. Anthropic has no embeddings API.
+fn resolve_endpoint(
+   provider: &str,
+   api_key: Option<String>,
+   custom: Option<&CustomProvider>,
+) -> Result<EmbeddingsEndpoint, ProviderError> {
+   if let Some(custom) = custom {
+      let api_key_header = api_key
+         .as_deref()
+         .and_then(|key| custom.api_key(key).header.map(str::to_string));
+      return Ok(EmbeddingsEndpoint {
+         api: EmbeddingsApi::OpenAi,
+         url: custom.embeddings_url(),
+         api_key,
+         api_key_header,
+      });
+   }
+   if provider == "ollama" {
+      return Ok(EmbeddingsEndpoint {
+         api: EmbeddingsApi::Ollama,
+         url: format!("{}/api/embeddings", ollama_base_url(None)),
+         api_key: None,
+         api_key_header: None,
+      });
+   }
+   let url = match ModelProvider::from_id(provider) {
+      Some(ModelProvider::OpenAi) => {
+         format!("{}/v1/embeddings", api_base(ModelProvider::OpenAi))
+      }
+      // Gemini's OpenAI-compatible endpoint
+      Some(ModelProvider::Google) => format!(
+         "{}/v1beta/openai/embeddings",
+         api_base(ModelProvider::Google)
+      ),
+      Some(ModelProvider::Anthropic) => {
+         return Err(ProviderError::InvalidRequest {
+            message: "Anthropic has no embeddings API".to_string(),
+         });
+      }
+      None => {
+         return Err(ProviderError::UnknownProvider {
+            provider: provider.to_string(),
+         });
+      }
+   };
+   if api_key.is_none() {
+      return Err(ProviderError::MissingCredential {
+         provider: provider.to_string(),
+      });
+   }
+   Ok(EmbeddingsEndpoint {
+      api: EmbeddingsApi::OpenAi,
+      url,
+      api_key,
+      api_key_header: None,
+   })
+}
+
+/// `indices` of `inputs` split into batches within the per-request input and size limits. An
+/// input larger than the size limit gets a batch of its own, for the provider to accept or not.
+fn batches(inputs: &[String], indices: &[usize]) -> Vec<Vec<usize>> {
+   let mut batches: Vec<Vec<usize>> = Vec::new();
+   let mut chars = 0;
+   for &index in indices {
+      let len = inputs[index].len();
+      match batches.last_mut() {
+         Some(batch) if batch.len() < MAX_BATCH_INPUTS && chars + len <= MAX_BATCH_CHARS => {
+            batch.push(index);
+            chars += len;
+         }
+         _ => {
+            batches.push(vec![index]);
+            chars = len;
+         }
+      }
+   }
+   batches
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbeddings {
+   data: Vec<OpenAiEmbedding>,
+   usage: Option<EmbeddingUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbedding {
+   index: usize,
+   embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbedding {
+   embedding: Vec<f32>,
+}
+
+fn malformed(url: &str, message: impl Into<String>) -> ProviderError {
+   ProviderError::MalformedResponse {
+      url: url.to_string(),
+      message: message.into(),
+   }
+}
+
+/// Embed one batch, returning a vector per input in input order
+async fn embed_batch(
+   client: &reqwest::Client,
+   endpoint: &EmbeddingsEndpoint,
+   model: &str,
+   inputs: &[&str],
+   retry: &RetryPolicy,
+   rate_limits: Option<&RateLimitRecorder>,
+) -> Result<(Vec<Vec<f32>>, Option<EmbeddingUsage>), ProviderError> {
+   let url = endpoint.url.as_str();
+   match endpoint.api {
+      EmbeddingsApi::OpenAi => {
+         let body = json!({ "model": model, "input": inputs });
+         let mut response: OpenAiEmbeddings = post_json(
+            client,
+            url,
+            endpoint.api_key(),
+            &body,
+            EMBEDDINGS_TIMEOUT,
+            retry,
+            rate_limits,
+         )
+         .await?;
+         if response.data.len() != inputs.len() {
+            return Err(malformed(
+               url,
+               format!(
+                  "{} embeddings returned for {} inputs",
+                  response.data.len(),
+                  inputs.len()
+               ),
+            ));
+         }
+         response.data.sort_by_key(|embedding| embedding.index);
+         if response
+            .data
+            .iter()
+            .enumerate()
+            .any(|(position, embedding)| embedding.index != position)
+         {
+            return Err(malformed(url, "embedding indices don't match the inputs"));
+         }
+         let vectors = response
+            .data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect();
+         Ok((vectors, response.usage))
+      }
+      EmbeddingsApi::Ollama => {
+         let mut vectors = Vec::with_capacity(inputs.len());
+         for input in inputs {
+            let body = json!({ "model": model, "prompt": input });
+            let response: OllamaEmbedding = post_json(
+               client,
+               url,
+               None,
+               &body,
+               EMBEDDINGS_TIMEOUT,
+               retry,
+               rate_limits,
+            )
+            .await?;
+            vectors.push(response.embedding);
+         }
+         Ok((vectors, None))
+      }
+   }
+}
+
+/// Embed `inputs` with `model` for semantic search over the workspace. OpenAI, Gemini, custom
+/// providers and Ollama are supported. Inputs are sent in batches the provider accepts, and each
+/// vector is cached on disk by model and content, so unchanged files aren't embedded again.
+#[command]
+pub async fn generate_embeddings(
+   http: State<'_, ProviderHttpClient>,
+   credentials: State<'_, CredentialStore>,
+   locks: State<'_, AgentSettingsLocks>,
+   cache: State<'_, EmbeddingsCache>,
+   provider: String,
+   model: String,
+   inputs: Vec<String>,
+) -> Result<Embeddings, ProviderError> {
+   let model = model.trim().to_string();
+   if model.is_empty() {
+      return Err(ProviderError::InvalidRequest {
+         message: "model is empty".to_string(),
+      });
+   }
+   let api_key = credentials
+      .get(&provider)
+      .map_err(|message| ProviderError::Credentials { message })?;
+   let custom = custom::find(&locks, &provider).await?;
+   let endpoint = resolve_endpoint(&provider, api_key, custom.as_ref())?;
+
+   let keys: Vec<String> = inputs
+      .iter()
+      .map(|input| EmbeddingsCache::key(&provider, &model, input))
+      .collect();
+   let lookup = (cache.inner().clone(), keys.clone());
+   let mut vectors = tokio::task::spawn_blocking(move || {
+      let (cache, keys) = lookup;
+      keys.iter().map(|key| cache.get(key)).collect::<Vec<_>>()
+   })
+   .await
+   .unwrap_or_else(|e| {
+      log::warn!("Failed to read the embeddings cache: {}", e);
+      vec![None; inputs.len()]
+   });
+   let missing: Vec<usize> = (0..inputs.len())
+      .filter(|&index| vectors[index].is_none())
+      .collect();
+   let cached = inputs.len() - missing.len();
+
+   let client = http.client();
+   let retry = http.retry_policy(EMBEDDINGS_RETRIES);
+   let rate_limits = http.rate_limits().recorder(&provider);
+   let mut usage = None;
+   let mut embedded = Vec::with_capacity(missing.len());
+   for batch in batches(&inputs, &missing) {
+      let batch_inputs: Vec<&str> = batch.iter().map(|&index| inputs[index].as_str()).collect();
+      let (batch_vectors, batch_usage) = embed_batch(
+         &client,
+         &endpoint,
+         &model,
+         &batch_inputs,
+         &retry,
+         Some(&rate_limits),
+      )
+      .await?;
+      EmbeddingUsage::add(&mut usage, batch_usage);
+      for (index, vector) in batch.into_iter().zip(batch_vectors) {
+         if vector.is_empty() {
+            return Err(malformed(
+               &endpoint.url,
+               format!("no embedding returned; is {} an embedding model?", model),
+            ));
+         }
+         embedded.push((keys[index].clone(), vector.clone()));
+         vectors[index] = Some(vector);
+      }
+   }
+
+   if !embedded.is_empty() {
+      let cache = cache.inner().clone();
+      if let Err(e) = tokio::task::spawn_blocking(move || cache.insert_all(&embedded)).await {
+         log::warn!("Failed to write the embeddings cache: {}", e);
+      }
+   }
+   log::info!(
+      "Embedded {} inputs with {} ({} cached)",
+      inputs.len(),
+      model,
+      cached
+   );
+   Ok(Embeddings {
+      vectors: vectors.into_iter().flatten().collect(),
+      usage,
+      cached,
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::providers::{
+      http::serve_once, models::normalize_base_url, network::test_client,
+   };
+
+   fn endpoint(api: EmbeddingsApi, base_url: &str, path: &str) -> EmbeddingsEndpoint {
+      EmbeddingsEndpoint {
+         api,
+         url: format!("{}{}", base_url, path),
+         api_key: Some("sk-test".into()),
+         api_key_header: None,
+      }
+   }
+
+   #[test]
+   fn test_resolve_endpoint() {
+      let openai = resolve_endpoint("openai", Some("sk".into()), None).unwrap();
+      assert_eq!(openai.api, EmbeddingsApi::OpenAi);
+      assert_eq!(openai.url, "https://api.openai.com/v1/embeddings");
+      assert_eq!(
+         resolve_endpoint("google", Some("key".into()), None)
+            .unwrap()
+            .url,
+         "https://generativelanguage.googleapis.com/v1beta/openai/embeddings"
+      );
+      let ollama = resolve_endpoint("ollama", None, None).unwrap();
+      assert_eq!(ollama.api, EmbeddingsApi::Ollama);
+      assert_eq!(ollama.url, "http://localhost:11434/api/embeddings");
+
+      let gateway = CustomProvider {
+         id: "gateway".into(),
+         display_name: "Gateway".into(),
+         base_url: normalize_base_url("https://llm.internal.example.com/").unwrap(),
+         api_key_header: Some("api-key".into()),
+         models_endpoint: None,
+         chat_endpoint: None,
+      };
+      let custom = resolve_endpoint("gateway", Some("k".into()), Some(&gateway)).unwrap();
+      assert_eq!(custom.url, "https://llm.internal.example.com/v1/embeddings");
+      assert_eq!(custom.api_key_header.as_deref(), Some("api-key"));
+
+      assert!(matches!(
+         resolve_endpoint("anthropic", Some("sk".into()), None),
+         Err(ProviderError::InvalidRequest { .. })
+      ));
+      assert!(matches!(
+         resolve_endpoint("openai", None, None),
+         Err(ProviderError::MissingCredential { .. })
+      ));
+      assert!(matches!(
+         resolve_endpoint("mistral", Some("k".into()), None),
+         Err(ProviderError::UnknownProvider { .. })
+      ));
+   }
+
+   #[test]
+   fn test_batches() {
+      let inputs: Vec<String> = (0..600).map(|_| "x".repeat(10)).collect();
+      let indices: Vec<usize> = (0..600).collect();
+      let lens: Vec<usize> = batches(&inputs, &indices).iter().map(Vec::len).collect();
+      assert_eq!(lens, vec![256, 256, 88]);
+
+      let inputs = vec![
+         "a".repeat(MAX_BATCH_CHARS - 10),
+         "b".repeat(20),
+         "c".repeat(MAX_BATCH_CHARS * 2),
+         "d".repeat(5),
+      ];
+      assert_eq!(
+         batches(&inputs, &[0, 1, 2, 3]),
+         vec![vec![0], vec![1], vec![2], vec![3]]
+      );
+      assert_eq!(batches(&inputs, &[1, 3]), vec![vec![1, 3]]);
+   }
+
+   #[tokio::test]
+   async fn test_embed_openai_batch() {
+      let (base_url, request) = serve_once(
+         "200 OK",
+         r#"{"data": [
+            {"index": 1, "embedding": [0.5, 0.5]},
+            {"index": 0, "embedding": [1.0, 0.0]}
+         ], "usage": {"prompt_tokens": 6, "total_tokens": 6}}"#,
+      )
+      .await;
+      let endpoint = endpoint(EmbeddingsApi::OpenAi, &base_url, "/v1/embeddings");
+      let (vectors, usage) = embed_batch(
+         &test_client(),
+         &endpoint,
+         "text-embedding-3-small",
+         &["fn main() {}", "struct Foo;"],
+         &RetryPolicy::NONE,
+         None,
+      )
+      .await
+      .unwrap();
+      assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.5, 0.5]]);
+      assert_eq!(
+         usage,
+         Some(EmbeddingUsage {
+            prompt_tokens: 6,
+            total_tokens: 6,
+         })
+      );
+
+      let request = request.await.unwrap();
+      assert!(request.starts_with("POST /v1/embeddings"));
+      assert!(request.contains("authorization: Bearer sk-test"));
+      assert!(request.contains(r#""input":["fn main() {}","struct Foo;"]"#));
+   }
+
+   #[tokio::test]
+   async fn test_embed_ollama() {
+      let (base_url, request) = serve_once("200 OK", r#"{"embedding": [0.1, 0.2, 0.3]}"#).await;
+      let endpoint = endpoint(EmbeddingsApi::Ollama, &base_url, "/api/embeddings");
+      let (vectors, usage) = embed_batch(
+         &test_client(),
+         &endpoint,
+         "nomic-embed-text",
+         &["fn main() {}"],
+         &RetryPolicy::NONE,
+         None,
+      )
+      .await
+      .unwrap();
+      assert_eq!(vectors, vec![vec![0.1, 0.2, 0.3]]);
+      assert_eq!(usage, None);
+      let request = request.await.unwrap();
+      assert!(request.contains(r#""prompt":"fn main() {}""#));
+      assert!(!request.contains("authorization"));
+   }
+
+   #[tokio::test]
+   async fn test_embedding_count_mismatch() {
+      let (base_url, _) =
+         serve_once("200 OK", r#"{"data": [{"index": 0, "embedding": [1.0]}]}"#).await;
+      let endpoint = endpoint(EmbeddingsApi::OpenAi, &base_url, "/v1/embeddings");
+      let error = embed_batch(
+         &test_client(),
+         &endpoint,
+         "text-embedding-3-small",
+         &["a", "b"],
+         &RetryPolicy::NONE,
+         None,
+      )
+      .await
+      .unwrap_err();
+      assert!(matches!(error, ProviderError::MalformedResponse { .. }));
+   }
+}
@@ -0,0 +1,267 @@
+use super::error::ProviderError;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+   fs,
+   io::ErrorKind,
+   path::{Path, PathBuf},
+   sync::{Arc, Mutex},
+   time::SystemTime,
+};
+use tauri::{State, command};
+
+/// Directory under the home directory embeddings are cached in
+const CACHE_DIR: &str = ".athas/cache/embeddings";
+/// Extension of cache entries, each the vector's `f32`s in little-endian order
+const ENTRY_EXTENSION: &str = "f32";
+/// Size the cache is evicted down to, least recently used entries first
+const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// What clearing the embeddings cache removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingsCacheCleared {
+   pub entries: usize,
+   pub bytes: u64,
+}
+
+/// Embedding vectors on disk, one file per model and content hash. Without a home directory
+/// nothing is cached.
+#[derive(Clone)]
+pub struct EmbeddingsCache {
+   dir: Option<PathBuf>,
+   max_bytes: u64,
+   /// Held while evicting or clearing so two don't race over the same files
+   lock: Arc<Mutex<()>>,
+}
+
+impl EmbeddingsCache {
+   /// The cache in `~/.athas/cache/embeddings`
+   pub fn new() -> Self {
+      Self {
+         dir: dirs::home_dir().map(|home| home.join(CACHE_DIR)),
+         max_bytes: DEFAULT_MAX_BYTES,
+         lock: Arc::default(),
+      }
+   }
+
+   #[cfg(test)]
+   fn in_dir(dir: &Path, max_bytes: u64) -> Self {
+      Self {
+         dir: Some(dir.to_path_buf()),
+         max_bytes,
+         lock: Arc::default(),
+      }
+   }
+
+   /// The key `input` embedded by `model` of `provider` is cached under
+   pub fn key(provider: &str, model: &str, input: &str) -> String {
+      let mut hasher = Sha256::new();
+      for part in [provider, model, input] {
+         hasher.update(part.as_bytes());
+         hasher.update([0]);
+      }
+      format!("{:x}", hasher.finalize())
+   }
+
+   fn entry_path(&self, key: &str) -> Option<PathBuf> {
+      let dir = self.dir.as_ref()?;
+      Some(dir.join(key).with_extension(ENTRY_EXTENSION))
+   }
+
+   /// The cached vector for `key`, marking it as recently used
+   pub fn get(&self, key: &str) -> Option<Vec<f32>> {
+      let path = self.entry_path(key)?;
+      let bytes = fs::read(&path).ok()?;
+      if bytes.is_empty() || bytes.len() % 4 != 0 {
+         log::warn!("Ignoring corrupt embeddings cache entry {}", path.display());
+         return None;
+      }
+      if let Err(e) = fs::File::options()
+         .write(true)
+         .open(&path)
+         .and_then(|file| file.set_modified(SystemTime::now()))
+      {
+         log::debug!("Failed to touch {}: {}", path.display(), e);
+      }
+      Some(
+         bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect(),
+      )
+   }
+
+   /// Cache each vector under its key, then evict the least recently used entries until the
+   /// cache fits its size cap. Failures are logged; a missing entry is only a cache miss.
+   pub fn insert_all(&self, entries: &[(String, Vec<f32>)]) {
+      let Some(dir) = &self.dir else {
+         return;
+      };
+      if let Err(e) = fs::create_dir_all(dir) {
+         log::warn!("Failed to create {}: {}", dir.display(), e);
+         return;
+      }
+      for (key, vector) in entries {
+         let Some(path) = self.entry_path(key) else {
+            continue;
+         };
+         let bytes: Vec<u8> = vector
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+         // Written aside and renamed so a reader never sees half a vector
+         let partial = path.with_extension("partial");
+         if let Err(e) = fs::write(&partial, bytes).and_then(|()| fs::rename(&partial, &path)) {
+            log::warn!("Failed to cache embedding in {}: {}", path.display(), e);
+            let _ = fs::remove_file(&partial);
+         }
+      }
+      if let Err(e) = self.evict() {
+         log::warn!("Failed to evict from {}: {}", dir.display(), e);
+      }
+   }
+
+   /// Cache entries with their size and when each was last used
+   fn entries(dir: &Path) -> std::io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+      let read_dir = match fs::read_dir(dir) {
+         Ok(read_dir) => read_dir,
+         Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+         Err(e) => return Err(e),
+      };
+      let mut entries = Vec::new();
+      for entry in read_dir {
+         let path = entry?.path();
+         if path.extension().and_then(|ext| ext.to_str()) != Some(ENTRY_EXTENSION) {
+            continue;
+         }
+         let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+         };
+         let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+         entries.push((path, metadata.len(), used));
+      }
+      Ok(entries)
+   }
+
+   fn evict(&self) -> std::io::Result<()> {
+      let Some(dir) = &self.dir else {
+         return Ok(());
+      };
+      let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+      let mut entries = Self::entries(dir)?;
+      let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+      if total <= self.max_bytes {
+         return Ok(());
+      }
+      entries.sort_by_key(|(_, _, used)| *used);
+      let mut evicted = 0;
+      for (path, size, _) in entries {
+         if total <= self.max_bytes {
+            break;
+         }
+         fs::remove_file(&path)?;
+         total -= size;
+         evicted += 1;
+      }
+      log::debug!("Evicted {} embeddings from {}", evicted, dir.display());
+      Ok(())
+   }
+
+   /// Remove every cached embedding
+   pub fn clear(&self) -> Result<EmbeddingsCacheCleared, ProviderError> {
+      let mut cleared = EmbeddingsCacheCleared {
+         entries: 0,
+         bytes: 0,
+      };
+      let Some(dir) = &self.dir else {
+         return Ok(cleared);
+      };
+      let cache_error = |e: std::io::Error| ProviderError::Cache {
+         path: dir.display().to_string(),
+         message: e.to_string(),
+      };
+      let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+      for (path, size, _) in Self::entries(dir).map_err(cache_error)? {
+         fs::remove_file(&path).map_err(cache_error)?;
+         cleared.entries += 1;
+         cleared.bytes += size;
+      }
+      Ok(cleared)
+   }
+}
+
+/// Remove every embedding cached by `generate_embeddings`
+#[command]
+pub async fn clear_embeddings_cache(
+   cache: State<'_, EmbeddingsCache>,
+) -> Result<EmbeddingsCacheCleared, ProviderError> {
+   let cache = cache.inner().clone();
+   let cleared = tokio::task::spawn_blocking(move || cache.clear())
+      .await
+      .map_err(|e| ProviderError::Cache {
+         path: CACHE_DIR.to_string(),
+         message: e.to_string(),
+      })??;
+   log::info!(
+      "Cleared {} cached embeddings ({} bytes)",
+      cleared.entries,
+      cleared.bytes
+   );
+   Ok(cleared)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use std::time::Duration;
+
+   #[test]
+   fn test_round_trip() {
+      let dir = tempfile::tempdir().unwrap();
+      let cache = EmbeddingsCache::in_dir(dir.path(), DEFAULT_MAX_BYTES);
+      let key = EmbeddingsCache::key("openai", "text-embedding-3-small", "fn main() {}");
+      assert_ne!(
+         key,
+         EmbeddingsCache::key("openai", "text-embedding-3-large", "fn main() {}")
+      );
+      assert_eq!(cache.get(&key), None);
+
+      cache.insert_all(&[(key.clone(), vec![0.25, -1.5, 3.0])]);
+      assert_eq!(cache.get(&key), Some(vec![0.25, -1.5, 3.0]));
+
+      assert_eq!(
+         cache.clear().unwrap(),
+         EmbeddingsCacheCleared {
+            entries: 1,
+            bytes: 12,
+         }
+      );
+      assert_eq!(cache.get(&key), None);
+   }
+
+   #[test]
+   fn test_evicts_least_recently_used() {
+      let dir = tempfile::tempdir().unwrap();
+      // Room for two four-value vectors
+      let cache = EmbeddingsCache::in_dir(dir.path(), 32);
+      let vector = vec![1.0; 4];
+      let old = SystemTime::now() - Duration::from_secs(60);
+
+      cache.insert_all(&[("a".into(), vector.clone()), ("b".into(), vector.clone())]);
+      for key in ["a", "b"] {
+         let file = fs::File::options()
+            .write(true)
+            .open(cache.entry_path(key).unwrap())
+            .unwrap();
+         file.set_modified(old).unwrap();
+      }
+      // Reading "a" makes "b" the oldest
+      assert!(cache.get("a").is_some());
+
+      cache.insert_all(&[("c".into(), vector)]);
+      assert!(cache.get("a").is_some());
+      assert!(cache.get("b").is_none());
+      assert!(cache.get("c").is_some());
+   }
+}
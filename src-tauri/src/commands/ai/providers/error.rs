@@ -0,0 +1,267 @@
+use serde::Serialize;
+use thiserror::Error;
+
+/// Errors returned by the commands that talk to model providers' APIs.
+///
+/// Serialized to the frontend as a tagged object such as `{ "type": "unauthorized", "url": "..." }`
+/// so the UI can tell an unreachable server from a rejected key.
+#[derive(Debug, Clone, Error, Serialize)]
+#[serde(
+   tag = "type",
+   rename_all = "camelCase",
+   rename_all_fields = "camelCase"
+)]
+pub enum ProviderError {
+   /// The base URL is empty or not an http(s) URL
+   #[error("Invalid provider URL '{url}': {message}")]
+   InvalidUrl { url: String, message: String },
+
+   /// The server couldn't be reached or didn't answer in time
+   #[error("Failed to reach {url}: {message}")]
+   Network {
+      url: String,
+      message: String,
+      timed_out: bool,
+   },
+
+   /// The server rejected the API key, or wanted one and none was sent
+   #[error("{url} rejected the API key (HTTP {status})")]
+   Unauthorized { url: String, status: u16 },
+
+   /// The server answered with an error status other than an authentication failure
+   #[error("{url} returned HTTP {status}: {message}")]
+   Http {
+      url: String,
+      status: u16,
+      message: String,
+   },
+
+   /// The server kept failing, or answering with a transient error status, until Athas stopped
+   /// retrying. `status` is the last one it answered with.
+   #[error("{url} still failing after {attempts} attempts: {message}")]
+   RetriesExhausted {
+      url: String,
+      attempts: u32,
+      status: Option<u16>,
+      message: String,
+   },
+
+   /// The server answered, but not with what the API defines
+   #[error("Unexpected response from {url}: {message}")]
+   MalformedResponse { url: String, message: String },
+
+   /// The server reported an error partway through a streamed response, or cut it short
+   #[error("Streamed response from {url} failed: {message}")]
+   Stream { url: String, message: String },
+
+   /// The request is missing something the provider needs
+   #[error("Invalid request: {message}")]
+   InvalidRequest { message: String },
+
+   /// A proxy or CA bundle setting can't be used
+   #[error("Invalid network setting '{setting}': {message}")]
+   InvalidNetworkConfig { setting: String, message: String },
+
+   /// The provider isn't one Athas knows how to call
+   #[error("Unknown provider '{provider}'")]
+   UnknownProvider { provider: String },
+
+   /// No API key was given and none is stored for the provider
+   #[error("No API key for {provider}")]
+   MissingCredential { provider: String },
+
+   /// The provider's API key couldn't be read from the credential store
+   #[error("Failed to read credential: {message}")]
+   Credentials { message: String },
+
+   /// The custom providers couldn't be read from Athas' settings
+   #[error("Failed to read provider settings: {message}")]
+   Settings { message: String },
+
+   /// Athas' on-disk cache of provider results couldn't be read or written
+   #[error("Cache at {path} failed: {message}")]
+   Cache { path: String, message: String },
+}
+
+impl ProviderError {
+   /// Classify a failed request to `url`
+   pub fn request(url: &str, error: reqwest::Error) -> Self {
+      ProviderError::Network {
+         url: url.to_string(),
+         message: error.to_string(),
+         timed_out: error.is_timeout(),
+      }
+   }
+
+   /// The error for a response to `url` with a non-success `status` and `body`
+   pub fn status(url: &str, status: reqwest::StatusCode, body: &str) -> Self {
+      let status_code = status.as_u16();
+      if matches!(status_code, 401 | 403) {
+         return ProviderError::Unauthorized {
+            url: url.to_string(),
+            status: status_code,
+         };
+      }
+      ProviderError::Http {
+         url: url.to_string(),
+         status: status_code,
+         message: error_message(body)
+            .or_else(|| status.canonical_reason().map(str::to_string))
+            .unwrap_or_default(),
+      }
+   }
+}
+
+/// The message of an OpenAI-style `{ "error": { "message": ... } }` body, or of a short plain-text
+/// one
+pub(super) fn error_message(body: &str) -> Option<String> {
+   const MAX_PLAIN_TEXT: usize = 200;
+
+   if let Ok(value) = serde_json::from_str::<serde_json::Value>(body) {
+      let error = value.get("error")?;
+      return error
+         .get("message")
+         .and_then(|message| message.as_str())
+         .or_else(|| error.as_str())
+         .map(str::to_string);
+   }
+   let body = body.trim();
+   (!body.is_empty() && body.len() <= MAX_PLAIN_TEXT).then(|| body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use reqwest::StatusCode;
+   use serde_json::json;
+
+   #[test]
+   fn test_serialized_shape() {
+      let cases = [
+         (
+            ProviderError::Unauthorized {
+               url: "http://localhost:4000/v1/models".into(),
+               status: 401,
+            },
+            json!({
+               "type": "unauthorized",
+               "url": "http://localhost:4000/v1/models",
+               "status": 401,
+            }),
+         ),
+         (
+            ProviderError::Network {
+               url: "http://localhost:4000/v1/models".into(),
+               message: "connection refused".into(),
+               timed_out: false,
+            },
+            json!({
+               "type": "network",
+               "url": "http://localhost:4000/v1/models",
+               "message": "connection refused",
+               "timedOut": false,
+            }),
+         ),
+         (
+            ProviderError::RetriesExhausted {
+               url: "https://api.openai.com/v1/models".into(),
+               attempts: 4,
+               status: Some(503),
+               message: "Service Unavailable".into(),
+            },
+            json!({
+               "type": "retriesExhausted",
+               "url": "https://api.openai.com/v1/models",
+               "attempts": 4,
+               "status": 503,
+               "message": "Service Unavailable",
+            }),
+         ),
+         (
+            ProviderError::MalformedResponse {
+               url: "http://localhost:4000/v1/models".into(),
+               message: "missing field `data`".into(),
+            },
+            json!({
+               "type": "malformedResponse",
+               "url": "http://localhost:4000/v1/models",
+               "message": "missing field `data`",
+            }),
+         ),
+         (
+            ProviderError::Stream {
+               url: "https://api.anthropic.com/v1/messages".into(),
+               message: "Overloaded".into(),
+            },
+            json!({
+               "type": "stream",
+               "url": "https://api.anthropic.com/v1/messages",
+               "message": "Overloaded",
+            }),
+         ),
+         (
+            ProviderError::InvalidRequest {
+               message: "no messages to send".into(),
+            },
+            json!({ "type": "invalidRequest", "message": "no messages to send" }),
+         ),
+         (
+            ProviderError::InvalidNetworkConfig {
+               setting: "httpsProxy".into(),
+               message: "relative URL without a base".into(),
+            },
+            json!({
+               "type": "invalidNetworkConfig",
+               "setting": "httpsProxy",
+               "message": "relative URL without a base",
+            }),
+         ),
+         (
+            ProviderError::MissingCredential {
+               provider: "openai".into(),
+            },
+            json!({ "type": "missingCredential", "provider": "openai" }),
+         ),
+         (
+            ProviderError::Settings {
+               message: "Failed to parse toml: expected `=`".into(),
+            },
+            json!({ "type": "settings", "message": "Failed to parse toml: expected `=`" }),
+         ),
+         (
+            ProviderError::Cache {
+               path: "/home/me/.athas/cache/embeddings".into(),
+               message: "Permission denied".into(),
+            },
+            json!({
+               "type": "cache",
+               "path": "/home/me/.athas/cache/embeddings",
+               "message": "Permission denied"
+            }),
+         ),
+      ];
+      for (error, expected) in cases {
+         assert_eq!(serde_json::to_value(&error).unwrap(), expected);
+      }
+   }
+
+   #[test]
+   fn test_status_errors() {
+      let url = "http://localhost:8000/v1/models";
+      assert!(matches!(
+         ProviderError::status(url, StatusCode::FORBIDDEN, ""),
+         ProviderError::Unauthorized { status: 403, .. }
+      ));
+      let error = ProviderError::status(
+         url,
+         StatusCode::NOT_FOUND,
+         r#"{"error": {"message": "model listing is disabled"}}"#,
+      );
+      assert_eq!(
+         error.to_string(),
+         "http://localhost:8000/v1/models returned HTTP 404: model listing is disabled"
+      );
+      let error = ProviderError::status(url, StatusCode::BAD_GATEWAY, "");
+      assert!(error.to_string().ends_with("HTTP 502: Bad Gateway"));
+   }
+}
@@ -0,0 +1,133 @@
+use super::{
+   error::ProviderError,
+   rate_limit::RateLimitRecorder,
+   retry::{RetryPolicy, send_with_retry},
+};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+/// Version of the Anthropic API requests are made against
+pub(super) const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// An API key and the header it is sent in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ApiKey<'a> {
+   pub key: &'a str,
+   /// `None` sends the key as a bearer token
+   pub header: Option<&'a str>,
+}
+
+impl<'a> ApiKey<'a> {
+   pub fn bearer(key: &'a str) -> Self {
+      Self { key, header: None }
+   }
+
+   pub fn apply(self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+      match self.header {
+         Some(header) => request.header(header, self.key),
+         None => request.bearer_auth(self.key),
+      }
+   }
+}
+
+/// `GET url` and parse the JSON response, sending `api_key` when given. Each attempt gives up
+/// after `timeout`, and transient failures are retried as `retry` allows.
+pub(super) async fn get_json<T: DeserializeOwned>(
+   client: &reqwest::Client,
+   url: &str,
+   api_key: Option<ApiKey<'_>>,
+   timeout: Duration,
+   retry: &RetryPolicy,
+   rate_limits: Option<&RateLimitRecorder>,
+) -> Result<T, ProviderError> {
+   let response = send_with_retry(retry, rate_limits, url, || {
+      let request = client.get(url).timeout(timeout);
+      match api_key {
+         Some(api_key) => api_key.apply(request),
+         None => request,
+      }
+   })
+   .await?;
+   read_json(url, response).await
+}
+
+/// `POST body` as JSON to `url` and parse the JSON response, like `get_json`. Only requests that
+/// are safe to repeat should be given a `retry` policy that retries.
+pub(super) async fn post_json<T: DeserializeOwned>(
+   client: &reqwest::Client,
+   url: &str,
+   api_key: Option<ApiKey<'_>>,
+   body: &serde_json::Value,
+   timeout: Duration,
+   retry: &RetryPolicy,
+   rate_limits: Option<&RateLimitRecorder>,
+) -> Result<T, ProviderError> {
+   let body = body.to_string();
+   let response = send_with_retry(retry, rate_limits, url, || {
+      let request = client
+         .post(url)
+         .header(reqwest::header::CONTENT_TYPE, "application/json")
+         .body(body.clone())
+         .timeout(timeout);
+      match api_key {
+         Some(api_key) => api_key.apply(request),
+         None => request,
+      }
+   })
+   .await?;
+   read_json(url, response).await
+}
+
+/// The JSON body of a successful response, or the error a failed one amounts to
+async fn read_json<T: DeserializeOwned>(
+   url: &str,
+   response: reqwest::Response,
+) -> Result<T, ProviderError> {
+   let status = response.status();
+   let body = response
+      .text()
+      .await
+      .map_err(|e| ProviderError::request(url, e))?;
+   if !status.is_success() {
+      return Err(ProviderError::status(url, status, &body));
+   }
+   serde_json::from_str(&body).map_err(|e| ProviderError::MalformedResponse {
+      url: url.to_string(),
+      message: e.to_string(),
+   })
+}
+
+/// Serve one HTTP response on a local port, returning the base URL and a handle resolving to the
+/// request that was received
+#[cfg(test)]
+pub(super) async fn serve_once(
+   status: &str,
+   body: &str,
+) -> (String, tokio::task::JoinHandle<String>) {
+   use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+   let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+   let base_url = format!("http://{}", listener.local_addr().unwrap());
+   let response = format!(
+      "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: \
+       close\r\n\r\n{}",
+      status,
+      body.len(),
+      body
+   );
+   let handle = tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await.unwrap();
+      let mut request = vec![0; 8192];
+      let read = socket.read(&mut request).await.unwrap();
+      socket.write_all(response.as_bytes()).await.unwrap();
+      String::from_utf8_lossy(&request[..read]).into_owned()
+   });
+   (base_url, handle)
+}
+
+/// A local port nothing listens on, having just been released
+#[cfg(test)]
+pub(super) fn closed_port() -> u16 {
+   let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+   listener.local_addr().unwrap().port()
+}
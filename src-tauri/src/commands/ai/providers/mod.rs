@@ -0,0 +1,28 @@
+mod completion;
+mod custom;
+mod embeddings;
+mod embeddings_cache;
+mod error;
+mod http;
+mod models;
+mod network;
+mod ollama;
+mod rate_limit;
+mod retry;
+mod sse;
+mod validate;
+
+pub use completion::{
+   ChatMessage, ChatRole, CompletionRequest, CompletionRequests, cancel_completion, complete,
+   start_completion,
+};
+pub use custom::{CustomProvider, is_built_in_provider};
+pub use embeddings::generate_embeddings;
+pub use embeddings_cache::{EmbeddingsCache, clear_embeddings_cache};
+pub use error::ProviderError;
+pub use models::{ProviderModelsCache, list_provider_models};
+pub use network::{NetworkConfig, ProviderHttpClient, test_network_config};
+pub use ollama::{detect_ollama, list_ollama_models, ollama_base_url};
+pub use rate_limit::{PROVIDER_RATE_LIMIT_EVENT, get_provider_rate_limits};
+pub use retry::RetrySettings;
+pub use validate::validate_provider_key;
@@ -0,0 +1,376 @@
+use super::{
+   custom::{self, CustomProvider},
+   error::ProviderError,
+   http::{ApiKey, get_json},
+   network::ProviderHttpClient,
+   rate_limit::RateLimitRecorder,
+   retry::RetryPolicy,
+   validate::api_base,
+};
+use crate::commands::ai::{
+   agent_settings::AgentSettingsLocks, credentials::CredentialStore, model_catalog::ModelProvider,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+   collections::HashMap,
+   sync::Mutex,
+   time::{Duration, Instant},
+};
+use tauri::{State, command};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Listing is safe to repeat, so transient failures are retried this many times by default
+const LIST_RETRIES: u32 = 3;
+/// How long a server's model list is reused before it is fetched again
+const CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// A model served by an OpenAI-compatible endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteModel {
+   pub id: String,
+   #[serde(alias = "owned_by")]
+   pub owned_by: Option<String>,
+   /// Unix timestamp, when the server reports one
+   pub created: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+   data: Vec<RemoteModel>,
+}
+
+/// Model lists fetched from servers, by the URL they were listed from
+#[derive(Default)]
+pub struct ProviderModelsCache {
+   entries: Mutex<HashMap<String, (Instant, Vec<RemoteModel>)>>,
+}
+
+impl ProviderModelsCache {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   fn get(&self, base_url: &str) -> Option<Vec<RemoteModel>> {
+      let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+      entries
+         .get(base_url)
+         .filter(|(fetched, _)| fetched.elapsed() < CACHE_TTL)
+         .map(|(_, models)| models.clone())
+   }
+
+   fn insert(&self, base_url: &str, models: Vec<RemoteModel>) {
+      let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+      entries.insert(base_url.to_string(), (Instant::now(), models));
+   }
+}
+
+/// `base_url` without trailing slashes, checked to be an http(s) URL
+pub(super) fn normalize_base_url(base_url: &str) -> Result<String, ProviderError> {
+   let url = base_url.trim().trim_end_matches('/');
+   let invalid = |message: &str| ProviderError::InvalidUrl {
+      url: base_url.to_string(),
+      message: message.to_string(),
+   };
+   let Some((scheme, rest)) = url.split_once("://") else {
+      return Err(invalid("expected an http:// or https:// URL"));
+   };
+   if !matches!(scheme, "http" | "https") {
+      return Err(invalid("expected an http:// or https:// URL"));
+   }
+   if rest.is_empty() {
+      return Err(invalid("the URL has no host"));
+   }
+   Ok(url.to_string())
+}
+
+/// Whether the key stored for `provider` may be sent to `base_url`. Only the provider's own API,
+/// or the base URL a custom provider was registered with, gets it, so a URL passed in from the
+/// webview can't collect a stored key.
+pub(super) fn stored_key_allowed(
+   provider: &str,
+   base_url: &str,
+   custom: Option<&CustomProvider>,
+) -> bool {
+   let own = match custom {
+      Some(custom) => normalize_base_url(&custom.base_url).ok(),
+      None => ModelProvider::from_id(provider).map(|known| api_base(known).to_string()),
+   };
+   let without_v1 = |url: &str| url.trim_end_matches("/v1").to_string();
+   own.is_some_and(|own| {
+      normalize_base_url(base_url).is_ok_and(|url| without_v1(&url) == without_v1(&own))
+   })
+}
+
+/// The endpoint at `path` of a `/v1` API, for base URLs given with or without their `/v1` suffix
+pub(super) fn v1_url(base_url: &str, path: &str) -> String {
+   if base_url.ends_with("/v1") {
+      format!("{}/{}", base_url, path)
+   } else {
+      format!("{}/v1/{}", base_url, path)
+   }
+}
+
+async fn fetch_models(
+   client: &reqwest::Client,
+   url: &str,
+   api_key: Option<ApiKey<'_>>,
+   retry: &RetryPolicy,
+   rate_limits: Option<&RateLimitRecorder>,
+) -> Result<Vec<RemoteModel>, ProviderError> {
+   let list: ModelList =
+      get_json(client, url, api_key, REQUEST_TIMEOUT, retry, rate_limits).await?;
+   Ok(list.data)
+}
+
+/// List the models an OpenAI-compatible server such as vLLM, LiteLLM or LM Studio serves, from
+/// `GET {base_url}/v1/models`. The API key stored for `api_key_provider` is sent when given, as
+/// long as `base_url` is that provider's own. When that is a custom provider, its models endpoint
+/// and key header are used, and `base_url` may be left out to list from its own base URL. Lists are
+/// cached for a few minutes per URL; `refresh` fetches again regardless.
+#[command]
+pub async fn list_provider_models(
+   http: State<'_, ProviderHttpClient>,
+   cache: State<'_, ProviderModelsCache>,
+   credentials: State<'_, CredentialStore>,
+   locks: State<'_, AgentSettingsLocks>,
+   base_url: Option<String>,
+   api_key_provider: Option<String>,
+   refresh: Option<bool>,
+) -> Result<Vec<RemoteModel>, ProviderError> {
+   let custom = match &api_key_provider {
+      Some(provider) => custom::find(&locks, provider).await?,
+      None => None,
+   };
+   let url = match (&base_url, &custom) {
+      (Some(base_url), Some(custom)) => CustomProvider {
+         base_url: normalize_base_url(base_url)?,
+         ..custom.clone()
+      }
+      .models_url(),
+      (Some(base_url), None) => v1_url(&normalize_base_url(base_url)?, "models"),
+      (None, Some(custom)) => custom.models_url(),
+      (None, None) => {
+         return Err(ProviderError::InvalidRequest {
+            message: "a base URL or a custom provider is needed to list models".to_string(),
+         });
+      }
+   };
+   if !refresh.unwrap_or(false)
+      && let Some(models) = cache.get(&url)
+   {
+      return Ok(models);
+   }
+
+   let stored_key = match (&api_key_provider, &base_url) {
+      (Some(provider), Some(base_url))
+         if !stored_key_allowed(provider, base_url, custom.as_ref()) =>
+      {
+         log::warn!(
+            "Not sending any key stored for {} to {}, which isn't its base URL",
+            provider,
+            base_url
+         );
+         None
+      }
+      (Some(provider), _) => credentials
+         .get(provider)
+         .map_err(|message| ProviderError::Credentials { message })?,
+      (None, _) => None,
+   };
+   let api_key = stored_key.as_deref().map(|key| match &custom {
+      Some(custom) => custom.api_key(key),
+      None => ApiKey::bearer(key),
+   });
+   let rate_limits = api_key_provider
+      .as_deref()
+      .map(|provider| http.rate_limits().recorder(provider));
+   let models = fetch_models(
+      &http.client(),
+      &url,
+      api_key,
+      &http.retry_policy(LIST_RETRIES),
+      rate_limits.as_ref(),
+   )
+   .await?;
+   log::debug!("Listed {} models from {}", models.len(), url);
+   cache.insert(&url, models.clone());
+   Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::providers::{
+      http::{closed_port, serve_once},
+      network::test_client,
+   };
+
+   #[test]
+   fn test_base_urls() {
+      assert_eq!(
+         normalize_base_url(" http://localhost:4000/ ").unwrap(),
+         "http://localhost:4000"
+      );
+      assert_eq!(
+         v1_url("http://localhost:4000/v1", "models"),
+         "http://localhost:4000/v1/models"
+      );
+      assert_eq!(
+         v1_url("https://llm.internal", "models"),
+         "https://llm.internal/v1/models"
+      );
+      for url in ["", "localhost:4000", "ftp://host", "http://"] {
+         assert!(
+            matches!(
+               normalize_base_url(url),
+               Err(ProviderError::InvalidUrl { .. })
+            ),
+            "{}",
+            url
+         );
+      }
+   }
+
+   #[test]
+   fn test_stored_key_only_goes_to_own_base_url() {
+      assert!(stored_key_allowed(
+         "anthropic",
+         "https://api.anthropic.com/",
+         None
+      ));
+      assert!(stored_key_allowed(
+         "openai",
+         "https://api.openai.com/v1",
+         None
+      ));
+      assert!(!stored_key_allowed(
+         "anthropic",
+         "https://attacker.example",
+         None
+      ));
+      assert!(!stored_key_allowed(
+         "openai",
+         "https://api.anthropic.com",
+         None
+      ));
+      assert!(!stored_key_allowed("vllm", "http://localhost:8000", None));
+
+      let gateway = CustomProvider {
+         id: "gateway".into(),
+         display_name: "Gateway".into(),
+         base_url: "https://llm.internal.example.com/v1/".into(),
+         api_key_header: None,
+         models_endpoint: None,
+         chat_endpoint: None,
+      };
+      assert!(stored_key_allowed(
+         "gateway",
+         "https://llm.internal.example.com",
+         Some(&gateway)
+      ));
+      assert!(!stored_key_allowed(
+         "gateway",
+         "https://attacker.example",
+         Some(&gateway)
+      ));
+   }
+
+   #[tokio::test]
+   async fn test_fetch_models() {
+      let body = r#"{"object": "list", "data": [
+         {"id": "llama-3.1-70b", "object": "model", "created": 1721172741, "owned_by": "vllm"},
+         {"id": "qwen2.5-coder"}
+      ]}"#;
+      let (base_url, server) = serve_once("200 OK", body).await;
+      let models = fetch_models(
+         &test_client(),
+         &v1_url(&base_url, "models"),
+         Some(ApiKey::bearer("sk-local")),
+         &RetryPolicy::NONE,
+         None,
+      )
+      .await
+      .unwrap();
+      assert_eq!(
+         models,
+         vec![
+            RemoteModel {
+               id: "llama-3.1-70b".into(),
+               owned_by: Some("vllm".into()),
+               created: Some(1721172741),
+            },
+            RemoteModel {
+               id: "qwen2.5-coder".into(),
+               owned_by: None,
+               created: None,
+            },
+         ]
+      );
+      let request = server.await.unwrap();
+      assert!(request.starts_with("GET /v1/models HTTP/1.1"));
+      assert!(
+         request
+            .to_ascii_lowercase()
+            .contains("authorization: bearer sk-local")
+      );
+   }
+
+   #[tokio::test]
+   async fn test_fetch_errors() {
+      let (base_url, _) = serve_once("401 Unauthorized", "{}").await;
+      assert!(matches!(
+         fetch_models(
+            &test_client(),
+            &v1_url(&base_url, "models"),
+            None,
+            &RetryPolicy::NONE,
+            None,
+         )
+         .await,
+         Err(ProviderError::Unauthorized { status: 401, .. })
+      ));
+
+      let (base_url, _) = serve_once("200 OK", "<html>LM Studio</html>").await;
+      assert!(matches!(
+         fetch_models(
+            &test_client(),
+            &v1_url(&base_url, "models"),
+            None,
+            &RetryPolicy::NONE,
+            None,
+         )
+         .await,
+         Err(ProviderError::MalformedResponse { .. })
+      ));
+
+      let base_url = format!("http://127.0.0.1:{}", closed_port());
+      assert!(matches!(
+         fetch_models(
+            &test_client(),
+            &v1_url(&base_url, "models"),
+            None,
+            &RetryPolicy::NONE,
+            None,
+         )
+         .await,
+         Err(ProviderError::Network {
+            timed_out: false,
+            ..
+         })
+      ));
+   }
+
+   #[test]
+   fn test_cache_is_keyed_by_base_url() {
+      let cache = ProviderModelsCache::new();
+      let model = RemoteModel {
+         id: "local".into(),
+         owned_by: None,
+         created: None,
+      };
+      cache.insert("http://localhost:1234", vec![model.clone()]);
+      assert_eq!(cache.get("http://localhost:1234"), Some(vec![model]));
+      assert_eq!(cache.get("http://localhost:8000"), None);
+   }
+}
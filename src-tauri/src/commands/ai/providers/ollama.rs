@@ -0,0 +1,193 @@
+use super::{
+   error::ProviderError, http::get_json, network::ProviderHttpClient, retry::RetryPolicy,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{State, command};
+
+pub const DEFAULT_OLLAMA_PORT: u16 = 11434;
+/// Ollama runs locally, so anything slower than this means it isn't there, and neither is retried
+const PROBE_TIMEOUT: Duration = Duration::from_millis(750);
+
+/// Base URL of the Ollama server on this machine
+pub fn ollama_base_url(port: Option<u16>) -> String {
+   format!("http://localhost:{}", port.unwrap_or(DEFAULT_OLLAMA_PORT))
+}
+
+/// Whether an Ollama server answers on this machine
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OllamaStatus {
+   pub running: bool,
+   pub base_url: String,
+   pub version: Option<String>,
+}
+
+/// A model pulled into the local Ollama server
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OllamaModel {
+   pub name: String,
+   /// Such as `8.0B`
+   pub parameter_size: Option<String>,
+   /// Such as `Q4_K_M`
+   pub quantization: Option<String>,
+   /// RFC 3339 timestamp of the last pull or change
+   pub modified_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+   version: String,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+   models: Vec<TagsModel>,
+}
+
+#[derive(Deserialize)]
+struct TagsModel {
+   name: String,
+   modified_at: Option<String>,
+   #[serde(default)]
+   details: TagsDetails,
+}
+
+#[derive(Default, Deserialize)]
+struct TagsDetails {
+   parameter_size: Option<String>,
+   quantization_level: Option<String>,
+}
+
+impl From<TagsModel> for OllamaModel {
+   fn from(model: TagsModel) -> Self {
+      Self {
+         name: model.name,
+         parameter_size: model.details.parameter_size,
+         quantization: model.details.quantization_level,
+         modified_at: model.modified_at,
+      }
+   }
+}
+
+async fn probe(client: &reqwest::Client, base_url: &str) -> OllamaStatus {
+   let url = format!("{}/api/version", base_url);
+   let version = match get_json::<VersionResponse>(
+      client,
+      &url,
+      None,
+      PROBE_TIMEOUT,
+      &RetryPolicy::NONE,
+      None,
+   )
+   .await
+   {
+      Ok(response) => Some(response.version),
+      Err(e) => {
+         log::debug!("Ollama not detected at {}: {}", base_url, e);
+         None
+      }
+   };
+   OllamaStatus {
+      running: version.is_some(),
+      base_url: base_url.to_string(),
+      version,
+   }
+}
+
+async fn fetch_models(
+   client: &reqwest::Client,
+   base_url: &str,
+) -> Result<Vec<OllamaModel>, ProviderError> {
+   let url = format!("{}/api/tags", base_url);
+   let tags: TagsResponse =
+      get_json(client, &url, None, PROBE_TIMEOUT, &RetryPolicy::NONE, None).await?;
+   Ok(tags.models.into_iter().map(OllamaModel::from).collect())
+}
+
+/// Check whether Ollama is running on `localhost`, on its default port unless `port` is given.
+/// Returns quickly with `running: false` when nothing answers.
+#[command]
+pub async fn detect_ollama(
+   http: State<'_, ProviderHttpClient>,
+   port: Option<u16>,
+) -> Result<OllamaStatus, ProviderError> {
+   Ok(probe(&http.client(), &ollama_base_url(port)).await)
+}
+
+/// List the models pulled into the local Ollama server. Fails fast with a network error when
+/// Ollama isn't running.
+#[command]
+pub async fn list_ollama_models(
+   http: State<'_, ProviderHttpClient>,
+   port: Option<u16>,
+) -> Result<Vec<OllamaModel>, ProviderError> {
+   fetch_models(&http.client(), &ollama_base_url(port)).await
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::providers::{
+      http::{closed_port, serve_once},
+      network::test_client,
+   };
+   use std::time::Instant;
+
+   #[tokio::test]
+   async fn test_probe() {
+      let (base_url, _) = serve_once("200 OK", r#"{"version": "0.6.2"}"#).await;
+      assert_eq!(
+         probe(&test_client(), &base_url).await,
+         OllamaStatus {
+            running: true,
+            base_url: base_url.clone(),
+            version: Some("0.6.2".into()),
+         }
+      );
+
+      let started = Instant::now();
+      let base_url = ollama_base_url(Some(closed_port()));
+      let status = probe(&test_client(), &base_url).await;
+      assert!(!status.running);
+      assert!(started.elapsed() < Duration::from_secs(1));
+   }
+
+   #[tokio::test]
+   async fn test_fetch_models() {
+      let body = r#"{"models": [
+         {
+            "name": "llama3.1:8b",
+            "modified_at": "2024-09-28T14:01:03.446276508+02:00",
+            "size": 4661230766,
+            "details": {"family": "llama", "parameter_size": "8.0B", "quantization_level": "Q4_0"}
+         },
+         {"name": "nomic-embed-text:latest"}
+      ]}"#;
+      let (base_url, server) = serve_once("200 OK", body).await;
+      assert_eq!(
+         fetch_models(&test_client(), &base_url).await.unwrap(),
+         vec![
+            OllamaModel {
+               name: "llama3.1:8b".into(),
+               parameter_size: Some("8.0B".into()),
+               quantization: Some("Q4_0".into()),
+               modified_at: Some("2024-09-28T14:01:03.446276508+02:00".into()),
+            },
+            OllamaModel {
+               name: "nomic-embed-text:latest".into(),
+               parameter_size: None,
+               quantization: None,
+               modified_at: None,
+            },
+         ]
+      );
+      assert!(server.await.unwrap().starts_with("GET /api/tags "));
+
+      assert!(matches!(
+         fetch_models(&test_client(), &ollama_base_url(Some(closed_port()))).await,
+         Err(ProviderError::Network { .. })
+      ));
+   }
+}
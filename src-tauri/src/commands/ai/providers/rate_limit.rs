@@ -0,0 +1,401 @@
+use super::network::ProviderHttpClient;
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use std::{
+   collections::HashMap,
+   sync::{Arc, Mutex, MutexGuard, RwLock},
+   time::Duration,
+};
+use tauri::{State, command};
+
+/// Event emitted when a provider's remaining capacity drops below `LOW_CAPACITY`
+pub const PROVIDER_RATE_LIMIT_EVENT: &str = "provider-rate-limit";
+
+/// Share of a limit left at which the UI is warned
+const LOW_CAPACITY: f64 = 0.1;
+
+/// What a provider limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RateLimitKind {
+   Requests,
+   Tokens,
+   InputTokens,
+   OutputTokens,
+}
+
+impl RateLimitKind {
+   const ALL: [RateLimitKind; 4] = [
+      RateLimitKind::Requests,
+      RateLimitKind::Tokens,
+      RateLimitKind::InputTokens,
+      RateLimitKind::OutputTokens,
+   ];
+
+   /// The kind as it appears in header names
+   fn header_name(self) -> &'static str {
+      match self {
+         RateLimitKind::Requests => "requests",
+         RateLimitKind::Tokens => "tokens",
+         RateLimitKind::InputTokens => "input-tokens",
+         RateLimitKind::OutputTokens => "output-tokens",
+      }
+   }
+}
+
+/// One limit as of a response. Fields the provider didn't send are `None`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitWindow {
+   pub kind: RateLimitKind,
+   pub limit: Option<u64>,
+   pub remaining: Option<u64>,
+   /// RFC 3339 time the limit is back to full
+   pub resets_at: Option<String>,
+}
+
+impl RateLimitWindow {
+   fn is_low(&self) -> bool {
+      match (self.remaining, self.limit) {
+         (Some(remaining), Some(limit)) if limit > 0 => {
+            (remaining as f64) < limit as f64 * LOW_CAPACITY
+         }
+         (Some(0), _) => true,
+         _ => false,
+      }
+   }
+}
+
+/// The limits a provider reported in its latest response
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitSnapshot {
+   pub provider: String,
+   pub windows: Vec<RateLimitWindow>,
+   /// RFC 3339 time of the response
+   pub observed_at: String,
+}
+
+/// Payload of `provider-rate-limit`: a limit that just dropped below `LOW_CAPACITY`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitAlert {
+   pub provider: String,
+   #[serde(flatten)]
+   pub window: RateLimitWindow,
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+   time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// A duration in OpenAI's reset format, such as `20ms`, `1s`, `6m0s` or `1h2m3.5s`
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+   let mut total = 0.0;
+   let mut rest = value.trim();
+   if rest.is_empty() {
+      return None;
+   }
+   while !rest.is_empty() {
+      let digits = rest
+         .find(|c: char| !c.is_ascii_digit() && c != '.')
+         .unwrap_or(rest.len());
+      let number: f64 = rest[..digits].parse().ok()?;
+      rest = &rest[digits..];
+      let unit_len = rest
+         .find(|c: char| c.is_ascii_digit())
+         .unwrap_or(rest.len());
+      let seconds = match &rest[..unit_len] {
+         "ms" => 0.001,
+         "s" => 1.0,
+         "m" => 60.0,
+         "h" => 3600.0,
+         "d" => 86400.0,
+         _ => return None,
+      };
+      total += number * seconds;
+      rest = &rest[unit_len..];
+   }
+   Some(Duration::from_secs_f64(total))
+}
+
+/// The rate limits in a response's headers: Anthropic's `anthropic-ratelimit-{kind}-{field}`,
+/// with reset times, or OpenAI's `x-ratelimit-{field}-{kind}`, with time left until the reset.
+/// Empty when the response has neither.
+pub(super) fn parse_rate_limits(headers: &HeaderMap, now: DateTime<Utc>) -> Vec<RateLimitWindow> {
+   let header = |name: String| headers.get(name)?.to_str().ok().map(str::trim);
+   let number = |name: String| header(name)?.parse::<u64>().ok();
+
+   RateLimitKind::ALL
+      .into_iter()
+      .filter_map(|kind| {
+         let name = kind.header_name();
+         let anthropic = |field: &str| format!("anthropic-ratelimit-{}-{}", name, field);
+         let openai = |field: &str| format!("x-ratelimit-{}-{}", field, name);
+
+         let limit = number(anthropic("limit")).or_else(|| number(openai("limit")));
+         let remaining = number(anthropic("remaining")).or_else(|| number(openai("remaining")));
+         let resets_at = match header(anthropic("reset")) {
+            Some(reset) => DateTime::parse_from_rfc3339(reset)
+               .ok()
+               .map(|reset| timestamp(reset.with_timezone(&Utc))),
+            None => header(openai("reset"))
+               .and_then(parse_reset_duration)
+               .and_then(|left| chrono::Duration::from_std(left).ok())
+               .map(|left| timestamp(now + left)),
+         };
+         (limit.is_some() || remaining.is_some() || resets_at.is_some()).then_some(
+            RateLimitWindow {
+               kind,
+               limit,
+               remaining,
+               resets_at,
+            },
+         )
+      })
+      .collect()
+}
+
+type AlertListener = Arc<dyn Fn(RateLimitAlert) + Send + Sync>;
+
+/// The latest rate limits each provider reported, by provider id
+#[derive(Clone, Default)]
+pub struct ProviderRateLimits {
+   snapshots: Arc<Mutex<HashMap<String, RateLimitSnapshot>>>,
+   listener: Arc<RwLock<Option<AlertListener>>>,
+}
+
+impl ProviderRateLimits {
+   fn lock(&self) -> MutexGuard<'_, HashMap<String, RateLimitSnapshot>> {
+      self.snapshots.lock().unwrap_or_else(|e| e.into_inner())
+   }
+
+   /// Call `listener` with every limit that drops below `LOW_CAPACITY`
+   pub fn on_low_capacity(&self, listener: impl Fn(RateLimitAlert) + Send + Sync + 'static) {
+      let mut current = self.listener.write().unwrap_or_else(|e| e.into_inner());
+      *current = Some(Arc::new(listener));
+   }
+
+   pub fn get(&self, provider: &str) -> Option<RateLimitSnapshot> {
+      self.lock().get(provider).cloned()
+   }
+
+   /// Keep the limits in `headers` as `provider`'s latest, alerting for those that were above
+   /// `LOW_CAPACITY` before and aren't now. Responses without rate limit headers change nothing.
+   fn record(&self, provider: &str, headers: &HeaderMap) {
+      let now = Utc::now();
+      let windows = parse_rate_limits(headers, now);
+      if windows.is_empty() {
+         return;
+      }
+
+      let dropped: Vec<RateLimitWindow> = {
+         let mut snapshots = self.lock();
+         let was_low = |kind: RateLimitKind| {
+            snapshots.get(provider).is_some_and(|snapshot| {
+               snapshot
+                  .windows
+                  .iter()
+                  .any(|window| window.kind == kind && window.is_low())
+            })
+         };
+         let dropped = windows
+            .iter()
+            .filter(|window| window.is_low() && !was_low(window.kind))
+            .cloned()
+            .collect();
+         snapshots.insert(
+            provider.to_string(),
+            RateLimitSnapshot {
+               provider: provider.to_string(),
+               windows,
+               observed_at: timestamp(now),
+            },
+         );
+         dropped
+      };
+
+      let listener = self
+         .listener
+         .read()
+         .unwrap_or_else(|e| e.into_inner())
+         .clone();
+      for window in dropped {
+         log::info!(
+            "{} rate limit on {:?} is low: {:?} of {:?} left",
+            provider,
+            window.kind,
+            window.remaining,
+            window.limit
+         );
+         if let Some(listener) = &listener {
+            listener(RateLimitAlert {
+               provider: provider.to_string(),
+               window,
+            });
+         }
+      }
+   }
+
+   /// A recorder for responses from `provider`
+   pub(super) fn recorder(&self, provider: &str) -> RateLimitRecorder {
+      RateLimitRecorder {
+         limits: self.clone(),
+         provider: provider.to_string(),
+      }
+   }
+}
+
+/// Records the rate limits of the responses one provider sends
+#[derive(Clone)]
+pub(super) struct RateLimitRecorder {
+   limits: ProviderRateLimits,
+   provider: String,
+}
+
+impl RateLimitRecorder {
+   pub fn record(&self, headers: &HeaderMap) {
+      self.limits.record(&self.provider, headers);
+   }
+}
+
+/// The rate limits `provider` reported in its latest response, `None` before it has sent any
+#[command]
+pub fn get_provider_rate_limits(
+   http: State<'_, ProviderHttpClient>,
+   provider: String,
+) -> Option<RateLimitSnapshot> {
+   http.rate_limits().get(&provider)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use reqwest::header::{HeaderName, HeaderValue};
+
+   fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+      pairs
+         .iter()
+         .map(|(name, value)| {
+            (
+               HeaderName::from_static(name),
+               HeaderValue::from_str(value).unwrap(),
+            )
+         })
+         .collect()
+   }
+
+   fn now() -> DateTime<Utc> {
+      DateTime::parse_from_rfc3339("2025-10-01T12:00:00Z")
+         .unwrap()
+         .with_timezone(&Utc)
+   }
+
+   #[test]
+   fn test_parse_anthropic_headers() {
+      let headers = headers(&[
+         ("anthropic-ratelimit-requests-limit", "50"),
+         ("anthropic-ratelimit-requests-remaining", "49"),
+         ("anthropic-ratelimit-requests-reset", "2025-10-01T12:00:01Z"),
+         ("anthropic-ratelimit-input-tokens-limit", "30000"),
+         ("anthropic-ratelimit-input-tokens-remaining", "2000"),
+         (
+            "anthropic-ratelimit-input-tokens-reset",
+            "2025-10-01T14:00:30+02:00",
+         ),
+         ("anthropic-ratelimit-output-tokens-remaining", "soon"),
+         ("request-id", "req_01"),
+      ]);
+      assert_eq!(
+         parse_rate_limits(&headers, now()),
+         vec![
+            RateLimitWindow {
+               kind: RateLimitKind::Requests,
+               limit: Some(50),
+               remaining: Some(49),
+               resets_at: Some("2025-10-01T12:00:01Z".into()),
+            },
+            RateLimitWindow {
+               kind: RateLimitKind::InputTokens,
+               limit: Some(30000),
+               remaining: Some(2000),
+               resets_at: Some("2025-10-01T12:00:30Z".into()),
+            },
+         ]
+      );
+   }
+
+   #[test]
+   fn test_parse_openai_headers() {
+      let headers = headers(&[
+         ("x-ratelimit-limit-requests", "500"),
+         ("x-ratelimit-remaining-requests", "499"),
+         ("x-ratelimit-reset-requests", "120ms"),
+         ("x-ratelimit-limit-tokens", "30000"),
+         ("x-ratelimit-remaining-tokens", "29900"),
+         ("x-ratelimit-reset-tokens", "6m0s"),
+      ]);
+      let windows = parse_rate_limits(&headers, now());
+      assert_eq!(windows.len(), 2);
+      assert_eq!(windows[0].remaining, Some(499));
+      assert_eq!(
+         windows[0].resets_at.as_deref(),
+         Some("2025-10-01T12:00:00Z")
+      );
+      assert_eq!(windows[1].kind, RateLimitKind::Tokens);
+      assert_eq!(
+         windows[1].resets_at.as_deref(),
+         Some("2025-10-01T12:06:00Z")
+      );
+
+      assert!(parse_rate_limits(&HeaderMap::new(), now()).is_empty());
+      assert_eq!(
+         parse_reset_duration("1h2m3.5s"),
+         Some(Duration::from_secs_f64(3723.5))
+      );
+      assert_eq!(parse_reset_duration("2 weeks"), None);
+      assert_eq!(parse_reset_duration(""), None);
+   }
+
+   #[test]
+   fn test_alerts_when_capacity_drops() {
+      let limits = ProviderRateLimits::default();
+      let alerts = Arc::new(Mutex::new(Vec::new()));
+      limits.on_low_capacity({
+         let alerts = alerts.clone();
+         move |alert| alerts.lock().unwrap().push(alert)
+      });
+      let recorder = limits.recorder("openai");
+      let response = |remaining: &str| {
+         headers(&[
+            ("x-ratelimit-limit-requests", "100"),
+            ("x-ratelimit-remaining-requests", remaining),
+         ])
+      };
+
+      recorder.record(&response("50"));
+      recorder.record(&response("9"));
+      recorder.record(&response("3"));
+      recorder.record(&HeaderMap::new());
+      let alerts = alerts.lock().unwrap().clone();
+      assert_eq!(alerts.len(), 1);
+      assert_eq!(alerts[0].provider, "openai");
+      assert_eq!(alerts[0].window.remaining, Some(9));
+      // Responses without headers leave the last snapshot in place
+      assert_eq!(limits.get("openai").unwrap().windows[0].remaining, Some(3));
+      assert!(limits.get("anthropic").is_none());
+
+      let value = serde_json::to_value(&alerts[0]).unwrap();
+      assert_eq!(
+         value,
+         serde_json::json!({
+            "provider": "openai",
+            "kind": "requests",
+            "limit": 100,
+            "remaining": 9,
+            "resetsAt": null,
+         })
+      );
+   }
+}
@@ -0,0 +1,310 @@
+use super::{error::ProviderError, rate_limit::RateLimitRecorder};
+use reqwest::{StatusCode, header::HeaderMap};
+use std::{
+   collections::hash_map::RandomState,
+   hash::{BuildHasher, Hasher},
+   time::Duration,
+};
+
+/// Delay before the first retry, doubled for each one after
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// How provider requests are retried, from the `ai.network` Athas settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetrySettings {
+   /// Replaces the retry limit of every request that is retried at all
+   pub max_retries: Option<u32>,
+   /// Longest wait before a retry. A server asking for a longer one isn't retried.
+   pub max_backoff: Duration,
+}
+
+impl Default for RetrySettings {
+   fn default() -> Self {
+      Self {
+         max_retries: None,
+         max_backoff: DEFAULT_MAX_BACKOFF,
+      }
+   }
+}
+
+/// How one kind of request is retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct RetryPolicy {
+   pub max_retries: u32,
+   pub max_backoff: Duration,
+   /// Whether to retry 429 responses. Checking a key wants to hear about them instead.
+   pub retry_rate_limited: bool,
+}
+
+impl RetryPolicy {
+   /// Never retry, for requests that must fail fast
+   pub const NONE: RetryPolicy = RetryPolicy {
+      max_retries: 0,
+      max_backoff: Duration::ZERO,
+      retry_rate_limited: false,
+   };
+
+   /// Retry up to `default_retries` times unless the settings say otherwise
+   pub fn new(settings: RetrySettings, default_retries: u32) -> Self {
+      Self {
+         max_retries: settings.max_retries.unwrap_or(default_retries),
+         max_backoff: settings.max_backoff,
+         retry_rate_limited: true,
+      }
+   }
+
+   fn retries(&self, status: StatusCode) -> bool {
+      match status.as_u16() {
+         429 => self.retry_rate_limited,
+         502..=504 => true,
+         _ => false,
+      }
+   }
+}
+
+/// The wait a response asks for in `Retry-After`, or OpenAI's and Anthropic's `retry-after-ms`
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+   let header = |name: &str| headers.get(name)?.to_str().ok().map(str::trim);
+   if let Some(ms) = header("retry-after-ms").and_then(|ms| ms.parse::<f64>().ok()) {
+      return (ms >= 0.0).then(|| Duration::from_secs_f64(ms / 1000.0));
+   }
+   let value = header("retry-after")?;
+   if let Ok(seconds) = value.parse::<u64>() {
+      return Some(Duration::from_secs(seconds));
+   }
+   let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+   let wait = date.signed_duration_since(chrono::Utc::now());
+   Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Exponential backoff for the `retry`th retry, jittered between half and all of it so clients
+/// that failed together don't retry together
+fn backoff(retry: u32, max_backoff: Duration) -> Duration {
+   let delay = BASE_DELAY
+      .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+      .min(max_backoff);
+   let random = RandomState::new().build_hasher().finish();
+   let fraction = 0.5 + (random % 1000) as f64 / 2000.0;
+   delay.mul_f64(fraction)
+}
+
+/// The wait before retrying, or `None` when the server wants a longer one than allowed
+fn retry_delay(retry: u32, policy: &RetryPolicy, asked: Option<Duration>) -> Option<Duration> {
+   match asked {
+      Some(asked) if asked > policy.max_backoff => None,
+      Some(asked) => Some(asked.max(backoff(retry, policy.max_backoff))),
+      None => Some(backoff(retry, policy.max_backoff)),
+   }
+}
+
+/// Send the request `build` makes, retrying transient failures as `policy` allows: 429 (when
+/// retried), 502, 503 and 504 responses, and failures to connect or time-outs. Other responses
+/// are returned as they are, as is the last one when the first attempt wasn't retried. Giving up
+/// after retrying is a `RetriesExhausted` error. The rate limits of every response, retried or
+/// not, go to `rate_limits`.
+pub(super) async fn send_with_retry(
+   policy: &RetryPolicy,
+   rate_limits: Option<&RateLimitRecorder>,
+   url: &str,
+   build: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<reqwest::Response, ProviderError> {
+   let mut attempts = 0;
+   loop {
+      attempts += 1;
+      let retries_left = attempts <= policy.max_retries;
+      let sent = build().send().await;
+      if let (Ok(response), Some(rate_limits)) = (&sent, rate_limits) {
+         rate_limits.record(response.headers());
+      }
+      match sent {
+         Ok(response) if policy.retries(response.status()) => {
+            let status = response.status();
+            let delay = retry_after(response.headers());
+            match retry_delay(attempts, policy, delay).filter(|_| retries_left) {
+               Some(delay) => {
+                  log::debug!(
+                     "{} answered HTTP {}, retrying in {}ms",
+                     url,
+                     status.as_u16(),
+                     delay.as_millis()
+                  );
+                  tokio::time::sleep(delay).await;
+               }
+               None if attempts == 1 => return Ok(response),
+               None => {
+                  let body = response.text().await.unwrap_or_default();
+                  let message = match ProviderError::status(url, status, &body) {
+                     ProviderError::Http { message, .. } => message,
+                     error => error.to_string(),
+                  };
+                  return Err(ProviderError::RetriesExhausted {
+                     url: url.to_string(),
+                     attempts,
+                     status: Some(status.as_u16()),
+                     message,
+                  });
+               }
+            }
+         }
+         Ok(response) => return Ok(response),
+         Err(e) if retries_left && (e.is_connect() || e.is_timeout()) => {
+            let delay = backoff(attempts, policy.max_backoff);
+            log::debug!(
+               "Request to {} failed, retrying in {}ms: {}",
+               url,
+               delay.as_millis(),
+               e
+            );
+            tokio::time::sleep(delay).await;
+         }
+         Err(e) if attempts == 1 => return Err(ProviderError::request(url, e)),
+         Err(e) => {
+            return Err(ProviderError::RetriesExhausted {
+               url: url.to_string(),
+               attempts,
+               status: None,
+               message: e.to_string(),
+            });
+         }
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::providers::{http::closed_port, network::test_client};
+   use reqwest::header::HeaderValue;
+   use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+   /// Serve `responses` in order, one per connection, returning the base URL
+   async fn serve(responses: Vec<String>) -> String {
+      let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let base_url = format!("http://{}", listener.local_addr().unwrap());
+      tokio::spawn(async move {
+         for response in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 8192];
+            let _ = socket.read(&mut request).await.unwrap();
+            socket.write_all(response.as_bytes()).await.unwrap();
+         }
+      });
+      base_url
+   }
+
+   fn response(status: &str, headers: &str, body: &str) -> String {
+      format!(
+         "HTTP/1.1 {}\r\n{}content-length: {}\r\nconnection: close\r\n\r\n{}",
+         status,
+         headers,
+         body.len(),
+         body
+      )
+   }
+
+   fn policy(max_retries: u32) -> RetryPolicy {
+      RetryPolicy::new(
+         RetrySettings {
+            max_retries: Some(max_retries),
+            max_backoff: Duration::from_millis(50),
+         },
+         3,
+      )
+   }
+
+   #[test]
+   fn test_retry_after() {
+      let headers = |name: &'static str, value: &str| {
+         let mut headers = HeaderMap::new();
+         headers.insert(name, HeaderValue::from_str(value).unwrap());
+         headers
+      };
+      assert_eq!(
+         retry_after(&headers("retry-after", "2")),
+         Some(Duration::from_secs(2))
+      );
+      assert_eq!(
+         retry_after(&headers("retry-after-ms", "150")),
+         Some(Duration::from_millis(150))
+      );
+      assert_eq!(
+         retry_after(&headers("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT")),
+         Some(Duration::ZERO)
+      );
+      assert_eq!(retry_after(&headers("retry-after", "soon")), None);
+
+      let policy = policy(3);
+      assert_eq!(retry_delay(1, &policy, Some(Duration::from_secs(30))), None);
+      // Waits at least as long as asked, and never longer than the cap
+      let delay = retry_delay(1, &policy, Some(Duration::from_millis(40))).unwrap();
+      assert!(delay >= Duration::from_millis(40) && delay <= policy.max_backoff);
+      for retry in 1..10 {
+         let delay = backoff(retry, Duration::from_secs(8));
+         assert!(delay <= Duration::from_secs(8) && delay >= BASE_DELAY / 2);
+      }
+   }
+
+   #[tokio::test]
+   async fn test_retries_transient_responses() {
+      let base_url = serve(vec![
+         response("503 Service Unavailable", "", "busy"),
+         response("429 Too Many Requests", "retry-after-ms: 10\r\n", "{}"),
+         response("200 OK", "", "{}"),
+      ])
+      .await;
+      let client = test_client();
+      let response = send_with_retry(&policy(3), None, &base_url, || client.get(&base_url))
+         .await
+         .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+   }
+
+   #[tokio::test]
+   async fn test_gives_up_with_attempt_count() {
+      let base_url = serve(vec![
+         response("502 Bad Gateway", "", ""),
+         response(
+            "503 Service Unavailable",
+            "",
+            r#"{"error": {"message": "overloaded"}}"#,
+         ),
+      ])
+      .await;
+      let client = test_client();
+      let error = send_with_retry(&policy(1), None, &base_url, || client.get(&base_url))
+         .await
+         .unwrap_err();
+      assert!(matches!(
+         &error,
+         ProviderError::RetriesExhausted {
+            attempts: 2,
+            status: Some(503),
+            ..
+         }
+      ));
+      assert!(error.to_string().contains("after 2 attempts: overloaded"));
+
+      // Without retries the response is the caller's to interpret
+      let base_url = serve(vec![response("429 Too Many Requests", "", "{}")]).await;
+      let response = send_with_retry(&RetryPolicy::NONE, None, &base_url, || {
+         client.get(&base_url)
+      })
+      .await
+      .unwrap();
+      assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+      let url = format!("http://127.0.0.1:{}", closed_port());
+      let error = send_with_retry(&policy(2), None, &url, || client.get(&url))
+         .await
+         .unwrap_err();
+      assert!(matches!(
+         error,
+         ProviderError::RetriesExhausted {
+            attempts: 3,
+            status: None,
+            ..
+         }
+      ));
+   }
+}
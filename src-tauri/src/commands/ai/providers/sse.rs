@@ -0,0 +1,97 @@
+/// One event of a `text/event-stream` response
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct SseEvent {
+   pub event: Option<String>,
+   pub data: String,
+}
+
+/// Splits a `text/event-stream` body into events as its bytes arrive. Lines are only decoded once
+/// complete, so characters split across chunks survive.
+#[derive(Debug, Default)]
+pub(super) struct SseParser {
+   buffer: Vec<u8>,
+   event: Option<String>,
+   data: Vec<String>,
+}
+
+impl SseParser {
+   /// Feed the next chunk of the body, returning the events it completed
+   pub fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+      self.buffer.extend_from_slice(bytes);
+      let mut events = Vec::new();
+      while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+         let line: Vec<u8> = self.buffer.drain(..=end).collect();
+         let line = String::from_utf8_lossy(&line);
+         let line = line.trim_end_matches(['\n', '\r']);
+
+         if line.is_empty() {
+            if self.data.is_empty() {
+               self.event = None;
+            } else {
+               events.push(SseEvent {
+                  event: self.event.take(),
+                  data: self.data.join("\n"),
+               });
+               self.data.clear();
+            }
+            continue;
+         }
+         if line.starts_with(':') {
+            continue;
+         }
+         let (field, value) = line.split_once(':').unwrap_or((line, ""));
+         let value = value.strip_prefix(' ').unwrap_or(value);
+         match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => {}
+         }
+      }
+      events
+   }
+
+   /// Events left unterminated when the body ended
+   pub fn finish(&mut self) -> Vec<SseEvent> {
+      self.push(b"\n\n")
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_events_split_across_chunks() {
+      let mut parser = SseParser::default();
+      let body = "event: message_start\r\ndata: {\"a\":\r\ndata: 1}\r\n\r\n: ping\n\ndata: \
+                  h\u{e9}llo\n\ndata: [DONE]";
+      let bytes = body.as_bytes();
+
+      let mut events = Vec::new();
+      // Cut inside the two-byte character and between CR and LF
+      for chunk in [&bytes[..5], &bytes[5..21], &bytes[21..63], &bytes[63..]] {
+         events.extend(parser.push(chunk));
+      }
+      assert_eq!(events.len(), 2);
+      events.extend(parser.finish());
+
+      assert_eq!(
+         events,
+         vec![
+            SseEvent {
+               event: Some("message_start".into()),
+               data: "{\"a\":\n1}".into(),
+            },
+            SseEvent {
+               event: None,
+               data: "h\u{e9}llo".into(),
+            },
+            SseEvent {
+               event: None,
+               data: "[DONE]".into(),
+            },
+         ]
+      );
+      assert!(parser.finish().is_empty());
+   }
+}
@@ -0,0 +1,313 @@
+use super::{
+   custom::{self, CustomProvider},
+   error::{ProviderError, error_message},
+   http::ANTHROPIC_VERSION,
+   network::ProviderHttpClient,
+   rate_limit::RateLimitRecorder,
+   retry::{RetryPolicy, send_with_retry},
+};
+use crate::commands::ai::{
+   agent_settings::AgentSettingsLocks, credentials::CredentialStore, model_catalog::ModelProvider,
+};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tauri::{State, command};
+
+const VALIDATION_TIMEOUT: Duration = Duration::from_secs(10);
+const VALIDATION_RETRIES: u32 = 2;
+
+/// What a provider made of an API key
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyValidation {
+   /// The provider accepted the key
+   pub valid: bool,
+   /// The key was accepted but the provider is throttling it right now
+   pub rate_limited: bool,
+   pub status_code: u16,
+   pub detail: String,
+   pub latency_ms: u64,
+}
+
+/// Base URL of each provider's API
+pub(super) fn api_base(provider: ModelProvider) -> &'static str {
+   match provider {
+      ModelProvider::Anthropic => "https://api.anthropic.com",
+      ModelProvider::OpenAi => "https://api.openai.com",
+      ModelProvider::Google => "https://generativelanguage.googleapis.com",
+   }
+}
+
+/// The provider a key is checked with
+#[derive(Debug, Clone, Copy)]
+enum KeyTarget<'a> {
+   /// A built-in provider, at its API's base URL
+   BuiltIn(ModelProvider, &'a str),
+   Custom(&'a CustomProvider),
+}
+
+/// The cheapest authenticated request each provider offers: listing models. Keys go in headers
+/// only, never the URL, so they can't end up in error messages.
+fn validation_request(
+   client: &reqwest::Client,
+   target: KeyTarget<'_>,
+   key: &str,
+) -> (String, reqwest::RequestBuilder) {
+   let (provider, base_url) = match target {
+      KeyTarget::BuiltIn(provider, base_url) => (provider, base_url),
+      KeyTarget::Custom(provider) => {
+         let url = provider.models_url();
+         let request = provider.api_key(key).apply(client.get(&url));
+         return (url, request);
+      }
+   };
+   match provider {
+      ModelProvider::Anthropic => {
+         let url = format!("{}/v1/models?limit=1", base_url);
+         let request = client
+            .get(&url)
+            .header("x-api-key", key)
+            .header("anthropic-version", ANTHROPIC_VERSION);
+         (url, request)
+      }
+      ModelProvider::OpenAi => {
+         let url = format!("{}/v1/models", base_url);
+         let request = client.get(&url).bearer_auth(key);
+         (url, request)
+      }
+      ModelProvider::Google => {
+         let url = format!("{}/v1beta/models?pageSize=1", base_url);
+         let request = client.get(&url).header("x-goog-api-key", key);
+         (url, request)
+      }
+   }
+}
+
+/// Interpret the provider's answer. Rate limiting means the key got past authentication, so it
+/// counts as valid. Google reports a malformed key as a 400. Server errors say nothing about the
+/// key and are returned as errors.
+fn interpret(
+   url: &str,
+   status: StatusCode,
+   body: &str,
+   latency_ms: u64,
+) -> Result<KeyValidation, ProviderError> {
+   let (valid, rate_limited, detail) = match status.as_u16() {
+      200..=299 => (true, false, "Key accepted".to_string()),
+      429 => (
+         true,
+         true,
+         "Rate limited, but the key was accepted".to_string(),
+      ),
+      400 | 401 | 403 => (
+         false,
+         false,
+         error_message(body).unwrap_or_else(|| "Key rejected".to_string()),
+      ),
+      _ => return Err(ProviderError::status(url, status, body)),
+   };
+   Ok(KeyValidation {
+      valid,
+      rate_limited,
+      status_code: status.as_u16(),
+      detail,
+      latency_ms,
+   })
+}
+
+async fn validate(
+   client: &reqwest::Client,
+   target: KeyTarget<'_>,
+   key: &str,
+   retry: &RetryPolicy,
+   rate_limits: Option<&RateLimitRecorder>,
+) -> Result<KeyValidation, ProviderError> {
+   let (url, _) = validation_request(client, target, key);
+
+   let started = Instant::now();
+   let response = send_with_retry(retry, rate_limits, &url, || {
+      let (_, request) = validation_request(client, target, key);
+      request.timeout(VALIDATION_TIMEOUT)
+   })
+   .await?;
+   let latency_ms = started.elapsed().as_millis() as u64;
+   let status = response.status();
+   let body = response
+      .text()
+      .await
+      .map_err(|e| ProviderError::request(&url, e))?;
+   interpret(&url, status, &body, latency_ms)
+}
+
+/// Check an API key with the provider, making the cheapest authenticated call it has. `key` is
+/// tested when given, otherwise the stored one. Custom providers are checked by listing their
+/// models. The key is never logged or returned.
+#[command]
+pub async fn validate_provider_key(
+   http: State<'_, ProviderHttpClient>,
+   credentials: State<'_, CredentialStore>,
+   locks: State<'_, AgentSettingsLocks>,
+   provider: String,
+   key: Option<String>,
+) -> Result<KeyValidation, ProviderError> {
+   let custom = custom::find(&locks, &provider).await?;
+   let target = match (ModelProvider::from_id(&provider), &custom) {
+      (Some(model_provider), _) => KeyTarget::BuiltIn(model_provider, api_base(model_provider)),
+      (None, Some(custom)) => KeyTarget::Custom(custom),
+      (None, None) => return Err(ProviderError::UnknownProvider { provider }),
+   };
+   let key = match key.map(|key| key.trim().to_string()) {
+      Some(key) if !key.is_empty() => key,
+      _ => credentials
+         .get(&provider)
+         .map_err(|message| ProviderError::Credentials { message })?
+         .ok_or_else(|| ProviderError::MissingCredential {
+            provider: provider.clone(),
+         })?,
+   };
+
+   let validation = validate(
+      &http.client(),
+      target,
+      &key,
+      // A 429 is the answer here: the key is valid but throttled
+      &RetryPolicy {
+         retry_rate_limited: false,
+         ..http.retry_policy(VALIDATION_RETRIES)
+      },
+      Some(&http.rate_limits().recorder(&provider)),
+   )
+   .await?;
+   log::info!(
+      "Validated {} API key: valid={} status={} in {}ms",
+      provider,
+      validation.valid,
+      validation.status_code,
+      validation.latency_ms
+   );
+   Ok(validation)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::providers::{
+      http::serve_once, network::test_client, retry::RetrySettings,
+   };
+
+   #[test]
+   fn test_interpret() {
+      let url = "https://api.openai.com/v1/models";
+      let cases = [
+         (StatusCode::OK, "{}", true, false, "Key accepted"),
+         (
+            StatusCode::TOO_MANY_REQUESTS,
+            "{}",
+            true,
+            true,
+            "Rate limited, but the key was accepted",
+         ),
+         (
+            StatusCode::UNAUTHORIZED,
+            r#"{"error": {"message": "Incorrect API key provided"}}"#,
+            false,
+            false,
+            "Incorrect API key provided",
+         ),
+         (
+            StatusCode::BAD_REQUEST,
+            r#"{"error": {"code": 400, "message": "API key not valid. Please pass a valid API key."}}"#,
+            false,
+            false,
+            "API key not valid. Please pass a valid API key.",
+         ),
+         (StatusCode::FORBIDDEN, "", false, false, "Key rejected"),
+      ];
+      for (status, body, valid, rate_limited, detail) in cases {
+         let result = interpret(url, status, body, 12).unwrap();
+         assert_eq!(
+            result,
+            KeyValidation {
+               valid,
+               rate_limited,
+               status_code: status.as_u16(),
+               detail: detail.to_string(),
+               latency_ms: 12,
+            }
+         );
+      }
+      assert!(matches!(
+         interpret(url, StatusCode::SERVICE_UNAVAILABLE, "overloaded", 12),
+         Err(ProviderError::Http { status: 503, .. })
+      ));
+   }
+
+   #[tokio::test]
+   async fn test_validate_sends_key_in_headers() {
+      let (base_url, server) = serve_once("200 OK", r#"{"data": []}"#).await;
+      let result = validate(
+         &test_client(),
+         KeyTarget::BuiltIn(ModelProvider::Anthropic, &base_url),
+         "sk-ant-test",
+         &RetryPolicy::NONE,
+         None,
+      )
+      .await
+      .unwrap();
+      assert!(result.valid);
+      let request = server.await.unwrap().to_ascii_lowercase();
+      assert!(request.starts_with("get /v1/models?limit=1 "));
+      assert!(request.contains("x-api-key: sk-ant-test"));
+      assert!(request.contains("anthropic-version: 2023-06-01"));
+
+      let (base_url, server) = serve_once("429 Too Many Requests", "{}").await;
+      let result = validate(
+         &test_client(),
+         KeyTarget::BuiltIn(ModelProvider::Google, &base_url),
+         "AIza-test",
+         &RetryPolicy {
+            retry_rate_limited: false,
+            ..RetryPolicy::new(RetrySettings::default(), 2)
+         },
+         None,
+      )
+      .await
+      .unwrap();
+      assert!(result.valid && result.rate_limited);
+      let request = server.await.unwrap();
+      assert!(!request.lines().next().unwrap().contains("AIza-test"));
+      assert!(
+         request
+            .to_ascii_lowercase()
+            .contains("x-goog-api-key: aiza-test")
+      );
+   }
+
+   #[tokio::test]
+   async fn test_validate_custom_provider() {
+      let (base_url, server) = serve_once("200 OK", r#"{"data": []}"#).await;
+      let provider = CustomProvider {
+         id: "gateway".into(),
+         display_name: "Gateway".into(),
+         base_url: base_url.clone(),
+         api_key_header: Some("api-key".into()),
+         models_endpoint: Some("/openai/models".into()),
+         chat_endpoint: None,
+      };
+      let result = validate(
+         &test_client(),
+         KeyTarget::Custom(&provider),
+         "gw-test",
+         &RetryPolicy::NONE,
+         None,
+      )
+      .await
+      .unwrap();
+      assert!(result.valid);
+      let request = server.await.unwrap().to_ascii_lowercase();
+      assert!(request.starts_with("get /openai/models "));
+      assert!(request.contains("api-key: gw-test"));
+      assert!(!request.contains("authorization"));
+   }
+}
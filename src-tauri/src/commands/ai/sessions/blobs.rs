@@ -0,0 +1,200 @@
+use super::{error::SessionError, store::write_atomic};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+   collections::HashSet,
+   fs,
+   io::ErrorKind,
+   path::{Path, PathBuf},
+};
+
+/// Directory of the sessions directory attachment snapshots are kept in, each in a file named by
+/// the SHA-256 of its content so a file attached to many messages is stored once
+const BLOBS_DIR: &str = "blobs";
+/// Longest snapshot kept of an attached file. Longer files are cut here and marked as cut.
+pub(super) const MAX_SNAPSHOT_BYTES: usize = 256 * 1024;
+
+/// What `gc_session_blobs` removed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionBlobsCollected {
+   pub removed: usize,
+   pub freed_bytes: u64,
+   /// Snapshots still referenced by a session
+   pub kept: usize,
+}
+
+fn blobs_dir(dir: &Path) -> PathBuf {
+   dir.join(BLOBS_DIR)
+}
+
+/// Whether `name` can be a blob's, which keeps hashes read from session files inside the blobs
+/// directory
+fn is_blob_name(name: &str) -> bool {
+   name.len() == 64
+      && name
+         .bytes()
+         .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+pub(super) fn content_hash(content: &str) -> String {
+   format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// The snapshot kept of `content`, and whether it was cut to fit `MAX_SNAPSHOT_BYTES`. A cut
+/// snapshot ends in a line saying how much of the file it holds.
+pub(super) fn snapshot(content: &str) -> (String, bool) {
+   if content.len() <= MAX_SNAPSHOT_BYTES {
+      return (content.to_string(), false);
+   }
+   let mut end = MAX_SNAPSHOT_BYTES;
+   while !content.is_char_boundary(end) {
+      end -= 1;
+   }
+   let snapshot = format!(
+      "{}\n[Snapshot truncated: {} of {} bytes kept]\n",
+      &content[..end],
+      end,
+      content.len()
+   );
+   (snapshot, true)
+}
+
+/// Store `snapshot` in the blobs of `dir` unless it is there already, returning its hash
+pub(super) fn put(dir: &Path, snapshot: &str) -> Result<String, SessionError> {
+   let blobs = blobs_dir(dir);
+   let hash = content_hash(snapshot);
+   let path = blobs.join(&hash);
+   if path.is_file() {
+      return Ok(hash);
+   }
+   fs::create_dir_all(&blobs).map_err(|e| SessionError::io(&blobs, e))?;
+   write_atomic(&path, snapshot)?;
+   Ok(hash)
+}
+
+/// The snapshot with `hash` in the blobs of `dir`, or `None` if there is none
+pub(super) fn get(dir: &Path, hash: &str) -> Result<Option<String>, SessionError> {
+   if !is_blob_name(hash) {
+      return Ok(None);
+   }
+   let path = blobs_dir(dir).join(hash);
+   match fs::read_to_string(&path) {
+      Ok(content) => Ok(Some(content)),
+      Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(SessionError::io(&path, e)),
+   }
+}
+
+/// Every run of 64 hex digits in `text`, which includes every blob a session file refers to
+/// however the file is laid out and even if part of it is corrupt
+pub(super) fn hashes_in(text: &str, hashes: &mut HashSet<String>) {
+   let is_hex = |b: u8| b.is_ascii_digit() || (b'a'..=b'f').contains(&b);
+   let bytes = text.as_bytes();
+   let mut start = 0;
+   while start < bytes.len() {
+      if !is_hex(bytes[start]) {
+         start += 1;
+         continue;
+      }
+      let end = bytes[start..]
+         .iter()
+         .position(|b| !is_hex(*b))
+         .map_or(bytes.len(), |len| start + len);
+      if end - start == 64 {
+         hashes.insert(text[start..end].to_string());
+      }
+      start = end;
+   }
+}
+
+/// Remove the blobs of `dir` not in `referenced`
+pub(super) fn collect(
+   dir: &Path,
+   referenced: &HashSet<String>,
+) -> Result<SessionBlobsCollected, SessionError> {
+   let blobs = blobs_dir(dir);
+   let entries = match fs::read_dir(&blobs) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(SessionBlobsCollected::default()),
+      Err(e) => return Err(SessionError::io(&blobs, e)),
+   };
+   let mut collected = SessionBlobsCollected::default();
+   for entry in entries {
+      let entry = entry.map_err(|e| SessionError::io(&blobs, e))?;
+      let name = entry.file_name().to_string_lossy().into_owned();
+      // Leftovers of interrupted writes go too
+      if is_blob_name(&name) && referenced.contains(&name) {
+         collected.kept += 1;
+         continue;
+      }
+      let bytes = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+      match fs::remove_file(entry.path()) {
+         Ok(()) => {
+            collected.removed += 1;
+            collected.freed_bytes += bytes;
+         }
+         Err(e) => log::warn!("Failed to remove {}: {}", entry.path().display(), e),
+      }
+   }
+   Ok(collected)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_snapshots_are_deduplicated() {
+      let dir = tempfile::tempdir().unwrap();
+      let hash = put(dir.path(), "fn main() {}\n").unwrap();
+      assert_eq!(put(dir.path(), "fn main() {}\n").unwrap(), hash);
+      assert_eq!(fs::read_dir(blobs_dir(dir.path())).unwrap().count(), 1);
+      assert_eq!(
+         get(dir.path(), &hash).unwrap().as_deref(),
+         Some("fn main() {}\n")
+      );
+      assert_eq!(get(dir.path(), "../index.jsonl").unwrap(), None);
+   }
+
+   #[test]
+   fn test_long_snapshots_are_marked_truncated() {
+      let (short, truncated) = snapshot("short");
+      assert_eq!((short.as_str(), truncated), ("short", false));
+
+      // A multibyte character straddling the limit isn't split
+      let content = format!("{}é{}", "a".repeat(MAX_SNAPSHOT_BYTES - 1), "b".repeat(10));
+      let (snapshot, truncated) = snapshot(&content);
+      assert!(truncated);
+      assert!(snapshot.starts_with(&"a".repeat(MAX_SNAPSHOT_BYTES - 1)));
+      assert!(snapshot.ends_with(&format!(
+         "a\n[Snapshot truncated: {} of {} bytes kept]\n",
+         MAX_SNAPSHOT_BYTES - 1,
+         content.len()
+      )));
+   }
+
+   #[test]
+   fn test_collects_unreferenced_blobs() {
+      let dir = tempfile::tempdir().unwrap();
+      let kept = put(dir.path(), "kept").unwrap();
+      let dropped = put(dir.path(), "dropped").unwrap();
+      fs::write(blobs_dir(dir.path()).join(".tmp-write"), "x").unwrap();
+
+      let mut referenced = HashSet::new();
+      hashes_in(
+         &format!(
+            "{{\"contentHash\":\"{}\"}}\nnot json {}x",
+            kept,
+            "f".repeat(65)
+         ),
+         &mut referenced,
+      );
+      assert_eq!(referenced, HashSet::from([kept.clone()]));
+
+      let collected = collect(dir.path(), &referenced).unwrap();
+      assert_eq!((collected.removed, collected.kept), (2, 1));
+      assert!(get(dir.path(), &kept).unwrap().is_some());
+      assert!(get(dir.path(), &dropped).unwrap().is_none());
+   }
+}
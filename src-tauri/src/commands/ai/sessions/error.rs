@@ -0,0 +1,149 @@
+use serde::Serialize;
+use std::path::Path;
+use thiserror::Error;
+
+/// Errors returned by the session store commands.
+///
+/// Serialized to the frontend as a tagged object such as
+/// `{ "type": "unknownSession", "sessionId": "..." }`.
+#[derive(Debug, Clone, Error, Serialize)]
+#[serde(
+   tag = "type",
+   rename_all = "camelCase",
+   rename_all_fields = "camelCase"
+)]
+pub enum SessionError {
+   /// The session id isn't one Athas generates
+   #[error("Invalid session id '{session_id}'")]
+   InvalidSessionId { session_id: String },
+
+   /// No session with this id is stored
+   #[error("No session '{session_id}'")]
+   UnknownSession { session_id: String },
+
+   /// The message has no attachment at this path
+   #[error("Message {message_index} of session '{session_id}' has no attachment '{path}'")]
+   UnknownAttachment {
+      session_id: String,
+      message_index: usize,
+      path: String,
+   },
+
+   /// The snapshot of an attachment wasn't saved, or has since been removed
+   #[error("The snapshot of '{path}' is not stored")]
+   AttachmentNotStored { path: String, content_hash: String },
+
+   /// A session file exists but can't be parsed
+   #[error("Session file {path} is corrupt: {message}")]
+   Corrupt { path: String, message: String },
+
+   /// A session file was written by a newer Athas
+   #[error("Session file {path} has schema version {version}, newer than this Athas supports")]
+   UnsupportedVersion { path: String, version: u32 },
+
+   /// Reading or writing a session file failed
+   #[error("Failed to access {path}: {message}")]
+   Io { path: String, message: String },
+
+   /// The pricing table to estimate costs with can't be read
+   #[error("Failed to load model prices: {message}")]
+   Pricing { message: String },
+
+   /// There is no home directory to keep sessions in
+   #[error("Could not determine home directory")]
+   HomeDirUnavailable,
+}
+
+impl SessionError {
+   pub(super) fn io(path: &Path, error: std::io::Error) -> Self {
+      SessionError::Io {
+         path: path.display().to_string(),
+         message: error.to_string(),
+      }
+   }
+
+   pub(super) fn corrupt(path: &Path, message: impl ToString) -> Self {
+      SessionError::Corrupt {
+         path: path.display().to_string(),
+         message: message.to_string(),
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   #[test]
+   fn test_serialized_shape() {
+      let cases = [
+         (
+            SessionError::InvalidSessionId {
+               session_id: "../x".into(),
+            },
+            json!({ "type": "invalidSessionId", "sessionId": "../x" }),
+         ),
+         (
+            SessionError::UnknownSession {
+               session_id: "7f1c".into(),
+            },
+            json!({ "type": "unknownSession", "sessionId": "7f1c" }),
+         ),
+         (
+            SessionError::UnknownAttachment {
+               session_id: "7f1c".into(),
+               message_index: 2,
+               path: "src/a.rs".into(),
+            },
+            json!({
+               "type": "unknownAttachment",
+               "sessionId": "7f1c",
+               "messageIndex": 2,
+               "path": "src/a.rs",
+            }),
+         ),
+         (
+            SessionError::AttachmentNotStored {
+               path: "src/a.rs".into(),
+               content_hash: "ab12".into(),
+            },
+            json!({ "type": "attachmentNotStored", "path": "src/a.rs", "contentHash": "ab12" }),
+         ),
+         (
+            SessionError::Corrupt {
+               path: "/s.json".into(),
+               message: "expected value".into(),
+            },
+            json!({ "type": "corrupt", "path": "/s.json", "message": "expected value" }),
+         ),
+         (
+            SessionError::UnsupportedVersion {
+               path: "/s.json".into(),
+               version: 9,
+            },
+            json!({ "type": "unsupportedVersion", "path": "/s.json", "version": 9 }),
+         ),
+         (
+            SessionError::Io {
+               path: "/s.json".into(),
+               message: "denied".into(),
+            },
+            json!({ "type": "io", "path": "/s.json", "message": "denied" }),
+         ),
+         (
+            SessionError::Pricing {
+               message: "bad toml".into(),
+            },
+            json!({ "type": "pricing", "message": "bad toml" }),
+         ),
+         (
+            SessionError::HomeDirUnavailable,
+            json!({ "type": "homeDirUnavailable" }),
+         ),
+      ];
+      for (error, expected) in cases {
+         assert_eq!(serde_json::to_value(&error).unwrap(), expected);
+      }
+   }
+}
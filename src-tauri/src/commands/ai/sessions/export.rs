@@ -0,0 +1,373 @@
+use super::{
+   blobs,
+   error::SessionError,
+   schema::{Session, SessionMessage, SessionRole},
+   store::{SessionStore, blocking, write_atomic},
+};
+use crate::commands::ai::agent_settings::redact_secrets;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+   collections::HashMap,
+   path::{Path, PathBuf},
+};
+use tauri::{State, command};
+
+/// What `export_session` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionExportFormat {
+   /// A transcript for reading, with a section per message
+   Markdown,
+   /// The session as stored, in its schema
+   Json,
+}
+
+/// What an export wrote
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionExport {
+   pub path: String,
+   pub bytes: usize,
+   /// Secrets replaced by `[redacted]`
+   pub redacted: usize,
+}
+
+/// Text with its secrets redacted, counting them in `redacted`
+fn redact(text: &str, redacted: &mut usize) -> String {
+   let (text, count) = redact_secrets(text);
+   *redacted += count;
+   text
+}
+
+fn redact_strings(value: &mut Value, redacted: &mut usize) {
+   match value {
+      Value::String(text) => *text = redact(text, redacted),
+      Value::Array(items) => items
+         .iter_mut()
+         .for_each(|item| redact_strings(item, redacted)),
+      Value::Object(map) => map
+         .values_mut()
+         .for_each(|child| redact_strings(child, redacted)),
+      _ => {}
+   }
+}
+
+/// Snapshots of attached files by content hash
+type Snapshots = HashMap<String, String>;
+
+/// The stored snapshots of the files attached to `session`
+fn snapshots(store: &SessionStore, session: &Session) -> Result<Snapshots, SessionError> {
+   let dir = store.dir()?;
+   let _guard = store.lock();
+   let mut snapshots = Snapshots::new();
+   let attachments = session
+      .messages
+      .iter()
+      .flat_map(|message| &message.attachments)
+      .filter(|attachment| attachment.stored);
+   for attachment in attachments {
+      if !snapshots.contains_key(&attachment.content_hash)
+         && let Some(content) = blobs::get(dir, &attachment.content_hash)?
+      {
+         snapshots.insert(attachment.content_hash.clone(), content);
+      }
+   }
+   Ok(snapshots)
+}
+
+/// The session in its stored schema. With `snapshots`, each attachment also has the `content`
+/// of its snapshot.
+fn to_json(session: &Session, snapshots: Option<&Snapshots>) -> Result<(String, usize), String> {
+   let mut value = serde_json::to_value(session).map_err(|e| e.to_string())?;
+   if let Some(snapshots) = snapshots
+      && let Some(Value::Array(messages)) = value.get_mut("messages")
+   {
+      let attachments = messages
+         .iter_mut()
+         .filter_map(|message| message.get_mut("attachments")?.as_array_mut())
+         .flatten()
+         .filter_map(Value::as_object_mut);
+      for attachment in attachments {
+         let content = attachment
+            .get("contentHash")
+            .and_then(Value::as_str)
+            .and_then(|hash| snapshots.get(hash));
+         if let Some(content) = content {
+            attachment.insert("content".into(), content.clone().into());
+         }
+      }
+   }
+   let mut redacted = 0;
+   redact_strings(&mut value, &mut redacted);
+   let content = serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?;
+   Ok((content, redacted))
+}
+
+fn role_label(role: SessionRole) -> &'static str {
+   match role {
+      SessionRole::User => "User",
+      SessionRole::Assistant => "Assistant",
+      SessionRole::System => "System",
+      SessionRole::Tool => "Tool",
+   }
+}
+
+/// A fence longer than any run of backticks in `content`, so the content can't close it
+fn fence_for(content: &str) -> String {
+   let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+   "`".repeat(longest.max(2) + 1)
+}
+
+fn render_message(
+   markdown: &mut String,
+   message: &SessionMessage,
+   snapshots: Option<&Snapshots>,
+   redacted: &mut usize,
+) {
+   let content = redact(&message.content, redacted);
+   let time = message.timestamp.format("%Y-%m-%d %H:%M UTC");
+   // Tool calls are folded away so the conversation reads on without them
+   if message.role == SessionRole::Tool {
+      markdown.push_str(&format!(
+         "<details>\n<summary>Tool · {}</summary>\n\n{}\n\n</details>\n\n",
+         time,
+         content.trim_end()
+      ));
+   } else {
+      let model = message
+         .model
+         .as_deref()
+         .map(|model| format!(" · {}", model))
+         .unwrap_or_default();
+      markdown.push_str(&format!(
+         "## {}\n\n_{}{}_\n\n{}\n\n",
+         role_label(message.role),
+         time,
+         model,
+         content.trim_end()
+      ));
+   }
+
+   for attachment in &message.attachments {
+      let snapshot = snapshots.map(|snapshots| snapshots.get(&attachment.content_hash));
+      let content = match snapshot {
+         None => {
+            markdown.push_str(&format!("Attached `{}`\n\n", attachment.path));
+            continue;
+         }
+         Some(None) => {
+            markdown.push_str(&format!(
+               "Attached `{}` (snapshot not stored)\n\n",
+               attachment.path
+            ));
+            continue;
+         }
+         Some(Some(content)) => redact(content, redacted),
+      };
+      let fence = fence_for(&content);
+      let language = Path::new(&attachment.path)
+         .extension()
+         .and_then(|ext| ext.to_str())
+         .unwrap_or_default();
+      markdown.push_str(&format!(
+         "<details>\n<summary>Attached <code>{}</code></summary>\n\n{}{}\n{}\n{}\n\n</details>\n\n",
+         attachment.path,
+         fence,
+         language,
+         content.trim_end_matches('\n'),
+         fence
+      ));
+   }
+}
+
+/// The session as a Markdown transcript: a heading per message labelled with its role, message
+/// content as written so fenced code stays intact, and tool calls in collapsible details.
+/// Attachments are listed by path, with the contents of their `snapshots` if given.
+fn to_markdown(session: &Session, snapshots: Option<&Snapshots>) -> (String, usize) {
+   let meta = &session.meta;
+   let mut redacted = 0;
+   let title = meta
+      .title
+      .as_deref()
+      .map(|title| redact(title, &mut redacted))
+      .unwrap_or_else(|| format!("Session {}", meta.id));
+   let mut markdown = format!("# {}\n\n- Agent: {}\n", title, meta.agent_id);
+   if let Some(model) = &meta.model {
+      markdown.push_str(&format!("- Model: {}\n", model));
+   }
+   if let Some(workspace) = &meta.workspace {
+      markdown.push_str(&format!("- Workspace: `{}`\n", workspace));
+   }
+   markdown.push_str(&format!(
+      "- Started: {}\n- Messages: {}\n- Tokens: {} in, {} out\n\n",
+      meta.created_at.format("%Y-%m-%d %H:%M UTC"),
+      meta.message_count,
+      meta.tokens.input,
+      meta.tokens.output
+   ));
+   for message in &session.messages {
+      render_message(&mut markdown, message, snapshots, &mut redacted);
+   }
+   (markdown, redacted)
+}
+
+/// Write a session to `path` as Markdown or JSON, replacing the file if it exists. Snapshots of
+/// attached files are left out unless `include_attachments` is set, and anything the secret
+/// scanner flags is replaced by `[redacted]`.
+#[command]
+pub async fn export_session(
+   store: State<'_, SessionStore>,
+   session_id: String,
+   format: SessionExportFormat,
+   path: String,
+   include_attachments: bool,
+) -> Result<SessionExport, SessionError> {
+   let target = PathBuf::from(&path);
+   if !target.is_absolute() {
+      return Err(SessionError::Io {
+         path,
+         message: "export path must be absolute".to_string(),
+      });
+   }
+   let export = blocking(&store, move |store| {
+      let session = store.get(&session_id)?;
+      let snapshots = match include_attachments {
+         true => Some(snapshots(&store, &session)?),
+         false => None,
+      };
+      let (content, redacted) = match format {
+         SessionExportFormat::Markdown => to_markdown(&session, snapshots.as_ref()),
+         SessionExportFormat::Json => to_json(&session, snapshots.as_ref())
+            .map_err(|message| SessionError::corrupt(&target, message))?,
+      };
+      write_atomic(&target, &content)?;
+      Ok(SessionExport {
+         path: target.display().to_string(),
+         bytes: content.len(),
+         redacted,
+      })
+   })
+   .await?;
+   log::info!(
+      "Exported session to {} ({} secrets redacted)",
+      export.path,
+      export.redacted
+   );
+   Ok(export)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::sessions::schema::{SessionAttachment, SessionMeta, TokenCounts};
+   use chrono::{TimeZone, Utc};
+
+   const SNAPSHOT: &str = "// uses ``` in a comment\nfn watch() {}\n";
+
+   fn snapshots() -> Snapshots {
+      Snapshots::from([(blobs::content_hash(SNAPSHOT), SNAPSHOT.to_string())])
+   }
+
+   fn session() -> Session {
+      let at = Utc.with_ymd_and_hms(2025, 10, 1, 12, 0, 0).unwrap();
+      let message = |role, content: &str| SessionMessage {
+         role,
+         content: content.into(),
+         timestamp: at,
+         tokens: None,
+         model: None,
+         attachments: Vec::new(),
+      };
+      Session {
+         meta: SessionMeta {
+            version: 2,
+            id: "0b6a2a4e-5d3c-4f0e-9c43-3f1f1b0a8e21".into(),
+            agent_id: "claude-code".into(),
+            workspace: Some("/home/me/athas".into()),
+            model: Some("claude-sonnet-4-5".into()),
+            title: Some("Watcher fires twice".into()),
+            pinned: false,
+            tags: Vec::new(),
+            created_at: at,
+            updated_at: at,
+            message_count: 3,
+            tokens: TokenCounts {
+               input: 120,
+               output: 40,
+            },
+            context_tokens: 160,
+         },
+         messages: vec![
+            SessionMessage {
+               attachments: vec![SessionAttachment {
+                  path: "src/watcher.rs".into(),
+                  content_hash: blobs::content_hash(SNAPSHOT),
+                  byte_len: SNAPSHOT.len() as u64,
+                  stored: true,
+                  truncated: false,
+               }],
+               ..message(
+                  SessionRole::User,
+                  "Why? My key is sk-ant-REDACTED",
+               )
+            },
+            message(SessionRole::Tool, "read_file src/watcher.rs"),
+            SessionMessage {
+               model: Some("claude-sonnet-4-5".into()),
+               ..message(
+                  SessionRole::Assistant,
+                  "Debounce it:\n\n```rust\nlet d = debounce(500);\n```\n",
+               )
+            },
+         ],
+      }
+   }
+
+   #[test]
+   fn test_markdown_transcript() {
+      let (markdown, redacted) = to_markdown(&session(), Some(&snapshots()));
+      assert_eq!(redacted, 1);
+      assert!(markdown.starts_with("# Watcher fires twice\n\n- Agent: claude-code\n"));
+      assert!(markdown.contains("## User\n\n_2025-10-01 12:00 UTC_\n\nWhy? My key is [redacted]"));
+      assert!(markdown.contains(
+         "<details>\n<summary>Tool · 2025-10-01 12:00 UTC</summary>\n\nread_file src/watcher.rs"
+      ));
+      assert!(markdown.contains("```rust\nlet d = debounce(500);\n```"));
+      // The attachment's own backticks can't close its fence
+      assert!(markdown.contains("````rs\n// uses ``` in a comment\nfn watch() {}\n````"));
+
+      let (markdown, _) = to_markdown(&session(), None);
+      assert!(markdown.contains("Attached `src/watcher.rs`\n"));
+      assert!(!markdown.contains("fn watch()"));
+      let (markdown, _) = to_markdown(&session(), Some(&Snapshots::new()));
+      assert!(markdown.contains("Attached `src/watcher.rs` (snapshot not stored)"));
+   }
+
+   #[test]
+   fn test_json_keeps_schema_without_attachment_content() {
+      let (json, redacted) = to_json(&session(), None).unwrap();
+      assert_eq!(redacted, 1);
+      let value: Value = serde_json::from_str(&json).unwrap();
+      assert_eq!(value["agentId"], "claude-code");
+      assert_eq!(value["messages"][0]["attachments"][0]["stored"], true);
+      assert!(
+         value["messages"][0]["attachments"][0]
+            .get("content")
+            .is_none()
+      );
+      assert!(!json.contains("0123456789abcdef"));
+
+      let (json, _) = to_json(&session(), Some(&snapshots())).unwrap();
+      let value: Value = serde_json::from_str(&json).unwrap();
+      assert_eq!(value["messages"][0]["attachments"][0]["content"], SNAPSHOT);
+      let restored: Session = Session {
+         meta: serde_json::from_value(value.clone()).unwrap(),
+         messages: serde_json::from_value(value["messages"].clone()).unwrap(),
+      };
+      assert_eq!(
+         restored.messages[0].attachments,
+         session().messages[0].attachments
+      );
+   }
+}
@@ -0,0 +1,173 @@
+use super::{error::SessionError, schema::SessionMeta, store::write_atomic};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+   collections::HashMap,
+   fs,
+   io::{ErrorKind, Write},
+   path::{Path, PathBuf},
+};
+
+/// Index of session summaries in the sessions directory, one entry per line
+const INDEX_FILE: &str = "index.jsonl";
+/// Lines the index may grow to before it is rewritten with one line per session
+const COMPACT_MIN_LINES: usize = 512;
+
+/// What `list_sessions` returns for each session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSummary {
+   pub id: String,
+   pub title: Option<String>,
+   pub agent_id: String,
+   #[serde(default)]
+   pub pinned: bool,
+   #[serde(default)]
+   pub tags: Vec<String>,
+   pub workspace: Option<String>,
+   pub message_count: usize,
+   pub created_at: DateTime<Utc>,
+   /// When the last message was appended
+   pub last_activity: DateTime<Utc>,
+   pub total_tokens: u64,
+}
+
+impl SessionSummary {
+   pub(super) fn of(meta: &SessionMeta) -> Self {
+      Self {
+         id: meta.id.clone(),
+         title: meta.title.clone(),
+         agent_id: meta.agent_id.clone(),
+         pinned: meta.pinned,
+         tags: meta.tags.clone(),
+         workspace: meta.workspace.clone(),
+         message_count: meta.message_count,
+         created_at: meta.created_at,
+         last_activity: meta.updated_at,
+         total_tokens: meta.tokens.input + meta.tokens.output,
+      }
+   }
+}
+
+/// A line of the index. Later lines for a session replace earlier ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum IndexEntry {
+   Put(SessionSummary),
+}
+
+fn index_path(dir: &Path) -> PathBuf {
+   dir.join(INDEX_FILE)
+}
+
+/// The summaries in the index of `dir` by session id, or `None` when there is no index yet.
+/// Lines that don't parse are skipped, as the session files can always rebuild the index.
+pub(super) fn read(dir: &Path) -> Result<Option<HashMap<String, SessionSummary>>, SessionError> {
+   let path = index_path(dir);
+   let content = match fs::read_to_string(&path) {
+      Ok(content) => content,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+      Err(e) => return Err(SessionError::io(&path, e)),
+   };
+   let mut summaries = HashMap::new();
+   let mut lines = 0;
+   for line in content.lines().filter(|line| !line.trim().is_empty()) {
+      lines += 1;
+      match serde_json::from_str(line) {
+         Ok(IndexEntry::Put(summary)) => {
+            summaries.insert(summary.id.clone(), summary);
+         }
+         Err(e) => log::warn!("Skipping line of {}: {}", path.display(), e),
+      }
+   }
+   if lines > COMPACT_MIN_LINES && lines > summaries.len() * 2 {
+      write(dir, summaries.values())?;
+   }
+   Ok(Some(summaries))
+}
+
+/// Record `summary` at the end of the index of `dir`
+pub(super) fn append(dir: &Path, summary: &SessionSummary) -> Result<(), SessionError> {
+   let path = index_path(dir);
+   let mut line = serde_json::to_string(&IndexEntry::Put(summary.clone()))
+      .map_err(|e| SessionError::corrupt(&path, e))?;
+   line.push('\n');
+   fs::File::options()
+      .create(true)
+      .append(true)
+      .open(&path)
+      .and_then(|mut index| index.write_all(line.as_bytes()))
+      .map_err(|e| SessionError::io(&path, e))
+}
+
+/// Remove the index of `dir`, so it is rebuilt from the session files
+pub(super) fn remove(dir: &Path) {
+   let path = index_path(dir);
+   if let Err(e) = fs::remove_file(&path)
+      && e.kind() != ErrorKind::NotFound
+   {
+      log::warn!("Failed to remove {}: {}", path.display(), e);
+   }
+}
+
+/// Replace the index of `dir` with one line for each of `summaries`
+pub(super) fn write<'a>(
+   dir: &Path,
+   summaries: impl IntoIterator<Item = &'a SessionSummary>,
+) -> Result<(), SessionError> {
+   let path = index_path(dir);
+   let mut content = String::new();
+   for summary in summaries {
+      let line = serde_json::to_string(&IndexEntry::Put(summary.clone()))
+         .map_err(|e| SessionError::corrupt(&path, e))?;
+      content.push_str(&line);
+      content.push('\n');
+   }
+   write_atomic(&path, &content)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use chrono::TimeZone;
+
+   fn summary(id: &str, message_count: usize) -> SessionSummary {
+      SessionSummary {
+         id: id.into(),
+         title: None,
+         agent_id: "claude-code".into(),
+         pinned: false,
+         tags: Vec::new(),
+         workspace: None,
+         message_count,
+         created_at: Utc.with_ymd_and_hms(2025, 10, 1, 12, 0, 0).unwrap(),
+         last_activity: Utc.with_ymd_and_hms(2025, 10, 1, 12, 0, 0).unwrap(),
+         total_tokens: 0,
+      }
+   }
+
+   #[test]
+   fn test_later_lines_win_and_index_compacts() {
+      let dir = tempfile::tempdir().unwrap();
+      assert_eq!(read(dir.path()).unwrap(), None);
+
+      for count in 0..=COMPACT_MIN_LINES {
+         append(dir.path(), &summary("a", count)).unwrap();
+      }
+      append(dir.path(), &summary("b", 1)).unwrap();
+      fs::OpenOptions::new()
+         .append(true)
+         .open(index_path(dir.path()))
+         .unwrap()
+         .write_all(b"{\"op\":\"put\",\"id\"\n")
+         .unwrap();
+
+      let summaries = read(dir.path()).unwrap().unwrap();
+      assert_eq!(summaries.len(), 2);
+      assert_eq!(summaries["a"].message_count, COMPACT_MIN_LINES);
+      // Rewritten with a line per session
+      let content = fs::read_to_string(index_path(dir.path())).unwrap();
+      assert_eq!(content.lines().count(), 2);
+      assert_eq!(read(dir.path()).unwrap().unwrap(), summaries);
+   }
+}
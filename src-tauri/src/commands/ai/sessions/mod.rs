@@ -0,0 +1,17 @@
+mod blobs;
+mod error;
+mod export;
+mod index;
+mod prune;
+mod schema;
+mod store;
+mod title;
+mod usage;
+
+pub use export::export_session;
+pub use prune::{SESSIONS_PRUNED_EVENT, prune_sessions, prune_sessions_on_startup};
+pub use store::{
+   SessionStore, append_session_message, create_session, gc_session_blobs, get_session,
+   get_session_attachment, list_sessions, update_session_meta,
+};
+pub use usage::{get_usage_stats, reset_usage_stats};
@@ -0,0 +1,406 @@
+use super::{
+   error::SessionError,
+   index::{self, SessionSummary},
+   store::{SessionListWarning, SessionStore, blocking, summaries, write_atomic},
+};
+use crate::commands::ai::agent_settings::{AgentSettingsLocks, read_athas_setting};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::{fs, io::ErrorKind, path::Path};
+use tauri::{State, command};
+
+const MAX_AGE_DAYS_KEY: &str = "ai.sessions.maxAgeDays";
+const MAX_TOTAL_MB_KEY: &str = "ai.sessions.maxTotalMb";
+/// Event emitted with a `SessionPruneReport` when pruning on startup removed sessions
+pub const SESSIONS_PRUNED_EVENT: &str = "sessions-pruned";
+/// How long after startup sessions are pruned, so it doesn't compete with the app loading
+const STARTUP_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+/// Startup pruning is skipped if sessions were pruned more recently than this
+const STARTUP_INTERVAL_HOURS: i64 = 24;
+/// When sessions were last pruned on startup, in the sessions directory
+const LAST_PRUNE_FILE: &str = "last-prune";
+
+/// How much session history to keep. Unset limits keep everything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Retention {
+   max_age_days: Option<u64>,
+   max_total_bytes: Option<u64>,
+}
+
+impl Retention {
+   fn is_unlimited(&self) -> bool {
+      self.max_age_days.is_none() && self.max_total_bytes.is_none()
+   }
+}
+
+/// The retention settings from their values under `ai.sessions`. Values that aren't positive
+/// integers are logged and treated as unset.
+fn retention(max_age_days: Option<Value>, max_total_mb: Option<Value>) -> Retention {
+   let positive = |key: &str, value: Option<Value>| {
+      let value = value?;
+      match value.as_u64().filter(|number| *number > 0) {
+         Some(number) => Some(number),
+         None => {
+            log::warn!("Ignoring {} = {}: expected a positive integer", key, value);
+            None
+         }
+      }
+   };
+   Retention {
+      max_age_days: positive(MAX_AGE_DAYS_KEY, max_age_days),
+      max_total_bytes: positive(MAX_TOTAL_MB_KEY, max_total_mb)
+         .map(|mb| mb.saturating_mul(1024 * 1024)),
+   }
+}
+
+async fn load_retention(locks: &AgentSettingsLocks) -> Retention {
+   let read = |key| async move {
+      read_athas_setting(locks, key).await.unwrap_or_else(|e| {
+         log::warn!("Ignoring {}: {}", key, e);
+         None
+      })
+   };
+   retention(read(MAX_AGE_DAYS_KEY).await, read(MAX_TOTAL_MB_KEY).await)
+}
+
+/// Why a session is pruned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionPruneReason {
+   /// Inactive for longer than `ai.sessions.maxAgeDays`
+   Age,
+   /// Among the oldest sessions while the total is over `ai.sessions.maxTotalMb`
+   DiskBudget,
+}
+
+/// A session pruning removes, or would remove on a dry run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrunedSession {
+   pub id: String,
+   pub title: Option<String>,
+   pub agent_id: String,
+   pub last_activity: DateTime<Utc>,
+   pub bytes: u64,
+   pub reason: SessionPruneReason,
+}
+
+/// What pruning removed, or would remove on a dry run
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionPruneReport {
+   pub dry_run: bool,
+   /// Oldest first
+   pub sessions: Vec<PrunedSession>,
+   pub freed_bytes: u64,
+   /// Size of the sessions that are kept
+   pub remaining_bytes: u64,
+   /// Sessions that couldn't be read or removed, and were kept
+   pub warnings: Vec<SessionListWarning>,
+}
+
+/// Bytes the files at `paths` take up, counting missing ones as empty
+fn size_of<'a>(paths: impl IntoIterator<Item = &'a Path>) -> u64 {
+   paths
+      .into_iter()
+      .filter_map(|path| fs::metadata(path).ok())
+      .map(|metadata| metadata.len())
+      .sum()
+}
+
+fn remove_file(path: &Path) -> Result<(), SessionError> {
+   match fs::remove_file(path) {
+      Ok(()) => Ok(()),
+      Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+      Err(e) => Err(SessionError::io(path, e)),
+   }
+}
+
+/// Remove the sessions `retention` doesn't keep as of `now`: first those inactive for longer than
+/// the maximum age, then the least recently active until the rest fit the disk budget. Pinned
+/// sessions are kept, though they count towards the budget. A dry run reports the sessions that
+/// would be removed and removes nothing.
+fn prune(
+   store: &SessionStore,
+   retention: Retention,
+   now: DateTime<Utc>,
+   dry_run: bool,
+) -> Result<SessionPruneReport, SessionError> {
+   let dir = store.dir()?;
+   let _guard = store.lock();
+   let mut warnings = Vec::new();
+   let mut sessions = Vec::new();
+   for summary in summaries(dir, &mut warnings)?.into_values() {
+      let paths = store.paths(&summary.id)?;
+      let bytes = size_of([paths.meta.as_path(), paths.log.as_path()]);
+      sessions.push((summary, paths, bytes));
+   }
+   sessions.sort_by(|(a, ..), (b, ..)| {
+      a.last_activity
+         .cmp(&b.last_activity)
+         .then_with(|| a.id.cmp(&b.id))
+   });
+
+   let cutoff = retention
+      .max_age_days
+      .map(|days| now - Duration::days(days.min(i32::MAX as u64) as i64));
+   let mut remaining_bytes: u64 = sessions.iter().map(|(.., bytes)| bytes).sum();
+   let mut kept: Vec<SessionSummary> = Vec::new();
+   let mut pruned = Vec::new();
+   let mut freed_bytes = 0;
+   for (summary, paths, bytes) in sessions {
+      let reason = if summary.pinned {
+         None
+      } else if cutoff.is_some_and(|cutoff| summary.last_activity < cutoff) {
+         Some(SessionPruneReason::Age)
+      } else if retention
+         .max_total_bytes
+         .is_some_and(|budget| remaining_bytes > budget)
+      {
+         Some(SessionPruneReason::DiskBudget)
+      } else {
+         None
+      };
+      let Some(reason) = reason else {
+         kept.push(summary);
+         continue;
+      };
+      if !dry_run
+         && let Err(error) = remove_file(&paths.log).and_then(|()| remove_file(&paths.meta))
+      {
+         log::warn!("Failed to prune session {}: {}", summary.id, error);
+         warnings.push(SessionListWarning {
+            path: paths.meta.display().to_string(),
+            error,
+         });
+         kept.push(summary);
+         continue;
+      }
+      remaining_bytes -= bytes;
+      freed_bytes += bytes;
+      pruned.push(PrunedSession {
+         id: summary.id,
+         title: summary.title,
+         agent_id: summary.agent_id,
+         last_activity: summary.last_activity,
+         bytes,
+         reason,
+      });
+   }
+
+   if !dry_run && !pruned.is_empty() {
+      index::write(dir, &kept)?;
+      log::info!(
+         "Pruned {} sessions, freeing {} bytes",
+         pruned.len(),
+         freed_bytes
+      );
+   }
+   Ok(SessionPruneReport {
+      dry_run,
+      sessions: pruned,
+      freed_bytes,
+      remaining_bytes,
+      warnings,
+   })
+}
+
+/// Whether startup pruning is due as of `now`, going by the last time it ran in `dir`
+fn startup_prune_due(dir: &Path, now: DateTime<Utc>) -> bool {
+   let last = fs::read_to_string(dir.join(LAST_PRUNE_FILE))
+      .ok()
+      .and_then(|content| DateTime::parse_from_rfc3339(content.trim()).ok());
+   last.is_none_or(|last| now - last.to_utc() >= Duration::hours(STARTUP_INTERVAL_HOURS))
+}
+
+/// Prune sessions by the retention settings shortly after startup, unless that happened within
+/// the last day. Returns what was removed, if anything, so the caller can emit
+/// `SESSIONS_PRUNED_EVENT`. Failures are logged.
+pub async fn prune_sessions_on_startup(
+   store: &SessionStore,
+   locks: &AgentSettingsLocks,
+) -> Option<SessionPruneReport> {
+   tokio::time::sleep(STARTUP_DELAY).await;
+   let retention = load_retention(locks).await;
+   if retention.is_unlimited() {
+      return None;
+   }
+   let result = blocking(store, move |store| {
+      let now = Utc::now();
+      let dir = store.dir()?;
+      if !dir.is_dir() || !startup_prune_due(dir, now) {
+         return Ok(None);
+      }
+      let report = prune(&store, retention, now, false)?;
+      write_atomic(&dir.join(LAST_PRUNE_FILE), &now.to_rfc3339())?;
+      Ok(Some(report))
+   })
+   .await;
+   match result {
+      Ok(report) => report.filter(|report| !report.sessions.is_empty()),
+      Err(e) => {
+         log::warn!("Failed to prune sessions: {}", e);
+         None
+      }
+   }
+}
+
+/// Remove the sessions beyond `ai.sessions.maxAgeDays` and `ai.sessions.maxTotalMb` in
+/// `~/.athas/settings.toml`, oldest first, keeping pinned sessions. With `dry_run` nothing is
+/// removed and the report lists exactly the sessions that would be, for the user to confirm.
+#[command]
+pub async fn prune_sessions(
+   store: State<'_, SessionStore>,
+   locks: State<'_, AgentSettingsLocks>,
+   dry_run: bool,
+) -> Result<SessionPruneReport, SessionError> {
+   let retention = load_retention(&locks).await;
+   blocking(&store, move |store| {
+      prune(&store, retention, Utc::now(), dry_run)
+   })
+   .await
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::commands::ai::{
+      sessions::{
+         schema::SessionRole,
+         store::{NewSessionMessage, SessionMetaUpdate},
+      },
+      token_count::TokenCounter,
+   };
+   use chrono::TimeZone;
+   use serde_json::json;
+
+   fn day(day: u32) -> DateTime<Utc> {
+      Utc.with_ymd_and_hms(2025, 10, day, 12, 0, 0).unwrap()
+   }
+
+   /// A store with a session last active on each of `days`, returning the store and their ids
+   fn store_with(dir: &Path, days: &[u32]) -> (SessionStore, Vec<String>) {
+      let store = SessionStore::in_dir(dir);
+      let counter = TokenCounter::new();
+      let mut ids = Vec::new();
+      let mut summaries = Vec::new();
+      for &active in days {
+         let meta = store.create("claude-code", None, None).unwrap();
+         let message = NewSessionMessage {
+            role: SessionRole::User,
+            content: "x".repeat(1000),
+            timestamp: None,
+            tokens: Some(Default::default()),
+            model: None,
+            attachments: Vec::new(),
+         };
+         let meta = store.append(&meta.id, message, &counter).unwrap();
+         summaries.push(SessionSummary {
+            last_activity: day(active),
+            ..SessionSummary::of(&meta)
+         });
+         ids.push(meta.id);
+      }
+      index::write(dir, &summaries).unwrap();
+      (store, ids)
+   }
+
+   fn pruned(report: &SessionPruneReport) -> Vec<(&str, SessionPruneReason)> {
+      report
+         .sessions
+         .iter()
+         .map(|session| (session.id.as_str(), session.reason))
+         .collect()
+   }
+
+   #[test]
+   fn test_retention_settings() {
+      assert!(retention(None, None).is_unlimited());
+      assert_eq!(
+         retention(Some(json!(30)), Some(json!(2))),
+         Retention {
+            max_age_days: Some(30),
+            max_total_bytes: Some(2 * 1024 * 1024),
+         }
+      );
+      assert!(retention(Some(json!(0)), Some(json!("lots"))).is_unlimited());
+   }
+
+   #[test]
+   fn test_prunes_by_age_then_budget() {
+      let dir = tempfile::tempdir().unwrap();
+      let (store, ids) = store_with(dir.path(), &[1, 2, 3, 4]);
+      let size = size_of([
+         store.paths(&ids[0]).unwrap().meta.as_path(),
+         store.paths(&ids[0]).unwrap().log.as_path(),
+      ]);
+      // The 1st is too old, and of the rest only two fit
+      let retention = Retention {
+         max_age_days: Some(10),
+         max_total_bytes: Some(size * 2 + size / 2),
+      };
+
+      let dry_run = prune(&store, retention, day(12), true).unwrap();
+      assert_eq!(
+         pruned(&dry_run),
+         [
+            (ids[0].as_str(), SessionPruneReason::Age),
+            (ids[1].as_str(), SessionPruneReason::DiskBudget),
+         ]
+      );
+      assert_eq!(dry_run.freed_bytes, size * 2);
+      assert!(store.get(&ids[0]).is_ok());
+
+      let report = prune(&store, retention, day(12), false).unwrap();
+      assert_eq!(pruned(&report), pruned(&dry_run));
+      assert_eq!(report.remaining_bytes, size * 2);
+      assert!(matches!(
+         store.get(&ids[0]),
+         Err(SessionError::UnknownSession { .. })
+      ));
+      let listed = summaries(dir.path(), &mut Vec::new()).unwrap();
+      assert_eq!(listed.len(), 2);
+      assert!(listed.contains_key(&ids[2]) && listed.contains_key(&ids[3]));
+   }
+
+   #[test]
+   fn test_pinned_sessions_are_kept() {
+      let dir = tempfile::tempdir().unwrap();
+      let (store, ids) = store_with(dir.path(), &[1, 2]);
+      let pinned = store
+         .update_meta(
+            &ids[0],
+            SessionMetaUpdate {
+               pinned: Some(true),
+               ..SessionMetaUpdate::default()
+            },
+         )
+         .unwrap();
+      assert!(pinned.pinned);
+      // Pinning re-indexes the session with the current time; put it back in the past
+      let mut listed = summaries(dir.path(), &mut Vec::new()).unwrap();
+      listed.get_mut(&ids[0]).unwrap().last_activity = day(1);
+      index::write(dir.path(), listed.values()).unwrap();
+
+      let retention = Retention {
+         max_age_days: Some(1),
+         max_total_bytes: Some(1),
+      };
+      let report = prune(&store, retention, day(20), false).unwrap();
+      assert_eq!(
+         pruned(&report),
+         [(ids[1].as_str(), SessionPruneReason::Age)]
+      );
+      assert!(store.get(&ids[0]).is_ok());
+   }
+
+   #[test]
+   fn test_startup_prune_is_debounced() {
+      let dir = tempfile::tempdir().unwrap();
+      assert!(startup_prune_due(dir.path(), day(1)));
+      fs::write(dir.path().join(LAST_PRUNE_FILE), day(1).to_rfc3339()).unwrap();
+      assert!(!startup_prune_due(dir.path(), day(1) + Duration::hours(23)));
+      assert!(startup_prune_due(dir.path(), day(2)));
+   }
+}
@@ -0,0 +1,246 @@
+use super::{blobs, error::SessionError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+/// Schema version new session files are written with. Bump it together with a migration in
+/// `MIGRATIONS` whenever the shape of the session or message records changes.
+pub const SESSION_SCHEMA_VERSION: u32 = 2;
+
+/// Upgrades a session's records from one schema version to the next: entry `n` takes version
+/// `n + 1` to `n + 2`. Applied to the raw JSON before it is parsed, so each one only needs to
+/// know the versions on either side of it. `dir` is the sessions directory.
+type Migration = fn(meta: &mut Value, messages: &mut [Value], dir: &Path);
+const MIGRATIONS: [Migration; SESSION_SCHEMA_VERSION as usize - 1] = [v1_to_v2];
+
+/// Version 1 kept attached files inline in the message; version 2 keeps them as snapshots in the
+/// blobs directory. A snapshot that can't be written is recorded as not stored.
+fn v1_to_v2(_meta: &mut Value, messages: &mut [Value], dir: &Path) {
+   let attachments = messages
+      .iter_mut()
+      .filter_map(|message| message.get_mut("attachments")?.as_array_mut())
+      .flatten()
+      .filter_map(Value::as_object_mut);
+   for attachment in attachments {
+      let Some(Value::String(content)) = attachment.remove("content") else {
+         continue;
+      };
+      let (snapshot, truncated) = blobs::snapshot(&content);
+      let stored = blobs::put(dir, &snapshot)
+         .inspect_err(|e| log::warn!("Failed to keep attachment snapshot: {}", e))
+         .is_ok();
+      attachment.insert("contentHash".into(), blobs::content_hash(&snapshot).into());
+      attachment.insert("byteLen".into(), content.len().into());
+      attachment.insert("stored".into(), stored.into());
+      attachment.insert("truncated".into(), truncated.into());
+   }
+}
+
+/// Who wrote a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionRole {
+   User,
+   Assistant,
+   System,
+   Tool,
+}
+
+/// Tokens a message took, as reported by the provider or counted by Athas
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenCounts {
+   #[serde(default)]
+   pub input: u64,
+   #[serde(default)]
+   pub output: u64,
+}
+
+impl TokenCounts {
+   pub fn add(&mut self, other: &TokenCounts) {
+      self.input += other.input;
+      self.output += other.output;
+   }
+}
+
+/// A file attached to a message as context, with a snapshot of it as it was then
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionAttachment {
+   pub path: String,
+   /// SHA-256 of the snapshot, which names its blob
+   pub content_hash: String,
+   /// Size of the file when it was attached
+   pub byte_len: u64,
+   /// Whether the snapshot was saved
+   pub stored: bool,
+   /// Whether the snapshot was cut short of the whole file
+   #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+   pub truncated: bool,
+}
+
+/// One message of a session, as stored in its message log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMessage {
+   pub role: SessionRole,
+   pub content: String,
+   pub timestamp: DateTime<Utc>,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub tokens: Option<TokenCounts>,
+   /// Model that wrote an assistant message
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   pub model: Option<String>,
+   #[serde(default, skip_serializing_if = "Vec::is_empty")]
+   pub attachments: Vec<SessionAttachment>,
+}
+
+/// Everything about a session but its messages, kept in `<id>.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMeta {
+   pub version: u32,
+   pub id: String,
+   pub agent_id: String,
+   /// Folder the session was started in
+   pub workspace: Option<String>,
+   /// Model of the latest assistant message, or the one the session was started with
+   pub model: Option<String>,
+   pub title: Option<String>,
+   /// Kept when sessions are pruned
+   #[serde(default)]
+   pub pinned: bool,
+   #[serde(default, skip_serializing_if = "Vec::is_empty")]
+   pub tags: Vec<String>,
+   pub created_at: DateTime<Utc>,
+   /// When the last message was appended
+   pub updated_at: DateTime<Utc>,
+   pub message_count: usize,
+   pub tokens: TokenCounts,
+   /// Tokens the conversation has grown to, taken as the input of an assistant message the
+   /// provider reported no usage for
+   #[serde(default)]
+   pub context_tokens: u64,
+}
+
+/// A session with its messages
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+   #[serde(flatten)]
+   pub meta: SessionMeta,
+   pub messages: Vec<SessionMessage>,
+}
+
+pub(super) fn schema_version(meta: &Value) -> Option<u32> {
+   meta.get("version")?.as_u64()?.try_into().ok()
+}
+
+/// Parse a session's raw records from `path`, bringing records of an older schema version up to
+/// the current one. Returns the session and whether it had to be upgraded.
+pub(super) fn upgrade(
+   path: &Path,
+   mut meta: Value,
+   mut messages: Vec<Value>,
+) -> Result<(Session, bool), SessionError> {
+   let version = schema_version(&meta)
+      .filter(|version| *version > 0)
+      .ok_or_else(|| SessionError::corrupt(path, "missing schema version"))?;
+   if version > SESSION_SCHEMA_VERSION {
+      return Err(SessionError::UnsupportedVersion {
+         path: path.display().to_string(),
+         version,
+      });
+   }
+   let dir = path.parent().unwrap_or(Path::new(""));
+   for migration in &MIGRATIONS[version as usize - 1..] {
+      migration(&mut meta, &mut messages, dir);
+   }
+   if let Value::Object(fields) = &mut meta {
+      fields.insert("version".into(), SESSION_SCHEMA_VERSION.into());
+   }
+
+   let meta: SessionMeta =
+      serde_json::from_value(meta).map_err(|e| SessionError::corrupt(path, e))?;
+   let messages = messages
+      .into_iter()
+      .map(serde_json::from_value)
+      .collect::<Result<Vec<SessionMessage>, _>>()
+      .map_err(|e| SessionError::corrupt(path, e))?;
+   Ok((Session { meta, messages }, version < SESSION_SCHEMA_VERSION))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use serde_json::json;
+
+   fn v1_meta() -> Value {
+      json!({
+         "version": 1,
+         "id": "0b6a2a4e-5d3c-4f0e-9c43-3f1f1b0a8e21",
+         "agentId": "claude-code",
+         "workspace": "/home/me/athas",
+         "model": "claude-sonnet-4-5",
+         "title": null,
+         "createdAt": "2025-10-01T12:00:00Z",
+         "updatedAt": "2025-10-01T12:01:00Z",
+         "messageCount": 1,
+         "tokens": { "input": 12, "output": 0 },
+      })
+   }
+
+   #[test]
+   fn test_v1_moves_attachments_to_snapshots() {
+      let dir = tempfile::tempdir().unwrap();
+      let message = json!({
+         "role": "user",
+         "content": "Why does the watcher fire twice?",
+         "timestamp": "2025-10-01T12:01:00Z",
+         "tokens": { "input": 12 },
+         "attachments": [{ "path": "src/watcher.rs", "content": "fn watch() {}\n" }],
+      });
+      let path = dir.path().join("s.json");
+      let (session, upgraded) = upgrade(&path, v1_meta(), vec![message]).unwrap();
+      assert!(upgraded);
+      assert_eq!(session.meta.version, SESSION_SCHEMA_VERSION);
+      assert_eq!(session.meta.agent_id, "claude-code");
+      assert_eq!(session.messages[0].role, SessionRole::User);
+      assert_eq!(
+         session.messages[0].tokens,
+         Some(TokenCounts {
+            input: 12,
+            output: 0
+         })
+      );
+      let attachment = &session.messages[0].attachments[0];
+      assert_eq!(attachment.path, "src/watcher.rs");
+      assert_eq!(attachment.byte_len, 14);
+      assert!(attachment.stored && !attachment.truncated);
+      assert_eq!(
+         blobs::get(dir.path(), &attachment.content_hash)
+            .unwrap()
+            .as_deref(),
+         Some("fn watch() {}\n")
+      );
+   }
+
+   #[test]
+   fn test_rejects_unknown_versions() {
+      let path = Path::new("/sessions/s.json");
+      let mut newer = v1_meta();
+      newer["version"] = json!(SESSION_SCHEMA_VERSION + 1);
+      assert!(matches!(
+         upgrade(path, newer, Vec::new()),
+         Err(SessionError::UnsupportedVersion { .. })
+      ));
+
+      let mut unversioned = v1_meta();
+      unversioned.as_object_mut().unwrap().remove("version");
+      assert!(matches!(
+         upgrade(path, unversioned, Vec::new()),
+         Err(SessionError::Corrupt { .. })
+      ));
+   }
+}
@@ -0,0 +1,1246 @@
+use super::{
+   blobs::{self, SessionBlobsCollected},
+   error::SessionError,
+   index::{self, SessionSummary},
+   schema::{
+      SESSION_SCHEMA_VERSION, Session, SessionAttachment, SessionMessage, SessionMeta, SessionRole,
+      TokenCounts, schema_version, upgrade,
+   },
+   title::title_in_background,
+   usage,
+};
+use crate::commands::ai::{agent_settings, token_count::TokenCounter};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+   collections::{HashMap, HashSet},
+   fs,
+   io::{BufRead, BufReader, ErrorKind, Write},
+   path::{Path, PathBuf},
+   sync::{Arc, Mutex},
+};
+use tauri::{AppHandle, State, command};
+use uuid::Uuid;
+
+/// Directory under the home directory sessions are kept in
+const SESSIONS_DIR: &str = ".athas/sessions";
+/// Extension of a session's metadata file, `<id>.json`
+const META_EXTENSION: &str = "json";
+/// Extension of a session's message log, `<id>.jsonl`, one message per line
+const LOG_EXTENSION: &str = "jsonl";
+/// Sessions `list_sessions` returns per page unless asked for another number
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+/// A message to append, as sent by the frontend
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewSessionMessage {
+   pub role: SessionRole,
+   pub content: String,
+   /// When the message was written, now if not given
+   #[serde(default)]
+   pub timestamp: Option<DateTime<Utc>>,
+   /// Usage the provider reported. Assistant messages without it are counted with the token
+   /// counter.
+   #[serde(default)]
+   pub tokens: Option<TokenCounts>,
+   #[serde(default)]
+   pub model: Option<String>,
+   /// Files attached to the message as context
+   #[serde(default)]
+   pub attachments: Vec<NewSessionAttachment>,
+}
+
+/// A file to attach to a message. Without `content` the file is read from `path`, which is
+/// taken relative to the session's workspace unless it is absolute.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewSessionAttachment {
+   pub path: String,
+   #[serde(default)]
+   pub content: Option<String>,
+}
+
+/// The snapshot of an attached file, as `get_session_attachment` returns it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionAttachmentContent {
+   pub path: String,
+   pub content: String,
+   /// Size of the whole file when it was attached
+   pub byte_len: u64,
+   /// Whether `content` was cut short, ending in a line saying so
+   pub truncated: bool,
+}
+
+/// Changes `update_session_meta` makes to a session. Unset fields are left as they are.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMetaUpdate {
+   /// An empty title clears it, and one is generated again after the next reply
+   #[serde(default)]
+   pub title: Option<String>,
+   #[serde(default)]
+   pub pinned: Option<bool>,
+   /// Replaces the session's tags. Blank and repeated tags are dropped.
+   #[serde(default)]
+   pub tags: Option<Vec<String>>,
+}
+
+/// Order `list_sessions` returns sessions in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionSort {
+   /// Most recently active first
+   #[default]
+   Recent,
+   /// Least recently active first
+   Oldest,
+}
+
+/// Which sessions `list_sessions` returns, and which page of them. Unset filters match every
+/// session.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionQuery {
+   #[serde(default)]
+   pub agent_id: Option<String>,
+   #[serde(default)]
+   pub workspace: Option<String>,
+   /// Matched case-insensitively against titles and message content
+   #[serde(default)]
+   pub text: Option<String>,
+   #[serde(default)]
+   pub sort: SessionSort,
+   #[serde(default)]
+   pub offset: usize,
+   /// 50 unless set, and at most 500
+   #[serde(default)]
+   pub limit: Option<usize>,
+}
+
+impl SessionQuery {
+   fn matches(&self, summary: &SessionSummary) -> bool {
+      self
+         .agent_id
+         .as_ref()
+         .is_none_or(|id| *id == summary.agent_id)
+         && self
+            .workspace
+            .as_ref()
+            .is_none_or(|workspace| summary.workspace.as_ref() == Some(workspace))
+   }
+
+   /// The text to search for, lowercased, if there is any
+   fn needle(&self) -> Option<String> {
+      self
+         .text
+         .as_deref()
+         .map(str::trim)
+         .filter(|text| !text.is_empty())
+         .map(str::to_lowercase)
+   }
+}
+
+/// A session file `list_sessions` couldn't read, and left out
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionListWarning {
+   pub path: String,
+   pub error: SessionError,
+}
+
+/// A page of sessions
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionPage {
+   pub sessions: Vec<SessionSummary>,
+   /// Sessions matching the query across all pages
+   pub total: usize,
+   pub warnings: Vec<SessionListWarning>,
+}
+
+/// Where a session's two files are
+pub(super) struct SessionPaths {
+   pub(super) meta: PathBuf,
+   pub(super) log: PathBuf,
+}
+
+/// Agent sessions on disk. Each session is a small metadata file, rewritten as messages are
+/// added, and a message log that is only ever appended to, so long sessions stay cheap to
+/// extend. An index of session summaries, also appended to, lets sessions be listed without
+/// opening each one.
+#[derive(Clone)]
+pub struct SessionStore {
+   dir: Option<PathBuf>,
+   /// Held while a session's files are read or written, so appends don't interleave
+   lock: Arc<Mutex<()>>,
+}
+
+impl SessionStore {
+   /// The store in `~/.athas/sessions`
+   pub fn new() -> Self {
+      Self {
+         dir: dirs::home_dir().map(|home| home.join(SESSIONS_DIR)),
+         lock: Arc::default(),
+      }
+   }
+
+   #[cfg(test)]
+   pub(super) fn in_dir(dir: &Path) -> Self {
+      Self {
+         dir: Some(dir.to_path_buf()),
+         lock: Arc::default(),
+      }
+   }
+
+   pub(super) fn dir(&self) -> Result<&Path, SessionError> {
+      self.dir.as_deref().ok_or(SessionError::HomeDirUnavailable)
+   }
+
+   pub(super) fn lock(&self) -> std::sync::MutexGuard<'_, ()> {
+      self.lock.lock().unwrap_or_else(|e| e.into_inner())
+   }
+
+   /// The files of session `id`. Ids are the UUIDs Athas generates, so nothing else can reach
+   /// outside the sessions directory.
+   pub(super) fn paths(&self, id: &str) -> Result<SessionPaths, SessionError> {
+      let valid = Uuid::parse_str(id).is_ok_and(|uuid| uuid.to_string() == id);
+      if !valid {
+         return Err(SessionError::InvalidSessionId {
+            session_id: id.to_string(),
+         });
+      }
+      let base = self.dir()?.join(id);
+      Ok(SessionPaths {
+         meta: base.with_extension(META_EXTENSION),
+         log: base.with_extension(LOG_EXTENSION),
+      })
+   }
+
+   /// Start an empty session
+   pub fn create(
+      &self,
+      agent_id: &str,
+      workspace: Option<String>,
+      model: Option<String>,
+   ) -> Result<SessionMeta, SessionError> {
+      let now = Utc::now();
+      let meta = SessionMeta {
+         version: SESSION_SCHEMA_VERSION,
+         id: Uuid::new_v4().to_string(),
+         agent_id: agent_id.to_string(),
+         workspace,
+         model,
+         title: None,
+         pinned: false,
+         tags: Vec::new(),
+         created_at: now,
+         updated_at: now,
+         message_count: 0,
+         tokens: TokenCounts::default(),
+         context_tokens: 0,
+      };
+      let paths = self.paths(&meta.id)?;
+      let dir = self.dir()?;
+      fs::create_dir_all(dir).map_err(|e| SessionError::io(dir, e))?;
+      let _guard = self.lock();
+      write_meta(&paths.meta, &meta)?;
+      record(dir, &meta);
+      Ok(meta)
+   }
+
+   /// Add a message to the end of session `id`, returning the session's updated metadata. The
+   /// tokens of assistant messages are recorded in the usage ledger, counted with `counter` when
+   /// the provider didn't report them.
+   pub fn append(
+      &self,
+      id: &str,
+      message: NewSessionMessage,
+      counter: &TokenCounter,
+   ) -> Result<SessionMeta, SessionError> {
+      let paths = self.paths(id)?;
+      let _guard = self.lock();
+      let mut meta = self.current_meta(id, &paths)?;
+
+      let is_assistant = message.role == SessionRole::Assistant;
+      let tokens = match message.tokens {
+         // Usage covers the whole conversation the provider was sent, and its reply
+         Some(tokens) if is_assistant => {
+            meta.context_tokens = tokens.input + tokens.output;
+            Some(tokens)
+         }
+         tokens => {
+            let model = message.model.as_deref().or(meta.model.as_deref());
+            let counted = count_tokens(counter, &message.content, model.unwrap_or_default());
+            let input = meta.context_tokens;
+            meta.context_tokens += counted;
+            match tokens {
+               None if is_assistant => Some(TokenCounts {
+                  input,
+                  output: counted,
+               }),
+               tokens => tokens,
+            }
+         }
+      };
+      let dir = self.dir()?;
+      let attachments = message
+         .attachments
+         .into_iter()
+         .map(|attachment| snapshot(dir, meta.workspace.as_deref(), attachment))
+         .collect::<Result<_, _>>()?;
+      let message = SessionMessage {
+         role: message.role,
+         content: message.content,
+         timestamp: message.timestamp.unwrap_or_else(Utc::now),
+         tokens,
+         model: message.model,
+         attachments,
+      };
+      let mut line = serde_json::to_string(&message).map_err(|e| SessionError::Io {
+         path: paths.log.display().to_string(),
+         message: e.to_string(),
+      })?;
+      line.push('\n');
+      fs::File::options()
+         .create(true)
+         .append(true)
+         .open(&paths.log)
+         .and_then(|mut log| log.write_all(line.as_bytes()))
+         .map_err(|e| SessionError::io(&paths.log, e))?;
+
+      meta.message_count += 1;
+      meta.updated_at = Utc::now();
+      if let Some(tokens) = &message.tokens {
+         meta.tokens.add(tokens);
+      }
+      if message.model.is_some() {
+         meta.model = message.model;
+      }
+      write_meta(&paths.meta, &meta)?;
+      record(dir, &meta);
+      if is_assistant && let Some(tokens) = &message.tokens {
+         // Stats missing a message are better than a message that failed to save
+         if let Err(e) = usage::record(
+            dir,
+            message.timestamp,
+            &meta.agent_id,
+            meta.model.as_deref(),
+            tokens,
+         ) {
+            log::warn!("Failed to record usage of session {}: {}", id, e);
+         }
+      }
+      Ok(meta)
+   }
+
+   /// Change the metadata of session `id` as `update` asks, returning the updated metadata
+   pub fn update_meta(
+      &self,
+      id: &str,
+      update: SessionMetaUpdate,
+   ) -> Result<SessionMeta, SessionError> {
+      let paths = self.paths(id)?;
+      let _guard = self.lock();
+      let mut meta = self.current_meta(id, &paths)?;
+      if let Some(title) = update.title {
+         let title = title.trim();
+         meta.title = (!title.is_empty()).then(|| title.to_string());
+      }
+      if let Some(pinned) = update.pinned {
+         meta.pinned = pinned;
+      }
+      if let Some(tags) = update.tags {
+         meta.tags = Vec::new();
+         for tag in tags.iter().map(|tag| tag.trim()) {
+            if !tag.is_empty() && !meta.tags.iter().any(|existing| existing == tag) {
+               meta.tags.push(tag.to_string());
+            }
+         }
+      }
+      write_meta(&paths.meta, &meta)?;
+      record(self.dir()?, &meta);
+      Ok(meta)
+   }
+
+   /// Give session `id` a generated title, unless it was given one since titling started.
+   /// Returns whether the title was set.
+   pub(super) fn set_generated_title(&self, id: &str, title: &str) -> Result<bool, SessionError> {
+      let paths = self.paths(id)?;
+      let _guard = self.lock();
+      let mut meta = self.current_meta(id, &paths)?;
+      if meta.title.is_some() {
+         return Ok(false);
+      }
+      meta.title = Some(title.to_string());
+      write_meta(&paths.meta, &meta)?;
+      record(self.dir()?, &meta);
+      Ok(true)
+   }
+
+   /// Session `id` with all its messages
+   pub fn get(&self, id: &str) -> Result<Session, SessionError> {
+      let paths = self.paths(id)?;
+      let _guard = self.lock();
+      Ok(load(id, &paths)?.0)
+   }
+
+   /// The snapshot of the file at `path` attached to message `message_index` of session `id`
+   pub fn get_attachment(
+      &self,
+      id: &str,
+      message_index: usize,
+      path: &str,
+   ) -> Result<SessionAttachmentContent, SessionError> {
+      let session = self.get(id)?;
+      let attachment = session
+         .messages
+         .get(message_index)
+         .and_then(|message| {
+            message
+               .attachments
+               .iter()
+               .find(|attachment| attachment.path == path)
+         })
+         .ok_or_else(|| SessionError::UnknownAttachment {
+            session_id: id.to_string(),
+            message_index,
+            path: path.to_string(),
+         })?;
+      let not_stored = || SessionError::AttachmentNotStored {
+         path: attachment.path.clone(),
+         content_hash: attachment.content_hash.clone(),
+      };
+      if !attachment.stored {
+         return Err(not_stored());
+      }
+      let _guard = self.lock();
+      let content = blobs::get(self.dir()?, &attachment.content_hash)?.ok_or_else(not_stored)?;
+      Ok(SessionAttachmentContent {
+         path: attachment.path.clone(),
+         content,
+         byte_len: attachment.byte_len,
+         truncated: attachment.truncated,
+      })
+   }
+
+   /// Remove the attachment snapshots no stored session refers to. Every session's log is
+   /// searched for the hashes it mentions, so a log that can't be parsed still keeps its
+   /// snapshots; one that can't be read at all stops the collection.
+   pub fn gc_blobs(&self) -> Result<SessionBlobsCollected, SessionError> {
+      let dir = self.dir()?;
+      let _guard = self.lock();
+      let read_dir = match fs::read_dir(dir) {
+         Ok(read_dir) => read_dir,
+         Err(e) if e.kind() == ErrorKind::NotFound => return Ok(SessionBlobsCollected::default()),
+         Err(e) => return Err(SessionError::io(dir, e)),
+      };
+      let mut referenced = HashSet::new();
+      for entry in read_dir {
+         let path = entry.map_err(|e| SessionError::io(dir, e))?.path();
+         if is_session_file(&path, LOG_EXTENSION) {
+            let log = fs::read(&path).map_err(|e| SessionError::io(&path, e))?;
+            blobs::hashes_in(&String::from_utf8_lossy(&log), &mut referenced);
+         }
+      }
+      let collected = blobs::collect(dir, &referenced)?;
+      log::info!(
+         "Removed {} unreferenced attachment snapshots, freeing {} bytes",
+         collected.removed,
+         collected.freed_bytes
+      );
+      Ok(collected)
+   }
+
+   /// The page of sessions `query` asks for, read from the index. Text is searched for in each
+   /// matching session's log a line at a time. Session files that can't be read are left out
+   /// and reported as warnings.
+   pub fn list(&self, query: &SessionQuery) -> Result<SessionPage, SessionError> {
+      let dir = self.dir()?;
+      let _guard = self.lock();
+      let mut warnings = Vec::new();
+      let summaries = summaries(dir, &mut warnings)?;
+
+      let mut sessions: Vec<SessionSummary> = summaries
+         .into_values()
+         .filter(|summary| query.matches(summary))
+         .collect();
+      if let Some(needle) = query.needle() {
+         sessions.retain(|summary| {
+            let in_title = summary
+               .title
+               .as_ref()
+               .is_some_and(|title| title.to_lowercase().contains(&needle));
+            in_title || log_contains(dir, &summary.id, &needle, &mut warnings)
+         });
+      }
+      sessions.sort_by(|a, b| {
+         let order = match query.sort {
+            SessionSort::Recent => b.last_activity.cmp(&a.last_activity),
+            SessionSort::Oldest => a.last_activity.cmp(&b.last_activity),
+         };
+         order.then_with(|| a.id.cmp(&b.id))
+      });
+
+      let total = sessions.len();
+      let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+      Ok(SessionPage {
+         sessions: sessions
+            .into_iter()
+            .skip(query.offset)
+            .take(limit)
+            .collect(),
+         total,
+         warnings,
+      })
+   }
+
+   /// The metadata of session `id` for a write, first rewriting the session in the current
+   /// schema version if it was stored in an older one so old and new records are never mixed
+   fn current_meta(&self, id: &str, paths: &SessionPaths) -> Result<SessionMeta, SessionError> {
+      let raw = read_raw_meta(id, &paths.meta)?;
+      if schema_version(&raw) == Some(SESSION_SCHEMA_VERSION) {
+         return serde_json::from_value(raw).map_err(|e| SessionError::corrupt(&paths.meta, e));
+      }
+      let (session, upgraded) = load(id, paths)?;
+      if upgraded {
+         let mut log = String::new();
+         for message in &session.messages {
+            let line =
+               serde_json::to_string(message).map_err(|e| SessionError::corrupt(&paths.log, e))?;
+            log.push_str(&line);
+            log.push('\n');
+         }
+         write_atomic(&paths.log, &log)?;
+         write_meta(&paths.meta, &session.meta)?;
+         log::info!(
+            "Upgraded session {} to schema version {}",
+            id,
+            SESSION_SCHEMA_VERSION
+         );
+      }
+      Ok(session.meta)
+   }
+}
+
+/// Snapshot the file `attachment` is of into the blobs of `dir`. A snapshot that can't be saved is
+/// logged and recorded as not stored rather than failing the message.
+fn snapshot(
+   dir: &Path,
+   workspace: Option<&str>,
+   attachment: NewSessionAttachment,
+) -> Result<SessionAttachment, SessionError> {
+   let content = match attachment.content {
+      Some(content) => content,
+      None => {
+         let path = match workspace {
+            Some(workspace) => Path::new(workspace).join(&attachment.path),
+            None => PathBuf::from(&attachment.path),
+         };
+         let bytes = fs::read(&path).map_err(|e| SessionError::io(&path, e))?;
+         String::from_utf8_lossy(&bytes).into_owned()
+      }
+   };
+   let (snapshot, truncated) = blobs::snapshot(&content);
+   let stored = match blobs::put(dir, &snapshot) {
+      Ok(_) => true,
+      Err(e) => {
+         log::warn!("Failed to keep a snapshot of {}: {}", attachment.path, e);
+         false
+      }
+   };
+   Ok(SessionAttachment {
+      path: attachment.path,
+      content_hash: blobs::content_hash(&snapshot),
+      byte_len: content.len() as u64,
+      stored,
+      truncated,
+   })
+}
+
+/// Tokens of `content` for `model`, or none if the tokenizer can't be loaded
+fn count_tokens(counter: &TokenCounter, content: &str, model: &str) -> u64 {
+   if content.is_empty() {
+      return 0;
+   }
+   match counter.count(&[content.to_string()], model) {
+      Ok(counts) => counts[0].tokens as u64,
+      Err(e) => {
+         log::warn!("Failed to count message tokens: {}", e);
+         0
+      }
+   }
+}
+
+/// Replace the file at `path` with `content` through the same atomic write as agent settings
+pub(super) fn write_atomic(path: &Path, content: &str) -> Result<(), SessionError> {
+   agent_settings::write_atomic(path, content).map_err(|e| SessionError::Io {
+      path: path.display().to_string(),
+      message: e.to_string(),
+   })
+}
+
+fn write_meta(path: &Path, meta: &SessionMeta) -> Result<(), SessionError> {
+   let content = serde_json::to_string_pretty(meta).map_err(|e| SessionError::corrupt(path, e))?;
+   write_atomic(path, &content)
+}
+
+fn read_raw_meta(id: &str, path: &Path) -> Result<Value, SessionError> {
+   let content = match fs::read_to_string(path) {
+      Ok(content) => content,
+      Err(e) if e.kind() == ErrorKind::NotFound => {
+         return Err(SessionError::UnknownSession {
+            session_id: id.to_string(),
+         });
+      }
+      Err(e) => return Err(SessionError::io(path, e)),
+   };
+   serde_json::from_str(&content).map_err(|e| SessionError::corrupt(path, e))
+}
+
+/// The metadata in `path`, brought up to the current schema version in memory
+fn read_meta(path: &Path) -> Result<SessionMeta, SessionError> {
+   let id = path
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .unwrap_or_default();
+   let raw = read_raw_meta(id, path)?;
+   Ok(upgrade(path, raw, Vec::new())?.0.meta)
+}
+
+/// The messages in a session's log. A last line that doesn't parse is a write cut short and is
+/// dropped; anything else that doesn't parse makes the log corrupt.
+fn read_log(path: &Path) -> Result<Vec<Value>, SessionError> {
+   let content = match fs::read_to_string(path) {
+      Ok(content) => content,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(SessionError::io(path, e)),
+   };
+   let lines: Vec<&str> = content
+      .lines()
+      .filter(|line| !line.trim().is_empty())
+      .collect();
+   let mut messages = Vec::with_capacity(lines.len());
+   for (index, line) in lines.iter().enumerate() {
+      match serde_json::from_str(line) {
+         Ok(message) => messages.push(message),
+         Err(e) if index + 1 == lines.len() && !content.ends_with('\n') => {
+            log::warn!(
+               "Dropping incomplete last message of {}: {}",
+               path.display(),
+               e
+            );
+         }
+         Err(e) => {
+            return Err(SessionError::corrupt(
+               path,
+               format!("line {}: {}", index + 1, e),
+            ));
+         }
+      }
+   }
+   Ok(messages)
+}
+
+/// Session `id` brought up to the current schema version, and whether it had to be upgraded
+fn load(id: &str, paths: &SessionPaths) -> Result<(Session, bool), SessionError> {
+   let raw = read_raw_meta(id, &paths.meta)?;
+   let (mut session, upgraded) = upgrade(&paths.meta, raw, read_log(&paths.log)?)?;
+   session.meta.message_count = session.messages.len();
+   Ok((session, upgraded))
+}
+
+/// Add the session to the index. If that fails the index is removed, to be rebuilt from the
+/// session files when sessions are next listed, rather than left without the session.
+fn record(dir: &Path, meta: &SessionMeta) {
+   if let Err(e) = index::append(dir, &SessionSummary::of(meta)) {
+      log::warn!("Failed to index session {}: {}", meta.id, e);
+      index::remove(dir);
+   }
+}
+
+/// The summaries of every session in `dir` from the index, rebuilt first if there is none. The
+/// caller holds the store's lock.
+pub(super) fn summaries(
+   dir: &Path,
+   warnings: &mut Vec<SessionListWarning>,
+) -> Result<HashMap<String, SessionSummary>, SessionError> {
+   match index::read(dir)? {
+      Some(summaries) => Ok(summaries),
+      None => rebuild_index(dir, warnings),
+   }
+}
+
+/// Whether `path` is one of a session's files with `extension`
+fn is_session_file(path: &Path, extension: &str) -> bool {
+   path.extension().and_then(|ext| ext.to_str()) == Some(extension)
+      && path
+         .file_stem()
+         .and_then(|stem| stem.to_str())
+         .is_some_and(|stem| Uuid::parse_str(stem).is_ok())
+}
+
+/// Index every session in `dir` from its metadata file, reporting the files that can't be read
+fn rebuild_index(
+   dir: &Path,
+   warnings: &mut Vec<SessionListWarning>,
+) -> Result<HashMap<String, SessionSummary>, SessionError> {
+   let read_dir = match fs::read_dir(dir) {
+      Ok(read_dir) => read_dir,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+      Err(e) => return Err(SessionError::io(dir, e)),
+   };
+   let mut summaries = HashMap::new();
+   for entry in read_dir {
+      let path = entry.map_err(|e| SessionError::io(dir, e))?.path();
+      if !is_session_file(&path, META_EXTENSION) {
+         continue;
+      }
+      match read_meta(&path) {
+         Ok(meta) => {
+            summaries.insert(meta.id.clone(), SessionSummary::of(&meta));
+         }
+         Err(error) => {
+            log::warn!("Skipping session: {}", error);
+            warnings.push(SessionListWarning {
+               path: path.display().to_string(),
+               error,
+            });
+         }
+      }
+   }
+   index::write(dir, summaries.values())?;
+   log::info!("Rebuilt the index of {} sessions", summaries.len());
+   Ok(summaries)
+}
+
+/// Whether a message in the log of session `id` contains `needle`, which is lowercase. The log
+/// is read a line at a time; one that can't be read is reported in `warnings`.
+fn log_contains(
+   dir: &Path,
+   id: &str,
+   needle: &str,
+   warnings: &mut Vec<SessionListWarning>,
+) -> bool {
+   let path = dir.join(id).with_extension(LOG_EXTENSION);
+   let mut warn = |error: SessionError| {
+      log::warn!("Skipping session in search: {}", error);
+      warnings.push(SessionListWarning {
+         path: path.display().to_string(),
+         error,
+      });
+   };
+   let log = match fs::File::open(&path) {
+      Ok(log) => log,
+      Err(e) if e.kind() == ErrorKind::NotFound => return false,
+      Err(e) => {
+         warn(SessionError::io(&path, e));
+         return false;
+      }
+   };
+   // A line that doesn't parse is only corrupt if another follows it; the last one may be a
+   // write cut short
+   let mut unparsed = None;
+   for (number, line) in BufReader::new(log).lines().enumerate() {
+      let line = match line {
+         Ok(line) => line,
+         Err(e) => {
+            warn(SessionError::io(&path, e));
+            return false;
+         }
+      };
+      if line.trim().is_empty() {
+         continue;
+      }
+      if let Some(message) = unparsed.take() {
+         warn(SessionError::corrupt(&path, message));
+         return false;
+      }
+      match serde_json::from_str::<SessionMessage>(&line) {
+         Ok(message) if message.content.to_lowercase().contains(needle) => return true,
+         Ok(_) => {}
+         Err(e) => unparsed = Some(format!("line {}: {}", number + 1, e)),
+      }
+   }
+   false
+}
+
+/// Run `work` on the store off the async runtime, as it reads and writes files
+pub(super) async fn blocking<T: Send + 'static>(
+   store: &SessionStore,
+   work: impl FnOnce(SessionStore) -> Result<T, SessionError> + Send + 'static,
+) -> Result<T, SessionError> {
+   let store = store.clone();
+   tokio::task::spawn_blocking(move || work(store))
+      .await
+      .map_err(|e| SessionError::Io {
+         path: SESSIONS_DIR.to_string(),
+         message: e.to_string(),
+      })?
+}
+
+/// Start a session with an agent, stored under `~/.athas/sessions`
+#[command]
+pub async fn create_session(
+   store: State<'_, SessionStore>,
+   agent_id: String,
+   workspace: Option<String>,
+   model: Option<String>,
+) -> Result<SessionMeta, SessionError> {
+   let meta = blocking(&store, move |store| {
+      store.create(&agent_id, workspace, model)
+   })
+   .await?;
+   log::info!("Created session {} for agent {}", meta.id, meta.agent_id);
+   Ok(meta)
+}
+
+/// Add a message to a session, returning the session's updated metadata. The first reply to a
+/// session without a title starts titling it in the background.
+#[command]
+pub async fn append_session_message(
+   app: AppHandle,
+   store: State<'_, SessionStore>,
+   counter: State<'_, TokenCounter>,
+   session_id: String,
+   message: NewSessionMessage,
+) -> Result<SessionMeta, SessionError> {
+   let counter = counter.inner().clone();
+   let is_reply = message.role == SessionRole::Assistant;
+   let id = session_id.clone();
+   let meta = blocking(&store, move |store| store.append(&id, message, &counter)).await?;
+   if is_reply && meta.title.is_none() {
+      title_in_background(&app, session_id);
+   }
+   Ok(meta)
+}
+
+/// Rename, pin or tag a session, returning its updated metadata. Pinned sessions are never
+/// pruned.
+#[command]
+pub async fn update_session_meta(
+   store: State<'_, SessionStore>,
+   session_id: String,
+   update: SessionMetaUpdate,
+) -> Result<SessionMeta, SessionError> {
+   blocking(&store, move |store| store.update_meta(&session_id, update)).await
+}
+
+/// The snapshot of a file attached to message `message_index` of a session, as the file was
+/// when it was attached
+#[command]
+pub async fn get_session_attachment(
+   store: State<'_, SessionStore>,
+   session_id: String,
+   message_index: usize,
+   path: String,
+) -> Result<SessionAttachmentContent, SessionError> {
+   blocking(&store, move |store| {
+      store.get_attachment(&session_id, message_index, &path)
+   })
+   .await
+}
+
+/// Remove the attachment snapshots no stored session refers to any more, such as those of
+/// pruned sessions
+#[command]
+pub async fn gc_session_blobs(
+   store: State<'_, SessionStore>,
+) -> Result<SessionBlobsCollected, SessionError> {
+   blocking(&store, |store| store.gc_blobs()).await
+}
+
+/// A session with all its messages
+#[command]
+pub async fn get_session(
+   store: State<'_, SessionStore>,
+   session_id: String,
+) -> Result<Session, SessionError> {
+   blocking(&store, move |store| store.get(&session_id)).await
+}
+
+/// Summaries of stored sessions, a page at a time, most recently active first unless `query`
+/// sorts them otherwise. Sessions can be narrowed to one agent or workspace and searched for
+/// text in their titles and messages.
+#[command]
+pub async fn list_sessions(
+   store: State<'_, SessionStore>,
+   query: Option<SessionQuery>,
+) -> Result<SessionPage, SessionError> {
+   let query = query.unwrap_or_default();
+   blocking(&store, move |store| store.list(&query)).await
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn message(role: SessionRole, content: &str, tokens: Option<TokenCounts>) -> NewSessionMessage {
+      NewSessionMessage {
+         role,
+         content: content.into(),
+         timestamp: None,
+         tokens,
+         model: None,
+         attachments: Vec::new(),
+      }
+   }
+
+   #[test]
+   fn test_create_append_and_get() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = SessionStore::in_dir(dir.path());
+      let counter = TokenCounter::new();
+      let created = store
+         .create("claude-code", Some("/home/me/athas".into()), None)
+         .unwrap();
+
+      store
+         .append(
+            &created.id,
+            message(SessionRole::User, "Hi", None),
+            &counter,
+         )
+         .unwrap();
+      let reply = NewSessionMessage {
+         model: Some("claude-sonnet-4-5".into()),
+         ..message(
+            SessionRole::Assistant,
+            "Hello",
+            Some(TokenCounts {
+               input: 10,
+               output: 3,
+            }),
+         )
+      };
+      let meta = store.append(&created.id, reply, &counter).unwrap();
+      assert_eq!(meta.message_count, 2);
+      assert_eq!(
+         meta.tokens,
+         TokenCounts {
+            input: 10,
+            output: 3,
+         }
+      );
+      assert_eq!(meta.model.as_deref(), Some("claude-sonnet-4-5"));
+
+      let session = store.get(&created.id).unwrap();
+      assert_eq!(session.meta, meta);
+      let contents: Vec<&str> = session
+         .messages
+         .iter()
+         .map(|m| m.content.as_str())
+         .collect();
+      assert_eq!(contents, ["Hi", "Hello"]);
+
+      // Appending only adds a line to the log
+      let log = fs::read_to_string(dir.path().join(&created.id).with_extension("jsonl")).unwrap();
+      assert_eq!(log.lines().count(), 2);
+   }
+
+   #[test]
+   fn test_assistant_tokens_are_counted_and_recorded() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = SessionStore::in_dir(dir.path());
+      let counter = TokenCounter::new();
+      let created = store
+         .create("codex-cli", None, Some("gpt-4o".into()))
+         .unwrap();
+      let count = |text: &str| counter.count(&[text.into()], "gpt-4o").unwrap()[0].tokens as u64;
+
+      let question = "Why does the watcher fire twice?";
+      let answer = "Both the file and its folder are watched.";
+      store
+         .append(
+            &created.id,
+            message(SessionRole::User, question, None),
+            &counter,
+         )
+         .unwrap();
+      let meta = store
+         .append(
+            &created.id,
+            message(SessionRole::Assistant, answer, None),
+            &counter,
+         )
+         .unwrap();
+      let counted = TokenCounts {
+         input: count(question),
+         output: count(answer),
+      };
+      assert_eq!(meta.tokens, counted);
+      assert_eq!(meta.context_tokens, counted.input + counted.output);
+      assert_eq!(
+         store.get(&created.id).unwrap().messages[1].tokens,
+         Some(counted)
+      );
+
+      // Reported usage replaces the running count
+      let usage = TokenCounts {
+         input: 400,
+         output: 20,
+      };
+      let meta = store
+         .append(
+            &created.id,
+            message(SessionRole::Assistant, "Debounce it.", Some(usage)),
+            &counter,
+         )
+         .unwrap();
+      assert_eq!(meta.context_tokens, 420);
+
+      let ledger = fs::read_to_string(dir.path().join("usage.jsonl")).unwrap();
+      assert_eq!(ledger.lines().count(), 2);
+      assert!(ledger.contains("\"input\":400"));
+   }
+
+   #[test]
+   fn test_update_meta_and_generated_titles() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = SessionStore::in_dir(dir.path());
+      let created = store.create("claude-code", None, None).unwrap();
+
+      assert!(
+         store
+            .set_generated_title(&created.id, "Watcher fires twice")
+            .unwrap()
+      );
+      let meta = store
+         .update_meta(
+            &created.id,
+            SessionMetaUpdate {
+               title: Some(" Debounce the watcher ".into()),
+               tags: Some(vec![
+                  "bug".into(),
+                  " ".into(),
+                  "watcher".into(),
+                  "bug".into(),
+               ]),
+               ..SessionMetaUpdate::default()
+            },
+         )
+         .unwrap();
+      assert_eq!(meta.title.as_deref(), Some("Debounce the watcher"));
+      assert_eq!(meta.tags, ["bug", "watcher"]);
+      assert!(!meta.pinned);
+      // A title the user chose isn't replaced by a generated one
+      assert!(!store.set_generated_title(&created.id, "Other").unwrap());
+
+      let page = store.list(&SessionQuery::default()).unwrap();
+      assert_eq!(page.sessions[0].tags, ["bug", "watcher"]);
+      let meta = store
+         .update_meta(
+            &created.id,
+            SessionMetaUpdate {
+               title: Some(String::new()),
+               ..SessionMetaUpdate::default()
+            },
+         )
+         .unwrap();
+      assert_eq!(meta.title, None);
+      assert_eq!(meta.tags, ["bug", "watcher"]);
+   }
+
+   #[test]
+   fn test_incomplete_last_message_is_dropped() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = SessionStore::in_dir(dir.path());
+      let counter = TokenCounter::new();
+      let created = store.create("codex-cli", None, None).unwrap();
+      store
+         .append(
+            &created.id,
+            message(SessionRole::User, "Hi", None),
+            &counter,
+         )
+         .unwrap();
+      let log = dir.path().join(&created.id).with_extension("jsonl");
+      let mut file = fs::File::options().append(true).open(&log).unwrap();
+      file.write_all(br#"{"role":"assistant","cont"#).unwrap();
+
+      assert_eq!(store.get(&created.id).unwrap().messages.len(), 1);
+
+      fs::write(&log, "not json\n{}\n").unwrap();
+      assert!(matches!(
+         store.get(&created.id),
+         Err(SessionError::Corrupt { .. })
+      ));
+   }
+
+   #[test]
+   fn test_attachments_are_snapshotted_and_collected() {
+      let dir = tempfile::tempdir().unwrap();
+      let workspace = tempfile::tempdir().unwrap();
+      fs::write(workspace.path().join("watcher.rs"), "fn watch() {}\n").unwrap();
+      let store = SessionStore::in_dir(dir.path());
+      let counter = TokenCounter::new();
+      let attach = |session: &SessionMeta| {
+         let message = NewSessionMessage {
+            attachments: vec![NewSessionAttachment {
+               path: "watcher.rs".into(),
+               content: None,
+            }],
+            ..message(SessionRole::User, "Why?", None)
+         };
+         store.append(&session.id, message, &counter).unwrap();
+      };
+      let workspace = Some(workspace.path().display().to_string());
+      let first = store
+         .create("claude-code", workspace.clone(), None)
+         .unwrap();
+      let second = store.create("claude-code", workspace, None).unwrap();
+      attach(&first);
+      attach(&second);
+
+      let snapshot = store.get_attachment(&first.id, 0, "watcher.rs").unwrap();
+      assert_eq!(snapshot.content, "fn watch() {}\n");
+      assert_eq!(snapshot.byte_len, 14);
+      assert!(!snapshot.truncated);
+      assert!(matches!(
+         store.get_attachment(&first.id, 0, "other.rs"),
+         Err(SessionError::UnknownAttachment { .. })
+      ));
+      // The same content is stored once for both sessions
+      assert_eq!(fs::read_dir(dir.path().join("blobs")).unwrap().count(), 1);
+
+      fs::remove_file(dir.path().join(&first.id).with_extension("jsonl")).unwrap();
+      assert_eq!(store.gc_blobs().unwrap().removed, 0);
+      fs::remove_file(dir.path().join(&second.id).with_extension("jsonl")).unwrap();
+      assert_eq!(store.gc_blobs().unwrap().removed, 1);
+
+      store
+         .append(
+            &first.id,
+            NewSessionMessage {
+               attachments: vec![NewSessionAttachment {
+                  path: "missing.rs".into(),
+                  content: None,
+               }],
+               ..message(SessionRole::User, "Why?", None)
+            },
+            &counter,
+         )
+         .unwrap_err();
+   }
+
+   #[test]
+   fn test_list_filters_sorts_and_pages() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = SessionStore::in_dir(dir.path());
+      let counter = TokenCounter::new();
+      let older = store
+         .create("claude-code", Some("/a".into()), None)
+         .unwrap();
+      let newer = store.create("codex-cli", Some("/b".into()), None).unwrap();
+      let latest = store
+         .create("claude-code", Some("/b".into()), None)
+         .unwrap();
+      store
+         .append(
+            &latest.id,
+            message(SessionRole::User, "Why does the Watcher fire twice?", None),
+            &counter,
+         )
+         .unwrap();
+
+      let ids = |query: SessionQuery| -> (Vec<String>, usize) {
+         let page = store.list(&query).unwrap();
+         assert!(page.warnings.is_empty());
+         let ids = page
+            .sessions
+            .into_iter()
+            .map(|summary| summary.id)
+            .collect();
+         (ids, page.total)
+      };
+      assert_eq!(
+         ids(SessionQuery::default()).0,
+         [latest.id.clone(), newer.id.clone(), older.id.clone()]
+      );
+      assert_eq!(
+         ids(SessionQuery {
+            sort: SessionSort::Oldest,
+            offset: 1,
+            limit: Some(1),
+            ..SessionQuery::default()
+         }),
+         (vec![newer.id.clone()], 3)
+      );
+      assert_eq!(
+         ids(SessionQuery {
+            agent_id: Some("claude-code".into()),
+            workspace: Some("/b".into()),
+            ..SessionQuery::default()
+         })
+         .0,
+         vec![latest.id.clone()]
+      );
+      assert_eq!(
+         ids(SessionQuery {
+            text: Some("watcher".into()),
+            ..SessionQuery::default()
+         }),
+         (vec![latest.id], 1)
+      );
+   }
+
+   #[test]
+   fn test_list_rebuilds_index_and_warns() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = SessionStore::in_dir(dir.path());
+      let counter = TokenCounter::new();
+      let kept = store.create("claude-code", None, None).unwrap();
+      store
+         .append(&kept.id, message(SessionRole::User, "Hi", None), &counter)
+         .unwrap();
+      let broken = store.create("claude-code", None, None).unwrap();
+      fs::write(dir.path().join(&broken.id).with_extension("json"), "{").unwrap();
+      fs::write(dir.path().join("notes.json"), "{").unwrap();
+      fs::remove_file(dir.path().join("index.jsonl")).unwrap();
+
+      let page = store.list(&SessionQuery::default()).unwrap();
+      assert_eq!(page.total, 1);
+      assert_eq!(page.sessions[0].id, kept.id);
+      assert_eq!(page.sessions[0].message_count, 1);
+      assert_eq!(page.warnings.len(), 1);
+      assert!(matches!(
+         page.warnings[0].error,
+         SessionError::Corrupt { .. }
+      ));
+      assert!(dir.path().join("index.jsonl").exists());
+
+      // A log corrupt partway through is reported by searches
+      fs::write(
+         dir.path().join(&kept.id).with_extension("jsonl"),
+         "not json\n{}\n",
+      )
+      .unwrap();
+      let page = store
+         .list(&SessionQuery {
+            text: Some("hi".into()),
+            ..SessionQuery::default()
+         })
+         .unwrap();
+      assert_eq!(page.total, 0);
+      assert_eq!(page.warnings.len(), 1);
+   }
+
+   #[test]
+   fn test_rejects_ids_outside_the_store() {
+      let dir = tempfile::tempdir().unwrap();
+      let store = SessionStore::in_dir(dir.path());
+      for id in ["../settings", "{0b6a2a4e-5d3c-4f0e-9c43-3f1f1b0a8e21}", ""] {
+         assert!(matches!(
+            store.get(id),
+            Err(SessionError::InvalidSessionId { .. })
+         ));
+      }
+      assert!(matches!(
+         store.get(&Uuid::new_v4().to_string()),
+         Err(SessionError::UnknownSession { .. })
+      ));
+   }
+}
@@ -0,0 +1,257 @@
+use super::{
+   error::SessionError,
+   schema::{Session, SessionRole},
+   store::{SessionStore, blocking},
+};
+use crate::commands::ai::{
+   agent_settings::{AgentSettingsLocks, read_athas_setting},
+   credentials::CredentialStore,
+   providers::{ChatMessage, ChatRole, CompletionRequest, ProviderHttpClient, complete},
+};
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Event emitted with a `SessionTitleUpdate` once a session has been titled
+pub const SESSION_TITLE_UPDATED_EVENT: &str = "session-title-updated";
+/// Whether titles are written by a model rather than taken from the first message
+const AUTO_TITLE_KEY: &str = "ai.sessions.autoTitle";
+/// Longest title, in characters
+const MAX_TITLE_CHARS: usize = 60;
+/// Characters of each message sent to the model to title the session, keeping the request to a
+/// few hundred tokens
+const PROMPT_CHARS: usize = 600;
+/// Titles are a handful of words
+const TITLE_MAX_TOKENS: u32 = 24;
+/// Cheap models to title sessions with, tried in order by which provider has a key
+const TITLE_MODELS: [(&str, &str); 3] = [
+   ("anthropic", "claude-haiku-4-5"),
+   ("openai", "gpt-5-mini"),
+   ("google", "gemini-2.5-flash-lite"),
+];
+const TITLE_PROMPT: &str = "Write a title of at most six words for the conversation below. Reply \
+                            with the title only, without quotes or a full stop.";
+
+/// Payload of `session-title-updated`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionTitleUpdate {
+   pub session_id: String,
+   pub title: String,
+}
+
+/// `text` on one line, cut at the last word that fits in `max` characters. A first word that
+/// doesn't fit is cut where it reaches the limit. Cut text ends in an ellipsis.
+fn truncate_words(text: &str, max: usize) -> String {
+   let words: Vec<&str> = text.split_whitespace().collect();
+   let line = words.join(" ");
+   if line.chars().count() <= max {
+      return line;
+   }
+   let mut cut = String::new();
+   for word in &words {
+      let len = cut.chars().count() + usize::from(!cut.is_empty()) + word.chars().count();
+      if len >= max {
+         break;
+      }
+      if !cut.is_empty() {
+         cut.push(' ');
+      }
+      cut.push_str(word);
+   }
+   if cut.is_empty() {
+      cut = line.chars().take(max - 1).collect();
+   }
+   cut.push('…');
+   cut
+}
+
+/// The session's first message from the user and the first reply to it
+fn first_exchange(session: &Session) -> Option<(&str, Option<&str>)> {
+   let mut messages = session.messages.iter();
+   let question = messages
+      .find(|message| message.role == SessionRole::User && !message.content.trim().is_empty())?;
+   let answer = messages.find(|message| message.role == SessionRole::Assistant);
+   Some((
+      question.content.as_str(),
+      answer.map(|message| message.content.as_str()),
+   ))
+}
+
+/// A title from the first message: its opening words, up to `MAX_TITLE_CHARS`
+fn heuristic_title(question: &str) -> Option<String> {
+   let title = truncate_words(question, MAX_TITLE_CHARS);
+   (!title.is_empty()).then_some(title)
+}
+
+/// The model's answer as a title: its first line without quotes, Markdown or a full stop
+fn clean_title(answer: &str) -> Option<String> {
+   let line = answer.lines().find(|line| !line.trim().is_empty())?;
+   let line = line
+      .trim()
+      .trim_start_matches('#')
+      .trim_matches(|c: char| c == '"' || c == '\'' || c == '*' || c == '`' || c.is_whitespace())
+      .trim_end_matches('.');
+   let line = line.strip_prefix("Title:").unwrap_or(line).trim();
+   let title = truncate_words(line, MAX_TITLE_CHARS);
+   (!title.is_empty()).then_some(title)
+}
+
+/// The request titling the session with `model`
+fn title_request(
+   provider: &str,
+   model: &str,
+   question: &str,
+   answer: Option<&str>,
+) -> CompletionRequest {
+   let excerpt = |text: &str| text.chars().take(PROMPT_CHARS).collect::<String>();
+   let mut conversation = format!("User: {}", excerpt(question));
+   if let Some(answer) = answer {
+      conversation.push_str(&format!("\n\nAssistant: {}", excerpt(answer)));
+   }
+   CompletionRequest {
+      provider: provider.to_string(),
+      model: model.to_string(),
+      messages: vec![
+         ChatMessage {
+            role: ChatRole::System,
+            content: TITLE_PROMPT.to_string(),
+         },
+         ChatMessage {
+            role: ChatRole::User,
+            content: conversation,
+         },
+      ],
+      temperature: Some(0.2),
+      max_tokens: Some(TITLE_MAX_TOKENS),
+      base_url: None,
+   }
+}
+
+/// A title written by the first of `TITLE_MODELS` whose provider has a key, if titles are to be
+/// written by a model and any has. Failures are logged.
+async fn model_title(app: &AppHandle, question: &str, answer: Option<&str>) -> Option<String> {
+   let locks = app.state::<AgentSettingsLocks>();
+   let enabled = match read_athas_setting(&locks, AUTO_TITLE_KEY).await {
+      Ok(value) => value.as_ref().and_then(Value::as_bool).unwrap_or(false),
+      Err(e) => {
+         log::warn!("Ignoring {}: {}", AUTO_TITLE_KEY, e);
+         false
+      }
+   };
+   if !enabled {
+      return None;
+   }
+   let credentials = app.state::<CredentialStore>();
+   let (provider, model) = TITLE_MODELS
+      .into_iter()
+      .find(|(provider, _)| matches!(credentials.get(provider), Ok(Some(_))))?;
+   let request = title_request(provider, model, question, answer);
+   let http = app.state::<ProviderHttpClient>();
+   match complete(&http, &credentials, &locks, &request).await {
+      Ok(answer) => clean_title(&answer),
+      Err(e) => {
+         log::warn!("Failed to title session with {}: {}", model, e);
+         None
+      }
+   }
+}
+
+async fn title_session(app: &AppHandle, session_id: String) -> Result<(), SessionError> {
+   let store = app.state::<SessionStore>();
+   let id = session_id.clone();
+   let session = blocking(&store, move |store| store.get(&id)).await?;
+   let Some((question, answer)) = first_exchange(&session) else {
+      return Ok(());
+   };
+   let title = match model_title(app, question, answer).await {
+      Some(title) => title,
+      None => match heuristic_title(question) {
+         Some(title) => title,
+         None => return Ok(()),
+      },
+   };
+
+   let id = session_id.clone();
+   let generated = title.clone();
+   let set = blocking(&store, move |store| {
+      store.set_generated_title(&id, &generated)
+   })
+   .await?;
+   if set {
+      log::info!("Titled session {}", session_id);
+      let _ = app.emit(
+         SESSION_TITLE_UPDATED_EVENT,
+         SessionTitleUpdate { session_id, title },
+      );
+   }
+   Ok(())
+}
+
+/// Title session `session_id` in the background, emitting `SESSION_TITLE_UPDATED_EVENT` when
+/// done. The title comes from a model when `ai.sessions.autoTitle` is on and a provider has a
+/// key, and from the first message otherwise or if that fails.
+pub(super) fn title_in_background(app: &AppHandle, session_id: String) {
+   let app = app.clone();
+   tauri::async_runtime::spawn(async move {
+      if let Err(e) = title_session(&app, session_id).await {
+         log::warn!("Failed to title session: {}", e);
+      }
+   });
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_heuristic_title_cuts_at_a_word() {
+      assert_eq!(
+         heuristic_title("  Why does the\nwatcher fire twice?  ").as_deref(),
+         Some("Why does the watcher fire twice?")
+      );
+      let long = "Refactor the settings journal so that undo works across restarts and agents";
+      let title = heuristic_title(long).unwrap();
+      assert_eq!(
+         title,
+         "Refactor the settings journal so that undo works across…"
+      );
+      assert!(title.chars().count() <= MAX_TITLE_CHARS);
+
+      let word = "x".repeat(100);
+      assert_eq!(
+         heuristic_title(&word).unwrap().chars().count(),
+         MAX_TITLE_CHARS
+      );
+      assert_eq!(heuristic_title(" \n "), None);
+   }
+
+   #[test]
+   fn test_cleans_model_titles() {
+      let cases = [
+         (
+            "Debouncing the file watcher",
+            Some("Debouncing the file watcher"),
+         ),
+         (
+            "\"Debouncing the watcher.\"\n",
+            Some("Debouncing the watcher"),
+         ),
+         ("## **Watcher fires twice**", Some("Watcher fires twice")),
+         ("Title: Watcher fix", Some("Watcher fix")),
+         ("  \n", None),
+      ];
+      for (answer, expected) in cases {
+         assert_eq!(clean_title(answer).as_deref(), expected, "{answer:?}");
+      }
+   }
+
+   #[test]
+   fn test_title_request_stays_small() {
+      let long = "word ".repeat(1000);
+      let request = title_request("anthropic", "claude-haiku-4-5", &long, Some(&long));
+      assert_eq!(request.max_tokens, Some(TITLE_MAX_TOKENS));
+      assert!(request.messages[1].content.chars().count() <= 2 * PROMPT_CHARS + 32);
+      assert_eq!(request.messages[0].role, ChatRole::System);
+   }
+}
@@ -0,0 +1,446 @@
+use super::{
+   error::SessionError,
+   schema::TokenCounts,
+   store::{SessionStore, blocking, write_atomic},
+};
+use crate::commands::ai::agent_settings::{AgentSettingsLocks, estimate_usage, pricing_table};
+use chrono::{DateTime, Duration, DurationRound, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+   collections::{BTreeMap, HashMap},
+   fs,
+   io::{ErrorKind, Write},
+   path::{Path, PathBuf},
+};
+use tauri::{State, command};
+
+/// Ledger of tokens used by assistant messages in the sessions directory, one record per line
+const USAGE_FILE: &str = "usage.jsonl";
+/// Resolution of the ledger. Every UTC offset in use is a multiple of it, so days in any
+/// timezone start on a bucket boundary.
+const BUCKET_MINUTES: i64 = 15;
+/// Lines the ledger may grow to before records in the same bucket are merged
+const COMPACT_MIN_LINES: usize = 4096;
+
+/// Tokens used by one agent and model in one bucket of time. The ledger may hold several records
+/// for the same bucket; they add up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageRecord {
+   /// Start of the bucket
+   at: DateTime<Utc>,
+   agent_id: String,
+   #[serde(default, skip_serializing_if = "Option::is_none")]
+   model: Option<String>,
+   input: u64,
+   output: u64,
+   /// Assistant messages the tokens were used by
+   messages: u64,
+}
+
+impl UsageRecord {
+   fn key(&self) -> (DateTime<Utc>, String, Option<String>) {
+      (self.at, self.agent_id.clone(), self.model.clone())
+   }
+}
+
+/// What `get_usage_stats` groups usage by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UsageGroupBy {
+   #[default]
+   Agent,
+   Model,
+   /// Calendar day in the timezone of the range
+   Day,
+}
+
+/// Which usage `get_usage_stats` counts. Times are rounded down to the quarter hour the ledger
+/// records usage in.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRange {
+   /// Start of the range, inclusive. Unset counts from the first usage recorded.
+   #[serde(default)]
+   pub from: Option<DateTime<Utc>>,
+   /// End of the range, exclusive. Unset counts up to now.
+   #[serde(default)]
+   pub to: Option<DateTime<Utc>>,
+   /// Offset from UTC of the timezone days are counted in, UTC unless set
+   #[serde(default)]
+   pub utc_offset_minutes: i32,
+}
+
+/// Usage of one agent, model or day
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageGroup {
+   /// The agent id, the model, or the day as `YYYY-MM-DD`. Messages without a model are grouped
+   /// under `unknown`.
+   pub key: String,
+   pub input_tokens: u64,
+   pub output_tokens: u64,
+   pub messages: u64,
+   /// Cost at list prices of the usage whose model has a price
+   pub estimated_usd: f64,
+   /// Messages left out of `estimated_usd` because their model has no price
+   pub unpriced_messages: u64,
+}
+
+impl UsageGroup {
+   fn new(key: String) -> Self {
+      Self {
+         key,
+         input_tokens: 0,
+         output_tokens: 0,
+         messages: 0,
+         estimated_usd: 0.0,
+         unpriced_messages: 0,
+      }
+   }
+
+   fn add(&mut self, record: &UsageRecord, usd: Option<f64>) {
+      self.input_tokens += record.input;
+      self.output_tokens += record.output;
+      self.messages += record.messages;
+      match usd {
+         Some(usd) => self.estimated_usd += usd,
+         None => self.unpriced_messages += record.messages,
+      }
+   }
+}
+
+/// Usage across a range, in groups ordered by key
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageStats {
+   pub groups: Vec<UsageGroup>,
+   pub total: UsageGroup,
+}
+
+fn usage_path(dir: &Path) -> PathBuf {
+   dir.join(USAGE_FILE)
+}
+
+fn bucket_of(at: DateTime<Utc>) -> DateTime<Utc> {
+   at.duration_trunc(Duration::minutes(BUCKET_MINUTES))
+      .unwrap_or(at)
+}
+
+/// Record the tokens an assistant message of `agent_id` used at the end of the ledger in `dir`
+pub(super) fn record(
+   dir: &Path,
+   at: DateTime<Utc>,
+   agent_id: &str,
+   model: Option<&str>,
+   tokens: &TokenCounts,
+) -> Result<(), SessionError> {
+   let path = usage_path(dir);
+   let record = UsageRecord {
+      at: bucket_of(at),
+      agent_id: agent_id.to_string(),
+      model: model.map(str::to_string),
+      input: tokens.input,
+      output: tokens.output,
+      messages: 1,
+   };
+   let mut line = serde_json::to_string(&record).map_err(|e| SessionError::corrupt(&path, e))?;
+   line.push('\n');
+   fs::File::options()
+      .create(true)
+      .append(true)
+      .open(&path)
+      .and_then(|mut ledger| ledger.write_all(line.as_bytes()))
+      .map_err(|e| SessionError::io(&path, e))
+}
+
+/// The records in the ledger of `dir`. Lines that don't parse are skipped. Once the ledger is
+/// long it is rewritten with a record per bucket, agent and model.
+fn read(dir: &Path) -> Result<Vec<UsageRecord>, SessionError> {
+   let path = usage_path(dir);
+   let content = match fs::read_to_string(&path) {
+      Ok(content) => content,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(SessionError::io(&path, e)),
+   };
+   let mut records = Vec::new();
+   for line in content.lines().filter(|line| !line.trim().is_empty()) {
+      match serde_json::from_str(line) {
+         Ok(record) => records.push(record),
+         Err(e) => log::warn!("Skipping line of {}: {}", path.display(), e),
+      }
+   }
+   if records.len() > COMPACT_MIN_LINES {
+      let lines = records.len();
+      records = merge(records);
+      if records.len() * 2 < lines {
+         write(dir, &records)?;
+      }
+   }
+   Ok(records)
+}
+
+/// `records` with those for the same bucket, agent and model added together
+fn merge(records: Vec<UsageRecord>) -> Vec<UsageRecord> {
+   let mut merged: BTreeMap<_, UsageRecord> = BTreeMap::new();
+   for record in records {
+      merged
+         .entry(record.key())
+         .and_modify(|existing| {
+            existing.input += record.input;
+            existing.output += record.output;
+            existing.messages += record.messages;
+         })
+         .or_insert(record);
+   }
+   merged.into_values().collect()
+}
+
+fn write(dir: &Path, records: &[UsageRecord]) -> Result<(), SessionError> {
+   let path = usage_path(dir);
+   let mut content = String::new();
+   for record in records {
+      let line = serde_json::to_string(record).map_err(|e| SessionError::corrupt(&path, e))?;
+      content.push_str(&line);
+      content.push('\n');
+   }
+   write_atomic(&path, &content)
+}
+
+/// Add up the records in `range` by `group_by`, pricing each with `price`, which returns the USD
+/// cost of a model's input and output tokens or `None` when the model has no price
+fn aggregate(
+   records: &[UsageRecord],
+   range: &UsageRange,
+   group_by: UsageGroupBy,
+   price: impl Fn(&str, u64, u64) -> Option<f64>,
+) -> UsageStats {
+   let offset = FixedOffset::east_opt(range.utc_offset_minutes * 60)
+      .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+   let from = range.from.map(bucket_of);
+   let to = range.to.map(bucket_of);
+   let mut groups: HashMap<String, UsageGroup> = HashMap::new();
+   let mut total = UsageGroup::new("total".to_string());
+   for record in records {
+      let in_range =
+         from.is_none_or(|from| record.at >= from) && to.is_none_or(|to| record.at < to);
+      if !in_range {
+         continue;
+      }
+      let usd = record
+         .model
+         .as_deref()
+         .and_then(|model| price(model, record.input, record.output));
+      let key = match group_by {
+         UsageGroupBy::Agent => record.agent_id.clone(),
+         UsageGroupBy::Model => record.model.clone().unwrap_or_else(|| "unknown".into()),
+         UsageGroupBy::Day => record
+            .at
+            .with_timezone(&offset)
+            .format("%Y-%m-%d")
+            .to_string(),
+      };
+      groups
+         .entry(key.clone())
+         .or_insert_with(|| UsageGroup::new(key))
+         .add(record, usd);
+      total.add(record, usd);
+   }
+   let mut groups: Vec<UsageGroup> = groups.into_values().collect();
+   groups.sort_by(|a, b| a.key.cmp(&b.key));
+   UsageStats { groups, total }
+}
+
+/// Tokens used by assistant messages across sessions and what they cost at list prices, grouped
+/// by agent, model or day. Read from a ledger kept as messages are appended, so it doesn't
+/// depend on how many sessions there are.
+#[command]
+pub async fn get_usage_stats(
+   store: State<'_, SessionStore>,
+   locks: State<'_, AgentSettingsLocks>,
+   range: Option<UsageRange>,
+   group_by: Option<UsageGroupBy>,
+) -> Result<UsageStats, SessionError> {
+   let table = pricing_table(&locks)
+      .await
+      .map_err(|e| SessionError::Pricing {
+         message: e.to_string(),
+      })?;
+   let range = range.unwrap_or_default();
+   let group_by = group_by.unwrap_or_default();
+   blocking(&store, move |store| {
+      let dir = store.dir()?;
+      let _guard = store.lock();
+      let records = read(dir)?;
+      Ok(aggregate(
+         &records,
+         &range,
+         group_by,
+         |model, input, output| {
+            estimate_usage(&table, model, input, output).map(|estimate| estimate.usd)
+         },
+      ))
+   })
+   .await
+}
+
+/// Forget all recorded usage. Sessions keep the token counts of their messages.
+#[command]
+pub async fn reset_usage_stats(store: State<'_, SessionStore>) -> Result<(), SessionError> {
+   blocking(&store, |store| {
+      let path = usage_path(store.dir()?);
+      let _guard = store.lock();
+      match fs::remove_file(&path) {
+         Ok(()) => {
+            log::info!("Reset usage statistics");
+            Ok(())
+         }
+         Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+         Err(e) => Err(SessionError::io(&path, e)),
+      }
+   })
+   .await
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use chrono::TimeZone;
+
+   fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+      Utc.with_ymd_and_hms(2025, 10, day, hour, minute, 0)
+         .unwrap()
+   }
+
+   fn usage(
+      dir: &Path,
+      at: DateTime<Utc>,
+      agent_id: &str,
+      model: Option<&str>,
+      input: u64,
+      output: u64,
+   ) {
+      record(dir, at, agent_id, model, &TokenCounts { input, output }).unwrap();
+   }
+
+   fn price(model: &str, input: u64, output: u64) -> Option<f64> {
+      (model == "gpt-5").then(|| (input + output) as f64 / 1000.0)
+   }
+
+   fn days(stats: &UsageStats) -> Vec<(&str, u64)> {
+      stats
+         .groups
+         .iter()
+         .map(|group| (group.key.as_str(), group.messages))
+         .collect()
+   }
+
+   #[test]
+   fn test_groups_and_prices() {
+      let dir = tempfile::tempdir().unwrap();
+      usage(
+         dir.path(),
+         at(1, 9, 0),
+         "claude-code",
+         Some("claude-x"),
+         100,
+         20,
+      );
+      usage(
+         dir.path(),
+         at(1, 9, 5),
+         "codex-cli",
+         Some("gpt-5"),
+         800,
+         200,
+      );
+      usage(dir.path(), at(1, 10, 0), "codex-cli", None, 10, 0);
+      let records = read(dir.path()).unwrap();
+
+      let stats = aggregate(&records, &UsageRange::default(), UsageGroupBy::Agent, price);
+      assert_eq!(stats.groups.len(), 2);
+      let codex = &stats.groups[1];
+      assert_eq!(codex.key, "codex-cli");
+      assert_eq!((codex.input_tokens, codex.output_tokens), (810, 200));
+      assert_eq!(codex.estimated_usd, 1.0);
+      assert_eq!(codex.unpriced_messages, 1);
+      assert_eq!(stats.total.messages, 3);
+      assert_eq!(stats.total.unpriced_messages, 2);
+
+      let stats = aggregate(&records, &UsageRange::default(), UsageGroupBy::Model, price);
+      let models: Vec<&str> = stats.groups.iter().map(|g| g.key.as_str()).collect();
+      assert_eq!(models, ["claude-x", "gpt-5", "unknown"]);
+   }
+
+   #[test]
+   fn test_days_follow_the_timezone() {
+      let dir = tempfile::tempdir().unwrap();
+      usage(dir.path(), at(1, 18, 29), "claude-code", None, 1, 1);
+      usage(dir.path(), at(1, 18, 30), "claude-code", None, 1, 1);
+      usage(dir.path(), at(1, 23, 59), "claude-code", None, 1, 1);
+      usage(dir.path(), at(2, 0, 0), "claude-code", None, 1, 1);
+      let records = read(dir.path()).unwrap();
+      let by_day = |utc_offset_minutes| {
+         let range = UsageRange {
+            utc_offset_minutes,
+            ..UsageRange::default()
+         };
+         aggregate(&records, &range, UsageGroupBy::Day, price)
+      };
+
+      assert_eq!(days(&by_day(0)), [("2025-10-01", 3), ("2025-10-02", 1)]);
+      // 00:00 in UTC-1 is 01:00 UTC
+      assert_eq!(days(&by_day(-60)), [("2025-10-01", 4)]);
+      // 00:00 in UTC+5:30 is 18:30 UTC the day before
+      assert_eq!(days(&by_day(330)), [("2025-10-01", 1), ("2025-10-02", 3)]);
+   }
+
+   #[test]
+   fn test_range_includes_from_and_excludes_to() {
+      let dir = tempfile::tempdir().unwrap();
+      usage(dir.path(), at(1, 23, 50), "claude-code", None, 1, 1);
+      usage(dir.path(), at(2, 0, 0), "claude-code", None, 1, 1);
+      usage(dir.path(), at(3, 0, 0), "claude-code", None, 1, 1);
+      let records = read(dir.path()).unwrap();
+
+      let range = UsageRange {
+         from: Some(at(2, 0, 0)),
+         to: Some(at(3, 0, 0)),
+         utc_offset_minutes: 0,
+      };
+      let stats = aggregate(&records, &range, UsageGroupBy::Day, price);
+      assert_eq!(days(&stats), [("2025-10-02", 1)]);
+
+      let range = UsageRange {
+         utc_offset_minutes: 120,
+         ..range
+      };
+      let stats = aggregate(&records, &range, UsageGroupBy::Day, price);
+      assert_eq!(days(&stats), [("2025-10-02", 1)]);
+      // The 2nd in UTC+2 starts at 22:00 UTC on the 1st
+      let range = UsageRange {
+         from: Some(at(1, 22, 0)),
+         to: Some(at(2, 22, 0)),
+         utc_offset_minutes: 120,
+      };
+      let stats = aggregate(&records, &range, UsageGroupBy::Day, price);
+      assert_eq!(days(&stats), [("2025-10-02", 2)]);
+   }
+
+   #[test]
+   fn test_ledger_compacts() {
+      let dir = tempfile::tempdir().unwrap();
+      for minute in 0..=COMPACT_MIN_LINES as u32 {
+         usage(dir.path(), at(1, 9, minute % 15), "claude-code", None, 2, 1);
+      }
+      usage(dir.path(), at(1, 10, 0), "claude-code", None, 1, 1);
+
+      let records = read(dir.path()).unwrap();
+      assert_eq!(records.len(), 2);
+      assert_eq!(records[0].messages, COMPACT_MIN_LINES as u64 + 1);
+      assert_eq!(records[0].input, 2 * (COMPACT_MIN_LINES as u64 + 1));
+      let content = fs::read_to_string(usage_path(dir.path())).unwrap();
+      assert_eq!(content.lines().count(), 2);
+      assert_eq!(read(dir.path()).unwrap(), records);
+   }
+}
@@ -0,0 +1,173 @@
+use serde::Serialize;
+use std::sync::{Arc, OnceLock};
+use tauri::{State, command};
+use tiktoken_rs::{CoreBPE, tokenizer::Tokenizer};
+
+/// How a token count was arrived at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CountMethod {
+   /// With the model's own tokenizer
+   Exact,
+   /// With an OpenAI tokenizer standing in for one Athas doesn't bundle, such as Anthropic's and
+   /// Google's. Usually within 10-20% of the real count.
+   Approximate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenCount {
+   pub tokens: usize,
+   pub method: CountMethod,
+}
+
+/// The bundled BPE encodings, each parsed the first time a model needs it
+#[derive(Default)]
+struct Encodings {
+   o200k: OnceLock<Result<CoreBPE, String>>,
+   cl100k: OnceLock<Result<CoreBPE, String>>,
+}
+
+/// Token counting for prompt budgeting. Cheap to clone, so counts can run on a blocking thread.
+#[derive(Clone, Default)]
+pub struct TokenCounter {
+   encodings: Arc<Encodings>,
+}
+
+/// The encoding to count `model`'s tokens with and whether it is the model's own
+fn encoding_for(model: &str) -> (Tokenizer, CountMethod) {
+   let model = model.trim().to_ascii_lowercase();
+   // Newer OpenAI models than the tokenizer crate lists all use o200k
+   let tokenizer = tiktoken_rs::tokenizer::get_tokenizer(&model).or_else(|| {
+      (model.starts_with("gpt-5") || model.starts_with("codex-")).then_some(Tokenizer::O200kBase)
+   });
+   match tokenizer {
+      Some(tokenizer @ (Tokenizer::O200kBase | Tokenizer::Cl100kBase)) => {
+         (tokenizer, CountMethod::Exact)
+      }
+      // Legacy completion models, whose encodings aren't bundled
+      Some(_) => (Tokenizer::Cl100kBase, CountMethod::Approximate),
+      None => (Tokenizer::O200kBase, CountMethod::Approximate),
+   }
+}
+
+impl TokenCounter {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   fn encoding(&self, tokenizer: Tokenizer) -> Result<&CoreBPE, String> {
+      let (cell, load): (_, fn() -> anyhow::Result<CoreBPE>) = match tokenizer {
+         Tokenizer::Cl100kBase => (&self.encodings.cl100k, tiktoken_rs::cl100k_base),
+         _ => (&self.encodings.o200k, tiktoken_rs::o200k_base),
+      };
+      cell
+         .get_or_init(|| load().map_err(|e| format!("Failed to load tokenizer: {e}")))
+         .as_ref()
+         .map_err(Clone::clone)
+   }
+
+   /// Count the tokens of each of `texts` as `model` would see them
+   pub fn count(&self, texts: &[String], model: &str) -> Result<Vec<TokenCount>, String> {
+      let (tokenizer, method) = encoding_for(model);
+      let encoding = self.encoding(tokenizer)?;
+      Ok(texts
+         .iter()
+         .map(|text| TokenCount {
+            tokens: encoding.encode_ordinary(text).len(),
+            method,
+         })
+         .collect())
+   }
+}
+
+/// Count on a blocking thread, as long texts take a while to encode
+pub(super) async fn count_blocking(
+   counter: &TokenCounter,
+   texts: Vec<String>,
+   model: String,
+) -> Result<Vec<TokenCount>, String> {
+   let counter = counter.clone();
+   tokio::task::spawn_blocking(move || counter.count(&texts, &model))
+      .await
+      .map_err(|e| format!("Token counting failed: {e}"))?
+}
+
+/// Count the tokens `text` takes up for `model`. OpenAI models are counted exactly; other models
+/// get an approximate count, as reported in `method`.
+#[command]
+pub async fn count_tokens(
+   counter: State<'_, TokenCounter>,
+   text: String,
+   model: String,
+) -> Result<TokenCount, String> {
+   let counts = count_blocking(&counter, vec![text], model).await?;
+   Ok(counts[0])
+}
+
+/// Count the tokens of several texts for `model` in one call, in the same order
+#[command]
+pub async fn count_tokens_batch(
+   counter: State<'_, TokenCounter>,
+   texts: Vec<String>,
+   model: String,
+) -> Result<Vec<TokenCount>, String> {
+   count_blocking(&counter, texts, model).await
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn test_encoding_for_models() {
+      let cases = [
+         ("gpt-4o-mini", Tokenizer::O200kBase, CountMethod::Exact),
+         ("gpt-5-codex", Tokenizer::O200kBase, CountMethod::Exact),
+         ("o3", Tokenizer::O200kBase, CountMethod::Exact),
+         ("gpt-4-turbo", Tokenizer::Cl100kBase, CountMethod::Exact),
+         (
+            "claude-sonnet-4-5",
+            Tokenizer::O200kBase,
+            CountMethod::Approximate,
+         ),
+         (
+            "gemini-2.5-pro",
+            Tokenizer::O200kBase,
+            CountMethod::Approximate,
+         ),
+      ];
+      for (model, tokenizer, method) in cases {
+         assert_eq!(encoding_for(model), (tokenizer, method), "{}", model);
+      }
+   }
+
+   #[test]
+   fn test_count() {
+      let counter = TokenCounter::new();
+      let texts = vec![
+         "hello world".to_string(),
+         String::new(),
+         "<|endoftext|>".to_string(),
+      ];
+      let counts = counter.count(&texts, "gpt-4o").unwrap();
+      assert_eq!(
+         counts.iter().map(|count| count.tokens).collect::<Vec<_>>(),
+         vec![2, 0, 7]
+      );
+      assert!(
+         counts
+            .iter()
+            .all(|count| count.method == CountMethod::Exact)
+      );
+
+      let counts = counter.count(&texts[..1], "claude-opus-4-1").unwrap();
+      assert_eq!(
+         counts,
+         vec![TokenCount {
+            tokens: 2,
+            method: CountMethod::Approximate,
+         }]
+      );
+   }
+}
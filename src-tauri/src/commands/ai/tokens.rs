@@ -1,34 +1,60 @@
-use crate::secure_storage::{get_secret, remove_secret, store_secret};
-use tauri::command;
+use crate::{
+   commands::ai::credentials::{CredentialStore, SecretBackend},
+   secure_storage::{get_secret, remove_secret, store_secret},
+};
+use tauri::{AppHandle, State, command};
 
-fn provider_key(provider_id: &str) -> String {
-   format!("ai_token_{}", provider_id)
+/// The credential store id of a provider the frontend names `provider_id`
+fn provider_key(provider_id: &str) -> &str {
+   match provider_id {
+      "gemini" => "google",
+      provider_id => provider_id,
+   }
 }
 
-/// Store an AI provider token using OS keychain when available.
+/// Where `store_ai_provider_token` kept tokens before they moved to the credential store: the
+/// keychain, or `secure.json` without one. The store reads tokens from here once, then removes
+/// them.
+pub struct LegacyTokenBackend(pub AppHandle);
+
+impl SecretBackend for LegacyTokenBackend {
+   fn get(&self, account: &str) -> Result<Option<String>, String> {
+      get_secret(&self.0, account)
+   }
+
+   fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+      store_secret(&self.0, account, secret)
+   }
+
+   fn delete(&self, account: &str) -> Result<(), String> {
+      remove_secret(&self.0, account)
+   }
+}
+
+/// Store an AI provider token, in the same store as `set_api_key`
 #[command]
 pub async fn store_ai_provider_token(
-   app: tauri::AppHandle,
+   store: State<'_, CredentialStore>,
    provider_id: String,
    token: String,
 ) -> Result<(), String> {
-   store_secret(&app, &provider_key(&provider_id), &token)
+   store.set(provider_key(&provider_id), &token).map(|_| ())
 }
 
 /// Get an AI provider token
 #[command]
 pub async fn get_ai_provider_token(
-   app: tauri::AppHandle,
+   store: State<'_, CredentialStore>,
    provider_id: String,
 ) -> Result<Option<String>, String> {
-   get_secret(&app, &provider_key(&provider_id))
+   store.get(provider_key(&provider_id))
 }
 
 /// Remove an AI provider token
 #[command]
 pub async fn remove_ai_provider_token(
-   app: tauri::AppHandle,
+   store: State<'_, CredentialStore>,
    provider_id: String,
 ) -> Result<(), String> {
-   remove_secret(&app, &provider_key(&provider_id))
+   store.delete(provider_key(&provider_id)).map(|_| ())
 }
@@ -126,7 +126,7 @@ impl Default for AgentRegistry {
    }
 }
 
-fn find_binary(binary_name: &str) -> Option<PathBuf> {
+pub(crate) fn find_binary(binary_name: &str) -> Option<PathBuf> {
    if let Ok(path) = which::which(binary_name) {
       return Some(path);
    }
@@ -4,4 +4,5 @@ mod config;
 pub mod types;
 
 pub use bridge::AcpAgentBridge;
+pub(crate) use config::find_binary;
 pub use types::{AcpAgentStatus, AgentConfig};
@@ -144,6 +144,60 @@ fn main() {
          // Set up file clipboard
          app.manage(FileClipboard::new(None));
 
+         // Set up agent settings file locks, allowed roots and change history
+         app.manage(AgentSettingsLocks::with_encryption(
+            ConfigEncryption::open()?
+         ));
+         app.manage(AgentSettingsRoots::new());
+         app.manage(AgentSettingsHistory::new());
+         app.manage(AgentSettingsWatcher::new(app.handle().clone()));
+         app.manage(SettingsWriteBuffer::new());
+         announce_pending_operations(app.handle());
+
+         // Set up provider API key storage, picking up the tokens older versions saved
+         app.manage(
+            CredentialStore::open()?
+               .with_legacy(Box::new(LegacyTokenBackend(app.handle().clone()))),
+         );
+
+         // Set up the HTTP client provider requests share, with the saved proxy settings, warning
+         // the frontend when a provider's rate limit runs low
+         let provider_http = ProviderHttpClient::new()?;
+         tauri::async_runtime::block_on(load_network_config(
+            &app.state::<AgentSettingsLocks>(),
+            &provider_http,
+         ));
+         let rate_limit_events = app.handle().clone();
+         provider_http.rate_limits().on_low_capacity(move |alert| {
+            let _ = rate_limit_events.emit(PROVIDER_RATE_LIMIT_EVENT, alert);
+         });
+         app.manage(provider_http);
+
+         // Set up the cache of models listed from provider endpoints
+         app.manage(ProviderModelsCache::new());
+
+         // Set up the on-disk cache of embeddings
+         app.manage(EmbeddingsCache::new());
+
+         // Set up the on-disk store of agent sessions, pruning old ones once the app has settled
+         app.manage(SessionStore::new());
+         {
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+               let store = app_handle.state::<SessionStore>();
+               let locks = app_handle.state::<AgentSettingsLocks>();
+               if let Some(report) = prune_sessions_on_startup(&store, &locks).await {
+                  let _ = app_handle.emit(SESSIONS_PRUNED_EVENT, report);
+               }
+            });
+         }
+
+         // Set up prompt token counting; tokenizers load on first use
+         app.manage(TokenCounter::new());
+
+         // Set up tracking of streaming completions
+         app.manage(CompletionRequests::new());
+
          // Auto-start interceptor on app launch
          {
             let claude_bridge_clone = claude_bridge.clone();
@@ -326,6 +380,14 @@ fn main() {
 
          Ok(())
       })
+      .on_window_event(|window, event| {
+         if let tauri::WindowEvent::Destroyed = event {
+            // Stop streaming completions nobody is left to read
+            window
+               .state::<CompletionRequests>()
+               .cancel_window(window.label());
+         }
+      })
       .invoke_handler(tauri::generate_handler![
          // File system commands
          open_file_external,
@@ -445,6 +507,113 @@ fn main() {
          respond_acp_permission,
          set_acp_session_mode,
          cancel_acp_prompt,
+         // Agent settings commands
+         get_agent_settings,
+         get_agent_settings_batch,
+         get_agent_settings_with_defaults,
+         get_effective_agent_settings,
+         get_layered_agent_settings,
+         set_agent_settings,
+         delete_agent_setting,
+         reset_agent_settings,
+         recover_agent_settings,
+         migrate_agent_settings,
+         get_agent_settings_history,
+         undo_agent_settings_change,
+         copy_agent_settings,
+         export_agent_settings_bundle,
+         import_agent_settings_bundle,
+         get_agent_config_values,
+         reveal_agent_config_secret,
+         list_agent_config_keys,
+         search_agent_configs,
+         set_agent_config_values,
+         patch_agent_settings,
+         preview_agent_settings_change,
+         list_agent_settings_backups,
+         restore_agent_settings_backup,
+         watch_agent_settings,
+         unwatch_agent_settings,
+         list_watched_settings,
+         list_pending_operations,
+         clear_settings_cache,
+         flush_pending_settings_writes,
+         recover_pending_operations,
+         resolve_agent_config_path,
+         list_known_agents,
+         set_agent_enabled,
+         get_athas_config_encryption,
+         set_athas_config_encryption,
+         encrypt_athas_config,
+         add_custom_agent,
+         update_custom_agent,
+         remove_custom_agent,
+         detect_installed_agents,
+         get_agent_version,
+         diagnose_agent,
+         set_api_key,
+         get_api_key,
+         delete_api_key,
+         list_credential_providers,
+         resolve_provider_credential,
+         list_provider_models,
+         validate_provider_key,
+         count_tokens,
+         count_tokens_batch,
+         estimate_cost,
+         get_pricing_table,
+         start_completion,
+         cancel_completion,
+         get_network_config,
+         set_network_config,
+         test_network_config,
+         list_custom_providers,
+         add_custom_provider,
+         update_custom_provider,
+         remove_custom_provider,
+         get_provider_rate_limits,
+         generate_embeddings,
+         clear_embeddings_cache,
+         list_mcp_servers,
+         add_mcp_server,
+         update_mcp_server,
+         remove_mcp_server,
+         materialize_mcp_env,
+         test_mcp_server,
+         sync_mcp_servers,
+         import_mcp_servers_from_claude_desktop,
+         create_session,
+         append_session_message,
+         get_session,
+         list_sessions,
+         export_session,
+         get_usage_stats,
+         reset_usage_stats,
+         update_session_meta,
+         prune_sessions,
+         get_session_attachment,
+         gc_session_blobs,
+         list_prompt_templates,
+         save_prompt_template,
+         delete_prompt_template,
+         render_prompt_template,
+         get_agent_memory,
+         update_agent_memory_section,
+         list_agent_commands,
+         save_agent_command,
+         delete_agent_command,
+         list_codex_profiles,
+         get_codex_profile,
+         set_active_codex_profile,
+         import_external_editor_config,
+         get_agent_defaults,
+         set_agent_default,
+         detect_ollama,
+         list_ollama_models,
+         configure_agent_for_ollama,
+         scan_agent_configs_for_secrets,
+         migrate_secret_to_keychain,
+         list_agent_models,
          // Theme commands
          get_system_theme,
          load_toml_themes,
@@ -519,6 +688,11 @@ fn main() {
          menu::toggle_menu_bar,
          menu::rebuild_menu_themes,
       ])
-      .run(tauri::generate_context!())
-      .expect("error while running tauri application");
+      .build(tauri::generate_context!())
+      .expect("error while building tauri application")
+      .run(|app, event| {
+         if let tauri::RunEvent::Exit = event {
+            tauri::async_runtime::block_on(flush_pending_writes(app));
+         }
+      });
 }